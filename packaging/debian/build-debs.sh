@@ -40,6 +40,8 @@ install -m755 "target/release/temple-demo" "${main_stage}/usr/bin/temple-demo"
 install -m755 "target/release/temple-hc" "${main_stage}/usr/bin/temple-hc"
 install -m755 "target/release/temple-paint" "${main_stage}/usr/bin/temple-paint"
 install -m755 "target/release/temple-edit" "${main_stage}/usr/bin/temple-edit"
+install -m755 "target/release/temple-doc" "${main_stage}/usr/bin/temple-doc"
+install -m755 "target/release/temple-sprite" "${main_stage}/usr/bin/temple-sprite"
 install -m755 "packaging/bin/templelinux-session" "${main_stage}/usr/bin/templelinux-session"
 
 mkdir -p "${main_stage}/usr/share/wayland-sessions"
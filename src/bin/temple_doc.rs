@@ -0,0 +1,284 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+use temple_rt::{doldoc, image};
+
+fn print_usage() {
+    eprintln!("temple-doc export [--html FILE] [--png PREFIX] <file.DD>");
+    eprintln!();
+    eprintln!(
+        "Renders a DolDoc (.DD, .DD.Z, .HC...) to a standalone HTML page and/or 640x480 PNG pages"
+    );
+    eprintln!("laid out like TempleShell's doc viewer (PREFIX-1.png, PREFIX-2.png...).");
+    eprintln!("With neither option, writes <name>.html to the current directory.");
+    eprintln!();
+    eprintln!("Examples:");
+    eprintln!("  temple-doc export third_party/TempleOS/Doc/HelpIndex.DD");
+    eprintln!("  temple-doc export --html site/Notes.html --png site/Notes Notes.DD");
+}
+
+fn main() -> io::Result<()> {
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+        Some("export") => {}
+        Some("--help" | "-h") => {
+            print_usage();
+            return Ok(());
+        }
+        Some(other) => {
+            eprintln!("temple-doc: unknown command: {other}");
+            print_usage();
+            process::exit(2);
+        }
+        None => {
+            print_usage();
+            process::exit(2);
+        }
+    }
+
+    let mut input: Option<PathBuf> = None;
+    let mut html: Option<PathBuf> = None;
+    let mut png: Option<PathBuf> = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" | "-h" => {
+                print_usage();
+                return Ok(());
+            }
+            "--html" | "--png" => {
+                let Some(path) = args.next() else {
+                    eprintln!("temple-doc: {arg} expects a path");
+                    print_usage();
+                    process::exit(2);
+                };
+                if arg == "--html" {
+                    html = Some(PathBuf::from(path));
+                } else {
+                    png = Some(PathBuf::from(path));
+                }
+            }
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => {
+                eprintln!("temple-doc: unexpected arg: {arg}");
+                print_usage();
+                process::exit(2);
+            }
+        }
+    }
+    let Some(input) = input else {
+        eprintln!("temple-doc: missing input file");
+        print_usage();
+        process::exit(2);
+    };
+    if html.is_none() && png.is_none() {
+        html = Some(PathBuf::from(doc_stem(&input)).with_extension("html"));
+    }
+
+    let buf = read_doc(&input)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", input.display())))?;
+    let (text, bins) = doldoc::parse_doc_blob(&buf);
+
+    if let Some(out) = html {
+        let title = input
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        fs::write(
+            &out,
+            doldoc::to_html(&text, &bins, &title, &doc_dir(&input)),
+        )?;
+        println!("{}", out.display());
+    }
+    if let Some(prefix) = png {
+        for (i, page) in doldoc::render_pages(&text, &bins).iter().enumerate() {
+            let out = page_path(&prefix, i + 1);
+            fs::write(&out, doldoc::encode_png(page, None)?)?;
+            println!("{}", out.display());
+        }
+    }
+    Ok(())
+}
+
+/// The document's bytes, expanded first if it is compressed (`Foo.DD.Z`).
+fn read_doc(path: &Path) -> io::Result<Vec<u8>> {
+    let buf = fs::read(path)?;
+    if image::is_compressed(&buf) {
+        image::expand(&buf)
+    } else {
+        Ok(buf)
+    }
+}
+
+/// `Foo.DD` (or `Foo.DD.Z`) -> `Foo`.
+fn doc_stem(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "doc".to_string());
+    let name = name.strip_suffix(".Z").unwrap_or(&name);
+    match name.split_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem.to_string(),
+        _ => name.to_string(),
+    }
+}
+
+/// The directory `path` sits in within a TempleOS tree (`third_party/TempleOS/Doc/Foo.DD` ->
+/// `Doc`), which links to other documents are made relative to. Outside a tree the document
+/// is taken to sit at its root.
+fn doc_dir(path: &Path) -> String {
+    let dir: Vec<String> = path
+        .parent()
+        .into_iter()
+        .flat_map(Path::components)
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    match dir.iter().rposition(|c| c == "TempleOS") {
+        Some(root) => dir[root + 1..].join("/"),
+        None => String::new(),
+    }
+}
+
+/// Page `n` (from 1) of a `--png` prefix: `out/Foo` -> `out/Foo-2.png`; a `.png` suffix on the
+/// prefix is dropped first.
+fn page_path(prefix: &Path, n: usize) -> PathBuf {
+    let prefix = prefix.to_string_lossy();
+    let prefix = prefix
+        .strip_suffix(".png")
+        .or_else(|| prefix.strip_suffix(".PNG"))
+        .unwrap_or(&prefix);
+    PathBuf::from(format!("{prefix}-{n}.png"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[test]
+    fn html_export_keeps_colors_links_anchors_and_trees() {
+        let doc = concat!(
+            "$AN,\"\",A=\"Top\"$Intro $RED$warm$FG$\n",
+            "$TR,\"Section\"$\n",
+            "$ID,2$Body with $LK,\"a link\",A=\"FI:::/Doc/Foo.DD\"$\n",
+            "$ID,-2$After <tree> & $LK,\"back\",A=\"AN:Top\"$\n",
+            "$HC,\"<b>raw</b>\"$\n",
+        );
+        let html = doldoc::to_html(doc, &BTreeMap::new(), "Test.DD", "Home");
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Test.DD</title>"));
+        assert!(html.contains("<a id=\"Top\"></a>Intro "));
+        assert!(html.contains(">warm</span>"));
+        assert!(html.contains("<details open><summary>Section</summary>  Body with "));
+        assert!(html.contains("<a href=\"../Doc/Foo.html\" title=\"FI:::/Doc/Foo.DD\">a link</a>"));
+        assert!(html.contains("</details>After &lt;tree&gt; &amp; "));
+        assert!(html.contains("<a href=\"#Top\" title=\"AN:Top\">back</a>"));
+        assert!(html.contains("<b>raw</b>"));
+    }
+
+    #[test]
+    fn collapsed_trees_and_sibling_trees_close_properly() {
+        let doc = "$TR+C,\"One\"$\n$TR,\"Two\"$\n$ID,2$inner\n$ID,-2$\n";
+        let html = doldoc::to_html(doc, &BTreeMap::new(), "t", "");
+        assert_eq!(html.matches("<details").count(), 2);
+        assert_eq!(html.matches("</details>").count(), 2);
+        assert!(html.contains("<details><summary>One</summary></details><details open>"));
+    }
+
+    #[test]
+    fn pages_are_screen_sized_and_split_like_the_viewer() {
+        let text: String = (0..70)
+            .map(|i| match i {
+                60 => "$BG,RED$X$BG$\n".to_string(),
+                _ => format!("line {i}\n"),
+            })
+            .collect();
+        let pages = doldoc::render_pages(&text, &BTreeMap::new());
+        assert_eq!(pages.len(), 2);
+        assert!(
+            pages
+                .iter()
+                .all(|p| p.width == doldoc::PAGE_W && p.height == doldoc::PAGE_H)
+        );
+        // Line 60 opens page two.
+        assert_eq!(pages[1].pixel(0, 0), Some(4));
+        assert_eq!(pages[1].pixel(8, 0), Some(doldoc::PAGE_BG));
+        assert_eq!(pages[0].pixel(0, 0), Some(doldoc::PAGE_BG));
+
+        let png = doldoc::encode_png(&pages[0], None).expect("encode");
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    }
+
+    #[test]
+    fn links_stay_relative_and_never_carry_a_scheme() {
+        let href = |spec| doldoc::link_href(spec, "Doc");
+        assert_eq!(href("::/Doc/Foo.DD").as_deref(), Some("Foo.html"));
+        assert_eq!(
+            href("FI:C:/Home/Bar.DD.Z").as_deref(),
+            Some("../Home/Bar.html")
+        );
+        assert_eq!(
+            href("FF:/Doc/Sub/Baz.DD,text").as_deref(),
+            Some("Sub/Baz.html")
+        );
+        assert_eq!(href("Other.DD").as_deref(), Some("Other.html"));
+        assert_eq!(href("My Notes.DD").as_deref(), Some("My%20Notes.html"));
+        assert_eq!(
+            href("https://templeos.org").as_deref(),
+            Some("https://templeos.org")
+        );
+        assert_eq!(href("AN:Top").as_deref(), Some("#Top"));
+        for spec in [
+            "javascript:alert(1)",
+            "FI:javascript:alert(1)",
+            "data:text/html,hi",
+            "MN:Print",
+            "\\\\host\\x.DD",
+        ] {
+            assert_eq!(href(spec), None, "{spec}");
+        }
+        assert_eq!(
+            href("//evil.example/x.DD").as_deref(),
+            Some("../evil.example/x.html")
+        );
+    }
+
+    #[test]
+    fn compressed_docs_export_like_plain_ones() {
+        let dir = std::env::temp_dir().join(format!("temple_doc_z_{}", std::process::id()));
+        let docs = dir.join("TempleOS/Doc");
+        fs::create_dir_all(&docs).unwrap();
+        let input = docs.join("Notes.DD.Z");
+        fs::write(
+            &input,
+            image::compress(b"$LK,\"home\",A=\"::/Home/Foo.DD\"$\n"),
+        )
+        .unwrap();
+        assert_eq!(doc_dir(&input), "Doc");
+        assert_eq!(doc_dir(Path::new("Notes.DD")), "");
+
+        let buf = read_doc(&input).unwrap();
+        let (text, bins) = doldoc::parse_doc_blob(&buf);
+        let html = doldoc::to_html(&text, &bins, "Notes.DD.Z", &doc_dir(&input));
+        assert!(html.contains("<a href=\"../Home/Foo.html\""));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn output_names_follow_the_input() {
+        assert_eq!(doc_stem(Path::new("Doc/HelpIndex.DD")), "HelpIndex");
+        assert_eq!(doc_stem(Path::new("Notes.DD.Z")), "Notes");
+        assert_eq!(
+            page_path(Path::new("out/Foo"), 2),
+            PathBuf::from("out/Foo-2.png")
+        );
+        assert_eq!(
+            page_path(Path::new("Foo.png"), 1),
+            PathBuf::from("Foo-1.png")
+        );
+    }
+}
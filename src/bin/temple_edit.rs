@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeMap,
     io,
     path::{Path, PathBuf},
     process::{Command, Stdio},
@@ -8,10 +9,19 @@ use std::{
 };
 
 use temple_rt::{
+    assets,
+    doldoc::{self, DocSprite, DocStyle},
     protocol,
-    rt::{Event, TempleRt},
+    rt::{Event, TempleRt, is_read_only_templeos_path},
 };
 
+#[path = "temple_edit/doldoc_view.rs"]
+mod doldoc_view;
+#[path = "temple_edit/search.rs"]
+mod search;
+use doldoc_view::InsertKind;
+use search::{FileHit, ReplacePreview, SearchOpts, Searcher};
+
 const FONT_W: i32 = 8;
 const FONT_H: i32 = 8;
 const UI_BG: u8 = 0;
@@ -34,6 +44,12 @@ const KEY_A_LOWER: u32 = b'a' as u32;
 const KEY_A_UPPER: u32 = b'A' as u32;
 const KEY_F_LOWER: u32 = b'f' as u32;
 const KEY_F_UPPER: u32 = b'F' as u32;
+const KEY_R_LOWER: u32 = b'r' as u32;
+const KEY_R_UPPER: u32 = b'R' as u32;
+const KEY_G_LOWER: u32 = b'g' as u32;
+const KEY_G_UPPER: u32 = b'G' as u32;
+const KEY_N_LOWER: u32 = b'n' as u32;
+const KEY_N_UPPER: u32 = b'N' as u32;
 
 fn clamp_usize(v: usize, min_v: usize, max_v: usize) -> usize {
     v.max(min_v).min(max_v)
@@ -241,6 +257,10 @@ fn is_word_byte(b: u8) -> bool {
 
 fn read_file_lines(path: &Path) -> io::Result<Vec<Vec<u8>>> {
     let buf = std::fs::read(path)?;
+    Ok(split_lines(&buf))
+}
+
+fn split_lines(buf: &[u8]) -> Vec<Vec<u8>> {
     let mut lines: Vec<Vec<u8>> = Vec::new();
     let mut cur: Vec<u8> = Vec::new();
     let mut i = 0;
@@ -258,29 +278,66 @@ fn read_file_lines(path: &Path) -> io::Result<Vec<Vec<u8>>> {
             b'\t' => {
                 cur.extend_from_slice(b"    ");
             }
-            b => {
-                if b.is_ascii() {
-                    cur.push(b);
-                }
-            }
+            b => cur.push(b),
         }
     }
     lines.push(cur);
     if lines.is_empty() {
         lines.push(Vec::new());
     }
-    Ok(lines)
+    lines
 }
 
 fn write_file_lines(path: &Path, lines: &[Vec<u8>]) -> io::Result<()> {
-    let mut out = Vec::new();
-    for (idx, line) in lines.iter().enumerate() {
-        out.extend_from_slice(line);
-        if idx + 1 < lines.len() {
-            out.push(b'\n');
-        }
+    std::fs::write(path, lines.join(&b'\n'))
+}
+
+/// Sprite data stored after the NUL terminator of a `.DD` file, keyed by `BI=` number.
+type DocBins = BTreeMap<u32, Vec<u8>>;
+
+/// Reads a file for editing. DolDoc files also return their bytes as read, which saving writes
+/// over so nothing the editor can't show is lost (see `doldoc_view::write_doc`).
+fn read_edit_file(path: &Path) -> io::Result<(Vec<Vec<u8>>, DocBins, Vec<u8>)> {
+    if !doldoc_view::is_doldoc_path(path) {
+        return Ok((read_file_lines(path)?, DocBins::new(), Vec::new()));
     }
-    std::fs::write(path, out)
+    let buf = std::fs::read(path)?;
+    let (text, bins) = doldoc::parse_doc_blob(&buf);
+    Ok((doldoc_view::doc_lines(&text), bins, buf))
+}
+
+/// Saves `lines`; for DolDoc files `original` is what was read and becomes what was written.
+fn write_edit_file(
+    path: &Path,
+    lines: &[Vec<u8>],
+    bins: &DocBins,
+    original: &mut Vec<u8>,
+) -> io::Result<()> {
+    if bins.is_empty() && original.is_empty() {
+        return write_file_lines(path, lines);
+    }
+    let out = doldoc_view::write_doc(original, lines, bins);
+    std::fs::write(path, &out)?;
+    *original = out;
+    Ok(())
+}
+
+/// Inserts `text` (which may contain newlines) at `at`, returning the position just after it.
+fn insert_text(lines: &mut Vec<Vec<u8>>, at: Pos, text: &str) -> Pos {
+    let mut pos = at;
+    let mut parts = text.split('\n');
+    if let Some(first) = parts.next() {
+        lines[pos.line].splice(pos.col..pos.col, first.bytes());
+        pos.col += first.len();
+    }
+    for part in parts {
+        let tail = lines[pos.line].split_off(pos.col);
+        pos.line += 1;
+        lines.insert(pos.line, part.as_bytes().to_vec());
+        lines[pos.line].extend_from_slice(&tail);
+        pos.col = part.len();
+    }
+    pos
 }
 
 fn draw_text_cells(rt: &mut TempleRt, col: i32, row: i32, fg: u8, bg: u8, text: &str) {
@@ -288,7 +345,13 @@ fn draw_text_cells(rt: &mut TempleRt, col: i32, row: i32, fg: u8, bg: u8, text:
 }
 
 fn draw_cell(rt: &mut TempleRt, col: i32, row: i32, fg: u8, bg: u8, ch: u8) {
-    rt.draw_char_8x8(col * FONT_W, row * FONT_H, fg, bg, ch as char);
+    rt.draw_char_8x8(
+        col * FONT_W,
+        row * FONT_H,
+        fg,
+        bg,
+        assets::decode_cp437_byte(ch),
+    );
 }
 
 fn ensure_cursor_visible(cursor_line: usize, top_line: &mut usize, view_rows: usize) {
@@ -331,16 +394,6 @@ fn temple_hc_program() -> String {
         .unwrap_or_else(|| "temple-hc".to_string())
 }
 
-fn is_read_only_templeos_path(path: &Path) -> bool {
-    let Some(root) = std::env::var_os("TEMPLEOS_ROOT") else {
-        return false;
-    };
-    let root = PathBuf::from(root);
-    let root = std::fs::canonicalize(&root).unwrap_or(root);
-    let abs = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
-    abs.starts_with(&root)
-}
-
 #[derive(Debug, Clone)]
 struct HelpOverlay {
     title: String,
@@ -399,55 +452,148 @@ fn load_help_overlay(topic: &str) -> io::Result<Option<HelpOverlay>> {
     Ok(None)
 }
 
-fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
-    if needle.is_empty() {
-        return None;
+/// An open file that is not currently being edited (see F6 and find-in-files jumps).
+struct Buffer {
+    path: PathBuf,
+    lines: Vec<Vec<u8>>,
+    bins: DocBins,
+    /// DolDoc files: the bytes last read or written (see `read_edit_file`).
+    original: Vec<u8>,
+    cursor_line: usize,
+    cursor_col: usize,
+    top_line: usize,
+    modified: bool,
+    read_only: bool,
+    wysiwyg: bool,
+}
+
+impl Buffer {
+    fn open(path: &Path) -> io::Result<Self> {
+        let (lines, bins, original) = match read_edit_file(path) {
+            Ok(v) => v,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                (vec![Vec::new()], DocBins::new(), Vec::new())
+            }
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            lines,
+            bins,
+            original,
+            cursor_line: 0,
+            cursor_col: 0,
+            top_line: 0,
+            modified: false,
+            read_only: is_read_only_templeos_path(path),
+            wysiwyg: doldoc_view::is_doldoc_path(path),
+        })
     }
-    haystack.windows(needle.len()).position(|w| w == needle)
 }
 
-fn find_next(lines: &[Vec<u8>], query: &[u8], start: Pos) -> Option<(Pos, Pos)> {
-    if query.is_empty() || lines.is_empty() {
-        return None;
+/// Writes every modified, writable buffer (Ctrl+Shift+S), returning how many were saved.
+fn save_buffers(buffers: &mut [Buffer]) -> Result<usize, String> {
+    let mut saved = 0;
+    for buf in buffers.iter_mut().filter(|b| b.modified && !b.read_only) {
+        write_edit_file(&buf.path, &buf.lines, &buf.bins, &mut buf.original)
+            .map_err(|err| format!("{}: {err}", buf.path.display()))?;
+        buf.modified = false;
+        saved += 1;
     }
+    Ok(saved)
+}
 
-    let start_line = start.line.min(lines.len() - 1);
-    let start_col = start.col;
+fn same_file(a: &Path, b: &Path) -> bool {
+    if a == b {
+        return true;
+    }
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
 
-    for line_idx in start_line..lines.len() {
-        let line = &lines[line_idx];
-        let from = if line_idx == start_line {
-            start_col.min(line.len())
-        } else {
-            0
-        };
-        if let Some(off) = find_subslice(&line[from..], query) {
-            let col = from + off;
-            return Some((
-                Pos::new(line_idx, col),
-                Pos::new(line_idx, col.saturating_add(query.len())),
-            ));
-        }
+/// Removes `path` from the stash if it is already open, otherwise loads it from disk.
+fn take_or_open_buffer(stash: &mut Vec<Buffer>, path: &Path) -> io::Result<Buffer> {
+    if let Some(idx) = stash.iter().position(|b| same_file(&b.path, path)) {
+        return Ok(stash.remove(idx));
     }
+    Buffer::open(path)
+}
 
-    // Wrap.
-    for line_idx in 0..=start_line {
-        let line = &lines[line_idx];
-        let to = if line_idx == start_line {
-            start_col.min(line.len())
-        } else {
-            line.len()
-        };
-        if let Some(off) = find_subslice(&line[..to], query) {
-            let col = off;
-            return Some((
-                Pos::new(line_idx, col),
-                Pos::new(line_idx, col.saturating_add(query.len())),
-            ));
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Prompt {
+    Find,
+    Replace,
+    ReplaceWith,
+    FindInFiles,
+    FindInDir,
+    Insert(InsertKind),
+    InsertLinkTarget,
+}
+
+impl Prompt {
+    fn label(self) -> &'static str {
+        match self {
+            Prompt::Find => "Find",
+            Prompt::Replace => "Replace",
+            Prompt::ReplaceWith => "With",
+            Prompt::FindInFiles => "Find in files",
+            Prompt::FindInDir => "In dir",
+            Prompt::Insert(InsertKind::Text) => "Insert text",
+            Prompt::Insert(InsertKind::Link) => "Link label",
+            Prompt::InsertLinkTarget => "Link target (empty=label)",
+            Prompt::Insert(InsertKind::Fg) => "FG color (empty=default)",
+            Prompt::Insert(InsertKind::Bg) => "BG color (empty=default)",
+            Prompt::Insert(InsertKind::Tree) => "Tree label",
+            Prompt::Insert(InsertKind::Sprite) => "Sprite BI (empty=new)",
         }
     }
 
-    None
+    fn is_insert(self) -> bool {
+        matches!(self, Prompt::Insert(_) | Prompt::InsertLinkTarget)
+    }
+}
+
+struct ResultsView {
+    title: String,
+    root: PathBuf,
+    hits: Vec<FileHit>,
+    selected: usize,
+    scroll: usize,
+}
+
+impl ResultsView {
+    fn row_text(&self, hit: &FileHit) -> String {
+        let rel = hit.path.strip_prefix(&self.root).unwrap_or(&hit.path);
+        format!(
+            "{}:{}:{}: {}",
+            rel.display(),
+            hit.line + 1,
+            hit.col + 1,
+            hit.text.trim_start()
+        )
+    }
+}
+
+struct ReplaceAllView {
+    previews: Vec<ReplacePreview>,
+    total: usize,
+    scroll: usize,
+}
+
+fn draw_plain_line(
+    rt: &mut TempleRt,
+    col: usize,
+    row: i32,
+    fg: u8,
+    bg: u8,
+    text: &[u8],
+    max_cols: usize,
+) {
+    for (i, &b) in text.iter().take(max_cols.saturating_sub(col)).enumerate() {
+        draw_cell(rt, (col + i) as i32, row, fg, bg, b);
+    }
 }
 
 fn draw_tdoc_line(rt: &mut TempleRt, row: i32, bg: u8, line: &str, max_cols: usize) {
@@ -551,6 +697,86 @@ fn draw_tdoc_line(rt: &mut TempleRt, row: i32, bg: u8, line: &str, max_cols: usi
     }
 }
 
+/// Draws the text area as rendered DolDoc (F7), with sprites overlaid at their `$SP$` anchors.
+fn draw_doldoc_lines(
+    rt: &mut TempleRt,
+    lines: &[Vec<u8>],
+    bins: &DocBins,
+    top_line: usize,
+    view_rows: usize,
+    cursor: Pos,
+    selection: Option<(Pos, Pos)>,
+) {
+    let cols = (rt.size().0 as i32 / FONT_W).max(1) as usize;
+    let avail_cols = cols.saturating_sub(LINE_NO_W);
+    let default = DocStyle::new(UI_FG, UI_BG);
+    let mut style = default;
+    for line in lines.iter().take(top_line) {
+        doldoc_view::layout_line(line, &mut style, default, bins);
+    }
+
+    let mut sprites: Vec<DocSprite> = Vec::new();
+    for row_idx in 0..view_rows {
+        let line_idx = top_line + row_idx;
+        let screen_row = 1 + row_idx as i32;
+        let Some(line) = lines.get(line_idx) else {
+            break;
+        };
+
+        let line_no_text = format!("{:>5} ", line_idx + 1);
+        let ln_col = if screen_row % 2 == 0 { 7 } else { 8 };
+        draw_text_cells(rt, 0, screen_row, ln_col, UI_BG, &line_no_text);
+
+        let layout = doldoc_view::layout_line(line, &mut style, default, bins);
+        let x0 = LINE_NO_W + layout.indent;
+        sprites.extend(layout.sprites.iter().map(|sp| DocSprite {
+            anchor_line: sp.anchor_line + line_idx,
+            bbox_line0: sp.bbox_line0 + line_idx as i32,
+            bbox_line1: sp.bbox_line1 + line_idx as i32,
+            ..sp.clone()
+        }));
+
+        let cursor_cell = (line_idx == cursor.line).then(|| layout.display_col(cursor.col));
+        let cursor_src = cursor_cell
+            .and_then(|i| layout.cells.get(i))
+            .map(|c| c.src.start);
+        for (i, cell) in layout.cells.iter().enumerate() {
+            if layout.indent + i >= avail_cols {
+                break;
+            }
+            let is_sel = selection.is_some_and(|sel| is_selected(sel, line_idx, cell.src.start));
+            let (fg, bg) = if cursor_src == Some(cell.src.start) {
+                (UI_BG, UI_FG)
+            } else if is_sel {
+                (SEL_FG, SEL_BG)
+            } else {
+                (cell.fg, cell.bg)
+            };
+            draw_cell(rt, (x0 + i) as i32, screen_row, fg, bg, cell.ch);
+        }
+
+        if cursor_cell.is_some_and(|i| i >= layout.cells.len()) {
+            let cx = x0
+                + layout
+                    .cells
+                    .len()
+                    .min(avail_cols.saturating_sub(layout.indent));
+            if cx < cols {
+                draw_cell(rt, cx as i32, screen_row, UI_BG, UI_FG, b' ');
+            }
+        }
+    }
+
+    let view = doldoc::Viewport {
+        x: LINE_NO_W as i32 * FONT_W,
+        y: FONT_H,
+        cols: avail_cols,
+        rows: view_rows,
+        first_line: top_line,
+    };
+    doldoc::render_sprites(rt, &sprites, bins, &view);
+}
+
 #[derive(Clone, Debug)]
 struct HcDiag {
     file: String,
@@ -595,31 +821,46 @@ fn main() -> io::Result<()> {
 
     let mut args = std::env::args().skip(1);
     let path = args.next().map(PathBuf::from);
-    let path = path.unwrap_or_else(|| PathBuf::from("Untitled.txt"));
-    let read_only = is_read_only_templeos_path(&path);
+    let mut path = path.unwrap_or_else(|| PathBuf::from("Untitled.txt"));
+    let mut read_only = is_read_only_templeos_path(&path);
 
-    let mut lines = match read_file_lines(&path) {
+    let (mut lines, mut bins, mut original) = match read_edit_file(&path) {
         Ok(v) => v,
-        Err(err) if err.kind() == io::ErrorKind::NotFound => vec![Vec::new()],
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            (vec![Vec::new()], DocBins::new(), Vec::new())
+        }
         Err(err) => return Err(err),
     };
+    let mut wysiwyg = doldoc_view::is_doldoc_path(&path);
+    let mut other_buffers: Vec<Buffer> = Vec::new();
 
     let mut cursor_line: usize = 0;
     let mut cursor_col: usize = 0;
     let mut top_line: usize = 0;
     let mut modified = false;
     let mut status_msg: String = String::new();
+    // Set by a Ctrl+Q that found unsaved buffers; a second Ctrl+Q quits anyway.
+    let mut quit_armed = false;
 
     let mut ctrl = false;
     let mut shift = false;
     let mut selection: Option<(Pos, Pos)> = None;
     let mut selection_anchor: Option<Pos> = None;
 
-    let mut search_active = false;
-    let mut search_query: String = String::new();
+    let mut prompt: Option<Prompt> = None;
+    let mut prompt_input: String = String::new();
     let mut search_feedback: String = String::new();
+    let mut search_opts = SearchOpts::default();
     let mut last_search: String = String::new();
     let mut last_match_end: Option<Pos> = None;
+    let mut replace_with: String = String::new();
+    let mut replace_session = false;
+    let mut replace_all: Option<ReplaceAllView> = None;
+    let mut grep_query: String = String::new();
+    let mut results: Option<ResultsView> = None;
+    let mut results_open = false;
+    let mut insert_menu = false;
+    let mut link_label: String = String::new();
 
     let mut help: Option<HelpOverlay> = None;
 
@@ -638,7 +879,35 @@ fn main() -> io::Result<()> {
     let (run_tx, run_rx) = mpsc::channel::<RunMsg>();
     let mut build_in_flight = false;
 
+    struct GrepMsg {
+        query: String,
+        root: PathBuf,
+        hits: Vec<FileHit>,
+        truncated: bool,
+    }
+    let (grep_tx, grep_rx) = mpsc::channel::<GrepMsg>();
+    let mut grep_in_flight = false;
+
     loop {
+        while let Ok(msg) = grep_rx.try_recv() {
+            grep_in_flight = false;
+            status_msg = format!(
+                "[{} match{} for '{}'{}]",
+                msg.hits.len(),
+                if msg.hits.len() == 1 { "" } else { "es" },
+                msg.query,
+                if msg.truncated { ", truncated" } else { "" }
+            );
+            results_open = !msg.hits.is_empty();
+            results = Some(ResultsView {
+                title: format!("FIND IN FILES: {} in {}", msg.query, msg.root.display()),
+                root: msg.root,
+                hits: msg.hits,
+                selected: 0,
+                scroll: 0,
+            });
+        }
+
         while let Ok(msg) = run_rx.try_recv() {
             build_in_flight = false;
             match msg {
@@ -691,14 +960,29 @@ fn main() -> io::Result<()> {
                 "HELP: {}  PgUp/PgDn scroll  Esc close",
                 overlay.title.as_str()
             )
+        } else if let Some(view) = replace_all.as_ref() {
+            format!(
+                "REPLACE ALL: {} replacement{} on {} line{}  Enter apply  Esc cancel",
+                view.total,
+                if view.total == 1 { "" } else { "s" },
+                view.previews.len(),
+                if view.previews.len() == 1 { "" } else { "s" }
+            )
+        } else if let Some(view) = results.as_ref().filter(|_| results_open) {
+            format!("{}  Enter jump  Esc close", view.title)
         } else {
             format!(
-                "{}{}{}  Ctrl+S save  Ctrl+Q quit  Ctrl+F find  F3 next  F5 run  F1 help",
+                "{}{}{}  Ctrl+S save (+Shift all)  Ctrl+Q quit  Ctrl+F find  F3 next  F5 run  F1 help",
                 path.display(),
                 if read_only { " [RO]" } else { "" },
                 if modified { " *" } else { "" }
             )
         };
+        let title = if other_buffers.is_empty() || help.is_some() {
+            title
+        } else {
+            format!("{title}  F6 +{} buf", other_buffers.len())
+        };
         draw_text_cells(&mut rt, 0, 0, UI_FG, BAR_BG, &title);
 
         // Bottom status bar
@@ -709,13 +993,43 @@ fn main() -> io::Result<()> {
             format!("Help  PgUp/PgDn scroll  Esc close")
         } else if build_in_flight {
             "[building...]".to_string()
-        } else if search_active {
-            let mut s = format!("Find: {search_query}  Enter find  Esc cancel  F3 next");
+        } else if grep_in_flight {
+            "[searching files...]".to_string()
+        } else if insert_menu {
+            doldoc_view::INSERT_MENU.to_string()
+        } else if let Some(kind) = prompt.filter(|k| k.is_insert()) {
+            let mut s = format!("{}: {prompt_input}  Enter ok  Esc cancel", kind.label());
+            if !search_feedback.is_empty() {
+                s.push_str("  ");
+                s.push_str(&search_feedback);
+            }
+            s
+        } else if let Some(kind) = prompt {
+            let mut s = format!(
+                "{} {}: {prompt_input}  Enter ok  Esc cancel  F9 case F10 word F11 regex",
+                kind.label(),
+                search_opts.flags_label()
+            );
+            if !search_feedback.is_empty() {
+                s.push_str("  ");
+                s.push_str(&search_feedback);
+            }
+            s
+        } else if replace_session {
+            let mut s = format!(
+                "Replace {}: Enter replace  N skip  A all  Esc stop",
+                search_opts.flags_label()
+            );
             if !search_feedback.is_empty() {
                 s.push_str("  ");
                 s.push_str(&search_feedback);
             }
             s
+        } else if status_msg.is_empty() && doldoc_view::is_doldoc_path(&path) {
+            format!(
+                "Ln {line_no}  Col {col_no}  F7 {} view  F8 insert  Ctrl+C copy  Ctrl+X cut  Ctrl+A all",
+                if wysiwyg { "source" } else { "doc" }
+            )
         } else if status_msg.is_empty() {
             format!(
                 "Ln {line_no}  Col {col_no}  PgUp/PgDn scroll  F5 run  Tab=spaces  Ctrl+C copy  Ctrl+X cut  Ctrl+A all"
@@ -725,8 +1039,44 @@ fn main() -> io::Result<()> {
         };
         draw_text_cells(&mut rt, 0, rows as i32 - 1, UI_FG, BAR_BG, &status);
 
-        // Text area (or help overlay)
-        if let Some(overlay) = help.as_ref() {
+        // Text area (or help / replace preview / results overlay)
+        if let Some(view) = replace_all.as_ref() {
+            let mut rows_out: Vec<(u8, Vec<u8>)> = Vec::new();
+            for p in &view.previews {
+                let mut before = format!("{:>5}- ", p.line + 1).into_bytes();
+                before.extend_from_slice(&p.before);
+                let mut after = format!("{:>5}+ ", p.line + 1).into_bytes();
+                after.extend_from_slice(&p.after);
+                rows_out.push((12, before));
+                rows_out.push((10, after));
+            }
+            for (row_idx, (fg, text)) in rows_out
+                .iter()
+                .skip(view.scroll)
+                .take(view_rows)
+                .enumerate()
+            {
+                draw_plain_line(&mut rt, 0, 1 + row_idx as i32, *fg, UI_BG, text, cols);
+            }
+        } else if let Some(view) = results.as_ref().filter(|_| results_open) {
+            for row_idx in 0..view_rows {
+                let idx = view.scroll + row_idx;
+                let Some(hit) = view.hits.get(idx) else {
+                    break;
+                };
+                let (fg, bg) = if idx == view.selected {
+                    (SEL_FG, SEL_BG)
+                } else {
+                    (UI_FG, UI_BG)
+                };
+                let screen_row = 1 + row_idx as i32;
+                if idx == view.selected {
+                    rt.fill_rect(0, screen_row * FONT_H, w, FONT_H, SEL_BG);
+                }
+                let text = view.row_text(hit);
+                draw_plain_line(&mut rt, 0, screen_row, fg, bg, text.as_bytes(), cols);
+            }
+        } else if let Some(overlay) = help.as_ref() {
             for row_idx in 0..view_rows {
                 let screen_row = 1 + row_idx;
                 let doc_idx = overlay.scroll + row_idx;
@@ -741,6 +1091,16 @@ fn main() -> io::Result<()> {
                     cols,
                 );
             }
+        } else if wysiwyg {
+            draw_doldoc_lines(
+                &mut rt,
+                &lines,
+                &bins,
+                top_line,
+                view_rows,
+                Pos::new(cursor_line, cursor_col),
+                selection,
+            );
         } else {
             for row_idx in 0..view_rows {
                 let line_idx = top_line + row_idx;
@@ -824,17 +1184,37 @@ fn main() -> io::Result<()> {
                         continue;
                     }
 
-                    if !search_active {
+                    if prompt.is_none() && !replace_session {
                         status_msg.clear();
                     }
+                    let quit_confirmed = std::mem::take(&mut quit_armed);
 
                     if ctrl {
                         match code {
+                            KEY_S_LOWER | KEY_S_UPPER if shift => {
+                                let current = (modified && !read_only)
+                                    .then(|| write_edit_file(&path, &lines, &bins, &mut original));
+                                status_msg = match current {
+                                    Some(Err(err)) => format!("[save error: {err}]"),
+                                    _ => {
+                                        if current.is_some() {
+                                            modified = false;
+                                        }
+                                        match save_buffers(&mut other_buffers) {
+                                            Ok(n) => format!(
+                                                "[saved {} buffer(s)]",
+                                                n + usize::from(current.is_some())
+                                            ),
+                                            Err(err) => format!("[save error: {err}]"),
+                                        }
+                                    }
+                                };
+                            }
                             KEY_S_LOWER | KEY_S_UPPER => {
                                 if read_only {
                                     status_msg = "[read-only]".to_string();
                                 } else {
-                                    match write_file_lines(&path, &lines) {
+                                    match write_edit_file(&path, &lines, &bins, &mut original) {
                                         Ok(()) => {
                                             modified = false;
                                             status_msg = "[saved]".to_string();
@@ -845,7 +1225,20 @@ fn main() -> io::Result<()> {
                                     }
                                 }
                             }
-                            KEY_Q_LOWER | KEY_Q_UPPER => return Ok(()),
+                            KEY_Q_LOWER | KEY_Q_UPPER => {
+                                let unsaved = usize::from(modified && !read_only)
+                                    + other_buffers
+                                        .iter()
+                                        .filter(|b| b.modified && !b.read_only)
+                                        .count();
+                                if unsaved == 0 || quit_confirmed {
+                                    return Ok(());
+                                }
+                                quit_armed = true;
+                                status_msg = format!(
+                                    "[{unsaved} unsaved buffer(s): Ctrl+Shift+S save all, Ctrl+Q again to quit]"
+                                );
+                            }
                             KEY_C_LOWER | KEY_C_UPPER => {
                                 let text = if let Some(sel) = selection {
                                     if selection_is_empty(sel) {
@@ -900,10 +1293,26 @@ fn main() -> io::Result<()> {
                                 selection = Some((Pos::new(0, 0), Pos::new(end_line, end_col)));
                                 selection_anchor = None;
                             }
-                            KEY_F_LOWER | KEY_F_UPPER => {
-                                search_active = true;
-                                search_query.clear();
+                            KEY_F_LOWER | KEY_F_UPPER | KEY_R_LOWER | KEY_R_UPPER | KEY_G_LOWER
+                            | KEY_G_UPPER => {
+                                let kind = match code {
+                                    KEY_F_LOWER | KEY_F_UPPER => Prompt::Find,
+                                    KEY_R_LOWER | KEY_R_UPPER => Prompt::Replace,
+                                    _ => Prompt::FindInFiles,
+                                };
+                                if kind == Prompt::Replace && read_only {
+                                    status_msg = "[read-only]".to_string();
+                                    continue;
+                                }
+                                prompt = Some(kind);
+                                prompt_input = selection
+                                    .filter(|sel| {
+                                        !selection_is_empty(*sel) && sel.0.line == sel.1.line
+                                    })
+                                    .map(|sel| selected_text(&lines, sel))
+                                    .unwrap_or_default();
                                 search_feedback.clear();
+                                replace_session = false;
                             }
                             protocol::KEY_HOME => {
                                 top_line = 0;
@@ -951,60 +1360,411 @@ fn main() -> io::Result<()> {
                         continue;
                     }
 
-                    if search_active {
+                    if let Some(view) = replace_all.as_mut() {
+                        let view_len = view.previews.len() * 2;
+                        let max_scroll = view_len.saturating_sub(view_rows.max(1));
                         match code {
                             protocol::KEY_ESCAPE => {
-                                search_active = false;
-                                search_feedback.clear();
+                                replace_all = None;
+                                status_msg = "[replace all cancelled]".to_string();
                             }
                             protocol::KEY_ENTER => {
-                                let query = search_query.trim().to_string();
-                                let q = query.as_bytes();
-                                if q.is_empty() {
-                                    search_feedback = "[empty]".to_string();
-                                    continue;
+                                let total = view.total;
+                                for p in view.previews.drain(..) {
+                                    if let Some(line) = lines.get_mut(p.line) {
+                                        *line = p.after;
+                                    }
                                 }
+                                replace_all = None;
+                                cursor_col = cursor_col.min(lines[cursor_line].len());
+                                selection = None;
+                                selection_anchor = None;
+                                modified = true;
+                                status_msg = format!("[replaced {total}]");
+                            }
+                            protocol::KEY_UP => view.scroll = view.scroll.saturating_sub(1),
+                            protocol::KEY_DOWN => view.scroll = (view.scroll + 1).min(max_scroll),
+                            protocol::KEY_PAGE_UP => {
+                                view.scroll = view.scroll.saturating_sub(view_rows.max(1));
+                            }
+                            protocol::KEY_PAGE_DOWN => {
+                                view.scroll = (view.scroll + view_rows.max(1)).min(max_scroll);
+                            }
+                            _ => {}
+                        }
+                        continue;
+                    }
 
-                                let start = Pos::new(cursor_line, cursor_col);
-                                if let Some((m0, m1)) = find_next(&lines, q, start) {
-                                    cursor_line = m0.line;
-                                    cursor_col = m0.col;
-                                    selection = Some((m0, m1));
-                                    selection_anchor = None;
-                                    last_search = query;
-                                    last_match_end = Some(m1);
-                                    search_active = false;
-                                    search_feedback.clear();
-                                } else {
-                                    search_feedback = "[not found]".to_string();
+                    if code == protocol::KEY_F9
+                        || code == protocol::KEY_F10
+                        || code == protocol::KEY_F11
+                    {
+                        match code {
+                            protocol::KEY_F9 => search_opts.case_sensitive ^= true,
+                            protocol::KEY_F10 => search_opts.whole_word ^= true,
+                            _ => search_opts.regex ^= true,
+                        }
+                        if prompt.is_none() && !replace_session {
+                            status_msg = format!("[find options {}]", search_opts.flags_label());
+                        }
+                        continue;
+                    }
+
+                    if let Some(view) = results.as_mut().filter(|_| results_open) {
+                        let view_len = view.hits.len();
+                        let page = view_rows.max(1);
+                        match code {
+                            protocol::KEY_ESCAPE | protocol::KEY_F4 => results_open = false,
+                            protocol::KEY_UP => view.selected = view.selected.saturating_sub(1),
+                            protocol::KEY_DOWN => {
+                                view.selected = (view.selected + 1).min(view_len.saturating_sub(1));
+                            }
+                            protocol::KEY_PAGE_UP => {
+                                view.selected = view.selected.saturating_sub(page)
+                            }
+                            protocol::KEY_PAGE_DOWN => {
+                                view.selected =
+                                    (view.selected + page).min(view_len.saturating_sub(1));
+                            }
+                            protocol::KEY_HOME => view.selected = 0,
+                            protocol::KEY_END => view.selected = view_len.saturating_sub(1),
+                            protocol::KEY_ENTER => {
+                                let Some(hit) = view.hits.get(view.selected).cloned() else {
+                                    continue;
+                                };
+                                if !same_file(&hit.path, &path) {
+                                    let next =
+                                        match take_or_open_buffer(&mut other_buffers, &hit.path) {
+                                            Ok(next) => next,
+                                            Err(err) => {
+                                                status_msg = format!("[open error: {err}]");
+                                                continue;
+                                            }
+                                        };
+                                    other_buffers.push(Buffer {
+                                        path: std::mem::replace(&mut path, next.path),
+                                        lines: std::mem::replace(&mut lines, next.lines),
+                                        bins: std::mem::replace(&mut bins, next.bins),
+                                        original: std::mem::replace(&mut original, next.original),
+                                        cursor_line,
+                                        cursor_col,
+                                        top_line,
+                                        modified,
+                                        read_only,
+                                        wysiwyg,
+                                    });
+                                    top_line = next.top_line;
+                                    modified = next.modified;
+                                    read_only = next.read_only;
+                                    wysiwyg = next.wysiwyg;
                                 }
+                                let m0 =
+                                    Pos::new(hit.line.min(lines.len().saturating_sub(1)), hit.col);
+                                let m0 = Pos::new(m0.line, m0.col.min(lines[m0.line].len()));
+                                let m1 = Pos::new(m0.line, hit.end_col.min(lines[m0.line].len()));
+                                cursor_line = m0.line;
+                                cursor_col = m0.col;
+                                selection = Some((m0, m1));
+                                selection_anchor = None;
+                                last_search = grep_query.clone();
+                                last_match_end = Some(m1);
+                                results_open = false;
                             }
-                            protocol::KEY_BACKSPACE => {
-                                search_query.pop();
+                            _ => {}
+                        }
+                        let page = view_rows.max(1);
+                        if view.selected < view.scroll {
+                            view.scroll = view.selected;
+                        } else if view.selected >= view.scroll + page {
+                            view.scroll = view.selected + 1 - page;
+                        }
+                        continue;
+                    }
+
+                    if insert_menu {
+                        insert_menu = false;
+                        if let Some(kind) = InsertKind::from_key(code) {
+                            prompt = Some(Prompt::Insert(kind));
+                            prompt_input.clear();
+                            search_feedback.clear();
+                        }
+                        continue;
+                    }
+
+                    if let Some(kind) = prompt {
+                        match code {
+                            protocol::KEY_ESCAPE => {
+                                prompt = None;
+                                search_feedback.clear();
                             }
-                            protocol::KEY_F3 => {
-                                if last_search.is_empty() {
-                                    search_feedback = "[no previous search]".to_string();
-                                } else {
-                                    let q = last_search.as_bytes();
-                                    let start =
-                                        last_match_end.unwrap_or(Pos::new(cursor_line, cursor_col));
-                                    if let Some((m0, m1)) = find_next(&lines, q, start) {
+                            protocol::KEY_ENTER if kind.is_insert() => {
+                                let input = prompt_input.clone();
+                                let at = Pos::new(cursor_line, cursor_col);
+                                // Text before the cursor, and text that stays after it.
+                                let (head, tail) = match kind {
+                                    Prompt::Insert(InsertKind::Text) => {
+                                        (doldoc_view::text_cmd(&input), String::new())
+                                    }
+                                    Prompt::Insert(InsertKind::Link) => {
+                                        if input.is_empty() {
+                                            search_feedback = "[empty]".to_string();
+                                            continue;
+                                        }
+                                        link_label = input;
+                                        prompt = Some(Prompt::InsertLinkTarget);
+                                        prompt_input.clear();
+                                        search_feedback.clear();
+                                        continue;
+                                    }
+                                    Prompt::InsertLinkTarget => (
+                                        doldoc_view::link_cmd(&link_label, input.trim()),
+                                        String::new(),
+                                    ),
+                                    Prompt::Insert(InsertKind::Fg)
+                                    | Prompt::Insert(InsertKind::Bg) => {
+                                        let op = if kind == Prompt::Insert(InsertKind::Fg) {
+                                            "FG"
+                                        } else {
+                                            "BG"
+                                        };
+                                        match doldoc_view::color_cmd(op, &input) {
+                                            Ok(v) => (v, String::new()),
+                                            Err(err) => {
+                                                search_feedback = format!("[{err}]");
+                                                continue;
+                                            }
+                                        }
+                                    }
+                                    Prompt::Insert(InsertKind::Tree) => {
+                                        doldoc_view::tree_cmds(&input)
+                                    }
+                                    Prompt::Insert(InsertKind::Sprite) => {
+                                        match doldoc_view::sprite_bin_for(
+                                            &mut bins, &input, &original,
+                                        ) {
+                                            Ok(bin) => {
+                                                (doldoc_view::sprite_cmd(bin), String::new())
+                                            }
+                                            Err(err) => {
+                                                search_feedback = format!("[{err}]");
+                                                continue;
+                                            }
+                                        }
+                                    }
+                                    _ => continue,
+                                };
+                                let end = insert_text(&mut lines, at, &head);
+                                insert_text(&mut lines, end, &tail);
+                                cursor_line = end.line;
+                                cursor_col = end.col;
+                                selection = None;
+                                selection_anchor = None;
+                                modified = true;
+                                prompt = None;
+                                search_feedback.clear();
+                            }
+                            protocol::KEY_ENTER => {
+                                let input = prompt_input.clone();
+                                if input.is_empty() && kind != Prompt::ReplaceWith {
+                                    search_feedback = "[empty]".to_string();
+                                    continue;
+                                }
+                                let searcher = match kind {
+                                    Prompt::Find | Prompt::Replace | Prompt::FindInFiles => {
+                                        match Searcher::new(&input, search_opts) {
+                                            Ok(v) => Some(v),
+                                            Err(err) => {
+                                                search_feedback = format!("[bad pattern: {err}]");
+                                                continue;
+                                            }
+                                        }
+                                    }
+                                    _ => None,
+                                };
+                                match kind {
+                                    Prompt::Find | Prompt::Replace => {
+                                        let Some(searcher) = searcher else {
+                                            continue;
+                                        };
+                                        let start = Pos::new(cursor_line, cursor_col);
+                                        let Some((m0, m1)) =
+                                            search::find_next(&lines, &searcher, start)
+                                        else {
+                                            search_feedback = "[not found]".to_string();
+                                            continue;
+                                        };
                                         cursor_line = m0.line;
                                         cursor_col = m0.col;
                                         selection = Some((m0, m1));
                                         selection_anchor = None;
+                                        last_search = input;
                                         last_match_end = Some(m1);
                                         search_feedback.clear();
-                                    } else {
-                                        search_feedback = "[not found]".to_string();
+                                        if kind == Prompt::Replace {
+                                            prompt = Some(Prompt::ReplaceWith);
+                                            prompt_input = replace_with.clone();
+                                        } else {
+                                            prompt = None;
+                                        }
+                                    }
+                                    Prompt::ReplaceWith => {
+                                        replace_with = input;
+                                        prompt = None;
+                                        replace_session = true;
+                                        search_feedback.clear();
+                                    }
+                                    Prompt::FindInFiles => {
+                                        grep_query = input;
+                                        prompt = Some(Prompt::FindInDir);
+                                        prompt_input = path
+                                            .parent()
+                                            .filter(|p| !p.as_os_str().is_empty())
+                                            .map(|p| p.to_string_lossy().to_string())
+                                            .unwrap_or_else(|| ".".to_string());
+                                        search_feedback.clear();
                                     }
+                                    Prompt::FindInDir => {
+                                        if grep_in_flight {
+                                            search_feedback =
+                                                "[search already running]".to_string();
+                                            continue;
+                                        }
+                                        let searcher = match Searcher::new(&grep_query, search_opts)
+                                        {
+                                            Ok(v) => v,
+                                            Err(err) => {
+                                                search_feedback = format!("[bad pattern: {err}]");
+                                                continue;
+                                            }
+                                        };
+                                        let root = PathBuf::from(input);
+                                        if !root.is_dir() {
+                                            search_feedback = "[not a directory]".to_string();
+                                            continue;
+                                        }
+
+                                        // Search unsaved buffers from memory rather than disk.
+                                        let mut unsaved: Vec<(PathBuf, Vec<Vec<u8>>)> =
+                                            other_buffers
+                                                .iter()
+                                                .filter(|b| b.modified)
+                                                .map(|b| (b.path.clone(), b.lines.clone()))
+                                                .collect();
+                                        if modified {
+                                            unsaved.push((path.clone(), lines.clone()));
+                                        }
+
+                                        prompt = None;
+                                        search_feedback.clear();
+                                        grep_in_flight = true;
+                                        let tx = grep_tx.clone();
+                                        let query = grep_query.clone();
+                                        thread::spawn(move || {
+                                            let (hits, truncated) =
+                                                search::find_in_files(&root, &searcher, &unsaved);
+                                            let _ = tx.send(GrepMsg {
+                                                query,
+                                                root,
+                                                hits,
+                                                truncated,
+                                            });
+                                        });
+                                    }
+                                    Prompt::Insert(_) | Prompt::InsertLinkTarget => {}
                                 }
                             }
+                            protocol::KEY_BACKSPACE => {
+                                prompt_input.pop();
+                            }
                             _ if code <= 0xFF => {
                                 let ch = code as u8 as char;
                                 if ch.is_ascii_graphic() || ch == ' ' {
-                                    search_query.push(ch);
+                                    prompt_input.push(ch);
+                                }
+                            }
+                            _ => {}
+                        }
+                        continue;
+                    }
+
+                    if replace_session {
+                        let searcher = match Searcher::new(&last_search, search_opts) {
+                            Ok(v) => v,
+                            Err(err) => {
+                                replace_session = false;
+                                status_msg = format!("[bad pattern: {err}]");
+                                continue;
+                            }
+                        };
+                        match code {
+                            protocol::KEY_ESCAPE => {
+                                replace_session = false;
+                                search_feedback.clear();
+                            }
+                            protocol::KEY_ENTER => {
+                                let Some((m0, _)) =
+                                    selection.map(|sel| normalize_sel(sel.0, sel.1))
+                                else {
+                                    search_feedback = "[no match selected]".to_string();
+                                    continue;
+                                };
+                                let line = &lines[m0.line];
+                                let Some(m) = searcher
+                                    .find_in_line(line, m0.col)
+                                    .filter(|m| m.start == m0.col)
+                                else {
+                                    search_feedback = "[selection is not a match]".to_string();
+                                    continue;
+                                };
+                                let repl = searcher.expand(line, &m, &replace_with);
+                                let after = m.start + repl.len();
+                                lines[m0.line].splice(m.start..m.end, repl);
+                                modified = true;
+
+                                let start = Pos::new(m0.line, after);
+                                if let Some((n0, n1)) = search::find_next(&lines, &searcher, start)
+                                {
+                                    cursor_line = n0.line;
+                                    cursor_col = n0.col;
+                                    selection = Some((n0, n1));
+                                    last_match_end = Some(n1);
+                                    search_feedback.clear();
+                                } else {
+                                    cursor_line = m0.line;
+                                    cursor_col = after;
+                                    selection = None;
+                                    replace_session = false;
+                                    status_msg = "[no more matches]".to_string();
+                                }
+                                selection_anchor = None;
+                            }
+                            KEY_N_LOWER | KEY_N_UPPER | protocol::KEY_F3 => {
+                                let start =
+                                    last_match_end.unwrap_or(Pos::new(cursor_line, cursor_col));
+                                if let Some((n0, n1)) = search::find_next(&lines, &searcher, start)
+                                {
+                                    cursor_line = n0.line;
+                                    cursor_col = n0.col;
+                                    selection = Some((n0, n1));
+                                    selection_anchor = None;
+                                    last_match_end = Some(n1);
+                                } else {
+                                    search_feedback = "[not found]".to_string();
+                                }
+                            }
+                            KEY_A_LOWER | KEY_A_UPPER => {
+                                let previews =
+                                    search::preview_replace_all(&lines, &searcher, &replace_with);
+                                replace_session = false;
+                                if previews.is_empty() {
+                                    status_msg = "[not found]".to_string();
+                                } else {
+                                    let total = previews.iter().map(|p| p.count).sum();
+                                    replace_all = Some(ReplaceAllView {
+                                        previews,
+                                        total,
+                                        scroll: 0,
+                                    });
                                 }
                             }
                             _ => {}
@@ -1012,6 +1772,10 @@ fn main() -> io::Result<()> {
                         continue;
                     }
 
+                    if wysiwyg {
+                        cursor_col = doldoc_view::snap_col(&lines[cursor_line], cursor_col);
+                    }
+
                     match code {
                         protocol::KEY_F1 => {
                             if let Some(topic) = topic_from_cursor_or_selection(
@@ -1040,7 +1804,7 @@ fn main() -> io::Result<()> {
                                     status_msg = "[read-only]".to_string();
                                     continue;
                                 }
-                                match write_file_lines(&path, &lines) {
+                                match write_edit_file(&path, &lines, &bins, &mut original) {
                                     Ok(()) => {
                                         modified = false;
                                     }
@@ -1112,10 +1876,24 @@ fn main() -> io::Result<()> {
                             if last_search.is_empty() {
                                 status_msg = "[no previous search]".to_string();
                             } else {
-                                let q = last_search.as_bytes();
-                                let start =
-                                    last_match_end.unwrap_or(Pos::new(cursor_line, cursor_col));
-                                if let Some((m0, m1)) = find_next(&lines, q, start) {
+                                let searcher = match Searcher::new(&last_search, search_opts) {
+                                    Ok(v) => v,
+                                    Err(err) => {
+                                        status_msg = format!("[bad pattern: {err}]");
+                                        continue;
+                                    }
+                                };
+                                let hit = if shift {
+                                    let start = selection
+                                        .map(|sel| normalize_sel(sel.0, sel.1).0)
+                                        .unwrap_or(Pos::new(cursor_line, cursor_col));
+                                    search::find_prev(&lines, &searcher, start)
+                                } else {
+                                    let start =
+                                        last_match_end.unwrap_or(Pos::new(cursor_line, cursor_col));
+                                    search::find_next(&lines, &searcher, start)
+                                };
+                                if let Some((m0, m1)) = hit {
                                     cursor_line = m0.line;
                                     cursor_col = m0.col;
                                     selection = Some((m0, m1));
@@ -1126,11 +1904,70 @@ fn main() -> io::Result<()> {
                                 }
                             }
                         }
+                        protocol::KEY_F4 => {
+                            if results.as_ref().is_some_and(|r| !r.hits.is_empty()) {
+                                results_open = true;
+                            } else {
+                                status_msg = "[no find-in-files results]".to_string();
+                            }
+                        }
+                        protocol::KEY_F6 => {
+                            if other_buffers.is_empty() {
+                                status_msg = "[no other buffers]".to_string();
+                                continue;
+                            }
+                            let next = other_buffers.remove(0);
+                            other_buffers.push(Buffer {
+                                path: std::mem::replace(&mut path, next.path),
+                                lines: std::mem::replace(&mut lines, next.lines),
+                                bins: std::mem::replace(&mut bins, next.bins),
+                                original: std::mem::replace(&mut original, next.original),
+                                cursor_line,
+                                cursor_col,
+                                top_line,
+                                modified,
+                                read_only,
+                                wysiwyg,
+                            });
+                            cursor_line = next.cursor_line;
+                            cursor_col = next.cursor_col;
+                            top_line = next.top_line;
+                            modified = next.modified;
+                            read_only = next.read_only;
+                            wysiwyg = next.wysiwyg;
+                            selection = None;
+                            selection_anchor = None;
+                            last_match_end = None;
+                        }
+                        protocol::KEY_F7 => {
+                            wysiwyg = !wysiwyg;
+                            status_msg = if wysiwyg {
+                                "[doc view]".to_string()
+                            } else {
+                                "[source view]".to_string()
+                            };
+                        }
+                        protocol::KEY_F8 => {
+                            if read_only {
+                                status_msg = "[read-only]".to_string();
+                            } else {
+                                wysiwyg = true;
+                                insert_menu = true;
+                            }
+                        }
                         protocol::KEY_UP => {
                             let prev = Pos::new(cursor_line, cursor_col);
                             if cursor_line > 0 {
                                 cursor_line -= 1;
-                                cursor_col = cursor_col.min(lines[cursor_line].len());
+                                cursor_col = if wysiwyg {
+                                    doldoc_view::vertical_col(
+                                        &lines[prev.line],
+                                        prev.col,
+                                        &lines[cursor_line],
+                                    )
+                                } else {
+                                    cursor_col.min(lines[cursor_line].len())
+                                };
                             }
                             if shift {
                                 selection_anchor.get_or_insert(prev);
@@ -1146,7 +1983,15 @@ fn main() -> io::Result<()> {
                             let prev = Pos::new(cursor_line, cursor_col);
                             if cursor_line + 1 < lines.len() {
                                 cursor_line += 1;
-                                cursor_col = cursor_col.min(lines[cursor_line].len());
+                                cursor_col = if wysiwyg {
+                                    doldoc_view::vertical_col(
+                                        &lines[prev.line],
+                                        prev.col,
+                                        &lines[cursor_line],
+                                    )
+                                } else {
+                                    cursor_col.min(lines[cursor_line].len())
+                                };
                             }
                             if shift {
                                 selection_anchor.get_or_insert(prev);
@@ -1161,7 +2006,11 @@ fn main() -> io::Result<()> {
                         protocol::KEY_LEFT => {
                             let prev = Pos::new(cursor_line, cursor_col);
                             if cursor_col > 0 {
-                                cursor_col -= 1;
+                                cursor_col = if wysiwyg {
+                                    doldoc_view::prev_col(&lines[cursor_line], cursor_col)
+                                } else {
+                                    cursor_col - 1
+                                };
                             } else if cursor_line > 0 {
                                 cursor_line -= 1;
                                 cursor_col = lines[cursor_line].len();
@@ -1179,7 +2028,11 @@ fn main() -> io::Result<()> {
                         protocol::KEY_RIGHT => {
                             let prev = Pos::new(cursor_line, cursor_col);
                             if cursor_col < lines[cursor_line].len() {
-                                cursor_col += 1;
+                                cursor_col = if wysiwyg {
+                                    doldoc_view::next_col(&lines[cursor_line], cursor_col)
+                                } else {
+                                    cursor_col + 1
+                                };
                             } else if cursor_line + 1 < lines.len() {
                                 cursor_line += 1;
                                 cursor_col = 0;
@@ -1269,8 +2122,13 @@ fn main() -> io::Result<()> {
                                 continue;
                             }
                             if cursor_col > 0 {
-                                cursor_col -= 1;
-                                lines[cursor_line].remove(cursor_col);
+                                let start = if wysiwyg {
+                                    doldoc_view::prev_col(&lines[cursor_line], cursor_col)
+                                } else {
+                                    cursor_col - 1
+                                };
+                                lines[cursor_line].drain(start..cursor_col);
+                                cursor_col = start;
                                 modified = true;
                             } else if cursor_line > 0 {
                                 let cur = lines.remove(cursor_line);
@@ -1295,7 +2153,12 @@ fn main() -> io::Result<()> {
                                 continue;
                             }
                             if cursor_col < lines[cursor_line].len() {
-                                lines[cursor_line].remove(cursor_col);
+                                let end = if wysiwyg {
+                                    doldoc_view::next_col(&lines[cursor_line], cursor_col)
+                                } else {
+                                    cursor_col + 1
+                                };
+                                lines[cursor_line].drain(cursor_col..end);
                                 modified = true;
                             } else if cursor_line + 1 < lines.len() {
                                 let next = lines.remove(cursor_line + 1);
@@ -1349,7 +2212,12 @@ fn main() -> io::Result<()> {
                                 &mut selection,
                             );
                             let ch = code as u8;
-                            if (ch as char).is_ascii_graphic() || ch == b' ' {
+                            if wysiwyg && ch == b'$' {
+                                // A lone `$` would start a command; `$$` shows as one dollar.
+                                lines[cursor_line].splice(cursor_col..cursor_col, *b"$$");
+                                cursor_col += 2;
+                                modified = true;
+                            } else if (ch as char).is_ascii_graphic() || ch == b' ' {
                                 lines[cursor_line].insert(cursor_col, ch);
                                 cursor_col += 1;
                                 modified = true;
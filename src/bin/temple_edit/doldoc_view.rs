@@ -0,0 +1,396 @@
+//! WYSIWYG layout for DolDoc source (`.DD` files, toggled with F7).
+//!
+//! The buffer always holds DolDoc source; this module only decides how each source line is
+//! drawn. Every displayed cell remembers the byte range of the source it came from, so cursor
+//! movement and deletion treat a whole `$LK,...$` (or `$$`) as a single character.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    ops::Range,
+    path::Path,
+};
+
+use temple_rt::{
+    assets,
+    doldoc::{self, DocSprite, DocStyle, LayoutOptions, Layouter},
+};
+
+/// Width handed to the layout engine; edit layouts don't wrap, so this only bounds `$ID$`.
+const LAYOUT_COLS: usize = 80;
+
+pub(super) fn is_doldoc_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("DD"))
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct Cell {
+    pub ch: u8,
+    pub fg: u8,
+    pub bg: u8,
+    pub src: Range<usize>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(super) struct LineLayout {
+    /// Blank columns drawn before the first cell (`$ID,n$`).
+    pub indent: usize,
+    pub cells: Vec<Cell>,
+    /// Sprites anchored on this line (line 0), columns counted from the left edge.
+    pub sprites: Vec<DocSprite>,
+}
+
+impl LineLayout {
+    /// Display column of the cursor at source byte `col`.
+    pub fn display_col(&self, col: usize) -> usize {
+        self.cells
+            .iter()
+            .position(|c| c.src.start >= col)
+            .unwrap_or(self.cells.len())
+    }
+
+    /// Source byte offset for display column `disp` (past the end means end of line).
+    pub fn source_col(&self, disp: usize, line_len: usize) -> usize {
+        self.cells
+            .get(disp)
+            .map(|c| c.src.start)
+            .unwrap_or(line_len)
+    }
+}
+
+/// Lays out one source line with the shared DolDoc engine in edit mode, updating `style` for
+/// the lines that follow.
+pub(super) fn layout_line(
+    line: &[u8],
+    style: &mut DocStyle,
+    default: DocStyle,
+    bins: &BTreeMap<u32, Vec<u8>>,
+) -> LineLayout {
+    let text = String::from_utf8_lossy(line);
+    let opts = LayoutOptions::edit(LAYOUT_COLS, default.fg, default.bg);
+    let mut engine = Layouter::new(opts, bins).with_style(*style);
+    for entry in doldoc::parse(&text) {
+        engine.push(&entry);
+    }
+    let indent = style.indent;
+    *style = engine.style();
+    let doc = engine.finish();
+
+    let cells = doc
+        .lines
+        .into_iter()
+        .next()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|c| {
+            c.src.map(|src| Cell {
+                ch: c.ch,
+                fg: c.fg,
+                bg: c.bg,
+                src,
+            })
+        })
+        .collect();
+    LineLayout {
+        indent,
+        cells,
+        sprites: doc.sprites,
+    }
+}
+
+/// Layout of `line` ignoring colors; enough for cursor movement.
+fn plain_layout(line: &[u8]) -> LineLayout {
+    let default = DocStyle::new(15, 0);
+    let mut style = default;
+    layout_line(line, &mut style, default, &BTreeMap::new())
+}
+
+/// Moves `col` back to the start of the cell it falls in.
+pub(super) fn snap_col(line: &[u8], col: usize) -> usize {
+    let col = col.min(line.len());
+    plain_layout(line)
+        .cells
+        .iter()
+        .find(|c| c.src.contains(&col))
+        .map(|c| c.src.start)
+        .unwrap_or(col)
+}
+
+pub(super) fn next_col(line: &[u8], col: usize) -> usize {
+    let col = snap_col(line, col);
+    plain_layout(line)
+        .cells
+        .iter()
+        .find(|c| c.src.start == col)
+        .map(|c| c.src.end)
+        .unwrap_or(line.len())
+}
+
+pub(super) fn prev_col(line: &[u8], col: usize) -> usize {
+    let col = snap_col(line, col);
+    plain_layout(line)
+        .cells
+        .iter()
+        .rev()
+        .find(|c| c.src.start < col)
+        .map(|c| c.src.start)
+        .unwrap_or(0)
+}
+
+/// Source column on `line` that is displayed at the same column as `col` on `from`.
+pub(super) fn vertical_col(from: &[u8], col: usize, line: &[u8]) -> usize {
+    let disp = plain_layout(from).display_col(col);
+    plain_layout(line).source_col(disp, line.len())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum InsertKind {
+    Text,
+    Link,
+    Fg,
+    Bg,
+    Tree,
+    Sprite,
+}
+
+impl InsertKind {
+    pub fn from_key(code: u32) -> Option<Self> {
+        match (code as u8).to_ascii_lowercase() {
+            b't' => Some(InsertKind::Text),
+            b'l' => Some(InsertKind::Link),
+            b'f' => Some(InsertKind::Fg),
+            b'b' => Some(InsertKind::Bg),
+            b'r' => Some(InsertKind::Tree),
+            b's' => Some(InsertKind::Sprite),
+            _ => None,
+        }
+    }
+}
+
+pub(super) const INSERT_MENU: &str =
+    "Insert: T text  L link  F fg color  B bg color  R tree  S sprite  Esc cancel";
+
+pub(super) fn text_cmd(text: &str) -> String {
+    format!("$TX,{}$", doldoc::quote_arg(text))
+}
+
+pub(super) fn link_cmd(label: &str, target: &str) -> String {
+    if target.is_empty() || target == label {
+        format!("$LK,{}$", doldoc::quote_arg(label))
+    } else {
+        format!(
+            "$LK,{},A={}$",
+            doldoc::quote_arg(label),
+            doldoc::quote_arg(target)
+        )
+    }
+}
+
+/// `$FG,RED$`, or `$FG$` (back to the default color) for empty input.
+pub(super) fn color_cmd(op: &str, input: &str) -> Result<String, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(format!("${op}$"));
+    }
+    doldoc::parse_color(input)
+        .map(|c| format!("${op},{}$", doldoc::color_name(c)))
+        .ok_or_else(|| format!("unknown color '{input}'"))
+}
+
+/// A tree node and an indented body; the cursor goes on the body line.
+pub(super) fn tree_cmds(label: &str) -> (String, String) {
+    (
+        format!("$TR,{}$\n$ID,2$", doldoc::quote_arg(label)),
+        "\n$ID,-2$".to_string(),
+    )
+}
+
+pub(super) fn sprite_cmd(bin: u32) -> String {
+    format!("$SP,\"\",BI={bin}$")
+}
+
+/// Picks the sprite for a new `$SP$`: `input` names an existing bin, or empty creates a new one
+/// numbered past every bin in the document (`original` is the file as read).
+pub(super) fn sprite_bin_for(
+    bins: &mut BTreeMap<u32, Vec<u8>>,
+    input: &str,
+    original: &[u8],
+) -> Result<u32, String> {
+    let input = input.trim();
+    if !input.is_empty() {
+        let num = input
+            .parse::<u32>()
+            .map_err(|_| format!("bad bin number '{input}'"))?;
+        return if bins.contains_key(&num) {
+            Ok(num)
+        } else {
+            Err(format!("no sprite BI={num}"))
+        };
+    }
+    let stored = doldoc::stored_bin_nums(original);
+    let num = bins.keys().chain(&stored).max().map(|n| n + 1).unwrap_or(1);
+    bins.insert(num, default_sprite());
+    Ok(num)
+}
+
+/// A yellow 32x16 box: `SPT_COLOR`, `SPT_RECT`, `SPT_END`.
+fn default_sprite() -> Vec<u8> {
+    let mut out = vec![1, 14, 12];
+    for v in [0i32, 0, 32, 16] {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    out.push(0);
+    out
+}
+
+/// A document's text as editor lines: one CP437 byte per character, as TempleOS stores it.
+pub(super) fn doc_lines(text: &str) -> Vec<Vec<u8>> {
+    let bytes: Vec<u8> = text.chars().map(assets::encode_cp437).collect();
+    super::split_lines(&bytes)
+}
+
+/// Encodes source lines back into a `.DD` file over `original` (the file as read, or empty).
+/// The editor expands tabs, so a line that still reads as one of the original lines is written
+/// with that line's bytes; other lines are written in the original's encoding (CP437 unless it
+/// read as UTF-8, like `doldoc::update_doc_blob`). The bin tail is kept as stored (see
+/// `doldoc::update_doc_bins`).
+pub(super) fn write_doc(
+    original: &[u8],
+    lines: &[Vec<u8>],
+    bins: &BTreeMap<u32, Vec<u8>>,
+) -> Vec<u8> {
+    let cutoff = original
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(original.len());
+    let utf8 = std::str::from_utf8(&original[..cutoff]).is_ok();
+    let mut unchanged: HashMap<Vec<u8>, VecDeque<&[u8]>> = HashMap::new();
+    for raw in original[..cutoff].split(|&b| b == b'\n') {
+        let decoded = match utf8 {
+            true => String::from_utf8_lossy(raw).into_owned(),
+            false => assets::decode_cp437_bytes(raw),
+        };
+        let shown = doc_lines(&decoded).swap_remove(0);
+        unchanged.entry(shown).or_default().push_back(raw);
+    }
+    let mut text = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            text.push(b'\n');
+        }
+        match unchanged.get_mut(line).and_then(VecDeque::pop_front) {
+            Some(raw) => text.extend_from_slice(raw),
+            None if utf8 => text.extend_from_slice(assets::decode_cp437_bytes(line).as_bytes()),
+            None => text.extend_from_slice(line),
+        }
+    }
+    doldoc::update_doc_bins(original, text, bins)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_are_single_cells_for_cursor_movement() {
+        let line = b"a$LK,\"Doc\",A=\"FI:x\"$b$$c";
+        let lk_end = line.iter().rposition(|&b| b == b'b').unwrap();
+        assert_eq!(next_col(line, 1), lk_end);
+        assert_eq!(prev_col(line, lk_end), 1);
+        assert_eq!(snap_col(line, 5), 1);
+        assert_eq!(next_col(line, lk_end + 1), lk_end + 3);
+
+        let default = DocStyle::new(15, 0);
+        let mut style = default;
+        let layout = layout_line(line, &mut style, default, &BTreeMap::new());
+        let shown: Vec<u8> = layout.cells.iter().map(|c| c.ch).collect();
+        assert_eq!(shown, b"aDocb$c");
+    }
+
+    #[test]
+    fn colors_and_indent_carry_to_following_lines() {
+        let default = DocStyle::new(15, 0);
+        let mut style = default;
+        let bins = BTreeMap::new();
+        layout_line(b"$FG,RED$$ID,2$x", &mut style, default, &bins);
+        assert_eq!((style.fg, style.indent), (4, 2));
+        let next = layout_line(b"y$FG$", &mut style, default, &bins);
+        assert_eq!((next.indent, next.cells[0].fg), (2, 4));
+        assert_eq!(style.fg, 15);
+    }
+
+    #[test]
+    fn inserted_commands_round_trip() {
+        assert_eq!(
+            link_cmd("Doc", "FI:::/Doc/A.DD"),
+            "$LK,\"Doc\",A=\"FI:::/Doc/A.DD\"$"
+        );
+        assert_eq!(color_cmd("FG", "red").unwrap(), "$FG,RED$");
+        assert_eq!(color_cmd("BG", "").unwrap(), "$BG$");
+        assert!(color_cmd("FG", "mauve").is_err());
+
+        let mut bins = BTreeMap::new();
+        let bin = sprite_bin_for(&mut bins, "", b"").unwrap();
+        let text = format!("hi {}", sprite_cmd(bin));
+        let blob = write_doc(b"", &[text.clone().into_bytes()], &bins);
+        let (back, back_bins) = doldoc::parse_doc_blob(&blob);
+        assert_eq!(back, text);
+        assert_eq!(back_bins, bins);
+    }
+
+    #[test]
+    fn saving_keeps_cp437_tabs_and_the_stored_bin_tail() {
+        let mut original = b"Caf\x82\t$SP,\"\",BI=1$\r\nplain\n".to_vec();
+        let cutoff = original.len();
+        // Bin 1 with the use count TempleOS left in it, then bin 7 that nothing refers to.
+        original.push(0);
+        for (num, data) in [(1u32, default_sprite()), (7, vec![0xAA, 0])] {
+            for v in [num, 0, data.len() as u32, 3] {
+                original.extend_from_slice(&v.to_le_bytes());
+            }
+            original.extend_from_slice(&data);
+        }
+        let (text, mut bins) = doldoc::parse_doc_blob(&original);
+        let mut lines = doc_lines(&text);
+        assert_eq!(write_doc(&original, &lines, &bins), original);
+
+        lines[1].extend_from_slice(b" more");
+        let bin = sprite_bin_for(&mut bins, "", &original).unwrap();
+        assert_eq!(bin, 8, "numbered past the unreferenced bin");
+        lines.push(sprite_cmd(bin).into_bytes());
+        let saved = write_doc(&original, &lines, &bins);
+
+        let mut expected = b"Caf\x82\t$SP,\"\",BI=1$\r\nplain more\n\n".to_vec();
+        expected.extend_from_slice(sprite_cmd(8).as_bytes());
+        expected.extend_from_slice(&original[cutoff..]);
+        assert_eq!(&saved[..expected.len()], &expected[..]);
+        let (_, back_bins) = doldoc::parse_doc_blob(&saved);
+        assert_eq!(back_bins, bins);
+    }
+
+    #[test]
+    fn editing_a_line_keeps_its_cp437_characters() {
+        let original = b"\xC9\xCD\xCD\xBB box\n\xBA hi \xBA\n".to_vec();
+        let (text, bins) = doldoc::parse_doc_blob(&original);
+        let mut lines = doc_lines(&text);
+        assert_eq!(lines[1], b"\xBA hi \xBA");
+
+        lines[1].splice(2..4, *b"HI!");
+        assert_eq!(
+            write_doc(&original, &lines, &bins),
+            b"\xC9\xCD\xCD\xBB box\n\xBA HI! \xBA\n"
+        );
+
+        // A document that reads as UTF-8 stays UTF-8.
+        let original = "caf\u{e9}\n".as_bytes().to_vec();
+        let (text, bins) = doldoc::parse_doc_blob(&original);
+        let mut lines = doc_lines(&text);
+        assert_eq!(lines[0], b"caf\x82");
+        lines[0].push(b'!');
+        assert_eq!(
+            write_doc(&original, &lines, &bins),
+            "caf\u{e9}!\n".as_bytes()
+        );
+    }
+}
@@ -0,0 +1,973 @@
+use std::path::{Path, PathBuf};
+
+use super::{Pos, is_word_byte, split_lines};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(super) struct SearchOpts {
+    pub(super) case_sensitive: bool,
+    pub(super) whole_word: bool,
+    pub(super) regex: bool,
+}
+
+impl SearchOpts {
+    pub(super) fn flags_label(&self) -> String {
+        format!(
+            "[{}{}{}]",
+            if self.case_sensitive { "Aa" } else { "aa" },
+            if self.whole_word { " W" } else { " -" },
+            if self.regex { " .*" } else { " --" }
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct Match {
+    pub(super) start: usize,
+    pub(super) end: usize,
+    groups: Vec<Option<(usize, usize)>>,
+}
+
+#[derive(Clone, Debug)]
+enum Pattern {
+    Literal(Vec<u8>),
+    Regex(Regex),
+}
+
+/// A compiled find query: literal or regex, with the case/whole-word options applied.
+#[derive(Clone, Debug)]
+pub(super) struct Searcher {
+    pattern: Pattern,
+    opts: SearchOpts,
+}
+
+impl Searcher {
+    pub(super) fn new(query: &str, opts: SearchOpts) -> Result<Self, String> {
+        if query.is_empty() {
+            return Err("empty query".to_string());
+        }
+        let pattern = if opts.regex {
+            Pattern::Regex(Regex::new(query, opts.whole_word)?)
+        } else {
+            Pattern::Literal(query.as_bytes().to_vec())
+        };
+        Ok(Self { pattern, opts })
+    }
+
+    fn literal_at(&self, needle: &[u8], line: &[u8], at: usize) -> Option<Match> {
+        let end = at.checked_add(needle.len())?;
+        let hay = line.get(at..end)?;
+        let same = if self.opts.case_sensitive {
+            hay == needle
+        } else {
+            hay.eq_ignore_ascii_case(needle)
+        };
+        if !same || end == at {
+            return None;
+        }
+        if self.opts.whole_word {
+            let before_ok = at == 0 || !is_word_byte(line[at - 1]);
+            let after_ok = end >= line.len() || !is_word_byte(line[end]);
+            if !before_ok || !after_ok {
+                return None;
+            }
+        }
+        Some(Match {
+            start: at,
+            end,
+            groups: Vec::new(),
+        })
+    }
+
+    /// First match starting at or after `from`. Empty matches are never returned: they are
+    /// never useful for find/replace in a line editor.
+    pub(super) fn find_in_line(&self, line: &[u8], from: usize) -> Option<Match> {
+        match &self.pattern {
+            Pattern::Literal(needle) => {
+                (from..=line.len()).find_map(|at| self.literal_at(needle, line, at))
+            }
+            Pattern::Regex(re) => re.find(line, from, !self.opts.case_sensitive),
+        }
+    }
+
+    /// Last match starting strictly before `before`.
+    pub(super) fn rfind_in_line(&self, line: &[u8], before: usize) -> Option<Match> {
+        match &self.pattern {
+            Pattern::Literal(needle) => (0..before.min(line.len() + 1))
+                .rev()
+                .find_map(|at| self.literal_at(needle, line, at)),
+            Pattern::Regex(_) => {
+                let mut last = None;
+                let mut from = 0;
+                while let Some(m) = self.find_in_line(line, from) {
+                    if m.start >= before {
+                        break;
+                    }
+                    from = m.start + 1;
+                    last = Some(m);
+                }
+                last
+            }
+        }
+    }
+
+    /// Expands `template` for a match. In regex mode `\0`-`\9` and `$0`-`$9` insert groups.
+    pub(super) fn expand(&self, line: &[u8], m: &Match, template: &str) -> Vec<u8> {
+        let tpl = template.as_bytes();
+        if !self.opts.regex {
+            return tpl.to_vec();
+        }
+
+        let group = |idx: usize| -> &[u8] {
+            if idx == 0 {
+                return &line[m.start..m.end];
+            }
+            match m.groups.get(idx - 1).copied().flatten() {
+                Some((s, e)) => &line[s..e],
+                None => &[],
+            }
+        };
+
+        let mut out = Vec::with_capacity(tpl.len());
+        let mut i = 0;
+        while i < tpl.len() {
+            let b = tpl[i];
+            if (b == b'\\' || b == b'$') && i + 1 < tpl.len() {
+                let n = tpl[i + 1];
+                if n.is_ascii_digit() {
+                    out.extend_from_slice(group((n - b'0') as usize));
+                    i += 2;
+                    continue;
+                }
+                if n == b {
+                    out.push(b);
+                    i += 2;
+                    continue;
+                }
+                if b == b'\\' && n == b't' {
+                    out.extend_from_slice(b"    ");
+                    i += 2;
+                    continue;
+                }
+            }
+            out.push(b);
+            i += 1;
+        }
+        out
+    }
+
+    /// Replaces every match in `line`, returning the new line and the replacement count.
+    pub(super) fn replace_line(&self, line: &[u8], template: &str) -> (Vec<u8>, usize) {
+        let mut out = Vec::with_capacity(line.len());
+        let mut copied = 0usize;
+        let mut count = 0usize;
+        let mut from = 0usize;
+        while let Some(m) = self.find_in_line(line, from) {
+            out.extend_from_slice(&line[copied..m.start]);
+            out.extend_from_slice(&self.expand(line, &m, template));
+            copied = m.end;
+            from = m.end;
+            count += 1;
+        }
+        out.extend_from_slice(&line[copied..]);
+        (out, count)
+    }
+}
+
+pub(super) fn find_next(lines: &[Vec<u8>], searcher: &Searcher, start: Pos) -> Option<(Pos, Pos)> {
+    if lines.is_empty() {
+        return None;
+    }
+    let start_line = start.line.min(lines.len() - 1);
+
+    for (line_idx, line) in lines.iter().enumerate().skip(start_line) {
+        let from = if line_idx == start_line {
+            start.col.min(line.len())
+        } else {
+            0
+        };
+        if let Some(m) = searcher.find_in_line(line, from) {
+            return Some((Pos::new(line_idx, m.start), Pos::new(line_idx, m.end)));
+        }
+    }
+
+    // Wrap.
+    for (line_idx, line) in lines.iter().enumerate().take(start_line + 1) {
+        if let Some(m) = searcher.find_in_line(line, 0) {
+            if line_idx == start_line && m.start >= start.col {
+                break;
+            }
+            return Some((Pos::new(line_idx, m.start), Pos::new(line_idx, m.end)));
+        }
+    }
+
+    None
+}
+
+pub(super) fn find_prev(lines: &[Vec<u8>], searcher: &Searcher, start: Pos) -> Option<(Pos, Pos)> {
+    if lines.is_empty() {
+        return None;
+    }
+    let start_line = start.line.min(lines.len() - 1);
+
+    let before = (0..=start_line).rev();
+    let wrapped = (start_line..lines.len()).rev();
+    for (n, line_idx) in before.chain(wrapped).enumerate() {
+        let line = &lines[line_idx];
+        let limit = if n == 0 { start.col } else { line.len() + 1 };
+        if let Some(m) = searcher.rfind_in_line(line, limit) {
+            return Some((Pos::new(line_idx, m.start), Pos::new(line_idx, m.end)));
+        }
+    }
+    None
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct ReplacePreview {
+    pub(super) line: usize,
+    pub(super) before: Vec<u8>,
+    pub(super) after: Vec<u8>,
+    pub(super) count: usize,
+}
+
+/// Computes every line that a replace-all would change, without touching the buffer.
+pub(super) fn preview_replace_all(
+    lines: &[Vec<u8>],
+    searcher: &Searcher,
+    template: &str,
+) -> Vec<ReplacePreview> {
+    let mut out = Vec::new();
+    for (idx, line) in lines.iter().enumerate() {
+        let (after, count) = searcher.replace_line(line, template);
+        if count > 0 {
+            out.push(ReplacePreview {
+                line: idx,
+                before: line.clone(),
+                after,
+                count,
+            });
+        }
+    }
+    out
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct FileHit {
+    pub(super) path: PathBuf,
+    pub(super) line: usize,
+    pub(super) col: usize,
+    pub(super) end_col: usize,
+    pub(super) text: String,
+}
+
+const FIND_IN_FILES_MAX_HITS: usize = 5000;
+const FIND_IN_FILES_MAX_BYTES: u64 = 2 * 1024 * 1024;
+
+fn is_searchable_file(path: &Path) -> bool {
+    let Some(ext) = path.extension().and_then(|e| e.to_str()) else {
+        return false;
+    };
+    matches!(
+        ext.to_ascii_uppercase().as_str(),
+        "HC" | "HH" | "DD" | "TD" | "IN" | "CPP" | "H" | "C" | "TXT" | "MD" | "RS" | "SH"
+    )
+}
+
+/// Recursively searches text files under `root`, skipping hidden directories and build output.
+/// Files listed in `unsaved` are searched from those in-memory lines instead of disk.
+pub(super) fn find_in_files(
+    root: &Path,
+    searcher: &Searcher,
+    unsaved: &[(PathBuf, Vec<Vec<u8>>)],
+) -> (Vec<FileHit>, bool) {
+    let unsaved: Vec<(PathBuf, &Vec<Vec<u8>>)> = unsaved
+        .iter()
+        .map(|(p, lines)| {
+            (
+                std::fs::canonicalize(p).unwrap_or_else(|_| p.clone()),
+                lines,
+            )
+        })
+        .collect();
+    let mut hits = Vec::new();
+    let mut stack = vec![root.to_path_buf()];
+    let mut truncated = false;
+
+    while let Some(dir) = stack.pop() {
+        let Ok(rd) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut entries: Vec<PathBuf> = rd.filter_map(|e| e.ok().map(|e| e.path())).collect();
+        entries.sort();
+        // Pop order is reversed, so push directories last-first to keep output sorted.
+        let mut subdirs = Vec::new();
+        for path in entries {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            if name.starts_with('.') || name == "target" {
+                continue;
+            }
+            if path.is_dir() {
+                subdirs.push(path);
+                continue;
+            }
+            if !is_searchable_file(&path) {
+                continue;
+            }
+            let canonical = std::fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+            let file_lines = match unsaved.iter().find(|(p, _)| *p == canonical) {
+                Some((_, lines)) => (*lines).clone(),
+                None => {
+                    let too_big = std::fs::metadata(&path)
+                        .map(|m| m.len() > FIND_IN_FILES_MAX_BYTES)
+                        .unwrap_or(true);
+                    if too_big {
+                        continue;
+                    }
+                    let Ok(buf) = std::fs::read(&path) else {
+                        continue;
+                    };
+                    split_lines(&buf)
+                }
+            };
+            for (line_idx, line) in file_lines.iter().enumerate() {
+                let mut from = 0usize;
+                while let Some(m) = searcher.find_in_line(line, from) {
+                    if hits.len() >= FIND_IN_FILES_MAX_HITS {
+                        truncated = true;
+                        return (hits, truncated);
+                    }
+                    hits.push(FileHit {
+                        path: path.clone(),
+                        line: line_idx,
+                        col: m.start,
+                        end_col: m.end,
+                        text: String::from_utf8_lossy(line).to_string(),
+                    });
+                    from = m.end;
+                }
+            }
+        }
+        stack.extend(subdirs.into_iter().rev());
+    }
+
+    (hits, truncated)
+}
+
+// A small regex engine over bytes: patterns compile to a Pike VM program, run breadth-first
+// over the line so time is linear in the line length and no stack grows with it. Lines never
+// contain '\n', so there is no multi-line mode. Supported: literals, `.`, `[...]` classes,
+// `\d\w\s\D\W\S\b\B`, `\t` (the spaces a tab loads as), `^`, `$`, `(...)`, `(?:...)`, `|`,
+// and `* + ? {n} {n,} {n,m}` with lazy `?` suffixes.
+
+/// Largest compiled program; counted repetitions are expanded, so `(a{200}){200}` is too big.
+const REGEX_MAX_INSTS: usize = 20_000;
+const TAB_SPACES: usize = 4;
+
+#[derive(Clone, Debug)]
+enum Node {
+    Byte(u8),
+    Any,
+    Class {
+        ranges: Vec<(u8, u8)>,
+        negated: bool,
+    },
+    Start,
+    End,
+    WordBoundary(bool),
+    Group {
+        alts: Vec<Vec<Node>>,
+        capture: Option<usize>,
+    },
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+struct RegexParser<'a> {
+    src: &'a [u8],
+    pos: usize,
+    groups: usize,
+}
+
+impl RegexParser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.src.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn parse_alts(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut alts = vec![self.parse_seq()?];
+        while self.peek() == Some(b'|') {
+            self.pos += 1;
+            alts.push(self.parse_seq()?);
+        }
+        Ok(alts)
+    }
+
+    fn parse_seq(&mut self) -> Result<Vec<Node>, String> {
+        let mut seq = Vec::new();
+        while let Some(b) = self.peek() {
+            if b == b'|' || b == b')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            let atom = self.parse_quantifier(atom)?;
+            seq.push(atom);
+        }
+        Ok(seq)
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        let b = self.bump().ok_or("unexpected end of pattern")?;
+        Ok(match b {
+            b'.' => Node::Any,
+            b'^' => Node::Start,
+            b'$' => Node::End,
+            b'(' => {
+                let capture = if self.src[self.pos..].starts_with(b"?:") {
+                    self.pos += 2;
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups - 1)
+                };
+                let alts = self.parse_alts()?;
+                if self.bump() != Some(b')') {
+                    return Err("missing ')'".to_string());
+                }
+                Node::Group { alts, capture }
+            }
+            b'[' => self.parse_class()?,
+            b'\\' => self.parse_escape()?,
+            b'*' | b'+' | b'?' | b'{' => {
+                return Err(format!("nothing to repeat before '{}'", b as char));
+            }
+            _ => Node::Byte(b),
+        })
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, String> {
+        let b = self.bump().ok_or("trailing '\\'")?;
+        Ok(match b {
+            b'd' | b'D' | b'w' | b'W' | b's' | b'S' => Node::Class {
+                ranges: escape_class_ranges(b.to_ascii_lowercase()),
+                negated: b.is_ascii_uppercase(),
+            },
+            b'b' => Node::WordBoundary(true),
+            b'B' => Node::WordBoundary(false),
+            // Tabs are expanded to four spaces when a file is loaded.
+            b't' => Node::Group {
+                alts: vec![vec![Node::Byte(b' '); TAB_SPACES]],
+                capture: None,
+            },
+            _ => Node::Byte(b),
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some(b'^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let b = self.bump().ok_or("missing ']'")?;
+            if b == b']' && !first {
+                break;
+            }
+            first = false;
+            let lo = if b == b'\\' {
+                let e = self.bump().ok_or("missing ']'")?;
+                if matches!(e, b'd' | b'w' | b's') {
+                    ranges.extend(escape_class_ranges(e));
+                    continue;
+                }
+                if e == b't' { b' ' } else { e }
+            } else {
+                b
+            };
+            if self.peek() == Some(b'-') && self.src.get(self.pos + 1).is_some_and(|&n| n != b']') {
+                self.pos += 1;
+                let mut hi = self.bump().ok_or("missing ']'")?;
+                if hi == b'\\' {
+                    hi = self.bump().ok_or("missing ']'")?;
+                }
+                if hi < lo {
+                    return Err("invalid class range".to_string());
+                }
+                ranges.push((lo, hi));
+            } else {
+                ranges.push((lo, lo));
+            }
+        }
+        Ok(Node::Class { ranges, negated })
+    }
+
+    fn parse_number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|b| b.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.src[start..self.pos])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    fn parse_quantifier(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some(b'*') => (0, None),
+            Some(b'+') => (1, None),
+            Some(b'?') => (0, Some(1)),
+            Some(b'{') => {
+                let save = self.pos;
+                self.pos += 1;
+                let Some(min) = self.parse_number() else {
+                    // Not a counted repetition; treat '{' literally.
+                    self.pos = save;
+                    return Ok(atom);
+                };
+                let max = if self.peek() == Some(b',') {
+                    self.pos += 1;
+                    self.parse_number()
+                } else {
+                    Some(min)
+                };
+                if self.peek() != Some(b'}') {
+                    return Err("missing '}'".to_string());
+                }
+                if max.is_some_and(|m| m < min) {
+                    return Err("invalid repetition range".to_string());
+                }
+                (min, max)
+            }
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        if matches!(atom, Node::Start | Node::End | Node::WordBoundary(_)) {
+            return Err("cannot repeat an anchor".to_string());
+        }
+        let greedy = if self.peek() == Some(b'?') {
+            self.pos += 1;
+            false
+        } else {
+            true
+        };
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy,
+        })
+    }
+}
+
+fn escape_class_ranges(kind: u8) -> Vec<(u8, u8)> {
+    match kind {
+        b'd' => vec![(b'0', b'9')],
+        b'w' => vec![(b'a', b'z'), (b'A', b'Z'), (b'0', b'9'), (b'_', b'_')],
+        _ => vec![(b' ', b' '), (b'\t', b'\t'), (b'\r', b'\r'), (b'\n', b'\n')],
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Look {
+    Start,
+    End,
+    WordBoundary(bool),
+    /// Whole-word search: no word byte just before / after the match.
+    NotWordBefore,
+    NotWordAfter,
+}
+
+#[derive(Clone, Debug)]
+enum Inst {
+    Byte(u8),
+    Any,
+    Class {
+        ranges: Vec<(u8, u8)>,
+        negated: bool,
+    },
+    Look(Look),
+    /// Try both, preferring the first.
+    Split(usize, usize),
+    Jmp(usize),
+    Save(usize),
+    Match,
+}
+
+#[derive(Clone, Debug)]
+struct Regex {
+    insts: Vec<Inst>,
+    /// Two per group, group 0 being the whole match.
+    slots: usize,
+}
+
+struct Compiler {
+    insts: Vec<Inst>,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> Result<usize, String> {
+        if self.insts.len() >= REGEX_MAX_INSTS {
+            return Err("pattern too large".to_string());
+        }
+        self.insts.push(inst);
+        Ok(self.insts.len() - 1)
+    }
+
+    fn patch(&mut self, at: usize, to: usize) {
+        match &mut self.insts[at] {
+            Inst::Split(_, b) => *b = to,
+            Inst::Jmp(t) => *t = to,
+            _ => unreachable!("only splits and jumps are patched"),
+        }
+    }
+
+    fn alts(&mut self, alts: &[Vec<Node>]) -> Result<(), String> {
+        let mut jumps = Vec::new();
+        for (n, alt) in alts.iter().enumerate() {
+            let split = if n + 1 < alts.len() {
+                let at = self.insts.len();
+                Some(self.push(Inst::Split(at + 1, 0))?)
+            } else {
+                None
+            };
+            for node in alt {
+                self.node(node)?;
+            }
+            if let Some(split) = split {
+                jumps.push(self.push(Inst::Jmp(0))?);
+                let next = self.insts.len();
+                self.patch(split, next);
+            }
+        }
+        let end = self.insts.len();
+        for j in jumps {
+            self.patch(j, end);
+        }
+        Ok(())
+    }
+
+    fn node(&mut self, node: &Node) -> Result<(), String> {
+        match node {
+            Node::Byte(b) => self.push(Inst::Byte(*b)).map(drop),
+            Node::Any => self.push(Inst::Any).map(drop),
+            Node::Class { ranges, negated } => self
+                .push(Inst::Class {
+                    ranges: ranges.clone(),
+                    negated: *negated,
+                })
+                .map(drop),
+            Node::Start => self.push(Inst::Look(Look::Start)).map(drop),
+            Node::End => self.push(Inst::Look(Look::End)).map(drop),
+            Node::WordBoundary(want) => self.push(Inst::Look(Look::WordBoundary(*want))).map(drop),
+            Node::Group { alts, capture } => {
+                if let Some(idx) = capture {
+                    self.push(Inst::Save(2 * idx + 2))?;
+                }
+                self.alts(alts)?;
+                if let Some(idx) = capture {
+                    self.push(Inst::Save(2 * idx + 3))?;
+                }
+                Ok(())
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.node(node)?;
+                }
+                let split = |body: usize, out: usize| {
+                    if *greedy {
+                        Inst::Split(body, out)
+                    } else {
+                        Inst::Split(out, body)
+                    }
+                };
+                match max {
+                    None => {
+                        let at = self.insts.len();
+                        self.push(split(at + 1, 0))?;
+                        self.node(node)?;
+                        self.push(Inst::Jmp(at))?;
+                        let out = self.insts.len();
+                        self.insts[at] = split(at + 1, out);
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            let at = self.insts.len();
+                            splits.push(self.push(split(at + 1, 0))?);
+                            self.node(node)?;
+                        }
+                        let out = self.insts.len();
+                        for at in splits {
+                            self.insts[at] = split(at + 1, out);
+                        }
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+type Slots = Vec<Option<usize>>;
+
+/// Threads at one position, in priority order, at most one per instruction.
+struct Threads {
+    seen: Vec<usize>,
+    generation: usize,
+    list: Vec<(usize, Slots)>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Self {
+        Self {
+            seen: vec![0; len],
+            generation: 1,
+            list: Vec::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.generation += 1;
+        self.list.clear();
+    }
+}
+
+impl Regex {
+    fn new(pattern: &str, whole_word: bool) -> Result<Self, String> {
+        let mut p = RegexParser {
+            src: pattern.as_bytes(),
+            pos: 0,
+            groups: 0,
+        };
+        let alts = p.parse_alts()?;
+        if p.pos < p.src.len() {
+            return Err("unmatched ')'".to_string());
+        }
+        let mut c = Compiler { insts: Vec::new() };
+        c.push(Inst::Save(0))?;
+        if whole_word {
+            c.push(Inst::Look(Look::NotWordBefore))?;
+        }
+        c.alts(&alts)?;
+        if whole_word {
+            c.push(Inst::Look(Look::NotWordAfter))?;
+        }
+        c.push(Inst::Save(1))?;
+        c.push(Inst::Match)?;
+        Ok(Self {
+            insts: c.insts,
+            slots: 2 * p.groups + 2,
+        })
+    }
+
+    fn looks(look: Look, hay: &[u8], i: usize) -> bool {
+        let before = i > 0 && is_word_byte(hay[i - 1]);
+        let after = i < hay.len() && is_word_byte(hay[i]);
+        match look {
+            Look::Start => i == 0,
+            Look::End => i == hay.len(),
+            Look::WordBoundary(want) => (before != after) == want,
+            Look::NotWordBefore => !before,
+            Look::NotWordAfter => !after,
+        }
+    }
+
+    /// Adds the thread at `pc` and everything it reaches without consuming a byte.
+    fn add(&self, threads: &mut Threads, pc: usize, slots: Slots, hay: &[u8], i: usize) {
+        let mut stack = vec![(pc, slots)];
+        while let Some((pc, mut slots)) = stack.pop() {
+            if threads.seen[pc] == threads.generation {
+                continue;
+            }
+            threads.seen[pc] = threads.generation;
+            match &self.insts[pc] {
+                Inst::Jmp(to) => stack.push((*to, slots)),
+                Inst::Split(a, b) => {
+                    // Pushed in reverse, so `a` and all it reaches come first.
+                    stack.push((*b, slots.clone()));
+                    stack.push((*a, slots));
+                }
+                Inst::Save(slot) => {
+                    slots[*slot] = Some(i);
+                    stack.push((pc + 1, slots));
+                }
+                Inst::Look(look) => {
+                    if Self::looks(*look, hay, i) {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                _ => threads.list.push((pc, slots)),
+            }
+        }
+    }
+
+    /// The leftmost non-empty match starting at or after `from`, preferring alternatives and
+    /// greedy/lazy repeats like a backtracker would.
+    fn find(&self, hay: &[u8], from: usize, icase: bool) -> Option<Match> {
+        let byte_eq = |a: u8, b: u8| {
+            if icase {
+                a.eq_ignore_ascii_case(&b)
+            } else {
+                a == b
+            }
+        };
+        let class_has = |ranges: &[(u8, u8)], b: u8| {
+            let hit = |c: u8| ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi);
+            hit(b) || (icase && (hit(b.to_ascii_lowercase()) || hit(b.to_ascii_uppercase())))
+        };
+
+        let mut cur = Threads::new(self.insts.len());
+        let mut next = Threads::new(self.insts.len());
+        let mut found: Option<Slots> = None;
+        for i in from..=hay.len() {
+            if found.is_none() {
+                // A match starting here ranks below every thread that started earlier.
+                self.add(&mut cur, 0, vec![None; self.slots], hay, i);
+            } else if cur.list.is_empty() {
+                break;
+            }
+            next.clear();
+            for (pc, slots) in std::mem::take(&mut cur.list) {
+                let step = match &self.insts[pc] {
+                    Inst::Byte(b) => i < hay.len() && byte_eq(hay[i], *b),
+                    Inst::Any => i < hay.len(),
+                    Inst::Class { ranges, negated } => {
+                        i < hay.len() && class_has(ranges, hay[i]) != *negated
+                    }
+                    Inst::Match => {
+                        if slots[0] == slots[1] {
+                            continue;
+                        }
+                        // Lower-priority threads can no longer win.
+                        found = Some(slots);
+                        break;
+                    }
+                    _ => false,
+                };
+                if step {
+                    self.add(&mut next, pc + 1, slots, hay, i + 1);
+                }
+            }
+            std::mem::swap(&mut cur, &mut next);
+        }
+        let slots = found?;
+        Some(Match {
+            start: slots[0]?,
+            end: slots[1]?,
+            groups: slots[2..]
+                .chunks_exact(2)
+                .map(|g| Some((g[0]?, g[1]?)))
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(case_sensitive: bool, whole_word: bool, regex: bool) -> SearchOpts {
+        SearchOpts {
+            case_sensitive,
+            whole_word,
+            regex,
+        }
+    }
+
+    fn first(query: &str, o: SearchOpts, line: &str) -> Option<(usize, usize)> {
+        let s = Searcher::new(query, o).expect("compile");
+        s.find_in_line(line.as_bytes(), 0).map(|m| (m.start, m.end))
+    }
+
+    #[test]
+    fn literal_case_and_whole_word() {
+        assert_eq!(
+            first("gr", opts(false, false, false), "U0 GrPlot()"),
+            Some((3, 5))
+        );
+        assert_eq!(first("gr", opts(true, false, false), "U0 GrPlot()"), None);
+        assert_eq!(
+            first("Gr", opts(true, true, false), "U0 GrPlot(Gr)"),
+            Some((10, 12))
+        );
+    }
+
+    #[test]
+    fn regex_classes_groups_and_quantifiers() {
+        let o = opts(true, false, true);
+        assert_eq!(first(r"\d+", o, "I x=1234;"), Some((4, 8)));
+        assert_eq!(first(r"^U0\s+(\w+)", o, "U0  Main()"), Some((0, 8)));
+        assert_eq!(first(r"a(b|c)*d", o, "xxabcbcd"), Some((2, 8)));
+        assert_eq!(first(r"[^a-z]{2,3}", o, "abcDEFGh"), Some((3, 6)));
+        assert_eq!(first(r"<.+?>", o, "<a><b>"), Some((0, 3)));
+        assert_eq!(first(r"\bx\b", o, "xx x"), Some((3, 4)));
+        assert!(Searcher::new("(ab", o).is_err());
+        assert!(Searcher::new("*a", o).is_err());
+    }
+
+    #[test]
+    fn regex_runs_in_constant_stack_on_long_lines() {
+        let line = vec![b'x'; 200_000];
+        let hit = std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(move || {
+                let s = Searcher::new(r"x.*(x)", opts(true, false, true)).expect("compile");
+                s.find_in_line(&line, 0).map(|m| (m.start, m.end, m.groups))
+            })
+            .expect("spawn")
+            .join()
+            .expect("no stack overflow");
+        assert_eq!(hit, Some((0, 200_000, vec![Some((199_999, 200_000))])));
+        assert!(Searcher::new("(a{200}){200}", opts(true, false, true)).is_err());
+    }
+
+    #[test]
+    fn regex_whole_word_tries_other_alternatives() {
+        let o = opts(true, true, true);
+        assert_eq!(first("ab|abc", o, "abc"), Some((0, 3)));
+        assert_eq!(first(r"\w+?", o, "foo bar"), Some((0, 3)));
+        assert_eq!(first("a*", opts(true, false, true), "baaa"), Some((1, 4)));
+        // Tabs are loaded as four spaces.
+        assert_eq!(
+            first(r"\tx", opts(true, false, true), "    x"),
+            Some((0, 5))
+        );
+        let s = Searcher::new(r"o+", opts(true, false, true)).expect("compile");
+        let m = s.rfind_in_line(b"foo boo", 6).expect("last");
+        assert_eq!((m.start, m.end), (5, 7));
+    }
+
+    #[test]
+    fn regex_replacement_expands_groups() {
+        let s = Searcher::new(r"(\w+)\((\w*)\)", opts(true, false, true)).expect("compile");
+        let (out, n) = s.replace_line(b"F(x); G();", r"\1<$2>");
+        assert_eq!(n, 2);
+        assert_eq!(out, b"F<x>; G<>;");
+    }
+
+    #[test]
+    fn find_next_and_prev_wrap_around() {
+        let lines: Vec<Vec<u8>> = vec![b"foo bar".to_vec(), b"bar foo".to_vec()];
+        let s = Searcher::new("foo", SearchOpts::default()).expect("compile");
+        let hit = find_next(&lines, &s, Pos::new(1, 5)).expect("wrap");
+        assert_eq!(hit, (Pos::new(0, 0), Pos::new(0, 3)));
+        let hit = find_prev(&lines, &s, Pos::new(0, 0)).expect("wrap back");
+        assert_eq!(hit, (Pos::new(1, 4), Pos::new(1, 7)));
+    }
+}
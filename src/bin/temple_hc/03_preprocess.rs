@@ -6,6 +6,13 @@ use std::{
     sync::Arc,
 };
 
+/// Preprocessor output: source segments, `#define`s, and each file's DolDoc bins.
+pub(super) type Preprocessed = (
+    Vec<SourceSegment>,
+    HashMap<String, String>,
+    HashMap<Arc<str>, BTreeMap<u32, Vec<u8>>>,
+);
+
 #[derive(Clone)]
 pub(super) struct SourceSegment {
     pub(super) file: Arc<str>,
@@ -132,11 +139,7 @@ pub(super) fn resolve_templeos_path(
 pub(super) fn preprocess_entry(
     path: &Path,
     templeos_root: Option<&Path>,
-) -> io::Result<(
-    Vec<SourceSegment>,
-    HashMap<String, String>,
-    HashMap<Arc<str>, BTreeMap<u32, Vec<u8>>>,
-)> {
+) -> io::Result<Preprocessed> {
     let mut out = Vec::new();
     let mut defines: HashMap<String, String> = HashMap::new();
     let mut bins_by_file: HashMap<Arc<str>, BTreeMap<u32, Vec<u8>>> = HashMap::new();
@@ -152,6 +155,61 @@ pub(super) fn preprocess_entry(
     Ok((out, defines, bins_by_file))
 }
 
+/// Preprocesses the body of a DolDoc macro as if it sat in `doc`: `#include`s resolve next to
+/// the document and `__DIR__`/`__FILE__` name it.
+pub(super) fn preprocess_macro(
+    text: &str,
+    doc: &Path,
+    templeos_root: Option<&Path>,
+) -> io::Result<Preprocessed> {
+    let mut out = Vec::new();
+    let mut defines: HashMap<String, String> = HashMap::new();
+    let mut bins_by_file: HashMap<Arc<str>, BTreeMap<u32, Vec<u8>>> = HashMap::new();
+    let mut stack: Vec<PathBuf> = Vec::new();
+
+    let file_label: Arc<str> = doc.display().to_string().into();
+    let base_dir = doc.parent().unwrap_or(Path::new("."));
+    let mut seg_start_line = 1usize;
+    let mut seg_bytes: Vec<u8> = Vec::new();
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim();
+        if !trimmed.starts_with('#') {
+            seg_bytes.extend_from_slice(line.as_bytes());
+            continue;
+        }
+        if !seg_bytes.is_empty() {
+            out.push(SourceSegment {
+                file: file_label.clone(),
+                start_line: seg_start_line,
+                bytes: mem::take(&mut seg_bytes),
+            });
+        }
+        seg_start_line = i + 2;
+        if trimmed.starts_with("#include") {
+            let spec = parse_include_spec(trimmed)?;
+            let include_path = resolve_templeos_path(&spec, base_dir, templeos_root)?;
+            preprocess_file(
+                &include_path,
+                templeos_root,
+                &mut stack,
+                &mut defines,
+                &mut bins_by_file,
+                &mut out,
+            )?;
+        } else if let Some((k, v)) = parse_define(trimmed) {
+            defines.insert(k, v);
+        }
+    }
+    if !seg_bytes.is_empty() {
+        out.push(SourceSegment {
+            file: file_label,
+            start_line: seg_start_line,
+            bytes: seg_bytes,
+        });
+    }
+    Ok((out, defines, bins_by_file))
+}
+
 fn preprocess_file(
     path: &Path,
     templeos_root: Option<&Path>,
@@ -470,6 +528,16 @@ pub(super) fn builtin_defines() -> HashMap<String, String> {
         ("DCF_TRANSFORMATION", "0x100"),
         ("DCF_SYMMETRY", "0x200"),
         ("DCF_JUST_MIRROR", "0x400"),
+        ("GR_SCALE", "0x100000000"),
+        // GRWrite flags.
+        ("DCSF_COMPRESSED", "1"),
+        ("DCSF_PALETTE_GET", "2"),
+        // Raster ops, in `dc->color` above the color.
+        ("ROP_EQU", "0x000"),
+        ("ROP_XOR", "0x100"),
+        ("ROP_COLLISION", "0x200"),
+        ("ROP_MONO", "0x300"),
+        ("ROPF_DITHER", "0x4000"),
         // Scan codes (subset).
         ("SC_ESC", "0x01"),
         ("SC_BACKSPACE", "0x0E"),
@@ -521,6 +589,51 @@ pub(super) fn builtin_defines() -> HashMap<String, String> {
         ("CTRLF_CAPTURE_LEFT_MS", "4"),
         ("CTRLF_CAPTURE_RIGHT_MS", "8"),
         ("CTRLF_CLICKED", "16"),
+        // DolDoc entry types (from ::/Kernel/KernelA.HH).
+        ("DOCT_TEXT", "0"),
+        ("DOCT_NEW_LINE", "1"),
+        ("DOCT_SOFT_NEW_LINE", "2"),
+        ("DOCT_TAB", "3"),
+        ("DOCT_PAGE_BREAK", "4"),
+        ("DOCT_CURSOR", "5"),
+        ("DOCT_MARKER", "6"),
+        ("DOCT_PMT", "7"),
+        ("DOCT_CLEAR", "8"),
+        ("DOCT_PAGE_LEN", "9"),
+        ("DOCT_LEFT_MARGIN", "10"),
+        ("DOCT_RIGHT_MARGIN", "11"),
+        ("DOCT_HEADER", "12"),
+        ("DOCT_FOOTER", "13"),
+        ("DOCT_INDENT", "14"),
+        ("DOCT_FOREGROUND", "15"),
+        ("DOCT_BACKGROUND", "16"),
+        ("DOCT_DFT_FOREGROUND", "17"),
+        ("DOCT_DFT_BACKGROUND", "18"),
+        ("DOCT_WORD_WRAP", "19"),
+        ("DOCT_HIGHLIGHT", "20"),
+        ("DOCT_BLINK", "21"),
+        ("DOCT_INVERT", "22"),
+        ("DOCT_SHIFTED_X", "23"),
+        ("DOCT_SHIFTED_Y", "24"),
+        ("DOCT_UNDERLINE", "25"),
+        ("DOCT_CURSOR_MOVEMENT", "26"),
+        ("DOCT_ANCHOR", "27"),
+        ("DOCT_LINK", "28"),
+        ("DOCT_BUTTON", "29"),
+        ("DOCT_DATA", "30"),
+        ("DOCT_CHECK_BOX", "31"),
+        ("DOCT_LIST", "32"),
+        ("DOCT_MACRO", "33"),
+        ("DOCT_MENU_VAL", "34"),
+        ("DOCT_HEX_ED", "35"),
+        ("DOCT_TREE", "36"),
+        ("DOCT_SPRITE", "37"),
+        ("DOCT_INS_BIN", "38"),
+        ("DOCT_INS_BIN_SIZE", "39"),
+        ("DOCT_SONG", "40"),
+        ("DOCT_HTML_CODE", "41"),
+        ("DOCT_ERROR", "42"),
+        ("DOCT_TYPES_NUM", "43"),
         // Graphics (common upstream globals/macros).
         ("GR_WIDTH", "SCR_W"),
         ("GR_HEIGHT", "SCR_H"),
@@ -2,7 +2,7 @@ use super::{
     ParseError, Program,
     preprocess::{
         SourceSegment, builtin_defines, compile_segments, discover_templeos_root, preprocess_entry,
-        resolve_templeos_path,
+        preprocess_macro, resolve_templeos_path,
     },
     vm,
 };
@@ -46,13 +46,22 @@ pub(super) fn run() -> io::Result<()> {
     }
 
     fn print_usage() {
-        eprintln!("temple-hc [--check] [program]");
+        eprintln!(
+            "temple-hc [--check] [--heap-debug] [--max-heap BYTES] [--max-depth N] [--max-steps N]"
+        );
+        eprintln!("          [--record FILE] [--replay FILE] [program]");
+        eprintln!("temple-hc --macro CODE [--doc FILE]");
         eprintln!();
         eprintln!("Examples:");
         eprintln!("  temple-hc");
         eprintln!("  temple-hc Hello.HC");
         eprintln!("  temple-hc ::/Demo/Graphics/NetOfDots.HC");
         eprintln!("  temple-hc --check Hello.HC");
+        eprintln!("  temple-hc --heap-debug MyGame.HC   (red zones, poisoning, leak report)");
+        eprintln!("  temple-hc --max-steps 1000000 --max-depth 512 MyGame.HC");
+        eprintln!("  temple-hc --record bug.log ::/Demo/Games/Maze.HC");
+        eprintln!("  temple-hc --replay bug.log ::/Demo/Games/Maze.HC");
+        eprintln!("  temple-hc --macro 'Cd(\"::/Demo\");Dir;'   (DolDoc macro, run for the shell)");
     }
 
     #[derive(Debug)]
@@ -86,15 +95,25 @@ pub(super) fn run() -> io::Result<()> {
 
     fn compile_program(
         spec: Option<&str>,
+        macro_src: Option<(&str, Option<&str>)>,
     ) -> Result<(Program, Arc<HashMap<String, String>>), TempleHcError> {
-        let (segments, defines, bins_by_file) = match spec {
-            Some(spec) => {
+        let (segments, defines, bins_by_file) = match (macro_src, spec) {
+            (Some((code, doc)), _) => {
+                let templeos_root = discover_templeos_root();
+                let base_dir = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+                let doc = match doc {
+                    Some(doc) => base_dir.join(doc),
+                    None => base_dir.join("Macro.HC"),
+                };
+                preprocess_macro(code, &doc, templeos_root.as_deref())?
+            }
+            (None, Some(spec)) => {
                 let templeos_root = discover_templeos_root();
                 let base_dir = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
                 let entry_path = resolve_templeos_path(spec, &base_dir, templeos_root.as_deref())?;
                 preprocess_entry(&entry_path, templeos_root.as_deref())?
             }
-            None => (
+            (None, None) => (
                 vec![SourceSegment {
                     file: "<demo>".into(),
                     start_line: 1,
@@ -115,6 +134,12 @@ pub(super) fn run() -> io::Result<()> {
     let mut args = env::args().skip(1);
     let mut mode = Mode::Run;
     let mut spec: Option<String> = None;
+    let mut heap_debug = false;
+    let mut limits = vm::VmLimits::default();
+    let mut record: Option<PathBuf> = None;
+    let mut replay: Option<PathBuf> = None;
+    let mut macro_code: Option<String> = None;
+    let mut macro_doc: Option<String> = None;
 
     while let Some(arg) = args.next() {
         match arg.as_str() {
@@ -125,6 +150,46 @@ pub(super) fn run() -> io::Result<()> {
             "--check" | "-c" => {
                 mode = Mode::Check;
             }
+            "--heap-debug" => {
+                heap_debug = true;
+            }
+            "--record" | "--replay" => {
+                let Some(path) = args.next() else {
+                    eprintln!("temple-hc: {arg} expects a file");
+                    print_usage();
+                    return Ok(());
+                };
+                if arg == "--record" {
+                    record = Some(PathBuf::from(path));
+                } else {
+                    replay = Some(PathBuf::from(path));
+                }
+            }
+            "--macro" | "--doc" => {
+                let Some(val) = args.next() else {
+                    eprintln!("temple-hc: {arg} expects a value");
+                    print_usage();
+                    return Ok(());
+                };
+                if arg == "--macro" {
+                    macro_code = Some(val);
+                } else {
+                    macro_doc = Some(val);
+                }
+            }
+            "--max-heap" | "--max-depth" | "--max-steps" => {
+                let Some(n) = args.next().and_then(|v| v.trim().parse::<u64>().ok()) else {
+                    eprintln!("temple-hc: {arg} expects a number");
+                    print_usage();
+                    return Ok(());
+                };
+                let n = (n > 0).then_some(n);
+                match arg.as_str() {
+                    "--max-heap" => limits.max_heap_bytes = n.map(|n| n as usize),
+                    "--max-depth" => limits.max_call_depth = n.map(|n| n as usize),
+                    _ => limits.max_steps_per_frame = n,
+                }
+            }
             _ if spec.is_none() => {
                 spec = Some(arg);
             }
@@ -136,7 +201,10 @@ pub(super) fn run() -> io::Result<()> {
         }
     }
 
-    let res = compile_program(spec.as_deref());
+    let macro_src = macro_code
+        .as_deref()
+        .map(|code| (code, macro_doc.as_deref()));
+    let res = compile_program(spec.as_deref(), macro_src);
 
     match (mode, res) {
         (Mode::Check, Ok((_program, _macros))) => Ok(()),
@@ -153,21 +221,53 @@ pub(super) fn run() -> io::Result<()> {
         (Mode::Run, Ok((program, macros))) => {
             let rt = temple_rt::rt::TempleRt::connect()?;
             let mut vm = vm::Vm::new(rt, program, macros);
-            match vm.run() {
+            vm.set_limits(limits);
+            if let Some(path) = &replay {
+                vm.start_replay(path).map_err(|err| {
+                    io::Error::new(err.kind(), format!("--replay {}: {err}", path.display()))
+                })?;
+            }
+            if let Some(path) = &record {
+                vm.start_recording(path).map_err(|err| {
+                    io::Error::new(err.kind(), format!("--record {}: {err}", path.display()))
+                })?;
+            }
+            if heap_debug {
+                vm.enable_heap_debug();
+            }
+            if macro_src.is_some() {
+                vm.enable_macro_mode();
+            }
+            let res = vm.run();
+            let res = vm.finish_macro(res);
+            if let Some(report) = vm.heap_leak_report() {
+                eprint!("{report}");
+            }
+            match res {
                 Ok(()) => Ok(()),
                 Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
                 Err(err) => Err(err),
             }
         }
-        (Mode::Run, Err(err)) => match &err {
-            TempleHcError::Parse(_) => {
-                eprintln!("{err}");
-                process::exit(2);
+        (Mode::Run, Err(err)) => {
+            // A macro's stderr goes nowhere the user looks: connect just to tell the shell.
+            if macro_src.is_some()
+                && let Ok(mut rt) = temple_rt::rt::TempleRt::connect()
+            {
+                let msg = err.to_string();
+                let first = msg.lines().next().unwrap_or_default();
+                let _ = rt.shell_request(&format!("error {first}"));
             }
-            TempleHcError::Io(_) => {
-                eprintln!("temple-hc: {err}");
-                process::exit(1);
+            match &err {
+                TempleHcError::Parse(_) => {
+                    eprintln!("{err}");
+                    process::exit(2);
+                }
+                TempleHcError::Io(_) => {
+                    eprintln!("temple-hc: {err}");
+                    process::exit(1);
+                }
             }
-        },
+        }
     }
 }
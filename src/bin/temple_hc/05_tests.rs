@@ -40,6 +40,8 @@ mod tests {
         last_snd_ona: Option<u8>,
         mute_msgs: u32,
         is_muted: bool,
+        shell_requests: Vec<String>,
+        input_acks: u32,
     }
 
     fn spawn_fake_shell(
@@ -90,6 +92,8 @@ mod tests {
             let mut last_snd_ona: Option<u8> = None;
             let mut mute_msgs = 0u32;
             let mut is_muted = false;
+            let mut shell_requests = Vec::new();
+            let mut input_acks = 0u32;
             let mut outgoing = Some(outgoing);
 
             if !send_after_first_present {
@@ -126,6 +130,14 @@ mod tests {
                         } else if msg.kind == protocol::MSG_MUTE {
                             mute_msgs = mute_msgs.wrapping_add(1);
                             is_muted = msg.a != 0;
+                        } else if msg.kind == protocol::MSG_INPUT_ACK {
+                            input_acks += 1;
+                        } else if msg.kind == protocol::MSG_SHELL_REQUEST {
+                            let mut buf = vec![0u8; msg.a as usize];
+                            if stream.read_exact(&mut buf).is_err() {
+                                break;
+                            }
+                            shell_requests.push(String::from_utf8_lossy(&buf).into_owned());
                         } else if msg.kind == protocol::MSG_CLIPBOARD_SET {
                             let mut remaining = msg.a as usize;
                             let mut buf = [0u8; 4096];
@@ -151,6 +163,8 @@ mod tests {
                 last_snd_ona,
                 mute_msgs,
                 is_muted,
+                shell_requests,
+                input_acks,
             }
         })
     }
@@ -160,6 +174,45 @@ mod tests {
         outgoing: Vec<protocol::Msg>,
         send_after_first_present: bool,
         capture_present: Option<u32>,
+    ) -> (String, FakeShellResult) {
+        run_over_fake_shell_with(
+            spec,
+            outgoing,
+            send_after_first_present,
+            capture_present,
+            |vm, res| {
+                res.expect("run program");
+                vm.captured_output().expect("capture enabled").to_string()
+            },
+        )
+    }
+
+    /// Runs `spec` against a fake shell; `finish` sees the VM and its result before teardown.
+    fn run_over_fake_shell_with(
+        spec: &str,
+        outgoing: Vec<protocol::Msg>,
+        send_after_first_present: bool,
+        capture_present: Option<u32>,
+        finish: impl FnOnce(&mut vm::Vm, std::io::Result<()>) -> String,
+    ) -> (String, FakeShellResult) {
+        run_over_fake_shell_setup_with(
+            spec,
+            outgoing,
+            send_after_first_present,
+            capture_present,
+            |_| {},
+            finish,
+        )
+    }
+
+    /// Like `run_over_fake_shell_with`, with `setup` applied to the VM before it runs.
+    fn run_over_fake_shell_setup_with(
+        spec: &str,
+        outgoing: Vec<protocol::Msg>,
+        send_after_first_present: bool,
+        capture_present: Option<u32>,
+        setup: impl FnOnce(&mut vm::Vm),
+        finish: impl FnOnce(&mut vm::Vm, std::io::Result<()>) -> String,
     ) -> (String, FakeShellResult) {
         let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         let templeos_root = root.join("third_party/TempleOS");
@@ -227,8 +280,9 @@ mod tests {
         })();
         let mut vm = vm::Vm::new(rt, program, macros);
         vm.enable_capture();
-        vm.run().expect("run program");
-        let out = vm.captured_output().expect("capture enabled").to_string();
+        setup(&mut vm);
+        let run_res = vm.run();
+        let out = finish(&mut vm, run_res);
         drop(vm);
 
         match old_sock {
@@ -405,6 +459,301 @@ Main;
         let _ = std::fs::remove_dir_all(&dir);
     }
 
+    fn write_temp_hc(tag: &str, src: &str) -> (PathBuf, PathBuf) {
+        let uniq = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir =
+            std::env::temp_dir().join(format!("templehc-{tag}-{uniq}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let entry = dir.join(format!("{tag}.HC"));
+        std::fs::write(&entry, src).unwrap();
+        (dir, entry)
+    }
+
+    #[test]
+    fn run_heap_free_reuses_blocks_and_realloc_keeps_data() {
+        let _guard = env_guard();
+
+        let (dir, entry) = write_temp_hc(
+            "heap",
+            r#"
+U8 *a = MAlloc(100);
+Free(a);
+U8 *b = MAlloc(100);
+"%d %d\n", a == b, MSize(b);
+StrCpy(b, "abc");
+b = ReAlloc(b, 1000);
+"%s %d\n", b, MSize(b);
+Free(b);
+Free(NULL);
+"%d\n", MSize(NULL);
+"#,
+        );
+
+        let (out, _res) = run_over_fake_shell_capture(entry.to_str().unwrap());
+        assert_eq!(out, "1 100\nabc 1000\n0\n");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn heap_debug_flag_switches_the_heap_after_vm_init() {
+        let _guard = env_guard();
+        let old = std::env::var("TEMPLE_HC_HEAP_DEBUG").ok();
+        unsafe {
+            std::env::remove_var("TEMPLE_HC_HEAP_DEBUG");
+        }
+
+        // What `--heap-debug` does: enable it on a VM that already set up its window DC.
+        let (dir, entry) = write_temp_hc(
+            "heapflag",
+            "GrLine3(, 0, 0, 0, 4, 4, 0);\nU8 *a = MAlloc(8);\na[8] = 1;\n",
+        );
+        let (err, _res) = run_over_fake_shell_setup_with(
+            entry.to_str().unwrap(),
+            vec![],
+            false,
+            None,
+            |vm| vm.enable_heap_debug(),
+            |vm, res| {
+                // Only `a`: the window DC's pinned matrix is never reported.
+                let report = vm.heap_leak_report().unwrap_or_default();
+                assert!(report.contains("1 leaked block, 8 bytes"), "{report}");
+                res.expect_err("expected heap error").to_string()
+            },
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+        match old {
+            Some(v) => unsafe { std::env::set_var("TEMPLE_HC_HEAP_DEBUG", v) },
+            None => unsafe { std::env::remove_var("TEMPLE_HC_HEAP_DEBUG") },
+        }
+        assert!(err.contains("heap buffer overflow"), "{err}");
+    }
+
+    #[test]
+    fn run_heap_debug_catches_overflow_use_after_free_and_leaks() {
+        let _guard = env_guard();
+        let old = std::env::var("TEMPLE_HC_HEAP_DEBUG").ok();
+        unsafe {
+            std::env::set_var("TEMPLE_HC_HEAP_DEBUG", "1");
+        }
+
+        let run_err = |tag: &str, src: &str| {
+            let (dir, entry) = write_temp_hc(tag, src);
+            let (err, _res) =
+                run_over_fake_shell_with(entry.to_str().unwrap(), vec![], false, None, |_, res| {
+                    res.expect_err("expected heap error").to_string()
+                });
+            let _ = std::fs::remove_dir_all(&dir);
+            err
+        };
+        let overflow = run_err("overflow", "U8 *a = MAlloc(8);\na[8] = 1;\n");
+        assert!(overflow.contains("heap buffer overflow"), "{overflow}");
+        let uaf = run_err("uaf", "U8 *a = MAlloc(8);\nFree(a);\na[0] = 1;\n");
+        assert!(uaf.contains("use after free"), "{uaf}");
+        let double = run_err("double", "U8 *a = MAlloc(8);\nFree(a);\nFree(a);\n");
+        assert!(double.contains("double free"), "{double}");
+
+        let (dir, entry) = write_temp_hc(
+            "leak",
+            "U8 *a = MAlloc(24);\nU8 *b = MAlloc(8);\nStrCpy(a, \"kept\");\nFree(b);\n",
+        );
+        let (report, _res) =
+            run_over_fake_shell_with(entry.to_str().unwrap(), vec![], false, None, |vm, res| {
+                res.expect("run program");
+                vm.heap_leak_report().unwrap_or_default()
+            });
+        let _ = std::fs::remove_dir_all(&dir);
+
+        match old {
+            Some(v) => unsafe { std::env::set_var("TEMPLE_HC_HEAP_DEBUG", v) },
+            None => unsafe { std::env::remove_var("TEMPLE_HC_HEAP_DEBUG") },
+        }
+
+        assert!(report.contains("1 leaked block, 24 bytes"), "{report}");
+        assert!(report.contains("\"kept"), "{report}");
+    }
+
+    #[test]
+    fn run_limits_stop_runaway_loops_recursion_and_allocs() {
+        let _guard = env_guard();
+        let vars = [
+            ("TEMPLE_HC_MAX_STEPS", "5000"),
+            ("TEMPLE_HC_MAX_DEPTH", "64"),
+            ("TEMPLE_HC_MAX_HEAP", "4096"),
+        ];
+        let old: Vec<_> = vars
+            .iter()
+            .map(|&(k, v)| {
+                let old = std::env::var(k).ok();
+                unsafe { std::env::set_var(k, v) };
+                (k, old)
+            })
+            .collect();
+
+        let run = |tag: &str, src: &str| {
+            let (dir, entry) = write_temp_hc(tag, src);
+            let (out, _res) = run_over_fake_shell_with(
+                entry.to_str().unwrap(),
+                vec![],
+                false,
+                None,
+                |vm, res| match res {
+                    Ok(()) => vm.captured_output().unwrap_or_default().to_string(),
+                    Err(err) => format!("error: {err}"),
+                },
+            );
+            let _ = std::fs::remove_dir_all(&dir);
+            out
+        };
+        let spin = run("spin", "I64 n = 0;\nwhile (TRUE) { n++; }\n");
+        let yielding = run(
+            "yielding",
+            "I64 i, j, n = 0;\nfor (i = 0; i < 20; i++) {\n  for (j = 0; j < 1000; j++) n++;\n  Yield;\n}\n\"%d\\n\", n;\n",
+        );
+        let recurse = run(
+            "recurse",
+            "I64 Down(I64 n) { return Down(n + 1); }\nDown(0);\n",
+        );
+        let shallow = run(
+            "shallow",
+            "I64 Fib(I64 n) { if (n < 2) return n; return Fib(n - 1) + Fib(n - 2); }\n\"%d\\n\", Fib(12);\n",
+        );
+        let hog = run("hog", "I64 i;\nfor (i = 0; i < 100; i++) MAlloc(100);\n");
+        let churn = run(
+            "churn",
+            "I64 i;\nfor (i = 0; i < 100; i++) Free(MAlloc(1000));\n\"ok\\n\";\n",
+        );
+
+        for (k, v) in old {
+            match v {
+                Some(v) => unsafe { std::env::set_var(k, v) },
+                None => unsafe { std::env::remove_var(k) },
+            }
+        }
+
+        assert!(spin.contains("step budget exceeded: 5000"), "{spin}");
+        assert_eq!(yielding, "20000\n");
+        assert!(
+            recurse.contains("call depth limit exceeded: 64"),
+            "{recurse}"
+        );
+        assert_eq!(shallow, "144\n");
+        assert!(hog.contains("out of memory"), "{hog}");
+        assert_eq!(churn, "ok\n");
+    }
+
+    #[test]
+    fn run_ctrl_alt_c_breaks_out_of_a_loop_that_never_yields() {
+        let _guard = env_guard();
+        let (dir, entry) = write_temp_hc(
+            "brk",
+            "Refresh;\ntry { while (TRUE) {} } catch { \"broke\\n\"; }\n",
+        );
+        let (out, _res) = run_over_fake_shell_capture_with_events_after_first_present(
+            entry.to_str().unwrap(),
+            vec![
+                protocol::Msg::key(protocol::KEY_CONTROL, true),
+                protocol::Msg::key(protocol::KEY_ALT, true),
+                protocol::Msg::key(b'c' as u32, true),
+            ],
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(out, "broke\n");
+    }
+
+    /// Prints `frame:msg:arg1` for every key-down message until `q`, then a random number.
+    const INPUT_LOG_TEST_HC: &str = r#"
+I64 f, code, a1, a2;
+for (f = 0; f < 1000000; f++) {
+  code = ScanMsg(&a1, &a2, 1 << 2);
+  while (code) {
+    "%d:%d:%d\n", f, code, a1;
+    if (a1 == 'q') f = 1000000;
+    code = ScanMsg(&a1, &a2, 1 << 2);
+  }
+  Refresh;
+}
+"%d\n", RandU16;
+"#;
+
+    #[test]
+    fn run_replay_feeds_logged_events_in_lockstep_with_presents() {
+        let _guard = env_guard();
+        let (dir, entry) = write_temp_hc("replay", INPUT_LOG_TEST_HC);
+        let log = dir.join("input.log");
+        std::fs::write(
+            &log,
+            "# temple-hc input log v1\nseed 7\n3 1 key 97 1\n5 2 key 98 1\n5 2 key 113 1\n",
+        )
+        .unwrap();
+
+        let (out, _res) = run_over_fake_shell_setup_with(
+            entry.to_str().unwrap(),
+            vec![],
+            false,
+            None,
+            |vm| vm.start_replay(&log).expect("start replay"),
+            |vm, res| {
+                res.expect("run program");
+                vm.captured_output().expect("capture enabled").to_string()
+            },
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut lines = out.lines();
+        assert_eq!(lines.next(), Some("3:2:97"), "{out}");
+        assert_eq!(lines.next(), Some("5:2:98"), "{out}");
+        assert_eq!(lines.next(), Some("5:2:113"), "{out}");
+    }
+
+    #[test]
+    fn run_record_then_replay_reproduces_the_session() {
+        let _guard = env_guard();
+        let (dir, entry) = write_temp_hc("record", INPUT_LOG_TEST_HC);
+        let log = dir.join("input.log");
+
+        let run = |outgoing: Vec<protocol::Msg>, record: bool| {
+            let (out, _res) = run_over_fake_shell_setup_with(
+                entry.to_str().unwrap(),
+                outgoing,
+                record,
+                None,
+                |vm| {
+                    if record {
+                        vm.start_recording(&log).expect("start recording");
+                    } else {
+                        vm.start_replay(&log).expect("start replay");
+                    }
+                },
+                |vm, res| {
+                    res.expect("run program");
+                    vm.captured_output().expect("capture enabled").to_string()
+                },
+            );
+            out
+        };
+        let recorded = run(
+            vec![
+                protocol::Msg::key(b'x' as u32, true),
+                protocol::Msg::key(b'x' as u32, false),
+                protocol::Msg::mouse_move(10, 20),
+                protocol::Msg::key(b'q' as u32, true),
+            ],
+            true,
+        );
+        let replayed = run(vec![], false);
+        let logged = std::fs::read_to_string(&log).unwrap_or_default();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(recorded.contains(":2:113\n"), "{recorded}");
+        assert!(logged.contains(" move 10 20\n"), "{logged}");
+        assert!(logged.contains("\nfixed_ts "), "tS is pinned too: {logged}");
+        assert_eq!(replayed, recorded, "{logged}");
+    }
+
     #[test]
     fn preprocess_includes_from_templeos_tree() {
         let _guard = env_guard();
@@ -529,6 +878,136 @@ Main;
         let _ = std::fs::remove_dir_all(&dir);
     }
 
+    #[test]
+    fn type_lays_out_doldoc_with_the_shared_engine() {
+        let _guard = env_guard();
+
+        let (dir, entry) = write_temp_hc(
+            "type",
+            r#"
+Cd(__DIR__);
+Type("Note.DD");
+"done\n";
+"#,
+        );
+        std::fs::write(
+            dir.join("Note.DD"),
+            "$FG,RED$Hello$FG$ $LK,\"World\",A=\"FI:x\"$\n$ID,2$Indented $$5$ID,-2$\n",
+        )
+        .unwrap();
+
+        let old_root = std::env::var("TEMPLE_ROOT").ok();
+        unsafe { std::env::set_var("TEMPLE_ROOT", &dir) };
+
+        let (out, _res) = run_over_fake_shell_capture(entry.to_str().unwrap());
+        assert_eq!(out, "Hello World\n  Indented $5\ndone\n");
+
+        match old_root {
+            Some(v) => unsafe { std::env::set_var("TEMPLE_ROOT", v) },
+            None => unsafe { std::env::remove_var("TEMPLE_ROOT") },
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn doc_functions_build_walk_save_and_reload_documents() {
+        let _guard = env_guard();
+
+        let (dir, entry) = write_temp_hc(
+            "docs",
+            r#"
+Cd(__DIR__);
+"Hi $$RED$$there$$FG$$\n";
+CDoc *doc = DocNew("Out.DD");
+DocPrint(doc, "$$RED$$Red$$FG$$ %d\n", 5);
+CDoc *links = DocRead("Links.DD");
+CDocEntry *doc_e = links->head.next;
+while (doc_e != links) {
+  if (doc_e->type_u8 == DOCT_LINK) {
+    doc_e->tag = "Earth";
+    DocEntryDel(links, doc_e->next);
+  }
+  doc_e = doc_e->next;
+}
+DocBottom(doc);
+DocInsDoc(doc, links);
+for (doc_e = doc->head.next; doc_e != doc; doc_e = doc_e->next) {
+  "%d@%d,%d ", doc_e->type_u8, doc_e->x, doc_e->y;
+}
+"\n";
+DocWrite(doc);
+I64 size;
+U8 *buf = DocSave(DocRead("Out.DD"), &size);
+"%d:%s", size, buf;
+DocClear;
+"after clear\n";
+DocInsDoc(, links);
+"%s", DocSave(DocPut);
+"#,
+        );
+        std::fs::write(dir.join("Links.DD"), "$LK,\"World\",A=\"FI:x\"$drop\nend\n").unwrap();
+
+        let old_root = std::env::var("TEMPLE_ROOT").ok();
+        unsafe { std::env::set_var("TEMPLE_ROOT", &dir) };
+
+        let (out, _res) = run_over_fake_shell_capture(entry.to_str().unwrap());
+        let saved = "$FG,RED$Red$FG$ 5\n$LK,\"Earth\",A=\"FI:x\"$\nend\n";
+        assert_eq!(std::fs::read_to_string(dir.join("Out.DD")).unwrap(), saved);
+        assert_eq!(
+            out,
+            format!(
+                "Hi there\n15@0,0 0@0,0 15@3,0 0@3,0 1@5,0 28@0,1 1@0,1 0@0,2 1@3,2 \n\
+                 {}:Red 5\nEarth\nend\nafter clear\nEarth\nend\nafter clear\nEarth\nend\n",
+                saved.len()
+            )
+        );
+
+        match old_root {
+            Some(v) => unsafe { std::env::set_var("TEMPLE_ROOT", v) },
+            None => unsafe { std::env::remove_var("TEMPLE_ROOT") },
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn print_interprets_doldoc_colors_attributes_and_links() {
+        let _guard = env_guard();
+
+        let (dir, entry) = write_temp_hc(
+            "print-doldoc",
+            r#"
+"$$BG,RED$$ $$BG$$$BG,GREEN$ $BG$\n";
+Print("$UL,1$u$UL,0$ $$XY$$ %d\n", 7);
+"$$LK,\"Home\",A=\"FI:~/Home.DD\"$$\n";
+GetChar;
+"done\n";
+"#,
+        );
+
+        let (out, res) = run_over_fake_shell_capture_with_events_after_first_present(
+            entry.to_str().unwrap(),
+            vec![
+                protocol::Msg::mouse_move(4, 20),
+                protocol::Msg::mouse_button(protocol::MOUSE_BUTTON_LEFT, true),
+                protocol::Msg::key(protocol::KEY_ESCAPE, true),
+            ],
+        );
+        assert_eq!(out, "  \nu $XY$ 7\nHome\ndone\n");
+        let px = |x: usize, y: usize| res.fb[y * 640 + x];
+        assert_eq!(px(0, 0), 4, "red background");
+        assert_eq!(px(8, 0), 2, "green background");
+        assert_eq!(px(16, 0), 0, "default background");
+        assert_ne!(px(0, 15), 0, "underlined cell");
+        assert_eq!(px(8, 15), 0, "underline off again");
+        assert!(
+            res.shell_requests.iter().any(|r| r == "doc FI:~/Home.DD"),
+            "clicking the link opens the document: {:?}",
+            res.shell_requests
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
     #[test]
     fn cd_can_enter_templeos_dirs_and_relative_filefind_works() {
         let _guard = env_guard();
@@ -841,6 +1320,465 @@ Main;
         let _ = std::fs::remove_dir_all(&dir);
     }
 
+    #[test]
+    fn offscreen_dcs_draw_peek_blot_and_extract() {
+        let _guard = env_guard();
+
+        let (dir, entry) = write_temp_hc(
+            "dcs",
+            r#"
+CDC *img = DCNew(13, 4);
+"%d %d %d %d\n", img->width, img->width_internal, img->height, img->color;
+img->color = RED;
+GrPlot(img, 3, 2);
+"%d %d %d %d\n", GrPeek(img, 3, 2), GrPeek(img, 4, 2), GrPeek(img, 13, 0), img->body[2 * 16 + 3];
+DCFill(img);
+img->color = GREEN;
+GrLine(img, 0, 0, 12, 0);
+GrBlot(, 100, 50, img);
+"%d %d %d\n", GrPeek(100, 50), GrPeek(112, 50), GrPeek(100, 51);
+CDC *part = DCExtract(, 99, 50, 102, 51);
+CDC *copy = DCCopy(img);
+"%d %d %d %d %d\n", part->width, part->height, GrPeek(part, 0, 0), GrPeek(part, 1, 0), copy->color;
+DCDel(img);
+DCDel(part);
+DCDel(copy);
+"#,
+        );
+
+        let (out, res) = run_over_fake_shell_capture(entry.to_str().unwrap());
+        assert_eq!(out, "13 16 4 0\n4 0 -1 4\n2 2 0\n4 2 0 2 2\n");
+        assert_eq!(res.fb[50 * 640 + 100], 2);
+        assert_eq!(
+            res.fb[51 * 640 + 100],
+            0,
+            "transparent pixels are not blotted"
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn gr_files_round_trip_and_bmps_read_as_dcs() {
+        let _guard = env_guard();
+
+        let (dir, entry) = write_temp_hc(
+            "grfiles",
+            r#"
+Cd(__DIR__);
+CDC *img = DCNew(13, 4);
+img->color = GREEN;
+GrLine(img, 0, 0, 12, 0);
+img->color = RED;
+GrPlot(img, 3, 2);
+I64 size = GRWrite("Pic", img);
+"%d\n", GRWrite("Raw.GR", img, DCSF_PALETTE_GET);
+CDC *back = GRRead("Pic");
+CDC *raw = GRRead("Raw.GR");
+"%d %d %d %d %d\n", back->width, back->height, GrPeek(back, 3, 2), GrPeek(back, 12, 0), GrPeek(raw, 3, 2);
+CDC *z = GRRead("Z");
+"%d %d\n", z->width, GrPeek(z, 1, 0);
+CDC *bmp = BMPRead("Pic.BMP");
+"%d %d %d %d %d\n", bmp->width, bmp->height, GrPeek(bmp, 0, 0), GrPeek(bmp, 1, 0), GrPeek(bmp, 0, 1);
+"%d %d\n", GRRead("Missing"), size;
+"#,
+        );
+        let mut z = temple_rt::doldoc::Canvas::new(2, 1, 0);
+        z.pixels = vec![9, 14];
+        let gr = temple_rt::image::encode_gr(&z, false, None);
+        std::fs::write(dir.join("Z.GR.Z"), temple_rt::image::compress(&gr)).unwrap();
+        // 3x2, 4 bits per pixel, bottom row first: red, white, red over three whites.
+        let mut bmp = b"BM".to_vec();
+        for v in [0u32, 0, 14 + 40 + 16 * 4, 40, 3, 2] {
+            bmp.extend_from_slice(&v.to_le_bytes());
+        }
+        bmp.extend_from_slice(&[1, 0, 4, 0]);
+        bmp.extend_from_slice(&[0; 24]);
+        bmp.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0, 0, 0, 0xAA, 0]);
+        bmp.extend_from_slice(&[0; 14 * 4]);
+        bmp.extend_from_slice(&[0, 0, 0, 0, 0x10, 0x10, 0, 0]);
+        std::fs::write(dir.join("Pic.BMP"), bmp).unwrap();
+
+        let old_root = std::env::var("TEMPLE_ROOT").ok();
+        unsafe { std::env::set_var("TEMPLE_ROOT", &dir) };
+
+        let (out, _res) = run_over_fake_shell_capture(entry.to_str().unwrap());
+        let size = std::fs::metadata(dir.join("Pic.GR")).unwrap().len();
+        assert!(size < 32 + 16 * 4, "{size} bytes is not compressed");
+        assert_eq!(
+            out,
+            format!("224\n13 4 4 2 4\n2 14\n3 2 4 15 15\n0 {size}\n")
+        );
+        let raw = temple_rt::image::decode_gr(&std::fs::read(dir.join("Raw.GR")).unwrap())
+            .expect("decodes");
+        assert_eq!(raw.palette.expect("palette")[4], [0xAA, 0, 0, 0xFF]);
+
+        match old_root {
+            Some(v) => unsafe { std::env::set_var("TEMPLE_ROOT", v) },
+            None => unsafe { std::env::remove_var("TEMPLE_ROOT") },
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn gr_print_uses_the_loaded_font_and_scale() {
+        let _guard = env_guard();
+
+        let (dir, entry) = write_temp_hc(
+            "grfont",
+            r#"
+CDC *dc = DCNew(64, 32);
+dc->color = RED;
+"%d %d %d\n", GrPrintScaleSet(3), GrFontLoad("Dot"), GrFontLoad("Missing");
+GrPrint(dc, 2, 1, "AA");
+"%d %d %d %d %d\n", GrPeek(dc, 2, 1), GrPeek(dc, 4, 3), GrPeek(dc, 5, 1), GrPeek(dc, 26, 1), GrPeek(dc, 2, 4);
+"%d %d\n", GrPrintScaleSet(1), GrFontLoad();
+"#,
+        );
+        // An 8x16 PSF2 font whose `A` is one dot at the top left.
+        let fonts = dir.join(temple_rt::font::FONTS_DIR);
+        std::fs::create_dir_all(&fonts).unwrap();
+        let mut psf = vec![0x72, 0xB5, 0x4A, 0x86];
+        for v in [0u32, 32, 0, 256, 16, 16, 8] {
+            psf.extend_from_slice(&v.to_le_bytes());
+        }
+        let mut glyphs = vec![0u8; 256 * 16];
+        glyphs[b'A' as usize * 16] = 0x80;
+        psf.extend_from_slice(&glyphs);
+        std::fs::write(fonts.join("Dot.psf"), psf).unwrap();
+
+        let old_root = std::env::var("TEMPLE_ROOT").ok();
+        unsafe { std::env::set_var("TEMPLE_ROOT", &dir) };
+
+        let (out, _res) = run_over_fake_shell_capture(entry.to_str().unwrap());
+        assert_eq!(out, "1 1 0\n4 4 0 4 0\n3 1\n");
+
+        match old_root {
+            Some(v) => unsafe { std::env::set_var("TEMPLE_ROOT", v) },
+            None => unsafe { std::env::remove_var("TEMPLE_ROOT") },
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn transformed_and_depth_tested_3d_drawing() {
+        let _guard = env_guard();
+
+        let (dir, entry) = write_temp_hc(
+            "gr3d",
+            r#"
+class CD3I32 { I32 x, y, z; };
+CDC *dc = DCNew(40, 40);
+I64 x = 10, y = 0, z = 0;
+Mat4x4RotZ(dc->r, 3.14159265358979 / 2);
+Mat4x4MulXYZ(dc->r, &x, &y, &z);
+"%d %d %d\n", x, y, z;
+
+Mat4x4IdentEqu(dc->r);
+Mat4x4TranslationEqu(dc->r, 5, 0, 0);
+dc->flags |= DCF_TRANSFORMATION;
+dc->x = 1;
+dc->color = RED;
+GrPlot3(dc, 2, 3, 0);
+"%d ", GrPeek(dc, 8, 3);
+Mat4x4IdentEqu(dc->r);
+Mat4x4RotZ(dc->r, 3.14159265358979 / 2);
+dc->x = 20;
+dc->y = 20;
+U8 *elems = CAlloc(16);
+elems[0] = 8;
+elems[1] = 3;
+Sprite3(dc, 0, 0, 0, elems);
+"%d %d\n", GrPeek(dc, 20, 23), GrPeek(dc, 23, 20);
+dc->flags &= ~DCF_TRANSFORMATION;
+
+DCDepthBufAlloc(dc);
+dc->color = GREEN;
+GrLine3(dc, 0, 10, 5, 20, 10, 5);
+dc->color = BLUE;
+GrLine3(dc, 0, 10, 9, 20, 10, 9);
+"%d ", GrPeek(dc, 10, 10);
+dc->color = YELLOW;
+GrLine3(dc, 0, 10, 1, 20, 10, 1);
+"%d ", GrPeek(dc, 10, 10);
+CD3I32 a, b, c;
+a.x = 0; a.y = 30; a.z = 3;
+b.x = 20; b.y = 30; b.z = 3;
+c.x = 0; c.y = 39; c.z = 3;
+dc->color = CYAN;
+GrFillTri0(dc, &a, &b, &c);
+a.z = 7; b.z = 7; c.z = 7;
+dc->color = RED;
+GrFillTri0(dc, &a, &b, &c);
+"%d\n", GrPeek(dc, 2, 32);
+DCDepthBufRst(dc);
+GrFillTri0(dc, &a, &b, &c);
+"%d\n", GrPeek(dc, 2, 32);
+Free(elems);
+DCDel(dc);
+"#,
+        );
+
+        let (out, _res) = run_over_fake_shell_capture(entry.to_str().unwrap());
+        assert_eq!(out, "0 10 0\n4 4 0\n2 14 3\n4\n");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn sprite_meshes_are_depth_sorted_and_lit() {
+        let _guard = env_guard();
+
+        let (dir, entry) = write_temp_hc(
+            "mesh",
+            r#"
+U0 PutI32(U8 *p, I64 v)
+{
+  p[0] = v; p[1] = v >> 8; p[2] = v >> 16; p[3] = v >> 24;
+}
+I64 verts[27] = {0, 0, 5, 10, 0, 5, 0, 10, 5,
+                 0, 0, 9, 10, 0, 9, 0, 10, 9,
+                 20, 0, 0, 30, 0, 0, 20, 10, 10};
+I64 tris[12] = {RED, 0, 1, 2, BLUE, 3, 4, 5, GREEN, 6, 7, 8};
+U8 *elems = CAlloc(256);
+I64 i, n = 0;
+elems[0] = 24;
+PutI32(elems + 1, 9);
+PutI32(elems + 5, 3);
+for (i = 0; i < 27; i++)
+  PutI32(elems + 9 + i * 4, verts[i]);
+for (i = 0; i < 12; i++)
+  PutI32(elems + 117 + i * 4, tris[i]);
+
+CDC *dc = DCNew(40, 20);
+Sprite3(dc, 0, 0, 0, elems);
+"%d ", GrPeek(dc, 2, 2);
+for (i = 0; i < 16; i++)
+  if (GrPeek(dc, 20 + i % 4, i / 4) == GREEN)
+    n++;
+"%d %d\n", n, GrPeek(dc, 20, 3) ^ GrPeek(dc, 20, 0);
+
+dc->ls.x = 0; dc->ls.y = 0; dc->ls.z = 0;
+Sprite3(dc, 0, 0, 0, elems);
+n = 0;
+for (i = 0; i < 16; i++)
+  if (GrPeek(dc, 20 + i % 4, i / 4) == GREEN)
+    n++;
+"%d\n", n;
+Free(elems);
+DCDel(dc);
+"#,
+        );
+
+        let (out, _res) = run_over_fake_shell_capture(entry.to_str().unwrap());
+        assert_eq!(out, "4 11 8\n16\n");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn raster_ops_xor_count_collisions_and_dither() {
+        let _guard = env_guard();
+
+        let (dir, entry) = write_temp_hc(
+            "rop",
+            r#"
+CDC *dc = DCNew(16, 8);
+dc->color = RED;
+GrRect(dc, 0, 0, 4, 4);
+dc->color = ROP_XOR + YELLOW;
+GrPlot(dc, 0, 0);
+GrPlot(dc, 5, 5);
+"%d %d ", GrPeek(dc, 0, 0), GrPeek(dc, 5, 5);
+GrPlot(dc, 0, 0);
+"%d\n", GrPeek(dc, 0, 0);
+
+dc->color = ROP_COLLISION;
+GrLine(dc, 0, 2, 15, 2);
+"%d %d\n", dc->collision_cnt, GrPeek(dc, 0, 2);
+
+dc->color = ROPF_DITHER + (WHITE << 16) + BLUE;
+GrLine(dc, 8, 0, 9, 0);
+"%d %d ", GrPeek(dc, 8, 0), GrPeek(dc, 9, 0);
+dc->color = GREEN;
+U8 *elems = CAlloc(32);
+elems[0] = 2;
+elems[1] = BLUE;
+elems[2] = WHITE;
+elems[3] = 10;
+elems[8] = 7;
+elems[12] = 1;
+elems[16] = 7;
+Sprite3(dc, 0, 0, 0, elems);
+GrPlot(dc, 2, 7);
+"%d %d %d\n", GrPeek(dc, 0, 7), GrPeek(dc, 1, 7), GrPeek(dc, 2, 7);
+
+CDC *img = DCNew(2, 1);
+DCFill(img);
+img->color = GREEN;
+GrPlot(img, 0, 0);
+dc->color = ROP_MONO + CYAN;
+GrBlot(dc, 12, 6, img);
+"%d %d\n", GrPeek(dc, 12, 6), GrPeek(dc, 13, 6);
+Free(elems);
+DCDel(img);
+DCDel(dc);
+"#,
+        );
+
+        let (out, _res) = run_over_fake_shell_capture(entry.to_str().unwrap());
+        assert_eq!(out, "10 14 4\n2 4\n1 15 15 1 2\n3 0\n");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn sprite_curves_match_their_snapshots() {
+        // The expected bitmaps are snapshots of our own rasterizer (see `ellipse_pixels` and
+        // `bspline` in `src/sprite.rs`), not output of TempleOS' `Gr` routines: this guards
+        // against regressions, not against drift from upstream.
+        let _guard = env_guard();
+
+        let (dir, entry) = write_temp_hc(
+            "curves",
+            r##"
+U0 PutI32(U8 *p, I64 v)
+{
+  p[0] = v; p[1] = v >> 8; p[2] = v >> 16; p[3] = v >> 24;
+}
+U0 PutI32s(U8 *p, I64 *vals, I64 cnt)
+{
+  I64 i;
+  for (i = 0; i < cnt; i++)
+    PutI32(p + i * 4, vals[i]);
+}
+U0 Show(U8 *elems, I64 x, I64 y, I64 w, I64 h)
+{
+  CDC *dc = DCNew(w, h);
+  I64 i, j;
+  dc->color = WHITE;
+  Sprite3(dc, x, y, 0, elems);
+  for (j = 0; j < h; j++) {
+    for (i = 0; i < w; i++)
+      if (GrPeek(dc, i, j))
+        "#";
+      else
+        ".";
+    "\n";
+  }
+  "\n";
+  DCDel(dc);
+}
+U8 *e = CAlloc(256);
+
+// SPT_ELLIPSE: center (0, 0), radii 5 x 3.
+I64 ellipse[4] = {0, 0, 5, 3};
+e[0] = 15;
+PutI32s(e + 1, ellipse, 4);
+Show(e, 6, 4, 13, 9);
+
+// SPT_ROTATED_RECT: (0, 0)-(6, 3) turned a quarter turn.
+MemSet(e, 0, 256);
+I64 rect[4] = {0, 0, 6, 3};
+e[0] = 13;
+PutI32s(e + 1, rect, 4);
+PutI32(e + 17, 0x54442D18);
+PutI32(e + 21, 0x3FF921FB);
+Show(e, 4, 1, 6, 9);
+
+// SPT_POLYGON: a 4 sided polygon of radius 4.
+MemSet(e, 0, 256);
+I64 poly[4] = {0, 0, 4, 4};
+e[0] = 16;
+PutI32s(e + 1, poly, 4);
+PutI32(e + 25, 4);
+Show(e, 5, 5, 11, 11);
+
+// SPT_BSPLINE2 and SPT_BSPLINE3 over the same control points.
+MemSet(e, 0, 256);
+I64 spline[13] = {4, 0, 0, 0, 4, 8, 0, 8, 8, 0, 12, 0, 0};
+e[0] = 17;
+PutI32s(e + 1, spline, 13);
+Show(e, 0, 0, 13, 9);
+e[0] = 19;
+Show(e, 0, 0, 13, 9);
+
+// SPT_POLYPT: right, right, down-right, down from (0, 0).
+MemSet(e, 0, 256);
+I64 pts[3] = {4, 0, 0};
+e[0] = 9;
+PutI32s(e + 1, pts, 3);
+e[13] = 4 | 4 << 3 | 7 << 6;
+e[14] = 7 >> 2 | 6 << 1;
+Show(e, 0, 0, 4, 3);
+Free(e);
+"##,
+        );
+
+        let (out, _res) = run_over_fake_shell_capture(entry.to_str().unwrap());
+        let expected = r"
+.............
+....#####....
+..##.....##..
+.#.........#.
+.#.........#.
+.#.........#.
+..##.....##..
+....#####....
+.............
+
+......
+.####.
+.#..#.
+.#..#.
+.#..#.
+.#..#.
+.#..#.
+.####.
+......
+
+...........
+.....#.....
+....#.#....
+...#...#...
+..#.....#..
+.#.......#.
+..#.....#..
+...#...#...
+....#.#....
+.....#.....
+...........
+
+#...........#
+.#.........#.
+.#.........#.
+.#.........#.
+..#.......#..
+...#.....#...
+...##...##...
+....##.##....
+.....###.....
+
+#...........#
+.#.........#.
+.#.........#.
+.#.........#.
+..#.......#..
+...#.....#...
+...#.....#...
+....##.##....
+.....###.....
+
+###.
+...#
+...#
+
+";
+        assert_eq!(out, &expected[1..]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
     #[test]
     fn run_snd_smoke_over_ipc() {
         let _guard = env_guard();
@@ -1323,6 +2261,167 @@ Main;
         let _ = std::fs::remove_dir_all(&dir);
     }
 
+    #[test]
+    fn pop_up_form_edits_format_members() {
+        let _guard = env_guard();
+        let (dir, entry) = write_temp_hc(
+            "popupform",
+            r#"
+class CSettings {
+  U8 *name format "$$DA-P,LEN=15,A=\"Name:%s\"$$\n";
+  I64 level format "$$DA,A=\"Level:%d\"$$\n";
+  Bool sound format "$$CB,\"Sound\"$$\n";
+  I64 color format "$$LS,D=\"ST_COLORS\"$$\n";
+  I64 unused;
+};
+
+U0 Main() {
+  CSettings s;
+  s.name = "Al";
+  s.level = 3;
+  s.sound = FALSE;
+  s.color = 1;
+  s.unused = 7;
+  I64 res = PopUpForm(&s);
+  "%d %s %d %d %d %d\n", res, s.name, s.level, s.sound, s.color, s.unused;
+}
+Main;
+"#,
+        );
+
+        let (out, _res) = run_over_fake_shell_capture_with_events(
+            entry.to_str().unwrap(),
+            vec![
+                protocol::Msg::key(b'x' as u32, true),
+                protocol::Msg::key(protocol::KEY_DOWN, true),
+                protocol::Msg::key(protocol::KEY_BACKSPACE, true),
+                protocol::Msg::key(b'9' as u32, true),
+                protocol::Msg::key(protocol::KEY_DOWN, true),
+                protocol::Msg::key(b' ' as u32, true),
+                protocol::Msg::key(protocol::KEY_DOWN, true),
+                protocol::Msg::key(b' ' as u32, true),
+                protocol::Msg::key(protocol::KEY_ESCAPE, true),
+            ],
+        );
+        assert_eq!(out, "1 Alx 9 1 2 7\n");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn macro_mode_turns_cd_dir_and_ed_into_shell_requests() {
+        let _guard = env_guard();
+        let (dir, entry) = write_temp_hc(
+            "docmacro",
+            "Cd(\"::/Kernel\");Dir;Ed(\"FontStd.HC\");View;\n\"%d\\n\",1+2;\n",
+        );
+
+        let (_out, res) = run_over_fake_shell_setup_with(
+            entry.to_str().unwrap(),
+            Vec::new(),
+            false,
+            None,
+            |vm| vm.enable_macro_mode(),
+            |vm, res| {
+                vm.finish_macro(res).expect("run macro");
+                String::new()
+            },
+        );
+        assert_eq!(
+            res.shell_requests,
+            [
+                "cd ::/Kernel",
+                "dir ::/Kernel",
+                "doc ::/Kernel/FontStd.HC",
+                "print 3"
+            ]
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn busy_loops_take_input_without_acking_it() {
+        let _guard = env_guard();
+        let old = std::env::var("TEMPLE_HC_MAX_STEPS").ok();
+        unsafe { std::env::set_var("TEMPLE_HC_MAX_STEPS", "2000000") };
+        let (dir, entry) = write_temp_hc("busy_ack", "while (TRUE) {}\n");
+
+        // The loop polls input (for Ctrl+Alt+C) but never yields: the shell must go on seeing
+        // it as not responding until the step budget stops it.
+        let (out, res) = run_over_fake_shell_with(
+            entry.to_str().unwrap(),
+            vec![
+                protocol::Msg::key(b'x' as u32, true),
+                protocol::Msg::key(b'x' as u32, false),
+            ],
+            false,
+            None,
+            |_vm, res| format!("{res:?}"),
+        );
+        match old {
+            Some(v) => unsafe { std::env::set_var("TEMPLE_HC_MAX_STEPS", v) },
+            None => unsafe { std::env::remove_var("TEMPLE_HC_MAX_STEPS") },
+        }
+        assert!(out.contains("step budget exceeded"), "{out}");
+        assert_eq!(res.input_acks, 0);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn macro_mode_reports_errors_to_the_shell() {
+        let _guard = env_guard();
+        let (dir, entry) = write_temp_hc("docmacro_err", "NoSuchFn(1);\n");
+
+        let (_out, res) = run_over_fake_shell_setup_with(
+            entry.to_str().unwrap(),
+            Vec::new(),
+            false,
+            None,
+            |vm| vm.enable_macro_mode(),
+            |vm, res| {
+                assert!(vm.finish_macro(res).is_err());
+                String::new()
+            },
+        );
+        assert_eq!(res.shell_requests.len(), 1);
+        assert!(
+            res.shell_requests[0].starts_with("error ")
+                && res.shell_requests[0].contains("NoSuchFn"),
+            "{:?}",
+            res.shell_requests
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn macro_mode_asks_for_a_window_before_waiting_for_input() {
+        let _guard = env_guard();
+        let (dir, entry) = write_temp_hc("docmacro_input", "\"%c\\n\",GetChar(,FALSE);\n");
+
+        let (_out, res) = run_over_fake_shell_setup_with(
+            entry.to_str().unwrap(),
+            vec![
+                protocol::Msg::key(b'x' as u32, true),
+                protocol::Msg::key(b'x' as u32, false),
+            ],
+            false,
+            None,
+            |vm| vm.enable_macro_mode(),
+            |vm, res| {
+                vm.finish_macro(res).expect("run macro");
+                String::new()
+            },
+        );
+        assert_eq!(res.shell_requests, ["show", "print x"]);
+        // Presenting after taking input tells the shell's hang watchdog the session is alive.
+        assert_eq!(res.input_acks, 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
     #[test]
     fn run_compare_chain_semantics_over_ipc() {
         let _guard = env_guard();
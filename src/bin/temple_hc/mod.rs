@@ -1075,6 +1075,8 @@ enum Stmt {
     Empty,
     Print {
         parts: Vec<Expr>,
+        /// Source file, whose DolDoc bins `$SP$` sprites in the text refer to.
+        file: Arc<str>,
     },
     Label(String),
     Goto(String),
@@ -1138,6 +1140,8 @@ struct FieldDef {
     pointer: bool,
     array_lens: Vec<Expr>,
     init: Option<Expr>,
+    /// `format "$$DA,...$$"` member meta data, used by `PopUpForm`.
+    format: Option<String>,
 }
 
 #[derive(Clone, Debug)]
@@ -1471,13 +1475,14 @@ impl Parser {
         }
 
         if matches!(self.peek().kind, TokenKind::Str(_) | TokenKind::Char(_)) {
+            let file = self.peek().span.file.clone();
             let mut parts = Vec::new();
             parts.push(self.parse_expr()?);
             while self.eat_sym(Sym::Comma) {
                 parts.push(self.parse_expr()?);
             }
             self.expect_sym(Sym::Semicolon)?;
-            return Ok(Stmt::Print { parts });
+            return Ok(Stmt::Print { parts, file });
         }
 
         if self.is_kw("throw") {
@@ -2242,14 +2247,28 @@ impl Parser {
 
             // Field declarations are essentially variable declarations without requiring runtime execution.
             let decls = self.parse_var_decl_list()?;
+            // Member meta data: `I64 age format "$$DA,...$$\n" help "...";`
+            let mut format = None;
+            while let TokenKind::Ident(meta) = &self.peek().kind {
+                let meta = meta.clone();
+                self.bump();
+                let value = self.parse_assign()?;
+                if meta == "format"
+                    && let Expr::Str(fmt) = value
+                {
+                    format = Some(fmt);
+                }
+            }
             self.expect_sym(Sym::Semicolon)?;
-            for decl in decls {
+            let last = decls.len().saturating_sub(1);
+            for (i, decl) in decls.into_iter().enumerate() {
                 fields.push(FieldDef {
                     ty: decl.ty,
                     name: decl.name,
                     pointer: decl.pointer,
                     array_lens: decl.array_lens,
                     init: decl.init,
+                    format: if i == last { format.take() } else { None },
                 });
             }
         }
@@ -8,6 +8,23 @@ pub(super) struct TempleMsg {
     pub(super) arg2: i64,
 }
 
+/// A link in printed text: the pixels it covers on screen and where it leads.
+#[derive(Clone, Debug)]
+pub(super) struct TextLink {
+    pub(super) x0: i32,
+    pub(super) x1: i32,
+    pub(super) y: i32,
+    pub(super) target: temple_rt::doldoc::LinkTarget,
+}
+
+/// A blinking character cell of printed text, redrawn with each frame.
+#[derive(Clone, Debug)]
+pub(super) struct TextBlink {
+    pub(super) x: i32,
+    pub(super) y: i32,
+    pub(super) cell: temple_rt::doldoc::DocCell,
+}
+
 #[derive(Clone, Debug)]
 pub(super) enum MenuAction {
     None,
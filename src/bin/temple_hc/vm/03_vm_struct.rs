@@ -1,5 +1,17 @@
 use super::prelude::*;
-use super::{Env, MenuState, ObjRef, TempleMsg};
+use super::{Env, Heap, InputStamp, MenuState, ObjRef, TempleMsg, TextBlink, TextLink};
+
+/// Per-VM resource budgets (`None` = unlimited), set from `TEMPLE_HC_MAX_HEAP`,
+/// `TEMPLE_HC_MAX_DEPTH` and `TEMPLE_HC_MAX_STEPS` or the matching `temple-hc` flags.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct VmLimits {
+    /// Live bytes the program may hold in `MAlloc`ed blocks.
+    pub(crate) max_heap_bytes: Option<usize>,
+    /// Nested HolyC function calls.
+    pub(crate) max_call_depth: Option<usize>,
+    /// Statements executed between two frames (`Refresh`, `Yield`, `Sleep`, a blocking read...).
+    pub(crate) max_steps_per_frame: Option<u64>,
+}
 
 pub(crate) struct Vm {
     pub(super) rt: TempleRt,
@@ -11,7 +23,7 @@ pub(crate) struct Vm {
     pub(super) cwd: String,
     pub(super) doldoc_bin_ptr_cache: HashMap<(Arc<str>, u32), i64>,
     pub(super) doldoc_bin_len_by_ptr: HashMap<i64, usize>,
-    pub(super) heap: Vec<u8>,
+    pub(super) heap: Heap,
     pub(super) scan_char: u32,
     pub(super) key_queue: VecDeque<u32>,
     pub(super) msg_queue: VecDeque<TempleMsg>,
@@ -22,10 +34,16 @@ pub(crate) struct Vm {
     pub(super) ms: ObjRef,
     pub(super) ms_pos: ObjRef,
     pub(super) dc_alias: ObjRef,
+    /// `Fs->put_doc`: the task's document, which `Print` output is appended to.
+    pub(super) put_doc: ObjRef,
     pub(super) text_x: i32,
     pub(super) text_y: i32,
     pub(super) text_fg: u8,
     pub(super) text_bg: u8,
+    /// DolDoc attributes (`$UL$`, `$IV$`, `$ID$`...) printed text has left on.
+    pub(super) text_style: temple_rt::doldoc::DocStyle,
+    pub(super) text_links: Vec<TextLink>,
+    pub(super) text_blinks: Vec<TextBlink>,
     pub(super) rng_seed: u64,
     pub(super) rng_state: u64,
     pub(super) start_instant: std::time::Instant,
@@ -36,4 +54,16 @@ pub(crate) struct Vm {
     pub(super) in_draw_it: bool,
     pub(super) last_host_error: Option<String>,
     pub(super) main_called: bool,
+    pub(super) limits: VmLimits,
+    pub(super) call_depth: usize,
+    pub(super) steps_since_frame: u64,
+    /// Frames presented so far and `poll_events` calls in the current one (input log stamps).
+    pub(super) frame: u64,
+    pub(super) polls_this_frame: u32,
+    pub(super) input_recorder: Option<io::LineWriter<std::fs::File>>,
+    pub(super) input_replay: Option<VecDeque<(InputStamp, Event)>>,
+    /// Running a DolDoc macro for the shell: `Cd`/`Dir` act on the shell's view.
+    pub(super) macro_mode: bool,
+    /// The macro session asked the shell for a window (see `macro_input_wait`).
+    pub(super) macro_window_shown: bool,
 }
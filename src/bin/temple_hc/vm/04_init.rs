@@ -1,5 +1,5 @@
 use super::prelude::*;
-use super::{ArrayValue, Env, Obj, Value, Vm};
+use super::{ArrayValue, Env, Heap, Obj, Value, Vm, VmLimits, doc};
 
 impl Vm {
     pub(crate) fn new(
@@ -54,6 +54,7 @@ impl Vm {
         env.define("LTPURPLE".to_string(), Value::Int(13));
         env.define("YELLOW".to_string(), Value::Int(14));
         env.define("WHITE".to_string(), Value::Int(15));
+        env.define("TRANSPARENT".to_string(), Value::Int(0xFF));
         env.define("COLORS_NUM".to_string(), Value::Int(16));
 
         let mut define_lists: HashMap<String, Vec<String>> = HashMap::new();
@@ -166,11 +167,12 @@ impl Vm {
         }));
         env.define("ms".to_string(), Value::Obj(ms.clone()));
 
+        let (lx, ly, lz) = temple_rt::gr3d::DEFAULT_LIGHT;
         let dc_ls = Rc::new(RefCell::new(Obj {
             fields: HashMap::from([
-                ("x".to_string(), Value::Int(0)),
-                ("y".to_string(), Value::Int(0)),
-                ("z".to_string(), Value::Int(0)),
+                ("x".to_string(), Value::Int(lx)),
+                ("y".to_string(), Value::Int(ly)),
+                ("z".to_string(), Value::Int(lz)),
             ]),
         }));
         let dc_alias = Rc::new(RefCell::new(Obj {
@@ -178,6 +180,7 @@ impl Vm {
                 ("color".to_string(), Value::Int(15)),
                 ("thick".to_string(), Value::Int(1)),
                 ("flags".to_string(), Value::Int(0)),
+                ("collision_cnt".to_string(), Value::Int(0)),
                 ("ls".to_string(), Value::Obj(dc_ls)),
                 ("width".to_string(), Value::Int(w as i64)),
                 ("height".to_string(), Value::Int(h as i64)),
@@ -217,6 +220,8 @@ impl Vm {
             ]),
         }));
 
+        let put_doc = doc::new_doc("");
+
         let fs = Rc::new(RefCell::new(Obj {
             fields: HashMap::from([
                 ("pix_width".to_string(), Value::Int(w as i64)),
@@ -234,6 +239,8 @@ impl Vm {
                 ("last_ctrl".to_string(), Value::Obj(ctrl_head)),
                 ("horz_scroll".to_string(), Value::Obj(horz_scroll)),
                 ("vert_scroll".to_string(), Value::Obj(vert_scroll)),
+                ("put_doc".to_string(), Value::Obj(put_doc.clone())),
+                ("display_doc".to_string(), Value::Obj(put_doc.clone())),
             ]),
         }));
         env.define("Fs".to_string(), Value::Obj(fs));
@@ -388,6 +395,10 @@ impl Vm {
             (seed, seed)
         };
 
+        let heap_debug = std::env::var("TEMPLE_HC_HEAP_DEBUG")
+            .ok()
+            .is_some_and(|v| !matches!(v.trim(), "" | "0"));
+
         let start_instant = std::time::Instant::now();
         let fixed_ts = std::env::var("TEMPLE_HC_FIXED_TS")
             .ok()
@@ -396,7 +407,7 @@ impl Vm {
         let mut rt = rt;
         rt.clear(0);
 
-        Self {
+        let mut vm = Self {
             rt,
             env,
             macros,
@@ -406,7 +417,7 @@ impl Vm {
             cwd,
             doldoc_bin_ptr_cache: HashMap::new(),
             doldoc_bin_len_by_ptr: HashMap::new(),
-            heap: vec![0u8],
+            heap: Heap::new(heap_debug),
             scan_char: 0,
             key_queue: VecDeque::new(),
             msg_queue: VecDeque::new(),
@@ -417,10 +428,14 @@ impl Vm {
             ms,
             ms_pos,
             dc_alias,
+            put_doc,
             text_x: 0,
             text_y: 0,
             text_fg: 15,
             text_bg: 0,
+            text_style: temple_rt::doldoc::DocStyle::new(15, 0),
+            text_links: Vec::new(),
+            text_blinks: Vec::new(),
             rng_seed,
             rng_state,
             start_instant,
@@ -431,9 +446,42 @@ impl Vm {
             in_draw_it: false,
             last_host_error: None,
             main_called: false,
+            limits: Self::limits_from_env(),
+            call_depth: 0,
+            steps_since_frame: 0,
+            frame: 0,
+            polls_this_frame: 0,
+            input_recorder: None,
+            input_replay: None,
+            macro_mode: false,
+            macro_window_shown: false,
+        };
+        let window_dc = Value::Obj(vm.dc_alias.clone());
+        vm.dc_init_3d(&window_dc, true);
+        vm
+    }
+
+    fn limits_from_env() -> VmLimits {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse::<T>().ok())
+        }
+        VmLimits {
+            max_heap_bytes: var("TEMPLE_HC_MAX_HEAP").filter(|&n| n > 0),
+            max_call_depth: var("TEMPLE_HC_MAX_DEPTH").filter(|&n| n > 0),
+            max_steps_per_frame: var("TEMPLE_HC_MAX_STEPS").filter(|&n| n > 0),
         }
     }
 
+    /// Overrides individual budgets (e.g. from command line flags); `None` fields are kept.
+    pub(crate) fn set_limits(&mut self, limits: VmLimits) {
+        let cur = &mut self.limits;
+        cur.max_heap_bytes = limits.max_heap_bytes.or(cur.max_heap_bytes);
+        cur.max_call_depth = limits.max_call_depth.or(cur.max_call_depth);
+        cur.max_steps_per_frame = limits.max_steps_per_frame.or(cur.max_steps_per_frame);
+    }
+
     fn compute_initial_cwd() -> String {
         let Ok(root) = std::env::var("TEMPLE_ROOT") else {
             return "/Home".to_string();
@@ -1,18 +1,231 @@
+use std::collections::BTreeMap;
+
 use super::prelude::*;
 use super::{Obj, Value, Vm};
 
-impl Vm {
-    pub(super) fn heap_alloc(&mut self, size: usize, zeroed: bool) -> i64 {
-        if size == 0 {
-            return 0;
+/// Bytes in the header stored in front of every allocation: `U64` size, `U32` signature and a
+/// `U32` allocation serial (see `CMemBlk` in TempleOS' `::/Kernel/KernelA.HH`).
+const HEAP_HDR_LEN: usize = 16;
+const HEAP_ALIGN: usize = 8;
+const HEAP_SIG_USED: u32 = u32::from_le_bytes(*b"MBUs");
+const HEAP_SIG_FREE: u32 = u32::from_le_bytes(*b"MBUn");
+
+/// Debug mode (`TEMPLE_HC_HEAP_DEBUG=1` or `--heap-debug`) guards each block with red zones,
+/// poisons fresh and freed memory, and keeps freed blocks out of circulation for a while so
+/// stale pointers hit poison instead of somebody else's data.
+const HEAP_REDZONE_LEN: usize = 16;
+const HEAP_REDZONE_BYTE: u8 = 0xFD;
+const HEAP_POISON_NEW: u8 = 0xCD;
+const HEAP_POISON_FREED: u8 = 0xDD;
+const HEAP_QUARANTINE_BYTES: usize = 1 << 20;
+const HEAP_LEAK_REPORT_MAX: usize = 20;
+
+#[derive(Clone, Copy, Debug)]
+struct HeapBlock {
+    /// Start of the block's span (its header).
+    span: usize,
+    span_len: usize,
+    size: usize,
+    serial: u64,
+    /// Owned by the VM (e.g. cached DolDoc sprites); never freed or reported as a leak.
+    pinned: bool,
+}
+
+/// The HolyC heap: one flat byte array addressed by `I64` pointers (0 is `NULL`).
+pub(super) struct Heap {
+    mem: Vec<u8>,
+    /// Live blocks, keyed by the address handed to HolyC.
+    blocks: BTreeMap<usize, HeapBlock>,
+    /// Free spans, keyed by start, coalesced with their neighbors.
+    free: BTreeMap<usize, usize>,
+    /// Freed blocks not yet returned to `free` (debug mode), oldest first.
+    quarantine: VecDeque<HeapBlock>,
+    quarantine_bytes: usize,
+    /// Sum of the requested sizes of all live blocks.
+    live_bytes: usize,
+    next_serial: u64,
+    debug: bool,
+}
+
+impl Heap {
+    pub(super) fn new(debug: bool) -> Self {
+        Self {
+            mem: vec![0u8; HEAP_ALIGN],
+            blocks: BTreeMap::new(),
+            free: BTreeMap::new(),
+            quarantine: VecDeque::new(),
+            quarantine_bytes: 0,
+            live_bytes: 0,
+            next_serial: 1,
+            debug,
+        }
+    }
+
+    fn redzone(&self) -> usize {
+        if self.debug { HEAP_REDZONE_LEN } else { 0 }
+    }
+
+    fn alloc(&mut self, size: usize, zeroed: bool, pinned: bool) -> usize {
+        let rz = self.redzone();
+        let need = (HEAP_HDR_LEN + 2 * rz + size).next_multiple_of(HEAP_ALIGN);
+
+        let fit = self
+            .free
+            .iter()
+            .find(|&(_, &len)| len >= need)
+            .map(|(&start, &len)| (start, len));
+        let (span, span_len) = match fit {
+            Some((start, len)) => {
+                self.free.remove(&start);
+                if len - need >= HEAP_HDR_LEN + HEAP_ALIGN {
+                    self.free.insert(start + need, len - need);
+                    (start, need)
+                } else {
+                    (start, len)
+                }
+            }
+            None => {
+                let start = self.mem.len();
+                self.mem.resize(start + need, 0);
+                (start, need)
+            }
+        };
+
+        let serial = self.next_serial;
+        self.next_serial += 1;
+        let addr = span + HEAP_HDR_LEN + rz;
+        self.write_header(span, size, HEAP_SIG_USED, serial);
+        let fill = if zeroed || !self.debug {
+            0
+        } else {
+            HEAP_POISON_NEW
+        };
+        self.mem[addr..addr + size].fill(fill);
+        if rz > 0 {
+            self.mem[span + HEAP_HDR_LEN..addr].fill(HEAP_REDZONE_BYTE);
+            self.mem[addr + size..span + span_len].fill(HEAP_REDZONE_BYTE);
         }
-        let addr = self.heap.len() as i64;
-        let fill = if zeroed { 0u8 } else { 0u8 };
-        self.heap.extend(std::iter::repeat(fill).take(size));
+        self.live_bytes += size;
+        self.blocks.insert(
+            addr,
+            HeapBlock {
+                span,
+                span_len,
+                size,
+                serial,
+                pinned,
+            },
+        );
         addr
     }
 
-    fn heap_check_range(&self, addr: i64, len: usize) -> Result<usize, String> {
+    fn write_header(&mut self, span: usize, size: usize, sig: u32, serial: u64) {
+        let hdr = &mut self.mem[span..span + HEAP_HDR_LEN];
+        hdr[0..8].copy_from_slice(&(size as u64).to_le_bytes());
+        hdr[8..12].copy_from_slice(&sig.to_le_bytes());
+        hdr[12..16].copy_from_slice(&(serial as u32).to_le_bytes());
+    }
+
+    /// Checks the header and red zones of a live block (debug mode only).
+    fn check_block(&self, addr: usize, block: &HeapBlock) -> Result<(), String> {
+        if !self.debug {
+            return Ok(());
+        }
+        let hdr = &self.mem[block.span..block.span + HEAP_HDR_LEN];
+        let size = u64::from_le_bytes(hdr[0..8].try_into().unwrap_or_default());
+        let sig = u32::from_le_bytes(hdr[8..12].try_into().unwrap_or_default());
+        let front = &self.mem[block.span + HEAP_HDR_LEN..addr];
+        let back = &self.mem[addr + block.size..block.span + block.span_len];
+        if sig != HEAP_SIG_USED || size != block.size as u64 {
+            return Err(format!(
+                "heap corruption: header of block #{} at {addr:#x} overwritten (buffer underrun?)",
+                block.serial
+            ));
+        }
+        if let Some(i) = front.iter().position(|&b| b != HEAP_REDZONE_BYTE) {
+            return Err(format!(
+                "heap buffer underrun: {} bytes before block #{} at {addr:#x} ({} bytes) were written",
+                front.len() - i,
+                block.serial,
+                block.size
+            ));
+        }
+        if let Some(i) = back.iter().position(|&b| b != HEAP_REDZONE_BYTE) {
+            return Err(format!(
+                "heap buffer overflow: write {} bytes past the end of block #{} at {addr:#x} ({} bytes)",
+                i + 1,
+                block.serial,
+                block.size
+            ));
+        }
+        Ok(())
+    }
+
+    fn free(&mut self, addr: usize) -> Result<(), String> {
+        let Some(block) = self.blocks.get(&addr).copied() else {
+            if self
+                .quarantine
+                .iter()
+                .any(|b| b.span + HEAP_HDR_LEN + self.redzone() == addr)
+            {
+                return Err(format!("Free: double free of {addr:#x}"));
+            }
+            if self.debug {
+                return Err(format!("Free: {addr:#x} is not a heap block"));
+            }
+            // Freeing something that never came from MAlloc: ignore rather than corrupt the heap.
+            return Ok(());
+        };
+        if block.pinned {
+            return Ok(());
+        }
+        self.check_block(addr, &block)?;
+        self.blocks.remove(&addr);
+        self.live_bytes -= block.size;
+        self.write_header(block.span, block.size, HEAP_SIG_FREE, block.serial);
+
+        if !self.debug {
+            self.release(block.span, block.span_len);
+            return Ok(());
+        }
+        self.mem[addr..addr + block.size].fill(HEAP_POISON_FREED);
+        self.quarantine_bytes += block.span_len;
+        self.quarantine.push_back(block);
+        while self.quarantine_bytes > HEAP_QUARANTINE_BYTES {
+            let Some(old) = self.quarantine.pop_front() else {
+                break;
+            };
+            self.quarantine_bytes -= old.span_len;
+            self.release(old.span, old.span_len);
+        }
+        Ok(())
+    }
+
+    /// Returns a span to the free list, merging it with adjacent free spans.
+    fn release(&mut self, mut start: usize, mut len: usize) {
+        if let Some((&prev, &prev_len)) = self.free.range(..start).next_back()
+            && prev + prev_len == start
+        {
+            self.free.remove(&prev);
+            start = prev;
+            len += prev_len;
+        }
+        if let Some(next_len) = self.free.remove(&(start + len)) {
+            len += next_len;
+        }
+        if start + len == self.mem.len() {
+            self.mem.truncate(start);
+        } else {
+            self.free.insert(start, len);
+        }
+    }
+
+    fn size_of(&self, addr: usize) -> Option<usize> {
+        self.blocks.get(&addr).map(|b| b.size)
+    }
+
+    /// Validates `len` bytes at `addr`. In debug mode the range must lie inside one live block.
+    fn check_range(&self, addr: i64, len: usize) -> Result<usize, String> {
         if len == 0 {
             return Ok(addr.max(0) as usize);
         }
@@ -25,28 +238,229 @@ impl Vm {
         let end = start
             .checked_add(len)
             .ok_or_else(|| "pointer overflow".to_string())?;
-        if end > self.heap.len() {
+        if end > self.mem.len() {
             return Err("pointer out of range".to_string());
         }
-        Ok(start)
+        if !self.debug {
+            return Ok(start);
+        }
+
+        if let Some((&base, block)) = self.blocks.range(..=start).next_back() {
+            if end <= base + block.size {
+                return Ok(start);
+            }
+            if start < block.span + block.span_len {
+                return Err(format!(
+                    "heap buffer overflow: {len} byte access at offset {} of block #{} at {base:#x} ({} bytes)",
+                    start - base,
+                    block.serial,
+                    block.size
+                ));
+            }
+        }
+        if let Some((&base, block)) = self.blocks.range(start..).next()
+            && end > block.span
+        {
+            return Err(format!(
+                "heap buffer underrun: access {} bytes before block #{} at {base:#x}",
+                base - start,
+                block.serial
+            ));
+        }
+        if let Some(block) = self
+            .quarantine
+            .iter()
+            .find(|b| start >= b.span && start < b.span + b.span_len)
+        {
+            return Err(format!(
+                "use after free: access to block #{} ({} bytes) after Free",
+                block.serial, block.size
+            ));
+        }
+        Err(format!("access to unallocated heap memory at {start:#x}"))
+    }
+
+    /// Bytes from `addr` to the end of its block (debug mode) or of the heap.
+    fn tail_end(&self, start: usize) -> usize {
+        if !self.debug {
+            return self.mem.len();
+        }
+        self.blocks
+            .range(..=start)
+            .next_back()
+            .map(|(&base, b)| base + b.size)
+            .filter(|&end| end > start)
+            .unwrap_or(self.mem.len())
+    }
+
+    /// `len` bytes at `addr` to write to, checked like any other access.
+    pub(super) fn slice_mut(&mut self, addr: i64, len: usize) -> Result<&mut [u8], String> {
+        let start = self.check_range(addr, len)?;
+        Ok(&mut self.mem[start..start + len])
+    }
+
+    /// Two non-overlapping ranges to write to at once (a DC's pixels and its depth buffer).
+    pub(super) fn slice_pair_mut(
+        &mut self,
+        a: i64,
+        a_len: usize,
+        b: i64,
+        b_len: usize,
+    ) -> Result<(&mut [u8], &mut [u8]), String> {
+        let a_start = self.check_range(a, a_len)?;
+        let b_start = self.check_range(b, b_len)?;
+        if a_start < b_start + b_len && b_start < a_start + a_len {
+            return Err("overlapping heap ranges".to_string());
+        }
+        if a_start < b_start {
+            let (lo, hi) = self.mem.split_at_mut(b_start);
+            Ok((&mut lo[a_start..a_start + a_len], &mut hi[..b_len]))
+        } else {
+            let (lo, hi) = self.mem.split_at_mut(a_start);
+            Ok((&mut hi[..a_len], &mut lo[b_start..b_start + b_len]))
+        }
+    }
+
+    /// Lists blocks still allocated (pinned ones excluded); `None` when nothing leaked.
+    pub(super) fn leak_report(&self) -> Option<String> {
+        use std::fmt::Write as _;
+
+        let leaked: Vec<(&usize, &HeapBlock)> =
+            self.blocks.iter().filter(|(_, b)| !b.pinned).collect();
+        let corrupt: Vec<String> = self
+            .blocks
+            .iter()
+            .filter_map(|(&addr, b)| self.check_block(addr, b).err())
+            .collect();
+        if leaked.is_empty() && corrupt.is_empty() {
+            return None;
+        }
+
+        let total: usize = leaked.iter().map(|(_, b)| b.size).sum();
+        let mut out = format!(
+            "temple-hc: heap: {} leaked block{}, {total} bytes\n",
+            leaked.len(),
+            if leaked.len() == 1 { "" } else { "s" }
+        );
+        for (addr, b) in leaked.iter().take(HEAP_LEAK_REPORT_MAX) {
+            let shown = b.size.min(16);
+            let preview = String::from_utf8_lossy(&self.mem[**addr..**addr + shown])
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_graphic() || c == ' ' {
+                        c
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>();
+            let _ = writeln!(
+                out,
+                "  #{} {} bytes at {addr:#x} \"{preview}\"",
+                b.serial, b.size
+            );
+        }
+        if leaked.len() > HEAP_LEAK_REPORT_MAX {
+            let _ = writeln!(out, "  ... {} more", leaked.len() - HEAP_LEAK_REPORT_MAX);
+        }
+        for msg in corrupt {
+            let _ = writeln!(out, "temple-hc: {msg}");
+        }
+        Some(out)
+    }
+}
+
+impl Vm {
+    pub(super) fn heap_alloc(&mut self, size: usize, zeroed: bool) -> Result<i64, String> {
+        if size == 0 {
+            return Ok(0);
+        }
+        if let Some(max) = self.limits.max_heap_bytes
+            && self.heap.live_bytes.saturating_add(size) > max
+        {
+            return Err(format!(
+                "out of memory: allocating {size} bytes would exceed the {max} byte heap budget ({} in use)",
+                self.heap.live_bytes
+            ));
+        }
+        Ok(self.heap.alloc(size, zeroed, false) as i64)
+    }
+
+    /// A zeroed block owned by the VM (the window DC's matrix): never freed or reported.
+    pub(super) fn heap_alloc_pinned(&mut self, size: usize) -> i64 {
+        self.heap.alloc(size, true, true) as i64
+    }
+
+    /// `Free(ptr)`; `NULL` is ignored.
+    pub(super) fn heap_free(&mut self, addr: i64) -> Result<(), String> {
+        match usize::try_from(addr) {
+            Ok(0) => Ok(()),
+            Ok(addr) => self.heap.free(addr),
+            Err(_) => Err(format!("Free: bad pointer {addr}")),
+        }
+    }
+
+    /// `MSize(ptr)`: the requested size of a heap block, 0 for `NULL` or non-heap pointers.
+    pub(super) fn heap_msize(&self, addr: i64) -> usize {
+        usize::try_from(addr)
+            .ok()
+            .and_then(|a| self.heap.size_of(a))
+            .unwrap_or(0)
+    }
+
+    /// `ReAlloc(ptr, new_size)`: moves the block, keeping its first `min(old, new)` bytes.
+    pub(super) fn heap_realloc(&mut self, addr: i64, new_size: usize) -> Result<i64, String> {
+        if addr == 0 {
+            return self.heap_alloc(new_size, false);
+        }
+        if new_size == 0 {
+            self.heap_free(addr)?;
+            return Ok(0);
+        }
+        let Some(old_size) = usize::try_from(addr)
+            .ok()
+            .and_then(|a| self.heap.size_of(a))
+        else {
+            return Err(format!("ReAlloc: {addr:#x} is not a heap block"));
+        };
+        let keep = self.heap_slice(addr, old_size.min(new_size))?.to_vec();
+        let new_addr = self.heap_alloc(new_size, true)?;
+        self.heap_write_bytes(new_addr, &keep)?;
+        self.heap_free(addr)?;
+        Ok(new_addr)
+    }
+
+    /// `--heap-debug`: switches to a debug heap before the program runs. The only blocks by
+    /// then are the VM's pinned ones (the window DC's matrix), which are made again.
+    pub(crate) fn enable_heap_debug(&mut self) {
+        if self.heap.debug || self.heap.blocks.values().any(|b| !b.pinned) {
+            return;
+        }
+        self.heap = Heap::new(true);
+        let window_dc = Value::Obj(self.dc_alias.clone());
+        self.dc_init_3d(&window_dc, true);
+    }
+
+    pub(crate) fn heap_leak_report(&self) -> Option<String> {
+        if self.heap.debug {
+            self.heap.leak_report()
+        } else {
+            None
+        }
+    }
+
+    fn heap_check_range(&self, addr: i64, len: usize) -> Result<usize, String> {
+        self.heap.check_range(addr, len)
     }
 
     pub(super) fn heap_slice(&self, addr: i64, len: usize) -> Result<&[u8], String> {
         let start = self.heap_check_range(addr, len)?;
-        Ok(&self.heap[start..start + len])
+        Ok(&self.heap.mem[start..start + len])
     }
 
     pub(super) fn heap_tail(&self, addr: i64) -> Result<&[u8], String> {
-        if addr <= 0 {
-            return Err("null pointer".to_string());
-        }
-        let start: usize = addr
-            .try_into()
-            .map_err(|_| "negative pointer".to_string())?;
-        if start >= self.heap.len() {
-            return Err("pointer out of range".to_string());
-        }
-        Ok(&self.heap[start..])
+        let start = self.heap_check_range(addr, 1)?;
+        Ok(&self.heap.mem[start..self.heap.tail_end(start)])
     }
 
     pub(super) fn heap_write_bytes(&mut self, addr: i64, bytes: &[u8]) -> Result<(), String> {
@@ -54,7 +468,7 @@ impl Vm {
             return Ok(());
         }
         let start = self.heap_check_range(addr, bytes.len())?;
-        self.heap[start..start + bytes.len()].copy_from_slice(bytes);
+        self.heap.mem[start..start + bytes.len()].copy_from_slice(bytes);
         Ok(())
     }
 
@@ -64,7 +478,7 @@ impl Vm {
 
     pub(super) fn heap_write_u8(&mut self, addr: i64, value: u8) -> Result<(), String> {
         let start = self.heap_check_range(addr, 1)?;
-        self.heap[start] = value;
+        self.heap.mem[start] = value;
         Ok(())
     }
 
@@ -90,7 +504,7 @@ impl Vm {
         }
         let start = self.heap_check_range(addr, bytes)?;
         let v = (value as u64).to_le_bytes();
-        self.heap[start..start + bytes].copy_from_slice(&v[..bytes]);
+        self.heap.mem[start..start + bytes].copy_from_slice(&v[..bytes]);
         Ok(())
     }
 
@@ -173,20 +587,21 @@ impl Vm {
             }
         };
 
-        if bytes.is_empty() {
-            let addr = self.heap_alloc(1, true);
-            let _ = self.heap_write_u8(addr, 0);
-            self.doldoc_bin_ptr_cache.insert(key, addr);
-            self.doldoc_bin_len_by_ptr.insert(addr, 0);
-            return Ok((addr, 0));
-        }
+        // Cached for the life of the VM, so pin the block: `Free` on it is a no-op.
+        let addr = self.pin_doldoc_bin(&bytes)?;
+        self.doldoc_bin_ptr_cache.insert(key, addr);
+        Ok((addr, bytes.len()))
+    }
+
+    /// Copies a DolDoc bin into a pinned, NUL-terminated heap block that sprite functions can
+    /// find the length of.
+    pub(super) fn pin_doldoc_bin(&mut self, bytes: &[u8]) -> Result<i64, String> {
         let len = bytes.len();
-        let addr = self.heap_alloc(len + 1, true);
-        self.heap_write_bytes(addr, &bytes)?;
+        let addr = self.heap.alloc(len + 1, true, true) as i64;
+        self.heap_write_bytes(addr, bytes)?;
         let _ = self.heap_write_u8(addr + len as i64, 0);
-        self.doldoc_bin_ptr_cache.insert(key, addr);
         self.doldoc_bin_len_by_ptr.insert(addr, len);
-        Ok((addr, len))
+        Ok(addr)
     }
 
     pub(super) fn set_seed(&mut self, seed: u64) {
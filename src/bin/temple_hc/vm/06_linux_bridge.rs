@@ -6,6 +6,7 @@ impl Vm {
         self.capture = Some(String::new());
     }
 
+    #[cfg(test)]
     pub(crate) fn captured_output(&self) -> Option<&str> {
         self.capture.as_deref()
     }
@@ -215,7 +216,7 @@ impl Vm {
         format!("/{}", parts.join("/"))
     }
 
-    fn resolve_temple_spec_read(&self, target: &str) -> Result<String, String> {
+    pub(super) fn resolve_temple_spec_read(&self, target: &str) -> Result<String, String> {
         let target = target.trim();
 
         if target.starts_with("::/") {
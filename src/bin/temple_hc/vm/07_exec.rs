@@ -133,11 +133,31 @@ impl Vm {
         self.exec_stmts_with_goto(stmts)
     }
 
+    /// Counts one statement (or loop iteration) against the per-frame budget. Input is polled every
+    /// `POLL_EVERY_STEPS` statements so Ctrl+Alt+C can break out of loops that never yield.
+    fn count_step(&mut self) -> Result<(), String> {
+        const POLL_EVERY_STEPS: u64 = 4096;
+
+        self.steps_since_frame += 1;
+        if self.steps_since_frame.is_multiple_of(POLL_EVERY_STEPS) {
+            self.poll_events()?;
+        }
+        if let Some(max) = self.limits.max_steps_per_frame
+            && self.steps_since_frame > max
+        {
+            return Err(format!(
+                "step budget exceeded: {max} statements without yielding (call Refresh, Yield or Sleep)"
+            ));
+        }
+        Ok(())
+    }
+
     fn exec_stmt(&mut self, stmt: &Stmt) -> Result<ControlFlow, String> {
+        self.count_step()?;
         match stmt {
             Stmt::Empty => Ok(ControlFlow::Continue),
-            Stmt::Print { parts } => {
-                self.exec_print(parts)?;
+            Stmt::Print { parts, file } => {
+                self.exec_print(parts, file)?;
                 Ok(ControlFlow::Continue)
             }
             Stmt::Label(_) => Ok(ControlFlow::Continue),
@@ -197,6 +217,7 @@ impl Vm {
                 try_block,
                 catch_block,
             } => {
+                let call_depth = self.call_depth;
                 let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
                     self.exec_block(try_block)
                 }));
@@ -205,6 +226,7 @@ impl Vm {
                     Err(payload) => {
                         if let Some(p) = payload.downcast_ref::<VmPanic>() {
                             if matches!(p, VmPanic::Throw) {
+                                self.call_depth = call_depth;
                                 return self.exec_block(catch_block);
                             }
                         }
@@ -230,6 +252,7 @@ impl Vm {
             }
             Stmt::While { cond, body } => {
                 loop {
+                    self.count_step()?;
                     if !self.eval_expr(cond)?.truthy() {
                         break;
                     }
@@ -245,6 +268,7 @@ impl Vm {
             }
             Stmt::DoWhile { body, cond } => {
                 loop {
+                    self.count_step()?;
                     match self.exec_block(body)? {
                         ControlFlow::Continue | ControlFlow::LoopContinue => {}
                         ControlFlow::Break => break,
@@ -282,6 +306,7 @@ impl Vm {
                 }
 
                 loop {
+                    self.count_step()?;
                     if let Some(cond) = cond {
                         if !self.eval_expr(cond)?.truthy() {
                             break;
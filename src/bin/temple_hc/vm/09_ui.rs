@@ -130,7 +130,8 @@ impl Vm {
     }
 
     pub(super) fn poll_events(&mut self) -> Result<(), String> {
-        while let Some(ev) = self.rt.try_next_event() {
+        self.polls_this_frame += 1;
+        while let Some(ev) = self.next_input_event() {
             match ev {
                 Event::Key { code, down } => {
                     match code {
@@ -249,6 +250,9 @@ impl Vm {
                             self.menu_handle_left_click(x as i32, y as i32);
                         }
                         self.ctrl_handle_left_button(down, x, y)?;
+                        if down && self.ctrl_capture_left.is_none() {
+                            self.follow_text_link(x as i32, y as i32)?;
+                        }
                     } else if button == protocol::MOUSE_BUTTON_RIGHT {
                         self.msg_queue.push_back(TempleMsg {
                             code: if down { 9 } else { 10 }, // MSG_MS_R_DOWN / MSG_MS_R_UP
@@ -1113,10 +1117,14 @@ impl Vm {
     }
 
     pub(super) fn present_with_overlays(&mut self) -> Result<(), String> {
+        self.draw_blinking_text();
         self.maybe_call_draw_it()?;
         self.maybe_draw_ctrls()?;
         self.render_menu_overlay();
         self.maybe_draw_mouse_overlay()?;
+        self.steps_since_frame = 0;
+        self.frame += 1;
+        self.polls_this_frame = 0;
         match self.rt.present() {
             Ok(()) => Ok(()),
             Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => {
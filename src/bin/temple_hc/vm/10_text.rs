@@ -1,5 +1,79 @@
 use super::prelude::*;
-use super::{Value, Vm};
+use super::{TextBlink, TextLink, Value, Vm, doc};
+use std::collections::BTreeMap;
+use temple_rt::doldoc;
+
+/// A `$$...$$` color code `Print` understands; `None` restores the default.
+#[derive(Clone, Copy, Debug)]
+enum PrintCode {
+    Fg(Option<u8>),
+    Bg(Option<u8>),
+}
+
+impl PrintCode {
+    /// The DolDoc command it stands for.
+    fn source(self) -> String {
+        let (op, color) = match self {
+            PrintCode::Fg(c) => ("FG", c),
+            PrintCode::Bg(c) => ("BG", c),
+        };
+        match color {
+            Some(c) => format!("${op},{}$", doldoc::color_name(c)),
+            None => format!("${op}$"),
+        }
+    }
+}
+
+enum PrintSeg<'a> {
+    /// DolDoc source, shown as is (`$$...$$` sequences that aren't commands included).
+    Text(&'a str),
+    Code(PrintCode),
+    /// A DolDoc command written with doubled dollars, as TempleOS sources store them: the
+    /// `LK,"Home",A="FI:~/"` of `$$LK,"Home",A="FI:~/"$$`.
+    Cmd(&'a str),
+}
+
+/// Splits `Print` text at the `$$...$$` color codes and commands in it.
+fn print_segments(text: &str) -> Vec<PrintSeg<'_>> {
+    let mut out = Vec::new();
+    let mut start = 0usize;
+    let mut i = 0usize;
+    while let Some(open) = text[i..].find("$$").map(|p| i + p) {
+        let after_open = open + 2;
+        let Some(end) = text[after_open..].find("$$").map(|p| after_open + p) else {
+            break;
+        };
+        let inner = &text[after_open..end];
+        let seg = match Vm::parse_print_code(inner) {
+            Some(code) => Some(PrintSeg::Code(code)),
+            None if is_doldoc_cmd(inner) => Some(PrintSeg::Cmd(inner)),
+            None => None,
+        };
+        if let Some(seg) = seg {
+            if start < open {
+                out.push(PrintSeg::Text(&text[start..open]));
+            }
+            out.push(seg);
+            start = end + 2;
+        }
+        i = end + 2;
+    }
+    if start < text.len() {
+        out.push(PrintSeg::Text(&text[start..]));
+    }
+    out
+}
+
+/// `inner` is a DolDoc command this implementation knows (`FG,RED`, `LK,"x"`, `UL,1`).
+fn is_doldoc_cmd(inner: &str) -> bool {
+    if inner.trim().is_empty() || inner.contains(['$', '\n']) {
+        return false;
+    }
+    match doldoc::parse(&format!("${inner}$")).as_slice() {
+        [e] => !e.code.is_empty() && e.ty != doldoc::EntryType::Unknown,
+        _ => false,
+    }
+}
 
 impl Vm {
     fn newline(&mut self) {
@@ -12,6 +86,15 @@ impl Vm {
             return;
         }
 
+        self.text_links.retain_mut(|l| {
+            l.y -= 8;
+            l.y >= 0
+        });
+        self.text_blinks.retain_mut(|b| {
+            b.y -= 8;
+            b.y >= 0
+        });
+
         // Scroll the framebuffer up by one text row (8 pixels).
         let w = w as usize;
         let shift_rows = 8usize;
@@ -54,68 +137,209 @@ impl Vm {
         }
     }
 
-    fn print_str(&mut self, text: &str) {
-        // TempleOS frequently uses DolDoc-style inline markup in strings, especially for color:
-        //   "$$RED$$Hello$$FG$$"
-        //
-        // For compatibility and a more TempleOS-like look, interpret a small subset here.
-        // Unknown sequences are preserved literally.
-        let mut i = 0usize;
-        while i < text.len() {
-            if text[i..].starts_with("$$") {
-                let after_open = i + 2;
-                if after_open <= text.len() {
-                    if let Some(end_rel) = text[after_open..].find("$$") {
-                        let end = after_open + end_rel;
-                        let code = &text[after_open..end];
-                        if self.try_apply_doldoc_code(code) {
-                            i = end + 2;
-                            continue;
-                        }
-
-                        // Unknown code: emit literally.
-                        self.put_char('$');
-                        self.put_char('$');
-                        for ch in code.chars() {
-                            self.put_char(ch);
-                        }
-                        self.put_char('$');
-                        self.put_char('$');
-                        i = end + 2;
-                        continue;
-                    }
-                }
+    pub(super) fn print_str(&mut self, text: &str) {
+        self.print_str_bins(text, &BTreeMap::new());
+    }
+
+    /// Prints `text` as DolDoc, with `bins` for the sprites in it, and appends it to the put
+    /// document. TempleOS programs color their output with inline commands (`$FG,RED$`, or
+    /// `$$RED$$` as sources write them), link to documents and embed sprites.
+    fn print_str_bins(&mut self, text: &str, bins: &BTreeMap<u32, Vec<u8>>) {
+        let src = Self::print_doc_source(text);
+        self.draw_doc_text(&src, bins);
+        let put_doc = self.put_doc.clone();
+        let _ = self.doc_insert_source(&put_doc, &src, bins);
+    }
+
+    /// Converts `Print` text to DolDoc source, as `print_str` shows and records it.
+    pub(super) fn print_doc_source(text: &str) -> String {
+        print_segments(text)
+            .into_iter()
+            .map(|seg| match seg {
+                PrintSeg::Text(run) => run.replace('\0', ""),
+                PrintSeg::Code(code) => code.source(),
+                PrintSeg::Cmd(inner) => format!("${inner}$"),
+            })
+            .collect()
+    }
+
+    /// Lays DolDoc `src` out from the text cursor on and draws it like printed text: colors and
+    /// attributes carry over between calls, long lines wrap, the window scrolls, and links and
+    /// blinking cells are remembered for clicks and later frames.
+    fn draw_doc_text(&mut self, src: &str, bins: &BTreeMap<u32, Vec<u8>>) {
+        for ch in doldoc::plain_text(src).chars() {
+            match ch {
+                '\t' => (0..4).for_each(|_| self.capture_push(' ')),
+                ch => self.capture_push(ch),
+            }
+        }
+
+        let (w, h) = self.rt.size();
+        let cols = (w as usize / 8).max(1);
+        let start = (self.text_x.max(0) / doldoc::CELL_W) as usize;
+        let mut style = self.text_style;
+        style.fg = self.text_fg;
+        style.bg = self.text_bg;
+        let opts = doldoc::LayoutOptions::view(cols, 15, 0);
+        let mut l = doldoc::Layouter::new(opts, bins)
+            .with_style(style)
+            .at_col(start);
+        for e in doldoc::parse(src) {
+            l.push(&e);
+        }
+        let style = l.style();
+        let (end_line, end_col) = l.cursor();
+        let mut doc = l.finish();
+        // The first line continues one that is already on screen.
+        if let Some(first) = doc.lines.first_mut() {
+            first.drain(..start.min(first.len()));
+        }
+
+        let mut view = doldoc::Viewport {
+            x: 0,
+            y: 0,
+            cols,
+            rows: 1,
+            first_line: 0,
+        };
+        for i in 0..=end_line {
+            let skip = if i == 0 { start } else { 0 };
+            if i > 0 {
+                self.newline();
+            }
+            view.x = skip as i32 * doldoc::CELL_W;
+            view.y = self.text_y;
+            view.first_line = i;
+            doldoc::render_cells(&mut self.rt, &doc, &view);
+            self.remember_text_line(&doc, i, skip, self.text_y);
+        }
+        self.text_x = end_col as i32 * doldoc::CELL_W;
+        self.text_style = style;
+        self.text_fg = style.fg;
+        self.text_bg = style.bg;
+
+        if !doc.sprites.is_empty() {
+            // Earlier lines may have scrolled up by now; sprites go where their lines ended up.
+            view.x = 0;
+            view.y = self.text_y - end_line as i32 * doldoc::CELL_H;
+            view.first_line = 0;
+            view.rows = ((h as i32 - view.y).max(0) / doldoc::CELL_H) as usize;
+            doldoc::render_sprites(&mut self.rt, &doc.sprites, bins, &view);
+        }
+    }
+
+    /// Remembers the links and blinking cells of laid-out line `line`, drawn at pixel row `y`
+    /// with its first `skip` cells (already on screen) left out.
+    pub(super) fn remember_text_line(
+        &mut self,
+        doc: &doldoc::DocLayout,
+        line: usize,
+        skip: usize,
+        y: i32,
+    ) {
+        for link in doc.links.iter().filter(|l| l.line == line) {
+            self.text_links.push(TextLink {
+                x0: link.col_start as i32 * doldoc::CELL_W,
+                x1: link.col_end as i32 * doldoc::CELL_W,
+                y,
+                target: link.target.clone(),
+            });
+        }
+        let Some(cells) = doc.lines.get(line) else {
+            return;
+        };
+        for (col, cell) in cells.iter().enumerate().filter(|(_, c)| c.blink) {
+            self.text_blinks.push(TextBlink {
+                x: (skip + col) as i32 * doldoc::CELL_W,
+                y,
+                cell: cell.clone(),
+            });
+        }
+    }
+
+    /// Forgets the links and blinking cells of printed text, as when the window is cleared.
+    pub(super) fn forget_text_marks(&mut self) {
+        self.text_links.clear();
+        self.text_blinks.clear();
+    }
+
+    /// Flips blinking cells of printed text between their colors and the inverse, twice a
+    /// second.
+    pub(super) fn draw_blinking_text(&mut self) {
+        if self.text_blinks.is_empty() {
+            return;
+        }
+        let t = self
+            .fixed_ts
+            .unwrap_or_else(|| self.start_instant.elapsed().as_secs_f64());
+        let on = (t * 2.0) as i64 % 2 == 0;
+        for b in &self.text_blinks {
+            let (fg, bg) = if on {
+                (b.cell.fg, b.cell.bg)
+            } else {
+                (b.cell.bg, b.cell.fg)
+            };
+            let ch = temple_rt::assets::decode_cp437_byte(b.cell.ch);
+            self.rt.draw_char_8x8(b.x, b.y, fg, bg, ch);
+            if b.cell.underline {
+                self.rt
+                    .fill_rect(b.x, b.y + doldoc::CELL_H - 1, doldoc::CELL_W, 1, fg);
             }
+        }
+    }
 
-            let ch = text[i..].chars().next().unwrap_or('\0');
-            self.put_char(ch);
-            i = i.saturating_add(ch.len_utf8().max(1));
+    /// Follows the link in printed text under (`x`, `y`), if any: documents open in the shell's
+    /// viewer, macros run in this task and web links in the browser.
+    pub(super) fn follow_text_link(&mut self, x: i32, y: i32) -> Result<bool, String> {
+        let Some(link) = self
+            .text_links
+            .iter()
+            .rev()
+            .find(|l| (l.y..l.y + doldoc::CELL_H).contains(&y) && (l.x0..l.x1).contains(&x))
+            .cloned()
+        else {
+            return Ok(false);
+        };
+        match link.target {
+            doldoc::LinkTarget::Doc(spec) => {
+                if let Err(err) = self.shell_request(&format!("doc {spec}")) {
+                    self.set_last_host_error(err);
+                }
+            }
+            doldoc::LinkTarget::Macro(code) => self.exec_snippet(Arc::from("<link>"), &code)?,
+            doldoc::LinkTarget::Url(url) => {
+                self.clear_last_host_error();
+                if let Err(err) = std::process::Command::new("xdg-open").arg(&url).spawn() {
+                    self.set_last_host_error(format!("xdg-open: {err}"));
+                }
+            }
+            doldoc::LinkTarget::Song(_)
+            | doldoc::LinkTarget::Form(_)
+            | doldoc::LinkTarget::Tree(_) => return Ok(false),
         }
+        Ok(true)
     }
 
-    fn try_apply_doldoc_code(&mut self, code: &str) -> bool {
+    fn parse_print_code(code: &str) -> Option<PrintCode> {
         let code = code.trim();
         if code.is_empty() {
-            return false;
+            return None;
         }
 
         let upper = code.to_ascii_uppercase();
 
         // Reset to default foreground/background.
         if upper == "FG" {
-            self.text_fg = 15;
-            return true;
+            return Some(PrintCode::Fg(None));
         }
         if upper == "BG" {
-            self.text_bg = 0;
-            return true;
+            return Some(PrintCode::Bg(None));
         }
 
         // Background set: $$BK,<idx>$$
         if let Some(rest) = upper.strip_prefix("BK,") {
             if let Ok(v) = rest.trim().parse::<i64>() {
-                self.text_bg = v.clamp(0, 15) as u8;
-                return true;
+                return Some(PrintCode::Bg(Some(v.clamp(0, 15) as u8)));
             }
         }
 
@@ -124,25 +348,14 @@ impl Vm {
             if let Ok(v) = rest.trim().parse::<i64>() {
                 let idx = v.clamp(0, 15) as u8;
                 match name.trim() {
-                    "FG" => {
-                        self.text_fg = idx;
-                        return true;
-                    }
-                    "BG" => {
-                        self.text_bg = idx;
-                        return true;
-                    }
+                    "FG" => return Some(PrintCode::Fg(Some(idx))),
+                    "BG" => return Some(PrintCode::Bg(Some(idx))),
                     _ => {}
                 }
             }
         }
 
-        if let Some(idx) = Self::doldoc_color_name_to_idx(&upper) {
-            self.text_fg = idx;
-            return true;
-        }
-
-        false
+        Self::doldoc_color_name_to_idx(&upper).map(|idx| PrintCode::Fg(Some(idx)))
     }
 
     fn doldoc_color_name_to_idx(name: &str) -> Option<u8> {
@@ -168,16 +381,19 @@ impl Vm {
     }
 
     fn print_putchars(&mut self, v: u64) {
+        let mut run = String::new();
         for i in 0..8usize {
             let b = ((v >> (i * 8)) & 0xff) as u8;
             if b == 0 {
                 break;
             }
             self.put_char(b as char);
+            run.push(b as char);
         }
+        doc::doc_put_text(&self.put_doc.clone(), &run);
     }
 
-    pub(super) fn exec_print(&mut self, parts: &[Expr]) -> Result<(), String> {
+    pub(super) fn exec_print(&mut self, parts: &[Expr], file: &Arc<str>) -> Result<(), String> {
         let mut values = Vec::with_capacity(parts.len());
         for expr in parts {
             values.push(self.eval_expr(expr)?);
@@ -187,8 +403,23 @@ impl Vm {
             return Ok(());
         }
 
+        // Only text that shows a sprite needs the file's bins.
+        let bins_for = |vm: &Self, text: &str| {
+            if text.contains("BI=") {
+                vm.program
+                    .bins_by_file
+                    .get(file)
+                    .cloned()
+                    .unwrap_or_default()
+            } else {
+                BTreeMap::new()
+            }
+        };
         match (values.first().cloned().unwrap(), values.len()) {
-            (Value::Str(s), 1) => self.print_str(&s),
+            (Value::Str(s), 1) => {
+                let bins = bins_for(self, &s);
+                self.print_str_bins(&s, &bins);
+            }
             (Value::Char(v), 1) => self.print_putchars(v),
             (Value::Int(v), 1) => self.print_str(&v.to_string()),
             (Value::Float(v), 1) => self.print_str(&v.to_string()),
@@ -199,7 +430,8 @@ impl Vm {
                     |ptr| self.read_cstr_lossy(ptr),
                     |idx, name| self.define_sub(idx, name),
                 )?;
-                self.print_str(&rendered);
+                let bins = bins_for(self, &rendered);
+                self.print_str_bins(&rendered, &bins);
             }
             (other, _) => {
                 return Err(format!(
@@ -10,6 +10,8 @@ impl Vm {
                 | "CAlloc"
                 | "ACAlloc"
                 | "Free"
+                | "MSize"
+                | "ReAlloc"
                 | "FileRead"
                 | "FileWrite"
                 | "StrLen"
@@ -67,6 +69,8 @@ impl Vm {
                 | "GrPlot"
                 | "GrLine"
                 | "GrLine3"
+                | "GrPlot3"
+                | "GrFillTri0"
                 | "GrBorder"
                 | "GrRect"
                 | "GrCircle"
@@ -74,22 +78,65 @@ impl Vm {
                 | "GrEllipse"
                 | "GrFloodFill"
                 | "GrPrint"
+                | "GrPrintScaleSet"
+                | "GrFontLoad"
                 | "GrPaletteColorSet"
+                | "GrBlot"
+                | "GrPeek"
                 | "Sprite3"
                 | "Sprite3YB"
                 | "SpriteInterpolate"
                 | "DCDepthBufAlloc"
+                | "DCDepthBufRst"
                 | "D3I32Norm"
+                | "Mat4x4IdentNew"
+                | "Mat4x4IdentEqu"
+                | "Mat4x4RotX"
+                | "Mat4x4RotY"
+                | "Mat4x4RotZ"
+                | "Mat4x4Scale"
+                | "Mat4x4TranslationEqu"
+                | "Mat4x4TranslationAdd"
+                | "Mat4x4MulMat4x4Equ"
+                | "Mat4x4MulXYZ"
                 | "Noise"
                 | "QSortI64"
                 | "DCFill"
                 | "DCAlias"
                 | "DCSymmetrySet"
                 | "DCDel"
+                | "DCNew"
+                | "DCClear"
+                | "DCExtract"
+                | "DCCopy"
+                | "GRRead"
+                | "GRWrite"
+                | "BMPRead"
+                | "DocNew"
+                | "DocDel"
+                | "DocPut"
+                | "DocDisplay"
                 | "DocClear"
+                | "DocTop"
                 | "DocCursor"
                 | "DocBottom"
                 | "DocScroll"
+                | "DocPrint"
+                | "Print"
+                | "DocLoad"
+                | "DocSave"
+                | "DocRead"
+                | "DocWrite"
+                | "DocInsDoc"
+                | "DocEntryDel"
+                | "DocRecalc"
+                | "DocForm"
+                | "Dir"
+                | "View"
+                | "Ed"
+                | "Type"
+                | "KeyMap"
+                | "PopUpForm"
                 | "Cd"
                 | "DefineLstLoad"
                 | "DefineSub"
@@ -143,14 +190,24 @@ impl Vm {
                 ));
             }
 
+            if let Some(max) = self.limits.max_call_depth
+                && self.call_depth >= max
+            {
+                return Err(format!(
+                    "call depth limit exceeded: {max} nested calls (runaway recursion?)"
+                ));
+            }
+
+            self.call_depth += 1;
             let flow = {
                 let _scope = EnvScopeGuard::new(&mut self.env);
                 for (param, value) in func.params.iter().cloned().zip(values) {
                     self.env.define(param, value);
                 }
                 self.exec_block_unscoped(&func.body)
-            }
-            .map_err(|err| format!("{err}\nwhile calling {name}()"))?;
+            };
+            self.call_depth -= 1;
+            let flow = flow.map_err(|err| format!("{err}\nwhile calling {name}()"))?;
 
             match flow {
                 ControlFlow::Continue => Ok(Value::Void),
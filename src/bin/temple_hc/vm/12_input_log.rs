@@ -0,0 +1,149 @@
+use std::io::Write as _;
+
+use super::Vm;
+use super::prelude::*;
+
+/// `--record`/`--replay` log format: a header, then one event per line tagged with the frame
+/// (number of presents so far) and the `poll_events` call within that frame it arrived in:
+///
+/// ```text
+/// # temple-hc input log v1
+/// seed 1234
+/// fixed_ts 12.5
+/// 17 1 key 97 1
+/// 17 3 move 320 200
+/// ```
+const INPUT_LOG_MAGIC: &str = "# temple-hc input log v1";
+
+/// Position of an event in the program's run: (frame, poll within frame).
+pub(super) type InputStamp = (u64, u32);
+
+fn format_event(stamp: InputStamp, ev: &Event) -> String {
+    let (frame, poll) = stamp;
+    let ev = match *ev {
+        Event::Key { code, down } => format!("key {code} {}", down as u8),
+        Event::MouseMove { x, y } => format!("move {x} {y}"),
+        Event::MouseButton { button, down } => format!("button {button} {}", down as u8),
+        Event::MouseWheel { dx, dy } => format!("wheel {dx} {dy}"),
+        Event::MouseEnter => "enter".to_string(),
+        Event::MouseLeave => "leave".to_string(),
+    };
+    format!("{frame} {poll} {ev}")
+}
+
+fn parse_event(line: &str) -> Option<(InputStamp, Event)> {
+    let mut it = line.split_ascii_whitespace();
+    let frame = it.next()?.parse::<u64>().ok()?;
+    let poll = it.next()?.parse::<u32>().ok()?;
+    let kind = it.next()?;
+    let mut num = || it.next().and_then(|v| v.parse::<i64>().ok());
+    let ev = match kind {
+        "key" => Event::Key {
+            code: num()? as u32,
+            down: num()? != 0,
+        },
+        "move" => Event::MouseMove {
+            x: num()? as u32,
+            y: num()? as u32,
+        },
+        "button" => Event::MouseButton {
+            button: num()? as u32,
+            down: num()? != 0,
+        },
+        "wheel" => Event::MouseWheel {
+            dx: num()? as i32,
+            dy: num()? as i32,
+        },
+        "enter" => Event::MouseEnter,
+        "leave" => Event::MouseLeave,
+        _ => return None,
+    };
+    Some(((frame, poll), ev))
+}
+
+impl Vm {
+    /// `--record <file>`: logs every input event the program sees. An unseeded RNG gets a fixed
+    /// seed and `tS` is pinned (as by `TEMPLE_HC_FIXED_TS`), so the replay draws the same random
+    /// numbers and reads the same clock.
+    pub(crate) fn start_recording(&mut self, path: &Path) -> io::Result<()> {
+        if self.rng_seed == 0 {
+            self.set_seed(Self::now_nanos() | 1);
+        }
+        let ts = *self
+            .fixed_ts
+            .get_or_insert_with(|| self.start_instant.elapsed().as_secs_f64());
+        let mut out = io::LineWriter::new(std::fs::File::create(path)?);
+        writeln!(out, "{INPUT_LOG_MAGIC}")?;
+        writeln!(out, "seed {}", self.rng_seed)?;
+        writeln!(out, "fixed_ts {ts}")?;
+        self.input_recorder = Some(out);
+        Ok(())
+    }
+
+    /// `--replay <file>`: feeds a recorded session back in lockstep with presents instead of live
+    /// input. Live input resumes once the log runs out.
+    pub(crate) fn start_replay(&mut self, path: &Path) -> io::Result<()> {
+        let text = std::fs::read_to_string(path)?;
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, l)| l.trim()) != Some(INPUT_LOG_MAGIC) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a temple-hc input log",
+            ));
+        }
+
+        let mut events = VecDeque::new();
+        for (i, line) in lines {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(seed) = line.strip_prefix("seed ") {
+                if let Ok(seed) = seed.trim().parse::<u64>() {
+                    self.set_seed(seed);
+                }
+                continue;
+            }
+            if let Some(ts) = line.strip_prefix("fixed_ts ") {
+                self.fixed_ts = ts.trim().parse::<f64>().ok();
+                continue;
+            }
+            let Some(ev) = parse_event(line) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: bad event: {line}", i + 1),
+                ));
+            };
+            events.push_back(ev);
+        }
+        self.input_replay = Some(events);
+        Ok(())
+    }
+
+    /// The next input event for `poll_events`: from the replay log while one is active (live
+    /// input is drained and dropped meanwhile), otherwise from the shell.
+    pub(super) fn next_input_event(&mut self) -> Option<Event> {
+        let stamp = (self.frame, self.polls_this_frame);
+        let ev = match self.input_replay.as_mut() {
+            Some(replay) => {
+                while self.rt.try_next_event().is_some() {}
+                match replay.front() {
+                    Some(&(at, ev)) if at <= stamp => {
+                        replay.pop_front();
+                        ev
+                    }
+                    Some(_) => return None,
+                    None => {
+                        self.input_replay = None;
+                        return None;
+                    }
+                }
+            }
+            None => self.rt.try_next_event()?,
+        };
+        if let Some(out) = self.input_recorder.as_mut() {
+            let _ = writeln!(out, "{}", format_event(stamp, &ev));
+        }
+        Some(ev)
+    }
+}
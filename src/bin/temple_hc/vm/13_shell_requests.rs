@@ -0,0 +1,72 @@
+use super::Vm;
+use super::prelude::*;
+
+impl Vm {
+    /// `--macro`: the program is a DolDoc `$MA$`/`$LK$` action run on the shell's behalf. Its
+    /// printed text goes back to the shell when it finishes.
+    pub(crate) fn enable_macro_mode(&mut self) {
+        self.macro_mode = true;
+        self.enable_capture();
+    }
+
+    /// Reports a macro's printed text, or the error that stopped it, back to the shell.
+    pub(crate) fn finish_macro(&mut self, res: io::Result<()>) -> io::Result<()> {
+        if !self.macro_mode {
+            return res;
+        }
+        let text = self.capture.take().unwrap_or_default();
+        let text = text.trim_end();
+        match &res {
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+                let first = err.to_string();
+                let first = first.lines().next().unwrap_or_default();
+                self.rt.shell_request(&format!("error {first}"))?;
+            }
+            _ if !text.is_empty() => self.rt.shell_request(&format!("print {text}"))?,
+            _ => {}
+        }
+        res
+    }
+
+    /// Called before blocking on input. The shell keeps a macro session hidden until it draws,
+    /// and one that only waits for a key would otherwise sit there unseen and unkillable.
+    pub(super) fn macro_input_wait(&mut self) -> Result<(), String> {
+        if !self.macro_mode || self.macro_window_shown {
+            return Ok(());
+        }
+        self.macro_window_shown = true;
+        self.shell_request("show")
+    }
+
+    /// Macros that `Cd` move the shell along (TempleOS runs them in the user's own task).
+    pub(super) fn notify_shell_cd(&mut self) -> Result<(), String> {
+        if !self.macro_mode {
+            return Ok(());
+        }
+        let spec = self.shell_spec(&self.cwd.clone())?;
+        self.shell_request(&format!("cd {spec}"))
+    }
+
+    pub(super) fn shell_request(&mut self, req: &str) -> Result<(), String> {
+        self.rt
+            .shell_request(req)
+            .map_err(|err| format!("shell request: {err}"))
+    }
+
+    /// The spec the shell uses for a HolyC path: files that only exist in the vendored TempleOS
+    /// tree are named `::/...`, everything else by its path under the Temple root.
+    pub(super) fn shell_spec(&self, target: &str) -> Result<String, String> {
+        let spec = self.resolve_temple_spec_read(target)?;
+        if spec.starts_with("::/") {
+            return Ok(spec);
+        }
+        let rel = spec.trim_start_matches('/');
+        let in_root = std::env::var_os("TEMPLE_ROOT")
+            .is_some_and(|root| PathBuf::from(root).join(rel).exists());
+        let in_tree = discover_templeos_root().is_some_and(|tree| tree.join(rel).exists());
+        if !in_root && in_tree {
+            return Ok(format!("::/{rel}"));
+        }
+        Ok(spec)
+    }
+}
@@ -0,0 +1,367 @@
+use super::prelude::*;
+use super::{Obj, ObjRef, Value, Vm};
+use std::collections::BTreeMap;
+use temple_rt::doldoc::{self, EntryType};
+
+// Entry fields HolyC can't name: the command as written, and its tag at the time, so a tag
+// changed through `doc_e->tag` can be written back into it.
+const SRC_FIELD: &str = "$src";
+const SRC_TAG_FIELD: &str = "$tag";
+
+const DOCT_TEXT: i64 = 0;
+const DOCT_NEW_LINE: i64 = 1;
+
+fn field(obj: &ObjRef, name: &str) -> Option<Value> {
+    obj.borrow().fields.get(name).cloned()
+}
+
+fn set(obj: &ObjRef, name: &str, value: Value) {
+    obj.borrow_mut().fields.insert(name.to_string(), value);
+}
+
+fn link(obj: &ObjRef, name: &str) -> Option<ObjRef> {
+    match field(obj, name) {
+        Some(Value::Obj(o)) => Some(o),
+        _ => None,
+    }
+}
+
+fn int_field(obj: &ObjRef, name: &str) -> i64 {
+    field(obj, name).and_then(|v| v.as_i64().ok()).unwrap_or(0)
+}
+
+fn str_field(obj: &ObjRef, name: &str) -> Option<String> {
+    match field(obj, name) {
+        Some(Value::Str(s)) => Some(s),
+        _ => None,
+    }
+}
+
+/// A fresh `CDoc`: the document is its own list head (`doc->head.next` is the first entry,
+/// `doc_e != doc` ends a walk), with the cursor at the end.
+pub(super) fn new_doc(filename: &str) -> ObjRef {
+    let name = Rc::new(RefCell::new(Obj {
+        fields: HashMap::from([("name".to_string(), Value::Str(filename.to_string()))]),
+    }));
+    let doc = Rc::new(RefCell::new(Obj {
+        fields: HashMap::from([
+            ("flags".to_string(), Value::Int(0)),
+            ("cur_col".to_string(), Value::Int(0)),
+            ("filename".to_string(), Value::Obj(name)),
+        ]),
+    }));
+    for name in ["head", "next", "last", "cur_entry"] {
+        set(&doc, name, Value::Obj(doc.clone()));
+    }
+    doc
+}
+
+/// Entries of `doc` in order.
+pub(super) fn doc_entries(doc: &ObjRef) -> Vec<ObjRef> {
+    let mut out = Vec::new();
+    let mut cur = link(doc, "next");
+    while let Some(e) = cur {
+        if Rc::ptr_eq(&e, doc) {
+            break;
+        }
+        cur = link(&e, "next");
+        out.push(e);
+    }
+    out
+}
+
+/// Unlinks every entry and puts the cursor back at the (now empty) end.
+pub(super) fn doc_clear(doc: &ObjRef) {
+    for name in ["next", "last", "cur_entry"] {
+        set(doc, name, Value::Obj(doc.clone()));
+    }
+    set(doc, "cur_col", Value::Int(0));
+}
+
+/// Removes `e` from its document, moving the cursor off it.
+pub(super) fn doc_entry_del(doc: &ObjRef, e: &ObjRef) {
+    let (Some(prev), Some(next)) = (link(e, "last"), link(e, "next")) else {
+        return;
+    };
+    set(&prev, "next", Value::Obj(next.clone()));
+    set(&next, "last", Value::Obj(prev));
+    if link(doc, "cur_entry").is_some_and(|cur| Rc::ptr_eq(&cur, e)) {
+        set(doc, "cur_entry", Value::Obj(next));
+        set(doc, "cur_col", Value::Int(0));
+    }
+}
+
+/// The entry new text goes in front of: `doc->cur_entry`, or the end if it is unset.
+fn cursor(doc: &ObjRef) -> ObjRef {
+    link(doc, "cur_entry").unwrap_or_else(|| doc.clone())
+}
+
+fn is_doc(doc: &ObjRef, e: &ObjRef) -> bool {
+    Rc::ptr_eq(doc, e)
+}
+
+/// Links `e` in front of `at`, placing it after its predecessor.
+fn insert_before(doc: &ObjRef, at: &ObjRef, e: ObjRef) {
+    let prev = link(at, "last").unwrap_or_else(|| doc.clone());
+    let (x, y) = if is_doc(doc, &prev) {
+        (0, 0)
+    } else if int_field(&prev, "type_u8") == DOCT_NEW_LINE {
+        (0, int_field(&prev, "y") + 1)
+    } else {
+        (
+            int_field(&prev, "x") + entry_width(&prev),
+            int_field(&prev, "y"),
+        )
+    };
+    set(&e, "x", Value::Int(x));
+    set(&e, "y", Value::Int(y));
+    set(&e, "last", Value::Obj(prev.clone()));
+    set(&e, "next", Value::Obj(at.clone()));
+    set(&prev, "next", Value::Obj(e.clone()));
+    set(at, "last", Value::Obj(e));
+}
+
+/// Columns an entry takes in its source line: the text of a text run, nothing for commands.
+fn entry_width(e: &ObjRef) -> i64 {
+    if int_field(e, "type_u8") != DOCT_TEXT {
+        return 0;
+    }
+    str_field(e, "tag").map_or(0, |t| t.chars().count() as i64)
+}
+
+fn text_entry(text: &str) -> ObjRef {
+    Rc::new(RefCell::new(Obj {
+        fields: HashMap::from([
+            ("type_u8".to_string(), Value::Int(DOCT_TEXT)),
+            ("type".to_string(), Value::Int(DOCT_TEXT)),
+            ("de_flags".to_string(), Value::Int(0)),
+            ("tag".to_string(), Value::Str(text.to_string())),
+        ]),
+    }))
+}
+
+/// Appends plain text at the cursor, growing the text run in front of it rather than adding an
+/// entry per `Print`.
+pub(super) fn doc_put_text(doc: &ObjRef, text: &str) {
+    let at = cursor(doc);
+    for (i, chunk) in text.split('\n').enumerate() {
+        if i > 0 {
+            let nl = text_entry("");
+            set(&nl, "type_u8", Value::Int(DOCT_NEW_LINE));
+            set(&nl, "type", Value::Int(DOCT_NEW_LINE));
+            set(&nl, "tag", Value::Int(0));
+            insert_before(doc, &at, nl);
+        }
+        if chunk.is_empty() {
+            continue;
+        }
+        let prev = link(&at, "last").unwrap_or_else(|| doc.clone());
+        if !is_doc(doc, &prev)
+            && int_field(&prev, "type_u8") == DOCT_TEXT
+            && field(&prev, SRC_FIELD).is_none()
+        {
+            let mut tag = str_field(&prev, "tag").unwrap_or_default();
+            tag.push_str(chunk);
+            set(&prev, "tag", Value::Str(tag));
+        } else {
+            insert_before(doc, &at, text_entry(chunk));
+        }
+    }
+}
+
+/// One entry's DolDoc source.
+fn entry_source(e: &ObjRef) -> String {
+    let tag = str_field(e, "tag");
+    if let Some(src) = str_field(e, SRC_FIELD) {
+        return match (str_field(e, SRC_TAG_FIELD), tag) {
+            (Some(old), Some(new)) if old != new => {
+                src.replacen(&doldoc::quote_arg(&old), &doldoc::quote_arg(&new), 1)
+            }
+            _ => src,
+        };
+    }
+    match int_field(e, "type_u8") {
+        DOCT_NEW_LINE => "\n".to_string(),
+        _ => doldoc::escape_text(&tag.unwrap_or_default()),
+    }
+}
+
+/// Renumbers `doc_e->x`/`y` (column and line in the source) after edits in the middle.
+pub(super) fn doc_recalc(doc: &ObjRef) {
+    let (mut x, mut y) = (0, 0);
+    for e in doc_entries(doc) {
+        set(&e, "x", Value::Int(x));
+        set(&e, "y", Value::Int(y));
+        if int_field(&e, "type_u8") == DOCT_NEW_LINE {
+            x = 0;
+            y += 1;
+        } else {
+            x += entry_width(&e);
+        }
+    }
+}
+
+/// Points a command's `BI=` at bin `num`.
+fn renumber_bin(src: &str, num: u32) -> String {
+    let upper = src.to_ascii_uppercase();
+    let Some(at) = upper.find("BI=") else {
+        return src.to_string();
+    };
+    let digits = src[at + 3..]
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(src.len(), |n| at + 3 + n);
+    format!("{}BI={num}{}", &src[..at], &src[digits..])
+}
+
+impl Vm {
+    /// `Fs->put_doc`, the document `Print` output goes to.
+    pub(super) fn is_put_doc(&self, doc: &ObjRef) -> bool {
+        Rc::ptr_eq(doc, &self.put_doc)
+    }
+
+    /// Parses DolDoc source and links its entries in at the cursor. Sprites get their bins
+    /// copied to the heap (`doc_e->bin_data->data`), renumbered after the ones already in `doc`.
+    pub(super) fn doc_insert_source(
+        &mut self,
+        doc: &ObjRef,
+        text: &str,
+        bins: &BTreeMap<u32, Vec<u8>>,
+    ) -> Result<(), String> {
+        let at = cursor(doc);
+        let mut next_bin = doc_entries(doc)
+            .iter()
+            .map(|e| int_field(e, "bin_num"))
+            .max()
+            .unwrap_or(0)
+            + 1;
+        let mut pending = String::new();
+        for e in doldoc::parse(text) {
+            match e.ty {
+                EntryType::Text if e.code.is_empty() => {
+                    pending.push_str(e.tag.as_deref().unwrap_or_default());
+                    continue;
+                }
+                EntryType::NewLine => {
+                    pending.push('\n');
+                    continue;
+                }
+                _ => {}
+            }
+            if !pending.is_empty() {
+                doc_put_text(doc, &std::mem::take(&mut pending));
+            }
+
+            let mut src = text.get(e.range.clone()).unwrap_or_default().to_string();
+            let obj = Rc::new(RefCell::new(Obj {
+                fields: HashMap::from([
+                    ("type_u8".to_string(), Value::Int(e.ty.doct())),
+                    ("type".to_string(), Value::Int(e.ty.doct())),
+                    ("de_flags".to_string(), Value::Int(0)),
+                    (
+                        "tag".to_string(),
+                        e.tag.clone().map_or(Value::Int(0), Value::Str),
+                    ),
+                    (
+                        "aux_str".to_string(),
+                        e.attr_str("A").map_or(Value::Int(0), Value::Str),
+                    ),
+                    (
+                        "left_macro".to_string(),
+                        e.attr_str("LM").map_or(Value::Int(0), Value::Str),
+                    ),
+                    (
+                        "right_macro".to_string(),
+                        e.attr_str("RM").map_or(Value::Int(0), Value::Str),
+                    ),
+                    ("bin_num".to_string(), Value::Int(0)),
+                    ("bin_data".to_string(), Value::Int(0)),
+                ]),
+            }));
+            if let Some(data) = e.bin().and_then(|n| bins.get(&n)) {
+                let num = next_bin;
+                next_bin += 1;
+                src = renumber_bin(&src, num as u32);
+                let addr = self.pin_doldoc_bin(data)?;
+                let bin = Obj {
+                    fields: HashMap::from([
+                        ("num".to_string(), Value::Int(num)),
+                        ("size".to_string(), Value::Int(data.len() as i64)),
+                        (
+                            "data".to_string(),
+                            Value::Ptr {
+                                addr,
+                                elem_bytes: 1,
+                            },
+                        ),
+                    ]),
+                };
+                set(&obj, "bin_num", Value::Int(num));
+                set(&obj, "bin_data", Value::Obj(Rc::new(RefCell::new(bin))));
+            }
+            set(&obj, SRC_FIELD, Value::Str(src));
+            if let Some(tag) = e.tag {
+                set(&obj, SRC_TAG_FIELD, Value::Str(tag));
+            }
+            insert_before(doc, &at, obj);
+        }
+        if !pending.is_empty() {
+            doc_put_text(doc, &pending);
+        }
+        Ok(())
+    }
+
+    /// The document as DolDoc source plus the bins its sprites use, ready for
+    /// [`doldoc::encode_doc_blob`].
+    pub(super) fn doc_source(
+        &self,
+        doc: &ObjRef,
+    ) -> Result<(String, BTreeMap<u32, Vec<u8>>), String> {
+        let mut text = String::new();
+        let mut bins = BTreeMap::new();
+        for e in doc_entries(doc) {
+            text.push_str(&entry_source(&e));
+            if let Some(Value::Obj(bin)) = field(&e, "bin_data") {
+                let num = int_field(&bin, "num");
+                let size = int_field(&bin, "size").max(0) as usize;
+                let addr = int_field(&bin, "data");
+                if let Ok(num) = u32::try_from(num) {
+                    bins.insert(num, self.heap_slice(addr, size)?.to_vec());
+                }
+            }
+        }
+        Ok((text, bins))
+    }
+
+    /// Redraws the window from the put document: its last screenful, laid out with the shared
+    /// DolDoc engine, leaving the text cursor where the document ends.
+    pub(super) fn redraw_put_doc(&mut self) -> Result<(), String> {
+        let put_doc = self.put_doc.clone();
+        let (text, bins) = self.doc_source(&put_doc)?;
+        let (w, h) = self.rt.size();
+        let cols = (w as usize / 8).max(1);
+        let rows = (h as usize / 8).max(1);
+        let opts = doldoc::LayoutOptions::view(cols, 15, 0);
+        let doc = doldoc::layout(&text, &bins, opts);
+        let first_line = doc.lines.len().saturating_sub(rows);
+        let view = doldoc::Viewport {
+            x: 0,
+            y: 0,
+            cols,
+            rows,
+            first_line,
+        };
+        self.rt.clear(0);
+        doldoc::render_cells(&mut self.rt, &doc, &view);
+        doldoc::render_sprites(&mut self.rt, &doc.sprites, &bins, &view);
+        self.forget_text_marks();
+        for line in first_line..doc.lines.len() {
+            let y = (line - first_line) as i32 * doldoc::CELL_H;
+            self.remember_text_line(&doc, line, 0, y);
+        }
+
+        let last = doc.lines.len().saturating_sub(1);
+        self.text_y = (last - first_line.min(last)) as i32 * doldoc::CELL_H;
+        self.text_x = doc.line_text(last).chars().count() as i32 * doldoc::CELL_W;
+        self.present_with_overlays()
+    }
+}
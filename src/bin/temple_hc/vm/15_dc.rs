@@ -0,0 +1,713 @@
+//! Device contexts: the window, or offscreen `CDC`s whose pixels live on the HolyC heap so
+//! programs can pre-render into them and blit them with `GrBlot`.
+
+use super::prelude::*;
+use super::{Obj, ObjRef, Value, Vm};
+use temple_rt::gr3d::{self, DepthBuf, Gr3, Mat4x4, Transform3};
+use temple_rt::rt::Rop;
+use temple_rt::sprite::SpriteTarget;
+
+/// TempleOS' `TRANSPARENT` color: skipped when blitting.
+pub(super) const TRANSPARENT: u8 = temple_rt::rt::TRANSPARENT;
+
+/// `DCF_TRANSFORMATION`: the `Gr*3` routines send points through `dc->r`.
+const DCF_TRANSFORMATION: i64 = 0x100;
+
+/// Where a `CDC` draws.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum DcTarget {
+    Screen,
+    /// An offscreen DC: `height` rows of `stride` (`width_internal`) bytes at `body`.
+    Body {
+        body: i64,
+        width: i32,
+        height: i32,
+        stride: i32,
+    },
+}
+
+impl DcTarget {
+    fn body_len(width: i32, height: i32) -> usize {
+        width.max(0) as usize * height.max(0) as usize
+    }
+}
+
+/// The pixels of an offscreen DC, clipped to its size.
+pub(super) struct BodyCanvas<'a> {
+    px: &'a mut [u8],
+    width: i32,
+    height: i32,
+    stride: i32,
+    font: &'a [u64; 256],
+    rop: Rop,
+    collisions: i64,
+}
+
+impl SpriteTarget for BodyCanvas<'_> {
+    fn set_pixel(&mut self, x: i32, y: i32, color: u8) {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return;
+        }
+        let px = &mut self.px[(y * self.stride + x) as usize];
+        if self.rop.plot(x, y, color, px) {
+            self.collisions += 1;
+        }
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, w: i32, h: i32, color: u8) {
+        if w <= 0 || h <= 0 {
+            return;
+        }
+        let x0 = x.clamp(0, self.width);
+        let y0 = y.clamp(0, self.height);
+        let x1 = x.saturating_add(w).clamp(0, self.width);
+        let y1 = y.saturating_add(h).clamp(0, self.height);
+        if !self.rop.is_plain() {
+            for yy in y0..y1 {
+                for xx in x0..x1 {
+                    self.set_pixel(xx, yy, color);
+                }
+            }
+            return;
+        }
+        for yy in y0..y1 {
+            let row = (yy * self.stride) as usize;
+            self.px[row + x0 as usize..row + x1 as usize].fill(color);
+        }
+    }
+
+    fn blit_8bpp(
+        &mut self,
+        dst_x: i32,
+        dst_y: i32,
+        src_w: i32,
+        src_h: i32,
+        stride: i32,
+        src: &[u8],
+    ) {
+        if src_w <= 0 || src_h <= 0 || stride <= 0 {
+            return;
+        }
+        for row in 0..src_h {
+            let start = (row * stride) as usize;
+            let Some(row_src) = src.get(start..start + src_w as usize) else {
+                return;
+            };
+            for (col, &px) in row_src.iter().enumerate() {
+                if px != TRANSPARENT {
+                    self.set_pixel(dst_x + col as i32, dst_y + row, self.rop.blit_color(px));
+                }
+            }
+        }
+    }
+
+    fn rop(&self) -> Rop {
+        self.rop
+    }
+
+    fn set_rop(&mut self, rop: Rop) {
+        self.rop = rop;
+    }
+}
+
+/// A DC being drawn on.
+pub(super) enum DcDraw<'a> {
+    Screen(&'a mut TempleRt),
+    Body(BodyCanvas<'a>),
+}
+
+impl DcDraw<'_> {
+    /// Back to plain drawing once a builtin is done; returns its `ROP_COLLISION` hits.
+    fn finish(&mut self) -> i64 {
+        self.set_rop(Rop::default());
+        match self {
+            DcDraw::Screen(rt) => rt.take_collisions(),
+            DcDraw::Body(c) => std::mem::take(&mut c.collisions),
+        }
+    }
+
+    /// `GrPrint`: 8x8 glyphs on a `bg` background; `\n` goes back to `x` (same row).
+    pub(super) fn draw_text(&mut self, x: i32, y: i32, fg: u8, bg: u8, text: &str) {
+        let c = match self {
+            DcDraw::Screen(rt) => return rt.draw_text(x, y, fg, bg, text),
+            DcDraw::Body(c) => c,
+        };
+        let mut cx = x;
+        for ch in text.chars() {
+            if ch == '\n' {
+                cx = x;
+                continue;
+            }
+            let glyph = c.font[temple_rt::assets::encode_cp437(ch) as usize];
+            for row in 0..8i32 {
+                let bits = (glyph >> (row * 8)) as u8;
+                for col in 0..8i32 {
+                    let on = bits & (1 << col) != 0;
+                    c.set_pixel(cx + col, y + row, if on { fg } else { bg });
+                }
+            }
+            cx += 8;
+        }
+    }
+}
+
+impl SpriteTarget for DcDraw<'_> {
+    fn set_pixel(&mut self, x: i32, y: i32, color: u8) {
+        match self {
+            DcDraw::Screen(rt) => SpriteTarget::set_pixel(*rt, x, y, color),
+            DcDraw::Body(c) => c.set_pixel(x, y, color),
+        }
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, w: i32, h: i32, color: u8) {
+        match self {
+            DcDraw::Screen(rt) => SpriteTarget::fill_rect(*rt, x, y, w, h, color),
+            DcDraw::Body(c) => c.fill_rect(x, y, w, h, color),
+        }
+    }
+
+    fn draw_line_thick(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u8, thick: i32) {
+        match self {
+            DcDraw::Screen(rt) => SpriteTarget::draw_line_thick(*rt, x1, y1, x2, y2, color, thick),
+            DcDraw::Body(c) => c.draw_line_thick(x1, y1, x2, y2, color, thick),
+        }
+    }
+
+    fn draw_rect_outline_thick(&mut self, x: i32, y: i32, w: i32, h: i32, color: u8, thick: i32) {
+        match self {
+            DcDraw::Screen(rt) => {
+                SpriteTarget::draw_rect_outline_thick(*rt, x, y, w, h, color, thick)
+            }
+            DcDraw::Body(c) => c.draw_rect_outline_thick(x, y, w, h, color, thick),
+        }
+    }
+
+    fn draw_circle_thick(&mut self, cx: i32, cy: i32, r: i32, color: u8, thick: i32) {
+        match self {
+            DcDraw::Screen(rt) => SpriteTarget::draw_circle_thick(*rt, cx, cy, r, color, thick),
+            DcDraw::Body(c) => c.draw_circle_thick(cx, cy, r, color, thick),
+        }
+    }
+
+    fn blit_8bpp(
+        &mut self,
+        dst_x: i32,
+        dst_y: i32,
+        src_w: i32,
+        src_h: i32,
+        stride: i32,
+        src: &[u8],
+    ) {
+        match self {
+            DcDraw::Screen(rt) => {
+                SpriteTarget::blit_8bpp(*rt, dst_x, dst_y, src_w, src_h, stride, src)
+            }
+            DcDraw::Body(c) => c.blit_8bpp(dst_x, dst_y, src_w, src_h, stride, src),
+        }
+    }
+
+    fn rop(&self) -> Rop {
+        match self {
+            DcDraw::Screen(rt) => rt.rop(),
+            DcDraw::Body(c) => c.rop,
+        }
+    }
+
+    fn set_rop(&mut self, rop: Rop) {
+        match self {
+            DcDraw::Screen(rt) => rt.set_rop(rop),
+            DcDraw::Body(c) => c.rop = rop,
+        }
+    }
+}
+
+fn int_field(dc: &ObjRef, name: &str) -> Option<i64> {
+    dc.borrow().fields.get(name).and_then(|v| v.as_i64().ok())
+}
+
+/// A non-`NULL` pointer field (`dc->r`, `dc->depth_buf`).
+fn ptr_field(dc: &ObjRef, name: &str) -> Option<i64> {
+    match dc.borrow().fields.get(name) {
+        Some(Value::Ptr { addr, .. } | Value::Int(addr)) if *addr != 0 => Some(*addr),
+        _ => None,
+    }
+}
+
+impl Vm {
+    /// The `CDC *` argument at `args[idx]`, `gr.dc` when missing or `NULL`.
+    pub(super) fn eval_dc_arg(&mut self, args: &[Expr], idx: usize) -> Result<Value, String> {
+        let v = match args.get(idx) {
+            None | Some(Expr::DefaultArg) => return Ok(Value::Obj(self.dc_alias.clone())),
+            Some(e) => self.eval_expr(e)?,
+        };
+        Ok(match v {
+            Value::Int(0) => Value::Obj(self.dc_alias.clone()),
+            Value::VarRef(name) => self.env.get(&name).unwrap_or(Value::Int(0)),
+            v => v,
+        })
+    }
+
+    /// Where `dc` draws: its heap `body` when it has one, else the window.
+    pub(super) fn dc_target(&self, dc: &Value) -> DcTarget {
+        let Value::Obj(dc) = dc else {
+            return DcTarget::Screen;
+        };
+        let body = match dc.borrow().fields.get("body") {
+            Some(Value::Ptr { addr, .. }) => *addr,
+            Some(Value::Int(addr)) => *addr,
+            _ => 0,
+        };
+        if body == 0 {
+            return DcTarget::Screen;
+        }
+        let width = int_field(dc, "width").unwrap_or(0) as i32;
+        let height = int_field(dc, "height").unwrap_or(0) as i32;
+        let stride = int_field(dc, "width_internal").unwrap_or(width as i64) as i32;
+        DcTarget::Body {
+            body,
+            width: width.clamp(0, stride.max(0)),
+            height: height.max(0),
+            stride: stride.max(0),
+        }
+    }
+
+    /// `dc`'s pen: its `color` and `thick`.
+    pub(super) fn dc_pen(&self, dc: &Value) -> (u8, i32) {
+        match dc {
+            Value::Obj(dc) => (
+                int_field(dc, "color").unwrap_or(15) as u8,
+                (int_field(dc, "thick").unwrap_or(1) as i32).max(1),
+            ),
+            _ => (15, 1),
+        }
+    }
+
+    /// Runs `f` with `dc` to draw on.
+    pub(super) fn with_dc<R>(
+        &mut self,
+        dc: &Value,
+        f: impl FnOnce(&mut DcDraw<'_>) -> R,
+    ) -> Result<R, String> {
+        let rop = self.dc_rop(dc);
+        let (out, hits) = match self.dc_target(dc) {
+            DcTarget::Screen => {
+                let mut draw = DcDraw::Screen(&mut self.rt);
+                draw.set_rop(rop);
+                (f(&mut draw), draw.finish())
+            }
+            DcTarget::Body {
+                body,
+                width,
+                height,
+                stride,
+            } => {
+                let px = self
+                    .heap
+                    .slice_mut(body, DcTarget::body_len(stride, height))
+                    .map_err(|err| format!("CDC body: {err}"))?;
+                let mut draw = DcDraw::Body(BodyCanvas {
+                    px,
+                    width,
+                    height,
+                    stride,
+                    font: self.rt.font_u64(),
+                    rop,
+                    collisions: 0,
+                });
+                (f(&mut draw), draw.finish())
+            }
+        };
+        self.dc_add_collisions(dc, hits);
+        Ok(out)
+    }
+
+    /// The raster op in `dc->color` (`ROP_*`, `ROPF_DITHER` and `c1`) against `dc->bkcolor`.
+    fn dc_rop(&self, dc: &Value) -> Rop {
+        match dc {
+            Value::Obj(dc) => Rop::new(
+                int_field(dc, "color").unwrap_or(0) as u32,
+                int_field(dc, "bkcolor").unwrap_or(0) as u8,
+            ),
+            _ => Rop::default(),
+        }
+    }
+
+    /// `ROP_COLLISION` counts into `dc->collision_cnt`.
+    fn dc_add_collisions(&self, dc: &Value, hits: i64) {
+        if hits == 0 {
+            return;
+        }
+        if let Value::Obj(dc) = dc {
+            let cnt = int_field(dc, "collision_cnt").unwrap_or(0);
+            dc.borrow_mut()
+                .fields
+                .insert("collision_cnt".to_string(), Value::Int(cnt + hits));
+        }
+    }
+
+    /// `DCNew`: a `width` x `height` offscreen DC with a zeroed (black) body.
+    pub(super) fn dc_new(&mut self, width: i64, height: i64) -> Result<ObjRef, String> {
+        if !(0..=0x4000).contains(&width) || !(0..=0x4000).contains(&height) {
+            return Err(format!("DCNew: bad size {width}x{height}"));
+        }
+        // TempleOS pads rows to a multiple of 8 pixels.
+        let stride = (width + 7) & !7;
+        let body = self.heap_alloc((stride * height) as usize, true)?;
+        let (lx, ly, lz) = gr3d::DEFAULT_LIGHT;
+        let ls = Rc::new(RefCell::new(Obj {
+            fields: HashMap::from([
+                ("x".to_string(), Value::Int(lx)),
+                ("y".to_string(), Value::Int(ly)),
+                ("z".to_string(), Value::Int(lz)),
+            ]),
+        }));
+        let dc = Rc::new(RefCell::new(Obj {
+            fields: HashMap::from([
+                ("width".to_string(), Value::Int(width)),
+                ("height".to_string(), Value::Int(height)),
+                ("width_internal".to_string(), Value::Int(stride)),
+                (
+                    "body".to_string(),
+                    Value::Ptr {
+                        addr: body,
+                        elem_bytes: 1,
+                    },
+                ),
+                ("color".to_string(), Value::Int(0)),
+                ("bkcolor".to_string(), Value::Int(0)),
+                ("thick".to_string(), Value::Int(1)),
+                ("flags".to_string(), Value::Int(0)),
+                ("collision_cnt".to_string(), Value::Int(0)),
+                ("ls".to_string(), Value::Obj(ls)),
+            ]),
+        }));
+        self.dc_init_3d(&Value::Obj(dc.clone()), false);
+        Ok(dc)
+    }
+
+    /// Size of what `dc` draws on.
+    pub(super) fn dc_size(&self, dc: &Value) -> (i32, i32) {
+        match self.dc_target(dc) {
+            DcTarget::Screen => {
+                let (w, h) = self.rt.size();
+                (w as i32, h as i32)
+            }
+            DcTarget::Body { width, height, .. } => (width, height),
+        }
+    }
+
+    /// The color at (`x`, `y`) of `dc`, `None` outside it.
+    pub(super) fn dc_peek(&mut self, dc: &Value, x: i32, y: i32) -> Result<Option<u8>, String> {
+        let (w, h) = self.dc_size(dc);
+        if x < 0 || y < 0 || x >= w || y >= h {
+            return Ok(None);
+        }
+        match self.dc_target(dc) {
+            DcTarget::Screen => {
+                let fb = self.rt.framebuffer_mut();
+                Ok(fb.get((y * w + x) as usize).copied())
+            }
+            DcTarget::Body { body, stride, .. } => {
+                Ok(Some(self.heap_read_u8(body + (y * stride + x) as i64)?))
+            }
+        }
+    }
+
+    /// A `w` x `h` block of `dc`'s pixels from (`x`, `y`), row by row; pixels outside it come
+    /// out `TRANSPARENT`.
+    pub(super) fn dc_pixels(
+        &mut self,
+        dc: &Value,
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+    ) -> Result<Vec<u8>, String> {
+        let (dw, dh) = self.dc_size(dc);
+        let (src, stride) = match self.dc_target(dc) {
+            DcTarget::Screen => (&*self.rt.framebuffer_mut(), dw),
+            DcTarget::Body {
+                body,
+                height,
+                stride,
+                ..
+            } => (
+                self.heap_slice(body, DcTarget::body_len(stride, height))?,
+                stride,
+            ),
+        };
+        let mut out = Vec::with_capacity(DcTarget::body_len(w, h));
+        for yy in y..y.saturating_add(h.max(0)) {
+            for xx in x..x.saturating_add(w.max(0)) {
+                let inside = (0..dw).contains(&xx) && (0..dh).contains(&yy);
+                let px = inside.then(|| src.get((yy * stride + xx) as usize).copied());
+                out.push(px.flatten().unwrap_or(TRANSPARENT));
+            }
+        }
+        Ok(out)
+    }
+
+    /// Frees an offscreen DC's body, and any DC's matrix and depth buffer (the window DC's
+    /// matrix is pinned, so it stays).
+    pub(super) fn dc_del(&mut self, dc: &Value) -> Result<(), String> {
+        let Value::Obj(obj) = dc else {
+            return Ok(());
+        };
+        if let DcTarget::Body { body, .. } = self.dc_target(dc) {
+            self.heap_free(body)?;
+            obj.borrow_mut()
+                .fields
+                .insert("body".to_string(), Value::Int(0));
+        }
+        for field in ["r", "depth_buf"] {
+            if let Some(addr) = ptr_field(obj, field) {
+                self.heap_free(addr)?;
+                obj.borrow_mut()
+                    .fields
+                    .insert(field.to_string(), Value::Int(0));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Vm {
+    /// Gives `dc` TempleOS' 3D state: an identity `r`, a zero `x`/`y`/`z` offset and no depth
+    /// buffer. The window DC's matrix is `pinned`, since nothing ever `DCDel`s it.
+    pub(super) fn dc_init_3d(&mut self, dc: &Value, pinned: bool) {
+        let Value::Obj(obj) = dc else {
+            return;
+        };
+        let r = if pinned {
+            self.heap_alloc_pinned(16 * 8)
+        } else {
+            self.heap_alloc(16 * 8, true).unwrap_or(0)
+        };
+        if r != 0 {
+            let _ = self.write_mat4(
+                &Value::Ptr {
+                    addr: r,
+                    elem_bytes: 8,
+                },
+                &gr3d::mat4x4_ident(),
+            );
+        }
+        let mut o = obj.borrow_mut();
+        o.fields.insert(
+            "r".to_string(),
+            Value::Ptr {
+                addr: r,
+                elem_bytes: 8,
+            },
+        );
+        for key in ["x", "y", "z"] {
+            o.fields.insert(key.to_string(), Value::Int(0));
+        }
+        o.fields.insert("depth_buf".to_string(), Value::Int(0));
+    }
+
+    /// An `I64 *` matrix argument: heap memory or a HolyC `I64 r[16]` array.
+    pub(super) fn read_mat4(&self, m: &Value) -> Result<Mat4x4, String> {
+        let mut r = [0i64; 16];
+        match m {
+            Value::Ptr { addr, .. } | Value::Int(addr) => {
+                for (i, slot) in r.iter_mut().enumerate() {
+                    *slot = self.heap_read_i64_le(addr + 8 * i as i64, 8)?;
+                }
+            }
+            Value::Array(arr) | Value::ArrayPtr { arr, .. } => {
+                let start = match m {
+                    Value::ArrayPtr { index, .. } => *index as usize,
+                    _ => 0,
+                };
+                let arr = arr.borrow();
+                for (i, slot) in r.iter_mut().enumerate() {
+                    *slot = match arr.elems.get(start + i) {
+                        Some(v) => v.as_i64()?,
+                        None => return Err("matrix: array has fewer than 16 entries".to_string()),
+                    };
+                }
+            }
+            Value::VarRef(name) => match self.env.get(name) {
+                Some(v) => return self.read_mat4(&v),
+                None => return Err(format!("matrix: unknown variable {name}")),
+            },
+            other => return Err(format!("expected an I64 *matrix, got {other:?}")),
+        }
+        Ok(r)
+    }
+
+    pub(super) fn write_mat4(&mut self, m: &Value, r: &Mat4x4) -> Result<(), String> {
+        match m {
+            Value::Ptr { addr, .. } | Value::Int(addr) => {
+                let bytes: Vec<u8> = r.iter().flat_map(|v| v.to_le_bytes()).collect();
+                self.heap_write_bytes(*addr, &bytes)
+            }
+            Value::Array(arr) | Value::ArrayPtr { arr, .. } => {
+                let start = match m {
+                    Value::ArrayPtr { index, .. } => *index as usize,
+                    _ => 0,
+                };
+                let mut arr = arr.borrow_mut();
+                if arr.elems.len() < start + 16 {
+                    return Err("matrix: array has fewer than 16 entries".to_string());
+                }
+                for (i, v) in r.iter().enumerate() {
+                    arr.elems[start + i] = Value::Int(*v);
+                }
+                Ok(())
+            }
+            Value::VarRef(name) => match self.env.get(name) {
+                Some(v) => self.write_mat4(&v, r),
+                None => Err(format!("matrix: unknown variable {name}")),
+            },
+            other => Err(format!("expected an I64 *matrix, got {other:?}")),
+        }
+    }
+
+    /// `dc->ls`, the light meshes are shaded by.
+    fn dc_light(&self, dc: &Value) -> (i64, i64, i64) {
+        let Value::Obj(dc) = dc else {
+            return gr3d::DEFAULT_LIGHT;
+        };
+        let Some(Value::Obj(ls)) = dc.borrow().fields.get("ls").cloned() else {
+            return gr3d::DEFAULT_LIGHT;
+        };
+        let axis = |name: &str| int_field(&ls, name).unwrap_or(0);
+        (axis("x"), axis("y"), axis("z"))
+    }
+
+    /// `dc`'s transform, and whether `DCF_TRANSFORMATION` is on.
+    pub(super) fn dc_transform(&self, dc: &Value) -> Result<(Transform3, bool), String> {
+        let Value::Obj(obj) = dc else {
+            return Ok((Transform3::default(), false));
+        };
+        let r = obj.borrow().fields.get("r").cloned();
+        let r = match r {
+            Some(m) if m.truthy() => self.read_mat4(&m)?,
+            _ => gr3d::mat4x4_ident(),
+        };
+        let xf = Transform3 {
+            r,
+            x: int_field(obj, "x").unwrap_or(0),
+            y: int_field(obj, "y").unwrap_or(0),
+            z: int_field(obj, "z").unwrap_or(0),
+        };
+        let flags = int_field(obj, "flags").unwrap_or(0);
+        Ok((xf, flags & DCF_TRANSFORMATION != 0))
+    }
+
+    /// `dc->depth_buf` with its row length and height, when it has one the size of the DC.
+    fn dc_depth(&self, dc: &Value) -> Option<(i64, i32, i32)> {
+        let Value::Obj(obj) = dc else {
+            return None;
+        };
+        let addr = ptr_field(obj, "depth_buf")?;
+        let (stride, height) = self.dc_depth_size(dc);
+        let len = DcTarget::body_len(stride, height) * 4;
+        (self.heap_msize(addr) >= len).then_some((addr, stride, height))
+    }
+
+    fn dc_depth_size(&self, dc: &Value) -> (i32, i32) {
+        match self.dc_target(dc) {
+            DcTarget::Screen => self.dc_size(dc),
+            DcTarget::Body { stride, height, .. } => (stride, height),
+        }
+    }
+
+    /// `DCDepthBufAlloc`: a fresh depth buffer for `dc`, all of it infinitely far away.
+    pub(super) fn dc_depth_buf_alloc(&mut self, dc: &Value) -> Result<(), String> {
+        let Value::Obj(obj) = dc else {
+            return Err("DCDepthBufAlloc: expected a CDC".to_string());
+        };
+        if let Some(old) = ptr_field(obj, "depth_buf") {
+            self.heap_free(old)?;
+        }
+        let (stride, height) = self.dc_depth_size(dc);
+        let addr = self.heap_alloc(DcTarget::body_len(stride, height) * 4, false)?;
+        obj.borrow_mut().fields.insert(
+            "depth_buf".to_string(),
+            Value::Ptr {
+                addr,
+                elem_bytes: 4,
+            },
+        );
+        self.dc_depth_buf_rst(dc)
+    }
+
+    /// `DCDepthBufRst`: clears `dc`'s depth buffer, if it has one.
+    pub(super) fn dc_depth_buf_rst(&mut self, dc: &Value) -> Result<(), String> {
+        if let Some((addr, stride, height)) = self.dc_depth(dc) {
+            let len = DcTarget::body_len(stride, height) * 4;
+            DepthBuf::reset(self.heap.slice_mut(addr, len)?);
+        }
+        Ok(())
+    }
+
+    /// Runs `f` with `dc` to draw on in 3D: through its transform and depth buffer.
+    pub(super) fn with_dc3<R>(
+        &mut self,
+        dc: &Value,
+        f: impl FnOnce(&mut Gr3<'_, DcDraw<'_>>) -> R,
+    ) -> Result<R, String> {
+        let (xf, transforming) = self.dc_transform(dc)?;
+        let depth = self.dc_depth(dc);
+        let light = self.dc_light(dc);
+        let rop = self.dc_rop(dc);
+        let depth_len = |stride: i32, height: i32| DcTarget::body_len(stride, height) * 4;
+        let (out, hits) = match self.dc_target(dc) {
+            DcTarget::Screen => {
+                let depth = match depth {
+                    Some((addr, stride, height)) => Some(DepthBuf::new(
+                        self.heap.slice_mut(addr, depth_len(stride, height))?,
+                        stride,
+                        height,
+                    )),
+                    None => None,
+                };
+                let mut draw = DcDraw::Screen(&mut self.rt);
+                draw.set_rop(rop);
+                let mut gr = Gr3::new(&mut draw, xf, transforming, depth);
+                gr.light = light;
+                (f(&mut gr), draw.finish())
+            }
+            DcTarget::Body {
+                body,
+                width,
+                height,
+                stride,
+            } => {
+                let body_len = DcTarget::body_len(stride, height);
+                let (px, depth) = match depth {
+                    Some((addr, d_stride, d_height)) => {
+                        let (px, cells) = self
+                            .heap
+                            .slice_pair_mut(body, body_len, addr, depth_len(d_stride, d_height))
+                            .map_err(|err| format!("CDC body: {err}"))?;
+                        (px, Some(DepthBuf::new(cells, d_stride, d_height)))
+                    }
+                    None => (
+                        self.heap
+                            .slice_mut(body, body_len)
+                            .map_err(|err| format!("CDC body: {err}"))?,
+                        None,
+                    ),
+                };
+                let mut draw = DcDraw::Body(BodyCanvas {
+                    px,
+                    width,
+                    height,
+                    stride,
+                    font: self.rt.font_u64(),
+                    rop,
+                    collisions: 0,
+                });
+                let mut gr = Gr3::new(&mut draw, xf, transforming, depth);
+                gr.light = light;
+                (f(&mut gr), draw.finish())
+            }
+        };
+        self.dc_add_collisions(dc, hits);
+        Ok(out)
+    }
+}
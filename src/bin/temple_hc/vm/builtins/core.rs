@@ -188,17 +188,44 @@ impl Vm {
                 let size: usize = size_i64
                     .try_into()
                     .map_err(|_| format!("{name}: size out of range"))?;
-                let addr = self.heap_alloc(size, zeroed);
+                let addr = self.heap_alloc(size, zeroed)?;
                 Ok(Value::Int(addr))
             }
             "Free" => {
                 if args.len() != 1 {
                     return Err("Free(ptr) expects 1 arg".to_string());
                 }
-                // No-op for now (we don't reclaim heap storage; objects are GC'd by Rust).
-                let _ = self.eval_expr(&args[0])?;
+                match self.eval_expr(&args[0])? {
+                    Value::Int(addr) | Value::Ptr { addr, .. } => self.heap_free(addr)?,
+                    // Class instances and arrays live outside the heap and are dropped by Rust.
+                    _ => {}
+                }
                 Ok(Value::Void)
             }
+            "MSize" => {
+                if args.len() != 1 {
+                    return Err("MSize(ptr) expects 1 arg".to_string());
+                }
+                let size = match self.eval_expr(&args[0])? {
+                    Value::Int(addr) | Value::Ptr { addr, .. } => self.heap_msize(addr),
+                    _ => 0,
+                };
+                Ok(Value::Int(size as i64))
+            }
+            "ReAlloc" => {
+                if args.len() != 2 && args.len() != 3 {
+                    return Err("ReAlloc(ptr, new_size[, mem_task]) expects 2-3 args".to_string());
+                }
+                let addr = self.eval_expr(&args[0])?.as_i64()?;
+                let size_i64 = self.eval_expr(&args[1])?.as_i64()?;
+                if size_i64 < 0 {
+                    return Err("ReAlloc: size must be non-negative".to_string());
+                }
+                let size: usize = size_i64
+                    .try_into()
+                    .map_err(|_| "ReAlloc: size out of range".to_string())?;
+                Ok(Value::Int(self.heap_realloc(addr, size)?))
+            }
             "FileRead" => {
                 if args.len() != 1 {
                     return Err("FileRead(path) expects 1 arg".to_string());
@@ -215,7 +242,7 @@ impl Vm {
                         return Err(format!("FileRead: {}: {err}", host_path.display()));
                     }
                 };
-                let addr = self.heap_alloc(bytes.len() + 1, true);
+                let addr = self.heap_alloc(bytes.len() + 1, true)?;
                 self.heap_write_bytes(addr, &bytes)?;
                 // Ensure a trailing 0 so HolyC code that expects a terminator won't run off.
                 let _ = self.heap_write_u8(addr + bytes.len() as i64, 0);
@@ -285,7 +312,7 @@ impl Vm {
                     Value::Int(0) => Ok(Value::Int(0)),
                     Value::Str(s) => {
                         let bytes = s.as_bytes();
-                        let dst = self.heap_alloc(bytes.len() + 1, true);
+                        let dst = self.heap_alloc(bytes.len() + 1, true)?;
                         self.heap_write_bytes(dst, bytes)?;
                         let _ = self.heap_write_u8(dst + bytes.len() as i64, 0);
                         Ok(Value::Int(dst))
@@ -299,7 +326,7 @@ impl Vm {
                             Value::Int(v) => v as usize,
                             _ => return Err("StrNew: StrLen returned non-int".to_string()),
                         };
-                        let dst = self.heap_alloc(len + 1, true);
+                        let dst = self.heap_alloc(len + 1, true)?;
                         let bytes = self.heap_slice(src, len + 1)?.to_vec();
                         self.heap_write_bytes(dst, &bytes)?;
                         Ok(Value::Int(dst))
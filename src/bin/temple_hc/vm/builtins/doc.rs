@@ -0,0 +1,285 @@
+use super::super::prelude::*;
+use super::super::{ObjRef, Value, Vm, doc};
+use temple_rt::doldoc;
+
+impl Vm {
+    pub(super) fn call_builtin_doc(&mut self, name: &str, args: &[Expr]) -> Result<Value, String> {
+        match name {
+            "DocNew" => {
+                if args.len() > 2 {
+                    return Err("DocNew(filename=NULL, task=NULL) expects 0-2 args".to_string());
+                }
+                let filename = self.doc_str_arg(name, args, 0)?.unwrap_or_default();
+                Ok(Value::Obj(doc::new_doc(&filename)))
+            }
+            "DocPut" | "DocDisplay" => {
+                if args.len() > 1 {
+                    return Err(format!("{name}(task=NULL) expects 0-1 args"));
+                }
+                if let Some(expr) = args.first() {
+                    self.eval_expr(expr)?;
+                }
+                Ok(Value::Obj(self.put_doc.clone()))
+            }
+            "DocDel" | "DocClear" => {
+                if args.len() > 1 {
+                    return Err(format!("{name}(doc=NULL) expects 0-1 args"));
+                }
+                let doc = self.doc_arg(args, 0)?;
+                doc::doc_clear(&doc);
+                if self.is_put_doc(&doc) {
+                    self.rt.clear(0);
+                    self.text_x = 0;
+                    self.text_y = 0;
+                    self.text_fg = 15;
+                    self.text_bg = 0;
+                    self.text_style = doldoc::DocStyle::new(15, 0);
+                    self.forget_text_marks();
+                }
+                Ok(Value::Void)
+            }
+            "DocTop" | "DocBottom" => {
+                if args.len() > 1 {
+                    return Err(format!("{name}(doc=NULL) expects 0-1 args"));
+                }
+                let doc = self.doc_arg(args, 0)?;
+                let at = match name {
+                    "DocTop" => doc.borrow().fields.get("next").cloned(),
+                    _ => None,
+                };
+                let at = at.unwrap_or_else(|| Value::Obj(doc.clone()));
+                let mut d = doc.borrow_mut();
+                d.fields.insert("cur_entry".to_string(), at);
+                d.fields.insert("cur_col".to_string(), Value::Int(0));
+                Ok(Value::Void)
+            }
+            "DocPrint" | "Print" => {
+                let (doc, fmt_idx) = match name {
+                    "Print" if args.is_empty() => {
+                        return Err("Print(fmt, ...) expects at least 1 arg".to_string());
+                    }
+                    "Print" => (self.put_doc.clone(), 0),
+                    _ if args.len() < 2 => {
+                        return Err("DocPrint(doc, fmt, ...) expects at least 2 args".to_string());
+                    }
+                    _ => (self.doc_arg(args, 0)?, 1),
+                };
+                let fmt = self.doc_str_arg(name, args, fmt_idx)?.unwrap_or_default();
+                let mut values = Vec::with_capacity(args.len() - fmt_idx - 1);
+                for expr in &args[fmt_idx + 1..] {
+                    values.push(self.eval_expr(expr)?);
+                }
+                let rendered = format_temple_fmt_with_cstr(
+                    &fmt,
+                    &values,
+                    |ptr| self.read_cstr_lossy(ptr),
+                    |idx, name| self.define_sub(idx, name),
+                )?;
+                if self.is_put_doc(&doc) {
+                    self.print_str(&rendered);
+                    self.present_with_overlays()?;
+                } else {
+                    let src = Self::print_doc_source(&rendered);
+                    self.doc_insert_source(&doc, &src, &Default::default())?;
+                }
+                Ok(Value::Void)
+            }
+            "DocLoad" => {
+                if !(3..=4).contains(&args.len()) {
+                    return Err(
+                        "DocLoad(doc=NULL, src, size, flags=0) expects 3-4 args".to_string()
+                    );
+                }
+                let doc = match self.eval_doc_value(args.first())? {
+                    Some(doc) => doc,
+                    None => doc::new_doc(""),
+                };
+                let src = self.eval_expr(&args[1])?.as_i64()?;
+                let size = usize::try_from(self.eval_expr(&args[2])?.as_i64()?)
+                    .map_err(|_| "DocLoad: size must be non-negative".to_string())?;
+                let buf = if size == 0 {
+                    Vec::new()
+                } else {
+                    self.heap_slice(src, size)?.to_vec()
+                };
+                let (text, bins) = doldoc::parse_doc_blob(&buf);
+                self.doc_insert_source(&doc, &text, &bins)?;
+                if self.is_put_doc(&doc) {
+                    self.redraw_put_doc()?;
+                }
+                Ok(Value::Obj(doc))
+            }
+            "DocSave" => {
+                if args.is_empty() || args.len() > 2 {
+                    return Err("DocSave(doc, _size=NULL) expects 1-2 args".to_string());
+                }
+                let doc = self.doc_arg(args, 0)?;
+                let (text, bins) = self.doc_source(&doc)?;
+                let blob = doldoc::encode_doc_blob(text.as_bytes(), &bins);
+                let addr = self.heap_alloc(blob.len() + 1, true)?;
+                self.heap_write_bytes(addr, &blob)?;
+                if let Some(expr) = args.get(1).filter(|e| !matches!(e, Expr::DefaultArg)) {
+                    let out = self.eval_expr(expr)?;
+                    self.doc_write_i64(out, blob.len() as i64)?;
+                }
+                Ok(Value::Ptr {
+                    addr,
+                    elem_bytes: 1,
+                })
+            }
+            "DocRead" => {
+                if args.len() > 2 {
+                    return Err("DocRead(filename=NULL, flags=0) expects 0-2 args".to_string());
+                }
+                let filename = self.doc_str_arg(name, args, 0)?.unwrap_or_default();
+                let doc = doc::new_doc(&filename);
+                if filename.is_empty() {
+                    return Ok(Value::Obj(doc));
+                }
+                let host = self.resolve_temple_fs_target_read(&filename)?;
+                match std::fs::read(&host) {
+                    Ok(buf) => {
+                        let (text, bins) = doldoc::parse_doc_blob(&buf);
+                        self.doc_insert_source(&doc, &text, &bins)?;
+                        // A freshly read document starts with the cursor at the top.
+                        let first = doc.borrow().fields.get("next").cloned();
+                        if let Some(first) = first {
+                            doc.borrow_mut()
+                                .fields
+                                .insert("cur_entry".to_string(), first);
+                        }
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                    Err(err) => return Err(format!("DocRead: {}: {err}", host.display())),
+                }
+                Ok(Value::Obj(doc))
+            }
+            "DocWrite" => {
+                if args.is_empty() || args.len() > 2 {
+                    return Err("DocWrite(doc, prompt=FALSE) expects 1-2 args".to_string());
+                }
+                let doc = self.doc_arg(args, 0)?;
+                if let Some(expr) = args.get(1) {
+                    self.eval_expr(expr)?;
+                }
+                let filename = match doc.borrow().fields.get("filename") {
+                    Some(Value::Obj(f)) => match f.borrow().fields.get("name") {
+                        Some(Value::Str(s)) => s.clone(),
+                        _ => String::new(),
+                    },
+                    _ => String::new(),
+                };
+                if filename.trim().is_empty() {
+                    return Err("DocWrite: the document has no filename".to_string());
+                }
+                let (text, bins) = self.doc_source(&doc)?;
+                let host = self.resolve_temple_fs_target_write(&filename)?;
+                if let Some(parent) = host.parent() {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|err| format!("DocWrite: {}: {err}", parent.display()))?;
+                }
+                std::fs::write(&host, doldoc::encode_doc_blob(text.as_bytes(), &bins))
+                    .map_err(|err| format!("DocWrite: {}: {err}", host.display()))?;
+                Ok(Value::Void)
+            }
+            "DocInsDoc" => {
+                if args.len() != 2 {
+                    return Err("DocInsDoc(doc=NULL, doc2) expects 2 args".to_string());
+                }
+                let doc = self.doc_arg(args, 0)?;
+                let Some(doc2) = self.eval_doc_value(args.get(1))? else {
+                    return Ok(Value::Void);
+                };
+                let (text, bins) = self.doc_source(&doc2)?;
+                self.doc_insert_source(&doc, &text, &bins)?;
+                if self.is_put_doc(&doc) {
+                    self.draw_doc_at_cursor(&text, &bins);
+                    self.present_with_overlays()?;
+                }
+                Ok(Value::Void)
+            }
+            "DocEntryDel" => {
+                if args.len() != 2 {
+                    return Err("DocEntryDel(doc, doc_e) expects 2 args".to_string());
+                }
+                let doc = self.doc_arg(args, 0)?;
+                let Some(e) = self.eval_doc_value(args.get(1))? else {
+                    return Err("DocEntryDel: doc_e must be an entry".to_string());
+                };
+                if !Rc::ptr_eq(&doc, &e) {
+                    doc::doc_entry_del(&doc, &e);
+                }
+                Ok(Value::Void)
+            }
+            "DocRecalc" => {
+                if args.is_empty() || args.len() > 2 {
+                    return Err("DocRecalc(doc, recalc_flags=0) expects 1-2 args".to_string());
+                }
+                let doc = self.doc_arg(args, 0)?;
+                doc::doc_recalc(&doc);
+                if self.is_put_doc(&doc) {
+                    self.redraw_put_doc()?;
+                }
+                Ok(Value::Void)
+            }
+            _ => Err(format!("internal: call_builtin_doc cannot handle {name}")),
+        }
+    }
+
+    /// A `CDoc *`/`CDocEntry *` argument; `None` for a missing arg or `NULL`.
+    fn eval_doc_value(&mut self, expr: Option<&Expr>) -> Result<Option<ObjRef>, String> {
+        let v = match expr {
+            None | Some(Expr::DefaultArg) => return Ok(None),
+            Some(expr) => self.eval_expr(expr)?,
+        };
+        match v {
+            Value::Int(0) => Ok(None),
+            Value::Obj(obj) => Ok(Some(obj)),
+            Value::VarRef(name) => match self.env.get(&name) {
+                Some(Value::Obj(obj)) => Ok(Some(obj)),
+                _ => Err(format!("{name} is not a document")),
+            },
+            other => Err(format!("expected a document, got {other:?}")),
+        }
+    }
+
+    /// Document argument `idx`, defaulting to the put document as TempleOS does.
+    fn doc_arg(&mut self, args: &[Expr], idx: usize) -> Result<ObjRef, String> {
+        Ok(self
+            .eval_doc_value(args.get(idx))?
+            .unwrap_or_else(|| self.put_doc.clone()))
+    }
+
+    fn doc_str_arg(
+        &mut self,
+        name: &str,
+        args: &[Expr],
+        idx: usize,
+    ) -> Result<Option<String>, String> {
+        match args.get(idx) {
+            None | Some(Expr::DefaultArg) => Ok(None),
+            Some(expr) => match self.eval_expr(expr)? {
+                Value::Int(0) => Ok(None),
+                Value::Str(s) => Ok(Some(s)),
+                Value::Int(ptr) => Ok(Some(self.read_cstr_lossy(ptr)?)),
+                Value::Ptr { addr, .. } => Ok(Some(self.read_cstr_lossy(addr)?)),
+                _ => Err(format!("{name}: argument {} must be a string", idx + 1)),
+            },
+        }
+    }
+
+    fn doc_write_i64(&mut self, ptr: Value, value: i64) -> Result<(), String> {
+        match ptr {
+            Value::Int(0) => Ok(()),
+            Value::VarRef(name) => self.env.assign(&name, Value::Int(value)),
+            Value::Ptr { addr, elem_bytes } => {
+                self.heap_write_i64_le(addr, elem_bytes.max(1), value)
+            }
+            Value::ObjFieldRef { obj, field } => {
+                obj.borrow_mut().fields.insert(field, Value::Int(value));
+                Ok(())
+            }
+            other => Err(format!("DocSave: _size must be a pointer, got {other:?}")),
+        }
+    }
+}
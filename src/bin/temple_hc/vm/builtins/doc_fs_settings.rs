@@ -1,5 +1,6 @@
 use super::super::prelude::*;
-use super::super::{Obj, Value, Vm};
+use super::super::{Obj, ObjRef, Value, Vm};
+use temple_rt::doldoc;
 
 impl Vm {
     pub(super) fn call_builtin_doc_fs_settings(
@@ -8,14 +9,6 @@ impl Vm {
         args: &[Expr],
     ) -> Result<Value, String> {
         match name {
-            "DocClear" => {
-                self.rt.clear(0);
-                self.text_x = 0;
-                self.text_y = 0;
-                self.text_fg = 15;
-                self.text_bg = 0;
-                Ok(Value::Void)
-            }
             "Cd" => {
                 if args.len() > 2 {
                     return Err("Cd(dir=\"...\", make_dirs=FALSE) expects 0-2 args".to_string());
@@ -72,6 +65,7 @@ impl Vm {
                             .fields
                             .insert("cur_dir".to_string(), Value::Str(new_cwd));
                     }
+                    self.notify_shell_cd()?;
                     return Ok(Value::Int(1));
                 }
 
@@ -86,6 +80,7 @@ impl Vm {
                                 .fields
                                 .insert("cur_dir".to_string(), Value::Str(new_cwd));
                         }
+                        self.notify_shell_cd()?;
                         return Ok(Value::Int(1));
                     }
                 }
@@ -328,12 +323,6 @@ impl Vm {
                 }
                 Ok(Value::Void)
             }
-            "DocBottom" => {
-                if !args.is_empty() {
-                    return Err("DocBottom expects 0 args".to_string());
-                }
-                Ok(Value::Void)
-            }
             "DocScroll" => {
                 if !args.is_empty() {
                     return Err("DocScroll expects 0 args".to_string());
@@ -374,11 +363,6 @@ impl Vm {
                 }
                 Ok(Value::Void)
             }
-            "DCFill" => {
-                self.rt.clear(0);
-                self.present_with_overlays()?;
-                Ok(Value::Void)
-            }
             "DCAlias" => {
                 if args.len() > 2 {
                     return Err("DCAlias(dc=NULL,task=NULL) expects 0-2 args".to_string());
@@ -413,7 +397,10 @@ impl Vm {
                     fields.insert("mem_task".to_string(), Value::Obj(task));
                 }
 
-                Ok(Value::Obj(Rc::new(RefCell::new(Obj { fields }))))
+                // An alias draws where `dc` does but has its own matrix and no depth buffer.
+                let alias = Value::Obj(Rc::new(RefCell::new(Obj { fields })));
+                self.dc_init_3d(&alias, false);
+                Ok(alias)
             }
             "DCSymmetrySet" => {
                 // Minimal stub: record symmetry line endpoints on the dc for sprite mirroring.
@@ -452,11 +439,11 @@ impl Vm {
 
                 Ok(Value::Void)
             }
-            "DCDel" => Ok(Value::Void),
             "PressAKey" => {
                 if !args.is_empty() {
                     return Err("PressAKey expects 0 args".to_string());
                 }
+                self.macro_input_wait()?;
                 // TempleOS draws directly to VRAM; flush at least once before we block so the
                 // user sees the frame.
                 self.present_with_overlays()?;
@@ -523,6 +510,7 @@ impl Vm {
                 let mut input = dft.unwrap_or_default();
                 let mut dirty = true;
                 let mut cancel_null = false;
+                self.macro_input_wait()?;
 
                 loop {
                     if dirty {
@@ -660,11 +648,111 @@ impl Vm {
                 }
 
                 let bytes = input.as_bytes();
-                let addr = self.heap_alloc(bytes.len() + 1, true);
+                let addr = self.heap_alloc(bytes.len() + 1, true)?;
                 self.heap_write_bytes(addr, bytes)?;
                 let _ = self.heap_write_u8(addr + bytes.len() as i64, 0);
                 Ok(Value::Int(addr))
             }
+            "PopUpForm" | "DocForm" => {
+                if args.is_empty() || args.len() > 2 {
+                    return Err(format!("{name}(&obj, class_name=NULL) expects 1-2 args"));
+                }
+                let obj = match self.eval_expr(&args[0])? {
+                    Value::Obj(obj) => obj,
+                    Value::VarRef(var) => match self.env.get(&var) {
+                        Some(Value::Obj(obj)) => obj,
+                        _ => return Err(format!("{name}: {var} is not a class object")),
+                    },
+                    other => return Err(format!("{name}: expected &obj, got {other:?}")),
+                };
+                let class_name = match args.get(1) {
+                    None | Some(Expr::DefaultArg) => None,
+                    Some(expr) => match self.eval_expr(expr)? {
+                        Value::Int(0) => None,
+                        Value::Str(s) => Some(s),
+                        Value::Int(ptr) => Some(self.read_cstr_lossy(ptr)?),
+                        Value::Ptr { addr, .. } => Some(self.read_cstr_lossy(addr)?),
+                        _ => return Err(format!("{name}: class_name must be a string")),
+                    },
+                };
+                self.pop_up_form(obj, class_name.as_deref())
+            }
+            "Dir" => {
+                if args.len() > 1 {
+                    return Err("Dir(files_find_mask=\"*\") expects 0-1 args".to_string());
+                }
+                let mask = match args.first() {
+                    None | Some(Expr::DefaultArg) => None,
+                    Some(expr) => match self.eval_expr(expr)? {
+                        Value::Int(0) => None,
+                        Value::Str(s) => Some(s),
+                        Value::Int(ptr) => Some(self.read_cstr_lossy(ptr)?),
+                        Value::Ptr { addr, .. } => Some(self.read_cstr_lossy(addr)?),
+                        _ => return Err("Dir: mask must be a string".to_string()),
+                    },
+                };
+                let dir = mask
+                    .as_deref()
+                    .filter(|m| !m.contains('*'))
+                    .unwrap_or(".")
+                    .to_string();
+                if self.macro_mode {
+                    let spec = self.shell_spec(&dir)?;
+                    self.shell_request(&format!("dir {spec}"))?;
+                    return Ok(Value::Void);
+                }
+
+                let host = self.resolve_temple_fs_target_read(&dir)?;
+                let mut entries: Vec<(String, bool)> = std::fs::read_dir(&host)
+                    .map_err(|err| format!("Dir: {}: {err}", host.display()))?
+                    .flatten()
+                    .map(|e| {
+                        let is_dir = e.file_type().is_ok_and(|t| t.is_dir());
+                        (e.file_name().to_string_lossy().into_owned(), is_dir)
+                    })
+                    .collect();
+                entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                let cwd = self.resolve_temple_spec_read(&dir)?;
+                let mut out = format!("Directory of {cwd}\n");
+                for (name, is_dir) in entries {
+                    out.push_str(&name);
+                    if is_dir {
+                        out.push('/');
+                    }
+                    out.push('\n');
+                }
+                self.print_str(&out);
+                Ok(Value::Void)
+            }
+            "View" => {
+                // The shell's doc viewer already shows whatever the macro opened.
+                for arg in args {
+                    self.eval_expr(arg)?;
+                }
+                Ok(Value::Void)
+            }
+            "Ed" | "Type" | "KeyMap" => {
+                if name == "KeyMap" {
+                    self.shell_request("keymap")?;
+                    return Ok(Value::Void);
+                }
+                let file = match args.first() {
+                    Some(expr) => match self.eval_expr(expr)? {
+                        Value::Str(s) => s,
+                        Value::Int(ptr) if ptr != 0 => self.read_cstr_lossy(ptr)?,
+                        Value::Ptr { addr, .. } => self.read_cstr_lossy(addr)?,
+                        _ => return Err(format!("{name}: filename must be a string")),
+                    },
+                    None => return Err(format!("{name}(filename) expects a filename")),
+                };
+                if name == "Type" && !self.macro_mode {
+                    self.type_doc(&file)?;
+                    return Ok(Value::Void);
+                }
+                let spec = self.shell_spec(&file)?;
+                self.shell_request(&format!("doc {spec}"))?;
+                Ok(Value::Void)
+            }
             "ClipPutS" => {
                 if args.len() != 1 {
                     return Err("ClipPutS(\"text\") expects 1 arg".to_string());
@@ -684,3 +772,357 @@ impl Vm {
         }
     }
 }
+
+/// One `format "..."` member shown by `PopUpForm`.
+struct FormRow {
+    field: String,
+    /// `U8 *` member: a heap string rather than an integer.
+    cstr: bool,
+    entry: doldoc::FormEntry,
+}
+
+impl Vm {
+    /// Finds the class of `obj`: the named one, or the first class with `format` members whose
+    /// fields are exactly the object's.
+    fn form_class_of(&self, obj: &ObjRef, class_name: Option<&str>) -> Option<String> {
+        if let Some(name) = class_name {
+            return self
+                .program
+                .classes
+                .contains_key(name)
+                .then(|| name.to_string());
+        }
+        let obj = obj.borrow();
+        let mut names: Vec<&String> = self.program.classes.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .find(|name| {
+                let def = &self.program.classes[*name];
+                def.fields.iter().any(|f| f.format.is_some())
+                    && def.fields.len() == obj.fields.len()
+                    && def.fields.iter().all(|f| obj.fields.contains_key(&f.name))
+            })
+            .cloned()
+    }
+
+    fn form_read_str(&self, v: &Value) -> Result<String, String> {
+        match v {
+            Value::Str(s) => Ok(s.clone()),
+            Value::Int(ptr) | Value::Ptr { addr: ptr, .. } => self.read_cstr_lossy(*ptr),
+            Value::Array(arr) => {
+                let arr = arr.borrow();
+                let bytes: Vec<u8> = arr
+                    .elems
+                    .iter()
+                    .map(|e| e.as_i64().unwrap_or(0) as u8)
+                    .take_while(|&b| b != 0)
+                    .collect();
+                Ok(bytes
+                    .into_iter()
+                    .map(temple_rt::assets::decode_cp437_byte)
+                    .collect())
+            }
+            other => Err(format!("PopUpForm: cannot show {other:?} as a string")),
+        }
+    }
+
+    /// Loads a member's current value into its form entry.
+    fn form_load_row(&self, row: &mut FormRow, value: &Value) -> Result<(), String> {
+        use doldoc::FormKind;
+
+        match row.entry.kind {
+            FormKind::CheckBox => row.entry.checked = value.truthy(),
+            FormKind::List => {
+                let idx = value.as_i64()?;
+                let list = row.entry.list.clone().unwrap_or_default();
+                row.entry.tag = self
+                    .define_sub(idx, &list)
+                    .unwrap_or_else(|| idx.to_string());
+            }
+            FormKind::Data => {
+                let (_, conv, _) = row.entry.fmt_parts();
+                row.entry.tag = match (conv, value) {
+                    ('s', _) => self.form_read_str(value)?,
+                    ('f' | 'n' | 'e' | 'g', v) => v.as_f64()?.to_string(),
+                    ('X', v) => format!("{:X}", v.as_i64()?),
+                    ('x', v) => format!("{:x}", v.as_i64()?),
+                    ('c', v) => char::from_u32(v.as_i64()? as u32)
+                        .map(String::from)
+                        .unwrap_or_default(),
+                    (_, Value::Float(f)) => f.to_string(),
+                    (_, v) => v.as_i64()?.to_string(),
+                };
+            }
+            FormKind::Button | FormKind::Menu => {}
+        }
+        Ok(())
+    }
+
+    /// Converts a form entry back into a member value; `old` decides the representation.
+    fn form_store_row(&mut self, row: &FormRow, old: &Value) -> Result<Value, String> {
+        use doldoc::FormKind;
+
+        match row.entry.kind {
+            FormKind::CheckBox => Ok(Value::Int(row.entry.checked as i64)),
+            FormKind::List => {
+                let list = row.entry.list.clone().unwrap_or_default();
+                let idx = self
+                    .define_lists
+                    .get(&list)
+                    .and_then(|items| {
+                        items
+                            .iter()
+                            .position(|it| it.eq_ignore_ascii_case(row.entry.tag.trim()))
+                    })
+                    .map(|i| i as i64)
+                    .or_else(|| row.entry.tag.trim().parse().ok());
+                Ok(idx.map(Value::Int).unwrap_or_else(|| old.clone()))
+            }
+            FormKind::Data => {
+                let text = row.entry.tag.as_str();
+                let (_, conv, _) = row.entry.fmt_parts();
+                if conv == 's' {
+                    return match old {
+                        Value::Str(_) => Ok(Value::Str(text.to_string())),
+                        Value::Array(arr) => {
+                            {
+                                let mut arr = arr.borrow_mut();
+                                let cap = arr.elems.len().saturating_sub(1);
+                                let mut bytes = text.bytes().take(cap);
+                                for elem in arr.elems.iter_mut() {
+                                    *elem = Value::Int(bytes.next().unwrap_or(0) as i64);
+                                }
+                            }
+                            Ok(old.clone())
+                        }
+                        _ if row.cstr => {
+                            let bytes = text.as_bytes();
+                            let addr = self.heap_alloc(bytes.len() + 1, true)?;
+                            self.heap_write_bytes(addr, bytes)?;
+                            Ok(Value::Int(addr))
+                        }
+                        _ => Err(format!("PopUpForm: {} is not a string member", row.field)),
+                    };
+                }
+                let text = text.trim();
+                let parsed = match conv {
+                    'X' | 'x' => i64::from_str_radix(text.trim_start_matches("0x"), 16)
+                        .ok()
+                        .map(Value::Int),
+                    'c' => text.chars().next().map(|c| Value::Int(c as i64)),
+                    _ => match old {
+                        Value::Float(_) => text.parse::<f64>().ok().map(Value::Float),
+                        _ => text
+                            .parse::<i64>()
+                            .ok()
+                            .or_else(|| text.parse::<f64>().ok().map(|f| f as i64))
+                            .map(Value::Int),
+                    },
+                };
+                Ok(parsed.unwrap_or_else(|| old.clone()))
+            }
+            FormKind::Button | FormKind::Menu => Ok(old.clone()),
+        }
+    }
+
+    /// `PopUpForm(&obj)`: edits the members of `obj` that carry `format "$$DA...$$"` meta data.
+    /// Up/Down/Tab move, typing edits data fields, Space toggles check boxes and steps lists,
+    /// Esc accepts (TRUE) and Shift+Esc cancels (FALSE). A button ends the form with its `LE=`.
+    fn pop_up_form(&mut self, obj: ObjRef, class_name: Option<&str>) -> Result<Value, String> {
+        use doldoc::FormKind;
+
+        let Some(class) = self.form_class_of(&obj, class_name) else {
+            return Err("PopUpForm: unknown class (pass the class name)".to_string());
+        };
+        let fields = self.program.classes[&class].fields.clone();
+        let mut rows: Vec<FormRow> = Vec::new();
+        for field in &fields {
+            let Some(fmt) = field.format.as_deref() else {
+                continue;
+            };
+            let fmt = fmt.replace("$$", "$");
+            let Some(entry) =
+                doldoc::tokenize(&fmt)
+                    .into_iter()
+                    .find_map(|piece| match piece.token {
+                        doldoc::DocToken::Cmd(cmd) => doldoc::FormEntry::from_cmd(&cmd),
+                        _ => None,
+                    })
+            else {
+                continue;
+            };
+            let mut row = FormRow {
+                field: field.name.clone(),
+                cstr: field.pointer && field.ty == "U8",
+                entry,
+            };
+            if let Some(value) = obj.borrow().fields.get(&field.name) {
+                self.form_load_row(&mut row, value)?;
+            }
+            rows.push(row);
+        }
+        if rows.is_empty() {
+            return Err(format!("PopUpForm: class {class} has no format members"));
+        }
+
+        let (sw, sh) = self.rt.size();
+        let (sw, sh) = (sw as i32, sh as i32);
+        self.macro_input_wait()?;
+        let hint = "Esc=OK  Shift+Esc=Cancel";
+        let cols = rows
+            .iter()
+            .map(|r| r.entry.display().chars().count() + 1)
+            .chain([hint.len(), class.len()])
+            .max()
+            .unwrap_or(0) as i32
+            + 2;
+        let w = (cols * 8).min(sw);
+        let h = (8 * (rows.len() as i32 + 3)).min(sh);
+        let x0 = ((sw - w) / 2).max(0);
+        let y0 = ((sh - h) / 2).max(0);
+        let underlay = Self::menu_capture_underlay(&mut self.rt, x0, y0, w, h);
+
+        let mut sel = 0usize;
+        let mut dirty = true;
+        let result = loop {
+            if dirty {
+                self.rt.fill_rect(x0, y0, w, h, 15);
+                self.rt.fill_rect(x0, y0, w, 8, 1);
+                self.rt.draw_text(x0 + 8, y0, 15, 1, &class);
+                for (i, row) in rows.iter().enumerate() {
+                    let y = y0 + 8 * (i as i32 + 1) + 4;
+                    let (fg, bg) = if i == sel { (15, 1) } else { (1, 15) };
+                    let mut text = row.entry.display();
+                    if i == sel && row.entry.kind == FormKind::Data {
+                        text.push('_');
+                    }
+                    self.rt.draw_text(x0 + 8, y, fg, bg, &text);
+                }
+                self.rt.draw_text(x0 + 8, y0 + h - 8, 8, 15, hint);
+                self.present_with_overlays()?;
+                dirty = false;
+            }
+
+            self.poll_events()?;
+            let Some(code) = self.key_queue.pop_front() else {
+                thread::sleep(Duration::from_millis(1));
+                continue;
+            };
+            dirty = true;
+            let row = &mut rows[sel];
+            match code {
+                0x1B => break Some(1),
+                0x1C => break None,
+                protocol::KEY_UP => sel = sel.checked_sub(1).unwrap_or(rows.len() - 1),
+                protocol::KEY_DOWN | 0x09 => sel = (sel + 1) % rows.len(),
+                0x08 if row.entry.kind == FormKind::Data => {
+                    row.entry.tag.pop();
+                }
+                0x20 | 0x0A if row.entry.kind == FormKind::CheckBox => {
+                    row.entry.checked = !row.entry.checked;
+                }
+                0x20 | 0x0A if row.entry.kind == FormKind::List => {
+                    let list = row.entry.list.clone().unwrap_or_default();
+                    let items: Vec<&str> = self
+                        .define_lists
+                        .get(&list)
+                        .map(|items| items.iter().map(String::as_str).collect())
+                        .or_else(|| doldoc::builtin_define_list(&list).map(<[&str]>::to_vec))
+                        .unwrap_or_default();
+                    if let Some(next) = row.entry.next_list_value(&items) {
+                        row.entry.tag = next;
+                    }
+                }
+                0x20 | 0x0A if matches!(row.entry.kind, FormKind::Button | FormKind::Menu) => {
+                    break Some(row.entry.left_exp.unwrap_or(1));
+                }
+                0x0A => break Some(1),
+                other => {
+                    if row.entry.kind == FormKind::Data
+                        && let Ok(b) = u8::try_from(other)
+                        && (b.is_ascii_graphic() || b == b' ')
+                        && row.entry.tag.chars().count() < row.entry.len
+                    {
+                        row.entry.tag.push(b as char);
+                    } else {
+                        dirty = false;
+                    }
+                }
+            }
+        };
+
+        if let Some(underlay) = underlay.as_ref() {
+            Self::menu_restore_underlay(&mut self.rt, underlay);
+            self.present_with_overlays()?;
+        }
+
+        let Some(result) = result else {
+            return Ok(Value::Int(0));
+        };
+        for row in &rows {
+            let old = obj
+                .borrow()
+                .fields
+                .get(&row.field)
+                .cloned()
+                .unwrap_or(Value::Int(0));
+            let new = self.form_store_row(row, &old)?;
+            obj.borrow_mut().fields.insert(row.field.clone(), new);
+        }
+        Ok(Value::Int(result))
+    }
+
+    /// `Type(filename)`: lays the document out with the shared DolDoc engine and draws it at the
+    /// text cursor, as if it had been printed.
+    fn type_doc(&mut self, file: &str) -> Result<(), String> {
+        let host = self.resolve_temple_fs_target_read(file)?;
+        let buf = std::fs::read(&host).map_err(|err| format!("Type: {file}: {err}"))?;
+        let (text, bins) = doldoc::parse_doc_blob(&buf);
+        let put_doc = self.put_doc.clone();
+        self.doc_insert_source(&put_doc, &text, &bins)?;
+        self.draw_doc_at_cursor(&text, &bins);
+        Ok(())
+    }
+
+    /// Draws a laid-out document at the text cursor, line by line, scrolling like printed text.
+    pub(super) fn draw_doc_at_cursor(
+        &mut self,
+        text: &str,
+        bins: &std::collections::BTreeMap<u32, Vec<u8>>,
+    ) {
+        let cols = (self.rt.size().0 as usize / 8).max(1);
+        let opts = doldoc::LayoutOptions::view(cols, self.text_fg, self.text_bg);
+        let doc = doldoc::layout(text, bins, opts);
+        let mut n = doc.lines.len();
+        while n > 0 && doc.line_text(n - 1).is_empty() {
+            n -= 1;
+        }
+
+        if self.text_x != 0 {
+            self.put_char('\n');
+        }
+        let mut view = doldoc::Viewport {
+            x: 0,
+            y: 0,
+            cols,
+            rows: 1,
+            first_line: 0,
+        };
+        for i in 0..n {
+            for ch in doc.line_text(i).chars() {
+                self.capture_push(ch);
+            }
+            view.y = self.text_y;
+            view.first_line = i;
+            doldoc::render_cells(&mut self.rt, &doc, &view);
+            self.put_char('\n');
+        }
+
+        // Earlier lines may have scrolled up by now; sprites go where their lines ended up.
+        view.y = self.text_y - n as i32 * doldoc::CELL_H;
+        view.first_line = 0;
+        view.rows = n;
+        doldoc::render_sprites(&mut self.rt, &doc.sprites, bins, &view);
+    }
+}
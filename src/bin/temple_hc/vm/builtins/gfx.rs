@@ -1,5 +1,13 @@
+use super::super::dc::{DcTarget, TRANSPARENT};
 use super::super::prelude::*;
 use super::super::{Value, Vm};
+use temple_rt::assets;
+use temple_rt::doldoc::Canvas;
+use temple_rt::font::{self, Font};
+use temple_rt::gr3d::{self, Transform3};
+use temple_rt::image;
+use temple_rt::rt::Rop;
+use temple_rt::sprite::{self, SpriteTarget, sprite_render3};
 
 impl Vm {
     pub(super) fn call_builtin_gfx(&mut self, name: &str, args: &[Expr]) -> Result<Value, String> {
@@ -20,7 +28,7 @@ impl Vm {
                 let x = self.eval_expr(x)?.as_i64()? as i32;
                 let y = self.eval_expr(y)?.as_i64()? as i32;
 
-                let color = match dc {
+                let color = match &dc {
                     Value::Obj(dc) => dc
                         .borrow()
                         .fields
@@ -30,7 +38,7 @@ impl Vm {
                     _ => 15u8,
                 };
 
-                self.rt.set_pixel(x, y, color);
+                self.with_dc(&dc, |t| t.set_pixel(x, y, color))?;
                 Ok(Value::Void)
             }
             "GrLine" => {
@@ -102,45 +110,15 @@ impl Vm {
                     _ => 15u8,
                 };
 
-                self.rt.draw_line_thick(x1, y1, x2, y2, color, thick);
+                self.with_dc(&dc, |t| t.draw_line_thick(x1, y1, x2, y2, color, thick))?;
 
                 Ok(Value::Void)
             }
             "GrLine3" => {
-                // GrLine3(dc?, x1, y1, z1, x2, y2, z2, thick?) — ignore Z.
-                let (dc, x1, y1, x2, y2, thick) = match args.len() {
-                    6 => (
-                        Value::Obj(self.dc_alias.clone()),
-                        &args[0],
-                        &args[1],
-                        &args[3],
-                        &args[4],
-                        None,
-                    ),
-                    7 if matches!(args[0], Expr::DefaultArg) => (
-                        Value::Obj(self.dc_alias.clone()),
-                        &args[1],
-                        &args[2],
-                        &args[4],
-                        &args[5],
-                        None,
-                    ),
-                    7 => {
-                        let dc = self.eval_expr(&args[0])?;
-                        (dc, &args[1], &args[2], &args[4], &args[5], None)
-                    }
-                    8 if matches!(args[0], Expr::DefaultArg) => (
-                        Value::Obj(self.dc_alias.clone()),
-                        &args[1],
-                        &args[2],
-                        &args[4],
-                        &args[5],
-                        Some(&args[7]),
-                    ),
-                    8 => {
-                        let dc = self.eval_expr(&args[0])?;
-                        (dc, &args[1], &args[2], &args[4], &args[5], Some(&args[7]))
-                    }
+                // GrLine3(dc?, x1, y1, z1, x2, y2, z2, thick?)
+                let (dc, rest) = match args.len() {
+                    6 => (Value::Obj(self.dc_alias.clone()), args),
+                    7 | 8 => (self.eval_dc_arg(args, 0)?, &args[1..]),
                     _ => {
                         return Err(
                             "GrLine3(dc?, x1, y1, z1, x2, y2, z2, thick?) expects 6-8 args"
@@ -148,36 +126,17 @@ impl Vm {
                         );
                     }
                 };
-
-                let x1 = self.eval_expr(x1)?.as_i64()? as i32;
-                let y1 = self.eval_expr(y1)?.as_i64()? as i32;
-                let x2 = self.eval_expr(x2)?.as_i64()? as i32;
-                let y2 = self.eval_expr(y2)?.as_i64()? as i32;
-                let thick = match thick {
-                    Some(e) => self.eval_expr(e)?.as_i64()? as i32,
-                    None => match &dc {
-                        Value::Obj(dc) => dc
-                            .borrow()
-                            .fields
-                            .get("thick")
-                            .and_then(|v| v.as_i64().ok())
-                            .unwrap_or(1) as i32,
-                        _ => 1,
-                    },
+                let mut v = [0i64; 6];
+                for (slot, e) in v.iter_mut().zip(rest) {
+                    *slot = self.eval_expr(e)?.as_i64()?;
                 }
-                .max(1);
-
-                let color = match &dc {
-                    Value::Obj(dc) => dc
-                        .borrow()
-                        .fields
-                        .get("color")
-                        .and_then(|v| v.as_i64().ok())
-                        .unwrap_or(15) as u8,
-                    _ => 15u8,
-                };
-
-                self.rt.draw_line_thick(x1, y1, x2, y2, color, thick);
+                let (color, mut thick) = self.dc_pen(&dc);
+                if let Some(e) = rest.get(6) {
+                    thick = (self.eval_expr(e)?.as_i64()? as i32).max(1);
+                }
+                self.with_dc3(&dc, |gr| {
+                    gr.line3((v[0], v[1], v[2]), (v[3], v[4], v[5]), color, thick)
+                })?;
                 Ok(Value::Void)
             }
             "GrBorder" => {
@@ -234,11 +193,13 @@ impl Vm {
 
                 let w = x2.saturating_sub(x1);
                 let h = y2.saturating_sub(y1);
-                self.rt.draw_rect_outline_thick(x1, y1, w, h, color, thick);
+                self.with_dc(&dc, |t| {
+                    t.draw_rect_outline_thick(x1, y1, w, h, color, thick)
+                })?;
                 Ok(Value::Void)
             }
             "GrEllipse" => {
-                // GrEllipse(dc?, x, y, r1, r2) — approximate with a polyline.
+                // GrEllipse(dc?, x, y, r1, r2): the same ellipse as an `SPT_ELLIPSE` sprite element.
                 let (dc, x, y, r1, r2) = match args.len() {
                     4 => (
                         Value::Obj(self.dc_alias.clone()),
@@ -291,18 +252,16 @@ impl Vm {
                     return Ok(Value::Void);
                 }
 
-                let steps = (((r1 + r2) * 0.5).round() as i32).clamp(12, 256) * 4;
-                let mut prev: Option<(i32, i32)> = None;
-                for i in 0..=steps {
-                    let t = (i as f64) / (steps as f64);
-                    let a = t * std::f64::consts::TAU;
-                    let px = (x + a.cos() * r1).round() as i32;
-                    let py = (y + a.sin() * r2).round() as i32;
-                    if let Some((ox, oy)) = prev {
-                        self.rt.draw_line_thick(ox, oy, px, py, color, thick);
-                    }
-                    prev = Some((px, py));
-                }
+                self.with_dc(&dc, |t| {
+                    sprite::draw_ellipse(
+                        t,
+                        (x.round() as i32, y.round() as i32),
+                        (r1.round() as i32, r2.round() as i32),
+                        0.0,
+                        color,
+                        thick,
+                    )
+                })?;
 
                 Ok(Value::Void)
             }
@@ -389,7 +348,7 @@ impl Vm {
                     .map(|v| v as u8)
                     .unwrap_or(default_color);
 
-                self.rt.draw_rect_outline_thick(x, y, w, h, color, thick);
+                self.with_dc(&dc, |t| t.draw_rect_outline_thick(x, y, w, h, color, thick))?;
                 Ok(Value::Void)
             }
             "GrPrint" => {
@@ -472,9 +431,62 @@ impl Vm {
                     _ => 15u8,
                 };
                 let bg = 0u8;
-                self.rt.draw_text(x, y, fg, bg, &rendered);
+                if self.rt.text_font().is_none() && self.rt.text_scale() == 1 {
+                    self.with_dc(&dc, |t| t.draw_text(x, y, fg, bg, &rendered))?;
+                    return Ok(Value::Void);
+                }
+                // The window's font and scale (`GrFontLoad`, `GrPrintScaleSet`).
+                let font = self
+                    .rt
+                    .text_font()
+                    .cloned()
+                    .unwrap_or_else(|| Font::from_u64(font::STD_FONT, self.rt.font_u64()));
+                let scale = self.rt.text_scale();
+                self.with_dc(&dc, |t| {
+                    font.draw_text(x, y, scale, &rendered, |px, py, on| {
+                        t.set_pixel(px, py, if on { fg } else { bg })
+                    })
+                })?;
                 Ok(Value::Void)
             }
+            "GrPrintScaleSet" => {
+                if args.len() != 1 {
+                    return Err("GrPrintScaleSet(scale) expects 1 arg".to_string());
+                }
+                let scale = self.eval_expr(&args[0])?.as_i64()?;
+                let old = self.rt.text_scale();
+                self.rt
+                    .set_text_scale(scale.clamp(1, font::MAX_SCALE as i64) as u32);
+                Ok(Value::Int(old as i64))
+            }
+            "GrFontLoad" => {
+                if args.len() > 1 {
+                    return Err("GrFontLoad(name=NULL) expects 0-1 args".to_string());
+                }
+                let name = match args.first() {
+                    None | Some(Expr::DefaultArg) => String::new(),
+                    Some(e) => match self.eval_expr(e)? {
+                        Value::Str(s) => s,
+                        Value::Int(0) => String::new(),
+                        Value::Int(ptr) | Value::Ptr { addr: ptr, .. } => {
+                            self.read_cstr_lossy(ptr)?
+                        }
+                        other => {
+                            return Err(format!(
+                                "GrFontLoad: name must be a string, got {other:?}"
+                            ));
+                        }
+                    },
+                };
+                let root = std::env::var_os("TEMPLE_ROOT").unwrap_or_default();
+                // Like the file built-ins, a font that is missing or unreadable just fails.
+                match font::load_font(std::path::Path::new(&root), &name) {
+                    Ok(f) if f.name == font::STD_FONT => self.rt.set_text_font(None),
+                    Ok(f) => self.rt.set_text_font(Some(f)),
+                    Err(_) => return Ok(Value::Int(0)),
+                }
+                Ok(Value::Int(1))
+            }
             "GrPaletteColorSet" => {
                 if args.len() != 2 {
                     return Err("GrPaletteColorSet(color_num, bgr48) expects 2 args".to_string());
@@ -654,123 +666,287 @@ impl Vm {
                     .map(|v| v as u8)
                     .unwrap_or(default_color);
 
-                self.rt.draw_circle_thick(x, y, r, color, thick);
+                self.with_dc(&dc, |t| t.draw_circle_thick(x, y, r, color, thick))?;
                 Ok(Value::Void)
             }
             "GrCircle3" => {
-                // GrCircle3(dc?, x, y, z, r, color?) — ignore Z.
-                let (dc, x, y, r, color) = match args.len() {
-                    4 => (
-                        Value::Obj(self.dc_alias.clone()),
-                        &args[0],
-                        &args[1],
-                        &args[3],
-                        None,
-                    ),
-                    5 if matches!(args[0], Expr::DefaultArg) => (
-                        Value::Obj(self.dc_alias.clone()),
-                        &args[1],
-                        &args[2],
-                        &args[4],
-                        None,
-                    ),
-                    5 => {
-                        let dc = self.eval_expr(&args[0])?;
-                        (dc, &args[1], &args[2], &args[4], None)
-                    }
-                    6 if matches!(args[0], Expr::DefaultArg) => (
-                        Value::Obj(self.dc_alias.clone()),
-                        &args[1],
-                        &args[2],
-                        &args[4],
-                        Some(&args[5]),
-                    ),
-                    6 => {
-                        let dc = self.eval_expr(&args[0])?;
-                        (dc, &args[1], &args[2], &args[4], Some(&args[5]))
-                    }
+                // GrCircle3(dc?, x, y, z, r, color?): a circle in the XY plane at depth z.
+                let (dc, rest) = match args.len() {
+                    4 => (Value::Obj(self.dc_alias.clone()), args),
+                    5 | 6 => (self.eval_dc_arg(args, 0)?, &args[1..]),
                     _ => {
                         return Err(
                             "GrCircle3(dc?, x, y, z, r, color?) expects 4-6 args".to_string()
                         );
                     }
                 };
-
-                let x = self.eval_expr(x)?.as_i64()? as i32;
-                let y = self.eval_expr(y)?.as_i64()? as i32;
-                let r = self.eval_expr(r)?.as_i64()? as i32;
-
-                let thick = match &dc {
-                    Value::Obj(dc) => dc
-                        .borrow()
-                        .fields
-                        .get("thick")
-                        .and_then(|v| v.as_i64().ok())
-                        .unwrap_or(1) as i32,
-                    _ => 1,
+                let mut v = [0i64; 4];
+                for (slot, e) in v.iter_mut().zip(rest) {
+                    *slot = self.eval_expr(e)?.as_i64()?;
                 }
-                .max(1);
-
-                let default_color = match &dc {
-                    Value::Obj(dc) => dc
-                        .borrow()
-                        .fields
-                        .get("color")
-                        .and_then(|v| v.as_i64().ok())
-                        .unwrap_or(15) as u8,
-                    _ => 15u8,
+                let [x, y, z, r] = v;
+                let (mut color, thick) = self.dc_pen(&dc);
+                if let Some(e) = rest.get(4) {
+                    color = self.eval_expr(e)?.as_i64()? as u8;
+                }
+                self.with_dc3(&dc, |gr| gr.circle3((x, y, z), r, color, thick))?;
+                Ok(Value::Void)
+            }
+            "GrPlot3" => {
+                let (dc, rest) = match args.len() {
+                    3 => (Value::Obj(self.dc_alias.clone()), args),
+                    4 => (self.eval_dc_arg(args, 0)?, &args[1..]),
+                    _ => return Err("GrPlot3(dc?, x, y, z) expects 3 or 4 args".to_string()),
                 };
-                let color = color
-                    .map(|e| self.eval_expr(e))
-                    .transpose()?
-                    .map(|v| v.as_i64())
-                    .transpose()?
-                    .map(|v| v as u8)
-                    .unwrap_or(default_color);
-
-                self.rt.draw_circle_thick(x, y, r, color, thick);
+                let mut p = [0i64; 3];
+                for (slot, e) in p.iter_mut().zip(rest) {
+                    *slot = self.eval_expr(e)?.as_i64()?;
+                }
+                let (color, _) = self.dc_pen(&dc);
+                self.with_dc3(&dc, |gr| gr.plot3((p[0], p[1], p[2]), color, 1))?;
                 Ok(Value::Void)
             }
-            "DCDepthBufAlloc" => {
-                if args.len() > 2 {
-                    return Err("DCDepthBufAlloc(dc=gr.dc, flags=0) expects 0-2 args".to_string());
+            "GrFillTri0" => {
+                // GrFillTri0(dc?, p1, p2, p3): points already on the DC, as in TempleOS; only
+                // the depth buffer applies.
+                let (dc, rest) = match args.len() {
+                    3 => (Value::Obj(self.dc_alias.clone()), args),
+                    4 => (self.eval_dc_arg(args, 0)?, &args[1..]),
+                    _ => return Err("GrFillTri0(dc?, p1, p2, p3) expects 3 or 4 args".to_string()),
+                };
+                let p0 = self.eval_d3(name, &rest[0])?;
+                let p1 = self.eval_d3(name, &rest[1])?;
+                let p2 = self.eval_d3(name, &rest[2])?;
+                let (color, _) = self.dc_pen(&dc);
+                self.with_dc3(&dc, |gr| gr.fill_tri(p0, p1, p2, color))?;
+                Ok(Value::Void)
+            }
+            "DCNew" => {
+                if !(2..=4).contains(&args.len()) {
+                    return Err(
+                        "DCNew(width, height, task=NULL, null_bitmap=FALSE) expects 2-4 args"
+                            .to_string(),
+                    );
+                }
+                let width = self.eval_expr(&args[0])?.as_i64()?;
+                let height = self.eval_expr(&args[1])?.as_i64()?;
+                for e in &args[2..] {
+                    self.eval_expr(e)?;
                 }
-                let _dc = match args.get(0) {
-                    None | Some(Expr::DefaultArg) => Value::Obj(self.dc_alias.clone()),
-                    Some(e) => self.eval_expr(e)?,
+                Ok(Value::Obj(self.dc_new(width, height)?))
+            }
+            "DCDel" => {
+                if args.len() != 1 {
+                    return Err("DCDel(dc) expects 1 arg".to_string());
+                }
+                let dc = self.eval_expr(&args[0])?;
+                self.dc_del(&dc)?;
+                Ok(Value::Void)
+            }
+            "DCFill" | "DCClear" => {
+                let max = if name == "DCFill" { 2 } else { 1 };
+                if args.len() > max {
+                    return Err(match name {
+                        "DCFill" => "DCFill(dc=NULL, val=TRANSPARENT) expects 0-2 args",
+                        _ => "DCClear(dc=NULL) expects 0-1 args",
+                    }
+                    .to_string());
+                }
+                let dc = self.eval_dc_arg(args, 0)?;
+                let val = match args.get(1) {
+                    None | Some(Expr::DefaultArg) => None,
+                    Some(e) => Some(self.eval_expr(e)?.as_i64()? as u8),
+                };
+                match self.dc_target(&dc) {
+                    // The window has no transparent layer to reveal: fill it black by default.
+                    DcTarget::Screen => {
+                        self.rt
+                            .clear(val.filter(|&v| v != TRANSPARENT).unwrap_or(0));
+                        self.present_with_overlays()?;
+                    }
+                    DcTarget::Body { .. } => {
+                        let val = match name {
+                            "DCFill" => val.unwrap_or(TRANSPARENT),
+                            _ => 0,
+                        };
+                        let (w, h) = self.dc_size(&dc);
+                        // A plain fill, whatever raster op `dc->color` has.
+                        self.with_dc(&dc, |t| {
+                            t.set_rop(Rop::default());
+                            t.fill_rect(0, 0, w, h, val)
+                        })?;
+                    }
+                }
+                Ok(Value::Void)
+            }
+            "GrBlot" => {
+                // GrBlot(dc=gr.dc, x, y, img): copies `img` with its TRANSPARENT pixels left out.
+                let (dc, rest) = match args.len() {
+                    3 => (Value::Obj(self.dc_alias.clone()), args),
+                    4 => (self.eval_dc_arg(args, 0)?, &args[1..]),
+                    _ => return Err("GrBlot(dc?, x, y, img) expects 3 or 4 args".to_string()),
                 };
-                let _flags = match args.get(1) {
-                    None | Some(Expr::DefaultArg) => Value::Int(0),
-                    Some(e) => self.eval_expr(e)?,
+                let x = self.eval_expr(&rest[0])?.as_i64()? as i32;
+                let y = self.eval_expr(&rest[1])?.as_i64()? as i32;
+                let img = self.eval_expr(&rest[2])?;
+                if !matches!(img, Value::Obj(_)) {
+                    return Ok(Value::Int(0));
+                }
+                let (w, h) = self.dc_size(&img);
+                let px = self.dc_pixels(&img, 0, 0, w, h)?;
+                self.with_dc(&dc, |t| t.blit_8bpp(x, y, w, h, w, &px))?;
+                Ok(Value::Int(1))
+            }
+            "GrPeek" => {
+                let (dc, rest) = match args.len() {
+                    2 => (Value::Obj(self.dc_alias.clone()), args),
+                    3 => (self.eval_dc_arg(args, 0)?, &args[1..]),
+                    _ => return Err("GrPeek(dc?, x, y) expects 2 or 3 args".to_string()),
                 };
+                let x = self.eval_expr(&rest[0])?.as_i64()? as i32;
+                let y = self.eval_expr(&rest[1])?.as_i64()? as i32;
+                Ok(Value::Int(self.dc_peek(&dc, x, y)?.map_or(-1, i64::from)))
+            }
+            "DCExtract" | "DCCopy" => {
+                let (dc, rect) = match (name, args.len()) {
+                    ("DCExtract", 4) => (Value::Obj(self.dc_alias.clone()), Some(&args[..4])),
+                    ("DCExtract", 5 | 6) => (self.eval_dc_arg(args, 0)?, Some(&args[1..5])),
+                    ("DCCopy", 1 | 2) => (self.eval_dc_arg(args, 0)?, None),
+                    ("DCExtract", _) => {
+                        return Err("DCExtract(dc?, x1, y1, x2, y2, task=NULL) expects 4-6 args"
+                            .to_string());
+                    }
+                    _ => return Err("DCCopy(dc, task=NULL) expects 1-2 args".to_string()),
+                };
+                let (x, y, w, h) = match rect {
+                    Some(rect) => {
+                        let mut v = [0i32; 4];
+                        for (slot, e) in v.iter_mut().zip(rect) {
+                            *slot = self.eval_expr(e)?.as_i64()? as i32;
+                        }
+                        let [x1, y1, x2, y2] = v;
+                        (x1, y1, x2 - x1 + 1, y2 - y1 + 1)
+                    }
+                    None => {
+                        let (w, h) = self.dc_size(&dc);
+                        (0, 0, w, h)
+                    }
+                };
+                let (w, h) = (w.max(0), h.max(0));
+                let px = self.dc_pixels(&dc, x, y, w, h)?;
+                let out = self.dc_new(w as i64, h as i64)?;
+                let out_v = Value::Obj(out.clone());
+                self.with_dc(&out_v, |t| t.blit_8bpp(0, 0, w, h, w, &px))?;
+                // A copy keeps the pen; an extract starts from a fresh DC.
+                if let (None, Value::Obj(src)) = (rect, &dc) {
+                    for key in ["color", "bkcolor", "thick", "flags"] {
+                        if let Some(v) = src.borrow().fields.get(key).cloned() {
+                            out.borrow_mut().fields.insert(key.to_string(), v);
+                        }
+                    }
+                }
+                Ok(out_v)
+            }
+            "GRRead" | "BMPRead" => {
+                let max = if name == "GRRead" { 2 } else { 3 };
+                if args.is_empty() || args.len() > max {
+                    return Err(match name {
+                        "GRRead" => "GRRead(filename, dcsf_flags=DCSF_COMPRESSED) expects 1-2 args",
+                        _ => {
+                            "BMPRead(filename, dither_probability=FALSE, use_ms_paint_palette=FALSE) expects 1-3 args"
+                        }
+                    }
+                    .to_string());
+                }
+                let Value::Str(path) = self.eval_expr(&args[0])? else {
+                    return Err(format!("{name}: filename must be a string"));
+                };
+                let mut dither = false;
+                for (i, e) in args.iter().enumerate().skip(1) {
+                    let v = match e {
+                        Expr::DefaultArg => 0,
+                        e => self.eval_expr(e)?.as_i64()?,
+                    };
+                    dither |= name == "BMPRead" && i == 1 && v != 0;
+                }
+                let ext = if name == "GRRead" { "GR" } else { "BMP" };
+                let Some(bytes) = self.read_image_file(&path, ext)? else {
+                    return Ok(Value::Int(0));
+                };
+                let decoded = match name {
+                    "GRRead" => image::decode_gr(&bytes).map(|gr| gr.image),
+                    _ if dither => image::decode_bmp(&bytes, image::Dither::FloydSteinberg),
+                    _ => image::decode_bmp(&bytes, image::Dither::Nearest),
+                };
+                // Like TempleOS, a file that is not an image reads as NULL.
+                let Ok(canvas) = decoded else {
+                    return Ok(Value::Int(0));
+                };
+                let (w, h) = (canvas.width as i32, canvas.height as i32);
+                let out = Value::Obj(self.dc_new(w as i64, h as i64)?);
+                self.with_dc(&out, |t| t.blit_8bpp(0, 0, w, h, w, &canvas.pixels))?;
+                Ok(out)
+            }
+            "GRWrite" => {
+                if !(2..=3).contains(&args.len()) {
+                    return Err(
+                        "GRWrite(filename, dc, dcsf_flags=DCSF_COMPRESSED) expects 2-3 args"
+                            .to_string(),
+                    );
+                }
+                let Value::Str(path) = self.eval_expr(&args[0])? else {
+                    return Err("GRWrite: filename must be a string".to_string());
+                };
+                let dc = self.eval_dc_arg(args, 1)?;
+                let flags = match args.get(2) {
+                    None | Some(Expr::DefaultArg) => DCSF_COMPRESSED,
+                    Some(e) => self.eval_expr(e)?.as_i64()?,
+                };
+                let (w, h) = self.dc_size(&dc);
+                let mut canvas = Canvas::new(w as u32, h as u32, TRANSPARENT);
+                canvas.pixels = self.dc_pixels(&dc, 0, 0, w, h)?;
+                // There is no palette state here: DCSF_PALETTE_GET saves the standard one.
+                let palette: [[u8; 4]; 16] =
+                    std::array::from_fn(|i| assets::TEMPLEOS_GR_PALETTE_STD_RGBA256[i]);
+                let bytes = image::encode_gr(
+                    &canvas,
+                    flags & DCSF_COMPRESSED != 0,
+                    (flags & DCSF_PALETTE_GET != 0).then_some(&palette),
+                );
+                let path = if has_extension(&path) {
+                    path
+                } else {
+                    format!("{path}.GR")
+                };
+                let host_path = self.resolve_temple_fs_target_write(&path)?;
+                if let Some(parent) = host_path.parent() {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|err| format!("GRWrite: {}: {err}", parent.display()))?;
+                }
+                std::fs::write(&host_path, &bytes)
+                    .map_err(|err| format!("GRWrite: {}: {err}", host_path.display()))?;
+                Ok(Value::Int(bytes.len() as i64))
+            }
+            "DCDepthBufAlloc" | "DCDepthBufRst" => {
+                if args.len() > 2 {
+                    return Err(format!("{name}(dc=gr.dc) expects 0-1 args"));
+                }
+                let dc = self.eval_dc_arg(args, 0)?;
+                if let Some(e) = args.get(1) {
+                    self.eval_expr(e)?;
+                }
+                match name {
+                    "DCDepthBufAlloc" => self.dc_depth_buf_alloc(&dc)?,
+                    _ => self.dc_depth_buf_rst(&dc)?,
+                }
                 Ok(Value::Void)
             }
             "D3I32Norm" => {
                 if args.len() != 1 {
                     return Err("D3I32Norm(p) expects 1 arg".to_string());
                 }
-                let v = self.eval_expr(&args[0])?;
-                let obj = match v {
-                    Value::Obj(obj) => obj,
-                    Value::ObjFieldRef { obj, field } => {
-                        match obj.borrow().fields.get(&field).cloned() {
-                            Some(Value::Obj(inner)) => inner,
-                            _ => {
-                                return Err("D3I32Norm: expected &obj_field with x/y/z".to_string());
-                            }
-                        }
-                    }
-                    Value::VarRef(name) => match self.env.get(&name) {
-                        Some(Value::Obj(obj)) => obj,
-                        _ => return Err("D3I32Norm: expected object".to_string()),
-                    },
-                    _ => return Err("D3I32Norm: expected object pointer".to_string()),
-                };
-
-                let o = obj.borrow();
-                let x = o.fields.get("x").and_then(|v| v.as_i64().ok()).unwrap_or(0) as f64;
-                let y = o.fields.get("y").and_then(|v| v.as_i64().ok()).unwrap_or(0) as f64;
-                let z = o.fields.get("z").and_then(|v| v.as_i64().ok()).unwrap_or(0) as f64;
+                let (x, y, z) = self.eval_d3(name, &args[0])?;
+                let (x, y, z) = (x as f64, y as f64, z as f64);
                 Ok(Value::Float((x * x + y * y + z * z).sqrt()))
             }
             "SpriteInterpolate" => {
@@ -786,119 +962,237 @@ impl Vm {
                     Ok(Value::Int(e1))
                 }
             }
-            "Sprite3YB" => {
-                // Sprite3YB(dc=gr.dc, x, y, z, elems, angle=0) — ignore rotation and Z.
+            "Sprite3YB" | "Sprite3" => {
+                // Sprite3(dc=gr.dc, x, y, z, elems, just_one_elem=FALSE) — just_one_elem is
+                // ignored. Sprite3YB(dc=gr.dc, x, y, z, elems, angle=0) turns the sprite `angle`
+                // radians about the Y axis through its origin, then moves it to (x, y, z).
                 if !(args.len() == 5 || args.len() == 6) {
-                    return Err(
-                        "Sprite3YB(dc, x, y, z, elems, angle?) expects 5-6 args".to_string()
-                    );
+                    return Err(match name {
+                        "Sprite3YB" => "Sprite3YB(dc, x, y, z, elems, angle?) expects 5-6 args",
+                        _ => "Sprite3(dc, x, y, z, elems, just_one_elem?) expects 5-6 args",
+                    }
+                    .to_string());
                 }
 
-                let dc = self.eval_expr(&args[0])?;
-                let x = self.eval_expr(&args[1])?.as_f64()? as i32;
-                let y = self.eval_expr(&args[2])?.as_f64()? as i32;
-                let _z = self.eval_expr(&args[3])?.as_f64()?;
+                let dc = self.eval_dc_arg(args, 0)?;
+                let x = self.eval_expr(&args[1])?.as_f64()? as i64;
+                let y = self.eval_expr(&args[2])?.as_f64()? as i64;
+                let z = self.eval_expr(&args[3])?.as_f64()? as i64;
                 let elems = self.eval_expr(&args[4])?.as_i64()?;
-                let _angle = if args.len() == 6 {
-                    self.eval_expr(&args[5])?
-                } else {
-                    Value::Float(0.0)
+                let last = match args.get(5) {
+                    Some(e) if !matches!(e, Expr::DefaultArg) => self.eval_expr(e)?.as_f64()?,
+                    _ => 0.0,
                 };
-
                 if elems == 0 {
                     return Ok(Value::Void);
                 }
 
-                let initial_color = match &dc {
-                    Value::Obj(dc) => dc
-                        .borrow()
-                        .fields
-                        .get("color")
-                        .and_then(|v| v.as_i64().ok())
-                        .unwrap_or(15) as u8,
-                    _ => 15u8,
-                } & 0x0f;
-                let initial_thick = match &dc {
-                    Value::Obj(dc) => dc
-                        .borrow()
-                        .fields
-                        .get("thick")
-                        .and_then(|v| v.as_i64().ok())
-                        .unwrap_or(1) as i32,
-                    _ => 1,
-                }
-                .max(1);
-
+                let (color, thick) = self.dc_pen(&dc);
                 let bytes_vec = if let Some(&len) = self.doldoc_bin_len_by_ptr.get(&elems) {
                     self.heap_slice(elems, len)?.to_vec()
                 } else {
                     self.heap_tail(elems)?.to_vec()
                 };
 
-                temple_rt::sprite::sprite_render_with_state(
-                    &mut self.rt,
-                    x,
-                    y,
-                    &bytes_vec,
-                    initial_color,
-                    initial_thick,
-                );
+                let placed = match name {
+                    "Sprite3YB" => {
+                        let (xf, transforming) = self.dc_transform(&dc)?;
+                        let r = gr3d::mat4x4_rot_y(&gr3d::mat4x4_ident(), last);
+                        let r = gr3d::mat4x4_translation_equ(&r, x, y, z);
+                        Some(if transforming {
+                            Transform3 {
+                                r: gr3d::mat4x4_mul(&xf.r, &r),
+                                ..xf
+                            }
+                        } else {
+                            Transform3 {
+                                r,
+                                ..Transform3::default()
+                            }
+                        })
+                    }
+                    _ => None,
+                };
+                self.with_dc3(&dc, |gr| match placed {
+                    Some(xf) => {
+                        gr.xf = xf;
+                        gr.transforming = true;
+                        sprite_render3(gr, 0, 0, 0, &bytes_vec, color & 0x0f, thick);
+                    }
+                    None => sprite_render3(gr, x, y, z, &bytes_vec, color & 0x0f, thick),
+                })?;
                 Ok(Value::Void)
             }
-            "Sprite3" => {
-                // Sprite3(dc=gr.dc, x, y, z, elems, just_one_elem=FALSE) — ignore Z and just_one_elem.
-                if !(args.len() == 5 || args.len() == 6) {
-                    return Err(
-                        "Sprite3(dc, x, y, z, elems, just_one_elem?) expects 5-6 args".to_string(),
-                    );
+            "Mat4x4IdentNew" => {
+                if args.len() > 1 {
+                    return Err("Mat4x4IdentNew(mem_task=NULL) expects 0-1 args".to_string());
                 }
-
-                let dc = self.eval_expr(&args[0])?;
-                let x = self.eval_expr(&args[1])?.as_f64()? as i32;
-                let y = self.eval_expr(&args[2])?.as_f64()? as i32;
-                let _z = self.eval_expr(&args[3])?.as_f64()?;
-                let elems = self.eval_expr(&args[4])?.as_i64()?;
-                if elems == 0 {
-                    return Ok(Value::Void);
+                for e in args {
+                    self.eval_expr(e)?;
                 }
-
-                let initial_color = match &dc {
-                    Value::Obj(dc) => dc
-                        .borrow()
-                        .fields
-                        .get("color")
-                        .and_then(|v| v.as_i64().ok())
-                        .unwrap_or(15) as u8,
-                    _ => 15u8,
-                } & 0x0f;
-                let initial_thick = match &dc {
-                    Value::Obj(dc) => dc
-                        .borrow()
-                        .fields
-                        .get("thick")
-                        .and_then(|v| v.as_i64().ok())
-                        .unwrap_or(1) as i32,
-                    _ => 1,
+                let addr = self.heap_alloc(16 * 8, false)?;
+                let r = Value::Ptr {
+                    addr,
+                    elem_bytes: 8,
+                };
+                self.write_mat4(&r, &gr3d::mat4x4_ident())?;
+                Ok(r)
+            }
+            "Mat4x4IdentEqu" => {
+                if args.len() != 1 {
+                    return Err("Mat4x4IdentEqu(r) expects 1 arg".to_string());
                 }
-                .max(1);
-
-                let bytes_vec = if let Some(&len) = self.doldoc_bin_len_by_ptr.get(&elems) {
-                    self.heap_slice(elems, len)?.to_vec()
-                } else {
-                    self.heap_tail(elems)?.to_vec()
+                let r = self.eval_expr(&args[0])?;
+                self.write_mat4(&r, &gr3d::mat4x4_ident())?;
+                Ok(r)
+            }
+            "Mat4x4RotX" | "Mat4x4RotY" | "Mat4x4RotZ" | "Mat4x4Scale" => {
+                if args.len() != 2 {
+                    return Err(match name {
+                        "Mat4x4Scale" => "Mat4x4Scale(m, s) expects 2 args".to_string(),
+                        _ => format!("{name}(m, phi) expects 2 args"),
+                    });
+                }
+                let m = self.eval_expr(&args[0])?;
+                let v = self.eval_expr(&args[1])?.as_f64()?;
+                let r = self.read_mat4(&m)?;
+                let r = match name {
+                    "Mat4x4RotX" => gr3d::mat4x4_rot_x(&r, v),
+                    "Mat4x4RotY" => gr3d::mat4x4_rot_y(&r, v),
+                    "Mat4x4RotZ" => gr3d::mat4x4_rot_z(&r, v),
+                    _ => gr3d::mat4x4_scale(&r, v),
                 };
-
-                temple_rt::sprite::sprite_render_with_state(
-                    &mut self.rt,
-                    x,
-                    y,
-                    &bytes_vec,
-                    initial_color,
-                    initial_thick,
-                );
+                self.write_mat4(&m, &r)?;
+                Ok(m)
+            }
+            "Mat4x4TranslationEqu" | "Mat4x4TranslationAdd" => {
+                if args.len() != 4 {
+                    return Err(format!("{name}(r, x, y, z) expects 4 args"));
+                }
+                let m = self.eval_expr(&args[0])?;
+                let x = self.eval_expr(&args[1])?.as_i64()?;
+                let y = self.eval_expr(&args[2])?.as_i64()?;
+                let z = self.eval_expr(&args[3])?.as_i64()?;
+                let r = self.read_mat4(&m)?;
+                let r = match name {
+                    "Mat4x4TranslationEqu" => gr3d::mat4x4_translation_equ(&r, x, y, z),
+                    _ => gr3d::mat4x4_translation_add(&r, x, y, z),
+                };
+                self.write_mat4(&m, &r)?;
+                Ok(m)
+            }
+            "Mat4x4MulMat4x4Equ" => {
+                if args.len() != 3 {
+                    return Err("Mat4x4MulMat4x4Equ(dst, m1, m2) expects 3 args".to_string());
+                }
+                let dst = self.eval_expr(&args[0])?;
+                let m1 = self.eval_expr(&args[1])?;
+                let m2 = self.eval_expr(&args[2])?;
+                let r = gr3d::mat4x4_mul(&self.read_mat4(&m1)?, &self.read_mat4(&m2)?);
+                self.write_mat4(&dst, &r)?;
+                Ok(dst)
+            }
+            "Mat4x4MulXYZ" => {
+                if args.len() != 4 {
+                    return Err("Mat4x4MulXYZ(r, _x, _y, _z) expects 4 args".to_string());
+                }
+                let m = self.eval_expr(&args[0])?;
+                let r = self.read_mat4(&m)?;
+                let mut ptrs = Vec::with_capacity(3);
+                let mut v = [0i64; 3];
+                for (slot, e) in v.iter_mut().zip(&args[1..]) {
+                    let ptr = self.eval_expr(e)?;
+                    *slot = self.read_i64_through(&ptr)?;
+                    ptrs.push(ptr);
+                }
+                let (x, y, z) = gr3d::mat4x4_mul_xyz(&r, v[0], v[1], v[2]);
+                for (ptr, v) in ptrs.into_iter().zip([x, y, z]) {
+                    self.write_i64_through(ptr, v)?;
+                }
                 Ok(Value::Void)
             }
             _ => Err(format!("internal: call_builtin_gfx cannot handle {name}")),
         }
     }
+
+    /// A `CD3I32 *` argument's (x, y, z).
+    fn eval_d3(&mut self, name: &str, expr: &Expr) -> Result<(i64, i64, i64), String> {
+        let obj = match self.eval_expr(expr)? {
+            Value::Obj(obj) => obj,
+            Value::ObjFieldRef { obj, field } => match obj.borrow().fields.get(&field).cloned() {
+                Some(Value::Obj(inner)) => inner,
+                _ => return Err(format!("{name}: expected &obj_field with x/y/z")),
+            },
+            Value::VarRef(var) => match self.env.get(&var) {
+                Some(Value::Obj(obj)) => obj,
+                _ => return Err(format!("{name}: expected object")),
+            },
+            _ => return Err(format!("{name}: expected object pointer")),
+        };
+        let o = obj.borrow();
+        let get = |k: &str| o.fields.get(k).and_then(|v| v.as_i64().ok()).unwrap_or(0);
+        Ok((get("x"), get("y"), get("z")))
+    }
+
+    fn read_i64_through(&self, ptr: &Value) -> Result<i64, String> {
+        match ptr {
+            Value::VarRef(name) => self
+                .env
+                .get(name)
+                .ok_or_else(|| format!("unknown variable {name}"))?
+                .as_i64(),
+            Value::Ptr { addr, elem_bytes } => self.heap_read_i64_le(*addr, (*elem_bytes).max(1)),
+            Value::ObjFieldRef { obj, field } => {
+                obj.borrow().fields.get(field).map_or(Ok(0), |v| v.as_i64())
+            }
+            other => Err(format!(
+                "Mat4x4MulXYZ: expected an I64 pointer, got {other:?}"
+            )),
+        }
+    }
+
+    /// The bytes of `path`, with `.ext` added when it has no extension, falling back to the
+    /// `.Z` file; `None` when neither exists.
+    fn read_image_file(&self, path: &str, ext: &str) -> Result<Option<Vec<u8>>, String> {
+        let path = if has_extension(path) {
+            path.to_string()
+        } else {
+            format!("{path}.{ext}")
+        };
+        for candidate in [path.clone(), format!("{path}.Z")] {
+            let host_path = self.resolve_temple_fs_target_read(&candidate)?;
+            match std::fs::read(&host_path) {
+                Ok(bytes) => return Ok(Some(bytes)),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(format!("{}: {err}", host_path.display())),
+            }
+        }
+        Ok(None)
+    }
+
+    fn write_i64_through(&mut self, ptr: Value, value: i64) -> Result<(), String> {
+        match ptr {
+            Value::VarRef(name) => self.env.assign(&name, Value::Int(value)),
+            Value::Ptr { addr, elem_bytes } => {
+                self.heap_write_i64_le(addr, elem_bytes.max(1), value)
+            }
+            Value::ObjFieldRef { obj, field } => {
+                obj.borrow_mut().fields.insert(field, Value::Int(value));
+                Ok(())
+            }
+            other => Err(format!(
+                "Mat4x4MulXYZ: expected an I64 pointer, got {other:?}"
+            )),
+        }
+    }
+}
+
+/// `GRWrite`/`GRRead` flags.
+const DCSF_COMPRESSED: i64 = 1;
+const DCSF_PALETTE_GET: i64 = 2;
+
+/// Whether the last path component has a `.` extension.
+fn has_extension(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.rfind('.').is_some_and(|i| i > 0)
 }
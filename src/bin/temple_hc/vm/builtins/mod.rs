@@ -2,6 +2,7 @@ use super::prelude::*;
 use super::{Value, Vm};
 
 mod core;
+mod doc;
 mod doc_fs_settings;
 mod gfx;
 mod linux;
@@ -51,11 +52,48 @@ impl Vm {
 
         if name.starts_with("Gr")
             || name.starts_with("Sprite")
-            || matches!(name, "DCDepthBufAlloc" | "D3I32Norm")
+            || name.starts_with("Mat4x4")
+            || matches!(
+                name,
+                "DCNew"
+                    | "DCDel"
+                    | "DCFill"
+                    | "DCClear"
+                    | "DCExtract"
+                    | "DCCopy"
+                    | "DCDepthBufAlloc"
+                    | "DCDepthBufRst"
+                    | "D3I32Norm"
+                    | "GRRead"
+                    | "GRWrite"
+                    | "BMPRead"
+            )
         {
             return self.call_builtin_gfx(name, args);
         }
 
+        if matches!(
+            name,
+            "DocNew"
+                | "DocDel"
+                | "DocPut"
+                | "DocDisplay"
+                | "DocClear"
+                | "DocTop"
+                | "DocBottom"
+                | "DocPrint"
+                | "Print"
+                | "DocLoad"
+                | "DocSave"
+                | "DocRead"
+                | "DocWrite"
+                | "DocInsDoc"
+                | "DocEntryDel"
+                | "DocRecalc"
+        ) {
+            return self.call_builtin_doc(name, args);
+        }
+
         if name.starts_with("Doc")
             || name.starts_with("Win")
             || name.starts_with("Reg")
@@ -66,16 +104,20 @@ impl Vm {
                 "Cd" | "FileFind"
                     | "DirMk"
                     | "PopUpOk"
+                    | "PopUpForm"
+                    | "Dir"
+                    | "View"
+                    | "Ed"
+                    | "Type"
+                    | "KeyMap"
                     | "AutoComplete"
                     | "Spawn"
                     | "PutExcept"
                     | "PressAKey"
                     | "GetStr"
                     | "ClipPutS"
-                    | "DCFill"
                     | "DCAlias"
                     | "DCSymmetrySet"
-                    | "DCDel"
             )
         {
             return self.call_builtin_doc_fs_settings(name, args);
@@ -187,6 +187,7 @@ impl Vm {
                     }
                 };
 
+                self.macro_input_wait()?;
                 // Flush at least once before we block so the user sees the current frame.
                 self.present_with_overlays()?;
                 let mut last_present = std::time::Instant::now();
@@ -252,6 +253,7 @@ impl Vm {
                     }
                 }
 
+                self.macro_input_wait()?;
                 // Flush at least once before we block so the user sees the current frame.
                 self.present_with_overlays()?;
                 let mut last_present = std::time::Instant::now();
@@ -373,6 +375,7 @@ impl Vm {
                 };
                 let mask = mask_i64 as u64;
 
+                self.macro_input_wait()?;
                 loop {
                     self.poll_events()?;
                     if let Some(msg) = self.scan_msg_mask(mask) {
@@ -11,17 +11,20 @@ use env::{ControlFlow, Env, EnvScopeGuard, VmPanic};
 
 #[path = "02_ui_types.rs"]
 mod ui_types;
-use ui_types::{MenuAction, MenuGroup, MenuItem, MenuState, MenuUnderlay, TempleMsg};
+use ui_types::{
+    MenuAction, MenuGroup, MenuItem, MenuState, MenuUnderlay, TempleMsg, TextBlink, TextLink,
+};
 
 #[path = "03_vm_struct.rs"]
 mod vm_struct;
-pub(super) use vm_struct::Vm;
+pub(super) use vm_struct::{Vm, VmLimits};
 
 #[path = "04_init.rs"]
 mod init;
 
 #[path = "05_heap_doldoc_rng.rs"]
 mod heap_doldoc_rng;
+use heap_doldoc_rng::Heap;
 
 #[path = "06_linux_bridge.rs"]
 mod linux_bridge;
@@ -41,3 +44,16 @@ mod call;
 
 #[path = "10_text.rs"]
 mod text;
+
+#[path = "12_input_log.rs"]
+mod input_log;
+use input_log::InputStamp;
+
+#[path = "13_shell_requests.rs"]
+mod shell_requests;
+
+#[path = "14_doc.rs"]
+mod doc;
+
+#[path = "15_dc.rs"]
+mod dc;
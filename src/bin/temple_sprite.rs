@@ -0,0 +1,1043 @@
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process, thread,
+    time::Duration,
+};
+
+use temple_rt::{
+    doldoc,
+    image::{self, Dither},
+    protocol,
+    rt::{Event, TempleRt, is_read_only_templeos_path},
+    sprite::{self, SpriteBounds, SpriteElem},
+};
+
+const UI_BAR_H: i32 = 16;
+const LIST_W: i32 = 224;
+const SELECT_COLOR: u8 = 14;
+const AXIS_COLOR: u8 = 8;
+
+fn print_usage() {
+    eprintln!("temple-sprite [--bin N] <file>");
+    eprintln!("temple-sprite import [--dither none|ordered|fs] <image.png|.bmp> <out>");
+    eprintln!("temple-sprite export [--bin N] <file> <out.png>");
+    eprintln!();
+    eprintln!(
+        "Edits the sprite in `$SP$` bin N (default: the first) of a DolDoc (.DD, .HC...), or a"
+    );
+    eprintln!(
+        "standalone sprite file holding just the element bytes. Saving (S) writes it back in"
+    );
+    eprintln!("place; a missing standalone file starts empty.");
+    eprintln!();
+    eprintln!("Tools: V select/move  P point  L line  G polygon (Enter closes)  C circle");
+    eprintln!("       B bitmap  T text (Enter ends)");
+    eprintln!("Keys:  ,/. color  +/- thickness  arrows move  PgUp/PgDn select  Del delete");
+    eprintln!("       Esc saves and quits  Shift+Esc quits without saving");
+    eprintln!();
+    eprintln!(
+        "import quantizes a PNG or BMP to the 16 colors as one bitmap: a .GR file, a new `$SP$`"
+    );
+    eprintln!("at the end of a .DD, or else a standalone sprite. export renders a sprite to PNG.");
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("temple-sprite: {msg}");
+    print_usage();
+    process::exit(2);
+}
+
+fn has_ext(path: &Path, ext: &str) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(ext))
+}
+
+fn import(mut args: impl Iterator<Item = String>) -> io::Result<()> {
+    let mut dither = Dither::Nearest;
+    let mut paths = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dither" => {
+                dither = args
+                    .next()
+                    .and_then(|name| Dither::from_name(&name))
+                    .unwrap_or_else(|| usage_error("--dither expects none, ordered or fs"));
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    let [input, out] = <[PathBuf; 2]>::try_from(paths)
+        .unwrap_or_else(|_| usage_error("import expects an image and an output file"));
+    if is_read_only_templeos_path(&out) {
+        eprintln!(
+            "temple-sprite: {}: read-only (TempleOS tree)",
+            out.display()
+        );
+        process::exit(1);
+    }
+
+    let bytes = fs::read(&input)?;
+    let canvas = if bytes.starts_with(b"BM") {
+        image::decode_bmp(&bytes, dither)
+    } else {
+        image::decode_png(&bytes).map(|(w, h, rgba)| image::quantize(w, h, &rgba, dither))
+    }
+    .map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", input.display())))?;
+    let bytes = if has_ext(&out, "GR") {
+        image::encode_gr(&canvas, true, None)
+    } else if has_ext(&out, "DD") {
+        let doc = match fs::read(&out) {
+            Ok(doc) => Some(doc),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        append_sprite(doc.as_deref(), image::bitmap_sprite(&canvas))
+    } else {
+        image::bitmap_sprite(&canvas)
+    };
+    fs::write(&out, bytes)?;
+    println!("{}", out.display());
+    Ok(())
+}
+
+/// `doc` (or an empty document) with `sprite` in a new bin, shown on a line of its own at the end.
+fn append_sprite(doc: Option<&[u8]>, sprite: Vec<u8>) -> Vec<u8> {
+    let doc = doc.unwrap_or_default();
+    let cutoff = doc.iter().position(|&b| b == 0).unwrap_or(doc.len());
+    let (text, _) = doldoc::parse_doc_blob(doc);
+    // Past every stored bin, referenced or not, and every number a command uses.
+    let bin = doldoc::stored_bin_nums(doc)
+        .into_iter()
+        .chain(doldoc::referenced_bins(&text))
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let mut text = doc[..cutoff].to_vec();
+    if !text.is_empty() && !text.ends_with(b"\n") {
+        text.push(b'\n');
+    }
+    text.extend_from_slice(format!("$SP,\"\",BI={bin}$\n").as_bytes());
+    doldoc::update_doc_bins(doc, text, &BTreeMap::from([(bin, sprite)]))
+}
+
+fn export(mut args: impl Iterator<Item = String>) -> io::Result<()> {
+    let mut bin = None;
+    let mut paths = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bin" => {
+                let n = args.next().and_then(|n| n.parse().ok());
+                bin = Some(n.unwrap_or_else(|| usage_error("--bin expects a number")));
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    let [input, out] = <[PathBuf; 2]>::try_from(paths)
+        .unwrap_or_else(|_| usage_error("export expects a sprite file and a PNG"));
+
+    let buf = fs::read(&input)?;
+    let elems = open_sprite(Some(&buf), bin)
+        .map(|(_, elems)| elems)
+        .map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {err}", input.display()),
+            )
+        })?;
+    let png = doldoc::sprite_png(&sprite::sprite_elem_bytes(&elems)).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: the sprite draws nothing", input.display()),
+        )
+    })??;
+    fs::write(&out, png)?;
+    println!("{}", out.display());
+    Ok(())
+}
+
+/// Where the sprite came from, to save it back there.
+#[derive(Debug)]
+enum Source {
+    /// Bin `bin` of a DolDoc, kept as last read or saved so everything else in it is written
+    /// back byte for byte.
+    Doc {
+        original: Vec<u8>,
+        bin: u32,
+    },
+    File,
+}
+
+fn open_sprite(buf: Option<&[u8]>, bin: Option<u32>) -> Result<(Source, Vec<SpriteElem>), String> {
+    let Some(buf) = buf else {
+        return match bin {
+            Some(_) => Err("file not found".to_string()),
+            None => Ok((Source::File, Vec::new())),
+        };
+    };
+    let (doc_text, bins) = doldoc::parse_doc_blob(buf);
+    let referenced = doldoc::referenced_bins(&doc_text);
+    let Some(&first) = referenced.first() else {
+        if bin.is_some() {
+            return Err("no sprites in this document".to_string());
+        }
+        let elems = sprite::sprite_elems(buf).ok_or("not a sprite")?;
+        return Ok((Source::File, elems));
+    };
+    let bin = bin.or_else(|| first_sprite_bin(&doc_text)).unwrap_or(first);
+    if !referenced.contains(&bin) {
+        return Err(format!("no `$SP$` uses bin {bin}"));
+    }
+    // Empty sprites are not kept as bins: they start empty again.
+    let elems = match bins.get(&bin) {
+        Some(data) => sprite::sprite_elems(data).ok_or(format!("bin {bin} is not a sprite"))?,
+        None => Vec::new(),
+    };
+    let source = Source::Doc {
+        original: buf.to_vec(),
+        bin,
+    };
+    Ok((source, elems))
+}
+
+/// The `BI=` of the first `$SP$` command in `text`.
+fn first_sprite_bin(text: &str) -> Option<u32> {
+    let at = text.find("$SP")?;
+    let cmd = &text[at + 1..];
+    let cmd = &cmd[..cmd.find('$')?];
+    doldoc::referenced_bins(cmd).into_iter().next()
+}
+
+/// The file with the sprite saved in: for a document only the sprite's own bin changes.
+fn save_bytes(source: &Source, elems: &[SpriteElem]) -> Vec<u8> {
+    let bytes = sprite::sprite_elem_bytes(elems);
+    match source {
+        Source::Doc { original, bin } => {
+            let cutoff = original
+                .iter()
+                .position(|&b| b == 0)
+                .unwrap_or(original.len());
+            let bins = BTreeMap::from([(*bin, bytes)]);
+            doldoc::update_doc_bins(original, original[..cutoff].to_vec(), &bins)
+        }
+        Source::File => bytes,
+    }
+}
+
+/// Writes the sprite back where it came from; returns the status line.
+fn save(path: &Path, source: &mut Source, ed: &mut Editor) -> io::Result<String> {
+    if is_read_only_templeos_path(path) {
+        return Ok("Read-only (TempleOS tree): Shift+Esc quits".to_string());
+    }
+    let bytes = save_bytes(source, &ed.elems);
+    fs::write(path, &bytes)?;
+    if let Source::Doc { original, .. } = source {
+        *original = bytes;
+    }
+    ed.dirty = false;
+    Ok("Saved".to_string())
+}
+
+/// [`save`] for the editor: a failed write goes on the status line and the edits stay unsaved.
+fn save_status(path: &Path, source: &mut Source, ed: &mut Editor) -> String {
+    save(path, source, ed).unwrap_or_else(|err| format!("Save failed: {err}"))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tool {
+    Select,
+    Point,
+    Line,
+    Polygon,
+    Circle,
+    Bitmap,
+    Text,
+}
+
+impl Tool {
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Select => "Select",
+            Tool::Point => "Point",
+            Tool::Line => "Line",
+            Tool::Polygon => "Polygon",
+            Tool::Circle => "Circle",
+            Tool::Bitmap => "Bitmap",
+            Tool::Text => "Text",
+        }
+    }
+}
+
+/// The sprite being edited, in sprite coordinates, and the tool state.
+struct Editor {
+    elems: Vec<SpriteElem>,
+    selected: Option<usize>,
+    tool: Tool,
+    color: u8,
+    thick: i32,
+    /// Points clicked so far for the current shape.
+    pending: Vec<(i32, i32)>,
+    /// Text typed so far, placed at `pending[0]`.
+    text: Vec<u8>,
+    dirty: bool,
+}
+
+impl Editor {
+    fn new(elems: Vec<SpriteElem>) -> Self {
+        Self {
+            elems,
+            selected: None,
+            tool: Tool::Select,
+            color: 15,
+            thick: 1,
+            pending: Vec::new(),
+            text: Vec::new(),
+            dirty: false,
+        }
+    }
+
+    fn set_tool(&mut self, tool: Tool) {
+        self.tool = tool;
+        self.cancel();
+    }
+
+    fn cancel(&mut self) {
+        self.pending.clear();
+        self.text.clear();
+    }
+
+    fn typing(&self) -> bool {
+        self.tool == Tool::Text && !self.pending.is_empty()
+    }
+
+    /// The color and thickness in effect after the last element, as `sprite_render` starts them.
+    fn state_at_end(&self) -> (u8, i32) {
+        let mut state = (15, 1);
+        for elem in &self.elems {
+            match elem {
+                SpriteElem::Color(c) | SpriteElem::DitherColor(c, _) => state.0 = *c,
+                SpriteElem::Thick(t) => state.1 = *t,
+                _ => {}
+            }
+        }
+        state
+    }
+
+    /// Appends `shape`, after the `SPT_COLOR`/`SPT_THICK` it needs, and selects it.
+    fn add(&mut self, shape: SpriteElem) {
+        let (color, thick) = self.state_at_end();
+        if color != self.color {
+            self.elems.push(SpriteElem::Color(self.color));
+        }
+        let thick_matters = matches!(
+            shape,
+            SpriteElem::Line { .. } | SpriteElem::PolyLine(_) | SpriteElem::Circle { .. }
+        );
+        if thick_matters && thick != self.thick {
+            self.elems.push(SpriteElem::Thick(self.thick));
+        }
+        self.elems.push(shape);
+        self.selected = Some(self.elems.len() - 1);
+        self.dirty = true;
+        self.cancel();
+    }
+
+    fn click(&mut self, (x, y): (i32, i32)) {
+        match self.tool {
+            Tool::Select => self.selected = self.hit(x, y),
+            Tool::Point => self.add(SpriteElem::Pt { x, y }),
+            Tool::Polygon => self.pending.push((x, y)),
+            Tool::Text if self.pending.is_empty() => self.pending.push((x, y)),
+            Tool::Text => {}
+            Tool::Line | Tool::Circle | Tool::Bitmap => {
+                let Some(&(x1, y1)) = self.pending.first() else {
+                    self.pending.push((x, y));
+                    return;
+                };
+                let shape = match self.tool {
+                    Tool::Line => SpriteElem::Line {
+                        x1,
+                        y1,
+                        x2: x,
+                        y2: y,
+                    },
+                    Tool::Circle => {
+                        let r = (((x - x1).pow(2) + (y - y1).pow(2)) as f64).sqrt().round();
+                        SpriteElem::Circle {
+                            x: x1,
+                            y: y1,
+                            r: r as i32,
+                        }
+                    }
+                    _ => {
+                        let (width, height) = ((x - x1).abs() + 1, (y - y1).abs() + 1);
+                        SpriteElem::Bitmap {
+                            x: x1.min(x),
+                            y: y1.min(y),
+                            width,
+                            height,
+                            body: vec![self.color; ((width + 7) / 8 * 8 * height) as usize],
+                        }
+                    }
+                };
+                self.add(shape);
+            }
+        }
+    }
+
+    /// Enter: closes the polygon or ends the text.
+    fn finish(&mut self) {
+        match self.tool {
+            Tool::Polygon if self.pending.len() >= 2 => {
+                let mut pts = std::mem::take(&mut self.pending);
+                if pts.len() >= 3 {
+                    pts.push(pts[0]);
+                }
+                self.add(SpriteElem::PolyLine(pts));
+            }
+            Tool::Text if !self.pending.is_empty() && !self.text.is_empty() => {
+                let (x, y) = self.pending[0];
+                let text = std::mem::take(&mut self.text);
+                self.add(SpriteElem::Text { x, y, text });
+            }
+            _ => {}
+        }
+    }
+
+    fn type_char(&mut self, ch: u8) {
+        self.text.push(ch);
+    }
+
+    fn backspace(&mut self) {
+        self.text.pop();
+    }
+
+    /// Where each element is drawn, after the `SPT_SHIFT`s before it.
+    fn bounds(&self) -> Vec<Option<SpriteBounds>> {
+        let (mut sx, mut sy) = (0, 0);
+        self.elems
+            .iter()
+            .map(|elem| {
+                if let SpriteElem::Shift { x, y } = elem {
+                    (sx, sy) = (sx + x, sy + y);
+                    return None;
+                }
+                let mut shifted = elem.clone();
+                shifted.translate(sx, sy);
+                sprite::sprite_bounds(&sprite::sprite_elem_bytes(&[shifted]))
+                    .filter(|b| b.width() > 0 && b.height() > 0)
+            })
+            .collect()
+    }
+
+    /// The topmost element drawn over (`x`, `y`).
+    fn hit(&self, x: i32, y: i32) -> Option<usize> {
+        self.bounds().iter().rposition(|b| {
+            b.is_some_and(|b| x >= b.x0 - 1 && x <= b.x1 && y >= b.y0 - 1 && y <= b.y1)
+        })
+    }
+
+    fn move_selected(&mut self, dx: i32, dy: i32) {
+        if let Some(elem) = self.selected.and_then(|i| self.elems.get_mut(i)) {
+            elem.translate(dx, dy);
+            self.dirty = true;
+        }
+    }
+
+    fn delete_selected(&mut self) {
+        let Some(i) = self.selected.filter(|&i| i < self.elems.len()) else {
+            return;
+        };
+        self.elems.remove(i);
+        self.selected = if self.elems.is_empty() {
+            None
+        } else {
+            Some(i.min(self.elems.len() - 1))
+        };
+        self.dirty = true;
+    }
+
+    fn select_step(&mut self, delta: isize) {
+        if self.elems.is_empty() {
+            return;
+        }
+        let last = self.elems.len() as isize - 1;
+        let i = match self.selected {
+            Some(i) => (i as isize + delta).clamp(0, last),
+            None if delta < 0 => last,
+            None => 0,
+        };
+        self.selected = Some(i as usize);
+    }
+}
+
+/// One line of the element list.
+fn describe(elem: &SpriteElem) -> String {
+    match elem {
+        SpriteElem::Color(c) => format!("Color {c}"),
+        SpriteElem::DitherColor(c0, c1) => format!("Dither {c0}/{c1}"),
+        SpriteElem::Thick(t) => format!("Thick {t}"),
+        SpriteElem::Shift { x, y } => format!("Shift {x},{y}"),
+        SpriteElem::Pt { x, y } => format!("Pt {x},{y}"),
+        SpriteElem::Line { x1, y1, x2, y2 } => format!("Line {x1},{y1} {x2},{y2}"),
+        SpriteElem::Rect { x1, y1, x2, y2 } => format!("Rect {x1},{y1} {x2},{y2}"),
+        SpriteElem::Arrow { x1, y1, x2, y2 } => format!("Arrow {x1},{y1} {x2},{y2}"),
+        SpriteElem::PolyLine(pts) => format!("PolyLine {} pts", pts.len()),
+        SpriteElem::Circle { x, y, r } => format!("Circle {x},{y} r{r}"),
+        SpriteElem::Bitmap {
+            x,
+            y,
+            width,
+            height,
+            ..
+        } => format!("Bitmap {x},{y} {width}x{height}"),
+        SpriteElem::Text { x, y, text }
+        | SpriteElem::TextBox { x, y, text }
+        | SpriteElem::TextDiamond { x, y, text } => {
+            format!("Text {x},{y} \"{}\"", String::from_utf8_lossy(text))
+        }
+        SpriteElem::PlanarSymmetry { .. } => "Symmetry".to_string(),
+        SpriteElem::TransformOn => "Transform on".to_string(),
+        SpriteElem::TransformOff => "Transform off".to_string(),
+        SpriteElem::PolyPt { x, y, steps } => format!("PolyPt {x},{y} {} steps", steps.len()),
+        SpriteElem::RotatedRect { x1, y1, x2, y2, .. } => format!("RotRect {x1},{y1} {x2},{y2}"),
+        SpriteElem::Ellipse {
+            x,
+            y,
+            width,
+            height,
+            ..
+        } => format!("Ellipse {x},{y} {width}x{height}"),
+        SpriteElem::Polygon { x, y, sides, .. } => format!("Polygon {x},{y} {sides} sides"),
+        SpriteElem::BSpline2(pts)
+        | SpriteElem::BSpline2Closed(pts)
+        | SpriteElem::BSpline3(pts)
+        | SpriteElem::BSpline3Closed(pts) => format!("BSpline {} pts", pts.len()),
+        SpriteElem::FloodFill { x, y } | SpriteElem::FloodFillNot { x, y } => {
+            format!("Fill {x},{y}")
+        }
+        SpriteElem::Mesh { vertices, tris } | SpriteElem::ShiftableMesh { vertices, tris, .. } => {
+            format!("Mesh {} verts {} tris", vertices.len(), tris.len())
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut args = env::args().skip(1).peekable();
+    match args.peek().map(String::as_str) {
+        Some("import") => return import(args.skip(1)),
+        Some("export") => return export(args.skip(1)),
+        _ => {}
+    }
+    let mut path: Option<PathBuf> = None;
+    let mut bin: Option<u32> = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" | "-h" => {
+                print_usage();
+                return Ok(());
+            }
+            "--bin" => {
+                let n = args.next().and_then(|n| n.parse().ok());
+                bin = Some(n.unwrap_or_else(|| usage_error("--bin expects a number")));
+            }
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => usage_error(&format!("unexpected arg: {arg}")),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage_error("missing file"));
+
+    let buf = match fs::read(&path) {
+        Ok(buf) => Some(buf),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => {
+            return Err(io::Error::new(
+                err.kind(),
+                format!("{}: {err}", path.display()),
+            ));
+        }
+    };
+    let (source, elems) = match open_sprite(buf.as_deref(), bin) {
+        Ok(opened) => opened,
+        Err(err) => {
+            eprintln!("temple-sprite: {}: {err}", path.display());
+            process::exit(1);
+        }
+    };
+
+    match run(&path, source, elems) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+fn draw(rt: &mut TempleRt, ed: &Editor, path: &Path, origin: (i32, i32), status: &str) {
+    let (w, h) = rt.size();
+    let (w, h) = (w as i32, h as i32);
+    let canvas_w = w - LIST_W;
+    let (ox, oy) = origin;
+
+    rt.clear(0);
+    rt.fill_rect(0, 0, w, UI_BAR_H, 4);
+    let title = format!(
+        "Temple Sprite - {}{}",
+        path.display(),
+        if ed.dirty { " *" } else { "" }
+    );
+    rt.draw_text(4, 4, 15, 4, &title);
+
+    rt.set_clip_rect(0, UI_BAR_H, canvas_w, h - UI_BAR_H);
+    rt.fill_rect(0, oy, canvas_w, 1, AXIS_COLOR);
+    rt.fill_rect(ox, UI_BAR_H, 1, h - UI_BAR_H, AXIS_COLOR);
+    sprite::sprite_render(rt, ox, oy, &sprite::sprite_elem_bytes(&ed.elems));
+    if let Some(b) = ed
+        .selected
+        .and_then(|i| ed.bounds().get(i).copied().flatten())
+    {
+        rt.draw_rect_outline(
+            ox + b.x0 - 1,
+            oy + b.y0 - 1,
+            b.width() + 2,
+            b.height() + 2,
+            SELECT_COLOR,
+        );
+    }
+    for &(x, y) in &ed.pending {
+        rt.fill_rect(ox + x - 1, oy + y - 1, 3, 3, SELECT_COLOR);
+    }
+    if let Some(&(x, y)) = ed.pending.first().filter(|_| ed.typing()) {
+        let text = format!("{}_", String::from_utf8_lossy(&ed.text));
+        rt.draw_text(ox + x, oy + y, ed.color, 0, &text);
+    }
+    rt.reset_clip_rect();
+
+    rt.fill_rect(canvas_w, UI_BAR_H, LIST_W, h - UI_BAR_H, 1);
+    let rows = ((h - UI_BAR_H - 24) / 8).max(1) as usize;
+    let first = ed.selected.unwrap_or(0).saturating_sub(rows - 1);
+    for (row, (i, elem)) in ed
+        .elems
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(rows)
+        .enumerate()
+    {
+        let (fg, bg) = if ed.selected == Some(i) {
+            (1, 15)
+        } else {
+            (15, 1)
+        };
+        let line = format!("{i:3} {}", describe(elem));
+        let line: String = line.chars().take((LIST_W / 8 - 1) as usize).collect();
+        rt.draw_text(canvas_w + 4, UI_BAR_H + 4 + row as i32 * 8, fg, bg, &line);
+    }
+
+    let info = format!(
+        "{}  Color {}  Thick {}  {status}",
+        ed.tool.name(),
+        ed.color,
+        ed.thick
+    );
+    rt.fill_rect(0, h - 10, canvas_w, 10, 0);
+    rt.fill_rect(4, h - 9, 8, 8, ed.color);
+    rt.draw_text(16, h - 9, 15, 0, &info);
+}
+
+fn run(path: &Path, mut source: Source, elems: Vec<SpriteElem>) -> io::Result<()> {
+    let mut rt = TempleRt::connect()?;
+    let (w, h) = rt.size();
+    let origin = ((w as i32 - LIST_W) / 2, (h as i32 + UI_BAR_H) / 2);
+    let to_sprite = |x: u32, y: u32| (x as i32 - origin.0, y as i32 - origin.1);
+
+    let mut ed = Editor::new(elems);
+    let mut status = String::new();
+    let mut mouse: Option<(i32, i32)> = None;
+    let mut drag: Option<(i32, i32)> = None;
+    let mut shift = false;
+
+    loop {
+        draw(&mut rt, &ed, path, origin, &status);
+        rt.present()?;
+
+        while let Some(ev) = rt.try_next_event() {
+            match ev {
+                Event::Key {
+                    code: protocol::KEY_SHIFT,
+                    down,
+                } => shift = down,
+                Event::Key { code, down: true } => {
+                    status.clear();
+                    match code {
+                        // TempleOS: Esc saves and exits, Shift+Esc aborts.
+                        protocol::KEY_ESCAPE if shift => return Ok(()),
+                        protocol::KEY_ESCAPE if !ed.pending.is_empty() => ed.cancel(),
+                        protocol::KEY_ESCAPE => {
+                            if ed.dirty {
+                                status = save_status(path, &mut source, &mut ed);
+                            }
+                            if !ed.dirty {
+                                return Ok(());
+                            }
+                        }
+                        protocol::KEY_ENTER => ed.finish(),
+                        protocol::KEY_BACKSPACE if ed.typing() => ed.backspace(),
+                        protocol::KEY_DELETE | protocol::KEY_BACKSPACE => ed.delete_selected(),
+                        protocol::KEY_PAGE_UP => ed.select_step(-1),
+                        protocol::KEY_PAGE_DOWN => ed.select_step(1),
+                        protocol::KEY_LEFT => ed.move_selected(-1, 0),
+                        protocol::KEY_RIGHT => ed.move_selected(1, 0),
+                        protocol::KEY_UP => ed.move_selected(0, -1),
+                        protocol::KEY_DOWN => ed.move_selected(0, 1),
+                        _ if (0x20..=0xFF).contains(&code) && ed.typing() => {
+                            ed.type_char(code as u8)
+                        }
+                        _ if ed.typing() => {}
+                        _ if code <= 0xFF => match (code as u8).to_ascii_lowercase() {
+                            b'v' => ed.set_tool(Tool::Select),
+                            b'p' => ed.set_tool(Tool::Point),
+                            b'l' => ed.set_tool(Tool::Line),
+                            b'g' => ed.set_tool(Tool::Polygon),
+                            b'c' => ed.set_tool(Tool::Circle),
+                            b'b' => ed.set_tool(Tool::Bitmap),
+                            b't' => ed.set_tool(Tool::Text),
+                            b',' => ed.color = (ed.color + 15) % 16,
+                            b'.' => ed.color = (ed.color + 1) % 16,
+                            b'+' | b'=' => ed.thick = (ed.thick + 1).min(32),
+                            b'-' => ed.thick = (ed.thick - 1).max(1),
+                            b's' => status = save_status(path, &mut source, &mut ed),
+                            _ => {}
+                        },
+                        _ => {}
+                    }
+                }
+                Event::Key { .. } => {}
+                Event::MouseMove { x, y } => {
+                    let pt = to_sprite(x, y);
+                    if let Some((dx, dy)) = drag {
+                        ed.move_selected(pt.0 - dx, pt.1 - dy);
+                        drag = Some(pt);
+                    }
+                    mouse = Some(pt);
+                }
+                Event::MouseButton { button, down } => {
+                    if button != protocol::MOUSE_BUTTON_LEFT {
+                        continue;
+                    }
+                    drag = None;
+                    let Some(pt) = mouse.filter(|&(x, y)| {
+                        x + origin.0 < w as i32 - LIST_W && y + origin.1 >= UI_BAR_H
+                    }) else {
+                        continue;
+                    };
+                    if down {
+                        ed.click(pt);
+                        if ed.tool == Tool::Select && ed.selected.is_some() {
+                            drag = Some(pt);
+                        }
+                    }
+                }
+                Event::MouseWheel { .. } | Event::MouseEnter => {}
+                Event::MouseLeave => {
+                    mouse = None;
+                    drag = None;
+                }
+            }
+        }
+
+        thread::sleep(Duration::from_millis(16));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    #[test]
+    fn tools_add_colored_elements_that_can_be_moved_and_deleted() {
+        let mut ed = Editor::new(Vec::new());
+        ed.color = 4;
+        ed.thick = 2;
+        ed.set_tool(Tool::Line);
+        ed.click((0, 0));
+        ed.click((10, 0));
+        ed.set_tool(Tool::Polygon);
+        for pt in [(0, 0), (8, 0), (4, 6)] {
+            ed.click(pt);
+        }
+        ed.finish();
+        ed.set_tool(Tool::Circle);
+        ed.click((20, 20));
+        ed.click((23, 24));
+        ed.color = 2;
+        ed.set_tool(Tool::Bitmap);
+        ed.click((1, 1));
+        ed.click((2, 3));
+        ed.set_tool(Tool::Text);
+        ed.click((0, 30));
+        for &ch in b"Hi!" {
+            ed.type_char(ch);
+        }
+        ed.backspace();
+        ed.finish();
+
+        assert_eq!(
+            ed.elems,
+            vec![
+                SpriteElem::Color(4),
+                SpriteElem::Thick(2),
+                SpriteElem::Line {
+                    x1: 0,
+                    y1: 0,
+                    x2: 10,
+                    y2: 0
+                },
+                SpriteElem::PolyLine(vec![(0, 0), (8, 0), (4, 6), (0, 0)]),
+                SpriteElem::Circle { x: 20, y: 20, r: 5 },
+                SpriteElem::Color(2),
+                SpriteElem::Bitmap {
+                    x: 1,
+                    y: 1,
+                    width: 2,
+                    height: 3,
+                    body: vec![2; 24]
+                },
+                SpriteElem::Text {
+                    x: 0,
+                    y: 30,
+                    text: b"Hi".to_vec()
+                },
+            ]
+        );
+
+        ed.set_tool(Tool::Select);
+        ed.click((21, 25));
+        assert_eq!(ed.selected, Some(4));
+        ed.move_selected(-20, -20);
+        assert_eq!(ed.elems[4], SpriteElem::Circle { x: 0, y: 0, r: 5 });
+        ed.select_step(-1);
+        ed.delete_selected();
+        assert_eq!(ed.elems.len(), 7);
+        assert_eq!(ed.selected, Some(3));
+        assert!(ed.dirty);
+    }
+
+    #[test]
+    fn sprites_save_back_into_their_document() {
+        let mut bins = BTreeMap::new();
+        bins.insert(1, vec![0]);
+        bins.insert(
+            2,
+            sprite::sprite_elem_bytes(&[SpriteElem::Pt { x: 1, y: 2 }]),
+        );
+        let text = b"Intro \xB0 $SP,\"\",BI=2$ and $SP,\"\",BI=1$\n";
+        let doc = doldoc::encode_doc_blob(text, &bins);
+
+        let (source, mut elems) = open_sprite(Some(&doc), None).expect("open");
+        assert_eq!(elems, vec![SpriteElem::Pt { x: 1, y: 2 }]);
+        elems.push(SpriteElem::Circle { x: 0, y: 0, r: 3 });
+        let saved = save_bytes(&source, &elems);
+        assert_eq!(&saved[..text.len()], text);
+
+        let (_, back) = open_sprite(Some(&saved), Some(2)).expect("reopen");
+        assert_eq!(back, elems);
+        let (_, other) = open_sprite(Some(&saved), Some(1)).expect("bin 1");
+        assert!(other.is_empty());
+        assert!(open_sprite(Some(&saved), Some(3)).is_err());
+
+        let (source, elems) = open_sprite(None, None).expect("new file");
+        assert!(matches!(source, Source::File) && elems.is_empty());
+        let raw = sprite::sprite_elem_bytes(&[SpriteElem::Color(3)]);
+        let (_, elems) = open_sprite(Some(&raw), None).expect("standalone");
+        assert_eq!(elems, vec![SpriteElem::Color(3)]);
+    }
+
+    #[test]
+    fn saving_a_sprite_leaves_the_other_bins_as_stored() {
+        let pt = sprite::sprite_elem_bytes(&[SpriteElem::Pt { x: 1, y: 2 }]);
+        let text = b"$SP,\"\",BI=1$ $SP,\"\",BI=2$\n";
+        let mut doc = text.to_vec();
+        doc.push(0);
+        // Bin 1 with a use count of 3, bin 2, then bin 7 that no `$SP$` refers to.
+        for (num, use_cnt, data) in [
+            (1u32, 3u32, &pt[..]),
+            (2, 1, &pt[..]),
+            (7, 1, &[0xAB, 0][..]),
+        ] {
+            for v in [num, 0, data.len() as u32, use_cnt] {
+                doc.extend_from_slice(&v.to_le_bytes());
+            }
+            doc.extend_from_slice(data);
+        }
+        let record_len = 16 + pt.len();
+        let start = text.len() + 1;
+
+        let (source, mut elems) = open_sprite(Some(&doc), Some(2)).expect("open");
+        elems.push(SpriteElem::Circle { x: 0, y: 0, r: 3 });
+        let saved = save_bytes(&source, &elems);
+        assert_eq!(&saved[..start + record_len], &doc[..start + record_len]);
+        assert!(saved.ends_with(&doc[start + 2 * record_len..]));
+        let (_, back) = open_sprite(Some(&saved), Some(2)).expect("reopen");
+        assert_eq!(back, elems);
+        assert_eq!(doldoc::stored_bin_nums(&saved), BTreeSet::from([1, 2, 7]));
+    }
+
+    #[test]
+    fn saving_clears_dirty_but_never_writes_the_templeos_tree() {
+        let dir = env::temp_dir().join(format!("temple-sprite-save-{}", process::id()));
+        fs::create_dir_all(&dir).expect("mkdir");
+        let path = dir.join("Pt.GR");
+        let mut source = Source::File;
+        let mut ed = Editor::new(Vec::new());
+        ed.set_tool(Tool::Point);
+        ed.click((1, 1));
+        assert!(ed.dirty);
+
+        assert_eq!(save(&path, &mut source, &mut ed).expect("save"), "Saved");
+        assert!(!ed.dirty);
+        let saved = fs::read(&path).expect("read");
+
+        ed.click((2, 2));
+        unsafe { env::set_var("TEMPLEOS_ROOT", &dir) };
+        let status = save(&path, &mut source, &mut ed).expect("save");
+        unsafe { env::remove_var("TEMPLEOS_ROOT") };
+        assert!(status.starts_with("Read-only"), "{status}");
+        assert!(ed.dirty);
+        assert_eq!(fs::read(&path).expect("read"), saved);
+
+        let missing = dir.join("no-such-dir/Pt.GR");
+        let status = save_status(&missing, &mut source, &mut ed);
+        assert!(status.starts_with("Save failed: "), "{status}");
+        assert!(ed.dirty);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn pngs_import_as_quantized_bitmaps_and_export_back() {
+        let mut art = doldoc::Canvas::new(5, 3, 0xFF);
+        art.pixels[..10].copy_from_slice(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        art.pixels[12] = 15;
+        let png = doldoc::encode_png(&art, Some(0xFF)).expect("encode");
+
+        let sprite = image::png_to_sprite(&png, Dither::Nearest).expect("import");
+        let elems = sprite::sprite_elems(&sprite).expect("parse");
+        let [
+            SpriteElem::Bitmap {
+                width: 5,
+                height: 3,
+                body,
+                ..
+            },
+        ] = &elems[..]
+        else {
+            panic!("not one bitmap: {elems:?}");
+        };
+        assert_eq!(&body[..5], &art.pixels[..5]);
+        assert_eq!(body.len(), 24);
+        assert_eq!(
+            doldoc::sprite_png(&sprite).expect("draws").expect("png"),
+            png
+        );
+
+        let gr = image::encode_gr(&art, false, None);
+        assert_eq!(gr.len(), 32 + 8 * 3);
+        assert_eq!(&gr[16..28], &[5, 0, 0, 0, 8, 0, 0, 0, 3, 0, 0, 0]);
+        assert_eq!(&gr[32..40], &[0, 1, 2, 3, 4, 0xFF, 0xFF, 0xFF]);
+        let back = image::decode_gr(&gr).expect("decodes");
+        assert_eq!(back.image.pixels, art.pixels);
+        assert!(back.palette.is_none());
+    }
+
+    #[test]
+    fn compressed_gr_bodies_expand_back_even_after_the_table_fills() {
+        // Long enough to recycle table entries, with runs, repeats and noise.
+        let mut seed = 0x2545F491u32;
+        let mut data = Vec::new();
+        for i in 0..40_000u32 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            data.push(match i % 3000 {
+                0..=999 => (i / 7 % 16) as u8,
+                1000..=1999 => (seed % 5) as u8,
+                _ => seed as u8 & 0x7F,
+            });
+        }
+        for data in [data.clone(), data.iter().map(|b| b | 0x80).collect()] {
+            let z = image::compress(&data);
+            assert!(image::is_compressed(&z));
+            assert!(z.len() < data.len());
+            assert_eq!(image::expand(&z).expect("expands"), data);
+        }
+        let noise: Vec<u8> = (0..300u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        assert_eq!(image::expand(&image::compress(&noise)).unwrap(), noise);
+        assert!(image::expand(&image::compress(&data)[..100]).is_err());
+
+        let mut art = doldoc::Canvas::new(40, 30, temple_rt::rt::TRANSPARENT);
+        art.pixels.iter_mut().step_by(3).for_each(|px| *px = 4);
+        let gr = image::encode_gr(&art, true, None);
+        assert!(gr.len() < 32 + 40 * 30);
+        assert_eq!(image::decode_gr(&gr).unwrap().image.pixels, art.pixels);
+        let gr_z = image::compress(&image::encode_gr(&art, false, None));
+        assert_eq!(image::decode_image(&gr_z).unwrap().pixels, art.pixels);
+    }
+
+    #[test]
+    fn dithering_mixes_palette_colors_for_in_between_shades() {
+        let gray = [0x80u8, 0x80, 0x80, 0xFF].repeat(64);
+        let colors = |dither| {
+            let canvas = image::quantize(8, 8, &gray, dither);
+            let mut counts = [0usize; 16];
+            for &px in &canvas.pixels {
+                counts[px as usize] += 1;
+            }
+            (counts[7], counts[8])
+        };
+        assert_eq!(colors(Dither::Nearest), (64, 0));
+        assert_eq!(colors(Dither::Ordered), (32, 32));
+        let (light, dark) = colors(Dither::FloydSteinberg);
+        assert_eq!(light + dark, 64);
+        assert!((28..=36).contains(&light), "{light} light gray");
+    }
+
+    #[test]
+    fn imports_append_a_sprite_to_the_document() {
+        let mut bins = BTreeMap::new();
+        bins.insert(
+            2,
+            sprite::sprite_elem_bytes(&[SpriteElem::Pt { x: 0, y: 0 }]),
+        );
+        let doc = doldoc::encode_doc_blob(b"Title $SP,\"\",BI=2$", &bins);
+        let bitmap = sprite::sprite_elem_bytes(&[SpriteElem::Color(4)]);
+
+        let out = append_sprite(Some(&doc), bitmap.clone());
+        let (text, back) = doldoc::parse_doc_blob(&out);
+        assert_eq!(text, "Title $SP,\"\",BI=2$\n$SP,\"\",BI=3$\n");
+        assert_eq!(back.get(&3), Some(&bitmap));
+        assert_eq!(back.get(&2), bins.get(&2));
+
+        let (text, _) = doldoc::parse_doc_blob(&append_sprite(None, bitmap.clone()));
+        assert_eq!(text, "$SP,\"\",BI=1$\n");
+
+        // Stored bins are kept as they are, and numbers past them: bin 5 is unreferenced.
+        let mut doc = b"Title $SP,\"\",BI=2$\0".to_vec();
+        for (num, data) in [(2u32, bins[&2].clone()), (5, vec![0xAB, 0])] {
+            for v in [num, 0, data.len() as u32, 2] {
+                doc.extend_from_slice(&v.to_le_bytes());
+            }
+            doc.extend_from_slice(&data);
+        }
+        let out = append_sprite(Some(&doc), bitmap.clone());
+        let tail = &doc[doc.iter().position(|&b| b == 0).unwrap()..];
+        let (text, back) = doldoc::parse_doc_blob(&out);
+        assert_eq!(text, "Title $SP,\"\",BI=2$\n$SP,\"\",BI=6$\n");
+        assert_eq!(&out[text.len()..text.len() + tail.len()], tail);
+        assert_eq!(back.get(&6), Some(&bitmap));
+    }
+}
@@ -0,0 +1,928 @@
+//! DolDoc support shared by TempleShell's doc viewer, `temple-edit` and HolyC's `Doc*` functions:
+//! the source tokenizer, typed entries ([`parse`]), the layout engine ([`layout`]) and a renderer
+//! for any [`sprite::SpriteTarget`] ([`render`]), plus HTML and page image export ([`to_html`],
+//! [`render_pages`]).
+//!
+//! DolDoc is TempleOS' document format: plain text with `$XX,...$` commands, optionally followed
+//! by a NUL byte and a tail of `CDocBin` records (sprites referenced by `BI=<n>`).
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Range,
+};
+
+use crate::{assets, sprite};
+
+mod entry;
+mod export;
+mod layout;
+mod render;
+
+pub use entry::{DocEntry, EntryType, parse, plain_text};
+pub use export::{
+    PAGE_BG, PAGE_FG, PAGE_H, PAGE_W, encode_png, link_href, render_pages, sprite_png, to_html,
+};
+pub use layout::{
+    CELL_H, CELL_W, DocCell, DocLayout, DocLink, DocSprite, DocStyle, DocTree, FormField,
+    LayoutOptions, Layouter, LinkTarget, TreeState, layout, layout_with_trees,
+};
+pub use render::{Canvas, Viewport, render, render_cells, render_sprites};
+
+/// Size of the on-disk `CDocBin` header: `num`, `flags`, `size`, `use_cnt` (all `U32`).
+const BIN_HEADER_LEN: usize = 16;
+const MAX_BIN_DATA_LEN: usize = 256 * 1024;
+
+/// A parsed `$XX+F1+F2,args$` command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocCmd<'a> {
+    /// Trimmed text between the dollar signs.
+    pub raw: &'a str,
+    pub op: &'a str,
+    pub flags: Vec<&'a str>,
+    pub args: &'a str,
+}
+
+impl DocCmd<'_> {
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f.eq_ignore_ascii_case(flag))
+    }
+
+    /// First quoted argument (the entry's tag/label), if any.
+    pub fn tag(&self) -> Option<String> {
+        parse_quoted_args(self.raw, 1).into_iter().next()
+    }
+
+    pub fn attr(&self, key: &str) -> Option<String> {
+        parse_attr_quoted(self.raw, key)
+    }
+
+    pub fn attr_i32(&self, key: &str) -> Option<i32> {
+        parse_kv_i32(self.args, key)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DocToken<'a> {
+    /// Literal text (may contain newlines).
+    Text(&'a str),
+    /// `$$`: a literal dollar sign.
+    Dollar,
+    /// `$$$FG,RED$$$`: a literal command, displayed as `$FG,RED$`.
+    EscapedCmd(&'a str),
+    Cmd(DocCmd<'a>),
+}
+
+/// A token plus the byte range it occupies in the source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocPiece<'a> {
+    pub range: Range<usize>,
+    pub token: DocToken<'a>,
+}
+
+/// Splits DolDoc source into text runs and commands, following TempleOS' `$` escaping rules.
+pub fn tokenize(text: &str) -> Vec<DocPiece<'_>> {
+    let mut out = Vec::new();
+    let mut pos = 0usize;
+
+    while let Some(rel) = text[pos..].find('$') {
+        let start = pos + rel;
+        if start > pos {
+            out.push(DocPiece {
+                range: pos..start,
+                token: DocToken::Text(&text[pos..start]),
+            });
+        }
+        let after = &text[start..];
+
+        if after.starts_with("$$$") {
+            if let Some(end_rel) = after[3..].find("$$$") {
+                let end = start + 3 + end_rel + 3;
+                out.push(DocPiece {
+                    range: start..end,
+                    token: DocToken::EscapedCmd(&after[3..3 + end_rel]),
+                });
+                pos = end;
+                continue;
+            }
+        }
+        if after.starts_with("$$") {
+            out.push(DocPiece {
+                range: start..start + 2,
+                token: DocToken::Dollar,
+            });
+            pos = start + 2;
+            continue;
+        }
+
+        let Some(end_rel) = after[1..].find('$') else {
+            // Unterminated command; keep the rest literally.
+            out.push(DocPiece {
+                range: start..text.len(),
+                token: DocToken::Text(&text[start..]),
+            });
+            pos = text.len();
+            break;
+        };
+        let end = start + 1 + end_rel + 1;
+        let raw = after[1..1 + end_rel].trim();
+        if raw.is_empty() {
+            out.push(DocPiece {
+                range: start..end,
+                token: DocToken::Dollar,
+            });
+        } else {
+            out.push(DocPiece {
+                range: start..end,
+                token: DocToken::Cmd(parse_cmd(raw)),
+            });
+        }
+        pos = end;
+    }
+
+    if pos < text.len() {
+        out.push(DocPiece {
+            range: pos..text.len(),
+            token: DocToken::Text(&text[pos..]),
+        });
+    }
+    out
+}
+
+/// Parses the text between the dollar signs of a command.
+pub fn parse_cmd(raw: &str) -> DocCmd<'_> {
+    let (op_flags, args) = raw.split_once(',').unwrap_or((raw, ""));
+    let mut parts = op_flags.split('+');
+    let op = parts.next().unwrap_or("").trim();
+    DocCmd {
+        raw,
+        op,
+        flags: parts.map(str::trim).collect(),
+        args,
+    }
+}
+
+fn unescape_quoted(chars: &mut impl Iterator<Item = char>) -> String {
+    let mut s = String::new();
+    let mut escaped = false;
+    for c in chars.by_ref() {
+        if escaped {
+            s.push(match c {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                '"' => '"',
+                '\\' => '\\',
+                other => other,
+            });
+            escaped = false;
+            continue;
+        }
+        if c == '\\' {
+            escaped = true;
+            continue;
+        }
+        if c == '"' {
+            break;
+        }
+        s.push(c);
+    }
+    s
+}
+
+/// Returns up to `max` double-quoted strings from a command, in order.
+pub fn parse_quoted_args(cmd: &str, max: usize) -> Vec<String> {
+    let mut out = Vec::new();
+    if max == 0 {
+        return out;
+    }
+
+    let mut chars = cmd.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '"' {
+            continue;
+        }
+        out.push(unescape_quoted(&mut chars));
+        if out.len() >= max {
+            break;
+        }
+    }
+    out
+}
+
+/// Returns the value of a `KEY="..."` attribute.
+pub fn parse_attr_quoted(cmd: &str, key: &str) -> Option<String> {
+    let needle = format!("{key}=\"");
+    let start = cmd.find(&needle)? + needle.len();
+    Some(unescape_quoted(&mut cmd[start..].chars()))
+}
+
+/// Returns the value of a numeric `KEY=<n>` attribute.
+pub fn parse_kv_i32(args: &str, key: &str) -> Option<i32> {
+    let needle = format!("{key}=");
+    let idx = args.find(&needle)? + needle.len();
+    let rest = &args[idx..];
+    let end = rest
+        .find(|c: char| c == ',' || c.is_ascii_whitespace())
+        .unwrap_or(rest.len());
+    rest[..end].trim().parse::<i32>().ok()
+}
+
+const COLOR_NAMES: [&str; 16] = [
+    "BLACK", "BLUE", "GREEN", "CYAN", "RED", "PURPLE", "BROWN", "LTGRAY", "DKGRAY", "LTBLUE",
+    "LTGREEN", "LTCYAN", "LTRED", "LTPURPLE", "YELLOW", "WHITE",
+];
+
+/// Parses a `$FG,...$`/`$BG,...$` color argument: a palette index or a TempleOS color name.
+pub fn parse_color(arg: &str) -> Option<u8> {
+    let arg = arg.trim();
+    if let Ok(v) = arg.parse::<u8>() {
+        return Some(v.min(15));
+    }
+    let upper = arg.to_ascii_uppercase();
+    let alias = match upper.as_str() {
+        "MAGENTA" => "PURPLE",
+        "LTMAGENTA" => "LTPURPLE",
+        "LGRAY" => "LTGRAY",
+        "DGRAY" => "DKGRAY",
+        other => other,
+    };
+    COLOR_NAMES
+        .iter()
+        .position(|name| *name == alias)
+        .map(|i| i as u8)
+}
+
+pub fn color_name(color: u8) -> &'static str {
+    COLOR_NAMES[(color & 0x0f) as usize]
+}
+
+/// Escapes text so it renders literally inside a DolDoc (`$` becomes `$$`).
+pub fn escape_text(s: &str) -> String {
+    s.replace('$', "$$")
+}
+
+/// Quotes a string for use as a command argument, e.g. `$TX,"..."$`.
+pub fn quote_arg(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            other => out.push(other),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Interactive form entries: data fields, check boxes, buttons, list pickers and menu items.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormKind {
+    /// `$DA$`: an editable value, shown through its `A="..."` format.
+    Data,
+    /// `$CB$`: a check box, checked when it carries the `+C` flag.
+    CheckBox,
+    /// `$BT$`: a push button.
+    Button,
+    /// `$LS$`: picks one string from a define list (`D="ST_COLORS"`).
+    List,
+    /// `$MU$`: a menu item; choosing it reports its `LE=` value.
+    Menu,
+}
+
+impl FormKind {
+    /// Maps a command op (flags like `-P` included) to its form kind.
+    pub fn from_op(op: &str) -> Option<Self> {
+        let base = op.split('-').next().unwrap_or(op).trim();
+        match base.to_ascii_uppercase().as_str() {
+            "DA" => Some(Self::Data),
+            "CB" => Some(Self::CheckBox),
+            "BT" => Some(Self::Button),
+            "LS" => Some(Self::List),
+            "MU" => Some(Self::Menu),
+            _ => None,
+        }
+    }
+
+    pub fn from_type(ty: EntryType) -> Option<Self> {
+        match ty {
+            EntryType::Data => Some(Self::Data),
+            EntryType::CheckBox => Some(Self::CheckBox),
+            EntryType::Button => Some(Self::Button),
+            EntryType::List => Some(Self::List),
+            EntryType::MenuVal => Some(Self::Menu),
+            _ => None,
+        }
+    }
+}
+
+/// A form entry's state, read from (and written back into) its command text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormEntry {
+    pub kind: FormKind,
+    /// The bare quoted argument: the label of a check box/button/menu item, or the current
+    /// value of a data field/list.
+    pub tag: String,
+    /// Data field format (`A="Name:%s"`); `%s` when absent.
+    pub fmt: String,
+    pub checked: bool,
+    /// `LE=` value of a button/menu item.
+    pub left_exp: Option<i64>,
+    /// `LM="..."` macro of a button/menu item.
+    pub left_macro: Option<String>,
+    /// Define list a `$LS$` picks from.
+    pub list: Option<String>,
+    /// Max data field length (`LEN=`).
+    pub len: usize,
+}
+
+const FORM_DEFAULT_LEN: usize = 64;
+
+impl FormEntry {
+    pub fn from_cmd(cmd: &DocCmd) -> Option<Self> {
+        Self::from_entry(&DocEntry::from_cmd(cmd, 0..0))
+    }
+
+    pub fn from_entry(entry: &DocEntry) -> Option<Self> {
+        let kind = FormKind::from_type(entry.ty)?;
+        let fmt = if kind == FormKind::Data {
+            entry.attr("A").unwrap_or("%s").to_string()
+        } else {
+            String::new()
+        };
+        Some(Self {
+            kind,
+            tag: entry.tag.clone().unwrap_or_default(),
+            fmt,
+            checked: entry.has_flag("C"),
+            left_exp: entry.attr_i64("LE"),
+            left_macro: entry.attr_str("LM"),
+            list: entry.attr_str("D"),
+            len: entry
+                .attr_i64("LEN")
+                .and_then(|n| usize::try_from(n).ok())
+                .filter(|&n| n > 0)
+                .unwrap_or(FORM_DEFAULT_LEN),
+        })
+    }
+
+    /// Splits a data field's format around its conversion: `("Name:", 's', "")`.
+    pub fn fmt_parts(&self) -> (&str, char, &str) {
+        split_fmt(&self.fmt)
+    }
+
+    /// The text the entry renders as.
+    pub fn display(&self) -> String {
+        match self.kind {
+            FormKind::Data => {
+                let (pre, _, post) = self.fmt_parts();
+                format!("{pre}{}{post}", self.tag)
+            }
+            FormKind::CheckBox => {
+                format!("[{}] {}", if self.checked { 'X' } else { ' ' }, self.tag)
+            }
+            FormKind::Button => format!("[ {} ]", self.tag),
+            FormKind::List => format!("[{}]", self.tag),
+            FormKind::Menu => self.tag.clone(),
+        }
+    }
+
+    /// Whether a data field holds a number (`%d`, `%X`, `%f`...) rather than a string.
+    pub fn is_numeric(&self) -> bool {
+        self.kind == FormKind::Data && self.fmt_parts().1 != 's'
+    }
+
+    /// The next value of a `$LS$` entry, wrapping around.
+    pub fn next_list_value(&self, items: &[&str]) -> Option<String> {
+        if items.is_empty() {
+            return None;
+        }
+        let cur = items
+            .iter()
+            .position(|it| it.eq_ignore_ascii_case(self.tag.trim()));
+        let next = cur.map(|i| (i + 1) % items.len()).unwrap_or(0);
+        Some(items[next].to_string())
+    }
+
+    /// Rewrites `raw` (the command text of this entry) with the entry's tag and check state,
+    /// keeping every other flag and attribute.
+    pub fn apply_to(&self, raw: &str) -> String {
+        let (op_flags, rest) = match raw.find(',') {
+            Some(i) => (&raw[..i], &raw[i..]),
+            None => (raw, ""),
+        };
+        let mut parts = op_flags.split('+');
+        let mut head = parts.next().unwrap_or("").to_string();
+        for flag in parts.filter(|f| !f.trim().eq_ignore_ascii_case("C")) {
+            head.push('+');
+            head.push_str(flag);
+        }
+        if self.checked {
+            head.push_str("+C");
+        }
+
+        let quoted = quote_arg(&self.tag);
+        let rest = match bare_tag_range(rest) {
+            Some(r) => format!("{}{quoted}{}", &rest[..r.start], &rest[r.end..]),
+            None => format!(",{quoted}{rest}"),
+        };
+        format!("{head}{rest}")
+    }
+}
+
+/// Byte range (quotes included) of a command's bare quoted argument, skipping `KEY="..."`
+/// attribute values.
+fn bare_tag_range(raw: &str) -> Option<Range<usize>> {
+    let bytes = raw.as_bytes();
+    let mut i = 0usize;
+    while i < bytes.len() {
+        if bytes[i] != b'"' {
+            i += 1;
+            continue;
+        }
+        let mut end = i + 1;
+        while end < bytes.len() && bytes[end] != b'"' {
+            end += if bytes[end] == b'\\' { 2 } else { 1 };
+        }
+        let end = (end + 1).min(bytes.len());
+        let is_attr = raw[..i].trim_end().ends_with('=');
+        if !is_attr {
+            return Some(i..end);
+        }
+        i = end;
+    }
+    None
+}
+
+/// Splits a printf-style format around its first conversion, e.g. `"Num:%5d pts"` into
+/// `("Num:", 'd', " pts")`. Formats without a conversion behave like a trailing `%s`.
+pub fn split_fmt(fmt: &str) -> (&str, char, &str) {
+    let bytes = fmt.as_bytes();
+    let mut i = 0usize;
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            i += 1;
+            continue;
+        }
+        if bytes.get(i + 1) == Some(&b'%') {
+            i += 2;
+            continue;
+        }
+        let mut j = i + 1;
+        while j < bytes.len() && !bytes[j].is_ascii_alphabetic() {
+            j += 1;
+        }
+        // Skip size prefixes like `%ld`/`%hd`.
+        while j < bytes.len() && matches!(bytes[j], b'l' | b'h') {
+            j += 1;
+        }
+        let Some(&conv) = bytes.get(j) else {
+            break;
+        };
+        return (&fmt[..i], conv as char, &fmt[j + 1..]);
+    }
+    (fmt, 's', "")
+}
+
+/// Define lists every DolDoc host knows about (HolyC programs can add more).
+pub fn builtin_define_list(name: &str) -> Option<&'static [&'static str]> {
+    match name.trim() {
+        "ST_COLORS" => Some(&COLOR_NAMES),
+        _ => None,
+    }
+}
+
+/// The define lists loaded with literal arguments, `DefineLstLoad("NAME", "a\0b\0")`, in
+/// HolyC source `src`, so hosts that can't run the source can still step `$LS$` entries.
+pub fn scan_define_lists(src: &str) -> Vec<(String, Vec<String>)> {
+    /// One or more adjacent string literals at the start of `s`, and the rest of `s`.
+    fn literals(s: &str) -> Option<(String, &str)> {
+        let mut out = String::new();
+        let mut rest = s.trim_start();
+        let mut any = false;
+        while let Some(body) = rest.strip_prefix('"') {
+            let mut chars = body.char_indices();
+            let end = loop {
+                let (i, c) = chars.next()?;
+                match c {
+                    '"' => break i,
+                    '\\' => out.push(match chars.next()?.1 {
+                        '0' => '\0',
+                        'n' => '\n',
+                        't' => '\t',
+                        other => other,
+                    }),
+                    c => out.push(c),
+                }
+            };
+            rest = body[end + 1..].trim_start();
+            any = true;
+        }
+        any.then_some((out, rest))
+    }
+
+    let mut out = Vec::new();
+    for (start, _) in src.match_indices("DefineLstLoad(") {
+        let args = &src[start + "DefineLstLoad(".len()..];
+        let Some((name, rest)) = literals(args) else {
+            continue;
+        };
+        let Some((entries, rest)) = rest.strip_prefix(',').and_then(literals) else {
+            continue;
+        };
+        if !rest.starts_with(')') || name.is_empty() {
+            continue;
+        }
+        let mut items: Vec<String> = entries.split('\0').map(str::to_string).collect();
+        if items.last().is_some_and(String::is_empty) {
+            items.pop();
+        }
+        out.push((name, items));
+    }
+    out
+}
+
+fn read_u32_le(buf: &[u8], off: usize) -> Option<u32> {
+    let b = buf.get(off..off + 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_i32_le(buf: &[u8], off: usize) -> Option<i32> {
+    let b = buf.get(off..off + 4)?;
+    Some(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn ceil_to_multiple(v: i32, step: i32) -> i32 {
+    if step <= 0 {
+        return v;
+    }
+    ((v + step - 1) / step) * step
+}
+
+/// Collects every `BI=<n>` number referenced by the document text.
+pub fn referenced_bins(text: &str) -> BTreeSet<u32> {
+    let mut out = BTreeSet::new();
+    let bytes = text.as_bytes();
+    let mut i = 0usize;
+    while i + 3 <= bytes.len() {
+        let b0 = bytes[i];
+        let b1 = bytes[i + 1];
+        if (b0 == b'B' || b0 == b'b') && (b1 == b'I' || b1 == b'i') && bytes[i + 2] == b'=' {
+            let mut j = i + 3;
+            let mut v: u32 = 0;
+            let mut any = false;
+            while j < bytes.len() && bytes[j].is_ascii_digit() {
+                any = true;
+                v = v
+                    .saturating_mul(10)
+                    .saturating_add((bytes[j] - b'0') as u32);
+                j += 1;
+            }
+            if any && v != 0 {
+                out.insert(v);
+            }
+            i = j;
+            continue;
+        }
+        i += 1;
+    }
+    out
+}
+
+fn repair_sprite_blob(prefix: &[u8]) -> Option<Vec<u8>> {
+    if prefix.is_empty() {
+        return None;
+    }
+
+    // --- Happy path: parse a sprite starting at offset 0. -------------------
+    //
+    // We intentionally accept trailing junk after the first `SPT_END` and truncate,
+    // because some vendored `.DD` files include extra (non-zero) padding after the
+    // sprite. TempleOS walks sprites until `SPT_END` and ignores any tail bytes.
+    if let Some(end) = sprite::sprite_parse_end_at_start(prefix) {
+        if end > 1 {
+            return Some(prefix[..end].to_vec());
+        }
+    }
+
+    // --- Small common corruptions / quirks ----------------------------------
+
+    // 1) Off-by-one: trailing `SPT_END` becomes `0xff`. Fix and retry.
+    if prefix.last() == Some(&0xff) {
+        let mut fixed = prefix.to_vec();
+        if let Some(last) = fixed.last_mut() {
+            *last = 0;
+        }
+        if let Some(end) = sprite::sprite_parse_end_at_start(&fixed) {
+            if end > 1 {
+                fixed.truncate(end);
+                return Some(fixed);
+            }
+        }
+    }
+
+    // 2) Missing trailing `SPT_END`: append it and retry.
+    {
+        let mut fixed = prefix.to_vec();
+        fixed.push(0);
+        if let Some(end) = sprite::sprite_parse_end_at_start(&fixed) {
+            if end > 1 {
+                fixed.truncate(end);
+                return Some(fixed);
+            }
+        }
+    }
+
+    // 2b) Missing/garbled `SPT_END` with trailing garbage: truncate at the last
+    // successfully-parsed element boundary and append `SPT_END`.
+    if let Some(last) = sprite::sprite_parse_last_good_prefix_len_at_start(prefix) {
+        if last > 1 && last <= prefix.len() {
+            let mut fixed = prefix[..last].to_vec();
+            fixed.push(0);
+            if sprite::sprite_parse_end_at_start(&fixed) == Some(fixed.len()) {
+                return Some(fixed);
+            }
+        }
+    }
+
+    // 3) Bitmap-only sprite missing `SPT_END`, with `0xff`/0 padding after the bitmap.
+    // This shows up in some PersonalMenu icons.
+    if prefix.first().copied().unwrap_or(0) & 0x7f == 23 {
+        // SPT_BITMAP: type + x + y + w + h + data[stride*h]
+        if prefix.len() >= 1 + 4 * 4 {
+            let w = read_i32_le(prefix, 1 + 8)?;
+            let h = read_i32_le(prefix, 1 + 12)?;
+            if w > 0 && h > 0 && w <= 2048 && h <= 2048 {
+                let stride = ceil_to_multiple(w, 8) as usize;
+                let data_len = stride.saturating_mul(h as usize);
+                let elem_len = 1usize + 4 * 4 + data_len;
+                if elem_len <= prefix.len()
+                    && prefix[elem_len..].iter().all(|&b| b == 0 || b == 0xff)
+                {
+                    let mut fixed = prefix[..elem_len].to_vec();
+                    fixed.push(0); // SPT_END
+                    return Some(fixed);
+                }
+            }
+        }
+    }
+
+    // 4) Missing `SPT_BITMAP` type byte: the blob starts at `x1` instead of `type`.
+    //
+    // Layout without the type byte:
+    //   x1(i32), y1(i32), w(i32), h(i32), data[stride*h], [optional SPT_END]
+    if prefix.len() >= 16 {
+        let w = read_i32_le(prefix, 8)?;
+        let h = read_i32_le(prefix, 12)?;
+        if w > 0 && h > 0 && w <= 2048 && h <= 2048 {
+            let stride = ceil_to_multiple(w, 8) as usize;
+            let data_len = stride.saturating_mul(h as usize);
+            let min_len = 16usize.saturating_add(data_len);
+            if min_len <= prefix.len() {
+                let mut fixed = Vec::with_capacity(min_len.saturating_add(2));
+                fixed.push(23u8); // SPT_BITMAP
+                fixed.extend_from_slice(&prefix[..min_len]);
+                fixed.push(0); // SPT_END
+
+                if sprite::sprite_parse_end_at_start(&fixed) == Some(fixed.len()) {
+                    return Some(fixed);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Recovers the sprite data for each referenced bin from a `CDocBin` tail.
+pub fn parse_doc_bins(bin_blob: &[u8], expected_bins: &BTreeSet<u32>) -> BTreeMap<u32, Vec<u8>> {
+    let mut bins: BTreeMap<u32, Vec<u8>> = BTreeMap::new();
+    if bin_blob.len() < BIN_HEADER_LEN + 1 {
+        return bins;
+    }
+
+    if expected_bins.is_empty() {
+        return bins;
+    }
+
+    // Some TempleOS docs have a binary tail that isn't cleanly parseable by walking
+    // `CDocBin.size` sequentially (sizes can be corrupted, padding can be non-zero, etc).
+    //
+    // Instead, scan the bin tail for plausible `CDocBin` headers and recover the best
+    // sprite for each referenced `BI=<n>` number.
+    let scan_end = bin_blob.len().saturating_sub(BIN_HEADER_LEN + 1);
+    for off in 0..=scan_end {
+        let Some(num) = read_u32_le(bin_blob, off) else {
+            continue;
+        };
+        if !expected_bins.contains(&num) {
+            continue;
+        }
+
+        let Some(flags) = read_u32_le(bin_blob, off + 4) else {
+            continue;
+        };
+        if flags != 0 {
+            continue;
+        }
+
+        // Records are typically preceded by the previous sprite's `SPT_END` (0).
+        // Some vendored files have 0xff here due to off-by-one corruption.
+        if off != 0 && !matches!(bin_blob.get(off - 1), Some(0 | 0xff)) {
+            continue;
+        }
+
+        let data_start = off.saturating_add(BIN_HEADER_LEN);
+        if data_start >= bin_blob.len() {
+            continue;
+        }
+
+        let mut best: Option<Vec<u8>> = None;
+
+        // Try the stored `size` field first when it looks sane.
+        if let Some(size_raw) = read_u32_le(bin_blob, off + 8) {
+            let size = size_raw as usize;
+            if size != 0 && size <= MAX_BIN_DATA_LEN && data_start + size <= bin_blob.len() {
+                let raw = &bin_blob[data_start..data_start + size];
+                best = repair_sprite_blob(raw);
+            }
+        }
+
+        // Attempt to repair from a bounded prefix too (ignore `size`).
+        //
+        // Prefer the longer recovered sprite, because some vendored docs (notably
+        // `::/PersonalMenu.DD`) have corrupted `CDocBin.size` fields that truncate the
+        // stored sprite data mid-element.
+        {
+            let end = (data_start + MAX_BIN_DATA_LEN).min(bin_blob.len());
+            let raw = &bin_blob[data_start..end];
+            if let Some(candidate) = repair_sprite_blob(raw) {
+                match &best {
+                    Some(prev) if prev.len() >= candidate.len() => {}
+                    _ => best = Some(candidate),
+                }
+            }
+        }
+
+        let Some(best) = best else {
+            continue;
+        };
+
+        if best.len() <= 1 {
+            continue;
+        }
+
+        match bins.get(&num) {
+            Some(prev) if prev.len() >= best.len() => {}
+            _ => {
+                bins.insert(num, best);
+            }
+        }
+    }
+
+    bins
+}
+
+/// Splits a `.DD` file into its text and the sprites stored after the NUL terminator.
+pub fn parse_doc_blob(buf: &[u8]) -> (String, BTreeMap<u32, Vec<u8>>) {
+    let cutoff = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    let text_bytes = &buf[..cutoff];
+    let text = std::str::from_utf8(text_bytes)
+        .map(|s| s.to_string())
+        .unwrap_or_else(|_| assets::decode_cp437_bytes(text_bytes));
+
+    if cutoff >= buf.len() {
+        return (text, BTreeMap::new());
+    }
+
+    let expected_bins = referenced_bins(&text);
+    let bins = parse_doc_bins(&buf[cutoff + 1..], &expected_bins);
+    (text, bins)
+}
+
+/// Inverse of [`parse_doc_blob`]: text, then (if there are bins) a NUL and `CDocBin` records.
+pub fn encode_doc_blob(text: &[u8], bins: &BTreeMap<u32, Vec<u8>>) -> Vec<u8> {
+    let mut out = text.to_vec();
+    if bins.is_empty() {
+        return out;
+    }
+    out.push(0);
+    for (num, data) in bins {
+        push_doc_bin(&mut out, *num, data);
+    }
+    out
+}
+
+fn push_doc_bin(out: &mut Vec<u8>, num: u32, data: &[u8]) {
+    out.extend_from_slice(&num.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes()); // flags
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(&1u32.to_le_bytes()); // use_cnt
+    out.extend_from_slice(data);
+}
+
+/// Every `BI=` number stored in `buf`'s bin tail, walking the `CDocBin` records by their
+/// sizes. Unlike [`parse_doc_blob`] this includes bins no command refers to, so new bins can be
+/// numbered past them.
+pub fn stored_bin_nums(buf: &[u8]) -> BTreeSet<u32> {
+    let mut nums = BTreeSet::new();
+    let Some(cutoff) = buf.iter().position(|&b| b == 0) else {
+        return nums;
+    };
+    let tail = &buf[cutoff + 1..];
+    let mut off = 0;
+    while let (Some(num), Some(size)) = (read_u32_le(tail, off), read_u32_le(tail, off + 8)) {
+        nums.insert(num);
+        let Some(next) = (size as usize)
+            .checked_add(off + BIN_HEADER_LEN)
+            .filter(|&next| next <= tail.len())
+        else {
+            break;
+        };
+        off = next;
+    }
+    nums
+}
+
+/// `text` and `bins` saved over the document `original` was read from, keeping what didn't
+/// change byte-for-byte: unchanged text keeps its bytes (CP437 text stays CP437), and the bin
+/// tail is kept as [`update_doc_bins`] does.
+pub fn update_doc_blob(original: &[u8], text: &str, bins: &BTreeMap<u32, Vec<u8>>) -> Vec<u8> {
+    let cutoff = original
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(original.len());
+    let (old_text, _) = parse_doc_blob(original);
+    let out = if old_text == text {
+        original[..cutoff].to_vec()
+    } else if std::str::from_utf8(&original[..cutoff]).is_ok() {
+        text.as_bytes().to_vec()
+    } else {
+        text.chars().map(assets::encode_cp437).collect()
+    };
+    update_doc_bins(original, out, bins)
+}
+
+/// The text bytes `text` followed by `bins`, reusing the bin tail of `original` byte-for-byte:
+/// the bins it holds stay as stored (headers, unreferenced and unrecoverable bins included) and
+/// new bins are appended. A stored bin whose data changed has just its own record rewritten;
+/// only when the records can't be walked to it is the tail written afresh.
+pub fn update_doc_bins(
+    original: &[u8],
+    mut text: Vec<u8>,
+    bins: &BTreeMap<u32, Vec<u8>>,
+) -> Vec<u8> {
+    let Some(cutoff) = original.iter().position(|&b| b == 0) else {
+        return encode_doc_blob(&text, bins);
+    };
+    let (_, old_bins) = parse_doc_blob(original);
+    let changed: BTreeMap<u32, &[u8]> = old_bins
+        .iter()
+        .filter_map(|(num, data)| {
+            let now = bins.get(num)?;
+            (now != data).then_some((*num, now.as_slice()))
+        })
+        .collect();
+    let Some(tail) = rewrite_doc_bins(&original[cutoff + 1..], &changed) else {
+        return encode_doc_blob(&text, bins);
+    };
+    text.push(0);
+    text.extend_from_slice(&tail);
+    for (num, data) in bins.iter().filter(|(num, _)| !old_bins.contains_key(num)) {
+        push_doc_bin(&mut text, *num, data);
+    }
+    text
+}
+
+/// `tail` with the records of the `changed` bins given their new data (flags and use count
+/// kept), walking the `CDocBin` records by their sizes; `None` if a changed bin isn't reached.
+fn rewrite_doc_bins(tail: &[u8], changed: &BTreeMap<u32, &[u8]>) -> Option<Vec<u8>> {
+    if changed.is_empty() {
+        return Some(tail.to_vec());
+    }
+    let mut out = Vec::with_capacity(tail.len());
+    let mut found = BTreeSet::new();
+    let mut off = 0;
+    while let (Some(num), Some(size)) = (read_u32_le(tail, off), read_u32_le(tail, off + 8)) {
+        let Some(next) = (size as usize)
+            .checked_add(off + BIN_HEADER_LEN)
+            .filter(|&next| next <= tail.len())
+        else {
+            break;
+        };
+        match changed.get(&num) {
+            Some(data) if found.insert(num) => {
+                out.extend_from_slice(&tail[off..off + 8]);
+                out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                out.extend_from_slice(&tail[off + 12..off + BIN_HEADER_LEN]);
+                out.extend_from_slice(data);
+            }
+            _ => out.extend_from_slice(&tail[off..next]),
+        }
+        off = next;
+    }
+    out.extend_from_slice(&tail[off..]);
+    (found.len() == changed.len()).then_some(out)
+}
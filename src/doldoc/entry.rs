@@ -0,0 +1,371 @@
+//! Typed DolDoc entries: the document as a list of text runs, line breaks and `$XX$` commands
+//! with their flags and attributes decoded.
+
+use std::ops::Range;
+
+use super::{DocCmd, DocToken, parse_color, tokenize, unescape_quoted};
+
+/// Entry types, after TempleOS' `DOCT_*` constants.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EntryType {
+    /// Plain text, or a `$TX$` command.
+    Text,
+    NewLine,
+    Tab,
+    PageBreak,
+    Cursor,
+    Marker,
+    Prompt,
+    Clear,
+    PageLen,
+    LeftMargin,
+    RightMargin,
+    Header,
+    Footer,
+    Indent,
+    Foreground,
+    Background,
+    DftForeground,
+    DftBackground,
+    WordWrap,
+    Highlight,
+    Blink,
+    Invert,
+    ShiftedX,
+    ShiftedY,
+    Underline,
+    CursorMove,
+    Anchor,
+    Link,
+    Button,
+    Data,
+    CheckBox,
+    List,
+    Macro,
+    MenuVal,
+    HexEdit,
+    Tree,
+    Sprite,
+    InsBin,
+    InsBinSize,
+    Song,
+    HtmlCode,
+    Error,
+    /// A command code this implementation doesn't know; see [`DocEntry::code`].
+    Unknown,
+}
+
+const ENTRY_CODES: [(EntryType, &str); 42] = [
+    (EntryType::Text, "TX"),
+    (EntryType::NewLine, "CR"),
+    (EntryType::Tab, "TB"),
+    (EntryType::PageBreak, "PB"),
+    (EntryType::Cursor, "CU"),
+    (EntryType::Marker, "MK"),
+    (EntryType::Prompt, "PT"),
+    (EntryType::Clear, "CL"),
+    (EntryType::PageLen, "PL"),
+    (EntryType::LeftMargin, "LM"),
+    (EntryType::RightMargin, "RM"),
+    (EntryType::Header, "HD"),
+    (EntryType::Footer, "FO"),
+    (EntryType::Indent, "ID"),
+    (EntryType::Foreground, "FG"),
+    (EntryType::Background, "BG"),
+    (EntryType::DftForeground, "FD"),
+    (EntryType::DftBackground, "BD"),
+    (EntryType::WordWrap, "WW"),
+    (EntryType::Highlight, "HL"),
+    (EntryType::Blink, "BK"),
+    (EntryType::Invert, "IV"),
+    (EntryType::ShiftedX, "SX"),
+    (EntryType::ShiftedY, "SY"),
+    (EntryType::Underline, "UL"),
+    (EntryType::CursorMove, "CM"),
+    (EntryType::Anchor, "AN"),
+    (EntryType::Link, "LK"),
+    (EntryType::Button, "BT"),
+    (EntryType::Data, "DA"),
+    (EntryType::CheckBox, "CB"),
+    (EntryType::List, "LS"),
+    (EntryType::Macro, "MA"),
+    (EntryType::MenuVal, "MU"),
+    (EntryType::HexEdit, "HX"),
+    (EntryType::Tree, "TR"),
+    (EntryType::Sprite, "SP"),
+    (EntryType::InsBin, "IB"),
+    (EntryType::InsBinSize, "BS"),
+    (EntryType::Song, "SO"),
+    (EntryType::HtmlCode, "HC"),
+    (EntryType::Error, "ER"),
+];
+
+impl EntryType {
+    pub fn from_code(code: &str) -> Self {
+        ENTRY_CODES
+            .iter()
+            .find(|(_, c)| c.eq_ignore_ascii_case(code))
+            .map(|(ty, _)| *ty)
+            .unwrap_or(EntryType::Unknown)
+    }
+
+    /// The two-letter command code (`""` for [`EntryType::Unknown`]).
+    pub fn code(self) -> &'static str {
+        ENTRY_CODES
+            .iter()
+            .find(|(ty, _)| *ty == self)
+            .map(|(_, c)| *c)
+            .unwrap_or("")
+    }
+
+    /// TempleOS' `DOCT_*` number (`DOCT_ERROR` for [`EntryType::Unknown`]). `DOCT_SOFT_NEW_LINE`
+    /// (2) has no entry type: soft breaks only exist in a laid-out document.
+    pub fn doct(self) -> i64 {
+        let idx = ENTRY_CODES
+            .iter()
+            .position(|(ty, _)| *ty == self)
+            .unwrap_or(ENTRY_CODES.len() - 1);
+        if idx >= 2 { idx as i64 + 1 } else { idx as i64 }
+    }
+}
+
+/// One document entry. Plain text runs are `Text` entries with the text in `tag`; every line
+/// break is its own `NewLine` entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocEntry {
+    pub ty: EntryType,
+    /// Command code as written (upper-cased); empty for plain text and line breaks.
+    pub code: String,
+    /// Source bytes the entry was parsed from.
+    pub range: Range<usize>,
+    /// Flags turned on with `+F`.
+    pub flags: Vec<String>,
+    /// Flags turned off with `-F` (`$MA-X$`, `$CM-RE$`).
+    pub cleared: Vec<String>,
+    /// The bare quoted argument: an entry's label, or the text of a `Text` entry.
+    pub tag: Option<String>,
+    /// Remaining positional arguments, unquoted: `RED` in `$FG,RED$`, the target in
+    /// `$LK,"label","target"$`.
+    pub args: Vec<String>,
+    /// `KEY=value` attributes in source order, quotes removed.
+    pub attrs: Vec<(String, String)>,
+}
+
+impl DocEntry {
+    fn text(range: Range<usize>, text: &str) -> Self {
+        Self {
+            ty: EntryType::Text,
+            code: String::new(),
+            range,
+            flags: Vec::new(),
+            cleared: Vec::new(),
+            tag: Some(text.to_string()),
+            args: Vec::new(),
+            attrs: Vec::new(),
+        }
+    }
+
+    fn new_line(at: usize) -> Self {
+        Self {
+            ty: EntryType::NewLine,
+            tag: None,
+            ..Self::text(at..at + 1, "")
+        }
+    }
+
+    /// Decodes a tokenized command found at `range`.
+    pub fn from_cmd(cmd: &DocCmd, range: Range<usize>) -> Self {
+        let mut code = String::new();
+        let mut flags = Vec::new();
+        let mut cleared = Vec::new();
+        let mut head = cmd.op;
+        for flag in &cmd.flags {
+            flags.push(flag.to_ascii_uppercase());
+        }
+        // `-F` parts ride along in `op` (`MA-X`) since only `+` splits flags.
+        if let Some((op, rest)) = head.split_once('-') {
+            head = op;
+            cleared.extend(
+                rest.split('-')
+                    .map(|f| f.trim().to_ascii_uppercase())
+                    .filter(|f| !f.is_empty()),
+            );
+        }
+        code.push_str(&head.trim().to_ascii_uppercase());
+
+        let mut tag = None;
+        let mut args = Vec::new();
+        let mut attrs = Vec::new();
+        for part in split_args(cmd.args) {
+            let part = part.trim();
+            if part.is_empty() {
+                continue;
+            }
+            if let Some(quoted) = part.strip_prefix('"') {
+                let s = unescape_quoted(&mut quoted.chars());
+                if tag.is_none() {
+                    tag = Some(s);
+                } else {
+                    args.push(s);
+                }
+                continue;
+            }
+            match part.split_once('=') {
+                Some((key, value)) if is_attr_key(key) => {
+                    let value = value.trim();
+                    let value = match value.strip_prefix('"') {
+                        Some(q) => unescape_quoted(&mut q.chars()),
+                        None => value.to_string(),
+                    };
+                    attrs.push((key.trim().to_ascii_uppercase(), value));
+                }
+                _ => args.push(part.to_string()),
+            }
+        }
+
+        let mut ty = EntryType::from_code(&code);
+        // `$RED$` is shorthand for `$FG,RED$`.
+        if ty == EntryType::Unknown && parse_color(&code).is_some() {
+            ty = EntryType::Foreground;
+            args.insert(0, code.clone());
+        }
+
+        Self {
+            ty,
+            code,
+            range,
+            flags,
+            cleared,
+            tag,
+            args,
+            attrs,
+        }
+    }
+
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f.eq_ignore_ascii_case(flag))
+    }
+
+    pub fn clears_flag(&self, flag: &str) -> bool {
+        self.cleared.iter().any(|f| f.eq_ignore_ascii_case(flag))
+    }
+
+    pub fn attr(&self, key: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// A numeric attribute (`LE=3`); `None` when missing or not a plain number.
+    pub fn attr_i64(&self, key: &str) -> Option<i64> {
+        self.attr(key)?.trim().parse::<i64>().ok()
+    }
+
+    /// A string attribute with surrounding blanks removed; `None` when missing or blank.
+    pub fn attr_str(&self, key: &str) -> Option<String> {
+        self.attr(key)
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    }
+
+    /// Positional argument `i` as a number (`2` in `$ID,2$`).
+    pub fn arg_i64(&self, i: usize) -> Option<i64> {
+        self.args.get(i)?.trim().parse::<i64>().ok()
+    }
+
+    /// The tag, unless it is missing or blank.
+    pub fn label(&self) -> Option<&str> {
+        self.tag.as_deref().filter(|s| !s.trim().is_empty())
+    }
+
+    /// `BI=<n>`: the document bin (sprite) the entry shows.
+    pub fn bin(&self) -> Option<u32> {
+        self.attr_i64("BI").and_then(|v| u32::try_from(v).ok())
+    }
+
+    /// The macro run when the entry is clicked: `LM="..."`, else `A="..."`.
+    pub fn left_macro(&self) -> Option<String> {
+        self.attr_str("LM").or_else(|| self.attr_str("A"))
+    }
+}
+
+fn is_attr_key(key: &str) -> bool {
+    let key = key.trim();
+    !key.is_empty() && key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// Splits command arguments at commas outside quoted strings.
+fn split_args(args: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let bytes = args.as_bytes();
+    let mut start = 0usize;
+    let mut in_quote = false;
+    let mut i = 0usize;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if in_quote => i += 1,
+            b'"' => in_quote = !in_quote,
+            b',' if !in_quote => {
+                out.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    out.push(&args[start.min(args.len())..]);
+    out
+}
+
+/// Parses DolDoc source into entries. `$$` and `$$$...$$$` escapes become `Text` entries
+/// showing the literal dollar signs.
+pub fn parse(text: &str) -> Vec<DocEntry> {
+    let mut out = Vec::new();
+    let push_text = |out: &mut Vec<DocEntry>, start: usize, chunk: &str| {
+        let mut at = start;
+        for line in chunk.split_inclusive('\n') {
+            let body = line.strip_suffix('\n').unwrap_or(line);
+            if !body.is_empty() {
+                out.push(DocEntry::text(at..at + body.len(), body));
+            }
+            if body.len() < line.len() {
+                out.push(DocEntry::new_line(at + body.len()));
+            }
+            at += line.len();
+        }
+    };
+
+    for piece in tokenize(text) {
+        match piece.token {
+            DocToken::Text(chunk) => push_text(&mut out, piece.range.start, chunk),
+            DocToken::Dollar => out.push(DocEntry::text(piece.range, "$")),
+            DocToken::EscapedCmd(inner) => {
+                out.push(DocEntry::text(piece.range, &format!("${inner}$")));
+            }
+            DocToken::Cmd(cmd) => out.push(DocEntry::from_cmd(&cmd, piece.range)),
+        }
+    }
+    out
+}
+
+/// The document's words without its commands: text runs, line breaks and the labels of links,
+/// trees, buttons and the like. Source line `n` stays line `n`, so hits map back to the file.
+pub fn plain_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for e in parse(text) {
+        match e.ty {
+            EntryType::NewLine => out.push('\n'),
+            ty => {
+                if let Some(tag) = e.tag.as_deref().filter(|_| ty != EntryType::HtmlCode) {
+                    out.push_str(&tag.replace('\n', " "));
+                }
+                // Keep the line breaks of commands spanning lines.
+                let breaks = text.get(e.range.clone()).unwrap_or_default().matches('\n');
+                out.extend(breaks.map(|_| '\n'));
+            }
+        }
+    }
+    out
+}
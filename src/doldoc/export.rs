@@ -0,0 +1,550 @@
+//! Exports documents for reading outside TempleShell: standalone HTML, and 640x480 page images
+//! laid out exactly like the shell's doc viewer.
+
+use std::{collections::BTreeMap, io};
+
+use super::{
+    CELL_H, CELL_W, Canvas, DocEntry, EntryType, FormEntry, LayoutOptions, Layouter, Viewport,
+    layout,
+    layout::{LINK_FG, TREE_FG, form_fg, strip_comment},
+    parse, render,
+};
+use crate::{assets, sprite};
+
+/// Page size of [`render_pages`]: the TempleOS screen.
+pub const PAGE_W: u32 = 640;
+pub const PAGE_H: u32 = 480;
+/// Default text colors, the shell doc viewer's.
+pub const PAGE_FG: u8 = 15;
+pub const PAGE_BG: u8 = 0;
+
+/// Background of sprite images; comes out transparent.
+const TRANSPARENT: u8 = 0xFF;
+
+fn export_options(cols: usize) -> LayoutOptions {
+    LayoutOptions {
+        strip_comments: true,
+        ..LayoutOptions::view(cols, PAGE_FG, PAGE_BG)
+    }
+}
+
+/// Lays the document out like the doc viewer and cuts it into screen-sized pages.
+pub fn render_pages(text: &str, bins: &BTreeMap<u32, Vec<u8>>) -> Vec<Canvas> {
+    let cols = (PAGE_W / CELL_W as u32) as usize;
+    let rows = (PAGE_H / CELL_H as u32) as usize;
+    let mut doc = layout(text, bins, export_options(cols));
+    while doc.lines.len() > 1
+        && doc
+            .lines
+            .last()
+            .is_some_and(|line| line.iter().all(|c| c.ch == b' '))
+    {
+        doc.lines.pop();
+    }
+    // Sprites can hang below the last line of text.
+    let end = doc
+        .sprites
+        .iter()
+        .map(|sp| sp.bbox_line1.max(0) as usize)
+        .fold(doc.lines.len(), usize::max);
+
+    (0..end.div_ceil(rows).max(1))
+        .map(|page| {
+            let mut canvas = Canvas::new(PAGE_W, PAGE_H, PAGE_BG);
+            let view = Viewport {
+                x: 0,
+                y: 0,
+                cols,
+                rows,
+                first_line: page * rows,
+            };
+            render(&mut canvas, &doc, bins, &view);
+            canvas
+        })
+        .collect()
+}
+
+/// Encodes `canvas` as an RGBA PNG in the standard palette.
+pub fn encode_png(canvas: &Canvas, transparent: Option<u8>) -> io::Result<Vec<u8>> {
+    let rgba = canvas.to_rgba(&assets::TEMPLEOS_GR_PALETTE_STD_RGBA256, transparent);
+    let mut out = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out, canvas.width, canvas.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder
+            .write_header()
+            .map_err(|err| io::Error::other(err.to_string()))?;
+        writer
+            .write_image_data(&rgba)
+            .map_err(|err| io::Error::other(err.to_string()))?;
+    }
+    Ok(out)
+}
+
+/// A sprite alone on a transparent background, cropped to its bounds; `None` when it draws
+/// nothing.
+pub fn sprite_png(data: &[u8]) -> Option<io::Result<Vec<u8>>> {
+    let bounds = sprite::sprite_bounds(data)?;
+    if bounds.width() <= 0 || bounds.height() <= 0 {
+        return None;
+    }
+    let mut canvas = Canvas::new(bounds.width() as u32, bounds.height() as u32, TRANSPARENT);
+    sprite::sprite_render(&mut canvas, -bounds.x0, -bounds.y0, data);
+    Some(encode_png(&canvas, Some(TRANSPARENT)))
+}
+
+/// Renders the document as a standalone HTML page: colored text, `$LK$` links and `$AN$`
+/// anchors, `$TR$` trees as `<details>`, `$HC$` blocks verbatim and sprites as embedded PNGs.
+/// `doc_dir` is the document's directory in the TempleOS tree (`"Doc"`, `""` for the root);
+/// links to other documents are made relative to it.
+pub fn to_html(text: &str, bins: &BTreeMap<u32, Vec<u8>>, title: &str, doc_dir: &str) -> String {
+    let mut w = HtmlWriter::new(bins, doc_dir);
+    for entry in parse(text) {
+        w.push(&entry);
+    }
+    w.finish(title)
+}
+
+/// Where a `$LK$` target points in an export of a document in `doc_dir`: `AN:` anchors within
+/// the page, `http(s)` URLs as they are, and documents at their own export, relative to this one
+/// (`::/Doc/Foo.DD` from `Doc` becomes `Foo.html`). Other specs (`MN:`, `HI:`, other URL
+/// schemes...) only mean something inside TempleOS, or not at all.
+pub fn link_href(spec: &str, doc_dir: &str) -> Option<String> {
+    let spec = spec.trim();
+    if let Some(name) = spec.strip_prefix("AN:") {
+        return Some(format!("#{}", name.trim()));
+    }
+    let path = if let Some(rest) = spec.strip_prefix("FI:") {
+        rest
+    } else if let Some(rest) = spec.strip_prefix("FF:") {
+        rest.split(',').next().unwrap_or(rest)
+    } else if spec.starts_with("http://") || spec.starts_with("https://") {
+        return Some(spec.to_string());
+    } else {
+        spec
+    };
+    let path = path.trim();
+    // `::/` is the TempleOS root, as is a drive letter (`C:/`).
+    let (rooted, path) = if let Some(rest) = path.strip_prefix("::") {
+        (true, rest)
+    } else if path.len() > 2
+        && path.as_bytes()[1] == b':'
+        && path.as_bytes()[0].is_ascii_alphabetic()
+    {
+        (true, &path[2..])
+    } else {
+        (path.starts_with('/'), path)
+    };
+    // Anything else with a scheme (`javascript:`, `MN:`...) has no page to point at.
+    if path.is_empty() || path.contains([':', '\\']) {
+        return None;
+    }
+    let path = path.strip_suffix(".Z").unwrap_or(path);
+    let stem = match path.rsplit_once('.') {
+        Some((stem, ext)) if !ext.contains('/') => stem,
+        _ => path,
+    };
+    let target = if rooted {
+        relative_to(doc_dir, stem.trim_start_matches('/'))
+    } else {
+        stem.to_string()
+    };
+    Some(format!("{}.html", percent_encode(&target)))
+}
+
+/// `target` (from the root of the tree) as seen from `dir`: `Doc/Foo` from `Home` is `../Doc/Foo`.
+fn relative_to(dir: &str, target: &str) -> String {
+    let dir: Vec<&str> = dir.split('/').filter(|c| !c.is_empty()).collect();
+    let target: Vec<&str> = target.split('/').filter(|c| !c.is_empty()).collect();
+    let common = dir
+        .iter()
+        .zip(&target[..target.len().saturating_sub(1)])
+        .take_while(|(a, b)| a.eq_ignore_ascii_case(b))
+        .count();
+    let mut parts = vec![".."; dir.len() - common];
+    parts.extend(&target[common..]);
+    parts.join("/")
+}
+
+/// Escapes everything in a link but path characters, so no target can leave its attribute or
+/// read as a scheme or host.
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"/.-_~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+fn css_color(color: u8) -> String {
+    let [r, g, b, _] = assets::TEMPLEOS_GR_PALETTE_STD_RGBA256[color as usize];
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Streams entries into HTML. Colors and indentation come from a [`Layouter`] fed the same
+/// entries, so they match what the viewer shows.
+struct HtmlWriter<'a> {
+    bins: &'a BTreeMap<u32, Vec<u8>>,
+    /// The document's directory in the TempleOS tree; see [`link_href`].
+    doc_dir: &'a str,
+    layouter: Layouter<'a>,
+    body: String,
+    /// Line breaks not written yet: a block element (`<details>`...) ends the line by itself.
+    pending_newlines: usize,
+    /// Drop the line break that follows a block-level entry in the source.
+    swallow_newline: bool,
+    at_line_start: bool,
+    /// Open `$TR$` blocks: the indent they were opened at, and whether their (further indented)
+    /// body has started. A tree closes when the indent drops back to its own.
+    trees: Vec<(usize, bool)>,
+}
+
+impl<'a> HtmlWriter<'a> {
+    fn new(bins: &'a BTreeMap<u32, Vec<u8>>, doc_dir: &'a str) -> Self {
+        let cols = (PAGE_W / CELL_W as u32) as usize;
+        Self {
+            bins,
+            doc_dir,
+            layouter: Layouter::new(export_options(cols), bins),
+            body: String::new(),
+            pending_newlines: 0,
+            swallow_newline: false,
+            at_line_start: true,
+            trees: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, e: &DocEntry) {
+        let style = self.layouter.style();
+        let (fg, bg) = (style.fg, style.bg);
+
+        if let Some(entry) = FormEntry::from_entry(e) {
+            self.text(&entry.display(), form_fg(entry.kind, fg), bg);
+        } else {
+            match e.ty {
+                EntryType::Text if e.code.is_empty() => {
+                    let text = e.tag.as_deref().unwrap_or_default();
+                    self.text(strip_comment(text), fg, bg);
+                }
+                EntryType::Text => {
+                    let text = e.tag.as_deref().unwrap_or_default();
+                    if e.has_flag("CX") {
+                        let html =
+                            format!("<div class=\"center\">{}</div>", self.span(text, fg, bg));
+                        self.block(&html);
+                    } else {
+                        self.text(text, fg, bg);
+                    }
+                }
+                EntryType::NewLine => self.new_line(),
+                EntryType::CursorMove => {
+                    if e.clears_flag("RE") {
+                        let n = e.attr_i64("LE").or_else(|| e.arg_i64(0)).unwrap_or(0);
+                        self.text(&" ".repeat(n.clamp(0, 256) as usize), fg, bg);
+                    } else {
+                        let n = e.attr_i64("RE").or_else(|| e.arg_i64(1)).unwrap_or(0);
+                        for _ in 0..n.clamp(0, 256) {
+                            self.new_line();
+                        }
+                    }
+                }
+                EntryType::Anchor => {
+                    if let Some(name) = e.attr_str("A") {
+                        self.raw(&format!("<a id=\"{}\"></a>", escape_html(&name)));
+                    }
+                }
+                EntryType::Tree => self.open_tree(e),
+                EntryType::Link => {
+                    if let Some(label) = e.tag.as_deref() {
+                        let spec = e
+                            .attr("A")
+                            .or(e.args.first().map(String::as_str))
+                            .unwrap_or(label);
+                        self.link(label, link_href(spec, self.doc_dir), spec, bg);
+                    }
+                }
+                EntryType::Macro => {
+                    let action = e.left_macro();
+                    if let Some(shown) = e.label().map(str::to_string).or(action.clone()) {
+                        self.link(&shown, None, action.as_deref().unwrap_or_default(), bg);
+                    }
+                }
+                EntryType::Song => {
+                    if let Some(shown) = e.label() {
+                        match e.attr_str("A").or_else(|| e.attr_str("LM")) {
+                            Some(song) => self.link(shown, None, &song, bg),
+                            None => self.text(shown, fg, bg),
+                        }
+                    }
+                }
+                EntryType::HtmlCode => {
+                    let html = e.tag.clone().unwrap_or_default();
+                    self.raw(&html);
+                }
+                EntryType::Sprite => self.sprite(e, fg, bg),
+                EntryType::InsBin => match e.label() {
+                    Some(tag) => self.text(tag, fg, bg),
+                    None => match e.bin().and_then(|n| self.img(n, "")) {
+                        Some(img) => self.raw(&img),
+                        None => self.text("[bin]", fg, bg),
+                    },
+                },
+                EntryType::Foreground
+                | EntryType::Background
+                | EntryType::DftForeground
+                | EntryType::DftBackground
+                | EntryType::Indent
+                | EntryType::Blink
+                | EntryType::Invert
+                | EntryType::Highlight
+                | EntryType::Underline => {}
+                _ => {
+                    if let Some(tag) = e.tag.as_deref() {
+                        self.text(tag, fg, bg);
+                    }
+                }
+            }
+        }
+
+        self.layouter.push(e);
+        let indent = self.layouter.style().indent;
+        if indent != style.indent {
+            self.indent_changed(indent);
+        }
+    }
+
+    fn finish(mut self, title: &str) -> String {
+        while !self.trees.is_empty() {
+            self.close_tree();
+        }
+        self.flush_newlines();
+
+        let mut out = String::new();
+        out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        out.push_str(&format!("<title>{}</title>\n", escape_html(title)));
+        out.push_str("<style>\n");
+        out.push_str(&format!(
+            "body {{ background: {}; color: {}; }}\n",
+            css_color(PAGE_BG),
+            css_color(PAGE_FG)
+        ));
+        out.push_str(".doc { font-family: monospace; white-space: pre; line-height: 1.2; }\n");
+        out.push_str(".center { text-align: center; }\n");
+        out.push_str(&format!("a, .link {{ color: {}; }}\n", css_color(LINK_FG)));
+        out.push_str(&format!(
+            "summary {{ color: {}; cursor: pointer; }}\n",
+            css_color(TREE_FG)
+        ));
+        out.push_str("img { vertical-align: top; }\n");
+        out.push_str("</style>\n</head>\n<body>\n<div class=\"doc\">");
+        out.push_str(&self.body);
+        out.push_str("</div>\n</body>\n</html>\n");
+        out
+    }
+
+    /// `$SP$`: the image goes after the tag, or before it with `+FST`.
+    fn sprite(&mut self, e: &DocEntry, fg: u8, bg: u8) {
+        let tag = e.label().unwrap_or_default();
+        let img = e.bin().and_then(|n| self.img(n, tag));
+        let action = e.left_macro();
+        let show_tag = |w: &mut Self| match &action {
+            Some(action) => w.link(tag, None, action, bg),
+            None => w.text(tag, fg, bg),
+        };
+        match img {
+            Some(img) if e.has_flag("FST") => {
+                self.raw(&img);
+                show_tag(self);
+            }
+            Some(img) => {
+                show_tag(self);
+                self.raw(&img);
+            }
+            None if tag.is_empty() => self.text("[sprite]", fg, bg),
+            None => show_tag(self),
+        }
+    }
+
+    fn img(&self, bin: u32, alt: &str) -> Option<String> {
+        let png = sprite_png(self.bins.get(&bin)?)?.ok()?;
+        Some(format!(
+            "<img alt=\"{}\" src=\"data:image/png;base64,{}\">",
+            escape_html(alt),
+            base64(&png)
+        ))
+    }
+
+    fn open_tree(&mut self, e: &DocEntry) {
+        let indent = self.layouter.style().indent;
+        // An earlier tree at this level that never got a body ends here.
+        while self
+            .trees
+            .last()
+            .is_some_and(|&(at, entered)| !entered && indent <= at)
+        {
+            self.close_tree();
+        }
+        let label = e.tag.as_deref().unwrap_or_default();
+        let open = if e.has_flag("C") { "" } else { " open" };
+        self.block(&format!(
+            "<details{open}><summary>{}</summary>",
+            escape_html(label)
+        ));
+        self.trees.push((indent, false));
+    }
+
+    fn close_tree(&mut self) {
+        self.trees.pop();
+        self.pending_newlines = self.pending_newlines.saturating_sub(1);
+        self.flush_newlines();
+        self.body.push_str("</details>");
+        self.at_line_start = true;
+    }
+
+    fn indent_changed(&mut self, indent: usize) {
+        if let Some(top) = self.trees.last_mut()
+            && indent > top.0
+        {
+            top.1 = true;
+        }
+        while self
+            .trees
+            .last()
+            .is_some_and(|&(at, entered)| entered && indent <= at)
+        {
+            self.close_tree();
+        }
+    }
+
+    /// Writes a block-level element, which breaks the line on both sides by itself.
+    fn block(&mut self, html: &str) {
+        self.pending_newlines = self.pending_newlines.saturating_sub(1);
+        self.flush_newlines();
+        self.body.push_str(html);
+        self.at_line_start = true;
+        self.swallow_newline = true;
+    }
+
+    fn raw(&mut self, html: &str) {
+        self.begin_inline();
+        self.body.push_str(html);
+    }
+
+    fn new_line(&mut self) {
+        if std::mem::take(&mut self.swallow_newline) {
+            return;
+        }
+        self.pending_newlines += 1;
+        self.at_line_start = true;
+    }
+
+    fn flush_newlines(&mut self) {
+        for _ in 0..std::mem::take(&mut self.pending_newlines) {
+            self.body.push('\n');
+        }
+    }
+
+    /// Ends pending line breaks and indents a fresh line before inline content.
+    fn begin_inline(&mut self) {
+        self.swallow_newline = false;
+        self.flush_newlines();
+        if std::mem::take(&mut self.at_line_start) {
+            let indent = self.layouter.style().indent;
+            self.body.push_str(&" ".repeat(indent));
+        }
+    }
+
+    fn span(&self, text: &str, fg: u8, bg: u8) -> String {
+        let text = escape_html(text);
+        let mut style = String::new();
+        if fg != PAGE_FG {
+            style.push_str(&format!("color: {};", css_color(fg)));
+        }
+        if bg != PAGE_BG {
+            style.push_str(&format!("background: {};", css_color(bg)));
+        }
+        if style.is_empty() {
+            text
+        } else {
+            format!("<span style=\"{style}\">{text}</span>")
+        }
+    }
+
+    fn text(&mut self, text: &str, fg: u8, bg: u8) {
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.new_line();
+            }
+            if line.is_empty() {
+                continue;
+            }
+            self.begin_inline();
+            let html = self.span(line, fg, bg);
+            self.body.push_str(&html);
+        }
+    }
+
+    /// A link; without an `href` (macros, songs, TempleOS-only specs) it is a styled span with
+    /// the target as its tooltip.
+    fn link(&mut self, label: &str, href: Option<String>, target: &str, bg: u8) {
+        if label.is_empty() {
+            return;
+        }
+        self.begin_inline();
+        let bg = if bg != PAGE_BG {
+            format!(" style=\"background: {};\"", css_color(bg))
+        } else {
+            String::new()
+        };
+        let label = escape_html(label);
+        let title = escape_html(target.trim());
+        let html = match href {
+            Some(href) => format!(
+                "<a href=\"{}\" title=\"{title}\"{bg}>{label}</a>",
+                escape_html(&href)
+            ),
+            None => format!("<span class=\"link\" title=\"{title}\"{bg}>{label}</span>"),
+        };
+        self.body.push_str(&html);
+    }
+}
@@ -0,0 +1,981 @@
+//! Lays DolDoc entries out on a grid of character cells, collecting the links, anchors, sprites
+//! and form fields along the way.
+
+use std::{collections::BTreeMap, ops::Range};
+
+use super::{DocEntry, EntryType, FormEntry, FormKind, parse, parse_color};
+use crate::{assets, sprite};
+
+/// Size of a character cell in pixels (TempleOS' 8x8 font).
+pub const CELL_W: i32 = 8;
+pub const CELL_H: i32 = 8;
+
+pub(super) const LINK_FG: u8 = 10;
+pub(super) const TREE_FG: u8 = 14;
+const HIGHLIGHT_FG: u8 = 11;
+const UNDERLINE_FG: u8 = 14;
+/// Blinking text gets a strong background; hosts that animate also flip its [`DocCell::blink`]
+/// cells.
+const BLINK_BG: u8 = 4;
+/// Dark gray dot shown in edit mode in place of commands with no visible text.
+const MARKER_CH: u8 = 0xF9;
+const MARKER_FG: u8 = 8;
+const TAB_COLS: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LayoutOptions {
+    /// Line width in cells; text wraps here outside edit mode.
+    pub cols: usize,
+    /// Default colors (`$FG$`/`$BG$` go back to these).
+    pub fg: u8,
+    pub bg: u8,
+    /// Source-faithful layout for editors: no wrapping, cursor moves or trees breaking lines, and
+    /// a marker cell for every command that shows no text, so each command stays visible.
+    pub edit: bool,
+    /// Hide `// comments` in text, which TempleOS sources embed but don't mean to show.
+    pub strip_comments: bool,
+}
+
+impl LayoutOptions {
+    pub fn view(cols: usize, fg: u8, bg: u8) -> Self {
+        Self {
+            cols: cols.max(1),
+            fg,
+            bg,
+            edit: false,
+            strip_comments: false,
+        }
+    }
+
+    pub fn edit(cols: usize, fg: u8, bg: u8) -> Self {
+        Self {
+            edit: true,
+            ..Self::view(cols, fg, bg)
+        }
+    }
+}
+
+/// Colors and indentation in effect at some point of the document. Carry it between calls to
+/// lay a document out piecewise (an editor does one source line at a time).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DocStyle {
+    pub fg: u8,
+    pub bg: u8,
+    /// Indentation in cells, clamped to the line.
+    pub indent: usize,
+    /// Sum of all `$ID$`s so far; nesting stays balanced even where it went below zero.
+    indent_raw: i64,
+    /// Colors to restore when `$BK$`/`$IV$`/`$HL$`/`$UL$` are switched off again.
+    blink_bg: Option<u8>,
+    invert: Option<(u8, u8)>,
+    highlight_fg: Option<u8>,
+    underline_fg: Option<u8>,
+}
+
+impl DocStyle {
+    pub fn new(fg: u8, bg: u8) -> Self {
+        Self {
+            fg,
+            bg,
+            indent: 0,
+            indent_raw: 0,
+            blink_bg: None,
+            invert: None,
+            highlight_fg: None,
+            underline_fg: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocCell {
+    pub ch: u8,
+    pub fg: u8,
+    pub bg: u8,
+    /// Source bytes the cell shows (a whole command for its label cells); `None` for padding.
+    pub src: Option<Range<usize>>,
+    /// Inside `$UL,1$`: drawn with a line under the glyph.
+    pub underline: bool,
+    /// Inside `$BK,1$`.
+    pub blink: bool,
+}
+
+impl DocCell {
+    fn blank(fg: u8, bg: u8) -> Self {
+        Self {
+            ch: b' ',
+            fg,
+            bg,
+            src: None,
+            underline: false,
+            blink: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LinkTarget {
+    /// `$LK$`: another document (`FI:`, `FF:`, `MN:`... specs or a plain path).
+    Doc(String),
+    /// HolyC run by `$MA$`, or by clicking a sprite with `LM="..."`.
+    Macro(String),
+    /// `$SO$`: a Psalmody song.
+    Song(String),
+    /// First URL of an `$HC$` HTML block.
+    Url(String),
+    /// Index into [`DocLayout::forms`].
+    Form(usize),
+    /// `$TR$` node: index into [`DocLayout::trees`].
+    Tree(usize),
+}
+
+/// A clickable run of cells on one line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocLink {
+    pub line: usize,
+    pub col_start: usize,
+    pub col_end: usize,
+    pub target: LinkTarget,
+}
+
+/// A sprite placed by `$SP$`/`$IB$`, with the cells its bounding box covers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocSprite {
+    pub anchor_line: usize,
+    pub anchor_col: usize,
+    pub bbox_line0: i32,
+    pub bbox_col0: i32,
+    pub bbox_line1: i32,
+    pub bbox_col1: i32,
+    pub bin_num: u32,
+    pub action: Option<String>,
+}
+
+/// A `$TR$` tree node. Its subtree is what follows its line while `$ID$` keeps the indent
+/// deeper than the node's own, as in TempleOS.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocTree {
+    /// Line of the node, or of the collapsed node hiding it.
+    pub line: usize,
+    pub collapsed: bool,
+    /// Inside a collapsed subtree.
+    pub hidden: bool,
+}
+
+/// Collapsed (`true`) or expanded state of `$TR$` nodes by index (document order), overriding
+/// their `+C` flag.
+pub type TreeState = BTreeMap<usize, bool>;
+
+/// A form entry (`$DA$`, `$CB$`...) and where its command sits in the document source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormField {
+    pub range: Range<usize>,
+    pub entry: FormEntry,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DocLayout {
+    /// Display lines; cells past a line's end are blank.
+    pub lines: Vec<Vec<DocCell>>,
+    pub links: Vec<DocLink>,
+    /// `$AN$` names and the line they sit on.
+    pub anchors: BTreeMap<String, usize>,
+    pub sprites: Vec<DocSprite>,
+    pub forms: Vec<FormField>,
+    /// Every `$TR$` node in document order, hidden ones included.
+    pub trees: Vec<DocTree>,
+}
+
+impl DocLayout {
+    /// Line `idx` as plain text (CP437 decoded), without trailing blanks.
+    pub fn line_text(&self, idx: usize) -> String {
+        let Some(line) = self.lines.get(idx) else {
+            return String::new();
+        };
+        let bytes: Vec<u8> = line.iter().map(|c| c.ch).collect();
+        assets::decode_cp437_bytes(&bytes).trim_end().to_string()
+    }
+}
+
+/// Lays out a whole document.
+pub fn layout(text: &str, bins: &BTreeMap<u32, Vec<u8>>, opts: LayoutOptions) -> DocLayout {
+    layout_with_trees(text, bins, opts, &TreeState::new())
+}
+
+/// Lays out a whole document with some `$TR$` nodes opened or closed.
+pub fn layout_with_trees(
+    text: &str,
+    bins: &BTreeMap<u32, Vec<u8>>,
+    opts: LayoutOptions,
+    trees: &TreeState,
+) -> DocLayout {
+    let mut l = Layouter::new(opts, bins).with_trees(trees);
+    for entry in parse(text) {
+        l.push(&entry);
+    }
+    l.finish()
+}
+
+/// A collapsed `$TR$` being skipped: the node's line and indent, and whether its line (which
+/// stays visible) has ended.
+#[derive(Clone, Copy, Debug)]
+struct Collapsed {
+    line: usize,
+    indent: i64,
+    past_line: bool,
+}
+
+/// Incremental layout: feed entries with [`Layouter::push`], then take the result.
+pub struct Layouter<'a> {
+    opts: LayoutOptions,
+    bins: &'a BTreeMap<u32, Vec<u8>>,
+    style: DocStyle,
+    default_fg: u8,
+    default_bg: u8,
+    out: DocLayout,
+    line: usize,
+    col: usize,
+    trees: Option<&'a TreeState>,
+    collapsed: Option<Collapsed>,
+}
+
+impl<'a> Layouter<'a> {
+    pub fn new(opts: LayoutOptions, bins: &'a BTreeMap<u32, Vec<u8>>) -> Self {
+        Self {
+            opts,
+            bins,
+            style: DocStyle::new(opts.fg, opts.bg),
+            default_fg: opts.fg,
+            default_bg: opts.bg,
+            out: DocLayout {
+                lines: vec![Vec::new()],
+                ..DocLayout::default()
+            },
+            line: 0,
+            col: 0,
+            trees: None,
+            collapsed: None,
+        }
+    }
+
+    /// Opens or closes `$TR$` nodes against their `+C` flags.
+    pub fn with_trees(mut self, trees: &'a TreeState) -> Self {
+        self.trees = Some(trees);
+        self
+    }
+
+    /// Starts from `style` instead of the defaults (continuing an earlier layout).
+    pub fn with_style(mut self, style: DocStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Starts at column `col` of the first line, as when appending to a line that already has
+    /// text on it.
+    pub fn at_col(mut self, col: usize) -> Self {
+        self.col = col.min(self.opts.cols);
+        self
+    }
+
+    pub fn style(&self) -> DocStyle {
+        self.style
+    }
+
+    /// Line and column the next cell goes to.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.line, self.col)
+    }
+
+    pub fn finish(self) -> DocLayout {
+        self.out
+    }
+
+    pub fn push(&mut self, e: &DocEntry) {
+        if self.skip_collapsed(e) {
+            return;
+        }
+        let fg = self.style.fg;
+        let bg = self.style.bg;
+        let edit = self.opts.edit;
+
+        if let Some(entry) = FormEntry::from_entry(e) {
+            let color = form_fg(entry.kind, fg);
+            let target = LinkTarget::Form(self.out.forms.len());
+            self.text(&entry.display(), color, bg, &e.range, false, Some(&target));
+            self.out.forms.push(FormField {
+                range: e.range.clone(),
+                entry,
+            });
+            return;
+        }
+
+        match e.ty {
+            EntryType::Text if e.code.is_empty() => {
+                let text = e.tag.as_deref().unwrap_or_default();
+                let per_char = text.len() == e.range.len();
+                self.text(text, fg, bg, &e.range, per_char, None);
+            }
+            EntryType::Text => match e.tag.as_deref() {
+                Some(s) if e.has_flag("CX") && !edit => self.text_centered(s, &e.range),
+                Some(s) => self.text(s, fg, bg, &e.range, false, None),
+                None => self.marker(&e.range),
+            },
+            EntryType::NewLine => self.new_line(),
+            EntryType::Foreground | EntryType::Background => {
+                let fore = e.ty == EntryType::Foreground;
+                let arg = e.args.first().map(|s| s.trim()).unwrap_or_default();
+                let color = if arg.is_empty() {
+                    Some(if fore {
+                        self.default_fg
+                    } else {
+                        self.default_bg
+                    })
+                } else {
+                    parse_color(arg)
+                };
+                match color {
+                    Some(c) if fore => self.style.fg = c,
+                    Some(c) => self.style.bg = c,
+                    None => {}
+                }
+                self.marker(&e.range);
+            }
+            EntryType::DftForeground | EntryType::DftBackground => {
+                if let Some(c) = e.args.first().and_then(|a| parse_color(a)) {
+                    if e.ty == EntryType::DftForeground {
+                        self.default_fg = c;
+                        self.style.fg = c;
+                    } else {
+                        self.default_bg = c;
+                        self.style.bg = c;
+                    }
+                }
+                self.marker(&e.range);
+            }
+            EntryType::Indent => {
+                if let Some(delta) = e.arg_i64(0) {
+                    let max = self.opts.cols.saturating_sub(1) as i64;
+                    self.style.indent_raw = self.style.indent_raw.saturating_add(delta);
+                    self.style.indent = self.style.indent_raw.clamp(0, max) as usize;
+                }
+                self.marker(&e.range);
+            }
+            EntryType::CursorMove if !edit => self.cursor_move(e),
+            EntryType::Blink => {
+                if e.arg_i64(0).unwrap_or(0) == 0 {
+                    if let Some(prev) = self.style.blink_bg.take() {
+                        self.style.bg = prev;
+                    }
+                } else {
+                    self.style.blink_bg.get_or_insert(bg);
+                    self.style.bg = BLINK_BG;
+                }
+                self.marker(&e.range);
+            }
+            EntryType::Invert => {
+                if e.arg_i64(0).unwrap_or(0) == 0 {
+                    if let Some((prev_fg, prev_bg)) = self.style.invert.take() {
+                        self.style.fg = prev_fg;
+                        self.style.bg = prev_bg;
+                    }
+                } else if self.style.invert.is_none() {
+                    self.style.invert = Some((fg, bg));
+                    self.style.fg = bg;
+                    self.style.bg = fg;
+                }
+                self.marker(&e.range);
+            }
+            EntryType::Highlight | EntryType::Underline => {
+                // No syntax highlighting yet; both tint the text, and underlined cells are
+                // flagged for the renderer to draw the line.
+                let (saved, tint) = if e.ty == EntryType::Highlight {
+                    (&mut self.style.highlight_fg, HIGHLIGHT_FG)
+                } else {
+                    (&mut self.style.underline_fg, UNDERLINE_FG)
+                };
+                if e.arg_i64(0).unwrap_or(0) == 0 {
+                    if let Some(prev) = saved.take() {
+                        self.style.fg = prev;
+                    }
+                } else {
+                    saved.get_or_insert(fg);
+                    self.style.fg = tint;
+                }
+                self.marker(&e.range);
+            }
+            EntryType::Anchor => {
+                if let Some(name) = e.attr_str("A") {
+                    self.out.anchors.insert(name, self.line);
+                }
+                self.marker(&e.range);
+            }
+            EntryType::Tree => match e.tag.as_deref() {
+                Some(label) if edit => {
+                    let mark = if e.has_flag("C") { "+]" } else { "-]" };
+                    self.text(
+                        &format!("{mark}{label}"),
+                        TREE_FG,
+                        bg,
+                        &e.range,
+                        false,
+                        None,
+                    );
+                }
+                Some(label) => {
+                    if self.col != 0 {
+                        self.new_line();
+                    }
+                    let index = self.out.trees.len();
+                    let collapsed = self.tree_collapsed(index, e);
+                    let mark = if collapsed { "+]" } else { "-]" };
+                    let target = LinkTarget::Tree(index);
+                    let default_bg = self.default_bg;
+                    let line = self.line;
+                    self.text(
+                        &format!("{mark}{label}"),
+                        TREE_FG,
+                        default_bg,
+                        &e.range,
+                        false,
+                        Some(&target),
+                    );
+                    self.out.trees.push(DocTree {
+                        line,
+                        collapsed,
+                        hidden: false,
+                    });
+                    if collapsed {
+                        self.collapsed = Some(Collapsed {
+                            line,
+                            indent: self.style.indent_raw,
+                            past_line: false,
+                        });
+                    }
+                }
+                None => self.marker(&e.range),
+            },
+            EntryType::Link => {
+                let Some(label) = e.tag.as_deref() else {
+                    self.marker(&e.range);
+                    return;
+                };
+                let target = e
+                    .attr("A")
+                    .or(e.args.first().map(String::as_str))
+                    .unwrap_or(label)
+                    .trim();
+                if target.is_empty() {
+                    self.marker(&e.range);
+                    return;
+                }
+                let target = LinkTarget::Doc(target.to_string());
+                self.text(label, LINK_FG, bg, &e.range, false, Some(&target));
+            }
+            EntryType::Macro => {
+                let action = e.left_macro();
+                let shown = e
+                    .label()
+                    .map(str::to_string)
+                    .or_else(|| action.clone())
+                    .unwrap_or_default();
+                let shown = shown.trim();
+                if shown.is_empty() {
+                    self.marker(&e.range);
+                    return;
+                }
+                let target = match action {
+                    Some(action) => LinkTarget::Macro(action),
+                    None => LinkTarget::Doc(shown.to_string()),
+                };
+                self.text(shown, LINK_FG, bg, &e.range, false, Some(&target));
+            }
+            // HTML blocks (often images/embeds) only matter for HTML export; show a placeholder,
+            // or the first URL as a link.
+            EntryType::HtmlCode => {
+                let html = e.tag.as_deref().unwrap_or_default();
+                match extract_first_http_url(html) {
+                    Some(url) => {
+                        let label = url_label(&url);
+                        let target = LinkTarget::Url(url);
+                        self.text(&label, LINK_FG, bg, &e.range, false, Some(&target));
+                    }
+                    None => self.text("[html]", fg, bg, &e.range, false, None),
+                }
+            }
+            EntryType::Song => {
+                let Some(shown) = e.label().map(str::trim) else {
+                    self.marker(&e.range);
+                    return;
+                };
+                match e.attr_str("A").or_else(|| e.attr_str("LM")) {
+                    Some(song) => {
+                        let target = LinkTarget::Song(song);
+                        self.text(shown, LINK_FG, bg, &e.range, false, Some(&target));
+                    }
+                    None => self.text(shown, fg, bg, &e.range, false, None),
+                }
+            }
+            EntryType::Sprite => self.sprite(e),
+            EntryType::InsBin => self.ins_bin(e),
+            _ => match e.tag.as_deref() {
+                Some(s) => self.text(s, fg, bg, &e.range, false, None),
+                None => self.marker(&e.range),
+            },
+        }
+    }
+
+    /// `$SP$`: the sprite (`BI=<n>`, stored after the document's NUL terminator) is anchored
+    /// after its tag, or before it with `+FST`.
+    fn tree_collapsed(&self, index: usize, e: &DocEntry) -> bool {
+        self.trees
+            .and_then(|t| t.get(&index))
+            .copied()
+            .unwrap_or_else(|| e.has_flag("C"))
+    }
+
+    /// Inside a collapsed tree's subtree, drops `e` (true) or lets it through: the node's own
+    /// line, `$ID$`s and color changes, so the state after the subtree is what it would be
+    /// expanded. Anchors in the subtree point at the node.
+    fn skip_collapsed(&mut self, e: &DocEntry) -> bool {
+        let Some(c) = self.collapsed.as_mut() else {
+            return false;
+        };
+        if !c.past_line {
+            c.past_line = e.ty == EntryType::NewLine;
+            return false;
+        }
+        let c = *c;
+        match e.ty {
+            EntryType::Indent => {
+                if let Some(delta) = e.arg_i64(0) {
+                    let max = self.opts.cols.saturating_sub(1) as i64;
+                    self.style.indent_raw = self.style.indent_raw.saturating_add(delta);
+                    self.style.indent = self.style.indent_raw.clamp(0, max) as usize;
+                }
+                if self.style.indent_raw <= c.indent {
+                    self.collapsed = None;
+                }
+                return true;
+            }
+            _ if self.style.indent_raw <= c.indent => {
+                self.collapsed = None;
+                return false;
+            }
+            EntryType::Foreground
+            | EntryType::Background
+            | EntryType::DftForeground
+            | EntryType::DftBackground
+            | EntryType::Blink
+            | EntryType::Invert
+            | EntryType::Highlight
+            | EntryType::Underline => {
+                // Style only: nothing is drawn outside edit mode.
+                self.collapsed = None;
+                self.push(e);
+                self.collapsed = Some(c);
+            }
+            EntryType::Anchor => {
+                if let Some(name) = e.attr_str("A") {
+                    self.out.anchors.insert(name, c.line);
+                }
+            }
+            EntryType::Tree if e.tag.is_some() => {
+                let index = self.out.trees.len();
+                let collapsed = self.tree_collapsed(index, e);
+                self.out.trees.push(DocTree {
+                    line: c.line,
+                    collapsed,
+                    hidden: true,
+                });
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn sprite(&mut self, e: &DocEntry) {
+        let tag = e.tag.as_deref().unwrap_or_default();
+        let action = e.left_macro();
+        let start = (self.line, self.col);
+        if !tag.is_empty() {
+            match &action {
+                Some(action) => {
+                    let target = LinkTarget::Macro(action.clone());
+                    self.text(tag, LINK_FG, self.style.bg, &e.range, false, Some(&target));
+                }
+                None => self.text(tag, self.style.fg, self.style.bg, &e.range, false, None),
+            }
+        }
+        let anchor = if e.has_flag("FST") {
+            start
+        } else {
+            (self.line, self.col)
+        };
+
+        match e.bin().filter(|n| self.bins.contains_key(n)) {
+            Some(bin_num) => self.push_sprite(anchor, bin_num, action),
+            None if tag.is_empty() && !self.opts.edit => {
+                let (fg, bg) = (self.style.fg, self.style.bg);
+                self.text("[sprite]", fg, bg, &e.range, false, None);
+            }
+            None => {}
+        }
+        if tag.is_empty() {
+            self.marker(&e.range);
+        }
+    }
+
+    /// `$IB$` (insert binary): untagged ones show their sprite inline and reserve its width.
+    fn ins_bin(&mut self, e: &DocEntry) {
+        let (fg, bg) = (self.style.fg, self.style.bg);
+        if let Some(tag) = e.label() {
+            self.text(tag, fg, bg, &e.range, false, None);
+            return;
+        }
+        let Some((bin_num, data)) = e
+            .bin()
+            .and_then(|n| self.bins.get(&n).map(|data| (n, data)))
+        else {
+            self.text("[bin]", fg, bg, &e.range, false, None);
+            return;
+        };
+        let px_right = sprite::sprite_bounds(data).unwrap_or_default().x1.max(0) as usize;
+        self.push_sprite((self.line, self.col), bin_num, e.left_macro());
+        if self.opts.edit {
+            self.marker(&e.range);
+            return;
+        }
+        let reserve = px_right
+            .div_ceil(CELL_W as usize)
+            .clamp(1, self.opts.cols)
+            .min(self.opts.cols.saturating_sub(self.col));
+        for _ in 0..reserve {
+            self.put(b' ', fg, bg, Some(e.range.clone()));
+        }
+    }
+
+    fn push_sprite(&mut self, (line, col): (usize, usize), bin_num: u32, action: Option<String>) {
+        let bounds = self
+            .bins
+            .get(&bin_num)
+            .and_then(|data| sprite::sprite_bounds(data))
+            .unwrap_or_default();
+        let (bbox_line0, bbox_col0, bbox_line1, bbox_col1) = sprite_bbox_cells(line, col, bounds);
+        self.out.sprites.push(DocSprite {
+            anchor_line: line,
+            anchor_col: col,
+            bbox_line0,
+            bbox_col0,
+            bbox_line1,
+            bbox_col1,
+            bin_num,
+            action,
+        });
+    }
+
+    /// `$CM$` moves the cursor `LE=` columns (`+LX`/`+RX`: from the left/right edge) and `RE=`
+    /// lines down; `$CM-RE$` only moves within the line.
+    fn cursor_move(&mut self, e: &DocEntry) {
+        let x = e.attr_i64("LE").or_else(|| e.arg_i64(0));
+        if e.clears_flag("RE") {
+            match x {
+                Some(x) if x > 0 => {
+                    let (fg, bg) = (self.style.fg, self.style.bg);
+                    for _ in 0..x {
+                        self.put(b' ', fg, bg, None);
+                    }
+                }
+                Some(x) if x < 0 => self.col = self.col.saturating_sub(x.unsigned_abs() as usize),
+                _ => {}
+            }
+            return;
+        }
+
+        let y = e.attr_i64("RE").or_else(|| e.arg_i64(1));
+        for _ in 0..y.unwrap_or(0).max(0) {
+            self.new_line();
+        }
+        if let Some(x) = x {
+            let cols = self.opts.cols as i64;
+            let col = if e.has_flag("LX") {
+                x
+            } else if e.has_flag("RX") {
+                cols + x
+            } else {
+                self.col as i64 + x
+            };
+            self.col = col.clamp(0, cols - 1) as usize;
+        }
+    }
+
+    /// `$TX+CX$`: centered on the current line.
+    fn text_centered(&mut self, s: &str, src: &Range<usize>) {
+        let cols = self.opts.cols;
+        let len = s.chars().count().min(cols);
+        self.col = (cols - len) / 2;
+        let (fg, bg) = (self.style.fg, self.style.bg);
+        for ch in s.chars().take(len) {
+            self.set_cell(assets::encode_cp437(ch), fg, bg, Some(src.clone()));
+            self.col += 1;
+        }
+    }
+
+    fn marker(&mut self, src: &Range<usize>) {
+        if self.opts.edit {
+            let bg = self.default_bg;
+            self.put(MARKER_CH, MARKER_FG, bg, Some(src.clone()));
+        }
+    }
+
+    /// Lays out `s`; cells map to `src` as a whole, or byte for byte with `per_char` (plain text).
+    fn text(
+        &mut self,
+        s: &str,
+        fg: u8,
+        bg: u8,
+        src: &Range<usize>,
+        per_char: bool,
+        link: Option<&LinkTarget>,
+    ) {
+        let mut run: Option<(usize, usize, usize)> = None;
+        let flush = |out: &mut DocLayout, run: &mut Option<(usize, usize, usize)>| {
+            if let (Some((line, col_start, col_end)), Some(target)) = (run.take(), link) {
+                out.links.push(DocLink {
+                    line,
+                    col_start,
+                    col_end,
+                    target: target.clone(),
+                });
+            }
+        };
+
+        for (line_off, line) in split_lines_with_offsets(s) {
+            if line_off > 0 {
+                flush(&mut self.out, &mut run);
+                self.new_line();
+            }
+            let body = if self.opts.strip_comments {
+                strip_comment(line)
+            } else {
+                line
+            };
+            for (i, ch) in body.char_indices() {
+                let cell_src = if per_char {
+                    let at = src.start + line_off + i;
+                    at..at + ch.len_utf8()
+                } else {
+                    src.clone()
+                };
+                let placed: Vec<(usize, usize)> = match ch {
+                    '\t' if !self.opts.edit => {
+                        let next = (self.col / TAB_COLS + 1) * TAB_COLS;
+                        let n = next.saturating_sub(self.col).max(1);
+                        (0..n)
+                            .map(|_| self.put(b' ', fg, bg, Some(cell_src.clone())))
+                            .collect()
+                    }
+                    '\t' => vec![self.put(b' ', fg, bg, Some(cell_src))],
+                    '\r' if !self.opts.edit => {
+                        self.col = 0;
+                        Vec::new()
+                    }
+                    _ => vec![self.put(assets::encode_cp437(ch), fg, bg, Some(cell_src))],
+                };
+                for (line, col) in placed {
+                    match &mut run {
+                        Some((run_line, _, end)) if *run_line == line && *end == col => *end += 1,
+                        _ => {
+                            flush(&mut self.out, &mut run);
+                            run = Some((line, col, col + 1));
+                        }
+                    }
+                }
+            }
+        }
+        flush(&mut self.out, &mut run);
+    }
+
+    /// Places one cell at the cursor (indenting fresh lines first) and advances, wrapping at
+    /// the line width outside edit mode. Returns where the cell went.
+    fn put(&mut self, ch: u8, fg: u8, bg: u8, src: Option<Range<usize>>) -> (usize, usize) {
+        let wrap = !self.opts.edit;
+        if wrap && self.col >= self.opts.cols {
+            self.new_line();
+        }
+        if self.col == 0 && self.style.indent > 0 {
+            let (ifg, ibg) = (self.style.fg, self.style.bg);
+            for _ in 0..self.style.indent.min(self.opts.cols) {
+                self.set_cell(b' ', ifg, ibg, None);
+                self.col += 1;
+            }
+        }
+        let at = (self.line, self.col);
+        self.set_cell(ch, fg, bg, src);
+        self.col += 1;
+        if wrap && self.col >= self.opts.cols {
+            self.new_line();
+        }
+        at
+    }
+
+    fn set_cell(&mut self, ch: u8, fg: u8, bg: u8, src: Option<Range<usize>>) {
+        let blank = DocCell::blank(self.opts.fg, self.opts.bg);
+        let line = &mut self.out.lines[self.line];
+        if line.len() <= self.col {
+            line.resize(self.col + 1, blank);
+        }
+        // Padding carries colors only; underlines and blinking stay on the text itself.
+        let shown = src.is_some();
+        line[self.col] = DocCell {
+            ch,
+            fg,
+            bg,
+            underline: shown && self.style.underline_fg.is_some(),
+            blink: shown && self.style.blink_bg.is_some(),
+            src,
+        };
+    }
+
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.col = 0;
+        if self.out.lines.len() <= self.line {
+            self.out.lines.resize(self.line + 1, Vec::new());
+        }
+    }
+}
+
+/// Text color of a form entry drawn over text in `fg`.
+pub(super) fn form_fg(kind: FormKind, fg: u8) -> u8 {
+    match kind {
+        FormKind::Data | FormKind::List => 11,
+        FormKind::CheckBox => fg,
+        FormKind::Button => 14,
+        FormKind::Menu => LINK_FG,
+    }
+}
+
+/// Splits at `\n`, yielding each line's byte offset within `s`.
+fn split_lines_with_offsets(s: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut off = 0usize;
+    s.split('\n').map(move |line| {
+        let at = off;
+        off += line.len() + 1;
+        (at, line)
+    })
+}
+
+/// Drops a trailing `// comment`, only when `//` starts the line or follows a blank (so URLs
+/// like `http://` survive).
+pub(super) fn strip_comment(line: &str) -> &str {
+    match line.find("//") {
+        Some(pos) if pos == 0 || line.as_bytes()[pos - 1].is_ascii_whitespace() => {
+            line[..pos].trim_end()
+        }
+        _ => line,
+    }
+}
+
+fn extract_first_http_url(html: &str) -> Option<String> {
+    let start = html.find("https://").or_else(|| html.find("http://"))?;
+    let rest = &html[start..];
+    let end = rest
+        .find(|c: char| c == '"' || c == '\'' || c.is_ascii_whitespace() || c == '<' || c == '>')
+        .unwrap_or(rest.len());
+    let url = rest[..end].trim().to_string();
+    if url.is_empty() { None } else { Some(url) }
+}
+
+fn url_label(url: &str) -> String {
+    let url = url.split('?').next().unwrap_or(url);
+    let url = url.split('#').next().unwrap_or(url);
+    let last = url.rsplit('/').next().unwrap_or(url).trim();
+    let mut label = if last.is_empty() { url } else { last }.to_string();
+    if label.len() > 32 {
+        label.truncate(29);
+        label.push_str("...");
+    }
+    label
+}
+
+/// Cells (`line0, col0, line1, col1`, end-exclusive) covered by a sprite anchored at a cell.
+fn sprite_bbox_cells(
+    anchor_line: usize,
+    anchor_col: usize,
+    bounds: sprite::SpriteBounds,
+) -> (i32, i32, i32, i32) {
+    let (mut x0, mut y0, mut x1, mut y1) = (bounds.x0, bounds.y0, bounds.x1, bounds.y1);
+    if x1 <= x0 {
+        x0 = 0;
+        x1 = CELL_W;
+    }
+    if y1 <= y0 {
+        y0 = 0;
+        y1 = CELL_H;
+    }
+
+    let anchor_line = anchor_line as i32;
+    let anchor_col = anchor_col as i32;
+    let col0 = anchor_col.saturating_add(x0.div_euclid(CELL_W));
+    let col1 = anchor_col.saturating_add(div_ceil_i32(x1, CELL_W));
+    let line0 = anchor_line.saturating_add(y0.div_euclid(CELL_H));
+    let line1 = anchor_line.saturating_add(div_ceil_i32(y1, CELL_H));
+
+    let col1 = if col1 <= col0 {
+        col0.saturating_add(1)
+    } else {
+        col1
+    };
+    let line1 = if line1 <= line0 {
+        line0.saturating_add(1)
+    } else {
+        line1
+    };
+    (line0, col0, line1, col1)
+}
+
+fn div_ceil_i32(v: i32, step: i32) -> i32 {
+    let q = v.div_euclid(step);
+    if v.rem_euclid(step) == 0 { q } else { q + 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doldoc_layout_collects_links_anchors_and_forms() {
+        let text = concat!(
+            "$AN,\"\",A=\"top\"$Intro $LK,\"Next\",A=\"FI:::/Doc/B.DD\"$\n",
+            "$ID,2$$MA-X+PU,\"Run\",LM=\"Beep;\"$ $CB+C,\"On\"$$ID,-2$\n",
+            "$HC,\"<img src=\\\"https://x.org/a.png\\\">\"$ // hidden\n",
+        );
+        let opts = LayoutOptions {
+            strip_comments: true,
+            ..LayoutOptions::view(80, 15, 0)
+        };
+        let built = layout(text, &BTreeMap::new(), opts);
+
+        assert_eq!(built.line_text(0), "Intro Next");
+        assert_eq!(built.line_text(1), "  Run [X] On");
+        assert_eq!(built.line_text(2), "a.png");
+        assert_eq!(built.anchors.get("top"), Some(&0));
+
+        let links: Vec<_> = built
+            .links
+            .iter()
+            .map(|l| (l.line, l.col_start, l.col_end, l.target.clone()))
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                (0, 6, 10, LinkTarget::Doc("FI:::/Doc/B.DD".to_string())),
+                (1, 2, 5, LinkTarget::Macro("Beep;".to_string())),
+                (1, 6, 12, LinkTarget::Form(0)),
+                (2, 0, 5, LinkTarget::Url("https://x.org/a.png".to_string())),
+            ]
+        );
+        assert_eq!(built.forms.len(), 1);
+        assert_eq!(&text[built.forms[0].range.clone()], "$CB+C,\"On\"$");
+    }
+}
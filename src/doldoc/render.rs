@@ -0,0 +1,258 @@
+//! Draws a [`DocLayout`] onto any [`SpriteTarget`].
+
+use std::collections::BTreeMap;
+
+use super::{CELL_H, CELL_W, DocLayout, DocSprite};
+use crate::{assets, rt::Rop, sprite::SpriteTarget};
+
+/// Where a layout is drawn: a window of `cols` x `rows` cells at pixel (`x`, `y`) showing the
+/// document from `first_line` on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: i32,
+    pub y: i32,
+    pub cols: usize,
+    pub rows: usize,
+    pub first_line: usize,
+}
+
+impl Viewport {
+    fn clip(&self) -> (i32, i32, i32, i32) {
+        let w = (self.cols as i32).saturating_mul(CELL_W);
+        let h = (self.rows as i32).saturating_mul(CELL_H);
+        (
+            self.x,
+            self.y,
+            self.x.saturating_add(w),
+            self.y.saturating_add(h),
+        )
+    }
+}
+
+/// Draws the text, then the sprites on top of it. Cells past the end of a line are left alone.
+pub fn render(
+    target: &mut impl SpriteTarget,
+    layout: &DocLayout,
+    bins: &BTreeMap<u32, Vec<u8>>,
+    view: &Viewport,
+) {
+    render_cells(target, layout, view);
+    render_sprites(target, &layout.sprites, bins, view);
+}
+
+pub fn render_cells(target: &mut impl SpriteTarget, layout: &DocLayout, view: &Viewport) {
+    for row in 0..view.rows {
+        let Some(line) = layout.lines.get(view.first_line + row) else {
+            break;
+        };
+        let y = view.y + row as i32 * CELL_H;
+        for (col, cell) in line.iter().take(view.cols).enumerate() {
+            let x = view.x + col as i32 * CELL_W;
+            target.fill_rect(x, y, CELL_W, CELL_H, cell.bg);
+            for gy in 0..CELL_H {
+                let bits = assets::sys_font_std_glyph_row_bits(cell.ch, gy as u8);
+                for gx in 0..CELL_W {
+                    if bits & (1u8 << gx) != 0 {
+                        target.set_pixel(x + gx, y + gy, cell.fg);
+                    }
+                }
+            }
+            if cell.underline {
+                target.fill_rect(x, y + CELL_H - 1, CELL_W, 1, cell.fg);
+            }
+        }
+    }
+}
+
+/// Draws the sprites that reach into the viewport, clipped to it.
+pub fn render_sprites(
+    target: &mut impl SpriteTarget,
+    sprites: &[DocSprite],
+    bins: &BTreeMap<u32, Vec<u8>>,
+    view: &Viewport,
+) {
+    let first = view.first_line as i32;
+    let end = first.saturating_add(view.rows as i32);
+    let mut clipped = Clipped {
+        inner: target,
+        rect: view.clip(),
+    };
+    for sp in sprites {
+        if sp.bbox_line1 <= first || sp.bbox_line0 >= end {
+            continue;
+        }
+        let Some(data) = bins.get(&sp.bin_num) else {
+            continue;
+        };
+        let x = view.x + sp.anchor_col as i32 * CELL_W;
+        let y = view.y + (sp.anchor_line as i32 - first) * CELL_H;
+        crate::sprite::sprite_render(&mut clipped, x, y, data);
+    }
+}
+
+/// Restricts drawing to `rect` (`x0, y0, x1, y1`, end-exclusive).
+struct Clipped<'t, T: SpriteTarget> {
+    inner: &'t mut T,
+    rect: (i32, i32, i32, i32),
+}
+
+impl<T: SpriteTarget> SpriteTarget for Clipped<'_, T> {
+    fn set_pixel(&mut self, x: i32, y: i32, color: u8) {
+        let (x0, y0, x1, y1) = self.rect;
+        if x >= x0 && y >= y0 && x < x1 && y < y1 {
+            self.inner.set_pixel(x, y, color);
+        }
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, w: i32, h: i32, color: u8) {
+        let (cx0, cy0, cx1, cy1) = self.rect;
+        let x0 = x.max(cx0);
+        let y0 = y.max(cy0);
+        let x1 = x.saturating_add(w).min(cx1);
+        let y1 = y.saturating_add(h).min(cy1);
+        if x1 > x0 && y1 > y0 {
+            self.inner.fill_rect(x0, y0, x1 - x0, y1 - y0, color);
+        }
+    }
+
+    fn blit_8bpp(
+        &mut self,
+        dst_x: i32,
+        dst_y: i32,
+        src_w: i32,
+        src_h: i32,
+        stride: i32,
+        src: &[u8],
+    ) {
+        if src_w <= 0 || src_h <= 0 || stride <= 0 {
+            return;
+        }
+        for row in 0..src_h {
+            let start = (row * stride) as usize;
+            let Some(row_src) = src.get(start..start + src_w as usize) else {
+                return;
+            };
+            for (col, &px) in row_src.iter().enumerate() {
+                // 0xFF is the transparent color of sprite bitmaps.
+                if px != 0xFF {
+                    self.set_pixel(dst_x + col as i32, dst_y + row, px);
+                }
+            }
+        }
+    }
+
+    fn rop(&self) -> Rop {
+        self.inner.rop()
+    }
+
+    fn set_rop(&mut self, rop: Rop) {
+        self.inner.set_rop(rop);
+    }
+}
+
+/// An 8-bit (palette index) offscreen image, for rendering documents and sprites off the screen.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    rop: Rop,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32, color: u8) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![color; width as usize * height as usize],
+            rop: Rop::default(),
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Option<u8> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.pixels
+            .get(y as usize * self.width as usize + x as usize)
+            .copied()
+    }
+
+    /// RGBA pixels through `palette`; `transparent` (if any) comes out fully transparent.
+    pub fn to_rgba(&self, palette: &[[u8; 4]; 256], transparent: Option<u8>) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.pixels.len() * 4);
+        for &px in &self.pixels {
+            if Some(px) == transparent {
+                out.extend_from_slice(&[0, 0, 0, 0]);
+            } else {
+                out.extend_from_slice(&palette[px as usize]);
+            }
+        }
+        out
+    }
+}
+
+impl SpriteTarget for Canvas {
+    fn set_pixel(&mut self, x: i32, y: i32, color: u8) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let px = &mut self.pixels[y as usize * self.width as usize + x as usize];
+        self.rop.plot(x, y, color, px);
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, w: i32, h: i32, color: u8) {
+        if w <= 0 || h <= 0 {
+            return;
+        }
+        let x0 = x.clamp(0, self.width as i32);
+        let y0 = y.clamp(0, self.height as i32);
+        let x1 = x.saturating_add(w).clamp(0, self.width as i32);
+        let y1 = y.saturating_add(h).clamp(0, self.height as i32);
+        if !self.rop.is_plain() {
+            for yy in y0..y1 {
+                for xx in x0..x1 {
+                    self.set_pixel(xx, yy, color);
+                }
+            }
+            return;
+        }
+        for yy in y0..y1 {
+            let row = yy as usize * self.width as usize;
+            self.pixels[row + x0 as usize..row + x1 as usize].fill(color);
+        }
+    }
+
+    fn blit_8bpp(
+        &mut self,
+        dst_x: i32,
+        dst_y: i32,
+        src_w: i32,
+        src_h: i32,
+        stride: i32,
+        src: &[u8],
+    ) {
+        if src_w <= 0 || src_h <= 0 || stride <= 0 {
+            return;
+        }
+        for row in 0..src_h {
+            let start = (row * stride) as usize;
+            let Some(row_src) = src.get(start..start + src_w as usize) else {
+                return;
+            };
+            for (col, &px) in row_src.iter().enumerate() {
+                if px != 0xFF {
+                    self.set_pixel(dst_x + col as i32, dst_y + row, self.rop.blit_color(px));
+                }
+            }
+        }
+    }
+
+    fn rop(&self) -> Rop {
+        self.rop
+    }
+
+    fn set_rop(&mut self, rop: Rop) {
+        self.rop = rop;
+    }
+}
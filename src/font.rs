@@ -0,0 +1,458 @@
+//! Bitmap fonts for text: TempleOS' 8x8 system font, and user fonts read from PSF (1 and 2) and
+//! BDF files under `TEMPLE_ROOT/Fonts`. Glyphs are indexed by CP437 code, like everything else
+//! that draws text, and drawn at whole-number scales.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::assets;
+
+/// Where user fonts live, under `TEMPLE_ROOT`.
+pub const FONTS_DIR: &str = "Fonts";
+
+/// The name that selects the built-in 8x8 font.
+pub const STD_FONT: &str = "std";
+
+/// The largest text scale windows and the terminal take.
+pub const MAX_SCALE: u32 = 8;
+
+/// Extensions tried, in order, after a bare font name.
+const FONT_EXTS: [&str; 3] = ["psf", "psfu", "bdf"];
+
+const MAX_WIDTH: u32 = 32;
+const MAX_HEIGHT: u32 = 64;
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE_512: u8 = 0x01;
+const PSF1_MODE_HAS_TAB: u8 = 0x06;
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Font {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    /// `height` rows for each of the 256 glyphs; bit `x` of a row is column `x`.
+    rows: Vec<u32>,
+}
+
+fn bad(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// A BDF bounding box (`FONTBOUNDINGBOX`, `BBX`): width, height and x/y offsets, each within
+/// the glyph sizes we support so the placement math can't overflow.
+fn bdf_box(rest: &str, what: &str) -> io::Result<[i32; 4]> {
+    let nums: Vec<i32> = rest
+        .split_whitespace()
+        .filter_map(|v| v.parse().ok())
+        .collect();
+    let (w, h) = (MAX_WIDTH as i32, MAX_HEIGHT as i32);
+    match nums[..] {
+        [bw, bh, xo, yo]
+            if (0..=w).contains(&bw)
+                && (0..=h).contains(&bh)
+                && (-w..=w).contains(&xo)
+                && (-h..=h).contains(&yo) =>
+        {
+            Ok([bw, bh, xo, yo])
+        }
+        _ => Err(bad(&format!("bad {what}"))),
+    }
+}
+
+/// The CP437 code `ch` is drawn with, if it has one.
+fn cp437_code(ch: char) -> Option<u8> {
+    let code = assets::encode_cp437(ch);
+    (code != b'?' || ch == '?').then_some(code)
+}
+
+impl Font {
+    fn blank(name: &str, width: u32, height: u32) -> io::Result<Self> {
+        if !(1..=MAX_WIDTH).contains(&width) || !(1..=MAX_HEIGHT).contains(&height) {
+            return Err(bad(&format!("unsupported glyph size {width}x{height}")));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            width,
+            height,
+            rows: vec![0; 256 * height as usize],
+        })
+    }
+
+    /// TempleOS' `sys_font_std`.
+    pub fn sys_std() -> Self {
+        Self::from_u64(STD_FONT, &assets::TEMPLEOS_SYS_FONT_STD_U64)
+    }
+
+    /// An 8x8 font in TempleOS' `text.font` layout: a byte per row, low bit leftmost.
+    pub fn from_u64(name: &str, glyphs: &[u64; 256]) -> Self {
+        let rows = glyphs
+            .iter()
+            .flat_map(|&g| (0..8).map(move |y| (g >> (y * 8)) as u32 & 0xFF))
+            .collect();
+        Self {
+            name: name.to_string(),
+            width: 8,
+            height: 8,
+            rows,
+        }
+    }
+
+    /// Row `y` of glyph `ch`.
+    pub fn row(&self, ch: u8, y: u32) -> u32 {
+        if y >= self.height {
+            return 0;
+        }
+        self.rows[ch as usize * self.height as usize + y as usize]
+    }
+
+    pub fn pixel(&self, ch: u8, x: u32, y: u32) -> bool {
+        x < self.width && self.row(ch, y) >> x & 1 != 0
+    }
+
+    /// The font squeezed or stretched to 8x8 cells, in `text.font` layout.
+    pub fn to_u64(&self) -> [u64; 256] {
+        std::array::from_fn(|ch| {
+            let mut glyph = 0u64;
+            for y in 0..8 {
+                for x in 0..8 {
+                    if self.pixel(ch as u8, x * self.width / 8, y * self.height / 8) {
+                        glyph |= 1 << (y * 8 + x);
+                    }
+                }
+            }
+            glyph
+        })
+    }
+
+    /// A PSF or BDF file, told apart by content.
+    pub fn parse(name: &str, bytes: &[u8]) -> io::Result<Self> {
+        if bytes.starts_with(&PSF2_MAGIC) {
+            Self::parse_psf2(name, bytes)
+        } else if bytes.starts_with(&PSF1_MAGIC) {
+            Self::parse_psf1(name, bytes)
+        } else if bytes.starts_with(b"STARTFONT") {
+            let text = std::str::from_utf8(bytes).map_err(|_| bad("BDF is not UTF-8"))?;
+            Self::parse_bdf(name, text)
+        } else {
+            Err(bad("not a PSF or BDF font"))
+        }
+    }
+
+    /// Puts a PSF glyph (rows of `row_len` bytes, high bit leftmost) at each of `codes` that no
+    /// earlier glyph took.
+    fn put_psf_glyph(&mut self, glyph: &[u8], row_len: usize, codes: &[u8], taken: &mut [bool]) {
+        for &code in codes {
+            if std::mem::replace(&mut taken[code as usize], true) {
+                continue;
+            }
+            for (y, row) in glyph.chunks_exact(row_len).enumerate() {
+                let mut bits = 0u32;
+                for x in 0..self.width as usize {
+                    if row[x / 8] & (0x80 >> (x % 8)) != 0 {
+                        bits |= 1 << x;
+                    }
+                }
+                self.rows[code as usize * self.height as usize + y] = bits;
+            }
+        }
+    }
+
+    fn parse_psf1(name: &str, bytes: &[u8]) -> io::Result<Self> {
+        let (mode, height) = match bytes.get(2..4) {
+            Some(&[mode, height]) => (mode, height as u32),
+            _ => return Err(bad("PSF header cut short")),
+        };
+        let count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+        let mut font = Self::blank(name, 8, height)?;
+        let glyphs_end = 4 + count * height as usize;
+        let glyphs = bytes
+            .get(4..glyphs_end)
+            .ok_or_else(|| bad("PSF glyphs cut short"))?;
+        // The table lists each glyph's UCS-2 codes, `0xFFFE` starting sequences we skip.
+        let mut table = (mode & PSF1_MODE_HAS_TAB != 0).then(|| {
+            bytes[glyphs_end..]
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        });
+        let mut taken = [false; 256];
+        for (idx, glyph) in glyphs.chunks_exact(height as usize).enumerate() {
+            let codes: Vec<u8> = match table.as_mut() {
+                Some(table) => table
+                    .by_ref()
+                    .take_while(|&u| u != 0xFFFF)
+                    .scan(false, |in_seq, u| {
+                        *in_seq |= u == 0xFFFE;
+                        Some((!*in_seq).then_some(u))
+                    })
+                    .flatten()
+                    .filter_map(|u| char::from_u32(u as u32).and_then(cp437_code))
+                    .collect(),
+                None if idx < 256 => vec![idx as u8],
+                None => Vec::new(),
+            };
+            font.put_psf_glyph(glyph, 1, &codes, &mut taken);
+        }
+        Ok(font)
+    }
+
+    fn parse_psf2(name: &str, bytes: &[u8]) -> io::Result<Self> {
+        let field = |k: usize| -> io::Result<u32> {
+            let b = bytes
+                .get(k * 4..k * 4 + 4)
+                .ok_or_else(|| bad("PSF header cut short"))?;
+            Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        };
+        let (header_len, flags, count) = (field(2)? as usize, field(3)?, field(4)? as usize);
+        let (glyph_len, height, width) = (field(5)? as usize, field(6)?, field(7)?);
+        let mut font = Self::blank(name, width, height)?;
+        let row_len = (width as usize).div_ceil(8);
+        if glyph_len != row_len * height as usize || count > 0x10000 {
+            return Err(bad("bad PSF glyph size"));
+        }
+        let glyphs_end = header_len + count * glyph_len;
+        let glyphs = bytes
+            .get(header_len..glyphs_end)
+            .ok_or_else(|| bad("PSF glyphs cut short"))?;
+        // The table lists each glyph's UTF-8 codes up to `0xFF`, `0xFE` starting sequences.
+        let mut table = (flags & PSF2_HAS_UNICODE_TABLE != 0)
+            .then(|| bytes[glyphs_end..].split(|&b| b == 0xFF));
+        let mut taken = [false; 256];
+        for (idx, glyph) in glyphs.chunks_exact(glyph_len).enumerate() {
+            let codes: Vec<u8> = match table.as_mut() {
+                Some(table) => {
+                    let entry = table.next().unwrap_or_default();
+                    let singles = entry.split(|&b| b == 0xFE).next().unwrap_or_default();
+                    String::from_utf8_lossy(singles)
+                        .chars()
+                        .filter_map(cp437_code)
+                        .collect()
+                }
+                None if idx < 256 => vec![idx as u8],
+                None => Vec::new(),
+            };
+            font.put_psf_glyph(glyph, row_len, &codes, &mut taken);
+        }
+        Ok(font)
+    }
+
+    fn parse_bdf(name: &str, text: &str) -> io::Result<Self> {
+        let nums = |rest: &str| -> Vec<i32> {
+            rest.split_whitespace()
+                .filter_map(|v| v.parse().ok())
+                .collect()
+        };
+        let mut lines = text.lines().map(str::trim);
+        let mut font: Option<Self> = None;
+        let (mut ascent, mut x_off) = (0, 0);
+        // `CHARSET_REGISTRY "IBM"` fonts are already numbered by CP437 code, others by Unicode.
+        let mut cp437 = false;
+        let mut taken = [false; 256];
+        let (mut code, mut bbx) = (None, [0i32; 4]);
+        while let Some(line) = lines.next() {
+            let (key, rest) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "FONTBOUNDINGBOX" => {
+                    let [w, h, xo, yo] = bdf_box(rest, "FONTBOUNDINGBOX")?;
+                    font = Some(Self::blank(name, w as u32, h as u32)?);
+                    (ascent, x_off) = (h + yo, xo);
+                }
+                "CHARSET_REGISTRY" => cp437 = rest.trim_matches('"').eq_ignore_ascii_case("IBM"),
+                "ENCODING" => {
+                    let n = nums(rest).first().copied().unwrap_or(-1);
+                    code = match u32::try_from(n) {
+                        Ok(n) if cp437 || n < 0x80 => u8::try_from(n).ok(),
+                        Ok(n) => char::from_u32(n).and_then(cp437_code),
+                        Err(_) => None,
+                    };
+                }
+                "BBX" => bbx = bdf_box(rest, "BBX")?,
+                "BITMAP" => {
+                    let font = font
+                        .as_mut()
+                        .ok_or_else(|| bad("BITMAP before FONTBOUNDINGBOX"))?;
+                    let [_, h, bx, by] = bbx;
+                    let top = ascent - (by + h);
+                    let keep = code.filter(|&c| !std::mem::replace(&mut taken[c as usize], true));
+                    for y in 0..h {
+                        let hex = lines.next().ok_or_else(|| bad("BITMAP cut short"))?;
+                        let (Some(code), Ok(bits)) = (keep, u64::from_str_radix(hex, 16)) else {
+                            continue;
+                        };
+                        let row_bits = hex.len() as i32 * 4;
+                        let fy = top + y;
+                        if !(0..font.height as i32).contains(&fy) {
+                            continue;
+                        }
+                        let row =
+                            &mut font.rows[code as usize * font.height as usize + fy as usize];
+                        for gx in 0..row_bits.min(64) {
+                            let fx = bx - x_off + gx;
+                            if bits >> (row_bits - 1 - gx) & 1 != 0
+                                && (0..font.width as i32).contains(&fx)
+                            {
+                                *row |= 1 << fx;
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        font.ok_or_else(|| bad("BDF has no FONTBOUNDINGBOX"))
+    }
+
+    /// Draws `text` from (`x`, `y`), each font pixel a `scale` x `scale` block, calling `plot`
+    /// with whether each pixel is inked. `\n` goes back to `x` on the same row, like `GrPrint`.
+    pub fn draw_text(
+        &self,
+        x: i32,
+        y: i32,
+        scale: u32,
+        text: &str,
+        mut plot: impl FnMut(i32, i32, bool),
+    ) {
+        let s = scale.max(1) as i32;
+        let mut cx = x;
+        for ch in text.chars() {
+            if ch == '\n' {
+                cx = x;
+                continue;
+            }
+            let code = assets::encode_cp437(ch);
+            for gy in 0..self.height {
+                let bits = self.row(code, gy);
+                for gx in 0..self.width {
+                    let on = bits >> gx & 1 != 0;
+                    for dy in 0..s {
+                        for dx in 0..s {
+                            plot(cx + gx as i32 * s + dx, y + gy as i32 * s + dy, on);
+                        }
+                    }
+                }
+            }
+            cx += self.width as i32 * s;
+        }
+    }
+}
+
+/// The file `name` names under `root/Fonts`: as given, or with a font extension added.
+pub fn font_path(root: &Path, name: &str) -> Option<PathBuf> {
+    let dir = root.join(FONTS_DIR);
+    std::iter::once(dir.join(name))
+        .chain(
+            FONT_EXTS
+                .iter()
+                .map(|ext| dir.join(format!("{name}.{ext}"))),
+        )
+        .find(|p| p.is_file())
+}
+
+/// The user font `name` (see [`font_path`]), or the system font for [`STD_FONT`].
+pub fn load_font(root: &Path, name: &str) -> io::Result<Font> {
+    if name.is_empty() || name.eq_ignore_ascii_case(STD_FONT) {
+        return Ok(Font::sys_std());
+    }
+    let path = font_path(root, name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no font {name} in {}", root.join(FONTS_DIR).display()),
+        )
+    })?;
+    let stem = Path::new(name)
+        .file_stem()
+        .map(|s| s.to_string_lossy())
+        .unwrap_or_default();
+    Font::parse(&stem, &fs::read(&path)?)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", path.display())))
+}
+
+/// The fonts in `root/Fonts`, by the name [`load_font`] takes.
+pub fn list_fonts(root: &Path) -> Vec<String> {
+    let Ok(rd) = fs::read_dir(root.join(FONTS_DIR)) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = rd
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let ext = path.extension()?.to_string_lossy().to_ascii_lowercase();
+            if !FONT_EXTS.contains(&ext.as_str()) {
+                return None;
+            }
+            Some(path.file_stem()?.to_string_lossy().into_owned())
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An 8x16 PSF2 font whose `A` is a single dot at the top left.
+    fn dot_psf2() -> Vec<u8> {
+        let mut psf = vec![0x72, 0xB5, 0x4A, 0x86];
+        for v in [0u32, 32, 0, 256, 16, 16, 8] {
+            psf.extend_from_slice(&v.to_le_bytes());
+        }
+        let mut glyphs = vec![0u8; 256 * 16];
+        glyphs[b'A' as usize * 16] = 0x80;
+        psf.extend_from_slice(&glyphs);
+        psf
+    }
+
+    #[test]
+    fn fonts_parse_from_psf_and_bdf_files() {
+        let psf2 = Font::parse("dot", &dot_psf2()).expect("psf2");
+        assert_eq!((psf2.width, psf2.height), (8, 16));
+        assert!(psf2.pixel(b'A', 0, 0));
+        assert!(!psf2.pixel(b'A', 1, 0) && !psf2.pixel(b'B', 0, 0));
+        assert_eq!(psf2.to_u64()[b'A' as usize], 1);
+
+        // PSF1 with a unicode table: glyph 0 is drawn for 'é' (CP437 0x82) only.
+        let mut psf1 = vec![0x36, 0x04, 0x02, 8];
+        let mut glyphs = vec![0u8; 256 * 8];
+        glyphs[0] = 0xFF;
+        psf1.extend_from_slice(&glyphs);
+        for idx in 0..256 {
+            if idx == 0 {
+                psf1.extend_from_slice(&0xE9u16.to_le_bytes());
+            }
+            psf1.extend_from_slice(&0xFFFFu16.to_le_bytes());
+        }
+        let psf1 = Font::parse("acute", &psf1).expect("psf1");
+        assert_eq!(psf1.row(0x82, 0), 0xFF);
+        assert_eq!(psf1.row(0, 0), 0);
+
+        let bdf = "STARTFONT 2.1\nFONTBOUNDINGBOX 4 6 0 -1\nSTARTCHAR A\nENCODING 65\n\
+                   BBX 3 2 1 0\nBITMAP\nE0\nA0\nENDCHAR\nENDFONT\n";
+        let bdf = Font::parse("tiny", bdf.as_bytes()).expect("bdf");
+        assert_eq!((bdf.width, bdf.height), (4, 6));
+        assert_eq!((bdf.row(b'A', 3), bdf.row(b'A', 4)), (0b1110, 0b1010));
+        assert!(Font::parse("junk", b"not a font").is_err());
+        for bad_box in [
+            "FONTBOUNDINGBOX 4 6 2147483647 -1",
+            "FONTBOUNDINGBOX 4 6 0 -1\nSTARTCHAR A\nENCODING 65\nBBX 3 2 1 -2147483648",
+            "FONTBOUNDINGBOX 4 6 0 -1\nSTARTCHAR A\nENCODING 65\nBBX 3 1000000000 0 0",
+        ] {
+            let text = format!("STARTFONT 2.1\n{bad_box}\nBITMAP\nE0\nENDCHAR\nENDFONT\n");
+            assert!(Font::parse("huge", text.as_bytes()).is_err(), "{bad_box}");
+        }
+
+        let mut inked = Vec::new();
+        psf2.draw_text(10, 20, 2, "AA", |x, y, on| {
+            if on {
+                inked.push((x, y));
+            }
+        });
+        let dot = |x| [(x, 20), (x + 1, 20), (x, 21), (x + 1, 21)];
+        assert_eq!(inked, [dot(10), dot(26)].concat());
+    }
+}
@@ -0,0 +1,592 @@
+//! TempleOS' 3D drawing pipeline: `GR_SCALE` fixed-point 4x4 matrices, the `DCF_TRANSFORMATION`
+//! transform of a `CDC`, and the depth buffer the `Gr*3` routines test against.
+
+use crate::sprite::{self, SpriteTarget};
+
+/// 1.0 in matrix entries (`GR_SCALE`).
+pub const GR_SCALE: i64 = 1 << 32;
+
+/// `dc->ls` of a fresh DC: a light behind the viewer, so faces turned to the screen show their
+/// own color.
+pub const DEFAULT_LIGHT: (i64, i64, i64) = (0, 0, -(1 << 16));
+
+/// 4x4 ordered-dither thresholds, `0..16`.
+const BAYER4: [i64; 16] = [0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5];
+
+/// TempleOS' lighting dither: `color` where lit, its bright/dark twin `color ^ 8` where not, in
+/// proportion to `lit` (`0..=1 << 16`).
+fn dither(color: u8, lit: i64, x: i32, y: i32) -> u8 {
+    let threshold = (BAYER4[((y & 3) * 4 + (x & 3)) as usize] * 2 + 1) << 11;
+    if threshold < lit { color } else { color ^ 8 }
+}
+
+/// A row-major 4x4 matrix of `GR_SCALE` fixed-point entries, like TempleOS' `I64 r[16]`.
+pub type Mat4x4 = [i64; 16];
+
+pub fn mat4x4_ident() -> Mat4x4 {
+    let mut r = [0; 16];
+    for i in 0..4 {
+        r[i * 5] = GR_SCALE;
+    }
+    r
+}
+
+/// `Mat4x4MulMat4x4Equ`: `a * b`.
+pub fn mat4x4_mul(a: &Mat4x4, b: &Mat4x4) -> Mat4x4 {
+    let mut r = [0; 16];
+    for i in 0..4 {
+        for j in 0..4 {
+            let sum: i128 = (0..4)
+                .map(|k| a[i * 4 + k] as i128 * b[k * 4 + j] as i128)
+                .sum();
+            r[i * 4 + j] = (sum >> 32) as i64;
+        }
+    }
+    r
+}
+
+fn rot(m: &Mat4x4, phi: f64, axis: usize) -> Mat4x4 {
+    let c = (phi.cos() * GR_SCALE as f64) as i64;
+    let s = (phi.sin() * GR_SCALE as f64) as i64;
+    // The two axes being rotated; the third keeps 1.0 on the diagonal.
+    let (a, b) = match axis {
+        0 => (1, 2),
+        1 => (2, 0),
+        _ => (0, 1),
+    };
+    let mut r = mat4x4_ident();
+    r[a * 5] = c;
+    r[b * 5] = c;
+    r[b * 4 + a] = s;
+    r[a * 4 + b] = -s;
+    mat4x4_mul(&r, m)
+}
+
+/// `Mat4x4RotX`: `m` followed by a rotation of `phi` radians about the X axis.
+pub fn mat4x4_rot_x(m: &Mat4x4, phi: f64) -> Mat4x4 {
+    rot(m, phi, 0)
+}
+
+/// `Mat4x4RotY`: `m` followed by a rotation of `phi` radians about the Y axis.
+pub fn mat4x4_rot_y(m: &Mat4x4, phi: f64) -> Mat4x4 {
+    rot(m, phi, 1)
+}
+
+/// `Mat4x4RotZ`: `m` followed by a rotation of `phi` radians about the Z axis.
+pub fn mat4x4_rot_z(m: &Mat4x4, phi: f64) -> Mat4x4 {
+    rot(m, phi, 2)
+}
+
+/// `Mat4x4Scale`: every entry times `s`, keeping `r[15]` at 1.0.
+pub fn mat4x4_scale(m: &Mat4x4, s: f64) -> Mat4x4 {
+    let mut r = m.map(|v| (v as f64 * s) as i64);
+    r[15] = GR_SCALE;
+    r
+}
+
+/// `Mat4x4TranslationEqu`: replaces the translation column with (`x`, `y`, `z`).
+pub fn mat4x4_translation_equ(m: &Mat4x4, x: i64, y: i64, z: i64) -> Mat4x4 {
+    let mut r = *m;
+    r[3] = x << 32;
+    r[7] = y << 32;
+    r[11] = z << 32;
+    r[15] = GR_SCALE;
+    r
+}
+
+/// `Mat4x4TranslationAdd`: adds (`x`, `y`, `z`) to the translation column.
+pub fn mat4x4_translation_add(m: &Mat4x4, x: i64, y: i64, z: i64) -> Mat4x4 {
+    let mut r = *m;
+    r[3] = r[3].wrapping_add(x << 32);
+    r[7] = r[7].wrapping_add(y << 32);
+    r[11] = r[11].wrapping_add(z << 32);
+    r
+}
+
+/// `Mat4x4MulXYZ`: the point (`x`, `y`, `z`) transformed by `m`.
+pub fn mat4x4_mul_xyz(m: &Mat4x4, x: i64, y: i64, z: i64) -> (i64, i64, i64) {
+    let row = |i: usize| {
+        let v = m[i * 4] as i128 * x as i128
+            + m[i * 4 + 1] as i128 * y as i128
+            + m[i * 4 + 2] as i128 * z as i128
+            + m[i * 4 + 3] as i128;
+        (v >> 32) as i64
+    };
+    (row(0), row(1), row(2))
+}
+
+/// A `CDC`'s 3D state: `dc->r` and the `dc->x`/`y`/`z` offset `DCTransform` adds after it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transform3 {
+    pub r: Mat4x4,
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+impl Default for Transform3 {
+    fn default() -> Self {
+        Self {
+            r: mat4x4_ident(),
+            x: 0,
+            y: 0,
+            z: 0,
+        }
+    }
+}
+
+impl Transform3 {
+    /// `DCTransform`: `r` times the point, plus the DC's offset.
+    pub fn apply(&self, x: i64, y: i64, z: i64) -> (i64, i64, i64) {
+        let (x, y, z) = mat4x4_mul_xyz(&self.r, x, y, z);
+        (
+            x.wrapping_add(self.x),
+            y.wrapping_add(self.y),
+            z.wrapping_add(self.z),
+        )
+    }
+}
+
+/// A `CDC`'s `I32 *depth_buf`: one little-endian `I32` per pixel, `stride` pixels per row.
+pub struct DepthBuf<'a> {
+    cells: &'a mut [u8],
+    stride: i32,
+    height: i32,
+}
+
+impl<'a> DepthBuf<'a> {
+    pub fn new(cells: &'a mut [u8], stride: i32, height: i32) -> Self {
+        Self {
+            cells,
+            stride: stride.max(0),
+            height: height.max(0),
+        }
+    }
+
+    /// `DCDepthBufRst`: every pixel infinitely far away.
+    pub fn reset(cells: &mut [u8]) {
+        for cell in cells.chunks_exact_mut(4) {
+            cell.copy_from_slice(&i32::MAX.to_le_bytes());
+        }
+    }
+
+    /// TempleOS' depth test: a pixel is drawn when `0 <= z <= depth`, which then becomes `z`.
+    fn test_and_set(&mut self, x: i32, y: i32, z: i64) -> bool {
+        if x < 0 || y < 0 || x >= self.stride || y >= self.height {
+            return false;
+        }
+        let at = (y as usize * self.stride as usize + x as usize) * 4;
+        let Some(cell) = self.cells.get_mut(at..at + 4) else {
+            return false;
+        };
+        let depth = i32::from_le_bytes([cell[0], cell[1], cell[2], cell[3]]) as i64;
+        if !(0..=depth).contains(&z) {
+            return false;
+        }
+        cell.copy_from_slice(&(z as i32).to_le_bytes());
+        true
+    }
+}
+
+/// Draws on `target` the way the `Gr*3` routines do: points go through the DC's transform while
+/// `transforming` (`DCF_TRANSFORMATION`) is set, and every pixel is depth tested when the DC has
+/// a depth buffer. With neither, drawing is exactly the 2D primitives'.
+pub struct Gr3<'a, T: SpriteTarget + ?Sized> {
+    pub target: &'a mut T,
+    pub xf: Transform3,
+    pub transforming: bool,
+    depth: Option<DepthBuf<'a>>,
+    /// Depth of pixels drawn through the [`SpriteTarget`] impl.
+    pub z: i64,
+    /// `dc->ls`: toward the light, `1 << 16` long. `(0, 0, 0)` draws meshes unlit.
+    pub light: (i64, i64, i64),
+}
+
+impl<'a, T: SpriteTarget + ?Sized> Gr3<'a, T> {
+    pub fn new(
+        target: &'a mut T,
+        xf: Transform3,
+        transforming: bool,
+        depth: Option<DepthBuf<'a>>,
+    ) -> Self {
+        Self {
+            target,
+            xf,
+            transforming,
+            depth,
+            z: 0,
+            light: DEFAULT_LIGHT,
+        }
+    }
+
+    fn is_2d(&self) -> bool {
+        !self.transforming && self.depth.is_none()
+    }
+
+    /// Where (`x`, `y`, `z`) lands on the DC.
+    pub fn project(&self, x: i64, y: i64, z: i64) -> (i64, i64, i64) {
+        if self.transforming {
+            self.xf.apply(x, y, z)
+        } else {
+            (x, y, z)
+        }
+    }
+
+    /// A pixel at DC coordinates, depth tested.
+    pub fn plot(&mut self, x: i32, y: i32, z: i64, color: u8) {
+        if let Some(depth) = self.depth.as_mut()
+            && !depth.test_and_set(x, y, z)
+        {
+            return;
+        }
+        self.target.set_pixel(x, y, color);
+    }
+
+    fn plot_thick(&mut self, x: i32, y: i32, z: i64, color: u8, thick: i32) {
+        if thick <= 1 {
+            return self.plot(x, y, z, color);
+        }
+        let half = thick / 2;
+        for yy in y - half..y - half + thick {
+            for xx in x - half..x - half + thick {
+                self.plot(xx, yy, z, color);
+            }
+        }
+    }
+
+    /// `GrPlot3`.
+    pub fn plot3(&mut self, p: (i64, i64, i64), color: u8, thick: i32) {
+        let (x, y, z) = self.project(p.0, p.1, p.2);
+        self.plot_thick(x as i32, y as i32, z, color, thick);
+    }
+
+    /// A line between two points already on the DC, with `z` interpolated along it.
+    fn line_projected(&mut self, a: (i64, i64, i64), b: (i64, i64, i64), color: u8, thick: i32) {
+        let (x1, y1, x2, y2) = (a.0 as i32, a.1 as i32, b.0 as i32, b.1 as i32);
+        if self.depth.is_none() {
+            return self.target.draw_line_thick(x1, y1, x2, y2, color, thick);
+        }
+        let steps = (x2 - x1).abs().max((y2 - y1).abs()).max(1) as i64;
+        let dx = (x2 - x1).abs();
+        let dy = -(y2 - y1).abs();
+        let sx = if x1 < x2 { 1 } else { -1 };
+        let sy = if y1 < y2 { 1 } else { -1 };
+        let (mut x, mut y, mut err) = (x1, y1, dx + dy);
+        let mut i = 0i64;
+        loop {
+            let z = a.2 + (b.2 - a.2) * i / steps;
+            self.plot_thick(x, y, z, color, thick);
+            if x == x2 && y == y2 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+            i += 1;
+        }
+    }
+
+    /// `GrLine3`.
+    pub fn line3(&mut self, a: (i64, i64, i64), b: (i64, i64, i64), color: u8, thick: i32) {
+        let a = self.project(a.0, a.1, a.2);
+        let b = self.project(b.0, b.1, b.2);
+        self.line_projected(a, b, color, thick);
+    }
+
+    /// `GrCircle3`: a circle in the XY plane around `c`, which the transform may turn into an
+    /// ellipse.
+    pub fn circle3(&mut self, c: (i64, i64, i64), r: i64, color: u8, thick: i32) {
+        if r <= 0 {
+            return;
+        }
+        if self.is_2d() {
+            return self
+                .target
+                .draw_circle_thick(c.0 as i32, c.1 as i32, r as i32, color, thick);
+        }
+        if !self.transforming {
+            // Same pixels as the 2D circle, each one depth tested at the circle's depth.
+            let z = c.2;
+            let mut plot = |x: i32, y: i32| self.plot_thick(x, y, z, color, thick);
+            let (cx, cy) = (c.0 as i32, c.1 as i32);
+            let (mut x, mut y, mut err) = (r as i32, 0i32, 0i32);
+            while x >= y {
+                for (px, py) in [
+                    (x, y),
+                    (y, x),
+                    (-y, x),
+                    (-x, y),
+                    (-x, -y),
+                    (-y, -x),
+                    (y, -x),
+                    (x, -y),
+                ] {
+                    plot(cx + px, cy + py);
+                }
+                y += 1;
+                if err <= 0 {
+                    err += 2 * y + 1;
+                } else {
+                    x -= 1;
+                    err -= 2 * x + 1;
+                }
+            }
+            return;
+        }
+        let segs = (r as usize).clamp(16, 256);
+        let at = |i: usize| {
+            let a = std::f64::consts::TAU * i as f64 / segs as f64;
+            (
+                c.0 + (r as f64 * a.cos()).round() as i64,
+                c.1 + (r as f64 * a.sin()).round() as i64,
+                c.2,
+            )
+        };
+        let mut prev = at(0);
+        for i in 1..=segs {
+            let next = at(i);
+            self.line3(prev, next, color, thick);
+            prev = next;
+        }
+    }
+
+    /// `GrFillTri0`: a filled triangle of points already on the DC, `z` interpolated across it.
+    pub fn fill_tri(
+        &mut self,
+        p0: (i64, i64, i64),
+        p1: (i64, i64, i64),
+        p2: (i64, i64, i64),
+        color: u8,
+    ) {
+        self.fill_tri_by(p0, p1, p2, |_, _| color);
+    }
+
+    /// A filled triangle whose pixel colors come from `color_at(x, y)`.
+    fn fill_tri_by(
+        &mut self,
+        p0: (i64, i64, i64),
+        p1: (i64, i64, i64),
+        p2: (i64, i64, i64),
+        mut color_at: impl FnMut(i32, i32) -> u8,
+    ) {
+        fn edge(a: (i64, i64), b: (i64, i64), p: (i64, i64)) -> i64 {
+            (p.0 - a.0) * (b.1 - a.1) - (p.1 - a.1) * (b.0 - a.0)
+        }
+        let (a, b, c) = ((p0.0, p0.1), (p1.0, p1.1), (p2.0, p2.1));
+        let area = edge(a, b, c);
+        if area == 0 {
+            return;
+        }
+        let min_x = a.0.min(b.0).min(c.0);
+        let max_x = a.0.max(b.0).max(c.0);
+        let min_y = a.1.min(b.1).min(c.1);
+        let max_y = a.1.max(b.1).max(c.1);
+        // Same guard as the sprite mesh renderer against runaway triangles.
+        if (max_x - min_x + 1).saturating_mul(max_y - min_y + 1) > 500_000 {
+            return;
+        }
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let w0 = edge(b, c, (x, y));
+                let w1 = edge(c, a, (x, y));
+                let w2 = edge(a, b, (x, y));
+                let inside = if area > 0 {
+                    w0 >= 0 && w1 >= 0 && w2 >= 0
+                } else {
+                    w0 <= 0 && w1 <= 0 && w2 <= 0
+                };
+                if inside {
+                    let z = (w0 as i128 * p0.2 as i128
+                        + w1 as i128 * p1.2 as i128
+                        + w2 as i128 * p2.2 as i128)
+                        / area as i128;
+                    self.plot(x as i32, y as i32, z as i64, color_at(x as i32, y as i32));
+                }
+            }
+        }
+    }
+
+    /// How lit a triangle is, `0..=1 << 16`: its normal against [`Gr3::light`], like
+    /// `DCLighting`. The normal is first turned toward the viewer (-z), so back faces and either
+    /// winding shade the same.
+    fn lighting(&self, p: [(i64, i64, i64); 3]) -> i64 {
+        let v1 = (p[0].0 - p[1].0, p[0].1 - p[1].1, p[0].2 - p[1].2);
+        let v2 = (p[2].0 - p[1].0, p[2].1 - p[1].1, p[2].2 - p[1].2);
+        let (v1, v2) = (
+            (v1.0 as f64, v1.1 as f64, v1.2 as f64),
+            (v2.0 as f64, v2.1 as f64, v2.2 as f64),
+        );
+        let mut n = (
+            v1.1 * v2.2 - v1.2 * v2.1,
+            v1.2 * v2.0 - v1.0 * v2.2,
+            v1.0 * v2.1 - v1.1 * v2.0,
+        );
+        if n.2 > 0.0 {
+            n = (-n.0, -n.1, -n.2);
+        }
+        let len = (n.0 * n.0 + n.1 * n.1 + n.2 * n.2).sqrt();
+        if len == 0.0 {
+            return 1 << 16;
+        }
+        let (lx, ly, lz) = self.light;
+        let dot = (n.0 * lx as f64 + n.1 * ly as f64 + n.2 * lz as f64) / len;
+        (dot as i64).clamp(0, 1 << 16)
+    }
+
+    /// `SPT_MESH`: `tris` (color and vertex indices) over vertices already on the DC, lit and
+    /// dithered. Without a depth buffer the far triangles are drawn first.
+    pub fn mesh(&mut self, pts: &[(i64, i64, i64)], tris: &[(u8, [usize; 3])]) {
+        let mut order: Vec<usize> = (0..tris.len()).collect();
+        if self.depth.is_none() {
+            order.sort_by_key(|&t| {
+                std::cmp::Reverse(tris[t].1.iter().map(|&i| pts[i].2).sum::<i64>())
+            });
+        }
+        for t in order {
+            let (color, [a, b, c]) = tris[t];
+            let p = [pts[a], pts[b], pts[c]];
+            if self.light == (0, 0, 0) {
+                self.fill_tri(p[0], p[1], p[2], color);
+                continue;
+            }
+            let lit = self.lighting(p);
+            self.fill_tri_by(p[0], p[1], p[2], |x, y| dither(color, lit, x, y));
+        }
+    }
+}
+
+/// 2D drawing at depth [`Gr3::z`], for sprite elements with no 3D form.
+impl<T: SpriteTarget + ?Sized> SpriteTarget for Gr3<'_, T> {
+    fn set_pixel(&mut self, x: i32, y: i32, color: u8) {
+        self.plot(x, y, self.z, color);
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, w: i32, h: i32, color: u8) {
+        if self.depth.is_none() {
+            return self.target.fill_rect(x, y, w, h, color);
+        }
+        for yy in y..y.saturating_add(h) {
+            for xx in x..x.saturating_add(w) {
+                self.set_pixel(xx, yy, color);
+            }
+        }
+    }
+
+    fn draw_line_thick(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u8, thick: i32) {
+        if self.depth.is_none() {
+            return self.target.draw_line_thick(x1, y1, x2, y2, color, thick);
+        }
+        sprite::draw_line_thick_default(self, x1, y1, x2, y2, color, thick);
+    }
+
+    fn draw_rect_outline_thick(&mut self, x: i32, y: i32, w: i32, h: i32, color: u8, thick: i32) {
+        if self.depth.is_none() {
+            return self
+                .target
+                .draw_rect_outline_thick(x, y, w, h, color, thick);
+        }
+        sprite::draw_rect_outline_thick_default(self, x, y, w, h, color, thick);
+    }
+
+    fn draw_circle_thick(&mut self, cx: i32, cy: i32, r: i32, color: u8, thick: i32) {
+        if self.depth.is_none() {
+            return self.target.draw_circle_thick(cx, cy, r, color, thick);
+        }
+        sprite::draw_circle_thick_default(self, cx, cy, r, color, thick);
+    }
+
+    fn blit_8bpp(
+        &mut self,
+        dst_x: i32,
+        dst_y: i32,
+        src_w: i32,
+        src_h: i32,
+        stride: i32,
+        src: &[u8],
+    ) {
+        if self.depth.is_none() {
+            return self
+                .target
+                .blit_8bpp(dst_x, dst_y, src_w, src_h, stride, src);
+        }
+        if src_w <= 0 || src_h <= 0 || stride <= 0 {
+            return;
+        }
+        for row in 0..src_h {
+            let start = (row * stride) as usize;
+            let Some(row_src) = src.get(start..start + src_w as usize) else {
+                return;
+            };
+            for (col, &px) in row_src.iter().enumerate() {
+                if px != 0xFF {
+                    self.set_pixel(dst_x + col as i32, dst_y + row, px);
+                }
+            }
+        }
+    }
+
+    fn rop(&self) -> crate::rt::Rop {
+        self.target.rop()
+    }
+
+    fn set_rop(&mut self, rop: crate::rt::Rop) {
+        self.target.set_rop(rop);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_6};
+
+    use super::*;
+
+    #[test]
+    fn rotations_compose_like_templeos_r_times_m() {
+        // `Mat4x4RotX(m, phi)` is `r * m`: the last rotation applied is the outermost one.
+        let x_then_z = mat4x4_rot_z(&mat4x4_rot_x(&mat4x4_ident(), FRAC_PI_2), FRAC_PI_2);
+        assert_eq!(mat4x4_mul_xyz(&x_then_z, 0, 100, 0), (0, 0, 100));
+        let z_then_x = mat4x4_rot_x(&mat4x4_rot_z(&mat4x4_ident(), FRAC_PI_2), FRAC_PI_2);
+        assert_eq!(mat4x4_mul_xyz(&z_then_x, 0, 100, 0), (-100, 0, 0));
+        let y = mat4x4_rot_y(&mat4x4_ident(), FRAC_PI_2);
+        assert_eq!(mat4x4_mul_xyz(&y, 100, 0, 0), (0, 0, -100));
+
+        // Upstream's `r[5]=r[10]=cos; r[9]=sin; r[6]=-sin` for X, and likewise for Y and Z.
+        let (c, s) = (0, GR_SCALE);
+        let mut rx = mat4x4_ident();
+        (rx[5], rx[6], rx[9], rx[10]) = (c, -s, s, c);
+        assert_eq!(mat4x4_rot_x(&mat4x4_ident(), FRAC_PI_2), rx);
+        let mut ry = mat4x4_ident();
+        (ry[0], ry[2], ry[8], ry[10]) = (c, s, -s, c);
+        assert_eq!(y, ry);
+
+        // A translation is rotated along with the rest of `m`.
+        let moved = mat4x4_translation_equ(&mat4x4_ident(), 10, 0, 0);
+        let moved = mat4x4_rot_z(&moved, FRAC_PI_2);
+        assert_eq!(mat4x4_mul_xyz(&moved, 0, 0, 0), (0, 10, 0));
+    }
+
+    #[test]
+    fn mul_xyz_truncates_like_the_fixed_point_original() {
+        // cos(pi/6) * 2^32 = 3719550786.1, truncated to I64; each row then shifts right by 32,
+        // rounding toward -infinity: 866.03 -> 866 but -866.03 -> -867.
+        let m = mat4x4_rot_z(&mat4x4_ident(), FRAC_PI_6);
+        assert_eq!((m[0], m[5], m[4]), (3719550786, 3719550786, -m[1]));
+        assert_eq!(mat4x4_mul_xyz(&m, 1000, 0, 0).0, 866);
+        assert_eq!(mat4x4_mul_xyz(&m, -1000, 0, 0).0, -867);
+        assert_eq!(mat4x4_mul_xyz(&m, 0, 0, -7), (0, 0, -7));
+
+        let t = Transform3 {
+            r: mat4x4_scale(&mat4x4_ident(), 2.0),
+            x: 5,
+            y: -5,
+            z: 1,
+        };
+        assert_eq!(t.apply(3, 4, 5), (11, 3, 11));
+    }
+}
@@ -0,0 +1,178 @@
+//! Images in and out of TempleOS: PNGs and BMPs quantized to the 16-color palette, as
+//! `SPT_BITMAP` sprites or `.GR` files, and `.GR` files back to pixels.
+//! [`crate::doldoc::sprite_png`] rasterizes sprites back to PNG.
+
+use std::io;
+
+use crate::{
+    assets,
+    doldoc::Canvas,
+    rt::TRANSPARENT,
+    sprite::{self, SpriteElem},
+};
+
+mod arc;
+mod bmp;
+mod gr;
+
+pub use arc::{compress, expand, is_compressed};
+pub use bmp::decode_bmp;
+pub use gr::{GrImage, decode_gr, encode_gr};
+
+/// How [`quantize`] spreads the difference between a pixel and its palette color.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dither {
+    /// Each pixel takes the nearest palette color.
+    #[default]
+    Nearest,
+    /// A 4x4 Bayer threshold, like TempleOS' own dithered colors.
+    Ordered,
+    /// Floyd–Steinberg error diffusion.
+    FloydSteinberg,
+}
+
+impl Dither {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "none" | "nearest" => Some(Dither::Nearest),
+            "ordered" | "bayer" => Some(Dither::Ordered),
+            "fs" | "floyd-steinberg" => Some(Dither::FloydSteinberg),
+            _ => None,
+        }
+    }
+}
+
+const BAYER4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Spacing of the palette's color levels (0x00, 0x55, 0xAA, 0xFF): how far ordered dithering
+/// pushes a pixel.
+const ORDERED_SPREAD: f32 = 85.0;
+
+/// Pixels at least this transparent become `TRANSPARENT`.
+const ALPHA_CUTOFF: u8 = 128;
+
+/// Decodes a PNG of any color type and depth to 8-bit RGBA: width, height, pixels.
+pub fn decode_png(bytes: &[u8]) -> io::Result<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buf)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+    let buf = &buf[..info.buffer_size()];
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buf.to_vec(),
+        png::ColorType::Rgb => buf
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+        png::ColorType::Indexed => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpanded indexed PNG",
+            ));
+        }
+    };
+    Ok((info.width, info.height, rgba))
+}
+
+/// The palette color closest to `rgb`.
+pub fn nearest_color(rgb: [f32; 3]) -> u8 {
+    let palette = &assets::TEMPLEOS_GR_PALETTE_STD_RGBA256[..16];
+    let dist = |c: &[u8; 4]| (0..3).map(|i| (rgb[i] - c[i] as f32).powi(2)).sum::<f32>();
+    (0..16)
+        .min_by(|&a, &b| dist(&palette[a]).total_cmp(&dist(&palette[b])))
+        .unwrap_or(0) as u8
+}
+
+/// Quantizes `width` x `height` RGBA pixels to the 16-color palette; mostly transparent pixels
+/// come out `TRANSPARENT`.
+pub fn quantize(width: u32, height: u32, rgba: &[u8], dither: Dither) -> Canvas {
+    let (w, h) = (width as usize, height as usize);
+    let mut canvas = Canvas::new(width, height, TRANSPARENT);
+    let mut err = vec![[0f32; 3]; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let i = y * w + x;
+            let Some(px) = rgba.get(i * 4..i * 4 + 4) else {
+                continue;
+            };
+            if px[3] < ALPHA_CUTOFF {
+                continue;
+            }
+            let mut rgb = [0f32; 3];
+            for c in 0..3 {
+                rgb[c] = px[c] as f32 + err[i][c];
+            }
+            if dither == Dither::Ordered {
+                let t = (BAYER4[y % 4][x % 4] as f32 + 0.5) / 16.0 - 0.5;
+                rgb = rgb.map(|v| v + t * ORDERED_SPREAD);
+            }
+            let color = nearest_color(rgb);
+            canvas.pixels[i] = color;
+            if dither != Dither::FloydSteinberg {
+                continue;
+            }
+            let chosen = assets::TEMPLEOS_GR_PALETTE_STD_RGBA256[color as usize];
+            let diff: [f32; 3] = std::array::from_fn(|c| rgb[c] - chosen[c] as f32);
+            let mut spread = |dx: isize, dy: usize, weight: f32| {
+                let nx = x as isize + dx;
+                if nx < 0 || nx as usize >= w || y + dy >= h {
+                    return;
+                }
+                let e = &mut err[(y + dy) * w + nx as usize];
+                for c in 0..3 {
+                    e[c] += diff[c] * weight;
+                }
+            };
+            spread(1, 0, 7.0 / 16.0);
+            spread(-1, 1, 3.0 / 16.0);
+            spread(0, 1, 5.0 / 16.0);
+            spread(1, 1, 1.0 / 16.0);
+        }
+    }
+    canvas
+}
+
+/// `canvas` as a sprite: one `SPT_BITMAP` at the origin.
+pub fn bitmap_sprite(canvas: &Canvas) -> Vec<u8> {
+    let (w, h) = (canvas.width as usize, canvas.height as usize);
+    let stride = w.div_ceil(8) * 8;
+    let mut body = vec![TRANSPARENT; stride * h];
+    for y in 0..h {
+        body[y * stride..y * stride + w].copy_from_slice(&canvas.pixels[y * w..(y + 1) * w]);
+    }
+    sprite::sprite_elem_bytes(&[SpriteElem::Bitmap {
+        x: 0,
+        y: 0,
+        width: w as i32,
+        height: h as i32,
+        body,
+    }])
+}
+
+/// A PNG quantized to a bitmap sprite.
+pub fn png_to_sprite(png: &[u8], dither: Dither) -> io::Result<Vec<u8>> {
+    let (w, h, rgba) = decode_png(png)?;
+    Ok(bitmap_sprite(&quantize(w, h, &rgba, dither)))
+}
+
+/// A `.GR`, `.GR.Z`, BMP or PNG file as palette pixels, told apart by content.
+pub fn decode_image(bytes: &[u8]) -> io::Result<Canvas> {
+    if bytes.starts_with(b"BM") {
+        decode_bmp(bytes, Dither::Nearest)
+    } else if bytes.starts_with(b"\x89PNG") {
+        let (w, h, rgba) = decode_png(bytes)?;
+        Ok(quantize(w, h, &rgba, Dither::Nearest))
+    } else {
+        Ok(decode_gr(bytes)?.image)
+    }
+}
@@ -0,0 +1,345 @@
+//! TempleOS' LZW (`CompressBuf`/`ExpandBuf`), used by `.Z` files and compressed `.GR` bodies.
+//!
+//! A `CArcCompress` is a 17-byte header (`compressed_size` and `expanded_size` as `U32` pairs,
+//! then `compression_type`) and the body. Codes are packed LSB first, starting one bit wider
+//! than the literals and growing to 12 bits; a full table recycles entries no other entry
+//! extends. Both directions follow TempleOS' table bookkeeping step for step, since the
+//! recycling order is part of the format.
+
+use std::io;
+
+const ARC_BITS_MAX: u32 = 12;
+const TABLE_LEN: usize = 1 << ARC_BITS_MAX;
+const NONE: u16 = u16::MAX;
+
+const CT_NONE: u8 = 1;
+const CT_7_BIT: u8 = 2;
+const CT_8_BIT: u8 = 3;
+
+/// `sizeof(CArcCompress)` up to its body.
+pub const ARC_HEADER_LEN: usize = 17;
+
+#[derive(Clone, Copy)]
+struct Entry {
+    next: u16,
+    basecode: u16,
+    ch: u8,
+}
+
+/// `CArcCtrl`'s string table.
+struct Table {
+    min_table_entry: usize,
+    compress: Vec<Entry>,
+    /// Head of the list of entries extending each code.
+    hash: Vec<u16>,
+    cur_entry: usize,
+    next_entry: usize,
+    cur_bits_in_use: u32,
+    next_bits_in_use: u32,
+    free_idx: usize,
+    free_limit: usize,
+    entry_used: bool,
+}
+
+impl Table {
+    fn new(min_bits: u32) -> Self {
+        let entry = Entry {
+            next: NONE,
+            basecode: 0,
+            ch: 0,
+        };
+        let mut t = Self {
+            min_table_entry: 1 << min_bits,
+            compress: vec![entry; TABLE_LEN],
+            hash: vec![NONE; TABLE_LEN],
+            cur_entry: 0,
+            next_entry: 0,
+            cur_bits_in_use: 0,
+            next_bits_in_use: min_bits + 1,
+            free_idx: 1 << min_bits,
+            free_limit: 1 << (min_bits + 1),
+            entry_used: true,
+        };
+        t.get_table_entry();
+        t.entry_used = true;
+        t
+    }
+
+    /// `ArcGetTableEntry`: moves on to the next free entry once the current one is used.
+    fn get_table_entry(&mut self) {
+        if !self.entry_used {
+            return;
+        }
+        let mut i = self.free_idx;
+        self.entry_used = false;
+        self.cur_entry = self.next_entry;
+        self.cur_bits_in_use = self.next_bits_in_use;
+        if self.next_bits_in_use < ARC_BITS_MAX {
+            self.next_entry = i;
+            i += 1;
+            if i == self.free_limit {
+                self.next_bits_in_use += 1;
+                self.free_limit = 1 << self.next_bits_in_use;
+            }
+        } else {
+            loop {
+                i += 1;
+                if i == self.free_limit {
+                    i = self.min_table_entry;
+                }
+                if self.hash[i] == NONE {
+                    break;
+                }
+            }
+            self.next_entry = i;
+            self.unlink(i);
+        }
+        self.free_idx = i;
+    }
+
+    fn unlink(&mut self, idx: usize) {
+        let parent = self.compress[idx].basecode as usize;
+        if self.hash[parent] as usize == idx {
+            self.hash[parent] = self.compress[idx].next;
+            return;
+        }
+        let mut at = self.hash[parent];
+        while at != NONE {
+            if self.compress[at as usize].next as usize == idx {
+                self.compress[at as usize].next = self.compress[idx].next;
+                return;
+            }
+            at = self.compress[at as usize].next;
+        }
+    }
+
+    /// Defines the current entry as `basecode` followed by `ch`.
+    fn define(&mut self, basecode: usize, ch: u8) {
+        self.entry_used = true;
+        let idx = self.cur_entry;
+        self.compress[idx] = Entry {
+            next: self.hash[basecode],
+            basecode: basecode as u16,
+            ch,
+        };
+        self.hash[basecode] = idx as u16;
+    }
+
+    fn find(&self, basecode: usize, ch: u8) -> Option<usize> {
+        let mut at = self.hash[basecode];
+        while at != NONE {
+            let e = &self.compress[at as usize];
+            if e.ch == ch {
+                return Some(at as usize);
+            }
+            at = e.next;
+        }
+        None
+    }
+}
+
+fn put_bits(out: &mut Vec<u8>, pos: &mut usize, val: usize, bits: u32) {
+    out.resize((*pos + bits as usize).div_ceil(8) + 4, 0);
+    let v = (val as u64) << (*pos % 8);
+    for (i, b) in v.to_le_bytes().iter().enumerate().take(5) {
+        out[*pos / 8 + i] |= b;
+    }
+    *pos += bits as usize;
+}
+
+fn get_bits(buf: &[u8], pos: usize, bits: u32) -> usize {
+    let mut word = [0u8; 8];
+    for (i, b) in word.iter_mut().enumerate().take(5) {
+        *b = buf.get(pos / 8 + i).copied().unwrap_or(0);
+    }
+    ((u64::from_le_bytes(word) >> (pos % 8)) & ((1 << bits) - 1)) as usize
+}
+
+fn header(compressed_size: usize, expanded_size: usize, compression_type: u8) -> [u8; 17] {
+    let mut h = [0u8; ARC_HEADER_LEN];
+    h[..4].copy_from_slice(&(compressed_size as u32).to_le_bytes());
+    h[8..12].copy_from_slice(&(expanded_size as u32).to_le_bytes());
+    h[16] = compression_type;
+    h
+}
+
+/// `CompressBuf`: `src` as a `CArcCompress`, stored as is when LZW would not make it smaller.
+pub fn compress(src: &[u8]) -> Vec<u8> {
+    let stored = || {
+        let mut out = header(ARC_HEADER_LEN + src.len(), src.len(), CT_NONE).to_vec();
+        out.extend_from_slice(src);
+        out
+    };
+    let Some((&first, rest)) = src.split_first() else {
+        return stored();
+    };
+    let compression_type = if src.iter().all(|&b| b < 0x80) {
+        CT_7_BIT
+    } else {
+        CT_8_BIT
+    };
+    let mut t = Table::new(if compression_type == CT_7_BIT { 7 } else { 8 });
+    let limit = (ARC_HEADER_LEN + src.len()) * 8;
+    let mut out = vec![0u8; ARC_HEADER_LEN];
+    let mut pos = ARC_HEADER_LEN * 8;
+    let mut saved = first as usize;
+    let mut rest = rest.iter();
+    'codes: while rest.len() > 0 {
+        if pos + t.cur_bits_in_use as usize > limit {
+            return stored();
+        }
+        t.get_table_entry();
+        let ch = loop {
+            let Some(&ch) = rest.next() else {
+                break 'codes;
+            };
+            match t.find(saved, ch) {
+                Some(code) => saved = code,
+                None => break ch,
+            }
+        };
+        put_bits(&mut out, &mut pos, saved, t.cur_bits_in_use);
+        t.define(saved, ch);
+        saved = ch as usize;
+    }
+    put_bits(&mut out, &mut pos, saved, t.next_bits_in_use);
+    let size = pos.div_ceil(8);
+    if size > limit / 8 {
+        return stored();
+    }
+    out.truncate(size);
+    out[..ARC_HEADER_LEN].copy_from_slice(&header(size, src.len(), compression_type));
+    out
+}
+
+/// Whether `buf` is a whole `CArcCompress`, as a `.Z` file holds.
+pub fn is_compressed(buf: &[u8]) -> bool {
+    let u32_at =
+        |off: usize| u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]]);
+    buf.len() >= ARC_HEADER_LEN
+        && u32_at(0) as usize == buf.len()
+        && u32_at(4) == 0
+        && u32_at(12) == 0
+        && (CT_NONE..=CT_8_BIT).contains(&buf[16])
+}
+
+fn bad(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// `ExpandBuf`: the bytes a `CArcCompress` at the start of `arc` holds.
+pub fn expand(arc: &[u8]) -> io::Result<Vec<u8>> {
+    if arc.len() < ARC_HEADER_LEN {
+        return Err(bad("compressed data cut short"));
+    }
+    let u32_at =
+        |off: usize| u32::from_le_bytes([arc[off], arc[off + 1], arc[off + 2], arc[off + 3]]);
+    let (compressed_size, expanded_size) = (u32_at(0) as usize, u32_at(8) as usize);
+    if expanded_size >= 0x2000_0000 {
+        return Err(bad("compressed data too large"));
+    }
+    let min_bits = match arc[16] {
+        CT_NONE => {
+            let body = arc.get(ARC_HEADER_LEN..ARC_HEADER_LEN + expanded_size);
+            return body
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| bad("stored data cut short"));
+        }
+        CT_7_BIT => 7,
+        CT_8_BIT => 8,
+        _ => return Err(bad("unknown compression type")),
+    };
+    let mut out = Vec::with_capacity(expanded_size);
+    if expanded_size == 0 {
+        return Ok(out);
+    }
+    let mut t = Table::new(min_bits);
+    let src_size = compressed_size.min(arc.len()) * 8;
+    let mut pos = ARC_HEADER_LEN * 8;
+    let mut stack: Vec<u8> = Vec::new();
+
+    let mut lastcode = get_bits(arc, pos, t.next_bits_in_use);
+    pos += t.next_bits_in_use as usize;
+    out.push(lastcode as u8);
+    t.get_table_entry();
+    let mut last_ch = lastcode as u8;
+    while out.len() < expanded_size && pos + t.next_bits_in_use as usize <= src_size {
+        let basecode = get_bits(arc, pos, t.next_bits_in_use);
+        pos += t.next_bits_in_use as usize;
+        let mut code = basecode;
+        if t.cur_entry == basecode {
+            stack.push(last_ch);
+            code = lastcode;
+        }
+        while code >= t.min_table_entry {
+            if stack.len() > TABLE_LEN {
+                return Err(bad("corrupt compressed data"));
+            }
+            stack.push(t.compress[code].ch);
+            code = t.compress[code].basecode as usize;
+        }
+        stack.push(code as u8);
+        last_ch = code as u8;
+        t.define(lastcode, last_ch);
+        t.get_table_entry();
+        while out.len() < expanded_size {
+            let Some(b) = stack.pop() else {
+                break;
+            };
+            out.push(b);
+        }
+        stack.clear();
+        lastcode = basecode;
+    }
+    if out.len() < expanded_size {
+        return Err(bad("compressed data cut short"));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // No `.Z` files are vendored under third_party/TempleOS, so these streams are worked out by
+    // hand from `CompressBuf`'s table bookkeeping rather than taken from a TempleOS disk.
+
+    #[test]
+    fn seven_bit_text_packs_into_hand_traced_codes() {
+        // "ABABABA": literals A and B, then entries 128 (AB) and 130 (ABA), all 8 bits wide.
+        let mut arc = vec![21, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0, CT_7_BIT];
+        arc.extend_from_slice(&[0x41, 0x42, 0x80, 0x82]);
+        assert_eq!(expand(&arc).expect("expand"), b"ABABABA");
+        assert_eq!(compress(b"ABABABA"), arc);
+        assert!(is_compressed(&arc));
+    }
+
+    #[test]
+    fn eight_bit_codes_are_nine_bits_lsb_first() {
+        // 0xB0 x4: codes 0xB0, 256 (B0 B0), 0xB0 at 9 bits each, packed from the low bit.
+        let mut arc = vec![21, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, CT_8_BIT];
+        arc.extend_from_slice(&[0xB0, 0x00, 0xC2, 0x02]);
+        assert_eq!(expand(&arc).expect("expand"), [0xB0; 4]);
+        assert_eq!(compress(&[0xB0; 4]), arc);
+    }
+
+    #[test]
+    fn stored_data_keeps_its_sizes_in_the_header() {
+        let mut arc = vec![20, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, CT_NONE];
+        arc.extend_from_slice(b"xyz");
+        assert!(is_compressed(&arc));
+        assert_eq!(expand(&arc).expect("expand"), b"xyz");
+        assert!(expand(&arc[..19]).is_err());
+
+        let empty = compress(b"");
+        assert_eq!(
+            empty,
+            [17, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, CT_NONE]
+        );
+        // "xyz" has nothing to share, but three 8-bit codes are no bigger than the bytes.
+        assert_eq!(
+            &compress(b"xyz")[..],
+            &[&arc[..16], &[CT_7_BIT][..], b"xyz"].concat()[..]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Windows `.BMP` files, as `BMPRead` takes them: uncompressed 1, 4 and 8-bit palette images,
+//! and 24/32-bit ones, quantized to the 16 colors.
+
+use super::*;
+
+fn bad(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// A BMP file quantized to the 16-color palette with `dither`.
+pub fn decode_bmp(bytes: &[u8], dither: Dither) -> io::Result<Canvas> {
+    let u16_at = |off: usize| -> io::Result<u32> {
+        let b = bytes
+            .get(off..off + 2)
+            .ok_or_else(|| bad("BMP header cut short"))?;
+        Ok(u16::from_le_bytes([b[0], b[1]]) as u32)
+    };
+    let u32_at = |off: usize| -> io::Result<u32> { Ok(u16_at(off)? | u16_at(off + 2)? << 16) };
+    if !bytes.starts_with(b"BM") {
+        return Err(bad("not a BMP file"));
+    }
+    let data_off = u32_at(10)? as usize;
+    let dib_len = u32_at(14)? as usize;
+    // The old OS/2 header has 16-bit sizes and 3-byte palette entries.
+    let (width, height, bpp, compression, colors, entry_len) = if dib_len == 12 {
+        (
+            u16_at(18)? as i32,
+            u16_at(20)? as i16 as i32,
+            u16_at(24)?,
+            0,
+            0,
+            3,
+        )
+    } else {
+        let (w, h) = (u32_at(18)? as i32, u32_at(22)? as i32);
+        (w, h, u16_at(28)?, u32_at(30)?, u32_at(46)? as usize, 4)
+    };
+    if compression != 0 {
+        return Err(bad("compressed BMPs are not supported"));
+    }
+    if !(1..=0x4000).contains(&width) || height == 0 || height.abs() > 0x4000 {
+        return Err(bad("bad BMP size"));
+    }
+    let palette: Vec<[u8; 4]> = if bpp <= 8 {
+        let cnt = if colors == 0 {
+            1 << bpp
+        } else {
+            colors.min(256)
+        };
+        let start = 14 + dib_len;
+        let raw = bytes
+            .get(start..start + cnt * entry_len)
+            .ok_or_else(|| bad("BMP palette cut short"))?;
+        raw.chunks_exact(entry_len)
+            .map(|c| [c[2], c[1], c[0], 255])
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let (w, h) = (width as usize, height.unsigned_abs() as usize);
+    let row_len = (w * bpp as usize).div_ceil(32) * 4;
+    let mut rgba = Vec::with_capacity(w * h * 4);
+    for y in 0..h {
+        // Rows run bottom up unless the height is negative.
+        let row = if height > 0 { h - 1 - y } else { y };
+        let start = data_off + row * row_len;
+        let src = bytes
+            .get(start..start + row_len)
+            .ok_or_else(|| bad("BMP pixels cut short"))?;
+        for x in 0..w {
+            let px = match bpp {
+                1 | 4 | 8 => {
+                    let bit = x * bpp as usize;
+                    let idx =
+                        (src[bit / 8] >> (8 - bpp as usize - bit % 8)) & ((1u16 << bpp) - 1) as u8;
+                    palette.get(idx as usize).copied().unwrap_or([0, 0, 0, 255])
+                }
+                24 => [src[x * 3 + 2], src[x * 3 + 1], src[x * 3], 255],
+                32 => [src[x * 4 + 2], src[x * 4 + 1], src[x * 4], 255],
+                _ => return Err(bad("unsupported BMP bit depth")),
+            };
+            rgba.extend_from_slice(&px);
+        }
+    }
+    Ok(quantize(w as u32, h as u32, &rgba, dither))
+}
@@ -0,0 +1,190 @@
+//! `.GR` files: a device context as `GRWrite`/`DCSave` store it. A 32-byte `CDC` header
+//! (`cdt`, `x0`, `y0`, `width`, `width_internal`, `height`, `flags`), then, with `DCF_PALETTE`,
+//! 16 `CBGR48` colors, then the 8bpp body, `width_internal` bytes per row, which
+//! `DCF_COMPRESSED` packs as a `CArcCompress`. A `.GR.Z` file is all of that compressed again.
+
+use super::*;
+
+/// Header `flags` set only in files.
+const DCF_COMPRESSED: u32 = 1;
+const DCF_PALETTE: u32 = 2;
+
+const CDC_HEADER_LEN: usize = 32;
+const CBGR48_LEN: usize = 8;
+
+/// A decoded `.GR` file.
+#[derive(Clone, Debug)]
+pub struct GrImage {
+    pub image: Canvas,
+    /// The colors saved with `DCSF_PALETTE_GET`.
+    pub palette: Option<[[u8; 4]; 16]>,
+}
+
+fn bad(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// `canvas` as a `.GR` file, its body compressed like `GRWrite`'s default `DCSF_COMPRESSED`
+/// when `compressed`, with `palette` when given.
+pub fn encode_gr(canvas: &Canvas, compressed: bool, palette: Option<&[[u8; 4]; 16]>) -> Vec<u8> {
+    let (w, h) = (canvas.width as usize, canvas.height as usize);
+    let stride = w.div_ceil(8) * 8;
+    let flags = if compressed { DCF_COMPRESSED } else { 0 }
+        | if palette.is_some() { DCF_PALETTE } else { 0 };
+    let mut out = Vec::with_capacity(CDC_HEADER_LEN + stride * h);
+    out.extend_from_slice(&0u64.to_le_bytes()); // cdt
+    for v in [0, 0, w as u32, stride as u32, h as u32, flags] {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    for [r, g, b, _] in palette.into_iter().flatten() {
+        for c in [b, g, r, &0] {
+            out.extend_from_slice(&(*c as u16 * 0x101).to_le_bytes());
+        }
+    }
+    let mut body = Vec::with_capacity(stride * h);
+    for row in canvas.pixels.chunks_exact(w.max(1)).take(h) {
+        body.extend_from_slice(row);
+        body.resize(body.len() + stride - w, TRANSPARENT);
+    }
+    if compressed {
+        out.extend_from_slice(&arc::compress(&body));
+    } else {
+        out.extend_from_slice(&body);
+    }
+    out
+}
+
+/// `DCLoad`: a `.GR` file, compressed or not, or a `.GR.Z` file.
+pub fn decode_gr(bytes: &[u8]) -> io::Result<GrImage> {
+    let expanded;
+    let bytes = if arc::is_compressed(bytes) {
+        expanded = arc::expand(bytes)?;
+        &expanded[..]
+    } else {
+        bytes
+    };
+    let header = bytes
+        .get(..CDC_HEADER_LEN)
+        .ok_or_else(|| bad("GR header cut short"))?;
+    let field = |k: usize| i32::from_le_bytes(header[8 + k * 4..12 + k * 4].try_into().unwrap());
+    let (width, stride, height, flags) = (field(2), field(3), field(4), field(5) as u32);
+    if !(0..=0x4000).contains(&width)
+        || !(width..=0x4000).contains(&stride)
+        || !(0..=0x4000).contains(&height)
+    {
+        return Err(bad("bad GR size"));
+    }
+    let mut pos = CDC_HEADER_LEN;
+    let palette = if flags & DCF_PALETTE != 0 {
+        let raw = bytes
+            .get(pos..pos + 16 * CBGR48_LEN)
+            .ok_or_else(|| bad("GR palette cut short"))?;
+        pos += raw.len();
+        Some(std::array::from_fn(|i| {
+            let c = |k: usize| raw[i * CBGR48_LEN + k * 2 + 1];
+            [c(2), c(1), c(0), 255]
+        }))
+    } else {
+        None
+    };
+    let body_len = (stride * height) as usize;
+    let body = if flags & DCF_COMPRESSED != 0 {
+        arc::expand(&bytes[pos..])?
+    } else {
+        bytes
+            .get(pos..pos + body_len)
+            .ok_or_else(|| bad("GR body cut short"))?
+            .to_vec()
+    };
+    if body.len() < body_len {
+        return Err(bad("GR body cut short"));
+    }
+    let mut image = Canvas::new(width as u32, height as u32, TRANSPARENT);
+    if width > 0 {
+        for (dst, src) in image
+            .pixels
+            .chunks_exact_mut(width as usize)
+            .zip(body.chunks(stride as usize))
+        {
+            dst.copy_from_slice(&src[..width as usize]);
+        }
+    }
+    Ok(GrImage { image, palette })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `CDC` header as `DCSave` writes it: `cdt`, then `x0`, `y0`, `width`, `width_internal`,
+    /// `height` and `flags` as `I32`s.
+    fn cdc_header(width: i32, stride: i32, height: i32, flags: u32) -> Vec<u8> {
+        let mut out = vec![0; 8];
+        for v in [0, 0, width, stride, height, flags as i32] {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        out
+    }
+
+    // No `.GR` files are vendored under third_party/TempleOS, so the files here are assembled
+    // byte by byte in `DCSave`'s layout.
+
+    #[test]
+    fn hand_built_files_decode_in_the_upstream_layout() {
+        // 3x2, rows padded to 8 bytes, a palette whose color 1 is BGR48 (0x1111, 0x2222, 0x3333).
+        let mut file = cdc_header(3, 8, 2, DCF_PALETTE);
+        assert_eq!(file.len(), CDC_HEADER_LEN);
+        for i in 0..16u16 {
+            let c: [u16; 4] = if i == 1 {
+                [0x1111, 0x2222, 0x3333, 0]
+            } else {
+                [0; 4]
+            };
+            for v in c {
+                file.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        file.extend_from_slice(&[1, 2, 3, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        file.extend_from_slice(&[4, 5, 6, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        let gr = decode_gr(&file).expect("decode");
+        assert_eq!((gr.image.width, gr.image.height), (3, 2));
+        assert_eq!(gr.image.pixels, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(gr.palette.expect("palette")[1], [0x33, 0x22, 0x11, 255]);
+        assert_eq!(
+            encode_gr(&gr.image, false, gr.palette.as_ref())[..CDC_HEADER_LEN],
+            file[..CDC_HEADER_LEN]
+        );
+
+        // A 7x1 "ABABABA" row plus its padding byte ('A'), as `CompressBuf` packs the body.
+        let mut file = cdc_header(7, 8, 1, DCF_COMPRESSED);
+        file.extend_from_slice(&[22, 0, 0, 0, 0, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 2]);
+        file.extend_from_slice(&[0x41, 0x42, 0x80, 0x82, 0x41]);
+        let gr = decode_gr(&file).expect("decode compressed");
+        assert_eq!(gr.image.pixels, b"ABABABA");
+        assert!(gr.palette.is_none());
+    }
+
+    #[test]
+    fn encoded_files_carry_the_32_byte_header_and_sizes() {
+        let mut canvas = Canvas::new(10, 3, 0);
+        canvas.pixels[0] = 14;
+        let file = encode_gr(&canvas, true, None);
+        assert_eq!(
+            file[..CDC_HEADER_LEN],
+            cdc_header(10, 16, 3, DCF_COMPRESSED)[..]
+        );
+        let body = &file[CDC_HEADER_LEN..];
+        assert!(arc::is_compressed(body));
+        assert_eq!(
+            &body[8..12],
+            &48u32.to_le_bytes(),
+            "expanded_size is stride * height"
+        );
+        let raw = encode_gr(&canvas, false, None);
+        assert_eq!(raw.len(), CDC_HEADER_LEN + 48);
+        assert_eq!(
+            decode_gr(&file).expect("decode").image.pixels,
+            canvas.pixels
+        );
+    }
+}
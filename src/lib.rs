@@ -1,4 +1,8 @@
 pub mod assets;
+pub mod doldoc;
+pub mod font;
+pub mod gr3d;
+pub mod image;
 pub mod protocol;
 pub mod rt;
 pub mod sprite;
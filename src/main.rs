@@ -17,7 +17,7 @@ use temple_rt::protocol;
 use wgpu::util::DeviceExt as _;
 use winit::{
     dpi::{PhysicalPosition, PhysicalSize},
-    event::{ElementState, Event, MouseButton, MouseScrollDelta, WindowEvent},
+    event::{ElementState, Event, MouseButton, MouseScrollDelta, StartCause, WindowEvent},
     event_loop::{ControlFlow, EventLoopBuilder, EventLoopProxy},
     keyboard::{Key, NamedKey},
     window::{Fullscreen, Window, WindowBuilder},
@@ -39,6 +39,8 @@ const COLOR_FG: u8 = 15;
 const COLOR_STATUS_BG: u8 = 4;
 const COLOR_SEL_BG: u8 = 1;
 const COLOR_SEL_FG: u8 = 15;
+const COLOR_FIND_BG: u8 = 14;
+const COLOR_FIND_FG: u8 = 0;
 
 const CURSOR_W: u32 = 8;
 const CURSOR_H: u32 = 8;
@@ -50,3 +52,5 @@ include!("templeshell/01_paths_browser.rs");
 include!("templeshell/02_shell.rs");
 include!("templeshell/03_gfx.rs");
 include!("templeshell/04_app.rs");
+include!("templeshell/05_doc_index.rs");
+include!("templeshell/06_doc_nav.rs");
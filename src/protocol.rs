@@ -26,6 +26,11 @@ pub const MSG_MUTE: u16 = 13;
 pub const MSG_PALETTE_COLOR_SET: u16 = 14;
 pub const MSG_SETTINGS_PUSH: u16 = 15;
 pub const MSG_SETTINGS_POP: u16 = 16;
+/// App -> shell: a UTF-8 request line of `a` bytes follows (`doc <spec>`, `dir <spec>`, ...).
+pub const MSG_SHELL_REQUEST: u16 = 18;
+/// App -> shell: the app handled the input it took since its last ack (it yielded or presented),
+/// so it isn't hung.
+pub const MSG_INPUT_ACK: u16 = 19;
 
 pub const KEY_STATE_UP: u32 = 0;
 pub const KEY_STATE_DOWN: u32 = 1;
@@ -208,6 +213,22 @@ impl Msg {
         }
     }
 
+    pub fn shell_request(byte_len: u32) -> Self {
+        Self {
+            kind: MSG_SHELL_REQUEST,
+            a: byte_len,
+            b: 0,
+        }
+    }
+
+    pub fn input_ack() -> Self {
+        Self {
+            kind: MSG_INPUT_ACK,
+            a: 0,
+            b: 0,
+        }
+    }
+
     pub fn shutdown() -> Self {
         Self {
             kind: MSG_SHUTDOWN,
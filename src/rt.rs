@@ -11,6 +11,7 @@ use std::{
 };
 
 use crate::assets;
+use crate::font::{self, Font};
 use crate::protocol::{self, Msg};
 
 pub struct TempleRt {
@@ -19,11 +20,87 @@ pub struct TempleRt {
     fb: memmap2::MmapMut,
     stream: UnixStream,
     events: mpsc::Receiver<Event>,
+    /// Input taken off the queue since the app last presented or called `ack_input`.
+    input_unacked: std::cell::Cell<bool>,
     present_acks: mpsc::Receiver<u32>,
     present_seq: u32,
     clip: ClipRect,
     font_u64: [u64; 256],
+    /// The window's text font (`TEMPLE_FONT`) and scale (`TEMPLE_TEXT_SCALE`) for
+    /// `draw_text_scaled`; `None` draws with `font_u64`.
+    text_font: Option<Font>,
+    text_scale: u32,
     sync_present: bool,
+    rop: Rop,
+    collisions: i64,
+}
+
+/// TempleOS' `TRANSPARENT` color.
+pub const TRANSPARENT: u8 = 0xFF;
+
+/// Raster ops, in `CColorROPU32` bits 8..10 (`c0.rop`).
+pub const ROP_EQU: u32 = 0x000;
+pub const ROP_XOR: u32 = 0x100;
+pub const ROP_COLLISION: u32 = 0x200;
+pub const ROP_MONO: u32 = 0x300;
+const ROP_MASK: u32 = 0x300;
+/// Alternates `c0` with `c1` (bits 16..24) in a checkerboard.
+pub const ROPF_DITHER: u32 = 0x4000;
+
+/// How drawn pixels combine with what is there: the raster op and dither bits of a TempleOS
+/// `dc->color`, and the `dc->bkcolor` that `ROP_COLLISION` does not count.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rop {
+    pub color: u32,
+    pub bkcolor: u8,
+}
+
+impl Rop {
+    pub fn new(color: u32, bkcolor: u8) -> Self {
+        Self { color, bkcolor }
+    }
+
+    /// Plain `ROP_EQU` without dithering: pixels are simply overwritten.
+    pub fn is_plain(self) -> bool {
+        self.color & (ROP_MASK | ROPF_DITHER) == 0
+    }
+
+    /// `SPT_COLOR`/`SPT_DITHER_COLOR`: the same raster op, dithering with `c1` or not at all.
+    pub fn with_dither(self, c1: Option<u8>) -> Self {
+        let color = self.color & !(ROPF_DITHER | 0xFF_0000);
+        Self {
+            color: match c1 {
+                Some(c1) => color | ROPF_DITHER | (c1 as u32) << 16,
+                None => color,
+            },
+            ..self
+        }
+    }
+
+    /// The color a blitted source pixel is drawn in: `c0` under `ROP_MONO`.
+    pub fn blit_color(self, src: u8) -> u8 {
+        if self.color & ROP_MASK == ROP_MONO {
+            self.color as u8
+        } else {
+            src
+        }
+    }
+
+    /// Draws `color` at (`x`, `y`) over `*px`. Returns whether it was a `ROP_COLLISION` hit:
+    /// that op only counts pixels that are neither `TRANSPARENT` nor `bkcolor`.
+    pub fn plot(self, x: i32, y: i32, color: u8, px: &mut u8) -> bool {
+        let color = if self.color & ROPF_DITHER != 0 && (x ^ y) & 1 != 0 {
+            (self.color >> 16) as u8
+        } else {
+            color
+        };
+        match self.color & ROP_MASK {
+            ROP_XOR => *px ^= color,
+            ROP_COLLISION => return *px != TRANSPARENT && *px != self.bkcolor,
+            _ => *px = color,
+        }
+        false
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -138,18 +215,27 @@ impl TempleRt {
             }
         });
 
-        Ok(Self {
+        let mut rt = Self {
             width,
             height,
             fb,
             stream,
             events: rx,
+            input_unacked: std::cell::Cell::new(false),
             present_acks: ack_rx,
             present_seq: 0,
             clip: ClipRect::full(width, height),
             font_u64: assets::TEMPLEOS_SYS_FONT_STD_U64,
+            text_font: None,
+            text_scale: 1,
             sync_present: env_truthy("TEMPLE_SYNC_PRESENT"),
-        })
+            rop: Rop::default(),
+            collisions: 0,
+        };
+        if let Err(err) = rt.load_env_text_font() {
+            eprintln!("temple_rt: TEMPLE_FONT: {err}");
+        }
+        Ok(rt)
     }
 
     pub fn size(&self) -> (u32, u32) {
@@ -201,7 +287,25 @@ impl TempleRt {
             return;
         }
         let idx = (y * self.width + x) as usize;
-        self.fb[idx] = color;
+        if self.rop.is_plain() {
+            self.fb[idx] = color;
+        } else if self.rop.plot(x as i32, y as i32, color, &mut self.fb[idx]) {
+            self.collisions += 1;
+        }
+    }
+
+    /// The raster op drawing goes through; [`Rop::default`] overwrites.
+    pub fn rop(&self) -> Rop {
+        self.rop
+    }
+
+    pub fn set_rop(&mut self, rop: Rop) {
+        self.rop = rop;
+    }
+
+    /// `ROP_COLLISION` hits since the last call.
+    pub fn take_collisions(&mut self) -> i64 {
+        std::mem::take(&mut self.collisions)
     }
 
     pub fn fill_rect(&mut self, x: i32, y: i32, w: i32, h: i32, color: u8) {
@@ -228,6 +332,14 @@ impl TempleRt {
         let x1 = x1 as u32;
         let y1 = y1 as u32;
 
+        if !self.rop.is_plain() {
+            for yy in y0..y1 {
+                for xx in x0..x1 {
+                    self.set_pixel(xx as i32, yy as i32, color);
+                }
+            }
+            return;
+        }
         for yy in y0..y1 {
             let row = (yy * self.width) as usize;
             let start = row + x0 as usize;
@@ -407,6 +519,19 @@ impl TempleRt {
         let copy_w = (dst_x1 - dst_x0) as usize;
         let src_x0 = (dst_x0 - dst_x) as usize;
 
+        if !self.rop.is_plain() {
+            for dy in dst_y0..dst_y1 {
+                let src_row = &src[(dy - dst_y) as usize * src_w + src_x0..][..copy_w];
+                for (i, &src_px) in src_row.iter().enumerate() {
+                    if Some(src_px) != transparent {
+                        let color = self.rop.blit_color(src_px);
+                        self.set_pixel(dst_x0 + i as i32, dy, color);
+                    }
+                }
+            }
+            return;
+        }
+
         match transparent {
             None => {
                 for dy in dst_y0..dst_y1 {
@@ -458,14 +583,79 @@ impl TempleRt {
         }
     }
 
+    /// The 8x8 font in use: one `u64` per CP437 glyph, a byte per row, low bit leftmost.
+    pub fn font_u64(&self) -> &[u64; 256] {
+        &self.font_u64
+    }
+
     pub fn set_font_glyph_u64(&mut self, glyph: u8, bits: u64) {
         self.font_u64[glyph as usize] = bits;
     }
 
+    /// The font `draw_text_scaled` uses, when not the 8x8 one.
+    pub fn text_font(&self) -> Option<&Font> {
+        self.text_font.as_ref()
+    }
+
+    pub fn set_text_font(&mut self, font: Option<Font>) {
+        self.text_font = font;
+    }
+
+    pub fn text_scale(&self) -> u32 {
+        self.text_scale
+    }
+
+    pub fn set_text_scale(&mut self, scale: u32) {
+        self.text_scale = scale.clamp(1, font::MAX_SCALE);
+    }
+
+    /// Size of a `draw_text_scaled` character cell.
+    pub fn text_char_size(&self) -> (u32, u32) {
+        let (w, h) = self
+            .text_font
+            .as_ref()
+            .map_or((8, 8), |f| (f.width, f.height));
+        (w * self.text_scale, h * self.text_scale)
+    }
+
+    /// Picks up the font and scale TempleShell gives a window: `TEMPLE_FONT` names a font in
+    /// `TEMPLE_ROOT/Fonts`, `TEMPLE_TEXT_SCALE` a whole-number scale.
+    pub fn load_env_text_font(&mut self) -> io::Result<()> {
+        if let Some(scale) = std::env::var("TEMPLE_TEXT_SCALE")
+            .ok()
+            .and_then(|v| v.trim().parse::<u32>().ok())
+        {
+            self.set_text_scale(scale);
+        }
+        let name = std::env::var("TEMPLE_FONT").unwrap_or_default();
+        let name = name.trim();
+        if name.is_empty() || name.eq_ignore_ascii_case(font::STD_FONT) {
+            return Ok(());
+        }
+        let root = std::env::var_os("TEMPLE_ROOT").unwrap_or_default();
+        self.text_font = Some(font::load_font(std::path::Path::new(&root), name)?);
+        Ok(())
+    }
+
+    /// Text in the window's font at its scale, on `bg` unless that is `None`; `\n` goes back to
+    /// `x` on the same row.
+    pub fn draw_text_scaled(&mut self, x: i32, y: i32, fg: u8, bg: Option<u8>, text: &str) {
+        let font = self
+            .text_font
+            .clone()
+            .unwrap_or_else(|| Font::from_u64(font::STD_FONT, &self.font_u64));
+        font.draw_text(x, y, self.text_scale, text, |px, py, on| match (on, bg) {
+            (true, _) => self.set_pixel(px, py, fg),
+            (false, Some(bg)) => self.set_pixel(px, py, bg),
+            (false, None) => {}
+        });
+    }
+
     pub fn present(&mut self) -> io::Result<()> {
         self.present_seq = self.present_seq.wrapping_add(1);
         let seq = self.present_seq;
         protocol::write_msg(&mut self.stream, Msg::present(seq))?;
+        self.ack_input()?;
         if self.sync_present {
             self.wait_for_present_ack(seq)?;
         }
@@ -550,8 +740,42 @@ impl TempleRt {
         Ok(())
     }
 
+    /// Asks the hosting shell to act on the app's behalf (open a document, list a directory...).
+    pub fn shell_request(&mut self, req: &str) -> io::Result<()> {
+        const MAX_BYTES: usize = 64 * 1024;
+
+        let bytes = req.as_bytes();
+        if bytes.len() > MAX_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "temple-rt: shell request too large",
+            ));
+        }
+
+        protocol::write_msg(&mut self.stream, Msg::shell_request(bytes.len() as u32))?;
+        self.stream.write_all(bytes)?;
+        Ok(())
+    }
+
     pub fn try_next_event(&self) -> Option<Event> {
-        self.events.try_recv().ok()
+        let ev = self.events.try_recv().ok()?;
+        if matches!(
+            ev,
+            Event::Key { .. } | Event::MouseButton { .. } | Event::MouseWheel { .. }
+        ) {
+            self.input_unacked.set(true);
+        }
+        Some(ev)
+    }
+
+    /// Tells the shell's hang watchdog the input taken so far was handled. `present` does this
+    /// itself; an app that waits for input without presenting calls it when it yields. Only
+    /// taking events off the queue is not enough: a busy loop polling for Ctrl+Alt+C does that.
+    pub fn ack_input(&self) -> io::Result<()> {
+        if self.input_unacked.replace(false) {
+            (&self.stream).write_all(&Msg::input_ack().to_bytes())?;
+        }
+        Ok(())
     }
 }
 
@@ -562,3 +786,14 @@ fn env_truthy(name: &str) -> bool {
     let v = val.to_string_lossy();
     !(v.is_empty() || v == "0" || v.eq_ignore_ascii_case("false") || v.eq_ignore_ascii_case("no"))
 }
+
+/// Files under `TEMPLEOS_ROOT` belong to the vendored TempleOS tree, which tools never write.
+pub fn is_read_only_templeos_path(path: &std::path::Path) -> bool {
+    let Some(root) = std::env::var_os("TEMPLEOS_ROOT") else {
+        return false;
+    };
+    let root = std::path::PathBuf::from(root);
+    let root = std::fs::canonicalize(&root).unwrap_or(root);
+    let abs = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    abs.starts_with(&root)
+}
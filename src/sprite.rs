@@ -1,6 +1,12 @@
 use std::ops::Range;
 
 use crate::assets;
+use crate::gr3d::{Gr3, Transform3};
+use crate::rt::Rop;
+
+mod elem;
+
+pub use elem::{MeshTri, SpriteElem, sprite_elem_bytes, sprite_elems};
 
 const SPG_TYPE_MASK: u8 = 0x7f;
 
@@ -101,6 +107,13 @@ pub trait SpriteTarget {
         stride: i32,
         src: &[u8],
     );
+
+    /// The raster op pixels are drawn with; targets without raster ops just overwrite.
+    fn rop(&self) -> Rop {
+        Rop::default()
+    }
+
+    fn set_rop(&mut self, _rop: Rop) {}
 }
 
 fn read_i32_le(bytes: &[u8], off: usize) -> Option<i32> {
@@ -108,6 +121,11 @@ fn read_i32_le(bytes: &[u8], off: usize) -> Option<i32> {
     Some(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
 }
 
+fn read_f64_le(bytes: &[u8], off: usize) -> Option<f64> {
+    let b = bytes.get(off..off + 8)?;
+    Some(f64::from_le_bytes(b.try_into().ok()?))
+}
+
 fn read_u16_le(bytes: &[u8], off: usize) -> Option<u16> {
     let b = bytes.get(off..off + 2)?;
     Some(u16::from_le_bytes([b[0], b[1]]))
@@ -358,6 +376,13 @@ fn sprite_bounds_from(bytes: &[u8], start: usize) -> Option<SpriteBounds> {
                     r.saturating_mul(2).saturating_add(1),
                 );
             }
+            SPT_POLYLINE | SPT_POLYPT | SPT_BSPLINE2 | SPT_BSPLINE2_CLOSED | SPT_BSPLINE3
+            | SPT_BSPLINE3_CLOSED | SPT_ELLIPSE | SPT_ROTATED_RECT | SPT_POLYGON => {
+                let (Stroke::Lines(pts) | Stroke::Pixels(pts)) = curve_stroke(bytes, off)?;
+                for (x, y) in pts {
+                    bounds.include_point(dx.saturating_add(x), dy.saturating_add(y));
+                }
+            }
             SPT_TEXT | SPT_TEXT_DIAMOND | SPT_TEXT_BOX => {
                 let x = read_i32_le(bytes, off + 1)?;
                 let y = read_i32_le(bytes, off + 5)?;
@@ -527,422 +552,454 @@ pub fn sprite_render_with_state(
     );
 }
 
-fn sprite_render_from(
-    target: &mut impl SpriteTarget,
-    base_x: i32,
-    base_y: i32,
+/// `Sprite3`: renders `bytes` at (`x`, `y`, `z`) through `gr`. While the transform is on (the
+/// DC's `DCF_TRANSFORMATION`, switched by `SPT_TRANSFORM_ON`/`SPT_TRANSFORM_OFF`) points, lines,
+/// rectangles, circles, arrows and meshes are transformed vertex by vertex; other elements are
+/// drawn flat at their transformed origin. Meshes are always depth tested against `gr`'s buffer.
+pub fn sprite_render3<T: SpriteTarget + ?Sized>(
+    gr: &mut Gr3<'_, T>,
+    x: i64,
+    y: i64,
+    z: i64,
     bytes: &[u8],
-    start: usize,
     initial_color: u8,
     initial_thick: i32,
 ) {
-    let mut off = start;
-    let mut color: u8 = initial_color & 0x0f;
-    let mut thick: i32 = initial_thick.max(1);
-    let mut dx: i32 = 0;
-    let mut dy: i32 = 0;
-
+    let was_transforming = gr.transforming;
+    let rop = gr.rop();
+    let (mut x, mut y, mut z) = (x, y, z);
+    let mut pen = Pen::new(initial_color, initial_thick);
+    let mut off = sprite_best_start(bytes);
     while off < bytes.len() {
         let t = bytes[off] & SPG_TYPE_MASK;
-        match t {
-            SPT_END => break,
-            SPT_COLOR => {
-                if let Some(&c) = bytes.get(off + 1) {
-                    color = c & 0x0f;
-                }
-            }
-            SPT_DITHER_COLOR => {
-                if let Some(v) = read_u16_le(bytes, off + 1) {
-                    color = (v as u8) & 0x0f;
+        let ok = match t {
+            // Like TempleOS, the origin moves so that switching keeps it where it was on screen.
+            SPT_TRANSFORM_ON => {
+                if !gr.transforming {
+                    (x, y, z) = (x - gr.xf.x, y - gr.xf.y, z - gr.xf.z);
+                    gr.transforming = true;
                 }
+                true
             }
-            SPT_THICK => {
-                if let Some(v) = read_i32_le(bytes, off + 1) {
-                    thick = v.max(1);
+            SPT_TRANSFORM_OFF => {
+                if gr.transforming {
+                    (x, y, z) = (x + gr.xf.x, y + gr.xf.y, z + gr.xf.z);
+                    gr.transforming = false;
                 }
+                true
             }
-            SPT_SHIFT => {
-                let Some(x) = read_i32_le(bytes, off + 1) else {
-                    break;
-                };
-                let Some(y) = read_i32_le(bytes, off + 5) else {
-                    break;
-                };
-                dx = dx.saturating_add(x);
-                dy = dy.saturating_add(y);
-            }
-            SPT_PT => {
-                let Some(x) = read_i32_le(bytes, off + 1) else {
-                    break;
-                };
-                let Some(y) = read_i32_le(bytes, off + 5) else {
-                    break;
-                };
-                if thick == 1 {
-                    target.set_pixel(base_x + dx + x, base_y + dy + y, color);
-                } else {
-                    let half = thick / 2;
-                    target.fill_rect(
-                        base_x + dx + x - half,
-                        base_y + dy + y - half,
-                        thick,
-                        thick,
-                        color,
-                    );
-                }
-            }
-            SPT_LINE => {
-                let Some(x1) = read_i32_le(bytes, off + 1) else {
-                    break;
-                };
-                let Some(y1) = read_i32_le(bytes, off + 5) else {
-                    break;
-                };
-                let Some(x2) = read_i32_le(bytes, off + 9) else {
-                    break;
-                };
-                let Some(y2) = read_i32_le(bytes, off + 13) else {
-                    break;
-                };
-                target.draw_line_thick(
-                    base_x + dx + x1,
-                    base_y + dy + y1,
-                    base_x + dx + x2,
-                    base_y + dy + y2,
-                    color,
-                    thick,
-                );
-            }
-            SPT_RECT => {
-                let Some(x1) = read_i32_le(bytes, off + 1) else {
-                    break;
-                };
-                let Some(y1) = read_i32_le(bytes, off + 5) else {
-                    break;
-                };
-                let Some(x2) = read_i32_le(bytes, off + 9) else {
-                    break;
-                };
-                let Some(y2) = read_i32_le(bytes, off + 13) else {
-                    break;
-                };
-                target.draw_rect_outline_thick(
-                    base_x + dx + x1,
-                    base_y + dy + y1,
-                    x2 - x1,
-                    y2 - y1,
-                    color,
-                    thick,
-                );
-            }
-            SPT_CIRCLE => {
-                let Some(x) = read_i32_le(bytes, off + 1) else {
-                    break;
-                };
-                let Some(y) = read_i32_le(bytes, off + 5) else {
-                    break;
-                };
-                let Some(r) = read_i32_le(bytes, off + 9) else {
-                    break;
-                };
-                target.draw_circle_thick(base_x + dx + x, base_y + dy + y, r, color, thick);
-            }
-            SPT_ARROW => {
-                let Some(x1) = read_i32_le(bytes, off + 1) else {
-                    break;
-                };
-                let Some(y1) = read_i32_le(bytes, off + 5) else {
-                    break;
-                };
-                let Some(x2) = read_i32_le(bytes, off + 9) else {
-                    break;
-                };
-                let Some(y2) = read_i32_le(bytes, off + 13) else {
-                    break;
-                };
-                draw_arrow(
-                    target,
-                    base_x + dx + x1,
-                    base_y + dy + y1,
-                    base_x + dx + x2,
-                    base_y + dy + y2,
-                    color,
-                    thick,
-                );
-            }
-            SPT_TEXT | SPT_TEXT_DIAMOND => {
-                let Some(x) = read_i32_le(bytes, off + 1) else {
-                    break;
-                };
-                let Some(y) = read_i32_le(bytes, off + 5) else {
-                    break;
-                };
-                let base = off + 1 + 4 + 4;
-                let Some(s) = sprite_text_nul_range(bytes, base) else {
-                    break;
-                };
-                draw_text_transparent_8x8(
-                    target,
-                    base_x + dx + x,
-                    base_y + dy + y,
-                    color,
-                    &bytes[s],
-                );
-            }
-            SPT_TEXT_BOX => {
-                let Some(x) = read_i32_le(bytes, off + 1) else {
-                    break;
-                };
-                let Some(y) = read_i32_le(bytes, off + 5) else {
-                    break;
-                };
-                let base = off + 1 + 4 + 4;
-                let Some(s) = sprite_text_nul_range(bytes, base) else {
-                    break;
-                };
-                let text = &bytes[s];
-                draw_text_transparent_8x8(target, base_x + dx + x, base_y + dy + y, color, text);
-                let (w, h) = measure_text_box(text);
-                let border = 2;
-                target.draw_rect_outline_thick(
-                    base_x + dx + x - border,
-                    base_y + dy + y - border,
-                    w + border * 2,
-                    h + border * 2,
-                    color,
-                    thick,
-                );
-            }
-            SPT_BITMAP => {
-                let Some(x) = read_i32_le(bytes, off + 1) else {
-                    break;
-                };
-                let Some(y) = read_i32_le(bytes, off + 5) else {
-                    break;
-                };
-                let Some(w) = read_i32_le(bytes, off + 9) else {
-                    break;
-                };
-                let Some(h) = read_i32_le(bytes, off + 13) else {
-                    break;
-                };
-                if w <= 0 || h <= 0 {
-                    // nothing
-                } else {
-                    let stride = ceil_to_multiple(w, 8);
-                    let data_off = off + 1 + 4 * 4;
-                    let data_len = match (stride as usize).checked_mul(h as usize) {
-                        Some(v) => v,
-                        None => break,
-                    };
-                    let Some(src) = bytes.get(data_off..data_off + data_len) else {
-                        break;
-                    };
-                    target.blit_8bpp(base_x + dx + x, base_y + dy + y, w, h, stride, src);
-                }
+            SPT_MESH | SPT_SHIFTABLE_MESH => render_mesh3(gr, bytes, off, (x, y, z), &pen),
+            SPT_PT | SPT_LINE | SPT_RECT | SPT_CIRCLE | SPT_ARROW if gr.transforming => {
+                render_elem3(gr, bytes, off, (x, y, z), &pen)
             }
-            SPT_MESH => {
-                let Some(vertex_cnt) = read_i32_le(bytes, off + 1) else {
-                    break;
-                };
-                let Some(tri_cnt) = read_i32_le(bytes, off + 5) else {
-                    break;
-                };
-                if vertex_cnt <= 0 || tri_cnt <= 0 {
-                    // nothing
-                } else {
-                    let vertex_cnt = vertex_cnt as usize;
-                    let tri_cnt = tri_cnt as usize;
-                    if vertex_cnt <= 65_536 && tri_cnt <= 65_536 {
-                        let verts_off = off + 1 + 4 + 4;
-                        let verts_bytes = vertex_cnt.saturating_mul(12);
-                        let tris_off = verts_off.saturating_add(verts_bytes);
-                        let tris_bytes = tri_cnt.saturating_mul(16);
-                        let Some(verts) = bytes.get(verts_off..tris_off) else {
-                            break;
-                        };
-                        let Some(tris) = bytes.get(tris_off..tris_off + tris_bytes) else {
-                            break;
-                        };
-                        render_mesh(
-                            target,
-                            base_x.saturating_add(dx),
-                            base_y.saturating_add(dy),
-                            verts,
-                            tris,
-                        );
-                    }
-                }
-            }
-            SPT_SHIFTABLE_MESH => {
-                let Some(tx) = read_i32_le(bytes, off + 1) else {
-                    break;
-                };
-                let Some(ty) = read_i32_le(bytes, off + 5) else {
-                    break;
-                };
-                let Some(vertex_cnt) = read_i32_le(bytes, off + 1 + 12) else {
-                    break;
-                };
-                let Some(tri_cnt) = read_i32_le(bytes, off + 1 + 16) else {
-                    break;
-                };
-                if vertex_cnt <= 0 || tri_cnt <= 0 {
-                    // nothing
-                } else {
-                    let vertex_cnt = vertex_cnt as usize;
-                    let tri_cnt = tri_cnt as usize;
-                    if vertex_cnt <= 65_536 && tri_cnt <= 65_536 {
-                        let verts_off = off + 1 + 4 * 5;
-                        let verts_bytes = vertex_cnt.saturating_mul(12);
-                        let tris_off = verts_off.saturating_add(verts_bytes);
-                        let tris_bytes = tri_cnt.saturating_mul(16);
-                        let Some(verts) = bytes.get(verts_off..tris_off) else {
-                            break;
-                        };
-                        let Some(tris) = bytes.get(tris_off..tris_off + tris_bytes) else {
-                            break;
-                        };
-                        render_mesh(
-                            target,
-                            base_x.saturating_add(dx).saturating_add(tx),
-                            base_y.saturating_add(dy).saturating_add(ty),
-                            verts,
-                            tris,
-                        );
-                    }
-                }
+            _ => {
+                let (ox, oy, oz) = gr.project(x, y, z);
+                gr.z = oz;
+                render_elem(gr, ox as i32, oy as i32, bytes, off, &mut pen)
             }
-            _ => {}
+        };
+        if !ok {
+            break;
         }
-
         let Some(sz) = sprite_elem_size(bytes, off) else {
             break;
         };
         off += sz;
     }
+    gr.transforming = was_transforming;
+    gr.set_rop(rop);
 }
 
-fn render_mesh(
-    target: &mut impl SpriteTarget,
-    base_x: i32,
-    base_y: i32,
-    verts: &[u8],
-    tris: &[u8],
-) {
-    fn edge(ax: i32, ay: i32, bx: i32, by: i32, cx: i32, cy: i32) -> i64 {
-        (cx as i64 - ax as i64) * (by as i64 - ay as i64)
-            - (cy as i64 - ay as i64) * (bx as i64 - ax as i64)
-    }
-
-    fn fill_tri(
-        target: &mut impl SpriteTarget,
-        x0: i32,
-        y0: i32,
-        x1: i32,
-        y1: i32,
-        x2: i32,
-        y2: i32,
-        color: u8,
-    ) {
-        let min_x = x0.min(x1).min(x2);
-        let max_x = x0.max(x1).max(x2);
-        let min_y = y0.min(y1).min(y2);
-        let max_y = y0.max(y1).max(y2);
-
-        let area = edge(x0, y0, x1, y1, x2, y2);
-        if area == 0 {
-            return;
+/// A point, line, rectangle, circle or arrow drawn through `gr`'s transform.
+fn render_elem3<T: SpriteTarget + ?Sized>(
+    gr: &mut Gr3<'_, T>,
+    bytes: &[u8],
+    off: usize,
+    origin: (i64, i64, i64),
+    pen: &Pen,
+) -> bool {
+    let at = |x: i32, y: i32| {
+        (
+            origin.0 + (pen.dx + x) as i64,
+            origin.1 + (pen.dy + y) as i64,
+            origin.2,
+        )
+    };
+    let arg = |i: usize| read_i32_le(bytes, off + 1 + 4 * i);
+    let (color, thick) = (pen.color, pen.thick);
+    match bytes[off] & SPG_TYPE_MASK {
+        SPT_PT => {
+            let (Some(x), Some(y)) = (arg(0), arg(1)) else {
+                return false;
+            };
+            gr.plot3(at(x, y), color, thick);
         }
-
-        // Clamp degenerate / huge triangles to avoid pathological slow paths.
-        let w = max_x.saturating_sub(min_x).saturating_add(1);
-        let h = max_y.saturating_sub(min_y).saturating_add(1);
-        if w as i64 * h as i64 > 500_000 {
-            return;
+        SPT_CIRCLE => {
+            let (Some(x), Some(y), Some(r)) = (arg(0), arg(1), arg(2)) else {
+                return false;
+            };
+            gr.circle3(at(x, y), r as i64, color, thick);
         }
-
-        let wants_pos = area > 0;
-        for y in min_y..=max_y {
-            for x in min_x..=max_x {
-                let w0 = edge(x1, y1, x2, y2, x, y);
-                let w1 = edge(x2, y2, x0, y0, x, y);
-                let w2 = edge(x0, y0, x1, y1, x, y);
-                let inside = if wants_pos {
-                    w0 >= 0 && w1 >= 0 && w2 >= 0
-                } else {
-                    w0 <= 0 && w1 <= 0 && w2 <= 0
-                };
-                if inside {
-                    target.set_pixel(x, y, color);
+        t => {
+            let (Some(x1), Some(y1), Some(x2), Some(y2)) = (arg(0), arg(1), arg(2), arg(3)) else {
+                return false;
+            };
+            match t {
+                SPT_RECT => {
+                    // The same pixels as the flat outline: `x2`/`y2` are exclusive.
+                    let (x2, y2) = (x2 - 1, y2 - 1);
+                    let corners = [(x1, y1), (x2, y1), (x2, y2), (x1, y2), (x1, y1)];
+                    for w in corners.windows(2) {
+                        gr.line3(at(w[0].0, w[0].1), at(w[1].0, w[1].1), color, thick);
+                    }
+                }
+                _ => {
+                    gr.line3(at(x1, y1), at(x2, y2), color, thick);
+                    if t == SPT_ARROW {
+                        for (ax, ay) in arrow_head(x1, y1, x2, y2, thick).into_iter().flatten() {
+                            gr.line3(at(ax, ay), at(x2, y2), color, thick);
+                        }
+                    }
                 }
             }
         }
     }
+    true
+}
 
-    let vertex_cnt = verts.len() / 12;
-    let tri_cnt = tris.len() / 16;
-    if vertex_cnt == 0 || tri_cnt == 0 {
-        return;
+/// A mesh's `(x, y, z)` shift (`SPT_SHIFTABLE_MESH` only), vertex bytes and triangle bytes.
+type MeshParts<'a> = ((i32, i32, i32), &'a [u8], &'a [u8]);
+
+/// The parts of the mesh at `off`; empty slices for an empty or implausibly large mesh, `None`
+/// when truncated.
+fn mesh_elem(bytes: &[u8], off: usize) -> Option<MeshParts<'_>> {
+    let (shift, head) = match bytes[off] & SPG_TYPE_MASK {
+        SPT_SHIFTABLE_MESH => (
+            (
+                read_i32_le(bytes, off + 1)?,
+                read_i32_le(bytes, off + 5)?,
+                read_i32_le(bytes, off + 9)?,
+            ),
+            off + 1 + 12,
+        ),
+        _ => ((0, 0, 0), off + 1),
+    };
+    let vertex_cnt = read_i32_le(bytes, head)?;
+    let tri_cnt = read_i32_le(bytes, head + 4)?;
+    if !(1..=65_536).contains(&vertex_cnt) || !(1..=65_536).contains(&tri_cnt) {
+        return Some((shift, &[], &[]));
     }
+    let verts_off = head + 8;
+    let tris_off = verts_off + vertex_cnt as usize * 12;
+    let verts = bytes.get(verts_off..tris_off)?;
+    let tris = bytes.get(tris_off..tris_off + tri_cnt as usize * 16)?;
+    Some((shift, verts, tris))
+}
+
+/// A mesh as lit, filled triangles (see [`Gr3::mesh`]), each vertex through `gr`'s transform
+/// while it is on.
+fn render_mesh3<T: SpriteTarget + ?Sized>(
+    gr: &mut Gr3<'_, T>,
+    bytes: &[u8],
+    off: usize,
+    origin: (i64, i64, i64),
+    pen: &Pen,
+) -> bool {
+    let Some((shift, verts, tris)) = mesh_elem(bytes, off) else {
+        return false;
+    };
+    let vertex_cnt = verts.len() / 12;
+    let pts: Vec<(i64, i64, i64)> = (0..vertex_cnt)
+        .map(|i| {
+            let coord =
+                |k: usize| read_i32_le(verts, i * 12 + k * 4).map_or(0, decode_mesh_coord) as i64;
+            gr.project(
+                origin.0 + (pen.dx + shift.0) as i64 + coord(0),
+                origin.1 + (pen.dy + shift.1) as i64 + coord(1),
+                origin.2 + shift.2 as i64 + coord(2),
+            )
+        })
+        .collect();
+    let tris: Vec<(u8, [usize; 3])> = tris
+        .chunks_exact(16)
+        .filter_map(|tri| {
+            let field = |k: usize| read_i32_le(tri, k * 4).unwrap_or(0);
+            let idx = |k: usize| decode_mesh_index(field(k), vertex_cnt);
+            Some((decode_mesh_color(field(0)), [idx(1)?, idx(2)?, idx(3)?]))
+        })
+        .collect();
+    gr.mesh(&pts, &tris);
+    true
+}
 
-    // Decode vertices once. Some vendored TempleOS sprites (notably PersonalMenu mesh icons) store
-    // coordinates/indices shifted into higher bytes; decode them into a small signed range so bounds
-    // and rendering behave reasonably.
-    let mut vxs: Vec<i32> = Vec::with_capacity(vertex_cnt);
-    let mut vys: Vec<i32> = Vec::with_capacity(vertex_cnt);
-    for i in 0..vertex_cnt {
-        let base = i * 12;
-        let Some(x_raw) = read_i32_le(verts, base) else {
+fn sprite_render_from(
+    target: &mut impl SpriteTarget,
+    base_x: i32,
+    base_y: i32,
+    bytes: &[u8],
+    start: usize,
+    initial_color: u8,
+    initial_thick: i32,
+) {
+    let mut off = start;
+    let mut pen = Pen::new(initial_color, initial_thick);
+    let rop = target.rop();
+    while off < bytes.len() {
+        if !render_elem(target, base_x, base_y, bytes, off, &mut pen) {
             break;
-        };
-        let Some(y_raw) = read_i32_le(verts, base + 4) else {
+        }
+        let Some(sz) = sprite_elem_size(bytes, off) else {
             break;
         };
-        vxs.push(decode_mesh_coord(x_raw));
-        vys.push(decode_mesh_coord(y_raw));
-    }
-    if vxs.len() != vertex_cnt || vys.len() != vertex_cnt {
-        return;
+        off += sz;
     }
+    target.set_rop(rop);
+}
 
-    for t in 0..tri_cnt {
-        let base = t * 16;
-        let Some(tri_color_raw) = read_i32_le(tris, base) else {
-            continue;
-        };
-        let Some(i0_raw) = read_i32_le(tris, base + 4) else {
-            continue;
-        };
-        let Some(i1_raw) = read_i32_le(tris, base + 8) else {
-            continue;
-        };
-        let Some(i2_raw) = read_i32_le(tris, base + 12) else {
-            continue;
-        };
+/// The color, pen width and `SPT_SHIFT` offset carried from one element to the next.
+#[derive(Clone, Copy, Debug)]
+struct Pen {
+    color: u8,
+    thick: i32,
+    dx: i32,
+    dy: i32,
+}
 
-        let Some(i0) = decode_mesh_index(i0_raw, vertex_cnt) else {
-            continue;
-        };
-        let Some(i1) = decode_mesh_index(i1_raw, vertex_cnt) else {
-            continue;
-        };
-        let Some(i2) = decode_mesh_index(i2_raw, vertex_cnt) else {
-            continue;
-        };
+impl Pen {
+    fn new(color: u8, thick: i32) -> Self {
+        Self {
+            color: color & 0x0f,
+            thick: thick.max(1),
+            dx: 0,
+            dy: 0,
+        }
+    }
+}
 
-        let color = decode_mesh_color(tri_color_raw);
-        fill_tri(
-            target,
-            base_x.saturating_add(vxs[i0]),
-            base_y.saturating_add(vys[i0]),
-            base_x.saturating_add(vxs[i1]),
-            base_y.saturating_add(vys[i1]),
-            base_x.saturating_add(vxs[i2]),
-            base_y.saturating_add(vys[i2]),
-            color,
-        );
+/// Draws the element at `off` flat at (`base_x`, `base_y`), or applies it to `pen`. `false` at
+/// `SPT_END` or a truncated element.
+fn render_elem(
+    target: &mut impl SpriteTarget,
+    base_x: i32,
+    base_y: i32,
+    bytes: &[u8],
+    off: usize,
+    pen: &mut Pen,
+) -> bool {
+    let Pen {
+        color,
+        thick,
+        dx,
+        dy,
+    } = *pen;
+    let t = bytes[off] & SPG_TYPE_MASK;
+    match t {
+        SPT_END => return false,
+        SPT_COLOR => {
+            if let Some(&c) = bytes.get(off + 1) {
+                pen.color = c & 0x0f;
+                target.set_rop(target.rop().with_dither(None));
+            }
+        }
+        SPT_DITHER_COLOR => {
+            if let Some(v) = read_u16_le(bytes, off + 1) {
+                pen.color = (v as u8) & 0x0f;
+                target.set_rop(target.rop().with_dither(Some((v >> 8) as u8 & 0x0f)));
+            }
+        }
+        SPT_THICK => {
+            if let Some(v) = read_i32_le(bytes, off + 1) {
+                pen.thick = v.max(1);
+            }
+        }
+        SPT_SHIFT => {
+            let Some(x) = read_i32_le(bytes, off + 1) else {
+                return false;
+            };
+            let Some(y) = read_i32_le(bytes, off + 5) else {
+                return false;
+            };
+            pen.dx = dx.saturating_add(x);
+            pen.dy = dy.saturating_add(y);
+        }
+        SPT_PT => {
+            let Some(x) = read_i32_le(bytes, off + 1) else {
+                return false;
+            };
+            let Some(y) = read_i32_le(bytes, off + 5) else {
+                return false;
+            };
+            if thick == 1 {
+                target.set_pixel(base_x + dx + x, base_y + dy + y, color);
+            } else {
+                let half = thick / 2;
+                target.fill_rect(
+                    base_x + dx + x - half,
+                    base_y + dy + y - half,
+                    thick,
+                    thick,
+                    color,
+                );
+            }
+        }
+        SPT_LINE => {
+            let Some(x1) = read_i32_le(bytes, off + 1) else {
+                return false;
+            };
+            let Some(y1) = read_i32_le(bytes, off + 5) else {
+                return false;
+            };
+            let Some(x2) = read_i32_le(bytes, off + 9) else {
+                return false;
+            };
+            let Some(y2) = read_i32_le(bytes, off + 13) else {
+                return false;
+            };
+            target.draw_line_thick(
+                base_x + dx + x1,
+                base_y + dy + y1,
+                base_x + dx + x2,
+                base_y + dy + y2,
+                color,
+                thick,
+            );
+        }
+        SPT_RECT => {
+            let Some(x1) = read_i32_le(bytes, off + 1) else {
+                return false;
+            };
+            let Some(y1) = read_i32_le(bytes, off + 5) else {
+                return false;
+            };
+            let Some(x2) = read_i32_le(bytes, off + 9) else {
+                return false;
+            };
+            let Some(y2) = read_i32_le(bytes, off + 13) else {
+                return false;
+            };
+            target.draw_rect_outline_thick(
+                base_x + dx + x1,
+                base_y + dy + y1,
+                x2 - x1,
+                y2 - y1,
+                color,
+                thick,
+            );
+        }
+        SPT_CIRCLE => {
+            let Some(x) = read_i32_le(bytes, off + 1) else {
+                return false;
+            };
+            let Some(y) = read_i32_le(bytes, off + 5) else {
+                return false;
+            };
+            let Some(r) = read_i32_le(bytes, off + 9) else {
+                return false;
+            };
+            target.draw_circle_thick(base_x + dx + x, base_y + dy + y, r, color, thick);
+        }
+        SPT_ARROW => {
+            let Some(x1) = read_i32_le(bytes, off + 1) else {
+                return false;
+            };
+            let Some(y1) = read_i32_le(bytes, off + 5) else {
+                return false;
+            };
+            let Some(x2) = read_i32_le(bytes, off + 9) else {
+                return false;
+            };
+            let Some(y2) = read_i32_le(bytes, off + 13) else {
+                return false;
+            };
+            draw_arrow(
+                target,
+                base_x + dx + x1,
+                base_y + dy + y1,
+                base_x + dx + x2,
+                base_y + dy + y2,
+                color,
+                thick,
+            );
+        }
+        SPT_TEXT | SPT_TEXT_DIAMOND => {
+            let Some(x) = read_i32_le(bytes, off + 1) else {
+                return false;
+            };
+            let Some(y) = read_i32_le(bytes, off + 5) else {
+                return false;
+            };
+            let base = off + 1 + 4 + 4;
+            let Some(s) = sprite_text_nul_range(bytes, base) else {
+                return false;
+            };
+            draw_text_transparent_8x8(target, base_x + dx + x, base_y + dy + y, color, &bytes[s]);
+        }
+        SPT_TEXT_BOX => {
+            let Some(x) = read_i32_le(bytes, off + 1) else {
+                return false;
+            };
+            let Some(y) = read_i32_le(bytes, off + 5) else {
+                return false;
+            };
+            let base = off + 1 + 4 + 4;
+            let Some(s) = sprite_text_nul_range(bytes, base) else {
+                return false;
+            };
+            let text = &bytes[s];
+            draw_text_transparent_8x8(target, base_x + dx + x, base_y + dy + y, color, text);
+            let (w, h) = measure_text_box(text);
+            let border = 2;
+            target.draw_rect_outline_thick(
+                base_x + dx + x - border,
+                base_y + dy + y - border,
+                w + border * 2,
+                h + border * 2,
+                color,
+                thick,
+            );
+        }
+        SPT_BITMAP => {
+            let Some(x) = read_i32_le(bytes, off + 1) else {
+                return false;
+            };
+            let Some(y) = read_i32_le(bytes, off + 5) else {
+                return false;
+            };
+            let Some(w) = read_i32_le(bytes, off + 9) else {
+                return false;
+            };
+            let Some(h) = read_i32_le(bytes, off + 13) else {
+                return false;
+            };
+            if w <= 0 || h <= 0 {
+                // nothing
+            } else {
+                let stride = ceil_to_multiple(w, 8);
+                let data_off = off + 1 + 4 * 4;
+                let data_len = match (stride as usize).checked_mul(h as usize) {
+                    Some(v) => v,
+                    None => return false,
+                };
+                let Some(src) = bytes.get(data_off..data_off + data_len) else {
+                    return false;
+                };
+                target.blit_8bpp(base_x + dx + x, base_y + dy + y, w, h, stride, src);
+            }
+        }
+        SPT_POLYLINE | SPT_POLYPT | SPT_BSPLINE2 | SPT_BSPLINE2_CLOSED | SPT_BSPLINE3
+        | SPT_BSPLINE3_CLOSED | SPT_ELLIPSE | SPT_ROTATED_RECT | SPT_POLYGON => {
+            let Some(stroke) = curve_stroke(bytes, off) else {
+                return false;
+            };
+            draw_stroke(target, &stroke, base_x + dx, base_y + dy, color, thick);
+        }
+        SPT_MESH | SPT_SHIFTABLE_MESH => {
+            let mut gr = Gr3::new(target, Transform3::default(), false, None);
+            return render_mesh3(&mut gr, bytes, off, (base_x as i64, base_y as i64, 0), pen);
+        }
+        _ => {}
     }
+    true
 }
 
 fn decode_mesh_coord(raw: i32) -> i32 {
@@ -1105,12 +1162,19 @@ fn draw_arrow(
     thick: i32,
 ) {
     target.draw_line_thick(x1, y1, x2, y2, color, thick);
+    for (ax, ay) in arrow_head(x1, y1, x2, y2, thick).into_iter().flatten() {
+        target.draw_line_thick(ax, ay, x2, y2, color, thick);
+    }
+}
 
+/// The far ends of the two strokes of an arrow head at (`x2`, `y2`); `None` for a zero-length
+/// arrow. Our own proportions, not `GrArrow3`'s.
+fn arrow_head(x1: i32, y1: i32, x2: i32, y2: i32, thick: i32) -> Option<[(i32, i32); 2]> {
     let dx = (x2 - x1) as f64;
     let dy = (y2 - y1) as f64;
     let len = (dx * dx + dy * dy).sqrt();
     if len <= 0.0001 {
-        return;
+        return None;
     }
 
     let ux = dx / len;
@@ -1125,12 +1189,314 @@ fn draw_arrow(
     let ay1 = (y2 as f64 - uy * arrow_len + py * arrow_w).round() as i32;
     let ax2 = (x2 as f64 - ux * arrow_len - px * arrow_w).round() as i32;
     let ay2 = (y2 as f64 - uy * arrow_len - py * arrow_w).round() as i32;
+    Some([(ax1, ay1), (ax2, ay2)])
+}
+
+/// One step of an `SPT_POLYPT` run per 3-bit code (TempleOS' `gr_x_offsets`/`gr_y_offsets`).
+const POLYPT_STEPS: [(i32, i32); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// What a curve element draws, in element coordinates.
+enum Stroke {
+    /// Vertices joined by lines, one `GrLine` per edge.
+    Lines(Vec<(i32, i32)>),
+    /// Single pixels; along curves each is a neighbor of the one before.
+    Pixels(Vec<(i32, i32)>),
+}
+
+/// The stroke of an `SPT_POLYLINE`, `SPT_POLYPT`, B-spline, `SPT_ELLIPSE`, `SPT_ROTATED_RECT` or
+/// `SPT_POLYGON` element; `None` when truncated.
+fn curve_stroke(bytes: &[u8], off: usize) -> Option<Stroke> {
+    let t = bytes[off] & SPG_TYPE_MASK;
+    let i32_at = |k: usize| read_i32_le(bytes, off + 1 + k * 4);
+    Some(match t {
+        SPT_POLYLINE => {
+            let num = i32_at(0)?.clamp(0, 65_536) as usize;
+            let pts = (0..num)
+                .map(|i| Some((i32_at(1 + i * 2)?, i32_at(2 + i * 2)?)))
+                .collect::<Option<_>>()?;
+            Stroke::Lines(pts)
+        }
+        SPT_POLYPT => {
+            let num = i32_at(0)?.clamp(0, 1 << 20) as usize;
+            let (mut x, mut y) = (i32_at(1)?, i32_at(2)?);
+            let bits = bytes.get(off + 13..off + 13 + (num * 3).div_ceil(8))?;
+            let mut pts = vec![(x, y)];
+            for i in 0..num {
+                let (byte, shift) = (i * 3 / 8, i * 3 % 8);
+                let pair = bits[byte] as u16 | (*bits.get(byte + 1).unwrap_or(&0) as u16) << 8;
+                let (sx, sy) = POLYPT_STEPS[(pair >> shift) as usize & 7];
+                (x, y) = (x + sx, y + sy);
+                pts.push((x, y));
+            }
+            Stroke::Pixels(pts)
+        }
+        SPT_BSPLINE2 | SPT_BSPLINE2_CLOSED | SPT_BSPLINE3 | SPT_BSPLINE3_CLOSED => {
+            let num = i32_at(0)?.clamp(0, 65_536) as usize;
+            let pts: Vec<(f64, f64)> = (0..num)
+                .map(|i| Some((i32_at(1 + i * 3)? as f64, i32_at(2 + i * 3)? as f64)))
+                .collect::<Option<_>>()?;
+            let closed = matches!(t, SPT_BSPLINE2_CLOSED | SPT_BSPLINE3_CLOSED);
+            Stroke::Pixels(bspline(
+                &pts,
+                matches!(t, SPT_BSPLINE3 | SPT_BSPLINE3_CLOSED),
+                closed,
+            ))
+        }
+        SPT_ELLIPSE => {
+            let (cx, cy) = (i32_at(0)?, i32_at(1)?);
+            let (rx, ry) = (i32_at(2)?, i32_at(3)?);
+            let angle = read_f64_le(bytes, off + 17)?;
+            let pts = ellipse_pixels(rx, ry, angle);
+            Stroke::Pixels(pts.into_iter().map(|(x, y)| (cx + x, cy + y)).collect())
+        }
+        SPT_ROTATED_RECT => {
+            let (x1, y1) = (i32_at(0)?, i32_at(1)?);
+            let (w, h) = ((i32_at(2)? - x1) as f64, (i32_at(3)? - y1) as f64);
+            let (sin, cos) = read_f64_le(bytes, off + 17)?.sin_cos();
+            // Turned about its first corner.
+            let corner = |x: f64, y: f64| {
+                (
+                    x1 + (x * cos - y * sin).round() as i32,
+                    y1 + (x * sin + y * cos).round() as i32,
+                )
+            };
+            let first = corner(0.0, 0.0);
+            Stroke::Lines(vec![
+                first,
+                corner(w, 0.0),
+                corner(w, h),
+                corner(0.0, h),
+                first,
+            ])
+        }
+        SPT_POLYGON => {
+            let (cx, cy) = (i32_at(0)?, i32_at(1)?);
+            let (rx, ry) = (i32_at(2)? as f64, i32_at(3)? as f64);
+            let (sin, cos) = read_f64_le(bytes, off + 17)?.sin_cos();
+            let sides = read_i32_le(bytes, off + 25)?.clamp(0, 1024) as usize;
+            if sides < 2 {
+                return Some(Stroke::Lines(Vec::new()));
+            }
+            // A regular polygon inscribed in the ellipse, like `GrRegPoly`.
+            let pts = (0..=sides)
+                .map(|i| {
+                    let (s, c) =
+                        (std::f64::consts::TAU * (i % sides) as f64 / sides as f64).sin_cos();
+                    let (x, y) = (rx * c, ry * s);
+                    (
+                        cx + (x * cos - y * sin).round() as i32,
+                        cy + (x * sin + y * cos).round() as i32,
+                    )
+                })
+                .collect();
+            Stroke::Lines(pts)
+        }
+        _ => return None,
+    })
+}
+
+/// `GrEllipse`: an ellipse with radii `rx` x `ry` about (`cx`, `cy`), turned by `angle` radians.
+pub fn draw_ellipse(
+    target: &mut impl SpriteTarget,
+    (cx, cy): (i32, i32),
+    (rx, ry): (i32, i32),
+    angle: f64,
+    color: u8,
+    thick: i32,
+) {
+    let stroke = Stroke::Pixels(ellipse_pixels(rx, ry, angle));
+    draw_stroke(target, &stroke, cx, cy, color, thick);
+}
+
+/// The pixels of an ellipse about the origin, each plotted once. An approximation sampled at
+/// about a pixel of arc per step, not a port of `GrEllipse3`, so it can be a pixel off upstream.
+fn ellipse_pixels(rx: i32, ry: i32, angle: f64) -> Vec<(i32, i32)> {
+    let (rx, ry) = (rx.abs() as f64, ry.abs() as f64);
+    let (sin, cos) = angle.sin_cos();
+    let n = (std::f64::consts::TAU * rx.max(ry)).ceil().max(4.0) as usize;
+    let mut pts = trace((0..=n).map(|i| {
+        let (s, c) = (std::f64::consts::TAU * i as f64 / n as f64).sin_cos();
+        let (x, y) = (rx * c, ry * s);
+        (x * cos - y * sin, x * sin + y * cos)
+    }));
+    if pts.len() > 1 && pts.first() == pts.last() {
+        pts.pop();
+    }
+    pts
+}
+
+fn draw_stroke(
+    target: &mut impl SpriteTarget,
+    stroke: &Stroke,
+    ox: i32,
+    oy: i32,
+    color: u8,
+    thick: i32,
+) {
+    match stroke {
+        Stroke::Lines(pts) => {
+            if let [p] = pts[..] {
+                return draw_stroke(target, &Stroke::Pixels(vec![p]), ox, oy, color, thick);
+            }
+            for w in pts.windows(2) {
+                let (a, b) = (w[0], w[1]);
+                target.draw_line_thick(ox + a.0, oy + a.1, ox + b.0, oy + b.1, color, thick);
+            }
+        }
+        Stroke::Pixels(pts) => {
+            let half = thick / 2;
+            for &(x, y) in pts {
+                if thick <= 1 {
+                    target.set_pixel(ox + x, oy + y, color);
+                } else {
+                    target.fill_rect(ox + x - half, oy + y - half, thick, thick, color);
+                }
+            }
+        }
+    }
+}
 
-    target.draw_line_thick(ax1, ay1, x2, y2, color, thick);
-    target.draw_line_thick(ax2, ay2, x2, y2, color, thick);
+/// Rounds points along a curve to pixels, dropping repeats and filling any gap with a line, so
+/// each pixel is plotted once.
+fn trace(pts: impl IntoIterator<Item = (f64, f64)>) -> Vec<(i32, i32)> {
+    let mut out: Vec<(i32, i32)> = Vec::new();
+    for (x, y) in pts {
+        let p = (x.round() as i32, y.round() as i32);
+        let Some(&(mut lx, mut ly)) = out.last() else {
+            out.push(p);
+            continue;
+        };
+        // Bresenham from the previous pixel, which is already out.
+        let (dx, dy) = ((p.0 - lx).abs(), -(p.1 - ly).abs());
+        let (sx, sy) = ((p.0 - lx).signum(), (p.1 - ly).signum());
+        let mut err = dx + dy;
+        while (lx, ly) != p {
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                lx += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                ly += sy;
+            }
+            out.push((lx, ly));
+        }
+    }
+    out
+}
+
+/// Pixels of a 2nd (`cubic == false`) or 3rd order uniform B-spline through `ctrl`. Open curves
+/// start and end at their end points; closed ones wrap around. Drawn through our own Bezier
+/// decomposition rather than `Gr2BSpline3`/`Gr3BSpline3`'s stepping, so it can be a pixel off.
+fn bspline(ctrl: &[(f64, f64)], cubic: bool, closed: bool) -> Vec<(i32, i32)> {
+    type P = (f64, f64);
+    fn mid(a: P, b: P) -> P {
+        ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0)
+    }
+    fn dist(a: P, b: P) -> f64 {
+        (a.0 - b.0).hypot(a.1 - b.1)
+    }
+    /// A Bezier curve over `c`, stepped so points land at most a pixel apart.
+    fn bezier(c: &[P], out: &mut Vec<P>) {
+        let steps = c
+            .windows(2)
+            .map(|w| dist(w[0], w[1]))
+            .sum::<f64>()
+            .ceil()
+            .max(1.0) as usize;
+        for i in 0..=steps {
+            let t = i as f64 / steps as f64;
+            let u = 1.0 - t;
+            let w: &[f64] = match c.len() {
+                3 => &[u * u, 2.0 * u * t, t * t],
+                _ => &[u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t],
+            };
+            out.push(
+                c.iter()
+                    .zip(w)
+                    .fold((0.0, 0.0), |acc, (p, w)| (acc.0 + p.0 * w, acc.1 + p.1 * w)),
+            );
+        }
+    }
+
+    let n = ctrl.len();
+    if n < 3 {
+        return trace(ctrl.iter().copied());
+    }
+    let at = |i: usize| ctrl[i % n];
+    let mut pts = Vec::new();
+    if !cubic {
+        // Quadratic pieces from edge midpoint to edge midpoint, bent toward each control point.
+        if closed {
+            for i in 0..n {
+                bezier(
+                    &[mid(at(i + n - 1), at(i)), at(i), mid(at(i), at(i + 1))],
+                    &mut pts,
+                );
+            }
+        } else {
+            for i in 1..n - 1 {
+                let a = if i == 1 {
+                    ctrl[0]
+                } else {
+                    mid(ctrl[i - 1], ctrl[i])
+                };
+                let c = if i == n - 2 {
+                    ctrl[n - 1]
+                } else {
+                    mid(ctrl[i], ctrl[i + 1])
+                };
+                bezier(&[a, ctrl[i], c], &mut pts);
+            }
+        }
+    } else {
+        // Open curves repeat their end points three times so they are reached.
+        let q: Vec<P> = if closed {
+            (0..n + 3).map(at).collect()
+        } else {
+            [ctrl[0]; 2]
+                .into_iter()
+                .chain(ctrl.iter().copied())
+                .chain([ctrl[n - 1]; 2])
+                .collect()
+        };
+        let lerp = |a: P, b: P, wa: f64, wb: f64, d: f64| {
+            ((a.0 * wa + b.0 * wb) / d, (a.1 * wa + b.1 * wb) / d)
+        };
+        for w in q.windows(4) {
+            let (a, b) = (
+                lerp(w[0], w[2], 1.0, 1.0, 2.0),
+                lerp(w[1], w[3], 1.0, 1.0, 2.0),
+            );
+            bezier(
+                &[
+                    lerp(a, w[1], 1.0, 2.0, 3.0),
+                    lerp(w[1], w[2], 2.0, 1.0, 3.0),
+                    lerp(w[1], w[2], 1.0, 2.0, 3.0),
+                    lerp(w[2], b, 2.0, 1.0, 3.0),
+                ],
+                &mut pts,
+            );
+        }
+    }
+    let mut out = trace(pts);
+    if closed && out.len() > 1 && out.first() == out.last() {
+        out.pop();
+    }
+    out
 }
 
-fn draw_line_thick_default<T: SpriteTarget + ?Sized>(
+pub(crate) fn draw_line_thick_default<T: SpriteTarget + ?Sized>(
     target: &mut T,
     x1: i32,
     y1: i32,
@@ -1173,7 +1539,7 @@ fn draw_line_thick_default<T: SpriteTarget + ?Sized>(
     }
 }
 
-fn draw_rect_outline_thick_default<T: SpriteTarget + ?Sized>(
+pub(crate) fn draw_rect_outline_thick_default<T: SpriteTarget + ?Sized>(
     target: &mut T,
     x: i32,
     y: i32,
@@ -1197,7 +1563,7 @@ fn draw_rect_outline_thick_default<T: SpriteTarget + ?Sized>(
     target.fill_rect(x + w - thick, y + thick, thick, h - 2 * thick, color);
 }
 
-fn draw_circle_thick_default<T: SpriteTarget + ?Sized>(
+pub(crate) fn draw_circle_thick_default<T: SpriteTarget + ?Sized>(
     target: &mut T,
     cx: i32,
     cy: i32,
@@ -1246,6 +1612,14 @@ fn draw_circle_thick_default<T: SpriteTarget + ?Sized>(
 }
 
 impl SpriteTarget for crate::rt::TempleRt {
+    fn rop(&self) -> Rop {
+        crate::rt::TempleRt::rop(self)
+    }
+
+    fn set_rop(&mut self, rop: Rop) {
+        crate::rt::TempleRt::set_rop(self, rop);
+    }
+
     fn set_pixel(&mut self, x: i32, y: i32, color: u8) {
         crate::rt::TempleRt::set_pixel(self, x, y, color);
     }
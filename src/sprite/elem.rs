@@ -0,0 +1,778 @@
+//! Typed sprite elements: [`sprite_elems`] parses an `SPT_*` stream into [`SpriteElem`]s and
+//! [`sprite_elem_bytes`] writes them back byte for byte, so tools can build and edit sprites.
+
+use super::*;
+use crate::rt::TRANSPARENT;
+
+/// One `CMeshTri`: a color (`CColorROPU32`) and three vertex numbers, kept as stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeshTri {
+    pub color: i32,
+    pub nums: [i32; 3],
+}
+
+/// One element of a TempleOS sprite, with its fields as stored. `SPT_END` is implied by the end
+/// of a list.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpriteElem {
+    Color(u8),
+    /// `c0`, drawn alternately with `c1`.
+    DitherColor(u8, u8),
+    Thick(i32),
+    PlanarSymmetry {
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+    },
+    TransformOn,
+    TransformOff,
+    Shift {
+        x: i32,
+        y: i32,
+    },
+    Pt {
+        x: i32,
+        y: i32,
+    },
+    /// A start point, then one 3-bit step code (`0..8`) per further point.
+    PolyPt {
+        x: i32,
+        y: i32,
+        steps: Vec<u8>,
+    },
+    Line {
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+    },
+    PolyLine(Vec<(i32, i32)>),
+    Rect {
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+    },
+    RotatedRect {
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+        angle: f64,
+    },
+    Circle {
+        x: i32,
+        y: i32,
+        r: i32,
+    },
+    Ellipse {
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        angle: f64,
+    },
+    Polygon {
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        angle: f64,
+        sides: i32,
+    },
+    BSpline2(Vec<[i32; 3]>),
+    BSpline2Closed(Vec<[i32; 3]>),
+    BSpline3(Vec<[i32; 3]>),
+    BSpline3Closed(Vec<[i32; 3]>),
+    FloodFill {
+        x: i32,
+        y: i32,
+    },
+    FloodFillNot {
+        x: i32,
+        y: i32,
+    },
+    /// `height` rows of `width` rounded up to 8 bytes.
+    Bitmap {
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        body: Vec<u8>,
+    },
+    Mesh {
+        vertices: Vec<[i32; 3]>,
+        tris: Vec<MeshTri>,
+    },
+    ShiftableMesh {
+        x: i32,
+        y: i32,
+        z: i32,
+        vertices: Vec<[i32; 3]>,
+        tris: Vec<MeshTri>,
+    },
+    Arrow {
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+    },
+    /// CP437 text without its NUL.
+    Text {
+        x: i32,
+        y: i32,
+        text: Vec<u8>,
+    },
+    TextBox {
+        x: i32,
+        y: i32,
+        text: Vec<u8>,
+    },
+    TextDiamond {
+        x: i32,
+        y: i32,
+        text: Vec<u8>,
+    },
+}
+
+/// The elements of the sprite at the start of `bytes`, up to `SPT_END`; `None` if it is cut off
+/// or has an unknown element. The `SPF_SEL` editor flag (bit 7 of the type) is dropped.
+pub fn sprite_elems(bytes: &[u8]) -> Option<Vec<SpriteElem>> {
+    let mut elems = Vec::new();
+    let mut off = 0;
+    loop {
+        if *bytes.get(off)? & SPG_TYPE_MASK == SPT_END {
+            return Some(elems);
+        }
+        let size = sprite_elem_size(bytes, off)?;
+        elems.push(parse_elem(bytes.get(off..off + size)?)?);
+        off += size;
+    }
+}
+
+/// `elems` as a sprite byte stream, ending in `SPT_END`.
+pub fn sprite_elem_bytes(elems: &[SpriteElem]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for elem in elems {
+        elem.write(&mut out);
+    }
+    out.push(SPT_END);
+    out
+}
+
+fn parse_elem(b: &[u8]) -> Option<SpriteElem> {
+    let i = |k: usize| read_i32_le(b, 1 + k * 4);
+    let f = |k: usize| read_f64_le(b, 1 + k * 4);
+    let triples = |from: usize, cnt: usize| -> Option<Vec<[i32; 3]>> {
+        (0..cnt)
+            .map(|n| Some([i(from + n * 3)?, i(from + n * 3 + 1)?, i(from + n * 3 + 2)?]))
+            .collect()
+    };
+    let text = |from: usize| Some(b.get(from..b.len() - 1)?.to_vec());
+    let mesh = |head: usize| -> Option<(Vec<[i32; 3]>, Vec<MeshTri>)> {
+        let (vertex_cnt, tri_cnt) = (i(head)?.max(0) as usize, i(head + 1)?.max(0) as usize);
+        let vertices = triples(head + 2, vertex_cnt)?;
+        let first_tri = head + 2 + vertex_cnt * 3;
+        let tris = (0..tri_cnt)
+            .map(|n| {
+                let k = first_tri + n * 4;
+                Some(MeshTri {
+                    color: i(k)?,
+                    nums: [i(k + 1)?, i(k + 2)?, i(k + 3)?],
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some((vertices, tris))
+    };
+    Some(match b[0] & SPG_TYPE_MASK {
+        SPT_COLOR => SpriteElem::Color(*b.get(1)?),
+        SPT_DITHER_COLOR => SpriteElem::DitherColor(*b.get(1)?, *b.get(2)?),
+        SPT_THICK => SpriteElem::Thick(i(0)?),
+        SPT_PLANAR_SYMMETRY => SpriteElem::PlanarSymmetry {
+            x1: i(0)?,
+            y1: i(1)?,
+            x2: i(2)?,
+            y2: i(3)?,
+        },
+        SPT_TRANSFORM_ON => SpriteElem::TransformOn,
+        SPT_TRANSFORM_OFF => SpriteElem::TransformOff,
+        SPT_SHIFT => SpriteElem::Shift { x: i(0)?, y: i(1)? },
+        SPT_PT => SpriteElem::Pt { x: i(0)?, y: i(1)? },
+        SPT_POLYPT => {
+            let num = i(0)?.max(0) as usize;
+            let bits = b.get(13..)?;
+            let steps = (0..num)
+                .map(|n| {
+                    let (byte, shift) = (n * 3 / 8, n * 3 % 8);
+                    let pair = bits[byte] as u16 | (*bits.get(byte + 1).unwrap_or(&0) as u16) << 8;
+                    (pair >> shift) as u8 & 7
+                })
+                .collect();
+            SpriteElem::PolyPt {
+                x: i(1)?,
+                y: i(2)?,
+                steps,
+            }
+        }
+        SPT_LINE => SpriteElem::Line {
+            x1: i(0)?,
+            y1: i(1)?,
+            x2: i(2)?,
+            y2: i(3)?,
+        },
+        SPT_POLYLINE => {
+            let num = i(0)?.max(0) as usize;
+            let pts = (0..num)
+                .map(|n| Some((i(1 + n * 2)?, i(2 + n * 2)?)))
+                .collect::<Option<_>>()?;
+            SpriteElem::PolyLine(pts)
+        }
+        SPT_RECT => SpriteElem::Rect {
+            x1: i(0)?,
+            y1: i(1)?,
+            x2: i(2)?,
+            y2: i(3)?,
+        },
+        SPT_ROTATED_RECT => SpriteElem::RotatedRect {
+            x1: i(0)?,
+            y1: i(1)?,
+            x2: i(2)?,
+            y2: i(3)?,
+            angle: f(4)?,
+        },
+        SPT_CIRCLE => SpriteElem::Circle {
+            x: i(0)?,
+            y: i(1)?,
+            r: i(2)?,
+        },
+        SPT_ELLIPSE => SpriteElem::Ellipse {
+            x: i(0)?,
+            y: i(1)?,
+            width: i(2)?,
+            height: i(3)?,
+            angle: f(4)?,
+        },
+        SPT_POLYGON => SpriteElem::Polygon {
+            x: i(0)?,
+            y: i(1)?,
+            width: i(2)?,
+            height: i(3)?,
+            angle: f(4)?,
+            sides: i(6)?,
+        },
+        t @ (SPT_BSPLINE2 | SPT_BSPLINE2_CLOSED | SPT_BSPLINE3 | SPT_BSPLINE3_CLOSED) => {
+            let pts = triples(1, i(0)?.max(0) as usize)?;
+            match t {
+                SPT_BSPLINE2 => SpriteElem::BSpline2(pts),
+                SPT_BSPLINE2_CLOSED => SpriteElem::BSpline2Closed(pts),
+                SPT_BSPLINE3 => SpriteElem::BSpline3(pts),
+                _ => SpriteElem::BSpline3Closed(pts),
+            }
+        }
+        SPT_FLOOD_FILL => SpriteElem::FloodFill { x: i(0)?, y: i(1)? },
+        SPT_FLOOD_FILL_NOT => SpriteElem::FloodFillNot { x: i(0)?, y: i(1)? },
+        SPT_BITMAP => SpriteElem::Bitmap {
+            x: i(0)?,
+            y: i(1)?,
+            width: i(2)?,
+            height: i(3)?,
+            body: b.get(17..)?.to_vec(),
+        },
+        SPT_MESH => {
+            let (vertices, tris) = mesh(0)?;
+            SpriteElem::Mesh { vertices, tris }
+        }
+        SPT_SHIFTABLE_MESH => {
+            let (vertices, tris) = mesh(3)?;
+            SpriteElem::ShiftableMesh {
+                x: i(0)?,
+                y: i(1)?,
+                z: i(2)?,
+                vertices,
+                tris,
+            }
+        }
+        SPT_ARROW => SpriteElem::Arrow {
+            x1: i(0)?,
+            y1: i(1)?,
+            x2: i(2)?,
+            y2: i(3)?,
+        },
+        SPT_TEXT => SpriteElem::Text {
+            x: i(0)?,
+            y: i(1)?,
+            text: text(9)?,
+        },
+        SPT_TEXT_BOX => SpriteElem::TextBox {
+            x: i(0)?,
+            y: i(1)?,
+            text: text(9)?,
+        },
+        SPT_TEXT_DIAMOND => SpriteElem::TextDiamond {
+            x: i(0)?,
+            y: i(1)?,
+            text: text(9)?,
+        },
+        _ => return None,
+    })
+}
+
+fn put_i32s(out: &mut Vec<u8>, vals: &[i32]) {
+    for v in vals {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+}
+
+fn put_mesh(out: &mut Vec<u8>, vertices: &[[i32; 3]], tris: &[MeshTri]) {
+    put_i32s(out, &[vertices.len() as i32, tris.len() as i32]);
+    for v in vertices {
+        put_i32s(out, v);
+    }
+    for t in tris {
+        put_i32s(out, &[t.color, t.nums[0], t.nums[1], t.nums[2]]);
+    }
+}
+
+impl SpriteElem {
+    /// Its `SPT_*` type byte.
+    pub fn kind(&self) -> u8 {
+        match self {
+            SpriteElem::Color(_) => SPT_COLOR,
+            SpriteElem::DitherColor(..) => SPT_DITHER_COLOR,
+            SpriteElem::Thick(_) => SPT_THICK,
+            SpriteElem::PlanarSymmetry { .. } => SPT_PLANAR_SYMMETRY,
+            SpriteElem::TransformOn => SPT_TRANSFORM_ON,
+            SpriteElem::TransformOff => SPT_TRANSFORM_OFF,
+            SpriteElem::Shift { .. } => SPT_SHIFT,
+            SpriteElem::Pt { .. } => SPT_PT,
+            SpriteElem::PolyPt { .. } => SPT_POLYPT,
+            SpriteElem::Line { .. } => SPT_LINE,
+            SpriteElem::PolyLine(_) => SPT_POLYLINE,
+            SpriteElem::Rect { .. } => SPT_RECT,
+            SpriteElem::RotatedRect { .. } => SPT_ROTATED_RECT,
+            SpriteElem::Circle { .. } => SPT_CIRCLE,
+            SpriteElem::Ellipse { .. } => SPT_ELLIPSE,
+            SpriteElem::Polygon { .. } => SPT_POLYGON,
+            SpriteElem::BSpline2(_) => SPT_BSPLINE2,
+            SpriteElem::BSpline2Closed(_) => SPT_BSPLINE2_CLOSED,
+            SpriteElem::BSpline3(_) => SPT_BSPLINE3,
+            SpriteElem::BSpline3Closed(_) => SPT_BSPLINE3_CLOSED,
+            SpriteElem::FloodFill { .. } => SPT_FLOOD_FILL,
+            SpriteElem::FloodFillNot { .. } => SPT_FLOOD_FILL_NOT,
+            SpriteElem::Bitmap { .. } => SPT_BITMAP,
+            SpriteElem::Mesh { .. } => SPT_MESH,
+            SpriteElem::ShiftableMesh { .. } => SPT_SHIFTABLE_MESH,
+            SpriteElem::Arrow { .. } => SPT_ARROW,
+            SpriteElem::Text { .. } => SPT_TEXT,
+            SpriteElem::TextBox { .. } => SPT_TEXT_BOX,
+            SpriteElem::TextDiamond { .. } => SPT_TEXT_DIAMOND,
+        }
+    }
+
+    /// Appends its on-disk bytes to `out`.
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.push(self.kind());
+        match self {
+            SpriteElem::Color(c) => out.push(*c),
+            SpriteElem::DitherColor(c0, c1) => out.extend_from_slice(&[*c0, *c1]),
+            SpriteElem::Thick(t) => put_i32s(out, &[*t]),
+            SpriteElem::TransformOn | SpriteElem::TransformOff => {}
+            SpriteElem::Shift { x, y }
+            | SpriteElem::Pt { x, y }
+            | SpriteElem::FloodFill { x, y }
+            | SpriteElem::FloodFillNot { x, y } => put_i32s(out, &[*x, *y]),
+            SpriteElem::PlanarSymmetry { x1, y1, x2, y2 }
+            | SpriteElem::Line { x1, y1, x2, y2 }
+            | SpriteElem::Rect { x1, y1, x2, y2 }
+            | SpriteElem::Arrow { x1, y1, x2, y2 } => put_i32s(out, &[*x1, *y1, *x2, *y2]),
+            SpriteElem::PolyPt { x, y, steps } => {
+                put_i32s(out, &[steps.len() as i32, *x, *y]);
+                let mut bits = vec![0u8; (steps.len() * 3).div_ceil(8)];
+                for (n, &step) in steps.iter().enumerate() {
+                    let (byte, shift) = (n * 3 / 8, n * 3 % 8);
+                    let pair = ((step & 7) as u16) << shift;
+                    bits[byte] |= pair as u8;
+                    if let Some(next) = bits.get_mut(byte + 1) {
+                        *next |= (pair >> 8) as u8;
+                    }
+                }
+                out.extend_from_slice(&bits);
+            }
+            SpriteElem::PolyLine(pts) => {
+                put_i32s(out, &[pts.len() as i32]);
+                for &(x, y) in pts {
+                    put_i32s(out, &[x, y]);
+                }
+            }
+            SpriteElem::RotatedRect {
+                x1,
+                y1,
+                x2,
+                y2,
+                angle,
+            } => {
+                put_i32s(out, &[*x1, *y1, *x2, *y2]);
+                out.extend_from_slice(&angle.to_le_bytes());
+            }
+            SpriteElem::Circle { x, y, r } => put_i32s(out, &[*x, *y, *r]),
+            SpriteElem::Ellipse {
+                x,
+                y,
+                width,
+                height,
+                angle,
+            } => {
+                put_i32s(out, &[*x, *y, *width, *height]);
+                out.extend_from_slice(&angle.to_le_bytes());
+            }
+            SpriteElem::Polygon {
+                x,
+                y,
+                width,
+                height,
+                angle,
+                sides,
+            } => {
+                put_i32s(out, &[*x, *y, *width, *height]);
+                out.extend_from_slice(&angle.to_le_bytes());
+                put_i32s(out, &[*sides]);
+            }
+            SpriteElem::BSpline2(pts)
+            | SpriteElem::BSpline2Closed(pts)
+            | SpriteElem::BSpline3(pts)
+            | SpriteElem::BSpline3Closed(pts) => {
+                put_i32s(out, &[pts.len() as i32]);
+                for p in pts {
+                    put_i32s(out, p);
+                }
+            }
+            SpriteElem::Bitmap {
+                x,
+                y,
+                width,
+                height,
+                body,
+            } => {
+                put_i32s(out, &[*x, *y, *width, *height]);
+                out.extend_from_slice(body);
+            }
+            SpriteElem::Mesh { vertices, tris } => put_mesh(out, vertices, tris),
+            SpriteElem::ShiftableMesh {
+                x,
+                y,
+                z,
+                vertices,
+                tris,
+            } => {
+                put_i32s(out, &[*x, *y, *z]);
+                put_mesh(out, vertices, tris);
+            }
+            SpriteElem::Text { x, y, text }
+            | SpriteElem::TextBox { x, y, text }
+            | SpriteElem::TextDiamond { x, y, text } => {
+                put_i32s(out, &[*x, *y]);
+                out.extend_from_slice(text);
+                out.push(0);
+            }
+        }
+    }
+
+    /// Moves it by (`dx`, `dy`). `SPT_SHIFT` offsets are relative and stay as they are.
+    pub fn translate(&mut self, dx: i32, dy: i32) {
+        self.map_points(|x, y| (x + dx, y + dy), |w, h| (w, h));
+    }
+
+    /// Scales its points and sizes by `sx` x `sy` about the origin. Line widths, `SPT_POLYPT`
+    /// steps and text stay as they are; bitmaps are resampled to the nearest pixel.
+    pub fn scale(&mut self, sx: f64, sy: f64) {
+        let (sx, sy) = (sx.abs(), sy.abs());
+        let round = |v: f64| v.round() as i32;
+        if let SpriteElem::Bitmap {
+            width,
+            height,
+            body,
+            ..
+        } = self
+        {
+            let (w, h) = (
+                round(*width as f64 * sx).max(0),
+                round(*height as f64 * sy).max(0),
+            );
+            let (old_stride, stride) = (ceil_to_multiple(*width, 8), ceil_to_multiple(w, 8));
+            let mut scaled = vec![TRANSPARENT; (stride * h) as usize];
+            for y in 0..h {
+                let from_y = ((y as f64 + 0.5) / sy) as i32;
+                for x in 0..w {
+                    let from_x = ((x as f64 + 0.5) / sx) as i32;
+                    let from =
+                        (from_y.min(*height - 1) * old_stride + from_x.min(*width - 1)) as usize;
+                    scaled[(y * stride + x) as usize] =
+                        body.get(from).copied().unwrap_or(TRANSPARENT);
+                }
+            }
+            (*width, *height, *body) = (w, h, scaled);
+        }
+        self.map_points(
+            |x, y| (round(x as f64 * sx), round(y as f64 * sy)),
+            |w, h| (round(w as f64 * sx), round(h as f64 * sy)),
+        );
+    }
+
+    /// Replaces each color it draws in with `map(color)`; `TRANSPARENT` bitmap pixels stay.
+    pub fn recolor(&mut self, map: impl Fn(u8) -> u8) {
+        match self {
+            SpriteElem::Color(c) => *c = map(*c),
+            SpriteElem::DitherColor(c0, c1) => (*c0, *c1) = (map(*c0), map(*c1)),
+            SpriteElem::Bitmap { body, .. } => {
+                for px in body.iter_mut().filter(|px| **px != TRANSPARENT) {
+                    *px = map(*px);
+                }
+            }
+            SpriteElem::Mesh { tris, .. } | SpriteElem::ShiftableMesh { tris, .. } => {
+                for t in tris {
+                    t.color = (t.color & !0xFF) | map(t.color as u8) as i32;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Applies `pt` to every position and `size` to every width and height pair.
+    fn map_points(
+        &mut self,
+        pt: impl Fn(i32, i32) -> (i32, i32),
+        size: impl Fn(i32, i32) -> (i32, i32),
+    ) {
+        let map = |x: &mut i32, y: &mut i32| (*x, *y) = pt(*x, *y);
+        match self {
+            SpriteElem::Color(_)
+            | SpriteElem::DitherColor(..)
+            | SpriteElem::Thick(_)
+            | SpriteElem::TransformOn
+            | SpriteElem::TransformOff
+            | SpriteElem::Shift { .. } => {}
+            SpriteElem::Pt { x, y }
+            | SpriteElem::PolyPt { x, y, .. }
+            | SpriteElem::FloodFill { x, y }
+            | SpriteElem::FloodFillNot { x, y }
+            | SpriteElem::Bitmap { x, y, .. }
+            | SpriteElem::Text { x, y, .. }
+            | SpriteElem::TextBox { x, y, .. }
+            | SpriteElem::TextDiamond { x, y, .. } => map(x, y),
+            SpriteElem::PlanarSymmetry { x1, y1, x2, y2 }
+            | SpriteElem::Line { x1, y1, x2, y2 }
+            | SpriteElem::Rect { x1, y1, x2, y2 }
+            | SpriteElem::RotatedRect { x1, y1, x2, y2, .. }
+            | SpriteElem::Arrow { x1, y1, x2, y2 } => {
+                map(x1, y1);
+                map(x2, y2);
+            }
+            SpriteElem::PolyLine(pts) => {
+                for (x, y) in pts {
+                    map(x, y);
+                }
+            }
+            SpriteElem::Circle { x, y, r } => {
+                map(x, y);
+                let (rx, ry) = size(*r, *r);
+                *r = (rx + ry) / 2;
+            }
+            SpriteElem::Ellipse {
+                x,
+                y,
+                width,
+                height,
+                ..
+            }
+            | SpriteElem::Polygon {
+                x,
+                y,
+                width,
+                height,
+                ..
+            } => {
+                map(x, y);
+                (*width, *height) = size(*width, *height);
+            }
+            SpriteElem::BSpline2(pts)
+            | SpriteElem::BSpline2Closed(pts)
+            | SpriteElem::BSpline3(pts)
+            | SpriteElem::BSpline3Closed(pts)
+            | SpriteElem::Mesh { vertices: pts, .. } => {
+                for [x, y, _] in pts {
+                    map(x, y);
+                }
+            }
+            SpriteElem::ShiftableMesh { x, y, vertices, .. } => {
+                map(x, y);
+                // The vertices are relative to (`x`, `y`): only their size changes.
+                let (ox, oy) = pt(0, 0);
+                for [vx, vy, _] in vertices {
+                    let (nx, ny) = pt(*vx, *vy);
+                    (*vx, *vy) = (nx - ox, ny - oy);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sprite_elems_round_trip_through_doldoc_bins() {
+        let tri = MeshTri {
+            color: 0x0C,
+            nums: [0, 1, 2],
+        };
+        let vertices = vec![[0, 0, 0], [8, 0, 0], [0, 8, 4]];
+        let mut bitmap = vec![0xFF; 16];
+        bitmap[..3].copy_from_slice(&[1, 2, 3]);
+        let elems = vec![
+            SpriteElem::Color(4),
+            SpriteElem::DitherColor(1, 14),
+            SpriteElem::Thick(2),
+            SpriteElem::PlanarSymmetry {
+                x1: 0,
+                y1: 0,
+                x2: 0,
+                y2: 10,
+            },
+            SpriteElem::TransformOn,
+            SpriteElem::TransformOff,
+            SpriteElem::Shift { x: 1, y: -1 },
+            SpriteElem::Pt { x: 3, y: 4 },
+            SpriteElem::PolyPt {
+                x: 1,
+                y: 1,
+                steps: vec![0, 1, 2, 7, 5],
+            },
+            SpriteElem::Line {
+                x1: 0,
+                y1: 0,
+                x2: 5,
+                y2: 5,
+            },
+            SpriteElem::PolyLine(vec![(0, 0), (4, 0), (4, 4)]),
+            SpriteElem::Rect {
+                x1: 1,
+                y1: 1,
+                x2: 6,
+                y2: 4,
+            },
+            SpriteElem::RotatedRect {
+                x1: 1,
+                y1: 1,
+                x2: 6,
+                y2: 4,
+                angle: 0.5,
+            },
+            SpriteElem::Circle { x: 8, y: 8, r: 3 },
+            SpriteElem::Ellipse {
+                x: 8,
+                y: 8,
+                width: 6,
+                height: 2,
+                angle: 0.0,
+            },
+            SpriteElem::Polygon {
+                x: 8,
+                y: 8,
+                width: 4,
+                height: 4,
+                angle: 0.0,
+                sides: 5,
+            },
+            SpriteElem::BSpline2(vec![[0, 0, 0], [4, 8, 0], [8, 0, 0]]),
+            SpriteElem::BSpline2Closed(vec![[0, 0, 0], [4, 8, 0], [8, 0, 0]]),
+            SpriteElem::BSpline3(vec![[0, 0, 0], [4, 8, 0], [8, 0, 0], [12, 8, 0]]),
+            SpriteElem::BSpline3Closed(vec![[0, 0, 0], [4, 8, 0], [8, 0, 0]]),
+            SpriteElem::FloodFill { x: 2, y: 2 },
+            SpriteElem::FloodFillNot { x: 2, y: 2 },
+            SpriteElem::Bitmap {
+                x: 0,
+                y: 0,
+                width: 3,
+                height: 2,
+                body: bitmap,
+            },
+            SpriteElem::Mesh {
+                vertices: vertices.clone(),
+                tris: vec![tri],
+            },
+            SpriteElem::ShiftableMesh {
+                x: 2,
+                y: 3,
+                z: 0,
+                vertices,
+                tris: vec![tri],
+            },
+            SpriteElem::Arrow {
+                x1: 0,
+                y1: 0,
+                x2: 9,
+                y2: 0,
+            },
+            SpriteElem::Text {
+                x: 0,
+                y: 0,
+                text: b"Hi".to_vec(),
+            },
+            SpriteElem::TextBox {
+                x: 0,
+                y: 10,
+                text: b"Box".to_vec(),
+            },
+            SpriteElem::TextDiamond {
+                x: 0,
+                y: 20,
+                text: b"Go".to_vec(),
+            },
+        ];
+        let bytes = sprite_elem_bytes(&elems);
+        assert!(sprite_is_valid(&bytes));
+
+        let bins = std::collections::BTreeMap::from([(1, bytes.clone())]);
+        let blob = crate::doldoc::encode_doc_blob(b"$SP,\"\",BI=1$\n", &bins);
+        let (_, parsed) = crate::doldoc::parse_doc_blob(&blob);
+        let parsed = sprite_elems(&parsed[&1]).expect("parse");
+        assert_eq!(parsed, elems);
+        assert_eq!(sprite_elem_bytes(&parsed), bytes);
+
+        let mut line = SpriteElem::Line {
+            x1: 1,
+            y1: 2,
+            x2: 3,
+            y2: 4,
+        };
+        line.translate(10, 20);
+        line.scale(2.0, 0.5);
+        assert_eq!(
+            line,
+            SpriteElem::Line {
+                x1: 22,
+                y1: 11,
+                x2: 26,
+                y2: 12
+            }
+        );
+
+        let mut bitmap = elems[22].clone();
+        bitmap.scale(2.0, 1.0);
+        bitmap.recolor(|c| c + 8);
+        let SpriteElem::Bitmap {
+            width,
+            height,
+            body,
+            ..
+        } = bitmap
+        else {
+            panic!("bitmap");
+        };
+        assert_eq!((width, height), (6, 2));
+        assert_eq!(&body[..7], &[9, 9, 10, 10, 11, 11, 0xFF]);
+        assert_eq!(body[8], 0xFF);
+    }
+}
@@ -82,6 +82,8 @@ type AppId = u32;
 enum TempleIpcEvent {
     AppConnected {
         id: AppId,
+        /// The client's pid, from the socket's peer credentials.
+        pid: Option<u32>,
         shm: File,
         width: u32,
         height: u32,
@@ -110,6 +112,33 @@ enum TempleIpcEvent {
         id: AppId,
         text: String,
     },
+    /// A line from `protocol::MSG_SHELL_REQUEST`.
+    ShellRequest {
+        id: AppId,
+        req: String,
+    },
+    /// Any other message from the app (`protocol::MSG_INPUT_ACK`, sound): it is still alive.
+    AppAlive {
+        id: AppId,
+    },
+}
+
+impl TempleIpcEvent {
+    /// The app that sent this, for the hang watchdog: anything it sends proves it is alive.
+    fn sender(&self) -> Option<AppId> {
+        match self {
+            TempleIpcEvent::AppPresent { id, .. }
+            | TempleIpcEvent::PaletteColorSet { id, .. }
+            | TempleIpcEvent::SettingsPush { id }
+            | TempleIpcEvent::SettingsPop { id }
+            | TempleIpcEvent::ClipboardSet { id, .. }
+            | TempleIpcEvent::ShellRequest { id, .. }
+            | TempleIpcEvent::AppAlive { id } => Some(*id),
+            TempleIpcEvent::AppConnected { .. }
+            | TempleIpcEvent::AppDisconnected { .. }
+            | TempleIpcEvent::Log(_) => None,
+        }
+    }
 }
 
 struct Framebuffer {
@@ -181,6 +210,90 @@ struct Cell {
     bg: u8,
 }
 
+/// A selected stretch of text, from `anchor` to `head` as (line, column) caret positions: the
+/// cells from the earlier one up to (not including) the later one are selected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct TextSelection {
+    anchor: (usize, usize),
+    head: (usize, usize),
+}
+
+impl TextSelection {
+    fn at(pos: (usize, usize)) -> Self {
+        Self {
+            anchor: pos,
+            head: pos,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    fn ordered(&self) -> ((usize, usize), (usize, usize)) {
+        if self.anchor <= self.head {
+            (self.anchor, self.head)
+        } else {
+            (self.head, self.anchor)
+        }
+    }
+
+    fn contains(&self, line: usize, col: usize) -> bool {
+        let (start, end) = self.ordered();
+        (line, col) >= start && (line, col) < end
+    }
+
+    /// Moves the head by `dy` lines and `dx` columns; columns wrap onto the neighbouring line.
+    fn step(&mut self, dy: isize, dx: isize, line_count: usize, cols: usize) {
+        let (mut line, mut col) = self.head;
+        let last = line_count.saturating_sub(1);
+        line = line.saturating_add_signed(dy).min(last);
+        if dx < 0 {
+            for _ in 0..dx.unsigned_abs() {
+                if col > 0 {
+                    col -= 1;
+                } else if line > 0 {
+                    line -= 1;
+                    col = cols;
+                }
+            }
+        } else {
+            for _ in 0..dx {
+                if col < cols {
+                    col += 1;
+                } else if line < last {
+                    line += 1;
+                    col = 0;
+                }
+            }
+        }
+        self.head = (line, col.min(cols));
+    }
+
+    /// The selected text, one line per row with trailing blanks dropped; CP437 comes out as
+    /// Unicode.
+    fn text<'a>(&self, line_at: impl Fn(usize) -> Option<&'a [Cell]>) -> String {
+        let (start, end) = self.ordered();
+        let mut out = String::new();
+        for line in start.0..=end.0 {
+            let cells = line_at(line).unwrap_or_default();
+            let from = if line == start.0 { start.1 } else { 0 };
+            let to = if line == end.0 { end.1 } else { cells.len() };
+            let bytes: Vec<u8> = cells
+                .get(from.min(cells.len())..to.min(cells.len()))
+                .unwrap_or_default()
+                .iter()
+                .map(|c| if c.ch == 0 { b' ' } else { c.ch })
+                .collect();
+            out.push_str(assets::decode_cp437_bytes(&bytes).trim_end_matches(' '));
+            if line != end.0 {
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
 struct Terminal {
     cells: Vec<Cell>,
     cursor_col: u32,
@@ -191,6 +304,10 @@ struct Terminal {
     scrollback: std::collections::VecDeque<Vec<Cell>>,
     view_offset: usize,
     scrollback_max: usize,
+    /// Mouse/Shift+arrow selection over the output; lines count from the oldest scrollback line.
+    selection: Option<TextSelection>,
+    /// Glyphs cells are drawn with (`font`), in `text.font` layout.
+    font: Box<[u64; 256]>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -213,9 +330,15 @@ impl Terminal {
             scrollback: std::collections::VecDeque::new(),
             view_offset: 0,
             scrollback_max: 2000,
+            selection: None,
+            font: Box::new(assets::TEMPLEOS_SYS_FONT_STD_U64),
         }
     }
 
+    fn set_font(&mut self, font: &temple_rt::font::Font) {
+        *self.font = font.to_u64();
+    }
+
     fn clear_output(&mut self) {
         let blank = Cell {
             ch: b' ',
@@ -228,6 +351,7 @@ impl Terminal {
         self.cursor_row = 0;
         self.scrollback.clear();
         self.view_offset = 0;
+        self.selection = None;
     }
 
     #[allow(dead_code)]
@@ -298,6 +422,7 @@ impl Terminal {
             self.cursor_col = 0;
             self.scrollback.clear();
             self.view_offset = 0;
+            self.selection = None;
             return;
         }
 
@@ -309,6 +434,13 @@ impl Terminal {
             self.scrollback.push_back(self.cells[start..end].to_vec());
             while self.scrollback.len() > self.scrollback_max {
                 self.scrollback.pop_front();
+                // History lines shift up by one; drop a selection that scrolled out.
+                self.selection = self.selection.and_then(|sel| {
+                    Some(TextSelection {
+                        anchor: (sel.anchor.0.checked_sub(1)?, sel.anchor.1),
+                        head: (sel.head.0.checked_sub(1)?, sel.head.1),
+                    })
+                });
             }
         }
         if self.view_offset > 0 {
@@ -374,7 +506,7 @@ impl Terminal {
             };
 
             for col in 0..TERM_COLS {
-                let cell = src
+                let mut cell = src
                     .and_then(|row| row.get(col as usize))
                     .copied()
                     .unwrap_or(Cell {
@@ -382,14 +514,21 @@ impl Terminal {
                         fg: self.fg,
                         bg: self.bg,
                     });
-                draw_cell_8x8(fb, col, row, cell, mode);
+                if self
+                    .selection
+                    .is_some_and(|sel| sel.contains(line_idx, col as usize))
+                {
+                    cell.fg = COLOR_SEL_FG;
+                    cell.bg = COLOR_SEL_BG;
+                }
+                draw_cell_8x8(fb, col, row, self.font[cell.ch as usize], cell, mode);
             }
         }
 
         for row in scroll_rows..TERM_ROWS {
             for col in 0..TERM_COLS {
                 let cell = self.cells[self.idx(col, row)];
-                draw_cell_8x8(fb, col, row, cell, mode);
+                draw_cell_8x8(fb, col, row, self.font[cell.ch as usize], cell, mode);
             }
         }
     }
@@ -427,6 +566,76 @@ impl Terminal {
     fn view_offset(&self) -> usize {
         self.view_offset
     }
+
+    /// Output lines: the scrollback, then the scroll region.
+    fn history_len(&self) -> usize {
+        self.scrollback.len() + self.scroll_rows.min(TERM_ROWS) as usize
+    }
+
+    fn history_line(&self, idx: usize) -> Option<&[Cell]> {
+        match idx.checked_sub(self.scrollback.len()) {
+            None => self.scrollback.get(idx).map(Vec::as_slice),
+            Some(row) if row < self.scroll_rows.min(TERM_ROWS) as usize => {
+                let start = row * TERM_COLS as usize;
+                self.cells.get(start..start + TERM_COLS as usize)
+            }
+            Some(_) => None,
+        }
+    }
+
+    /// History line shown on screen row `row` (`None` below the scroll region).
+    fn history_line_at_row(&self, row: u32) -> Option<usize> {
+        let scroll_rows = self.scroll_rows.min(TERM_ROWS);
+        if row >= scroll_rows {
+            return None;
+        }
+        let start = self
+            .history_len()
+            .saturating_sub(scroll_rows as usize + self.view_offset);
+        Some(start + row as usize)
+    }
+
+    /// Mouse selection: starts (or, with `extend`, moves the head of) a selection at the caret
+    /// position `col` of screen row `row`.
+    fn select_at(&mut self, col: u32, row: u32, extend: bool) {
+        let row = row.min(self.scroll_rows.min(TERM_ROWS).saturating_sub(1));
+        let Some(line) = self.history_line_at_row(row) else {
+            return;
+        };
+        let pos = (line, col.min(TERM_COLS) as usize);
+        match self.selection.as_mut() {
+            Some(sel) if extend => sel.head = pos,
+            _ => self.selection = Some(TextSelection::at(pos)),
+        }
+    }
+
+    /// Shift+arrows: extends the selection (starting at the output cursor) and scrolls the view
+    /// to keep its head on screen.
+    fn select_step(&mut self, dy: isize, dx: isize) {
+        let cursor = (
+            self.scrollback.len() + self.cursor_row as usize,
+            self.cursor_col as usize,
+        );
+        let len = self.history_len();
+        let sel = self.selection.get_or_insert(TextSelection::at(cursor));
+        sel.step(dy, dx, len, TERM_COLS as usize);
+        let head = sel.head.0;
+
+        let rows = self.scroll_rows.min(TERM_ROWS) as usize;
+        let top = len.saturating_sub(rows + self.view_offset);
+        if head < top {
+            self.view_offset += top - head;
+        } else if head >= top + rows {
+            self.view_offset = self.view_offset.saturating_sub(head + 1 - top - rows);
+        }
+        self.view_offset = self.view_offset.min(self.scrollback.len());
+    }
+
+    fn selected_text(&self) -> Option<String> {
+        self.selection
+            .filter(|sel| !sel.is_empty())
+            .map(|sel| sel.text(|line| self.history_line(line)))
+    }
 }
 
 impl fmt::Write for Terminal {
@@ -442,20 +651,38 @@ fn sanitize_ascii(ch: char) -> u8 {
     assets::encode_cp437(ch)
 }
 
-fn draw_cell_8x8(fb: &mut Framebuffer, col: u32, row: u32, cell: Cell, mode: TerminalRenderMode) {
+fn draw_cell_8x8(
+    fb: &mut Framebuffer,
+    col: u32,
+    row: u32,
+    glyph: u64,
+    cell: Cell,
+    mode: TerminalRenderMode,
+) {
     let x = col * FONT_W;
     let y = row * FONT_H;
     match mode {
-        TerminalRenderMode::Opaque => draw_char_8x8(fb, x, y, cell.ch, cell.fg, cell.bg),
+        TerminalRenderMode::Opaque => draw_glyph_8x8(fb, x, y, glyph, cell.fg, cell.bg),
         TerminalRenderMode::OverWallpaper => {
-            draw_char_8x8_over_wallpaper(fb, x, y, cell.ch, cell.fg, cell.bg)
+            draw_glyph_8x8_over_wallpaper(fb, x, y, glyph, cell.fg, cell.bg)
         }
     }
 }
 
 fn draw_char_8x8(fb: &mut Framebuffer, x: u32, y: u32, ch: u8, fg: u8, bg: u8) {
+    draw_glyph_8x8(
+        fb,
+        x,
+        y,
+        assets::TEMPLEOS_SYS_FONT_STD_U64[ch as usize],
+        fg,
+        bg,
+    );
+}
+
+fn draw_glyph_8x8(fb: &mut Framebuffer, x: u32, y: u32, glyph: u64, fg: u8, bg: u8) {
     for row in 0..8u32 {
-        let row_bits = assets::sys_font_std_glyph_row_bits(ch, row as u8);
+        let row_bits = (glyph >> (row * 8)) as u8;
         for col in 0..8u32 {
             let on = (row_bits & (1u8 << col as u8)) != 0;
             fb.put_pixel(x + col, y + row, if on { fg } else { bg });
@@ -463,16 +690,16 @@ fn draw_char_8x8(fb: &mut Framebuffer, x: u32, y: u32, ch: u8, fg: u8, bg: u8) {
     }
 }
 
-fn draw_char_8x8_over_wallpaper(fb: &mut Framebuffer, x: u32, y: u32, ch: u8, fg: u8, bg: u8) {
+fn draw_glyph_8x8_over_wallpaper(fb: &mut Framebuffer, x: u32, y: u32, glyph: u64, fg: u8, bg: u8) {
     // Dither black background pixels so a wallpaper can still show through while keeping text readable.
     // Only applies to black background (bg==0); other backgrounds remain fully opaque.
     if bg != 0 {
-        draw_char_8x8(fb, x, y, ch, fg, bg);
+        draw_glyph_8x8(fb, x, y, glyph, fg, bg);
         return;
     }
 
     for row in 0..8u32 {
-        let row_bits = assets::sys_font_std_glyph_row_bits(ch, row as u8);
+        let row_bits = (glyph >> (row * 8)) as u8;
         for col in 0..8u32 {
             let px = x + col;
             let py = y + row;
@@ -562,6 +789,34 @@ fn blit_scaled_indices(fb: &mut Framebuffer, dst: RectI32, src: &[u8], src_w: u3
     }
 }
 
+/// Presentation mode: the bottom-left `1/scale` of the screen, where the prompt and the latest
+/// output are, magnified to fill it.
+fn zoom_bottom_left(fb: &mut Framebuffer, scale: u32) {
+    if scale <= 1 {
+        return;
+    }
+    let (w, h) = (INTERNAL_W / scale, INTERNAL_H / scale);
+    let y0 = INTERNAL_H - h;
+    let mut src = Vec::with_capacity((w * h) as usize);
+    for y in y0..INTERNAL_H {
+        let row = (y * INTERNAL_W) as usize;
+        src.extend_from_slice(&fb.indices[row..row + w as usize]);
+    }
+    let dst = RectI32 {
+        x: 0,
+        y: 0,
+        w: INTERNAL_W as i32,
+        h: INTERNAL_H as i32,
+    };
+    blit_scaled_indices(fb, dst, &src, w, h);
+}
+
+/// Where a screen point falls before [`zoom_bottom_left`] magnified it.
+fn unzoom_point(x: u32, y: u32, scale: u32) -> (u32, u32) {
+    let scale = scale.max(1);
+    (x / scale, INTERNAL_H - INTERNAL_H / scale + y / scale)
+}
+
 fn draw_software_cursor(fb: &mut Framebuffer, x: u32, y: u32) {
     const BORDER: [u8; CURSOR_H as usize] = [
         0b00000001, 0b00000011, 0b00000101, 0b00001001, 0b00010001, 0b00100001, 0b01000001,
@@ -34,6 +34,14 @@ fn pick_temple_root(test_mode: bool) -> PathBuf {
     default_temple_root()
 }
 
+/// Whether the file browser previews `name` as an image.
+fn is_image_name(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    [".GR", ".GR.Z", ".BMP", ".PNG"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
+}
+
 #[derive(Clone, Debug, Default)]
 struct TemplePath {
     components: Vec<String>,
@@ -113,6 +121,8 @@ struct FileBrowserState {
     selected: usize,
     scroll: usize,
     msg: String,
+    /// The last image file previewed and its pixels, `None` when it doesn't decode.
+    preview: Option<(PathBuf, Option<temple_rt::doldoc::Canvas>)>,
 }
 
 impl FileBrowserState {
@@ -123,6 +133,7 @@ impl FileBrowserState {
             selected: 0,
             scroll: 0,
             msg: String::new(),
+            preview: None,
         }
     }
 }
@@ -134,10 +145,14 @@ enum DocKind {
     PlainText,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 enum DocLinkTarget {
     Doc(String),
     Action(String),
+    /// Index into `DocViewerState::forms`.
+    Form(usize),
+    /// Index into `DocViewerState::trees`.
+    Tree(usize),
 }
 
 #[derive(Clone, Debug)]
@@ -148,30 +163,48 @@ struct DocLink {
     target: DocLinkTarget,
 }
 
-#[derive(Clone, Debug)]
-struct DocSprite {
-    anchor_line: usize,
-    anchor_col: usize,
-    bbox_line0: i32,
-    bbox_col0: i32,
-    bbox_line1: i32,
-    bbox_col1: i32,
-    bin_num: u32,
-    action: Option<String>,
+/// A find match in the doc viewer: line, start and end column.
+type FindMatch = (usize, usize, usize);
+
+/// What the doc viewer's bottom-line prompt is asking for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DocPrompt {
+    /// `/`: text to find.
+    Find,
+    /// `b`: name for a bookmark of the current position.
+    Bookmark,
 }
 
 #[derive(Clone, Debug)]
 struct DocViewerState {
     spec: String,
     kind: DocKind,
+    /// Document source, rewritten as form entries are edited.
+    source: String,
+    /// Host file edits are saved to (`None` for generated docs).
+    host: Option<PathBuf>,
+    /// Form entries changed since the last save.
+    modified: bool,
     lines: Vec<Vec<Cell>>,
     scroll: usize,
     links: Vec<DocLink>,
     selected_link: Option<usize>,
     selected_sprite: Option<usize>,
     anchors: std::collections::BTreeMap<String, usize>,
-    sprites: Vec<DocSprite>,
+    sprites: Vec<temple_rt::doldoc::DocSprite>,
     bins: std::collections::BTreeMap<u32, Vec<u8>>,
+    forms: Vec<temple_rt::doldoc::FormField>,
+    trees: Vec<temple_rt::doldoc::DocTree>,
+    /// Trees opened or closed by the user (kept in `Cfg/DocTrees.txt`).
+    tree_state: temple_rt::doldoc::TreeState,
+    /// Data field being edited: form index and the text typed so far.
+    editing: Option<(usize, String)>,
+    /// Bottom-line prompt being typed.
+    prompt: Option<(DocPrompt, String)>,
+    /// Last find query and the match shown.
+    find: Option<(String, Option<FindMatch>)>,
+    /// Mouse/Shift+arrow selection over `lines`.
+    selection: Option<TextSelection>,
     msg: String,
 }
 
@@ -11,16 +11,36 @@ struct Shell {
     tapp_connected: bool,
     tapp_child: Option<std::process::Child>,
     tapp_last: Option<TappLaunch>,
+    /// temple-hc sessions running DolDoc macros (see `run_doc_macro`).
+    macro_children: Vec<MacroChild>,
     pending_window_titles: std::collections::VecDeque<String>,
     pending_window_kinds: std::collections::VecDeque<PendingWindowKind>,
     exit_requested: bool,
     pending_screenshot: Option<(String, PathBuf)>,
     browser: Option<FileBrowserState>,
     doc_viewer: Option<DocViewerState>,
+    /// Loaded on the first `search`.
+    doc_index: Option<DocIndex>,
+    /// Query of the last `search`; the doc viewer's find starts from it.
+    last_search: String,
+    /// Doc viewer pages left by following links (most recent last), and pages gone back from.
+    doc_back: Vec<DocViewerState>,
+    doc_forward: Vec<DocViewerState>,
+    /// Presentation zoom of the terminal (`font scale`): 1 is off.
+    text_scale: u32,
+    /// Define lists found in HolyC sources, for doc viewer `$LS$` entries.
+    define_lists: Option<std::collections::BTreeMap<String, Vec<String>>>,
 }
 
+/// Shell vars holding the font and text scale; apps launched from the shell get them too.
+const FONT_VAR: &str = "TEMPLE_FONT";
+const TEXT_SCALE_VAR: &str = "TEMPLE_TEXT_SCALE";
+/// Largest presentation zoom of the terminal.
+const FONT_MAX_ZOOM: u32 = 4;
+
 const SHELL_COMMANDS: &[&str] = &[
     "apps",
+    "bookmarks",
     "browse",
     "cat",
     "cd",
@@ -33,6 +53,7 @@ const SHELL_COMMANDS: &[&str] = &[
     "files",
     "find",
     "fm",
+    "font",
     "grep",
     "hc",
     "head",
@@ -50,6 +71,7 @@ const SHELL_COMMANDS: &[&str] = &[
     "rm",
     "run",
     "screenshot",
+    "search",
     "set",
     "shot",
     "shutdown",
@@ -64,6 +86,17 @@ const SHELL_COMMANDS: &[&str] = &[
 enum PendingWindowKind {
     Normal,
     Wallpaper,
+    /// No window until the app presents a frame or waits for input (DolDoc macros that only
+    /// talk to the shell).
+    Hidden,
+}
+
+/// A temple-hc session started by `run_doc_macro`. It is matched to its connection by pid, so
+/// it never takes (or leaves behind) a title queued for another app.
+struct MacroChild {
+    child: std::process::Child,
+    title: String,
+    connected: bool,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -73,18 +106,13 @@ enum DocActionOutcome {
     CloseDocViewer,
 }
 
-#[derive(Default)]
-struct DolDocMeta {
-    links: Vec<DocLink>,
-    anchors: std::collections::BTreeMap<String, usize>,
-    sprites: Vec<DocSprite>,
-}
-
 struct BuiltDoc {
     lines: Vec<Vec<Cell>>,
     links: Vec<DocLink>,
     anchors: std::collections::BTreeMap<String, usize>,
-    sprites: Vec<DocSprite>,
+    sprites: Vec<temple_rt::doldoc::DocSprite>,
+    forms: Vec<temple_rt::doldoc::FormField>,
+    trees: Vec<temple_rt::doldoc::DocTree>,
 }
 
 impl Shell {
@@ -95,6 +123,7 @@ impl Shell {
         let _ = std::fs::create_dir_all(root_dir.join("Doc"));
         let _ = std::fs::create_dir_all(root_dir.join("Cfg"));
         let _ = std::fs::create_dir_all(root_dir.join("Apps"));
+        let _ = std::fs::create_dir_all(root_dir.join(temple_rt::font::FONTS_DIR));
         let linuxbridge_path = root_dir.join("Apps/LinuxBridge.HC");
         if test_mode || !linuxbridge_path.exists() {
             let _ = std::fs::write(&linuxbridge_path, TEMPLELINUX_LINUXBRIDGE_HC);
@@ -130,12 +159,19 @@ impl Shell {
             tapp_connected: false,
             tapp_child: None,
             tapp_last: None,
+            macro_children: Vec::new(),
             pending_window_titles: std::collections::VecDeque::new(),
             pending_window_kinds: std::collections::VecDeque::new(),
             exit_requested: false,
             pending_screenshot: None,
             browser: None,
             doc_viewer: None,
+            doc_index: None,
+            last_search: String::new(),
+            doc_back: Vec::new(),
+            doc_forward: Vec::new(),
+            text_scale: 1,
+            define_lists: None,
         };
         if !test_mode {
             shell.load_state();
@@ -426,6 +462,7 @@ impl Shell {
         };
 
         term.scroll_view_to_bottom();
+        term.selection = None;
         for row in 0..=PROMPT_ROW {
             term.fill_row(row, COLOR_FG, COLOR_BG);
         }
@@ -506,6 +543,35 @@ impl Shell {
         term.write_at(0, PROMPT_ROW, COLOR_FG, COLOR_STATUS_BG, hint);
     }
 
+    /// The selected file's pixels when it is a `.GR`, `.GR.Z`, `.BMP` or `.PNG` image, decoded
+    /// once per selection.
+    fn browser_preview(&mut self) -> Option<&temple_rt::doldoc::Canvas> {
+        let state = self.browser.as_mut()?;
+        let entry = state.entries.get(state.selected)?;
+        if state.tab != BrowserTab::Files
+            || entry.kind != BrowserEntryKind::File
+            || !is_image_name(&entry.name)
+        {
+            return None;
+        }
+        let host = self.cwd.resolve(&entry.name).to_host_path(&self.root_dir);
+        if state.preview.as_ref().is_none_or(|(path, _)| *path != host) {
+            let image = std::fs::read(&host)
+                .ok()
+                .and_then(|bytes| temple_rt::image::decode_image(&bytes).ok())
+                .map(|mut image| {
+                    for px in &mut image.pixels {
+                        if *px == temple_rt::rt::TRANSPARENT {
+                            *px = COLOR_BG;
+                        }
+                    }
+                    image
+                });
+            state.preview = Some((host, image));
+        }
+        state.preview.as_ref()?.1.as_ref()
+    }
+
     fn doc_view_rows() -> usize {
         // Row 0: title bar, row PROMPT_ROW: hint bar.
         PROMPT_ROW.saturating_sub(1).max(1) as usize
@@ -535,21 +601,243 @@ impl Shell {
         None
     }
 
+    /// Next (or previous) case-insensitive match of `needle` after (before) `from` (line,
+    /// column), wrapping around the document. Returns the match and whether it wrapped.
+    fn find_in_lines(
+        lines: &[Vec<Cell>],
+        needle: &str,
+        from: (usize, usize),
+        backwards: bool,
+    ) -> Option<(FindMatch, bool)> {
+        let needle = needle.trim().to_ascii_lowercase();
+        if needle.is_empty() || lines.is_empty() {
+            return None;
+        }
+        let line_text = |line: &[Cell]| -> String {
+            line.iter()
+                .map(|cell| {
+                    let b = cell.ch.to_ascii_lowercase();
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        ' '
+                    }
+                })
+                .collect()
+        };
+        let n = lines.len();
+        let (from_line, from_col) = (from.0.min(n - 1), from.1);
+        for step in 0..=n {
+            let idx = if backwards {
+                (from_line + n - step % n) % n
+            } else {
+                (from_line + step) % n
+            };
+            let text = line_text(&lines[idx]);
+            let hit = if backwards {
+                let end = if step == 0 { from_col } else { text.len() };
+                text.get(..end.saturating_add(needle.len() - 1).min(text.len()))
+                    .and_then(|t| t.rfind(&needle))
+                    .filter(|&col| step != 0 || col < from_col)
+            } else {
+                let start = if step == 0 { from_col } else { 0 };
+                text.get(start.min(text.len())..)
+                    .and_then(|t| t.find(&needle))
+                    .map(|col| col + start.min(text.len()))
+            };
+            if let Some(col) = hit {
+                let wrapped = if backwards {
+                    idx > from_line || step == n
+                } else {
+                    idx < from_line || step == n
+                };
+                return Some(((idx, col, col + needle.len()), wrapped));
+            }
+        }
+        None
+    }
+
+    /// Finds `query` (or repeats the last find, else the longest word of the last `search`) in
+    /// the open document and scrolls the match into view.
+    fn doc_find(&mut self, query: Option<String>, backwards: bool) {
+        let fallback = self
+            .last_search
+            .split_whitespace()
+            .max_by_key(|w| w.len())
+            .unwrap_or_default()
+            .to_string();
+        let Some(state) = self.doc_viewer.as_mut() else {
+            return;
+        };
+        let (query, from) = match (query, state.find.take()) {
+            (Some(q), _) => (q, (state.scroll, 0)),
+            (None, Some((q, Some((line, col, _))))) => {
+                let col = if backwards { col } else { col + 1 };
+                (q, (line, col))
+            }
+            (None, Some((q, None))) => (q, (state.scroll, 0)),
+            (None, None) => (fallback, (state.scroll, 0)),
+        };
+        if query.trim().is_empty() {
+            state.msg = "find: nothing to find (press /)".to_string();
+            return;
+        }
+
+        let hit = Self::find_in_lines(&state.lines, &query, from, backwards);
+        state.msg = match hit {
+            Some((_, true)) => format!("find: {query} (wrapped)"),
+            Some((_, false)) => format!("find: {query}"),
+            None => format!("not found: {query}"),
+        };
+        let hit = hit.map(|(hit, _)| hit);
+        if let Some((line, _, _)) = hit {
+            let rows = Self::doc_view_rows().max(1);
+            let max_scroll = state.lines.len().saturating_sub(rows);
+            if line < state.scroll || line >= state.scroll + rows {
+                state.scroll = line.saturating_sub(rows / 3).min(max_scroll);
+            }
+        }
+        state.find = Some((query, hit));
+    }
+
+    /// Keys while the find or bookmark prompt is open; every key is consumed.
+    fn handle_key_doc_prompt(&mut self, key: &Key, term: &mut Terminal) -> bool {
+        let Some(state) = self.doc_viewer.as_mut() else {
+            return false;
+        };
+        let Some((prompt, mut buf)) = state.prompt.take() else {
+            return false;
+        };
+        match key {
+            Key::Named(NamedKey::Escape) => {}
+            Key::Named(NamedKey::Enter) => {
+                if !buf.trim().is_empty() {
+                    match prompt {
+                        DocPrompt::Find => self.doc_find(Some(buf), false),
+                        DocPrompt::Bookmark => self.add_doc_bookmark(&buf),
+                    }
+                }
+            }
+            Key::Named(NamedKey::Backspace) => {
+                buf.pop();
+                state.prompt = Some((prompt, buf));
+            }
+            Key::Named(NamedKey::Space) => {
+                buf.push(' ');
+                state.prompt = Some((prompt, buf));
+            }
+            Key::Character(s) => {
+                buf.extend(s.chars().filter(|c| !c.is_control()));
+                state.prompt = Some((prompt, buf));
+            }
+            _ => state.prompt = Some((prompt, buf)),
+        }
+        self.render_doc_viewer(term);
+        true
+    }
+
+    /// Mouse selection in the doc viewer at caret position `col` of screen row `row`; rows
+    /// off the content area are clamped to it.
+    fn doc_select_at(&mut self, col: u32, row: u32, extend: bool) {
+        let Some(state) = self.doc_viewer.as_mut() else {
+            return;
+        };
+        if state.lines.is_empty() {
+            return;
+        }
+        let rows = Self::doc_view_rows();
+        let row_idx = (row.max(1) as usize - 1).min(rows - 1);
+        let line = (state.scroll + row_idx).min(state.lines.len() - 1);
+        let pos = (line, col.min(TERM_COLS) as usize);
+        match state.selection.as_mut() {
+            Some(sel) if extend => sel.head = pos,
+            _ => state.selection = Some(TextSelection::at(pos)),
+        }
+    }
+
+    /// Shift+arrows in the doc viewer: extends the selection (from the top of the view) and
+    /// keeps its head on screen.
+    fn doc_select_step(&mut self, dy: isize, dx: isize, term: &mut Terminal) {
+        let Some(state) = self.doc_viewer.as_mut() else {
+            return;
+        };
+        if state.lines.is_empty() {
+            return;
+        }
+        let rows = Self::doc_view_rows().max(1);
+        let sel = state
+            .selection
+            .get_or_insert(TextSelection::at((state.scroll, 0)));
+        sel.step(dy, dx, state.lines.len(), TERM_COLS as usize);
+        let head = sel.head.0;
+        if head < state.scroll {
+            state.scroll = head;
+        } else if head >= state.scroll + rows {
+            state.scroll = head + 1 - rows;
+        }
+        self.render_doc_viewer(term);
+    }
+
+    /// Drops the doc viewer's and the terminal's selection; true if there was one.
+    fn clear_selection(&mut self, term: &mut Terminal) -> bool {
+        let had = term.selection.take().is_some();
+        let had_doc = self
+            .doc_viewer
+            .as_mut()
+            .is_some_and(|state| state.selection.take().is_some());
+        if had_doc {
+            self.render_doc_viewer(term);
+        }
+        had || had_doc
+    }
+
+    /// Copies the doc viewer's (or else the terminal's) selection to the clipboard.
+    fn copy_selection(&mut self, term: &mut Terminal) -> bool {
+        use fmt::Write as _;
+
+        let text = match self.doc_viewer.as_ref() {
+            Some(state) => state
+                .selection
+                .filter(|sel| !sel.is_empty())
+                .map(|sel| sel.text(|line| state.lines.get(line).map(Vec::as_slice))),
+            None => term.selected_text(),
+        };
+        let Some(text) = text else {
+            return false;
+        };
+        let result = self.clipboard.set_text(&text);
+        match self.doc_viewer.as_mut() {
+            Some(state) => {
+                state.msg = match result {
+                    Ok(()) => format!("copied {} chars", text.chars().count()),
+                    Err(err) => format!("clipboard: set: {err}"),
+                };
+                self.render_doc_viewer(term);
+            }
+            None => {
+                if let Err(err) = result {
+                    let _ = writeln!(term, "clipboard: set: {err}");
+                }
+            }
+        }
+        true
+    }
+
     fn build_doc(
         &self,
         kind: DocKind,
         text: &str,
         bins: &std::collections::BTreeMap<u32, Vec<u8>>,
+        trees: &temple_rt::doldoc::TreeState,
     ) -> BuiltDoc {
         use fmt::Write as _;
 
         let mut t = Terminal::new(COLOR_FG, COLOR_BG, Self::doc_view_rows() as u32);
         t.scrollback_max = 10_000;
 
-        let mut meta = DolDocMeta::default();
         match kind {
+            DocKind::DolDoc => return Self::build_doldoc(text, bins, trees),
             DocKind::TempleDoc => self.render_tdoc(text, &mut t),
-            DocKind::DolDoc => meta = self.render_doldoc(text, bins, &mut t),
             DocKind::PlainText => {
                 for line in text.lines() {
                     let _ = writeln!(&mut t, "{line}");
@@ -564,19 +852,82 @@ impl Shell {
             let end = start + TERM_COLS as usize;
             lines.push(t.cells[start..end].to_vec());
         }
+        Self::trim_blank_lines(&mut lines);
+        BuiltDoc {
+            lines,
+            links: Vec::new(),
+            anchors: Default::default(),
+            sprites: Vec::new(),
+            forms: Vec::new(),
+            trees: Vec::new(),
+        }
+    }
 
+    /// Lays a DolDoc out with the shared `temple_rt::doldoc` engine, `trees` opening or closing
+    /// `$TR$` nodes.
+    fn build_doldoc(
+        text: &str,
+        bins: &std::collections::BTreeMap<u32, Vec<u8>>,
+        trees: &temple_rt::doldoc::TreeState,
+    ) -> BuiltDoc {
+        use temple_rt::doldoc::{self, LayoutOptions, LinkTarget};
+
+        let opts = LayoutOptions {
+            strip_comments: true,
+            ..LayoutOptions::view(TERM_COLS as usize, COLOR_FG, COLOR_BG)
+        };
+        let doc = doldoc::layout_with_trees(text, bins, opts, trees);
+        let mut lines: Vec<Vec<Cell>> = doc
+            .lines
+            .iter()
+            .map(|line| {
+                line.iter()
+                    .map(|c| Cell {
+                        ch: c.ch,
+                        fg: c.fg,
+                        bg: c.bg,
+                    })
+                    .collect()
+            })
+            .collect();
+        Self::trim_blank_lines(&mut lines);
+
+        let links = doc
+            .links
+            .into_iter()
+            .map(|link| DocLink {
+                line: link.line,
+                col_start: link.col_start,
+                col_end: link.col_end,
+                target: match link.target {
+                    LinkTarget::Doc(spec) => DocLinkTarget::Doc(spec),
+                    LinkTarget::Macro(action) => DocLinkTarget::Action(action),
+                    LinkTarget::Song(song) => {
+                        DocLinkTarget::Action(format!("templelinux:song:{song}"))
+                    }
+                    LinkTarget::Url(url) => DocLinkTarget::Action(format!("templelinux:browse:{url}")),
+                    LinkTarget::Form(idx) => DocLinkTarget::Form(idx),
+                    LinkTarget::Tree(idx) => DocLinkTarget::Tree(idx),
+                },
+            })
+            .collect();
+        BuiltDoc {
+            lines,
+            links,
+            anchors: doc.anchors,
+            sprites: doc.sprites,
+            forms: doc.forms,
+            trees: doc.trees,
+        }
+    }
+
+    fn trim_blank_lines(lines: &mut Vec<Vec<Cell>>) {
         while lines
             .last()
             .is_some_and(|line| line.iter().all(|c| c.ch == b' '))
         {
             lines.pop();
         }
-        BuiltDoc {
-            lines,
-            links: meta.links,
-            anchors: meta.anchors,
-            sprites: meta.sprites,
-        }
     }
 
     fn open_doc_viewer(
@@ -590,12 +941,22 @@ impl Shell {
         term: &mut Terminal,
     ) {
         self.browser = None;
+        match self.doc_viewer.take() {
+            Some(prev) => self.push_doc_history(prev),
+            None => {
+                self.doc_back.clear();
+                self.doc_forward.clear();
+            }
+        }
+        let tree_state = self.load_doc_tree_state(&spec);
         let BuiltDoc {
             lines,
             links,
             anchors,
             sprites,
-        } = self.build_doc(kind, text, &bins);
+            forms,
+            trees,
+        } = self.build_doc(kind, text, &bins, &tree_state);
         let selected_link = if links.is_empty() { None } else { Some(0) };
         let mut msg = String::new();
         if truncated {
@@ -618,6 +979,9 @@ impl Shell {
         self.doc_viewer = Some(DocViewerState {
             spec,
             kind,
+            source: text.to_string(),
+            host: None,
+            modified: false,
             lines,
             scroll,
             links,
@@ -626,11 +990,226 @@ impl Shell {
             anchors,
             sprites,
             bins,
+            forms,
+            trees,
+            tree_state,
+            editing: None,
+            prompt: None,
+            find: None,
+            selection: None,
             msg,
         });
         self.render_doc_viewer(term);
     }
 
+    /// Re-lays out the open document after its source changed, keeping the view position.
+    fn rebuild_doc_viewer(&mut self) {
+        let Some(state) = self.doc_viewer.as_ref() else {
+            return;
+        };
+        let built = self.build_doc(state.kind, &state.source, &state.bins, &state.tree_state);
+        let Some(state) = self.doc_viewer.as_mut() else {
+            return;
+        };
+        let max_scroll = built
+            .lines
+            .len()
+            .saturating_sub(Self::doc_view_rows().max(1));
+        state.scroll = state.scroll.min(max_scroll);
+        state.selected_link = state
+            .selected_link
+            .filter(|_| !built.links.is_empty())
+            .map(|i| i.min(built.links.len() - 1));
+        state.lines = built.lines;
+        state.links = built.links;
+        state.anchors = built.anchors;
+        state.sprites = built.sprites;
+        state.forms = built.forms;
+        state.trees = built.trees;
+    }
+
+    /// Writes form entry `idx` back into the document source; `s` saves it (see
+    /// [`Self::save_doc_viewer`]).
+    fn commit_doc_form(&mut self, idx: usize, entry: temple_rt::doldoc::FormEntry) {
+        let Some(state) = self.doc_viewer.as_mut() else {
+            return;
+        };
+        let Some(field) = state.forms.get(idx) else {
+            return;
+        };
+        let range = field.range.clone();
+        let raw = state.source[range.start + 1..range.end - 1].trim();
+        let cmd = format!("${}$", entry.apply_to(raw));
+        state.source.replace_range(range, &cmd);
+        state.modified = true;
+
+        state.msg = match &state.host {
+            Some(host) if is_read_only_doc(&state.spec, host) => {
+                "modified (TempleOS docs are read-only)".to_string()
+            }
+            Some(_) => "modified (s to save)".to_string(),
+            None => "modified (not saved)".to_string(),
+        };
+        self.rebuild_doc_viewer();
+    }
+
+    /// `s` in the doc viewer: saves form edits to the document's file, keeping the bytes of
+    /// everything else. Docs from the TempleOS tree are never written.
+    fn save_doc_viewer(&mut self) {
+        let Some(state) = self.doc_viewer.as_mut() else {
+            return;
+        };
+        if !state.modified {
+            state.msg = "no changes".to_string();
+            return;
+        }
+        state.msg = match &state.host {
+            None => "not saved: not a file".to_string(),
+            Some(host) if is_read_only_doc(&state.spec, host) => {
+                "not saved: TempleOS docs are read-only".to_string()
+            }
+            Some(host) => {
+                let original = std::fs::read(host).unwrap_or_default();
+                let blob =
+                    temple_rt::doldoc::update_doc_blob(&original, &state.source, &state.bins);
+                match std::fs::write(host, blob) {
+                    Ok(()) => {
+                        state.modified = false;
+                        "saved".to_string()
+                    }
+                    Err(err) => format!("not saved: {err}"),
+                }
+            }
+        };
+    }
+
+    /// The items of define list `name`: the built-in lists, then lists that HolyC sources in
+    /// the TempleOS tree or the Temple root load with literal arguments (scanned once).
+    fn doc_define_list(&mut self, name: &str) -> Option<Vec<String>> {
+        if let Some(items) = temple_rt::doldoc::builtin_define_list(name) {
+            return Some(items.iter().map(|s| s.to_string()).collect());
+        }
+        let root_dir = self.root_dir.clone();
+        let lists = self.define_lists.get_or_insert_with(|| {
+            let mut lists = std::collections::BTreeMap::new();
+            let mut files = Vec::new();
+            for root in discover_templeos_root().into_iter().chain([root_dir]) {
+                walk_dir_collect_hc_files(&root, &mut files);
+            }
+            for path in files {
+                let Ok(src) = std::fs::read(&path) else {
+                    continue;
+                };
+                for (name, items) in temple_rt::doldoc::scan_define_lists(&String::from_utf8_lossy(&src))
+                {
+                    lists.entry(name).or_insert(items);
+                }
+            }
+            lists
+        });
+        lists.get(name.trim()).cloned()
+    }
+
+    /// Enter/Space/click on a form entry: toggles check boxes, steps lists, starts editing data
+    /// fields and runs buttons/menu items.
+    fn activate_doc_form(&mut self, idx: usize, term: &mut Terminal) {
+        use temple_rt::doldoc::FormKind;
+
+        let Some(state) = self.doc_viewer.as_mut() else {
+            return;
+        };
+        let Some(mut entry) = state.forms.get(idx).map(|f| f.entry.clone()) else {
+            return;
+        };
+        match entry.kind {
+            FormKind::CheckBox => {
+                entry.checked = !entry.checked;
+                self.commit_doc_form(idx, entry);
+            }
+            FormKind::List => {
+                let list = entry.list.clone().unwrap_or_default();
+                let next = self.doc_define_list(&list).and_then(|items| {
+                    entry.next_list_value(&items.iter().map(String::as_str).collect::<Vec<_>>())
+                });
+                match next {
+                    Some(next) => {
+                        entry.tag = next;
+                        self.commit_doc_form(idx, entry);
+                    }
+                    None => {
+                        if let Some(state) = self.doc_viewer.as_mut() {
+                            state.msg = format!("unknown list: {list}");
+                        }
+                    }
+                }
+            }
+            FormKind::Data => {
+                state.editing = Some((idx, entry.tag.clone()));
+                state.msg = "editing (Enter accept, Esc cancel)".to_string();
+            }
+            FormKind::Button | FormKind::Menu => {
+                if let Some(action) = entry.left_macro.clone() {
+                    if self.exec_doldoc_action(&action, term) == DocActionOutcome::CloseDocViewer {
+                        self.doc_viewer = None;
+                        self.draw_prompt(term);
+                        return;
+                    }
+                } else {
+                    state.msg = match entry.left_exp {
+                        Some(le) => format!("{}: LE={le}", entry.tag),
+                        None => format!("{}: no action", entry.tag),
+                    };
+                }
+            }
+        }
+        self.render_doc_viewer(term);
+    }
+
+    /// Keys while a data field is being edited; every key is consumed.
+    fn handle_key_doc_form_edit(&mut self, key: &Key, term: &mut Terminal) -> bool {
+        let Some(state) = self.doc_viewer.as_mut() else {
+            return false;
+        };
+        let Some((idx, mut buf)) = state.editing.take() else {
+            return false;
+        };
+        let Some(mut entry) = state.forms.get(idx).map(|f| f.entry.clone()) else {
+            return true;
+        };
+        match key {
+            Key::Named(NamedKey::Escape) => state.msg.clear(),
+            Key::Named(NamedKey::Enter) => {
+                let value = buf.trim();
+                if entry.is_numeric() && value.parse::<f64>().is_err() && !value.is_empty() {
+                    state.msg = format!("not a number: {value}");
+                    state.editing = Some((idx, buf));
+                } else {
+                    entry.tag = value.to_string();
+                    self.commit_doc_form(idx, entry);
+                }
+            }
+            Key::Named(NamedKey::Backspace) => {
+                buf.pop();
+                state.editing = Some((idx, buf));
+            }
+            Key::Named(NamedKey::Space) | Key::Character(_) => {
+                let text = match key {
+                    Key::Character(s) => s.as_str(),
+                    _ => " ",
+                };
+                for ch in text.chars().filter(|c| !c.is_control() && *c != '$') {
+                    if buf.chars().count() < entry.len {
+                        buf.push(ch);
+                    }
+                }
+                state.editing = Some((idx, buf));
+            }
+            _ => state.editing = Some((idx, buf)),
+        }
+        self.render_doc_viewer(term);
+        true
+    }
+
     fn render_doc_viewer(&self, term: &mut Terminal) {
         let Some(state) = self.doc_viewer.as_ref() else {
             return;
@@ -672,6 +1251,14 @@ impl Shell {
             }
 
             let mut line = state.lines[line_idx].clone();
+            if let Some((_, Some((find_line, start, end)))) = &state.find
+                && *find_line == line_idx
+            {
+                for cell in line.iter_mut().take(*end).skip(*start) {
+                    cell.fg = COLOR_FIND_FG;
+                    cell.bg = COLOR_FIND_BG;
+                }
+            }
             if let Some(sel) = state.selected_link {
                 if let Some(link) = state.links.get(sel) {
                     if link.line == line_idx {
@@ -682,6 +1269,14 @@ impl Shell {
                     }
                 }
             }
+            if let Some(sel) = &state.selection {
+                for (col, cell) in line.iter_mut().enumerate() {
+                    if sel.contains(line_idx, col) {
+                        cell.fg = COLOR_SEL_FG;
+                        cell.bg = COLOR_SEL_BG;
+                    }
+                }
+            }
 
             for col in 0..TERM_COLS {
                 let idx = term.idx(col, row);
@@ -695,13 +1290,89 @@ impl Shell {
 
         // Bottom bar
         term.fill_row(PROMPT_ROW, COLOR_FG, COLOR_STATUS_BG);
-        let hint = "Esc back  ↑↓ scroll  PgUp/PgDn  Tab link  Enter open";
+        if let Some((idx, buf)) = &state.editing {
+            let (pre, _, post) = state
+                .forms
+                .get(*idx)
+                .map(|f| f.entry.fmt_parts())
+                .unwrap_or(("", 's', ""));
+            let line = format!("{pre}{buf}_{post}");
+            term.write_at(0, PROMPT_ROW, COLOR_FG, COLOR_STATUS_BG, &line);
+            return;
+        }
+        if let Some((prompt, buf)) = &state.prompt {
+            let label = match prompt {
+                DocPrompt::Find => "Find",
+                DocPrompt::Bookmark => "Bookmark as",
+            };
+            let line = format!("{label}: {buf}_");
+            term.write_at(0, PROMPT_ROW, COLOR_FG, COLOR_STATUS_BG, &line);
+            return;
+        }
+        let hint = if state.forms.is_empty() {
+            "Esc close  Bksp back  ↑↓ scroll  Tab link  Enter open  / find  n next  b mark"
+        } else {
+            "Esc close  Bksp back  ↑↓ scroll  Tab field  Enter/Space change  / find  b mark"
+        };
         term.write_at(0, PROMPT_ROW, COLOR_FG, COLOR_STATUS_BG, hint);
     }
 
     fn handle_key_doc_viewer(&mut self, key: &Key, term: &mut Terminal) -> bool {
         let mut open_doc: Option<String> = None;
         let mut open_action: Option<String> = None;
+        let mut open_form: Option<usize> = None;
+        let mut open_tree: Option<(usize, Option<bool>)> = None;
+
+        if self
+            .doc_viewer
+            .as_ref()
+            .is_some_and(|state| state.editing.is_some())
+        {
+            return self.handle_key_doc_form_edit(key, term);
+        }
+        if self
+            .doc_viewer
+            .as_ref()
+            .is_some_and(|state| state.prompt.is_some())
+        {
+            return self.handle_key_doc_prompt(key, term);
+        }
+        match key {
+            Key::Character(s) if s == "/" => {
+                if let Some(state) = self.doc_viewer.as_mut() {
+                    state.prompt = Some((DocPrompt::Find, String::new()));
+                }
+                self.render_doc_viewer(term);
+                return true;
+            }
+            Key::Character(s) if s == "b" => {
+                if let Some(state) = self.doc_viewer.as_mut() {
+                    let name = Self::default_bookmark_name(&state.spec);
+                    state.prompt = Some((DocPrompt::Bookmark, name));
+                }
+                self.render_doc_viewer(term);
+                return true;
+            }
+            Key::Character(s) if s == "B" => {
+                self.cmd_bookmarks(&[], term);
+                return true;
+            }
+            Key::Named(NamedKey::Backspace | NamedKey::BrowserBack) => {
+                return self.doc_go(false, term);
+            }
+            Key::Named(NamedKey::BrowserForward) => return self.doc_go(true, term),
+            Key::Character(s) if s == "n" || s == "N" => {
+                self.doc_find(None, s == "N");
+                self.render_doc_viewer(term);
+                return true;
+            }
+            Key::Named(NamedKey::F3) => {
+                self.doc_find(None, false);
+                self.render_doc_viewer(term);
+                return true;
+            }
+            _ => {}
+        }
 
         if let Some(state) = self.doc_viewer.as_mut() {
             let content_rows = Self::doc_view_rows().max(1);
@@ -709,10 +1380,20 @@ impl Shell {
 
             match key {
                 Key::Named(NamedKey::Escape) => {
+                    if std::mem::take(&mut state.modified) {
+                        state.msg = "unsaved changes: Esc again discards them".to_string();
+                        self.render_doc_viewer(term);
+                        return true;
+                    }
                     self.doc_viewer = None;
                     self.draw_prompt(term);
                     return true;
                 }
+                Key::Character(s) if s == "s" && state.modified => {
+                    self.save_doc_viewer();
+                    self.render_doc_viewer(term);
+                    return true;
+                }
                 Key::Named(NamedKey::ArrowUp) => {
                     state.scroll = state.scroll.saturating_sub(1);
                 }
@@ -756,6 +1437,8 @@ impl Shell {
                                 DocLinkTarget::Action(action) => {
                                     open_action = Some(action.clone());
                                 }
+                                DocLinkTarget::Form(idx) => open_form = Some(*idx),
+                                DocLinkTarget::Tree(idx) => open_tree = Some((*idx, None)),
                             }
                         }
                     } else if let Some(sel) = state.selected_sprite {
@@ -764,10 +1447,46 @@ impl Shell {
                         }
                     }
                 }
+                Key::Named(NamedKey::Space) => {
+                    match state
+                        .selected_link
+                        .and_then(|sel| state.links.get(sel))
+                        .map(|link| &link.target)
+                    {
+                        Some(DocLinkTarget::Form(idx)) => open_form = Some(*idx),
+                        Some(DocLinkTarget::Tree(idx)) => open_tree = Some((*idx, None)),
+                        _ => return false,
+                    }
+                }
+                Key::Named(NamedKey::ArrowLeft | NamedKey::ArrowRight) => {
+                    let Some(DocLinkTarget::Tree(idx)) = state
+                        .selected_link
+                        .and_then(|sel| state.links.get(sel))
+                        .map(|link| &link.target)
+                    else {
+                        return false;
+                    };
+                    let collapse = matches!(key, Key::Named(NamedKey::ArrowLeft));
+                    open_tree = Some((*idx, Some(collapse)));
+                }
+                Key::Character(s) if (s == "+" || s == "-") && !state.trees.is_empty() => {
+                    self.set_doc_trees(None, Some(s == "-"), term);
+                    return true;
+                }
                 _ => return false,
             }
         }
 
+        if let Some((idx, collapsed)) = open_tree {
+            self.set_doc_trees(Some(idx), collapsed, term);
+            return true;
+        }
+
+        if let Some(idx) = open_form {
+            self.activate_doc_form(idx, term);
+            return true;
+        }
+
         if let Some(action) = open_action {
             match self.exec_doldoc_action(&action, term) {
                 DocActionOutcome::Unsupported => {
@@ -801,8 +1520,6 @@ impl Shell {
     }
 
 	    fn exec_doldoc_action(&mut self, action: &str, term: &mut Terminal) -> DocActionOutcome {
-	        use fmt::Write as _;
-
 	        let action = action.trim();
 		        if let Some(url) = action.strip_prefix("templelinux:browse:") {
 		            let url = url.trim();
@@ -855,73 +1572,158 @@ impl Shell {
 	            return DocActionOutcome::KeepDocViewer;
 	        }
 
-	        fn split_macro_stmts(s: &str) -> Vec<String> {
-	            let mut out: Vec<String> = Vec::new();
-	            let mut buf = String::new();
-	            let mut in_str = false;
-	            let mut escaped = false;
-            for ch in s.chars() {
-                if escaped {
-                    buf.push(ch);
-                    escaped = false;
-                    continue;
+        self.run_doc_macro(action, term)
+    }
+
+    /// Runs a `$MA$` macro or `$LK$` action in a temple-hc session hosted by the shell. The
+    /// session starts in the shell's cwd with the open document as its `__FILE__`, and reaches
+    /// back through `protocol::MSG_SHELL_REQUEST` (see `handle_app_request`). It only gets a
+    /// window if it draws.
+    fn run_doc_macro(&mut self, action: &str, term: &mut Terminal) -> DocActionOutcome {
+        let action = action.trim();
+        if action.is_empty() {
+            return DocActionOutcome::Unsupported;
+        }
+        let Ok(sock) = std::env::var("TEMPLE_SOCK") else {
+            if let Some(state) = self.doc_viewer.as_mut() {
+                state.msg = "macro: TEMPLE_SOCK is not set".to_string();
+            }
+            return DocActionOutcome::KeepDocViewer;
+        };
+
+        let program = std::env::current_exe()
+            .ok()
+            .map(|exe| exe.with_file_name("temple-hc"))
+            .filter(|p| p.exists())
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|| "temple-hc".to_string());
+        let mut cmd = std::process::Command::new(&program);
+        cmd.arg("--macro").arg(action);
+        if let Some(host) = self.doc_viewer.as_ref().and_then(|s| s.host.as_ref()) {
+            cmd.arg("--doc").arg(host);
+        }
+        for (k, v) in &self.vars {
+            cmd.env(k, v);
+        }
+        cmd.env("TEMPLE_SOCK", &sock)
+            .env("TEMPLE_ROOT", self.root_dir.as_os_str())
+            .current_dir(self.cwd.to_host_path(&self.root_dir));
+        if let Some(root) = discover_templeos_root() {
+            cmd.env("TEMPLEOS_ROOT", root.as_os_str());
+        }
+
+        self.reap_macro_children(term);
+        let msg = match cmd.spawn() {
+            Ok(child) => {
+                let title = action.lines().next().unwrap_or(action).to_string();
+                self.macro_children.push(MacroChild {
+                    child,
+                    title,
+                    connected: false,
+                });
+                String::new()
+            }
+            Err(err) => format!("macro: {program}: {err}"),
+        };
+        match self.doc_viewer.as_mut() {
+            Some(state) => state.msg = msg,
+            None if !msg.is_empty() => {
+                use fmt::Write as _;
+                let _ = writeln!(term, "{msg}");
+            }
+            None => {}
+        }
+        DocActionOutcome::KeepDocViewer
+    }
+
+    /// The window title of the macro session that just connected from `pid`, if it is one.
+    fn take_macro_window(&mut self, pid: Option<u32>) -> Option<String> {
+        let pid = pid?;
+        let child = self
+            .macro_children
+            .iter_mut()
+            .find(|c| !c.connected && c.child.id() == pid)?;
+        child.connected = true;
+        Some(child.title.clone())
+    }
+
+    /// Whether a macro session was started but has not connected yet.
+    fn macro_children_pending(&self) -> bool {
+        self.macro_children.iter().any(|c| !c.connected)
+    }
+
+    /// Forgets finished macro sessions. One that exits without ever connecting couldn't report
+    /// its own failure, so the shell does.
+    fn reap_macro_children(&mut self, term: &mut Terminal) {
+        let mut failed = Vec::new();
+        self.macro_children
+            .retain_mut(|c| match c.child.try_wait() {
+                Ok(None) => true,
+                Ok(Some(status)) => {
+                    if !c.connected {
+                        failed.push(format!("macro: temple-hc exited before connecting ({status})"));
+                    }
+                    false
                 }
-                if in_str && ch == '\\' {
-                    buf.push(ch);
-                    escaped = true;
-                    continue;
+                Err(err) => {
+                    failed.push(format!("macro: {err}"));
+                    false
                 }
-                if ch == '"' {
-                    in_str = !in_str;
-                    buf.push(ch);
-                    continue;
+            });
+        for msg in failed {
+            self.show_app_message(&msg, term);
+        }
+    }
+
+    /// Acts on a `protocol::MSG_SHELL_REQUEST` line from a HolyC session, mostly DolDoc macros:
+    /// `doc <spec>`, `dir <spec>`, `cd <spec>`, `keymap`, `print <text>`, `error <text>`. (`show`,
+    /// sent by a hidden session that waits for input, is the window manager's.)
+    fn handle_app_request(&mut self, id: AppId, req: &str, term: &mut Terminal) {
+        use fmt::Write as _;
+
+        let (verb, arg) = req.split_once(' ').unwrap_or((req, ""));
+        let arg = arg.trim();
+        match verb {
+            "doc" => {
+                if !self.try_show_doc(arg, term) {
+                    self.show_app_message(&format!("not found: {arg}"), term);
                 }
-                if ch == ';' && !in_str {
-                    let stmt = buf.trim();
-                    if !stmt.is_empty() {
-                        out.push(stmt.to_string());
+            }
+            "dir" => self.open_dir_doc(arg, term),
+            "keymap" => self.open_keymap_doc(term),
+            "cd" => {
+                // The vendored tree is read-only and outside the shell's namespace.
+                if !arg.starts_with("::/") {
+                    let next = TemplePath::root().resolve(arg);
+                    if next.to_host_path(&self.root_dir).is_dir() {
+                        self.cwd = next;
                     }
-                    buf.clear();
-                    continue;
                 }
-                buf.push(ch);
             }
-            let stmt = buf.trim();
-            if !stmt.is_empty() {
-                out.push(stmt.to_string());
+            "print" => self.show_app_message(arg, term),
+            "error" => self.show_app_message(&format!("macro: {arg}"), term),
+            _ => {
+                let _ = writeln!(term, "app {id}: unknown shell request: {verb}");
             }
-            out
         }
+    }
 
-        fn extract_first_string_lit(s: &str) -> Option<String> {
-            let start = s.find('"')? + 1;
-            let mut out = String::new();
-            let mut escaped = false;
-            for ch in s[start..].chars() {
-                if escaped {
-                    out.push(match ch {
-                        'n' => '\n',
-                        'r' => '\r',
-                        't' => '\t',
-                        '"' => '"',
-                        '\\' => '\\',
-                        other => other,
-                    });
-                    escaped = false;
-                    continue;
-                }
-                if ch == '\\' {
-                    escaped = true;
-                    continue;
-                }
-                if ch == '"' {
-                    return Some(out);
-                }
-                out.push(ch);
-            }
-            Some(out)
+    /// Macro output: the doc viewer's status line while it is open, the terminal otherwise.
+    fn show_app_message(&mut self, text: &str, term: &mut Terminal) {
+        use fmt::Write as _;
+
+        if let Some(state) = self.doc_viewer.as_mut() {
+            state.msg = text.lines().last().unwrap_or_default().to_string();
+            self.render_doc_viewer(term);
+        } else {
+            let _ = writeln!(term, "{text}");
+            self.draw_prompt(term);
         }
+    }
 
+    /// Shows a directory as a DolDoc listing: subdirectories are `Cd(...);Dir;` macros, files
+    /// are links.
+    fn open_dir_doc(&mut self, spec: &str, term: &mut Terminal) {
         fn encode_attr_value(s: &str) -> String {
             let mut out = String::new();
             for ch in s.chars() {
@@ -1016,147 +1818,32 @@ impl Shell {
             out
         }
 
-        fn extract_include_spec(s: &str) -> Option<String> {
-            let s = s.trim_start();
-            let idx = s.find("#include")?;
-            let mut rest = s[idx + "#include".len()..].trim_start();
-            if !rest.starts_with('"') {
-                return None;
-            }
-            rest = &rest[1..];
-
-            let mut out = String::new();
-            let mut escaped = false;
-            for ch in rest.chars() {
-                if escaped {
-                    out.push(match ch {
-                        'n' => '\n',
-                        'r' => '\r',
-                        't' => '\t',
-                        '"' => '"',
-                        '\\' => '\\',
-                        other => other,
-                    });
-                    escaped = false;
-                    continue;
-                }
-                if ch == '\\' {
-                    escaped = true;
-                    continue;
-                }
-                if ch == '"' {
-                    return Some(out);
-                }
-                out.push(ch);
-            }
-            Some(out)
-        }
-
-        let action = action.trim();
-        if action.is_empty() {
-            return DocActionOutcome::Unsupported;
-        }
-
-        if let Some(spec) = extract_include_spec(action) {
-            let spec = spec.trim();
-            if spec.is_empty() {
-                return DocActionOutcome::Unsupported;
-            }
-
-            let _ = writeln!(term, "Launching: {spec}");
-            let spec_owned = spec.to_string();
-            self.cmd_tapp(&["hc", spec_owned.as_str()], term);
-            return DocActionOutcome::CloseDocViewer;
-        }
-
-        // Minimal, safe, deny-by-default `LM="..."` macro support.
-        //
-        // TempleOS docs often use macros like:
-        //   LM="Cd(\"::/Demo/Games\");Dir;View;\n"
-        // We interpret a small subset to open a read-only directory listing view.
-        let stmts = split_macro_stmts(action);
-        let mut cd_spec: Option<String> = None;
-        let mut want_dir = false;
-        let mut want_view = false;
-        let mut want_keymap = false;
-        let mut saw_infile = false;
-
-        for stmt in &stmts {
-            let s = stmt.trim();
-            if s.is_empty() {
-                continue;
-            }
-            let s = s.strip_suffix("()").unwrap_or(s).trim();
-
-            if s == "Dir" {
-                want_dir = true;
-                continue;
-            }
-            if s == "View" {
-                want_view = true;
-                continue;
-            }
-            if s == "KeyMap" {
-                want_keymap = true;
-                continue;
-            }
-            if s.starts_with("InFile") {
-                saw_infile = true;
-                continue;
-            }
-            if s.starts_with("Cd") {
-                if let Some(spec) = extract_first_string_lit(s) {
-                    cd_spec = Some(spec);
-                } else {
-                    cd_spec = Some("::/".to_string());
-                }
-                continue;
-            }
-        }
-
-        if want_keymap {
-            self.open_keymap_doc(term);
-            return DocActionOutcome::KeepDocViewer;
+        let spec = if spec.is_empty() { "::/" } else { spec };
+        let Some(host_dir) = spec_to_host_dir(spec, &self.root_dir, &self.cwd) else {
+            self.show_app_message("Dir: TempleOS tree not found.", term);
+            return;
+        };
+        if !host_dir.is_dir() {
+            self.show_app_message(&format!("Dir: not a directory: {spec}"), term);
+            return;
         }
 
-        // If the macro looks like a "directory action", open a directory listing view.
-        // Also allow `Cd("...")` alone (often paired with `InFile`) to at least navigate.
-        if want_view || want_dir || saw_infile || cd_spec.is_some() {
-            let spec = cd_spec
-                .as_deref()
-                .map(str::trim)
-                .filter(|s| !s.is_empty())
-                .unwrap_or("::/");
-
-            let Some(host_dir) = spec_to_host_dir(spec, &self.root_dir, &self.cwd) else {
-                let _ = writeln!(term, "DolDoc action: TempleOS tree not found.");
-                return DocActionOutcome::CloseDocViewer;
-            };
-            if !host_dir.is_dir() {
-                let _ = writeln!(term, "DolDoc action: not a directory: {spec}");
-                return DocActionOutcome::CloseDocViewer;
-            }
-
-            let mut entries: Vec<(String, bool)> = Vec::new();
-            if let Ok(rd) = std::fs::read_dir(&host_dir) {
-                for entry in rd.flatten() {
-                    let name = entry.file_name().to_string_lossy().into_owned();
-                    let is_dir = entry.file_type().ok().is_some_and(|ft| ft.is_dir());
-                    entries.push((name, is_dir));
-                }
+        let mut entries: Vec<(String, bool)> = Vec::new();
+        if let Ok(rd) = std::fs::read_dir(&host_dir) {
+            for entry in rd.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let is_dir = entry.file_type().ok().is_some_and(|ft| ft.is_dir());
+                entries.push((name, is_dir));
             }
-            entries.sort_by(|(a_name, a_dir), (b_name, b_dir)| match b_dir.cmp(a_dir) {
-                std::cmp::Ordering::Equal => a_name.cmp(b_name),
-                other => other,
-            });
-
-            let doc = build_dir_doc(spec, &entries);
-            let bins: std::collections::BTreeMap<u32, Vec<u8>> = std::collections::BTreeMap::new();
-            self.open_doc_viewer(spec.to_string(), DocKind::DolDoc, &doc, bins, false, None, term);
-            return DocActionOutcome::KeepDocViewer;
         }
+        entries.sort_by(|(a_name, a_dir), (b_name, b_dir)| match b_dir.cmp(a_dir) {
+            std::cmp::Ordering::Equal => a_name.cmp(b_name),
+            other => other,
+        });
 
-        DocActionOutcome::Unsupported
+        let doc = build_dir_doc(spec, &entries);
+        let bins: std::collections::BTreeMap<u32, Vec<u8>> = std::collections::BTreeMap::new();
+        self.open_doc_viewer(spec.to_string(), DocKind::DolDoc, &doc, bins, false, None, term);
     }
 
     fn doc_wheel(&mut self, delta_lines: isize, term: &mut Terminal) -> bool {
@@ -1206,6 +1893,17 @@ impl Shell {
             if let Some(hit) = hit_link {
                 state.selected_link = Some(hit);
                 state.selected_sprite = None;
+                match state.links[hit].target {
+                    DocLinkTarget::Form(idx) => {
+                        self.activate_doc_form(idx, term);
+                        return None;
+                    }
+                    DocLinkTarget::Tree(idx) => {
+                        self.set_doc_trees(Some(idx), None, term);
+                        return None;
+                    }
+                    _ => {}
+                }
                 Some(hit)
             } else {
                 let hit_sprite = state
@@ -1808,6 +2506,8 @@ impl Shell {
             "mkdir" => self.cmd_mkdir(&args, term),
             "touch" => self.cmd_touch(&args, term),
             "grep" => self.cmd_grep(&args, term),
+            "search" => self.cmd_search(&args, term),
+            "bookmarks" => self.cmd_bookmarks(&args, term),
             "find" => self.cmd_find(&args, term),
             "head" => self.cmd_head(&args, term),
             "tail" => self.cmd_tail(&args, term),
@@ -1817,6 +2517,7 @@ impl Shell {
             "clip" => self.cmd_clip(line, &args, term),
             "env" => self.cmd_env(&args, term),
             "set" => self.cmd_set(&args, term),
+            "font" => self.cmd_font(&args, term),
 	            "ws" => self.cmd_ws(&args, term),
 	            "run" => self.cmd_run(&args, term),
 	            "hc" | "holyc" => {
@@ -1891,6 +2592,8 @@ impl Shell {
             let _ = writeln!(term, "");
             let _ = writeln!(term, "  Text:");
             let _ = writeln!(term, "    grep <needle> <path> Search file");
+            let _ = writeln!(term, "    search <terms>       Search all docs and sources");
+            let _ = writeln!(term, "    bookmarks            Doc viewer bookmarks (rm <name>)");
             let _ = writeln!(term, "    find [path] [s]      Find files (substring)");
             let _ = writeln!(term, "    head [-n N] <path>   First lines");
             let _ = writeln!(term, "    tail [-n N] <path>   Last lines");
@@ -1912,6 +2615,7 @@ impl Shell {
             let _ = writeln!(term, "  Misc:");
             let _ = writeln!(term, "    env [name]           Show TempleShell vars");
             let _ = writeln!(term, "    set <k=v>            Set TempleShell var");
+            let _ = writeln!(term, "    font [name]          Terminal/app font (font scale <n>)");
             let _ = writeln!(term, "    screenshot [path]    Save a PNG screenshot (alias: shot)");
             let _ = writeln!(term, "    shutdown             Exit TempleShell (alias: exit)");
             let _ = writeln!(term, "");
@@ -2007,6 +2711,17 @@ impl Shell {
             "grep" => {
                 let _ = writeln!(term, "grep <needle> <path>");
             }
+            "search" => {
+                let _ = writeln!(term, "search <terms>");
+                let _ = writeln!(term, "  Ranked full-text search over the .DD/.HC/.HH files of the");
+                let _ = writeln!(term, "  TempleOS tree and TEMPLE_ROOT (index kept in /Cfg/DocIndex.txt).");
+                let _ = writeln!(term, "  Results open in the doc viewer; / finds in a doc, n/N next/prev.");
+            }
+            "bookmarks" => {
+                let _ = writeln!(term, "bookmarks [rm <name>]");
+                let _ = writeln!(term, "  Lists doc viewer bookmarks (kept in /Cfg/Bookmarks.txt) as links.");
+                let _ = writeln!(term, "  In the doc viewer: b bookmarks the current spot, B opens this list.");
+            }
             "find" => {
                 let _ = writeln!(term, "find [path] [name-substring]");
             }
@@ -2039,6 +2754,11 @@ impl Shell {
                 let _ = writeln!(term, "set <name> <value...>");
                 let _ = writeln!(term, "set <name> (clears)");
             }
+            "font" => {
+                let _ = writeln!(term, "font (list fonts in /Fonts)");
+                let _ = writeln!(term, "font <name>  (std: system font)");
+                let _ = writeln!(term, "font scale <1-{}>", FONT_MAX_ZOOM);
+            }
             "open" => {
                 let _ = writeln!(term, "open <path>");
             }
@@ -2090,7 +2810,7 @@ impl Shell {
 	            }
 	            "edit" => {
 	                let _ = writeln!(term, "edit <path>");
-	                let _ = writeln!(term, "hotkeys: Ctrl+S save  Ctrl+Q quit  F5 run/check  F1 help");
+	                let _ = writeln!(term, "hotkeys: Ctrl+S save (Ctrl+Shift+S all)  Ctrl+Q quit  F5 run/check  F1 help");
 	            }
 	            "files" | "fm" => {
 	                let _ = writeln!(term, "files [dir]");
@@ -2130,6 +2850,7 @@ impl Shell {
 - `Left`/`Right` move cursor
 - `Home`/`End` line start/end
 - `Backspace`/`Delete` delete char
+- Drag or `Shift`+arrows select output (copied on release / `Ctrl+C`)
 
 ## File browser (`files` / `apps`)
 - `Tab` switch Files/Apps
@@ -2147,6 +2868,12 @@ impl Shell {
 - `Home`/`End` jump top/bottom
 - `Tab` next link
 - `Enter` open link
+- `/` find, `n`/`N` (or `F3`) next/previous match
+- `Enter`/`Space`/click on a `+]`/`-]` tree toggles it; `Left`/`Right` close/open; `-`/`+` all
+- `Backspace` (or `Alt+Left`, mouse Back) previous page; `Alt+Right` (mouse Forward) next
+- `b` bookmark this spot; `B` list bookmarks
+- `Enter`/`Space` change a form entry; `s` saves the changes (TempleOS docs are read-only)
+- Drag or `Shift`+arrows select text (copied on release / `Ctrl+C`)
 
 ## Window manager
 - Click window to focus; drag title to move
@@ -2408,294 +3135,29 @@ impl Shell {
                     let file = file.trim_start_matches("::").trim_start_matches('/');
                     return (format!("/{file}"), jump);
                 }
-                if file.starts_with('/') {
-                    return (format!("::{file}"), jump);
-                }
-                if file.contains('/') {
-                    return (format!("::/{file}"), jump);
-                }
-                (t.to_string(), None)
-            }
-            // Manual-node style links: best-effort jump to the symbol definition.
-            "MN" | "HI" => self
-                .resolve_manual_node_target(rest)
-                .map(|(spec, jump)| (spec, Some(jump)))
-                .unwrap_or((t.to_string(), None)),
-            _ => (t.to_string(), None),
-        }
-    }
-
-    fn try_show_doc(&mut self, topic: &str, term: &mut Terminal) -> bool {
-        const MAX_BYTES: u64 = 2 * 1024 * 1024;
-
-        fn parse_doc_blob(buf: &[u8]) -> (String, std::collections::BTreeMap<u32, Vec<u8>>) {
-            fn read_u32_le(buf: &[u8], off: usize) -> Option<u32> {
-                let b = buf.get(off..off + 4)?;
-                Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
-            }
-
-            fn extract_expected_bins(text: &str) -> std::collections::BTreeSet<u32> {
-                let mut out = std::collections::BTreeSet::new();
-                let bytes = text.as_bytes();
-                let mut i = 0usize;
-                while i + 3 <= bytes.len() {
-                    let b0 = bytes[i];
-                    let b1 = bytes[i + 1];
-                    if (b0 == b'B' || b0 == b'b') && (b1 == b'I' || b1 == b'i') && bytes[i + 2] == b'='
-                    {
-                        let mut j = i + 3;
-                        let mut v: u32 = 0;
-                        let mut any = false;
-                        while j < bytes.len() && bytes[j].is_ascii_digit() {
-                            any = true;
-                            v = v.saturating_mul(10).saturating_add((bytes[j] - b'0') as u32);
-                            j += 1;
-                        }
-                        if any && v != 0 {
-                            out.insert(v);
-                        }
-                        i = j;
-                        continue;
-                    }
-                    i += 1;
-                }
-                out
-            }
-
-            fn parse_doc_bins(
-                bin_blob: &[u8],
-                expected_bins: &std::collections::BTreeSet<u32>,
-            ) -> std::collections::BTreeMap<u32, Vec<u8>> {
-                const BIN_HEADER_LEN: usize = 16;
-                const MAX_DATA_LEN: usize = 256 * 1024;
-
-                fn read_i32_le(buf: &[u8], off: usize) -> Option<i32> {
-                    let b = buf.get(off..off + 4)?;
-                    Some(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
-                }
-
-                fn ceil_to_multiple(v: i32, step: i32) -> i32 {
-                    if step <= 0 {
-                        return v;
-                    }
-                    ((v + step - 1) / step) * step
-                }
-
-                fn repair_sprite_blob(prefix: &[u8]) -> Option<Vec<u8>> {
-                    if prefix.is_empty() {
-                        return None;
-                    }
-
-                    // --- Happy path: parse a sprite starting at offset 0. -------------------
-                    //
-                    // We intentionally accept trailing junk after the first `SPT_END` and truncate,
-                    // because some vendored `.DD` files include extra (non-zero) padding after the
-                    // sprite. TempleOS walks sprites until `SPT_END` and ignores any tail bytes.
-                    if let Some(end) = temple_rt::sprite::sprite_parse_end_at_start(prefix) {
-                        if end > 1 {
-                            return Some(prefix[..end].to_vec());
-                        }
-                    }
-
-                    // --- Small common corruptions / quirks ----------------------------------
-
-                    // 1) Off-by-one: trailing `SPT_END` becomes `0xff`. Fix and retry.
-                    if prefix.last() == Some(&0xff) {
-                        let mut fixed = prefix.to_vec();
-                        if let Some(last) = fixed.last_mut() {
-                            *last = 0;
-                        }
-                        if let Some(end) = temple_rt::sprite::sprite_parse_end_at_start(&fixed) {
-                            if end > 1 {
-                                fixed.truncate(end);
-                                return Some(fixed);
-                            }
-                        }
-                    }
-
-                    // 2) Missing trailing `SPT_END`: append it and retry.
-                    {
-                        let mut fixed = prefix.to_vec();
-                        fixed.push(0);
-                        if let Some(end) = temple_rt::sprite::sprite_parse_end_at_start(&fixed) {
-                            if end > 1 {
-                                fixed.truncate(end);
-                                return Some(fixed);
-                            }
-                        }
-                    }
-
-                    // 2b) Missing/garbled `SPT_END` with trailing garbage: truncate at the last
-                    // successfully-parsed element boundary and append `SPT_END`.
-                    if let Some(last) = temple_rt::sprite::sprite_parse_last_good_prefix_len_at_start(prefix) {
-                        if last > 1 && last <= prefix.len() {
-                            let mut fixed = prefix[..last].to_vec();
-                            fixed.push(0);
-                            if temple_rt::sprite::sprite_parse_end_at_start(&fixed)
-                                == Some(fixed.len())
-                            {
-                                return Some(fixed);
-                            }
-                        }
-                    }
-
-                    // 3) Bitmap-only sprite missing `SPT_END`, with `0xff`/0 padding after the bitmap.
-                    // This shows up in some PersonalMenu icons.
-                    if prefix.first().copied().unwrap_or(0) & 0x7f == 23 {
-                        // SPT_BITMAP: type + x + y + w + h + data[stride*h]
-                        if prefix.len() >= 1 + 4 * 4 {
-                            let w = read_i32_le(prefix, 1 + 8)?;
-                            let h = read_i32_le(prefix, 1 + 12)?;
-                            if w > 0 && h > 0 && w <= 2048 && h <= 2048 {
-                                let stride = ceil_to_multiple(w, 8) as usize;
-                                let data_len = stride.saturating_mul(h as usize);
-                                let elem_len = 1usize + 4 * 4 + data_len;
-                                if elem_len <= prefix.len()
-                                    && prefix[elem_len..]
-                                        .iter()
-                                        .all(|&b| b == 0 || b == 0xff)
-                                {
-                                    let mut fixed = prefix[..elem_len].to_vec();
-                                    fixed.push(0); // SPT_END
-                                    return Some(fixed);
-                                }
-                            }
-                        }
-                    }
-
-                    // 4) Missing `SPT_BITMAP` type byte: the blob starts at `x1` instead of `type`.
-                    //
-                    // Layout without the type byte:
-                    //   x1(i32), y1(i32), w(i32), h(i32), data[stride*h], [optional SPT_END]
-                    if prefix.len() >= 16 {
-                        let w = read_i32_le(prefix, 8)?;
-                        let h = read_i32_le(prefix, 12)?;
-                        if w > 0 && h > 0 && w <= 2048 && h <= 2048 {
-                            let stride = ceil_to_multiple(w, 8) as usize;
-                            let data_len = stride.saturating_mul(h as usize);
-                            let min_len = 16usize.saturating_add(data_len);
-                            if min_len <= prefix.len() {
-                                let mut fixed = Vec::with_capacity(min_len.saturating_add(2));
-                                fixed.push(23u8); // SPT_BITMAP
-                                fixed.extend_from_slice(&prefix[..min_len]);
-                                fixed.push(0); // SPT_END
-
-                                if temple_rt::sprite::sprite_parse_end_at_start(&fixed)
-                                    == Some(fixed.len())
-                                {
-                                    return Some(fixed);
-                                }
-                            }
-                        }
-                    }
-
-                    None
-                }
-
-                let mut bins: std::collections::BTreeMap<u32, Vec<u8>> =
-                    std::collections::BTreeMap::new();
-                if bin_blob.len() < BIN_HEADER_LEN + 1 {
-                    return bins;
-                }
-
-                if expected_bins.is_empty() {
-                    return bins;
-                }
-
-                // Some TempleOS docs have a binary tail that isn't cleanly parseable by walking
-                // `CDocBin.size` sequentially (sizes can be corrupted, padding can be non-zero, etc).
-                //
-                // Instead, scan the bin tail for plausible `CDocBin` headers and recover the best
-                // sprite for each referenced `BI=<n>` number.
-                let scan_end = bin_blob.len().saturating_sub(BIN_HEADER_LEN + 1);
-                for off in 0..=scan_end {
-                    let Some(num) = read_u32_le(bin_blob, off) else {
-                        continue;
-                    };
-                    if !expected_bins.contains(&num) {
-                        continue;
-                    }
-
-                    let Some(flags) = read_u32_le(bin_blob, off + 4) else {
-                        continue;
-                    };
-                    if flags != 0 {
-                        continue;
-                    }
-
-                    // Records are typically preceded by the previous sprite's `SPT_END` (0).
-                    // Some vendored files have 0xff here due to off-by-one corruption.
-                    if off != 0 && !matches!(bin_blob.get(off - 1), Some(0 | 0xff)) {
-                        continue;
-                    }
-
-                    let data_start = off.saturating_add(BIN_HEADER_LEN);
-                    if data_start >= bin_blob.len() {
-                        continue;
-                    }
-
-                    let mut best: Option<Vec<u8>> = None;
-
-                    // Try the stored `size` field first when it looks sane.
-                    if let Some(size_raw) = read_u32_le(bin_blob, off + 8) {
-                        let size = size_raw as usize;
-                        if size != 0 && size <= MAX_DATA_LEN && data_start + size <= bin_blob.len()
-                        {
-                            let raw = &bin_blob[data_start..data_start + size];
-                            best = repair_sprite_blob(raw);
-                        }
-                    }
-
-                    // Attempt to repair from a bounded prefix too (ignore `size`).
-                    //
-                    // Prefer the longer recovered sprite, because some vendored docs (notably
-                    // `::/PersonalMenu.DD`) have corrupted `CDocBin.size` fields that truncate the
-                    // stored sprite data mid-element.
-                    {
-                        let end = (data_start + MAX_DATA_LEN).min(bin_blob.len());
-                        let raw = &bin_blob[data_start..end];
-                        if let Some(candidate) = repair_sprite_blob(raw) {
-                            match &best {
-                                Some(prev) if prev.len() >= candidate.len() => {}
-                                _ => best = Some(candidate),
-                            }
-                        }
-                    }
-
-                    let Some(best) = best else {
-                        continue;
-                    };
-
-                    if best.len() <= 1 {
-                        continue;
-                    }
-
-                    match bins.get(&num) {
-                        Some(prev) if prev.len() >= best.len() => {}
-                        _ => {
-                            bins.insert(num, best);
-                        }
-                    }
-                }
-
-                bins
+                if file.starts_with('/') {
+                    return (format!("::{file}"), jump);
+                }
+                if file.contains('/') {
+                    return (format!("::/{file}"), jump);
+                }
+                (t.to_string(), None)
             }
+            // Manual-node style links: best-effort jump to the symbol definition.
+            "MN" | "HI" => self
+                .resolve_manual_node_target(rest)
+                .map(|(spec, jump)| (spec, Some(jump)))
+                .unwrap_or((t.to_string(), None)),
+            _ => (t.to_string(), None),
+        }
+    }
 
-            let cutoff = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
-            let text_bytes = &buf[..cutoff];
-            let text = std::str::from_utf8(text_bytes)
-                .map(|s| s.to_string())
-                .unwrap_or_else(|_| assets::decode_cp437_bytes(text_bytes));
-
-            if cutoff >= buf.len() {
-                return (text, std::collections::BTreeMap::new());
-            }
+    fn try_show_doc(&mut self, topic: &str, term: &mut Terminal) -> bool {
+        const MAX_BYTES: u64 = 2 * 1024 * 1024;
 
-            let expected_bins = extract_expected_bins(&text);
-            let bins = parse_doc_bins(&buf[cutoff + 1..], &expected_bins);
-            (text, bins)
+        if let Some(name) = topic.trim().strip_prefix("BM:") {
+            return self.open_doc_bookmark(name, term);
         }
-
         let (topic, jump) = self.normalize_doc_target(topic);
         let topic = topic.trim();
         let jump = jump.as_deref();
@@ -2703,15 +3165,20 @@ impl Shell {
             return false;
         }
 
-        if let Some(state) = self.doc_viewer.as_mut() {
-            if let Some(&line) = state.anchors.get(topic) {
+        if let Some(state) = self.doc_viewer.as_ref()
+            && let Some(&line) = state.anchors.get(topic)
+        {
+            // Jumps within the page go in the history too, so Back returns to the link.
+            let prev = state.clone();
+            self.push_doc_history(prev);
+            if let Some(state) = self.doc_viewer.as_mut() {
                 let content_rows = Self::doc_view_rows().max(1);
                 let max_scroll = state.lines.len().saturating_sub(content_rows);
                 state.scroll = line.min(max_scroll);
                 state.msg = "jump".to_string();
-                self.render_doc_viewer(term);
-                return true;
             }
+            self.render_doc_viewer(term);
+            return true;
         }
 
         let mut candidates: Vec<TemplePath> = Vec::new();
@@ -2737,7 +3204,7 @@ impl Shell {
             if file.take(MAX_BYTES).read_to_end(&mut buf).is_err() {
                 continue;
             }
-            let (text, bins) = parse_doc_blob(&buf);
+            let (text, bins) = temple_rt::doldoc::parse_doc_blob(&buf);
 
             let kind = if host
                 .extension()
@@ -2757,6 +3224,11 @@ impl Shell {
                 jump,
                 term,
             );
+            if let Some(state) = self.doc_viewer.as_mut()
+                && meta_len <= MAX_BYTES
+            {
+                state.host = Some(host);
+            }
             return true;
         }
 
@@ -2802,7 +3274,7 @@ impl Shell {
             if file.take(MAX_BYTES).read_to_end(&mut buf).is_err() {
                 continue;
             }
-            let (text, bins) = parse_doc_blob(&buf);
+            let (text, bins) = temple_rt::doldoc::parse_doc_blob(&buf);
 
             let kind = if host
                 .extension()
@@ -2814,6 +3286,11 @@ impl Shell {
                 DocKind::PlainText
             };
             self.open_doc_viewer(spec.clone(), kind, &text, bins, meta_len > MAX_BYTES, jump, term);
+            if let Some(state) = self.doc_viewer.as_mut()
+                && meta_len <= MAX_BYTES
+            {
+                state.host = Some(host);
+            }
             return true;
         }
 
@@ -2911,745 +3388,6 @@ impl Shell {
         term.set_colors(saved_fg, saved_bg);
     }
 
-    fn render_doldoc(
-        &self,
-        text: &str,
-        bins: &std::collections::BTreeMap<u32, Vec<u8>>,
-        term: &mut Terminal,
-    ) -> DolDocMeta {
-        use fmt::Write as _;
-
-        let default_fg = term.fg;
-        let default_bg = term.bg;
-        let mut fg = default_fg;
-        let mut bg = default_bg;
-        let mut indent_cols: i32 = 0;
-        let mut meta = DolDocMeta::default();
-
-        let mut bk_saved_bg: Option<u8> = None;
-        let mut iv_saved: Option<(u8, u8)> = None;
-        let mut hl_saved_fg: Option<u8> = None;
-        let mut ul_saved_fg: Option<u8> = None;
-
-        fn write_text_chunk(term: &mut Terminal, chunk: &str, fg: u8, bg: u8, indent_cols: i32) {
-            term.set_colors(fg, bg);
-
-            for line in chunk.split_inclusive('\n') {
-                let (mut body, has_nl) = line
-                    .strip_suffix('\n')
-                    .map(|s| (s, true))
-                    .unwrap_or((line, false));
-
-                // TempleOS docs commonly embed "//" comments in the source that are not meant
-                // to display. Be conservative and only treat it as a comment when preceded
-                // by whitespace (to avoid "http://").
-                if let Some(pos) = body.find("//") {
-                    if pos == 0
-                        || body
-                            .as_bytes()
-                            .get(pos.saturating_sub(1))
-                            .is_some_and(|b| b.is_ascii_whitespace())
-                    {
-                        body = body[..pos].trim_end();
-                    }
-                }
-
-                for ch in body.chars() {
-                    if term.cursor_col == 0 && ch != '\n' && indent_cols > 0 {
-                        for _ in 0..(indent_cols as usize).min(TERM_COLS as usize) {
-                            term.put_char(' ');
-                        }
-                    }
-                    term.put_char(ch);
-                }
-
-                if has_nl {
-                    term.put_char('\n');
-                }
-            }
-        }
-
-        fn parse_quoted_args(cmd: &str, max: usize) -> Vec<String> {
-            let mut out = Vec::new();
-            if max == 0 {
-                return out;
-            }
-
-            let mut chars = cmd.chars().peekable();
-            while let Some(ch) = chars.next() {
-                if ch != '"' {
-                    continue;
-                }
-
-                let mut s = String::new();
-                let mut escaped = false;
-                while let Some(c) = chars.next() {
-                    if escaped {
-                        s.push(match c {
-                            'n' => '\n',
-                            'r' => '\r',
-                            't' => '\t',
-                            '"' => '"',
-                            '\\' => '\\',
-                            other => other,
-                        });
-                        escaped = false;
-                        continue;
-                    }
-                    if c == '\\' {
-                        escaped = true;
-                        continue;
-                    }
-                    if c == '"' {
-                        break;
-                    }
-                    s.push(c);
-                }
-                out.push(s);
-                if out.len() >= max {
-                    break;
-                }
-            }
-            out
-        }
-
-        fn parse_attr_quoted(cmd: &str, key: &str) -> Option<String> {
-            let needle = format!("{key}=\"");
-            let start = cmd.find(&needle)? + needle.len();
-
-            let mut out = String::new();
-            let mut escaped = false;
-            for ch in cmd[start..].chars() {
-                if escaped {
-                    out.push(match ch {
-                        'n' => '\n',
-                        'r' => '\r',
-                        't' => '\t',
-                        '"' => '"',
-                        '\\' => '\\',
-                        other => other,
-                    });
-                    escaped = false;
-                    continue;
-                }
-                if ch == '\\' {
-                    escaped = true;
-                    continue;
-                }
-                if ch == '"' {
-                    return Some(out);
-                }
-                out.push(ch);
-            }
-            Some(out)
-        }
-
-	        fn parse_kv_i32(args: &str, key: &str) -> Option<i32> {
-	            let needle = format!("{key}=");
-	            let idx = args.find(&needle)? + needle.len();
-	            let rest = &args[idx..];
-	            let end = rest
-	                .find(|c: char| c == ',' || c.is_ascii_whitespace())
-	                .unwrap_or(rest.len());
-	            rest[..end].trim().parse::<i32>().ok()
-	        }
-
-	        fn extract_first_http_url(html: &str) -> Option<String> {
-	            let start = html
-	                .find("https://")
-	                .or_else(|| html.find("http://"))?;
-	            let rest = &html[start..];
-	            let end = rest
-	                .find(|c: char| {
-	                    c == '"' || c == '\'' || c.is_ascii_whitespace() || c == '<' || c == '>'
-	                })
-	                .unwrap_or(rest.len());
-	            let url = rest[..end].trim().to_string();
-	            if url.is_empty() { None } else { Some(url) }
-	        }
-
-	        fn url_label(url: &str) -> String {
-	            let url = url.split('?').next().unwrap_or(url);
-	            let url = url.split('#').next().unwrap_or(url);
-	            let last = url.rsplit('/').next().unwrap_or(url).trim();
-	            let mut label = if last.is_empty() { url } else { last }.to_string();
-	            if label.len() > 32 {
-	                label.truncate(29);
-	                label.push_str("...");
-	            }
-	            label
-	        }
-
-	        fn write_link_chunk(
-	            term: &mut Terminal,
-	            chunk: &str,
-	            fg: u8,
-	            bg: u8,
-            indent_cols: i32,
-            target: &DocLinkTarget,
-            links: &mut Vec<DocLink>,
-        ) {
-            term.set_colors(fg, bg);
-
-            let mut cur: Option<(usize, usize, usize)> = None;
-            let flush = |links: &mut Vec<DocLink>, cur: &mut Option<(usize, usize, usize)>| {
-                if let Some((line, col_start, col_end)) = cur.take() {
-                    links.push(DocLink {
-                        line,
-                        col_start,
-                        col_end,
-                        target: target.clone(),
-                    });
-                }
-            };
-
-            for line in chunk.split_inclusive('\n') {
-                let (mut body, has_nl) = line
-                    .strip_suffix('\n')
-                    .map(|s| (s, true))
-                    .unwrap_or((line, false));
-
-                if let Some(pos) = body.find("//") {
-                    if pos == 0
-                        || body
-                            .as_bytes()
-                            .get(pos.saturating_sub(1))
-                            .is_some_and(|b| b.is_ascii_whitespace())
-                    {
-                        body = body[..pos].trim_end();
-                    }
-                }
-
-                for ch in body.chars() {
-                    if term.cursor_col == 0 && ch != '\n' && indent_cols > 0 {
-                        for _ in 0..(indent_cols as usize).min(TERM_COLS as usize) {
-                            let line_idx = term.scrollback.len() + term.cursor_row as usize;
-                            let col_idx = term.cursor_col as usize;
-                            match &mut cur {
-                                Some((cur_line, _start, end))
-                                    if *cur_line == line_idx && col_idx == *end =>
-                                {
-                                    *end += 1;
-                                }
-                                Some(_) => {
-                                    flush(links, &mut cur);
-                                    cur = Some((line_idx, col_idx, col_idx + 1));
-                                }
-                                None => cur = Some((line_idx, col_idx, col_idx + 1)),
-                            }
-                            term.put_char(' ');
-                        }
-                    }
-
-                    let line_idx = term.scrollback.len() + term.cursor_row as usize;
-                    let col_idx = term.cursor_col as usize;
-                    match &mut cur {
-                        Some((cur_line, _start, end))
-                            if *cur_line == line_idx && col_idx == *end =>
-                        {
-                            *end += 1;
-                        }
-                        Some(_) => {
-                            flush(links, &mut cur);
-                            cur = Some((line_idx, col_idx, col_idx + 1));
-                        }
-                        None => cur = Some((line_idx, col_idx, col_idx + 1)),
-                    }
-                    term.put_char(ch);
-                }
-
-                if has_nl {
-                    term.put_char('\n');
-                    flush(links, &mut cur);
-                }
-            }
-
-            flush(links, &mut cur);
-        }
-
-        fn div_ceil_i32(v: i32, step: i32) -> i32 {
-            debug_assert!(step > 0);
-            let q = v.div_euclid(step);
-            let r = v.rem_euclid(step);
-            if r == 0 { q } else { q + 1 }
-        }
-
-        fn sprite_bbox_cells(
-            anchor_line: usize,
-            anchor_col: usize,
-            bounds: temple_rt::sprite::SpriteBounds,
-        ) -> (i32, i32, i32, i32) {
-            let mut x0 = bounds.x0;
-            let mut y0 = bounds.y0;
-            let mut x1 = bounds.x1;
-            let mut y1 = bounds.y1;
-            if x1 <= x0 {
-                x0 = 0;
-                x1 = FONT_W as i32;
-            }
-            if y1 <= y0 {
-                y0 = 0;
-                y1 = FONT_H as i32;
-            }
-
-            let cell_w = FONT_W as i32;
-            let cell_h = FONT_H as i32;
-            let anchor_line = anchor_line as i32;
-            let anchor_col = anchor_col as i32;
-
-            let col0 = anchor_col.saturating_add(x0.div_euclid(cell_w));
-            let col1 = anchor_col.saturating_add(div_ceil_i32(x1, cell_w));
-            let line0 = anchor_line.saturating_add(y0.div_euclid(cell_h));
-            let line1 = anchor_line.saturating_add(div_ceil_i32(y1, cell_h));
-
-            let col1 = if col1 <= col0 { col0.saturating_add(1) } else { col1 };
-            let line1 = if line1 <= line0 { line0.saturating_add(1) } else { line1 };
-            (line0, col0, line1, col1)
-        }
-
-        let mut rest = text;
-        while let Some(start) = rest.find('$') {
-            let (before, after) = rest.split_at(start);
-            write_text_chunk(term, before, fg, bg, indent_cols);
-
-            // TempleOS DolDoc escaping:
-            // - "$$" renders a literal '$'
-            // - "$$$...$$$" renders a literal DolDoc cmd, like "$FG,RED$"
-            //   (used heavily in docs like `Doc/DolDocOverview.DD` when describing DolDoc itself).
-            if after.starts_with("$$") {
-                if after.starts_with("$$$") {
-                    if let Some(end_rel) = after[3..].find("$$$") {
-                        let inner = &after[3..3 + end_rel];
-                        write_text_chunk(term, "$", fg, bg, indent_cols);
-                        write_text_chunk(term, inner, fg, bg, indent_cols);
-                        write_text_chunk(term, "$", fg, bg, indent_cols);
-                        rest = &after[3 + end_rel + 3..];
-                        continue;
-                    }
-                }
-
-                write_text_chunk(term, "$", fg, bg, indent_cols);
-                rest = &after[2..];
-                continue;
-            }
-
-            let after = &after[1..];
-            let Some(end) = after.find('$') else {
-                // Unterminated command; render the rest literally.
-                write_text_chunk(term, "$", fg, bg, indent_cols);
-                write_text_chunk(term, after, fg, bg, indent_cols);
-                rest = "";
-                break;
-            };
-
-            let (cmd, after_cmd) = after.split_at(end);
-            rest = &after_cmd[1..];
-
-            let cmd = cmd.trim();
-            if cmd.is_empty() {
-                write_text_chunk(term, "$", fg, bg, indent_cols);
-                continue;
-            }
-
-            let (op_flags, args) = cmd.split_once(',').unwrap_or((cmd, ""));
-            let mut parts = op_flags.split('+');
-            let op = parts.next().unwrap_or("").trim();
-            let flags: Vec<&str> = parts.collect();
-
-            match op {
-                "FG" => {
-                    let arg = args.trim();
-                    if arg.is_empty() {
-                        fg = default_fg;
-                    } else if let Ok(v) = arg.parse::<u8>() {
-                        fg = v.min(15);
-                    }
-                }
-                "BG" => {
-                    let arg = args.trim();
-                    if arg.is_empty() {
-                        bg = default_bg;
-                    } else if let Ok(v) = arg.parse::<u8>() {
-                        bg = v.min(15);
-                    }
-                }
-                "WW" => {
-                    // Word-wrap control. We always wrap at TERM_COLS; ignore for now.
-                }
-                "CM-RE" => {
-                    // Cursor move (X only). Used by docs like `Doc/Job.DD` for column layouts.
-                    let x = parse_kv_i32(args, "LE").or_else(|| args.trim().parse::<i32>().ok());
-                    if let Some(x) = x {
-                        if x > 0 {
-                            for _ in 0..x {
-                                term.put_char(' ');
-                            }
-                        } else if x < 0 {
-                            term.cursor_col = term.cursor_col.saturating_sub((-x) as u32);
-                        }
-                    }
-                }
-                "CM" => {
-                    let x = parse_kv_i32(args, "LE").or_else(|| {
-                        args.split(',')
-                            .next()
-                            .and_then(|s| s.trim().parse::<i32>().ok())
-                    });
-                    let y = parse_kv_i32(args, "RE").or_else(|| {
-                        args.split(',')
-                            .nth(1)
-                            .and_then(|s| s.trim().parse::<i32>().ok())
-                    });
-
-                    if let Some(y) = y {
-                        if y > 0 {
-                            for _ in 0..y {
-                                term.put_char('\n');
-                            }
-                        }
-                    }
-
-                    if let Some(x) = x {
-                        let mut col = term.cursor_col as i32;
-                        if flags.iter().any(|f| f.eq_ignore_ascii_case(&"LX")) {
-                            col = x;
-                        } else if flags.iter().any(|f| f.eq_ignore_ascii_case(&"RX")) {
-                            col = TERM_COLS as i32 + x;
-                        } else {
-                            col += x;
-                        }
-                        term.cursor_col = col.clamp(0, TERM_COLS as i32 - 1) as u32;
-                    }
-                }
-                "BK" => {
-                    // Blink in TempleOS text mode; approximate by using a strong background.
-                    let v = args.trim().parse::<i32>().unwrap_or(0);
-                    if v == 0 {
-                        if let Some(prev) = bk_saved_bg.take() {
-                            bg = prev;
-                        }
-                    } else {
-                        if bk_saved_bg.is_none() {
-                            bk_saved_bg = Some(bg);
-                        }
-                        bg = COLOR_STATUS_BG;
-                    }
-                }
-                "IV" => {
-                    // Inverted video.
-                    let v = args.trim().parse::<i32>().unwrap_or(0);
-                    if v == 0 {
-                        if let Some((prev_fg, prev_bg)) = iv_saved.take() {
-                            fg = prev_fg;
-                            bg = prev_bg;
-                        }
-                    } else if iv_saved.is_none() {
-                        iv_saved = Some((fg, bg));
-                        std::mem::swap(&mut fg, &mut bg);
-                    }
-                }
-                "HL" => {
-                    // Syntax highlighting toggle. We don't parse syntax yet; just tint code blocks.
-                    let v = args.trim().parse::<i32>().unwrap_or(0);
-                    if v == 0 {
-                        if let Some(prev) = hl_saved_fg.take() {
-                            fg = prev;
-                        }
-                    } else {
-                        if hl_saved_fg.is_none() {
-                            hl_saved_fg = Some(fg);
-                        }
-                        fg = 11;
-                    }
-                }
-                "UL" => {
-                    // Underline: approximate by tinting.
-                    let v = args.trim().parse::<i32>().unwrap_or(0);
-                    if v == 0 {
-                        if let Some(prev) = ul_saved_fg.take() {
-                            fg = prev;
-                        }
-                    } else {
-                        if ul_saved_fg.is_none() {
-                            ul_saved_fg = Some(fg);
-                        }
-                        fg = 14;
-                    }
-                }
-                "ID" => {
-                    if let Ok(delta) = args.trim().parse::<i32>() {
-                        indent_cols = (indent_cols + delta).clamp(0, TERM_COLS as i32 - 1);
-                    }
-                }
-                "AN" => {
-                    // Anchor tag; doesn't render text by default.
-                    if let Some(anchor) = parse_attr_quoted(cmd, "A") {
-                        let anchor = anchor.trim().to_string();
-                        if !anchor.is_empty() {
-                            let line_idx = term.scrollback.len() + term.cursor_row as usize;
-                            meta.anchors.insert(anchor, line_idx);
-                        }
-                    }
-                }
-                "TR" => {
-                    if let Some(s) = parse_quoted_args(cmd, 1).into_iter().next() {
-                        if term.cursor_col != 0 {
-                            let _ = writeln!(term, "");
-                        }
-                        term.set_colors(14, default_bg);
-                        let _ = writeln!(term, "{s}");
-                        term.set_colors(fg, bg);
-                    }
-                }
-                "LK" => {
-                    let mut quoted = parse_quoted_args(cmd, 2);
-                    let label = quoted.first().cloned();
-                    let second = if quoted.len() >= 2 {
-                        Some(quoted.remove(1))
-                    } else {
-                        None
-                    };
-                    let Some(label) = label else {
-                        continue;
-                    };
-
-                    let target = parse_attr_quoted(cmd, "A")
-                        .or(second)
-                        .unwrap_or_else(|| label.clone());
-                    let target = target.trim().to_string();
-                    if target.is_empty() {
-                        continue;
-                    }
-
-                    let target = DocLinkTarget::Doc(target);
-                    write_link_chunk(term, &label, 10, bg, indent_cols, &target, &mut meta.links);
-                    term.set_colors(fg, bg);
-	                }
-	                "TX" => {
-	                    if let Some(s) = parse_quoted_args(cmd, 1).into_iter().next() {
-	                        let cx = flags.iter().any(|f| f.eq_ignore_ascii_case(&"CX"));
-	                        if cx {
-                            let row = term.cursor_row.min(PROMPT_ROW.saturating_sub(1));
-                            let len = s.chars().count().min(TERM_COLS as usize);
-                            let col = TERM_COLS.saturating_sub(len as u32) / 2;
-                            term.write_at(col, row, fg, bg, &s);
-                            term.cursor_row = row;
-                            term.cursor_col = (col + len as u32).min(TERM_COLS);
-                        } else {
-                            write_text_chunk(term, &s, fg, bg, indent_cols);
-                        }
-	                    }
-	                }
-	                // HTML blocks (often images/embeds). TempleOS uses these primarily for HTML export;
-	                // show a small placeholder and optionally expose the first URL as a clickable link.
-	                "HC" => {
-	                    let html = parse_quoted_args(cmd, 1).into_iter().next().unwrap_or_default();
-	                    if let Some(url) = extract_first_http_url(&html) {
-	                        let label = url_label(&url);
-	                        let target = DocLinkTarget::Action(format!("templelinux:browse:{url}"));
-	                        write_link_chunk(
-	                            term,
-	                            &label,
-	                            10,
-	                            bg,
-	                            indent_cols,
-	                            &target,
-	                            &mut meta.links,
-	                        );
-	                        term.set_colors(fg, bg);
-	                    } else {
-	                        write_text_chunk(term, "[html]", fg, bg, indent_cols);
-	                        term.set_colors(fg, bg);
-	                    }
-	                }
-	                // Psalmody songs embedded in docs.
-	                "SO" => {
-	                    let label = parse_quoted_args(cmd, 1).into_iter().next();
-	                    let action = parse_attr_quoted(cmd, "A")
-	                        .or_else(|| parse_attr_quoted(cmd, "LM"))
-	                        .map(|s| s.trim().to_string())
-	                        .filter(|s| !s.is_empty());
-
-	                    let shown = label.unwrap_or_default();
-	                    let shown = shown.trim();
-	                    if shown.is_empty() {
-	                        continue;
-	                    }
-
-	                    if let Some(song) = action {
-	                        let target = DocLinkTarget::Action(format!("templelinux:song:{song}"));
-	                        write_link_chunk(
-	                            term,
-	                            shown,
-	                            10,
-	                            bg,
-	                            indent_cols,
-	                            &target,
-	                            &mut meta.links,
-	                        );
-	                    } else {
-	                        write_text_chunk(term, shown, fg, bg, indent_cols);
-	                    }
-	                    term.set_colors(fg, bg);
-	                }
-                "SP" => {
-                    // Sprite: `BI=<n>` refers to binary data stored after the NUL terminator
-                    // in `.DD` files (TempleOS `CDocBin` tail).
-                    let tag = parse_quoted_args(cmd, 1).into_iter().next().unwrap_or_default();
-                    let action = parse_attr_quoted(cmd, "LM")
-                        .or_else(|| parse_attr_quoted(cmd, "A"))
-                        .map(|s| s.trim().to_string())
-                        .filter(|s| !s.is_empty());
-
-                    let start_line = term.scrollback.len() + term.cursor_row as usize;
-                    let start_col = term.cursor_col as usize;
-
-                    if !tag.is_empty() {
-                        if let Some(action) = action.as_ref() {
-                            let target = DocLinkTarget::Action(action.clone());
-                            write_link_chunk(
-                                term,
-                                &tag,
-                                10,
-                                bg,
-                                indent_cols,
-                                &target,
-                                &mut meta.links,
-                            );
-                        } else {
-                            write_text_chunk(term, &tag, fg, bg, indent_cols);
-                        }
-                        term.set_colors(fg, bg);
-                    }
-
-                    let end_line = term.scrollback.len() + term.cursor_row as usize;
-                    let end_col = term.cursor_col as usize;
-
-                    let from_start = flags
-                        .iter()
-                        .any(|f| f.eq_ignore_ascii_case(&"FST"));
-                    let (anchor_line, anchor_col) = if from_start {
-                        (start_line, start_col)
-                    } else {
-                        (end_line, end_col)
-                    };
-
-                    let bi = parse_kv_i32(args, "BI").and_then(|v| u32::try_from(v).ok());
-                    let data = bi.and_then(|n| bins.get(&n).map(|b| (n, b)));
-                    if let Some((bin_num, data)) = data {
-                        let bounds = temple_rt::sprite::sprite_bounds(data).unwrap_or_default();
-                        let (bbox_line0, bbox_col0, bbox_line1, bbox_col1) =
-                            sprite_bbox_cells(anchor_line, anchor_col, bounds);
-                        meta.sprites.push(DocSprite {
-                            anchor_line,
-                            anchor_col,
-                            bbox_line0,
-                            bbox_col0,
-                            bbox_line1,
-                            bbox_col1,
-                            bin_num,
-                            action,
-                        });
-                    } else if tag.is_empty() {
-                        // Fallback: show a small placeholder tag so docs don't look like they're missing content.
-                        write_text_chunk(term, "[sprite]", fg, bg, indent_cols);
-                        term.set_colors(fg, bg);
-                    }
-                }
-                // Insert Binary (pointer): not visual; show a minimal placeholder.
-                "IB" => {
-                    let tag = parse_quoted_args(cmd, 1).into_iter().next().unwrap_or_default();
-                    let bi = parse_kv_i32(args, "BI").and_then(|v| u32::try_from(v).ok());
-                    let data = bi.and_then(|n| bins.get(&n).map(|b| (n, b)));
-
-                    if tag.trim().is_empty() {
-                        if let Some((bin_num, data)) = data {
-                            let line_idx = term.scrollback.len() + term.cursor_row as usize;
-                            let col_idx = term.cursor_col as usize;
-                            let action = parse_attr_quoted(cmd, "LM")
-                                .or_else(|| parse_attr_quoted(cmd, "A"))
-                                .map(|s| s.trim().to_string())
-                                .filter(|s| !s.is_empty());
-                            let bounds = temple_rt::sprite::sprite_bounds(data).unwrap_or_default();
-                            let (bbox_line0, bbox_col0, bbox_line1, bbox_col1) =
-                                sprite_bbox_cells(line_idx, col_idx, bounds);
-                            meta.sprites.push(DocSprite {
-                                anchor_line: line_idx,
-                                anchor_col: col_idx,
-                                bbox_line0,
-                                bbox_col0,
-                                bbox_line1,
-                                bbox_col1,
-                                bin_num,
-                                action,
-                            });
-
-                            // Reserve horizontal space so inline binaries don't overlap following text.
-                            let reserve_cols = {
-                                let px_right = bounds.x1.max(0) as u32;
-                                (px_right.saturating_add(FONT_W - 1) / FONT_W).clamp(1, TERM_COLS)
-                            };
-
-                            let avail = TERM_COLS.saturating_sub(term.cursor_col);
-                            for _ in 0..reserve_cols.min(avail) {
-                                term.put_char(' ');
-                            }
-
-                            term.set_colors(fg, bg);
-                        } else {
-                            write_text_chunk(term, "[bin]", fg, bg, indent_cols);
-                            term.set_colors(fg, bg);
-                        }
-                    } else {
-                        write_text_chunk(term, &tag, fg, bg, indent_cols);
-                        term.set_colors(fg, bg);
-                    }
-                }
-                // Menu anchors are often directory links in DemoIndex.
-                "MA-X" | "MA" => {
-                    let label = parse_quoted_args(cmd, 1).into_iter().next();
-                    let action = parse_attr_quoted(cmd, "LM")
-                        .or_else(|| parse_attr_quoted(cmd, "A"))
-                        .map(|s| s.trim().to_string())
-                        .filter(|s| !s.is_empty());
-
-                    let shown = label
-                        .clone()
-                        .or_else(|| action.clone())
-                        .unwrap_or_default();
-                    let shown = shown.trim();
-                    if shown.is_empty() {
-                        continue;
-                    }
-
-                    let target = if let Some(action) = action {
-                        DocLinkTarget::Action(action)
-                    } else {
-                        DocLinkTarget::Doc(shown.to_string())
-                    };
-
-                    write_link_chunk(term, shown, 10, bg, indent_cols, &target, &mut meta.links);
-                    term.set_colors(fg, bg);
-                }
-                other => {
-                    // Best-effort: if the command carries a quoted label/path, show it.
-                    if let Some(s) = parse_quoted_args(cmd, 1).into_iter().next() {
-                        let color = match other {
-                            "MA-X" | "MA" => 10,
-                            _ => fg,
-                        };
-                        write_text_chunk(term, &s, color, bg, indent_cols);
-                        term.set_colors(fg, bg);
-                    }
-                }
-            }
-        }
-
-        write_text_chunk(term, rest, fg, bg, indent_cols);
-        term.set_colors(default_fg, default_bg);
-        meta
-    }
-
     fn cmd_pwd(&self, term: &mut Terminal) {
         use fmt::Write as _;
         let _ = writeln!(term, "{}", self.cwd.display());
@@ -4395,6 +4133,77 @@ impl Shell {
         let _ = writeln!(term, "[cleared {name}]");
     }
 
+    /// Applies the saved `TEMPLE_FONT`/`TEMPLE_TEXT_SCALE` vars to the terminal.
+    fn restore_font(&mut self, term: &mut Terminal) {
+        if let Some(name) = self.vars.get(FONT_VAR) {
+            match temple_rt::font::load_font(&self.root_dir, name) {
+                Ok(font) => term.set_font(&font),
+                Err(err) => eprintln!("templeshell: {FONT_VAR}: {err}"),
+            }
+        }
+        self.text_scale = self
+            .vars
+            .get(TEXT_SCALE_VAR)
+            .and_then(|v| v.trim().parse::<u32>().ok())
+            .unwrap_or(1)
+            .clamp(1, FONT_MAX_ZOOM);
+    }
+
+    fn cmd_font(&mut self, args: &[&str], term: &mut Terminal) {
+        use fmt::Write as _;
+
+        match args {
+            [] => {
+                let cur = self
+                    .vars
+                    .get(FONT_VAR)
+                    .map(String::as_str)
+                    .unwrap_or(temple_rt::font::STD_FONT);
+                let _ = writeln!(term, "font: {cur} (scale {})", self.text_scale);
+                let _ = writeln!(term, "  {}", temple_rt::font::STD_FONT);
+                for name in temple_rt::font::list_fonts(&self.root_dir) {
+                    let _ = writeln!(term, "  {name}");
+                }
+            }
+            ["scale", n] => {
+                let Some(scale) = n
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|s| (1..=FONT_MAX_ZOOM).contains(s))
+                else {
+                    let _ = writeln!(term, "font: scale must be 1-{FONT_MAX_ZOOM}");
+                    return;
+                };
+                self.text_scale = scale;
+                if scale == 1 {
+                    self.vars.remove(TEXT_SCALE_VAR);
+                } else {
+                    self.vars.insert(TEXT_SCALE_VAR.to_string(), scale.to_string());
+                }
+                self.save_vars();
+                let _ = writeln!(term, "[font scale {scale}]");
+            }
+            [name] => match temple_rt::font::load_font(&self.root_dir, name) {
+                Ok(font) => {
+                    term.set_font(&font);
+                    if name.eq_ignore_ascii_case(temple_rt::font::STD_FONT) {
+                        self.vars.remove(FONT_VAR);
+                    } else {
+                        self.vars.insert(FONT_VAR.to_string(), name.to_string());
+                    }
+                    self.save_vars();
+                    let _ = writeln!(term, "[font {} {}x{}]", font.name, font.width, font.height);
+                }
+                Err(err) => {
+                    let _ = writeln!(term, "font: {err}");
+                }
+            },
+            _ => {
+                let _ = writeln!(term, "font [name] | font scale <1-{FONT_MAX_ZOOM}>");
+            }
+        }
+    }
+
     fn cmd_open(&self, args: &[&str], term: &mut Terminal) {
         let Some(target) = args.first().copied() else {
             use fmt::Write as _;
@@ -4915,6 +4724,17 @@ impl Shell {
                 .unwrap_or_else(|| "temple-edit".to_string())
         };
 
+        let sprite_program = || {
+            std::env::current_exe()
+                .ok()
+                .and_then(|exe| {
+                    let candidate = exe.with_file_name("temple-sprite");
+                    candidate.exists().then_some(candidate)
+                })
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_else(|| "temple-sprite".to_string())
+        };
+
         if let Some((&sub, rest)) = args.split_first() {
             if sub == "linuxbridge" || sub == "bridge" {
                 if !rest.is_empty() {
@@ -5276,6 +5096,7 @@ impl Shell {
             Some((&"paint", rest)) => (paint_program(), rest),
             Some((&"edit", rest)) => (edit_program(), rest),
             Some((&"editor", rest)) => (edit_program(), rest),
+            Some((&"sprite", rest)) => (sprite_program(), rest),
             Some((&program, rest)) => (program.to_string(), rest),
         };
 
@@ -5291,6 +5112,10 @@ impl Shell {
                 .first()
                 .map(|p| format!("Edit {}", Path::new(p).display()))
                 .unwrap_or_else(|| "Edit".to_string()),
+            Some((&"sprite", rest)) => rest
+                .last()
+                .map(|p| format!("Sprite {}", Path::new(p).display()))
+                .unwrap_or_else(|| "Sprite".to_string()),
             Some((&other, _)) => Path::new(other)
                 .file_name()
                 .map(|s| s.to_string_lossy().to_string())
@@ -324,6 +324,36 @@ struct TempleAppSession {
     height: u32,
     cmd_tx: mpsc::Sender<protocol::Msg>,
     pending_present_ack_seq: Option<u32>,
+    /// When the first input the app hasn't answered since was sent (hang watchdog).
+    unanswered_input_since: Option<std::time::Instant>,
+}
+
+impl TempleAppSession {
+    /// Queues `msg` for the app; input starts the hang watchdog. False once the app is gone.
+    fn send(&mut self, msg: protocol::Msg) -> bool {
+        let is_input = matches!(
+            msg.kind,
+            protocol::MSG_KEY | protocol::MSG_MOUSE_BUTTON | protocol::MSG_MOUSE_WHEEL
+        );
+        if is_input && self.unanswered_input_since.is_none() {
+            self.unanswered_input_since = Some(std::time::Instant::now());
+        }
+        self.cmd_tx.send(msg).is_ok()
+    }
+
+    /// The app sent something (a frame, an input ack...): it is not hung.
+    fn note_alive(&mut self) {
+        self.unanswered_input_since = None;
+    }
+
+    /// When the watchdog flags the app as not responding, unless it answers first.
+    fn hang_deadline(&self) -> Option<std::time::Instant> {
+        self.unanswered_input_since.map(|t| t + APP_HANG_TIMEOUT)
+    }
+
+    fn not_responding_at(&self, now: std::time::Instant) -> bool {
+        self.hang_deadline().is_some_and(|t| now >= t)
+    }
 }
 
 #[derive(Debug, Clone)]
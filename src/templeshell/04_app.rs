@@ -2,6 +2,12 @@ const WIN_TITLE_H: i32 = 16;
 const WIN_CLOSE_W: i32 = 16;
 const WIN_DEFAULT_W: i32 = 320;
 const WIN_DEFAULT_H: i32 = 256;
+/// An app that hasn't presented for this long after being sent input is flagged as not responding.
+const APP_HANG_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+/// The button that breaks a not-responding app (see `AppWindow::break_rect`).
+const BREAK_LABEL: &str = " Break ";
+/// How often the shell checks on macro sessions that haven't connected yet.
+const MACRO_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
 
 #[derive(Clone, Copy, Debug)]
 struct RectI32 {
@@ -61,6 +67,17 @@ impl AppWindow {
         }
     }
 
+    /// The "Break" button on the not-responding banner.
+    fn break_rect(&self) -> RectI32 {
+        let client = self.client_rect();
+        RectI32 {
+            x: client.x + 4,
+            y: client.y + 12,
+            w: 8 * BREAK_LABEL.len() as i32,
+            h: 12.min(client.h - 12).max(0),
+        }
+    }
+
     fn hit_test(&self, px: i32, py: i32) -> WindowHit {
         if !self.rect.contains(px, py) {
             return WindowHit::None;
@@ -87,7 +104,7 @@ struct DragState {
 
 struct FbSpriteTarget<'a> {
     fb: &'a mut Framebuffer,
-    clip: Option<(i32, i32, i32, i32)>,
+    rop: temple_rt::rt::Rop,
 }
 
 impl temple_rt::sprite::SpriteTarget for FbSpriteTarget<'_> {
@@ -95,12 +112,8 @@ impl temple_rt::sprite::SpriteTarget for FbSpriteTarget<'_> {
         if x < 0 || y < 0 || x >= INTERNAL_W as i32 || y >= INTERNAL_H as i32 {
             return;
         }
-        if let Some((x0, y0, x1, y1)) = self.clip {
-            if x < x0 || y < y0 || x >= x1 || y >= y1 {
-                return;
-            }
-        }
-        self.fb.put_pixel(x as u32, y as u32, color);
+        let idx = (y as u32 * INTERNAL_W + x as u32) as usize;
+        self.rop.plot(x, y, color, &mut self.fb.indices[idx]);
     }
 
     fn fill_rect(&mut self, x: i32, y: i32, w: i32, h: i32, color: u8) {
@@ -108,28 +121,24 @@ impl temple_rt::sprite::SpriteTarget for FbSpriteTarget<'_> {
             return;
         }
 
-        let mut x0 = x;
-        let mut y0 = y;
-        let mut x1 = x.saturating_add(w);
-        let mut y1 = y.saturating_add(h);
-
-        x0 = x0.clamp(0, INTERNAL_W as i32);
-        y0 = y0.clamp(0, INTERNAL_H as i32);
-        x1 = x1.clamp(0, INTERNAL_W as i32);
-        y1 = y1.clamp(0, INTERNAL_H as i32);
-
-        if let Some((cx0, cy0, cx1, cy1)) = self.clip {
-            x0 = x0.max(cx0);
-            y0 = y0.max(cy0);
-            x1 = x1.min(cx1);
-            y1 = y1.min(cy1);
-        }
+        let x0 = x.clamp(0, INTERNAL_W as i32);
+        let y0 = y.clamp(0, INTERNAL_H as i32);
+        let x1 = x.saturating_add(w).clamp(0, INTERNAL_W as i32);
+        let y1 = y.saturating_add(h).clamp(0, INTERNAL_H as i32);
 
         let w = x1 - x0;
         let h = y1 - y0;
         if w <= 0 || h <= 0 {
             return;
         }
+        if !self.rop.is_plain() {
+            for yy in y0..y1 {
+                for xx in x0..x1 {
+                    self.set_pixel(xx, yy, color);
+                }
+            }
+            return;
+        }
         fill_rect_i32(self.fb, x0, y0, w, h, color);
     }
 
@@ -151,10 +160,18 @@ impl temple_rt::sprite::SpriteTarget for FbSpriteTarget<'_> {
                 if px == 0xFF {
                     continue;
                 }
-                self.set_pixel(dst_x + col as i32, dst_y + row as i32, px);
+                self.set_pixel(dst_x + col as i32, dst_y + row as i32, self.rop.blit_color(px));
             }
         }
     }
+
+    fn rop(&self) -> temple_rt::rt::Rop {
+        self.rop
+    }
+
+    fn set_rop(&mut self, rop: temple_rt::rt::Rop) {
+        self.rop = rop;
+    }
 }
 
 struct App {
@@ -172,8 +189,12 @@ struct App {
     browser_last_click: Option<(std::time::Instant, usize)>,
     cursor_internal: Option<(u32, u32)>,
     mouse_left_down: bool,
+    /// A left-button drag over the shell or doc viewer is selecting text.
+    select_drag: bool,
     output_size: PhysicalSize<u32>,
     temple_apps: std::collections::BTreeMap<AppId, TempleAppSession>,
+    /// Sessions without a window yet (DolDoc macros); they get one when they first present.
+    hidden_apps: std::collections::BTreeMap<AppId, String>,
     focused_app: Option<AppId>,
     hovered_app: Option<AppId>,
     mouse_capture_app: Option<AppId>,
@@ -254,8 +275,10 @@ impl App {
             browser_last_click: None,
             cursor_internal: None,
             mouse_left_down: false,
+            select_drag: false,
             output_size,
             temple_apps: std::collections::BTreeMap::new(),
+            hidden_apps: std::collections::BTreeMap::new(),
             focused_app: None,
             hovered_app: None,
             mouse_capture_app: None,
@@ -266,6 +289,7 @@ impl App {
             test,
         };
         app.update_status_line();
+        app.shell.restore_font(&mut app.terminal);
         if app.test.is_none() {
             app.shell.run_autostart(&mut app.terminal);
         }
@@ -395,6 +419,7 @@ impl App {
 
     fn drop_app(&mut self, id: AppId) {
         self.temple_apps.remove(&id);
+        self.hidden_apps.remove(&id);
         self.windows.retain(|w| w.id != id);
         if self.wallpaper_app == Some(id) {
             self.wallpaper_app = None;
@@ -460,16 +485,65 @@ impl App {
     }
 
     fn send_app_msg(&mut self, id: AppId, msg: protocol::Msg) -> bool {
-        let failed = match self.temple_apps.get(&id) {
-            Some(sess) => sess.cmd_tx.send(msg).is_err(),
-            None => true,
-        };
+        let failed = !self
+            .temple_apps
+            .get_mut(&id)
+            .is_some_and(|sess| sess.send(msg));
         if failed {
             self.drop_app(id);
         }
         failed
     }
 
+    /// Clears `id`'s hang watchdog; true if that takes down its "Not responding" banner.
+    fn note_app_alive(&mut self, id: AppId) -> bool {
+        let was_flagged = self.app_not_responding(id);
+        if let Some(sess) = self.temple_apps.get_mut(&id) {
+            sess.note_alive();
+        }
+        was_flagged
+    }
+
+    /// Whether `id` has ignored input for longer than `APP_HANG_TIMEOUT`.
+    fn app_not_responding(&self, id: AppId) -> bool {
+        let now = std::time::Instant::now();
+        self.temple_apps
+            .get(&id)
+            .is_some_and(|sess| sess.not_responding_at(now))
+    }
+
+    /// When the watchdog next needs to look at the apps (to flag one as not responding, or to
+    /// notice a macro session that died before connecting).
+    fn watchdog_deadline(&self) -> Option<std::time::Instant> {
+        let now = std::time::Instant::now();
+        let macro_poll = self
+            .shell
+            .macro_children_pending()
+            .then(|| now + MACRO_POLL_INTERVAL);
+        self.temple_apps
+            .values()
+            .filter_map(TempleAppSession::hang_deadline)
+            .filter(|&t| t > now)
+            .chain(macro_poll)
+            .min()
+    }
+
+    /// TempleOS' Ctrl+Alt+C: makes the app throw out of whatever it's doing.
+    fn break_app(&mut self, id: AppId) {
+        for (code, down) in [
+            (protocol::KEY_CONTROL, true),
+            (protocol::KEY_ALT, true),
+            (b'c' as u32, true),
+            (b'c' as u32, false),
+            (protocol::KEY_ALT, false),
+            (protocol::KEY_CONTROL, false),
+        ] {
+            if self.send_app_msg(id, protocol::Msg::key(code, down)) {
+                return;
+            }
+        }
+    }
+
     fn flush_present_acks(&mut self) {
         let mut drop_ids = Vec::new();
         for (&id, sess) in self.temple_apps.iter_mut() {
@@ -572,6 +646,28 @@ impl App {
             } else if let Some(sess) = self.temple_apps.get(&win.id) {
                 let client = win.client_rect();
                 blit_scaled_indices(&mut self.fb, client, &sess.fb, sess.width, sess.height);
+                if self.app_not_responding(win.id) {
+                    fill_rect_i32(&mut self.fb, client.x, client.y, client.w, 28, 4);
+                    draw_text_8x8(
+                        &mut self.fb,
+                        client.x + 4,
+                        client.y + 2,
+                        15,
+                        4,
+                        "Not responding.",
+                    );
+                    let button = win.break_rect();
+                    fill_rect_i32(&mut self.fb, button.x, button.y, button.w, button.h, 15);
+                    draw_text_8x8(&mut self.fb, button.x, button.y + 2, 4, 15, BREAK_LABEL);
+                    draw_text_8x8(
+                        &mut self.fb,
+                        button.x + button.w + 8,
+                        button.y + 2,
+                        14,
+                        4,
+                        "or Ctrl+Alt+C",
+                    );
+                }
             }
 
             // Border + title separator.
@@ -725,37 +821,42 @@ impl App {
                 .render(&mut self.fb, TerminalRenderMode::Opaque);
         }
         if let Some(state) = self.shell.doc_viewer.as_ref() {
-            let content_rows = Shell::doc_view_rows().max(1);
-            let view_start = state.scroll;
-            let view_end = view_start + content_rows;
-
-            let clip = Some((
-                0,
-                FONT_H as i32,
-                INTERNAL_W as i32,
-                (PROMPT_ROW * FONT_H) as i32,
-            ));
             let mut target = FbSpriteTarget {
                 fb: &mut self.fb,
-                clip,
+                rop: temple_rt::rt::Rop::default(),
             };
-
-            let view_start_i32 = view_start as i32;
-            let view_end_i32 = view_end as i32;
-            for sp in &state.sprites {
-                if sp.bbox_line1 <= view_start_i32 || sp.bbox_line0 >= view_end_i32 {
-                    continue;
-                }
-                let Some(data) = state.bins.get(&sp.bin_num) else {
-                    continue;
-                };
-
-                let base_x = sp.anchor_col as i32 * FONT_W as i32;
-                let row_off = sp.anchor_line as i32 - view_start_i32;
-                let base_y = (1 + row_off) as i32 * FONT_H as i32;
-                temple_rt::sprite::sprite_render(&mut target, base_x, base_y, data);
-            }
+            let view = temple_rt::doldoc::Viewport {
+                x: 0,
+                y: FONT_H as i32,
+                cols: TERM_COLS as usize,
+                rows: Shell::doc_view_rows().max(1),
+                first_line: state.scroll,
+            };
+            temple_rt::doldoc::render_sprites(&mut target, &state.sprites, &state.bins, &view);
+        }
+        if let Some(image) = self.shell.browser_preview() {
+            // Right half of the list, shrunk to fit.
+            let (max_w, max_h) = (
+                INTERNAL_W as i32 / 2 - 16,
+                (PROMPT_ROW as i32 - 2) * FONT_H as i32 - 8,
+            );
+            let (w, h) = (image.width as i32, image.height as i32);
+            let (w, h) = if w <= max_w && h <= max_h {
+                (w, h)
+            } else if w * max_h > h * max_w {
+                (max_w, (h * max_w / w).max(1))
+            } else {
+                ((w * max_h / h).max(1), max_h)
+            };
+            let dst = RectI32 {
+                x: INTERNAL_W as i32 / 2 + 8,
+                y: 2 * FONT_H as i32 + 4,
+                w,
+                h,
+            };
+            blit_scaled_indices(&mut self.fb, dst, &image.pixels, image.width, image.height);
         }
+        zoom_bottom_left(&mut self.fb, self.shell.text_scale);
         if !self.windows.is_empty() {
             self.draw_windows();
         }
@@ -1180,6 +1281,20 @@ fn discover_templeos_root() -> Option<PathBuf> {
     None
 }
 
+/// Docs from the TempleOS tree (`::/...`), which the doc viewer never writes to.
+fn is_read_only_doc(spec: &str, host: &Path) -> bool {
+    if spec.starts_with("::/") {
+        return true;
+    }
+    let Some(root) = discover_templeos_root() else {
+        return false;
+    };
+    let root = std::fs::canonicalize(&root).unwrap_or(root);
+    std::fs::canonicalize(host)
+        .unwrap_or_else(|_| host.to_path_buf())
+        .starts_with(root)
+}
+
 fn walk_dir_collect_hc_files(dir: &Path, out: &mut Vec<PathBuf>) {
     let rd = match std::fs::read_dir(dir) {
         Ok(rd) => rd,
@@ -1438,6 +1553,12 @@ fn spawn_temple_ipc_server(
 
             let id = next_id;
             next_id = next_id.wrapping_add(1).max(1);
+            let pid = nix::sys::socket::getsockopt(
+                &stream,
+                nix::sys::socket::sockopt::PeerCredentials,
+            )
+            .ok()
+            .and_then(|cred| u32::try_from(cred.pid()).ok());
 
             let hello = match protocol::read_msg(&mut stream) {
                 Ok(m) => m,
@@ -1582,11 +1703,48 @@ fn spawn_temple_ipc_server(
                                         TempleIpcEvent::ClipboardSet { id, text },
                                     ));
                                 }
+                                protocol::MSG_SHELL_REQUEST => {
+                                    const MAX_REQUEST_BYTES: usize = 64 * 1024;
+
+                                    let len = msg.a as usize;
+                                    if len > MAX_REQUEST_BYTES {
+                                        let _ = proxy.send_event(UserEvent::Ipc(
+                                            TempleIpcEvent::Log(format!(
+                                                "ipc[{id}]: shell request too large: {len} bytes"
+                                            )),
+                                        ));
+                                        break;
+                                    }
+
+                                    let mut buf = vec![0u8; len];
+                                    if let Err(err) = reader.read_exact(&mut buf) {
+                                        let _ =
+                                            proxy.send_event(UserEvent::Ipc(TempleIpcEvent::Log(
+                                                format!("ipc[{id}]: read shell request: {err}"),
+                                            )));
+                                        break;
+                                    }
+                                    let req = String::from_utf8_lossy(&buf).to_string();
+                                    let _ = proxy.send_event(UserEvent::Ipc(
+                                        TempleIpcEvent::ShellRequest { id, req },
+                                    ));
+                                }
                                 protocol::MSG_SND => {
                                     audio.snd(msg.a as u8);
+                                    let _ = proxy.send_event(UserEvent::Ipc(
+                                        TempleIpcEvent::AppAlive { id },
+                                    ));
                                 }
                                 protocol::MSG_MUTE => {
                                     audio.mute(msg.a != 0);
+                                    let _ = proxy.send_event(UserEvent::Ipc(
+                                        TempleIpcEvent::AppAlive { id },
+                                    ));
+                                }
+                                protocol::MSG_INPUT_ACK => {
+                                    let _ = proxy.send_event(UserEvent::Ipc(
+                                        TempleIpcEvent::AppAlive { id },
+                                    ));
                                 }
                                 _ => {}
                             },
@@ -1601,6 +1759,7 @@ fn spawn_temple_ipc_server(
 
             let _ = proxy.send_event(UserEvent::Ipc(TempleIpcEvent::AppConnected {
                 id,
+                pid,
                 shm,
                 width: INTERNAL_W,
                 height: INTERNAL_H,
@@ -1745,125 +1904,167 @@ fn main() {
 
     event_loop
         .run(move |event, elwt| {
-            elwt.set_control_flow(ControlFlow::Wait);
+            elwt.set_control_flow(match app.watchdog_deadline() {
+                Some(deadline) => ControlFlow::WaitUntil(deadline),
+                None => ControlFlow::Wait,
+            });
 
             match event {
-                Event::UserEvent(UserEvent::Ipc(ev)) => match ev {
-                    TempleIpcEvent::PaletteColorSet {
-                        id: _,
-                        color_index,
-                        rgba,
-                    } => {
-                        if let Some(slot) = app.palette.get_mut(color_index as usize) {
-                            *slot = rgba;
-                            window.request_redraw();
-                        }
-                    }
-                    TempleIpcEvent::SettingsPush { id: _ } => {
-                        const MAX_STACK: usize = 64;
-                        if app.palette_stack.len() < MAX_STACK {
-                            app.palette_stack.push(app.palette);
-                        }
-                    }
-                    TempleIpcEvent::SettingsPop { id: _ } => {
-                        if let Some(p) = app.palette_stack.pop() {
-                            app.palette = p;
-                            window.request_redraw();
-                        }
-                    }
-                    TempleIpcEvent::Log(line) => {
-                        use fmt::Write as _;
-                        let _ = writeln!(&mut app.terminal, "{line}");
+                Event::UserEvent(UserEvent::Ipc(ev)) => {
+                    if let Some(id) = ev.sender()
+                        && app.note_app_alive(id)
+                    {
                         window.request_redraw();
                     }
-                    TempleIpcEvent::ClipboardSet { id, text } => {
-                        use fmt::Write as _;
-                        match app.shell.clipboard.set_text(&text) {
-                            Ok(()) => {
-                                let _ = writeln!(
-                                    &mut app.terminal,
-                                    "[clipboard set from app {id}: {} bytes]",
-                                    text.len()
-                                );
+                    match ev {
+                        // Handled above: all it does is feed the hang watchdog.
+                        TempleIpcEvent::AppAlive { .. } => {}
+                        TempleIpcEvent::PaletteColorSet {
+                            id: _,
+                            color_index,
+                            rgba,
+                        } => {
+                            if let Some(slot) = app.palette.get_mut(color_index as usize) {
+                                *slot = rgba;
+                                window.request_redraw();
                             }
-                            Err(err) => {
-                                let _ = writeln!(&mut app.terminal, "clipboard: set: {err}");
+                        }
+                        TempleIpcEvent::SettingsPush { id: _ } => {
+                            const MAX_STACK: usize = 64;
+                            if app.palette_stack.len() < MAX_STACK {
+                                app.palette_stack.push(app.palette);
                             }
                         }
-                        window.request_redraw();
-                    }
-                    TempleIpcEvent::AppConnected {
-                        id,
-                        shm,
-                        width,
-                        height,
-                        cmd_tx,
-                    } => {
-                        let len = (width * height) as usize;
-                        match unsafe { memmap2::MmapOptions::new().len(len).map(&shm) } {
-                            Ok(map) => {
-                                use fmt::Write as _;
-                                let _ = writeln!(&mut app.terminal, "[temple app connected: {id}]");
-                                app.temple_apps.insert(
-                                    id,
-                                    TempleAppSession {
-                                        fb: map,
-                                        width,
-                                        height,
-                                        cmd_tx,
-                                        pending_present_ack_seq: None,
-                                    },
-                                );
-                                let title = app
-                                    .shell
-                                    .take_queued_window_title()
-                                    .unwrap_or_else(|| format!("App {id}"));
-                                let kind = app.shell.take_queued_window_kind();
-                                match kind {
-                                    PendingWindowKind::Normal => app.open_app_window(id, title),
-                                    PendingWindowKind::Wallpaper => app.set_wallpaper_app(id, title),
-                                }
-                                app.shell.tapp_connected = !app.temple_apps.is_empty();
-                                app.update_status_line();
+                        TempleIpcEvent::SettingsPop { id: _ } => {
+                            if let Some(p) = app.palette_stack.pop() {
+                                app.palette = p;
                                 window.request_redraw();
                             }
-                            Err(err) => {
-                                use fmt::Write as _;
-                                let _ =
-                                    writeln!(&mut app.terminal, "ipc: failed to map shm: {err}");
-                            }
                         }
-                    }
-                    TempleIpcEvent::AppPresent { id, seq } => {
-                        if app.test.is_none() && !app.window_focused {
-                            // If we're unfocused, avoid rendering churn but ACK promptly so clients
-                            // don't stall when `TEMPLE_SYNC_PRESENT=1`.
-                            let _ = app.send_app_msg(id, protocol::Msg::present_ack(seq));
-                        } else if let Some(sess) = app.temple_apps.get_mut(&id) {
-                            sess.pending_present_ack_seq = Some(seq);
+                        TempleIpcEvent::Log(line) => {
+                            use fmt::Write as _;
+                            let _ = writeln!(&mut app.terminal, "{line}");
                             window.request_redraw();
                         }
-                        if let Some(test) = app.test.as_mut() {
-                            test.on_app_present(id);
+                        TempleIpcEvent::ShellRequest { id, req } if req == "show" => {
+                            // A hidden macro session waiting for input needs a window to get it.
+                            if let Some(title) = app.hidden_apps.remove(&id) {
+                                app.open_app_window(id, title);
+                                window.request_redraw();
+                            }
                         }
-                    }
-                    TempleIpcEvent::AppDisconnected { id } => {
-                        if app.temple_apps.contains_key(&id)
-                            || app.windows.iter().any(|w| w.id == id)
-                        {
+                        TempleIpcEvent::ShellRequest { id, req } => {
+                            app.shell.handle_app_request(id, &req, &mut app.terminal);
+                            window.request_redraw();
+                        }
+                        TempleIpcEvent::ClipboardSet { id, text } => {
                             use fmt::Write as _;
-                            let _ = writeln!(&mut app.terminal, "[temple app disconnected: {id}]");
-                            app.drop_app(id);
+                            match app.shell.clipboard.set_text(&text) {
+                                Ok(()) => {
+                                    let _ = writeln!(
+                                        &mut app.terminal,
+                                        "[clipboard set from app {id}: {} bytes]",
+                                        text.len()
+                                    );
+                                }
+                                Err(err) => {
+                                    let _ = writeln!(&mut app.terminal, "clipboard: set: {err}");
+                                }
+                            }
                             window.request_redraw();
                         }
-                        if let Some(test) = app.test.as_mut() {
-                            test.on_app_disconnected();
-                            if test.exit_now {
-                                elwt.exit();
+                        TempleIpcEvent::AppConnected {
+                            id,
+                            pid,
+                            shm,
+                            width,
+                            height,
+                            cmd_tx,
+                        } => {
+                            let len = (width * height) as usize;
+                            match unsafe { memmap2::MmapOptions::new().len(len).map(&shm) } {
+                                Ok(map) => {
+                                    use fmt::Write as _;
+                                    let (title, kind) = match app.shell.take_macro_window(pid) {
+                                        Some(title) => (title, PendingWindowKind::Hidden),
+                                        None => (
+                                            app.shell
+                                                .take_queued_window_title()
+                                                .unwrap_or_else(|| format!("App {id}")),
+                                            app.shell.take_queued_window_kind(),
+                                        ),
+                                    };
+                                    if kind != PendingWindowKind::Hidden {
+                                        let _ =
+                                            writeln!(&mut app.terminal, "[temple app connected: {id}]");
+                                    }
+                                    app.temple_apps.insert(
+                                        id,
+                                        TempleAppSession {
+                                            fb: map,
+                                            width,
+                                            height,
+                                            cmd_tx,
+                                            pending_present_ack_seq: None,
+                                            unanswered_input_since: None,
+                                        },
+                                    );
+                                    match kind {
+                                        PendingWindowKind::Normal => app.open_app_window(id, title),
+                                        PendingWindowKind::Wallpaper => app.set_wallpaper_app(id, title),
+                                        PendingWindowKind::Hidden => {
+                                            app.hidden_apps.insert(id, title);
+                                        }
+                                    }
+                                    app.shell.tapp_connected = !app.temple_apps.is_empty();
+                                    app.update_status_line();
+                                    window.request_redraw();
+                                }
+                                Err(err) => {
+                                    use fmt::Write as _;
+                                    let _ =
+                                        writeln!(&mut app.terminal, "ipc: failed to map shm: {err}");
+                                }
+                            }
+                        }
+                        TempleIpcEvent::AppPresent { id, seq } => {
+                            if let Some(title) = app.hidden_apps.remove(&id) {
+                                app.open_app_window(id, title);
+                            }
+                            if app.test.is_none() && !app.window_focused {
+                                // If we're unfocused, avoid rendering churn but ACK promptly so clients
+                                // don't stall when `TEMPLE_SYNC_PRESENT=1`.
+                                let _ = app.send_app_msg(id, protocol::Msg::present_ack(seq));
+                            } else if let Some(sess) = app.temple_apps.get_mut(&id) {
+                                sess.pending_present_ack_seq = Some(seq);
+                                window.request_redraw();
+                            }
+                            if let Some(test) = app.test.as_mut() {
+                                test.on_app_present(id);
+                            }
+                        }
+                        TempleIpcEvent::AppDisconnected { id } => {
+                            app.shell.reap_macro_children(&mut app.terminal);
+                            if app.hidden_apps.contains_key(&id) {
+                                app.drop_app(id);
+                                window.request_redraw();
+                            } else if app.temple_apps.contains_key(&id)
+                                || app.windows.iter().any(|w| w.id == id)
+                            {
+                                use fmt::Write as _;
+                                let _ = writeln!(&mut app.terminal, "[temple app disconnected: {id}]");
+                                app.drop_app(id);
+                                window.request_redraw();
+                            }
+                            if let Some(test) = app.test.as_mut() {
+                                test.on_app_disconnected();
+                                if test.exit_now {
+                                    elwt.exit();
+                                }
                             }
                         }
                     }
-                },
+                }
                 Event::WindowEvent { event, window_id } if window_id == main_window_id => {
                     match event {
                         WindowEvent::CloseRequested => {
@@ -1921,6 +2122,59 @@ fn main() {
                                 window.request_redraw();
                                 return;
                             }
+                            if down
+                                && app.mods.alt
+                                && app.focused_app.is_none()
+                                && app.shell.in_doc_viewer()
+                            {
+                                let forward = match &event.logical_key {
+                                    Key::Named(NamedKey::ArrowLeft) => Some(false),
+                                    Key::Named(NamedKey::ArrowRight) => Some(true),
+                                    _ => None,
+                                };
+                                if let Some(forward) = forward {
+                                    app.shell.doc_go(forward, &mut app.terminal);
+                                    window.request_redraw();
+                                    return;
+                                }
+                            }
+                            if down && app.focused_app.is_none() && !app.shell.in_browser() {
+                                let step = match &event.logical_key {
+                                    Key::Named(NamedKey::ArrowUp) => Some((-1, 0)),
+                                    Key::Named(NamedKey::ArrowDown) => Some((1, 0)),
+                                    Key::Named(NamedKey::ArrowLeft) => Some((0, -1)),
+                                    Key::Named(NamedKey::ArrowRight) => Some((0, 1)),
+                                    _ => None,
+                                };
+                                let is_copy = app.mods.ctrl
+                                    && matches!(&event.logical_key, Key::Character(s) if s.eq_ignore_ascii_case("c"));
+                                if let Some((dy, dx)) = step.filter(|_| app.mods.shift) {
+                                    if app.shell.in_doc_viewer() {
+                                        app.shell.doc_select_step(dy, dx, &mut app.terminal);
+                                    } else {
+                                        app.terminal.select_step(dy, dx);
+                                    }
+                                    app.update_status_line();
+                                    window.request_redraw();
+                                    return;
+                                }
+                                if is_copy && app.shell.copy_selection(&mut app.terminal) {
+                                    window.request_redraw();
+                                    return;
+                                }
+                                let is_modifier = matches!(
+                                    &event.logical_key,
+                                    Key::Named(
+                                        NamedKey::Shift
+                                            | NamedKey::Control
+                                            | NamedKey::Alt
+                                            | NamedKey::Super
+                                    )
+                                );
+                                if !is_modifier && app.shell.clear_selection(&mut app.terminal) {
+                                    window.request_redraw();
+                                }
+                            }
                             if down && app.mods.ctrl {
                                 if let Key::Character(s) = &event.logical_key {
                                     if s.eq_ignore_ascii_case("w") {
@@ -2074,6 +2328,18 @@ fn main() {
                                 }
                             }
 
+                            if app.select_drag && app.mouse_left_down {
+                                let (x_u, y_u) = unzoom_point(x_u, y_u, app.shell.text_scale);
+                                let (col, row) = ((x_u + FONT_W / 2) / FONT_W, y_u / FONT_H);
+                                if app.shell.in_doc_viewer() {
+                                    app.shell.doc_select_at(col, row, true);
+                                    app.shell.render_doc_viewer(&mut app.terminal);
+                                } else {
+                                    app.terminal.select_at(col, row, true);
+                                }
+                                window.request_redraw();
+                            }
+
                             let new_hover = app.client_window_at_point(x, y);
                             if new_hover != app.hovered_app {
                                 if let Some(old) = app.hovered_app {
@@ -2137,6 +2403,11 @@ fn main() {
                                 app.mouse_left_down = down;
                                 if !down {
                                     app.drag = None;
+                                    if std::mem::take(&mut app.select_drag)
+                                        && app.shell.copy_selection(&mut app.terminal)
+                                    {
+                                        window.request_redraw();
+                                    }
                                 }
                             }
 
@@ -2171,6 +2442,19 @@ fn main() {
                                                 });
                                             }
                                         }
+                                        WindowHit::Client
+                                            if button == MouseButton::Left
+                                                && app.app_not_responding(id)
+                                                && app
+                                                    .windows
+                                                    .iter()
+                                                    .find(|w| w.id == id)
+                                                    .is_some_and(|w| {
+                                                        w.break_rect().contains(x, y)
+                                                    }) =>
+                                        {
+                                            app.break_app(id);
+                                        }
                                         WindowHit::Client => {
                                             app.mouse_capture_app = Some(id);
 
@@ -2220,6 +2504,8 @@ fn main() {
                                 app.update_status_line();
 
                                 if button == MouseButton::Left {
+                                    let (x_u, y_u) =
+                                        unzoom_point(x_u, y_u, app.shell.text_scale);
                                     if app.shell.in_browser() {
                                         let row = y_u / FONT_H;
                                         if let Some(idx) =
@@ -2248,6 +2534,13 @@ fn main() {
                                     } else if app.shell.in_doc_viewer() {
                                         let col = x_u / FONT_W;
                                         let row = y_u / FONT_H;
+                                        app.shell.doc_select_at(
+                                            (x_u + FONT_W / 2) / FONT_W,
+                                            row,
+                                            false,
+                                        );
+                                        app.shell.render_doc_viewer(&mut app.terminal);
+                                        app.select_drag = true;
                                         if let Some(idx) =
                                             app.shell.doc_click_cell(col, row, &mut app.terminal)
                                         {
@@ -2271,7 +2564,22 @@ fn main() {
                                                 app.browser_last_click = Some((now, idx));
                                             }
                                         }
+                                    } else {
+                                        app.terminal.select_at(
+                                            (x_u + FONT_W / 2) / FONT_W,
+                                            y_u / FONT_H,
+                                            false,
+                                        );
+                                        app.select_drag = true;
                                     }
+                                } else if app.shell.in_doc_viewer()
+                                    && (temple_button == protocol::MOUSE_BUTTON_BACK
+                                        || temple_button == protocol::MOUSE_BUTTON_FORWARD)
+                                {
+                                    app.shell.doc_go(
+                                        temple_button == protocol::MOUSE_BUTTON_FORWARD,
+                                        &mut app.terminal,
+                                    );
                                 }
 
                                 window.request_redraw();
@@ -2371,6 +2679,11 @@ fn main() {
                         elwt.exit();
                     }
                 }
+                Event::NewEvents(StartCause::ResumeTimeReached { .. }) => {
+                    // The watchdog deadline passed: redraw to show the "Not responding" banner.
+                    app.shell.reap_macro_children(&mut app.terminal);
+                    window.request_redraw();
+                }
                 _ => {}
             }
 
@@ -2385,6 +2698,64 @@ fn main() {
 mod tests {
     use super::*;
 
+    #[test]
+    fn break_button_sits_on_the_not_responding_banner() {
+        let win = AppWindow {
+            id: 1,
+            title: "App".to_string(),
+            rect: RectI32 {
+                x: 10,
+                y: 20,
+                w: 200,
+                h: 150,
+            },
+            closing: false,
+        };
+        let (client, button) = (win.client_rect(), win.break_rect());
+        assert!(client.contains(button.x, button.y));
+        assert!(button.y + button.h <= client.y + 28);
+        assert_eq!(button.w, 8 * BREAK_LABEL.len() as i32);
+        // An ordinary click in the client area is not a break.
+        assert!(!button.contains(client.x + client.w / 2, client.y + client.h / 2));
+    }
+
+    #[test]
+    fn a_busy_looping_app_is_flagged_until_it_answers() {
+        let (cmd_tx, cmd_rx) = mpsc::channel();
+        let fb = memmap2::MmapOptions::new()
+            .len(16)
+            .map_anon()
+            .and_then(|map| map.make_read_only())
+            .expect("map");
+        let mut sess = TempleAppSession {
+            fb,
+            width: 4,
+            height: 4,
+            cmd_tx,
+            pending_present_ack_seq: None,
+            unanswered_input_since: None,
+        };
+        let now = std::time::Instant::now();
+
+        // Frames and other output don't start the watchdog; input does.
+        assert!(sess.send(protocol::Msg::present_ack(1)));
+        assert_eq!(sess.hang_deadline(), None);
+        assert!(sess.send(protocol::Msg::key(b'x' as u32, true)));
+        let deadline = sess.hang_deadline().expect("watching");
+        assert!(sess.send(protocol::Msg::key(b'x' as u32, false)));
+        assert_eq!(sess.hang_deadline(), Some(deadline), "first unanswered input counts");
+
+        // A loop that polls input but never yields sends nothing back (no ack, no frame).
+        assert!(!sess.not_responding_at(now));
+        assert!(sess.not_responding_at(deadline));
+        sess.note_alive();
+        assert!(!sess.not_responding_at(deadline + APP_HANG_TIMEOUT));
+        assert_eq!(cmd_rx.try_iter().count(), 3);
+
+        drop(cmd_rx);
+        assert!(!sess.send(protocol::Msg::key(b'x' as u32, true)));
+    }
+
     #[test]
     fn letterbox_integer_scale_with_letterbox() {
         let lb = Letterbox::new(1920, 1080);
@@ -2475,4 +2846,467 @@ mod tests {
         let spec = resolve_templeos_program_spec(&programs, "Demo/Print.HC").expect("resolve");
         assert_eq!(spec, "::/Demo/Print.HC");
     }
+
+    #[test]
+    fn doc_form_entries_write_back_into_their_commands() {
+        use temple_rt::doldoc::{FormEntry, FormKind, parse_cmd};
+
+        let raw = "CB+C,\"Sound\",LE=1";
+        let mut cb = FormEntry::from_cmd(&parse_cmd(raw)).expect("check box");
+        assert_eq!(cb.kind, FormKind::CheckBox);
+        assert!(cb.checked);
+        assert_eq!(cb.display(), "[X] Sound");
+        cb.checked = false;
+        assert_eq!(cb.apply_to(raw), "CB,\"Sound\",LE=1");
+
+        let raw = "DA-P,A=\"Level:%d\",\"3\"";
+        let mut da = FormEntry::from_cmd(&parse_cmd(raw)).expect("data field");
+        assert_eq!(da.display(), "Level:3");
+        assert!(da.is_numeric());
+        da.tag = "12".to_string();
+        assert_eq!(da.apply_to(raw), "DA-P,A=\"Level:%d\",\"12\"");
+
+        let raw = "LS,D=\"ST_COLORS\"";
+        let mut ls = FormEntry::from_cmd(&parse_cmd(raw)).expect("list");
+        let items = temple_rt::doldoc::builtin_define_list("ST_COLORS").expect("colors");
+        ls.tag = ls.next_list_value(items).expect("first color");
+        assert_eq!(ls.tag, items[0]);
+        assert_eq!(ls.apply_to(raw), format!("LS,\"{}\",D=\"ST_COLORS\"", items[0]));
+    }
+
+    #[test]
+    fn doc_forms_save_only_on_request_and_keep_the_file_bytes() {
+        let mut shell = Shell::new(true);
+        let mut term = Terminal::new(COLOR_FG, COLOR_BG, 30);
+        std::fs::write(
+            shell.root_dir.join("Home/Lists.HC"),
+            "DefineLstLoad(\"ST_SIZES\", \"Small\\0\"\n  \"Big\\0\");\n",
+        )
+        .expect("write");
+        // CP437 text (0x82 is 'é') and a bin tail no entry refers to.
+        let mut original = b"Caf\x82 $CB,\"On\"$ $LS,\"Small\",D=\"ST_SIZES\"$\n".to_vec();
+        let tail = [0u8, 7, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 9, 0, 0, 0, 0xAA, 0xBB];
+        original.extend_from_slice(&tail);
+        let host = shell.root_dir.join("Doc/Form.DD");
+        std::fs::write(&host, &original).expect("write");
+
+        assert!(shell.try_show_doc("/Doc/Form.DD", &mut term));
+        shell.activate_doc_form(0, &mut term);
+        shell.activate_doc_form(1, &mut term);
+        assert_eq!(std::fs::read(&host).expect("read"), original, "saved before `s`");
+
+        assert!(shell.handle_key(&Key::Character("s".into()), &mut term));
+        let mut expected = b"Caf\x82 $CB+C,\"On\"$ $LS,\"Big\",D=\"ST_SIZES\"$\n".to_vec();
+        expected.extend_from_slice(&tail);
+        assert_eq!(std::fs::read(&host).expect("read"), expected);
+        assert_eq!(shell.doc_viewer.as_ref().unwrap().msg, "saved");
+
+        // Docs from the TempleOS tree are never written.
+        shell.doc_viewer.as_mut().unwrap().spec = "::/Doc/Form.DD".to_string();
+        shell.activate_doc_form(0, &mut term);
+        assert!(shell.handle_key(&Key::Character("s".into()), &mut term));
+        assert_eq!(std::fs::read(&host).expect("read"), expected);
+        assert!(shell.doc_viewer.as_ref().unwrap().msg.contains("read-only"));
+        let _ = std::fs::remove_dir_all(&shell.root_dir);
+    }
+
+    #[test]
+    fn macro_sessions_are_matched_by_pid_and_reported_when_they_die_unconnected() {
+        let mut shell = Shell::new(true);
+        let mut term = Terminal::new(COLOR_FG, COLOR_BG, 30);
+        std::fs::write(shell.root_dir.join("Doc/Macro.DD"), "Hi\n").expect("write");
+        assert!(shell.try_show_doc("/Doc/Macro.DD", &mut term));
+
+        let spawn = |code: &str| {
+            std::process::Command::new("sh")
+                .args(["-c", code])
+                .spawn()
+                .expect("spawn sh")
+        };
+        let running = spawn("sleep 5");
+        let running_pid = running.id();
+        shell.macro_children.push(MacroChild {
+            child: running,
+            title: "Cd(\"::/Demo\");".to_string(),
+            connected: false,
+        });
+        let mut dead = spawn("exit 3");
+        dead.wait().expect("wait");
+        shell.macro_children.push(MacroChild {
+            child: dead,
+            title: "Oops;".to_string(),
+            connected: false,
+        });
+        shell.queue_window_title("Game".to_string());
+
+        shell.reap_macro_children(&mut term);
+        let msg = &shell.doc_viewer.as_ref().unwrap().msg;
+        assert!(msg.starts_with("macro: temple-hc exited before connecting"), "{msg}");
+        assert_eq!(shell.macro_children.len(), 1);
+
+        // Another app's queued title is left alone.
+        assert_eq!(shell.take_macro_window(Some(running_pid + 100_000)), None);
+        assert_eq!(
+            shell.take_macro_window(Some(running_pid)).as_deref(),
+            Some("Cd(\"::/Demo\");")
+        );
+        assert!(!shell.macro_children_pending());
+        assert_eq!(shell.take_queued_window_title().as_deref(), Some("Game"));
+        assert_eq!(shell.take_queued_window_kind(), PendingWindowKind::Normal);
+
+        for c in &mut shell.macro_children {
+            let _ = c.child.kill();
+            let _ = c.child.wait();
+        }
+        let _ = std::fs::remove_dir_all(&shell.root_dir);
+    }
+
+    #[test]
+    fn doldoc_trees_collapse_their_indented_subtree() {
+        let text = concat!(
+            "$TR+C,\"Closed\"$\n",
+            "$ID,2$$AN,\"\",A=\"deep\"$hidden\n",
+            "$TR,\"Inner\"$\n",
+            "$ID,2$also hidden\n",
+            "$ID,-4$$TR,\"Open\"$\n",
+            "$ID,2$shown\n",
+            "$TR+C,\"Nested\"$\n",
+            "$ID,2$nested body\n",
+            "$ID,-2$after nested\n",
+            "$ID,-2$$ID,-2$back out\n",
+            "$ID,2$indented again\n",
+        );
+        let lines = |built: &BuiltDoc| -> Vec<String> {
+            built
+                .lines
+                .iter()
+                .map(|l| l.iter().map(|c| c.ch as char).collect::<String>())
+                .collect()
+        };
+
+        let built = Shell::build_doldoc(text, &Default::default(), &Default::default());
+        assert_eq!(
+            lines(&built),
+            vec![
+                "+]Closed",
+                "-]Open",
+                "  shown",
+                "  +]Nested",
+                "  after nested",
+                "back out",
+                // The `$ID,-2$` that went below zero is balanced by this `$ID,2$`.
+                "indented again",
+            ]
+        );
+        assert_eq!(built.anchors.get("deep"), Some(&0));
+        let trees: Vec<_> = built
+            .trees
+            .iter()
+            .map(|t| (t.line, t.collapsed, t.hidden))
+            .collect();
+        assert_eq!(
+            trees,
+            vec![(0, true, false), (0, false, true), (1, false, false), (3, true, false)]
+        );
+        assert_eq!(built.links[0].target, DocLinkTarget::Tree(0));
+
+        let open: temple_rt::doldoc::TreeState = [(0, false), (3, false)].into_iter().collect();
+        let built = Shell::build_doldoc(text, &Default::default(), &open);
+        assert_eq!(&lines(&built)[..4], ["-]Closed", "  hidden", "  -]Inner", "    also hidden"]);
+        assert_eq!(built.anchors.get("deep"), Some(&1));
+        assert!(lines(&built).contains(&"    nested body".to_string()));
+    }
+
+    #[test]
+    fn doc_viewer_tree_toggles_are_remembered_per_document() {
+        let mut shell = Shell::new(true);
+        let mut term = Terminal::new(COLOR_FG, COLOR_BG, 30);
+        std::fs::write(
+            shell.root_dir.join("Doc/Tree.DD"),
+            "$TR+C,\"Topics\"$\n$ID,2$one\ntwo\n$ID,-2$end\n",
+        )
+        .expect("write");
+        assert!(shell.try_show_doc("/Doc/Tree.DD", &mut term));
+        let line_count = |shell: &Shell| shell.doc_viewer.as_ref().unwrap().lines.len();
+        assert_eq!(line_count(&shell), 2);
+
+        // The tree is the selected link: Enter opens it, Left closes it again.
+        assert!(shell.handle_key(&Key::Named(NamedKey::Enter), &mut term));
+        assert_eq!(line_count(&shell), 4);
+        assert!(shell.handle_key(&Key::Named(NamedKey::ArrowLeft), &mut term));
+        assert_eq!(line_count(&shell), 2);
+        assert!(shell.handle_key(&Key::Character("+".into()), &mut term));
+        assert_eq!(line_count(&shell), 4);
+
+        shell.doc_viewer = None;
+        assert!(shell.try_show_doc("/Doc/Tree.DD", &mut term));
+        assert_eq!(line_count(&shell), 4);
+        let _ = std::fs::remove_dir_all(&shell.root_dir);
+    }
+
+    #[test]
+    fn file_browser_previews_the_selected_image() {
+        let mut shell = Shell::new(true);
+        let mut term = Terminal::new(COLOR_FG, COLOR_BG, 30);
+        let mut art = temple_rt::doldoc::Canvas::new(3, 2, temple_rt::rt::TRANSPARENT);
+        art.pixels[0] = 4;
+        let home = shell.root_dir.join("Home");
+        std::fs::write(
+            home.join("Pic.GR"),
+            temple_rt::image::encode_gr(&art, true, None),
+        )
+        .expect("write");
+        std::fs::write(home.join("Notes.txt"), "not an image\n").expect("write");
+        std::fs::write(home.join("Bad.BMP"), "BM").expect("write");
+
+        shell.cmd_files(&["/Home"], &mut term);
+        let select = |shell: &mut Shell, name: &str| {
+            let state = shell.browser.as_mut().unwrap();
+            state.selected = state.entries.iter().position(|e| e.name == name).unwrap();
+            shell.browser_preview().map(|c| (c.width, c.height, c.pixels.clone()))
+        };
+        let (w, h, pixels) = select(&mut shell, "Pic.GR").expect("preview");
+        assert_eq!((w, h), (3, 2));
+        assert_eq!(pixels[..2], [4, COLOR_BG]);
+        assert!(select(&mut shell, "Notes.txt").is_none());
+        assert!(select(&mut shell, "Bad.BMP").is_none());
+        let _ = std::fs::remove_dir_all(&shell.root_dir);
+    }
+
+    /// An 8x16 PSF2 font whose `A` is a single dot at the top left.
+    fn dot_psf2() -> Vec<u8> {
+        let mut psf = vec![0x72, 0xB5, 0x4A, 0x86];
+        for v in [0u32, 32, 0, 256, 16, 16, 8] {
+            psf.extend_from_slice(&v.to_le_bytes());
+        }
+        let mut glyphs = vec![0u8; 256 * 16];
+        glyphs[b'A' as usize * 16] = 0x80;
+        psf.extend_from_slice(&glyphs);
+        psf
+    }
+
+    #[test]
+    fn font_command_loads_user_fonts_and_saves_them_in_vars() {
+        let mut shell = Shell::new(true);
+        let mut term = Terminal::new(COLOR_FG, COLOR_BG, 30);
+        let fonts = shell.root_dir.join(temple_rt::font::FONTS_DIR);
+        std::fs::write(fonts.join("Dot.psf"), dot_psf2()).expect("write");
+        std::fs::write(fonts.join("README.txt"), "not a font\n").expect("write");
+        assert_eq!(temple_rt::font::list_fonts(&shell.root_dir), ["Dot"]);
+
+        shell.cmd_font(&["Dot"], &mut term);
+        assert_eq!(shell.vars.get("TEMPLE_FONT").map(String::as_str), Some("Dot"));
+        assert_eq!(term.font[b'A' as usize], 1);
+        shell.cmd_font(&["Missing"], &mut term);
+        assert_eq!(shell.vars.get("TEMPLE_FONT").map(String::as_str), Some("Dot"));
+
+        shell.cmd_font(&["scale", "3"], &mut term);
+        assert_eq!(shell.text_scale, 3);
+        assert_eq!(shell.vars.get("TEMPLE_TEXT_SCALE").map(String::as_str), Some("3"));
+        shell.cmd_font(&["scale", "9"], &mut term);
+        assert_eq!(shell.text_scale, 3);
+
+        // Restoring the vars brings the font and zoom back on a fresh terminal.
+        let mut fresh = Terminal::new(COLOR_FG, COLOR_BG, 30);
+        shell.text_scale = 1;
+        shell.restore_font(&mut fresh);
+        assert_eq!((fresh.font[b'A' as usize], shell.text_scale), (1, 3));
+
+        shell.cmd_font(&["std"], &mut term);
+        shell.cmd_font(&["scale", "1"], &mut term);
+        assert!(!shell.vars.contains_key("TEMPLE_FONT"));
+        assert!(!shell.vars.contains_key("TEMPLE_TEXT_SCALE"));
+        assert_eq!(term.font[b'A' as usize], assets::TEMPLEOS_SYS_FONT_STD_U64[b'A' as usize]);
+
+        // With 2x zoom the screen shows the bottom-left quarter of the terminal.
+        assert_eq!(unzoom_point(0, 0, 1), (0, 0));
+        assert_eq!(unzoom_point(100, 40, 2), (50, INTERNAL_H / 2 + 20));
+        let _ = std::fs::remove_dir_all(&shell.root_dir);
+    }
+
+    #[test]
+    fn doc_index_ranks_matches_and_refreshes_incrementally() {
+        let dir = std::env::temp_dir().join(format!("templelinux-doc-index-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("Doc")).expect("mkdir");
+        std::fs::write(
+            dir.join("Doc/Sprites.DD"),
+            "$FG,RED$Sprite$FG$ intro\nDraw a sprite with $LK,\"Sprite3\",A=\"MN:Sprite3\"$\n",
+        )
+        .expect("write");
+        std::fs::write(dir.join("Doc/Other.HC"), "// mentions a sprite once\nU0 Main() {}\n")
+            .expect("write");
+        std::fs::write(dir.join("Doc/Notes.txt"), "sprite sprite sprite\n").expect("write");
+
+        let roots = [("/", dir.clone())];
+        let mut index = DocIndex::default();
+        assert!(index.refresh(&roots));
+        assert_eq!(index.docs.len(), 2);
+
+        let hits = index.search("Sprite");
+        let specs: Vec<&str> = hits.iter().map(|h| h.spec.as_str()).collect();
+        assert_eq!(specs, vec!["/Doc/Sprites.DD", "/Doc/Other.HC"]);
+        let hits = index.search("draw sprite3");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].line, 2);
+        // Command names and arguments aren't document text.
+        assert!(index.search("fg red").is_empty());
+
+        let path = dir.join("DocIndex.txt");
+        index.save(&path).expect("save");
+        let mut loaded = DocIndex::load(&path);
+        assert!(!loaded.refresh(&roots));
+        assert_eq!(loaded.search("sprite").len(), 2);
+
+        std::fs::write(dir.join("Doc/Other.HC"), "U0 Main() {}\n").expect("write");
+        assert!(loaded.refresh(&roots));
+        assert_eq!(loaded.search("sprite").len(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn doc_viewer_find_walks_matches_and_wraps() {
+        let lines: Vec<Vec<Cell>> = ["Alpha beta", "gamma", "BETA beta"]
+            .iter()
+            .map(|s| {
+                s.bytes()
+                    .map(|ch| Cell {
+                        ch,
+                        fg: COLOR_FG,
+                        bg: COLOR_BG,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let find = |from, back| Shell::find_in_lines(&lines, "Beta", from, back);
+        assert_eq!(find((0, 0), false), Some(((0, 6, 10), false)));
+        assert_eq!(find((0, 7), false), Some(((2, 0, 4), false)));
+        assert_eq!(find((2, 1), false), Some(((2, 5, 9), false)));
+        assert_eq!(find((2, 6), false), Some(((0, 6, 10), true)));
+        assert_eq!(find((2, 5), true), Some(((2, 0, 4), false)));
+        assert_eq!(find((2, 0), true), Some(((0, 6, 10), false)));
+        assert_eq!(find((0, 6), true), Some(((2, 5, 9), true)));
+        assert_eq!(Shell::find_in_lines(&lines, "delta", (0, 0), false), None);
+    }
+
+    #[test]
+    fn doc_viewer_history_and_bookmarks_restore_position() {
+        let mut shell = Shell::new(true);
+        let mut term = Terminal::new(COLOR_FG, COLOR_BG, 30);
+        let long: String = (0..200).map(|i| format!("line {i}\n")).collect();
+        std::fs::write(shell.root_dir.join("Doc/Long.DD"), &long).expect("write");
+        std::fs::write(
+            shell.root_dir.join("Doc/Short.DD"),
+            "$AN,\"\",A=\"Mid\"$top\n$LK,\"long\",A=\"FF:::Doc/Long.DD\"$\n",
+        )
+        .expect("write");
+        let spec = |shell: &Shell| shell.doc_viewer.as_ref().map(|s| s.spec.clone());
+        let scroll = |shell: &Shell| shell.doc_viewer.as_ref().map_or(0, |s| s.scroll);
+
+        assert!(shell.try_show_doc("/Doc/Long.DD", &mut term));
+        shell.doc_viewer.as_mut().unwrap().scroll = 120;
+        assert!(shell.try_show_doc("/Doc/Short.DD", &mut term));
+        assert!(shell.try_show_doc("Mid", &mut term));
+        assert_eq!(shell.doc_back.len(), 2);
+
+        assert!(shell.handle_key(&Key::Named(NamedKey::Backspace), &mut term));
+        assert!(shell.doc_go(false, &mut term));
+        assert_eq!(spec(&shell).as_deref(), Some("/Doc/Long.DD"));
+        assert_eq!(scroll(&shell), 120);
+        assert!(shell.doc_go(true, &mut term));
+        assert_eq!(spec(&shell).as_deref(), Some("/Doc/Short.DD"));
+
+        // Following a link drops the forward history.
+        assert!(shell.try_show_doc("FF:::Doc/Long.DD", &mut term));
+        assert!(shell.doc_forward.is_empty());
+        assert!(shell.doc_go(true, &mut term));
+        assert_eq!(shell.doc_viewer.as_ref().unwrap().msg, "no next page");
+
+        shell.doc_viewer.as_mut().unwrap().scroll = 42;
+        shell.add_doc_bookmark("Long $ list\t");
+        assert_eq!(
+            load_doc_bookmarks(&shell.bookmarks_path()),
+            vec![DocBookmark {
+                name: "Long list".to_string(),
+                spec: "/Doc/Long.DD".to_string(),
+                line: 42,
+            }]
+        );
+        shell.doc_viewer = None;
+        assert!(shell.try_show_doc("BM:Long list", &mut term));
+        assert_eq!(scroll(&shell), 42);
+        assert!(shell.doc_back.is_empty());
+
+        shell.cmd_bookmarks(&["rm", "Long", "list"], &mut term);
+        assert!(load_doc_bookmarks(&shell.bookmarks_path()).is_empty());
+        let _ = std::fs::remove_dir_all(&shell.root_dir);
+    }
+
+    #[test]
+    fn terminal_selection_copies_plain_unicode_text() {
+        use fmt::Write as _;
+
+        let mut term = Terminal::new(COLOR_FG, COLOR_BG, 4);
+        let _ = write!(term, "first line   \ncafé ░ box\nthird\nfourth\nfifth");
+        // "first line" has scrolled into the scrollback; row 0 shows "café ░ box".
+        assert_eq!(term.history_line_at_row(0), Some(1));
+
+        term.select_at(5, 0, false);
+        term.select_at(3, 1, true);
+        assert_eq!(term.selected_text().as_deref(), Some("░ box\nthi"));
+
+        // Dragging backwards selects the same way.
+        term.select_at(3, 1, false);
+        term.select_at(5, 0, true);
+        assert_eq!(term.selected_text().as_deref(), Some("░ box\nthi"));
+
+        // Shift+arrows start at the output cursor and scroll the view to follow.
+        term.selection = None;
+        term.select_step(0, -5);
+        assert_eq!(term.selected_text().as_deref(), Some("fifth"));
+        term.select_step(-4, 0);
+        assert_eq!(term.view_offset(), 1);
+        assert_eq!(
+            term.selected_text().as_deref(),
+            Some("first line\ncafé ░ box\nthird\nfourth\nfifth")
+        );
+    }
+
+    #[test]
+    fn doc_viewer_selection_follows_the_document() {
+        let mut shell = Shell::new(true);
+        let mut term = Terminal::new(COLOR_FG, COLOR_BG, 30);
+        let text: String = (0..100).map(|i| format!("row {i}\n")).collect();
+        shell.open_doc_viewer(
+            "t".to_string(),
+            DocKind::PlainText,
+            &text,
+            Default::default(),
+            false,
+            None,
+            &mut term,
+        );
+        let selected = |shell: &Shell| {
+            let state = shell.doc_viewer.as_ref().unwrap();
+            state
+                .selection
+                .map(|sel| sel.text(|l| state.lines.get(l).map(Vec::as_slice)))
+        };
+
+        shell.doc_select_at(4, 1, false);
+        shell.doc_select_at(2, 2, true);
+        assert_eq!(selected(&shell).as_deref(), Some("0\nro"));
+
+        shell.doc_viewer.as_mut().unwrap().selection = None;
+        let rows = Shell::doc_view_rows() as isize;
+        shell.doc_select_step(rows + 2, 0, &mut term);
+        let state = shell.doc_viewer.as_ref().unwrap();
+        assert_eq!(state.scroll, 3);
+        assert_eq!(state.selection.unwrap().head, (rows as usize + 2, 0));
+
+        assert!(shell.clear_selection(&mut term));
+        assert_eq!(selected(&shell), None);
+        let _ = std::fs::remove_dir_all(&shell.root_dir);
+    }
 }
@@ -0,0 +1,372 @@
+/// Full-text index behind `search`: the words of every `.DD`, `.HC` and `.HH` file in the
+/// TempleOS tree and under TEMPLE_ROOT, DolDoc commands stripped. It lives in
+/// `Cfg/DocIndex.txt` and is refreshed before each search; files whose size and mtime haven't
+/// changed are not read again.
+#[derive(Debug, Default)]
+struct DocIndex {
+    docs: Vec<IndexedDoc>,
+}
+
+#[derive(Debug)]
+struct IndexedDoc {
+    /// `::/Doc/Foo.DD` (TempleOS tree) or `/Home/Notes.DD` (TEMPLE_ROOT).
+    spec: String,
+    /// Where the file was found by the last refresh (not persisted).
+    host: PathBuf,
+    mtime: u64,
+    len: u64,
+    /// Word -> source lines (from 1) it occurs on.
+    words: std::collections::BTreeMap<String, Vec<u32>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct DocSearchHit {
+    spec: String,
+    host: PathBuf,
+    score: f64,
+    /// Source line (from 1) matching the most query words.
+    line: u32,
+}
+
+const DOC_INDEX_MAGIC: &str = "# TempleShell doc index v1";
+const DOC_INDEX_EXTS: [&str; 3] = ["dd", "hc", "hh"];
+const DOC_INDEX_MAX_BYTES: u64 = 2 * 1024 * 1024;
+const DOC_INDEX_MAX_WORD: usize = 48;
+
+/// Lowercased words (letters, digits, `_`) of at least two characters.
+fn doc_index_words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .filter(|w| (2..=DOC_INDEX_MAX_WORD).contains(&w.len()))
+        .map(|w| w.to_ascii_lowercase())
+}
+
+fn file_stamp(meta: &std::fs::Metadata) -> (u64, u64) {
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    (mtime, meta.len())
+}
+
+/// The document text of an indexed file: DolDoc commands and the binary tail stripped.
+fn read_doc_plain_text(host: &Path) -> Option<String> {
+    use std::io::Read as _;
+
+    let file = std::fs::File::open(host).ok()?;
+    let mut buf = Vec::new();
+    file.take(DOC_INDEX_MAX_BYTES).read_to_end(&mut buf).ok()?;
+    let (text, _bins) = temple_rt::doldoc::parse_doc_blob(&buf);
+    Some(temple_rt::doldoc::plain_text(&text))
+}
+
+impl IndexedDoc {
+    fn read(spec: String, host: PathBuf, mtime: u64, len: u64) -> Self {
+        let mut words: std::collections::BTreeMap<String, Vec<u32>> = Default::default();
+        let text = read_doc_plain_text(&host).unwrap_or_default();
+        for (idx, line) in text.lines().enumerate() {
+            let line_no = idx as u32 + 1;
+            for word in doc_index_words(line) {
+                let lines = words.entry(word).or_default();
+                if lines.last() != Some(&line_no) {
+                    lines.push(line_no);
+                }
+            }
+        }
+        Self {
+            spec,
+            host,
+            mtime,
+            len,
+            words,
+        }
+    }
+
+    fn file_name(&self) -> &str {
+        self.spec.rsplit('/').next().unwrap_or(&self.spec)
+    }
+}
+
+impl DocIndex {
+    fn load(path: &Path) -> Self {
+        let Ok(text) = std::fs::read_to_string(path) else {
+            return Self::default();
+        };
+        let mut lines = text.lines();
+        if lines.next() != Some(DOC_INDEX_MAGIC) {
+            return Self::default();
+        }
+
+        let mut docs: Vec<IndexedDoc> = Vec::new();
+        for line in lines {
+            if let Some(rest) = line.strip_prefix("D ") {
+                let mut it = rest.splitn(3, ' ');
+                let mtime = it.next().and_then(|v| v.parse::<u64>().ok());
+                let len = it.next().and_then(|v| v.parse::<u64>().ok());
+                let (Some(mtime), Some(len), Some(spec)) = (mtime, len, it.next()) else {
+                    return Self::default();
+                };
+                docs.push(IndexedDoc {
+                    spec: spec.to_string(),
+                    host: PathBuf::new(),
+                    mtime,
+                    len,
+                    words: Default::default(),
+                });
+                continue;
+            }
+            let Some(doc) = docs.last_mut() else {
+                return Self::default();
+            };
+            let mut it = line.split(' ');
+            let Some(word) = it.next().filter(|w| !w.is_empty()) else {
+                continue;
+            };
+            let lines: Vec<u32> = it.filter_map(|v| v.parse::<u32>().ok()).collect();
+            doc.words.insert(word.to_string(), lines);
+        }
+        Self { docs }
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        use std::fmt::Write as _;
+
+        let mut out = String::new();
+        out.push_str(DOC_INDEX_MAGIC);
+        out.push('\n');
+        for doc in &self.docs {
+            let _ = writeln!(out, "D {} {} {}", doc.mtime, doc.len, doc.spec);
+            for (word, lines) in &doc.words {
+                out.push_str(word);
+                for line in lines {
+                    let _ = write!(out, " {line}");
+                }
+                out.push('\n');
+            }
+        }
+        std::fs::write(path, out)
+    }
+
+    /// Rescans `roots` (spec prefix, host dir), re-reading new and changed files and dropping
+    /// deleted ones. Returns whether anything changed.
+    fn refresh(&mut self, roots: &[(&str, PathBuf)]) -> bool {
+        let mut found: Vec<(String, PathBuf, u64, u64)> = Vec::new();
+        for (prefix, root) in roots {
+            let mut stack = vec![root.clone()];
+            while let Some(dir) = stack.pop() {
+                let Ok(entries) = std::fs::read_dir(&dir) else {
+                    continue;
+                };
+                for entry in entries.flatten() {
+                    let Ok(ft) = entry.file_type() else {
+                        continue;
+                    };
+                    let host = entry.path();
+                    if ft.is_dir() {
+                        stack.push(host);
+                        continue;
+                    }
+                    let indexed = host
+                        .extension()
+                        .and_then(|s| s.to_str())
+                        .is_some_and(|ext| {
+                            DOC_INDEX_EXTS.iter().any(|e| ext.eq_ignore_ascii_case(e))
+                        });
+                    if !indexed {
+                        continue;
+                    }
+                    let Ok(meta) = entry.metadata() else {
+                        continue;
+                    };
+                    let Ok(rel) = host.strip_prefix(root) else {
+                        continue;
+                    };
+                    let rel = rel.to_string_lossy().replace('\\', "/");
+                    let (mtime, len) = file_stamp(&meta);
+                    found.push((format!("{prefix}{rel}"), host, mtime, len));
+                }
+            }
+        }
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        found.dedup_by(|a, b| a.0 == b.0);
+
+        let mut old: std::collections::HashMap<String, IndexedDoc> = self
+            .docs
+            .drain(..)
+            .map(|doc| (doc.spec.clone(), doc))
+            .collect();
+        let mut changed = false;
+        for (spec, host, mtime, len) in found {
+            match old.remove(&spec) {
+                Some(mut doc) if doc.mtime == mtime && doc.len == len => {
+                    doc.host = host;
+                    self.docs.push(doc);
+                }
+                _ => {
+                    changed = true;
+                    self.docs.push(IndexedDoc::read(spec, host, mtime, len));
+                }
+            }
+        }
+        changed || !old.is_empty()
+    }
+
+    /// Documents containing every word of `query`, best first. Scores are tf-idf with a bonus
+    /// for words in the file name.
+    fn search(&self, query: &str) -> Vec<DocSearchHit> {
+        let mut words: Vec<String> = doc_index_words(query).collect();
+        words.sort();
+        words.dedup();
+        if words.is_empty() {
+            return Vec::new();
+        }
+
+        let total = self.docs.len().max(1) as f64;
+        let idf: Vec<f64> = words
+            .iter()
+            .map(|w| {
+                let df = self.docs.iter().filter(|d| d.words.contains_key(w)).count();
+                (1.0 + total / (df.max(1) as f64)).ln()
+            })
+            .collect();
+
+        let mut hits = Vec::new();
+        for doc in &self.docs {
+            let Some(postings) = words
+                .iter()
+                .map(|w| doc.words.get(w))
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            let name = doc.file_name().to_ascii_lowercase();
+            let mut score = 0.0;
+            let mut per_line: std::collections::BTreeMap<u32, usize> = Default::default();
+            for ((word, lines), idf) in words.iter().zip(&postings).zip(&idf) {
+                score += (1.0 + lines.len() as f64).ln() * idf;
+                if name.contains(word.as_str()) {
+                    score += 2.0 * idf;
+                }
+                for &line in lines.iter() {
+                    *per_line.entry(line).or_default() += 1;
+                }
+            }
+            // The first line with the most distinct query words.
+            let line = per_line
+                .iter()
+                .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+                .map(|(&line, _)| line)
+                .unwrap_or(1);
+            hits.push(DocSearchHit {
+                spec: doc.spec.clone(),
+                host: doc.host.clone(),
+                score,
+                line,
+            });
+        }
+        hits.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.spec.cmp(&b.spec))
+        });
+        hits
+    }
+}
+
+impl Shell {
+    fn doc_index_path(&self) -> PathBuf {
+        self.cfg_dir().join("DocIndex.txt")
+    }
+
+    /// Loads (first use) and refreshes the doc index, saving it when files changed.
+    fn refresh_doc_index(&mut self) -> &DocIndex {
+        let mut roots: Vec<(&str, PathBuf)> = Vec::new();
+        if let Some(templeos_root) = discover_templeos_root() {
+            roots.push(("::/", templeos_root));
+        }
+        roots.push(("/", self.root_dir.clone()));
+
+        let path = self.doc_index_path();
+        let index = self
+            .doc_index
+            .get_or_insert_with(|| DocIndex::load(&path));
+        if index.refresh(&roots) {
+            let _ = index.save(&path);
+        }
+        index
+    }
+
+    fn cmd_search(&mut self, args: &[&str], term: &mut Terminal) {
+        use fmt::Write as _;
+
+        const MAX_RESULTS: usize = 100;
+        const SNIPPET_COLS: usize = 72;
+        const JUMP_CHARS: usize = 40;
+
+        let query = args.join(" ");
+        if doc_index_words(&query).next().is_none() {
+            let _ = writeln!(term, "search: expected: search <terms>");
+            return;
+        }
+
+        let index = self.refresh_doc_index();
+        let indexed = index.docs.len();
+        let hits = index.search(&query);
+
+        let mut doc = String::new();
+        let _ = writeln!(
+            doc,
+            "$FG,14${}$FG$  {} result{} in {indexed} files\n",
+            temple_rt::doldoc::escape_text(&query),
+            hits.len(),
+            if hits.len() == 1 { "" } else { "s" },
+        );
+        for hit in hits.iter().take(MAX_RESULTS) {
+            let line = read_doc_plain_text(&hit.host)
+                .and_then(|text| {
+                    text.lines()
+                        .nth(hit.line.saturating_sub(1) as usize)
+                        .map(|l| l.trim().to_string())
+                })
+                .unwrap_or_default();
+            // `FF:<file>,<text>` opens the file at the first line showing `<text>`.
+            let jump: String = line
+                .split('$')
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .take(JUMP_CHARS)
+                .collect();
+            let file = match hit.spec.strip_prefix('/') {
+                Some(rel) => format!("::{rel}"),
+                None => hit.spec.clone(),
+            };
+            let target = format!("FF:{file},{}", jump.trim()).replace('$', "");
+            let _ = writeln!(
+                doc,
+                "$LK,{},A={}$ $FG,8$line {}$FG$",
+                temple_rt::doldoc::quote_arg(&hit.spec.replace('$', "")),
+                temple_rt::doldoc::quote_arg(&target),
+                hit.line
+            );
+            let snippet: String = line.chars().take(SNIPPET_COLS).collect();
+            let _ = writeln!(doc, "  {}", temple_rt::doldoc::escape_text(&snippet));
+        }
+        if hits.len() > MAX_RESULTS {
+            let _ = writeln!(doc, "\n({} more not shown)", hits.len() - MAX_RESULTS);
+        }
+
+        self.last_search = query.clone();
+        self.open_doc_viewer(
+            format!("search {query}"),
+            DocKind::DolDoc,
+            &doc,
+            Default::default(),
+            false,
+            None,
+            term,
+        );
+    }
+}
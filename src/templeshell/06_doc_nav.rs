@@ -0,0 +1,319 @@
+/// Pages kept on each side of the doc viewer's back/forward history.
+const DOC_HISTORY_MAX: usize = 64;
+
+/// A named place in a document: opened with `try_show_doc(spec)`, then scrolled to `line`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct DocBookmark {
+    name: String,
+    spec: String,
+    line: usize,
+}
+
+/// Reads `Cfg/Bookmarks.txt`: one `name<TAB>spec<TAB>line` per line, `#` comments.
+fn load_doc_bookmarks(path: &Path) -> Vec<DocBookmark> {
+    let Ok(buf) = std::fs::read(path) else {
+        return Vec::new();
+    };
+    let text = String::from_utf8_lossy(&buf);
+    let mut out = Vec::new();
+    for line in text.lines() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.splitn(3, '\t');
+        let (Some(name), Some(spec)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let (name, spec) = (name.trim(), spec.trim());
+        if name.is_empty() || spec.is_empty() {
+            continue;
+        }
+        let line = parts
+            .next()
+            .and_then(|l| l.trim().parse().ok())
+            .unwrap_or(0);
+        out.push(DocBookmark {
+            name: name.to_string(),
+            spec: spec.to_string(),
+            line,
+        });
+    }
+    out
+}
+
+fn save_doc_bookmarks(path: &Path, bookmarks: &[DocBookmark]) -> std::io::Result<()> {
+    let mut out = String::new();
+    for bm in bookmarks {
+        out.push_str(&format!("{}\t{}\t{}\n", bm.name, bm.spec, bm.line));
+    }
+    std::fs::write(path, out)
+}
+
+/// Bookmark names end up in DolDoc links and tab-separated lines: no `$`, tabs or newlines.
+fn clean_bookmark_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .filter(|&c| c != '$')
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl Shell {
+    fn bookmarks_path(&self) -> PathBuf {
+        self.cfg_dir().join("Bookmarks.txt")
+    }
+
+    /// Remembers `prev` (the page being left) for Back, dropping the forward history.
+    fn push_doc_history(&mut self, mut prev: DocViewerState) {
+        prev.editing = None;
+        prev.prompt = None;
+        prev.msg.clear();
+        self.doc_back.push(prev);
+        if self.doc_back.len() > DOC_HISTORY_MAX {
+            self.doc_back.remove(0);
+        }
+        self.doc_forward.clear();
+    }
+
+    /// Back (or forward) one page in the doc viewer, restoring where it was scrolled to.
+    fn doc_go(&mut self, forward: bool, term: &mut Terminal) -> bool {
+        let Some(cur) = self.doc_viewer.as_mut() else {
+            return false;
+        };
+        let next = if forward {
+            self.doc_forward.pop()
+        } else {
+            self.doc_back.pop()
+        };
+        let Some(mut next) = next else {
+            cur.msg = if forward {
+                "no next page".to_string()
+            } else {
+                "no previous page".to_string()
+            };
+            self.render_doc_viewer(term);
+            return true;
+        };
+
+        let mut cur = self.doc_viewer.take().expect("doc viewer is open");
+        cur.editing = None;
+        cur.prompt = None;
+        cur.msg.clear();
+        if forward {
+            self.doc_back.push(cur);
+        } else {
+            self.doc_forward.push(cur);
+        }
+        next.msg = if forward { "forward" } else { "back" }.to_string();
+        self.doc_viewer = Some(next);
+        self.render_doc_viewer(term);
+        true
+    }
+
+    /// Saves the open document and scroll position as bookmark `name` (replacing one with the
+    /// same name).
+    fn add_doc_bookmark(&mut self, name: &str) {
+        let path = self.bookmarks_path();
+        let Some(state) = self.doc_viewer.as_mut() else {
+            return;
+        };
+        let name = clean_bookmark_name(name);
+        if name.is_empty() {
+            state.msg = "bookmark: empty name".to_string();
+            return;
+        }
+        if state.host.is_none() {
+            state.msg = "bookmark: only files can be bookmarked".to_string();
+            return;
+        }
+
+        let mut bookmarks = load_doc_bookmarks(&path);
+        let bm = DocBookmark {
+            name: name.clone(),
+            spec: state.spec.clone(),
+            line: state.scroll,
+        };
+        match bookmarks.iter_mut().find(|b| b.name == name) {
+            Some(old) => *old = bm,
+            None => bookmarks.push(bm),
+        }
+        state.msg = match save_doc_bookmarks(&path, &bookmarks) {
+            Ok(()) => format!("bookmarked: {name}"),
+            Err(err) => format!("bookmark: {err}"),
+        };
+    }
+
+    /// Default name offered by the bookmark prompt: the file name without its extension.
+    fn default_bookmark_name(spec: &str) -> String {
+        let file = spec.rsplit('/').next().unwrap_or(spec);
+        let stem = file.split('.').next().unwrap_or(file);
+        clean_bookmark_name(if stem.is_empty() { file } else { stem })
+    }
+
+    fn open_doc_bookmark(&mut self, name: &str, term: &mut Terminal) -> bool {
+        let bookmarks = load_doc_bookmarks(&self.bookmarks_path());
+        let Some(bm) = bookmarks.into_iter().find(|b| b.name == name.trim()) else {
+            return false;
+        };
+        if !self.try_show_doc(&bm.spec, term) {
+            return false;
+        }
+        if let Some(state) = self.doc_viewer.as_mut() {
+            let max_scroll = state
+                .lines
+                .len()
+                .saturating_sub(Self::doc_view_rows().max(1));
+            state.scroll = bm.line.min(max_scroll);
+            state.msg = format!("bookmark: {}", bm.name);
+        }
+        self.render_doc_viewer(term);
+        true
+    }
+
+    /// `bookmarks` lists the saved bookmarks as links; `bookmarks rm <name>` deletes one.
+    fn cmd_bookmarks(&mut self, args: &[&str], term: &mut Terminal) {
+        use fmt::Write as _;
+
+        let path = self.bookmarks_path();
+        let mut bookmarks = load_doc_bookmarks(&path);
+        match args {
+            [] => {}
+            ["rm", name @ ..] if !name.is_empty() => {
+                let name = name.join(" ");
+                let before = bookmarks.len();
+                bookmarks.retain(|b| b.name != name);
+                if bookmarks.len() == before {
+                    let _ = writeln!(term, "bookmarks: no such bookmark: {name}");
+                } else if let Err(err) = save_doc_bookmarks(&path, &bookmarks) {
+                    let _ = writeln!(term, "bookmarks: {}: {err}", path.display());
+                }
+                return;
+            }
+            _ => {
+                let _ = writeln!(term, "bookmarks: expected: bookmarks [rm <name>]");
+                return;
+            }
+        }
+
+        let mut doc = String::new();
+        let _ = writeln!(doc, "$FG,14$Bookmarks$FG$\n");
+        if bookmarks.is_empty() {
+            let _ = writeln!(doc, "None yet: press b in the doc viewer to add one.");
+        }
+        for bm in &bookmarks {
+            let _ = writeln!(
+                doc,
+                "$LK,{},A={}$ $FG,8${} line {}$FG$",
+                temple_rt::doldoc::quote_arg(&bm.name),
+                temple_rt::doldoc::quote_arg(&format!("BM:{}", bm.name)),
+                temple_rt::doldoc::escape_text(&bm.spec),
+                bm.line + 1
+            );
+        }
+        self.open_doc_viewer(
+            "TempleLinux:Bookmarks".to_string(),
+            DocKind::DolDoc,
+            &doc,
+            Default::default(),
+            false,
+            None,
+            term,
+        );
+    }
+}
+
+/// Reads `Cfg/DocTrees.txt`: per document, `spec<TAB>tree:0|1 ...` (1 = collapsed).
+fn load_doc_tree_states(path: &Path) -> std::collections::BTreeMap<String, temple_rt::doldoc::TreeState> {
+    let mut out = std::collections::BTreeMap::new();
+    let Ok(buf) = std::fs::read(path) else {
+        return out;
+    };
+    for line in String::from_utf8_lossy(&buf).lines() {
+        let Some((spec, states)) = line.split_once('\t') else {
+            continue;
+        };
+        let state: temple_rt::doldoc::TreeState = states
+            .split_whitespace()
+            .filter_map(|s| {
+                let (idx, collapsed) = s.split_once(':')?;
+                Some((idx.parse().ok()?, collapsed == "1"))
+            })
+            .collect();
+        if !spec.is_empty() && !state.is_empty() {
+            out.insert(spec.to_string(), state);
+        }
+    }
+    out
+}
+
+impl Shell {
+    fn doc_trees_path(&self) -> PathBuf {
+        self.cfg_dir().join("DocTrees.txt")
+    }
+
+    fn load_doc_tree_state(&self, spec: &str) -> temple_rt::doldoc::TreeState {
+        load_doc_tree_states(&self.doc_trees_path())
+            .remove(spec)
+            .unwrap_or_default()
+    }
+
+    fn save_doc_tree_state(&self, spec: &str, state: &temple_rt::doldoc::TreeState) {
+        let path = self.doc_trees_path();
+        let mut all = load_doc_tree_states(&path);
+        if state.is_empty() {
+            all.remove(spec);
+        } else {
+            all.insert(spec.to_string(), state.clone());
+        }
+        let mut out = String::new();
+        for (spec, state) in &all {
+            out.push_str(spec);
+            out.push('\t');
+            let states: Vec<String> = state
+                .iter()
+                .map(|(idx, collapsed)| format!("{idx}:{}", u8::from(*collapsed)))
+                .collect();
+            out.push_str(&states.join(" "));
+            out.push('\n');
+        }
+        let _ = std::fs::write(&path, out);
+    }
+
+    /// Opens or closes tree node `idx` (`None` toggles it), or every node with `idx` `None`,
+    /// then re-lays the document out and remembers the choice for it.
+    fn set_doc_trees(&mut self, idx: Option<usize>, collapsed: Option<bool>, term: &mut Terminal) {
+        let Some(state) = self.doc_viewer.as_mut() else {
+            return;
+        };
+        match idx {
+            Some(idx) => {
+                let Some(tree) = state.trees.get(idx) else {
+                    return;
+                };
+                let collapsed = collapsed.unwrap_or(!tree.collapsed);
+                state.tree_state.insert(idx, collapsed);
+            }
+            None => {
+                let collapsed = collapsed.unwrap_or(false);
+                state.tree_state = (0..state.trees.len()).map(|i| (i, collapsed)).collect();
+            }
+        }
+        let (spec, tree_state) = (state.spec.clone(), state.tree_state.clone());
+        self.save_doc_tree_state(&spec, &tree_state);
+        self.rebuild_doc_viewer();
+
+        if let Some(state) = self.doc_viewer.as_mut()
+            && let Some(idx) = idx
+        {
+            state.selected_sprite = None;
+            state.selected_link = state
+                .links
+                .iter()
+                .position(|l| matches!(l.target, DocLinkTarget::Tree(i) if i == idx))
+                .or(state.selected_link);
+        }
+        self.render_doc_viewer(term);
+    }
+}